@@ -1,4 +1,3 @@
-use shadow_rs;
 fn main() -> shadow_rs::SdResult<()> {
     shadow_rs::new()
 }
\ No newline at end of file
@@ -24,7 +24,7 @@ ensure_self_signed() {
     ssl_path="/usr/local/openresty/nginx/ssl/${domain}"
     if [ ! -d "$ssl_path" ]; then
         echo "generating self signed cert for $domain"
-        mkdir -p $ssl_path
+        mkdir -p "$ssl_path"
         country=SE
         state=Kalmar
         city=Kalmar
@@ -56,11 +56,17 @@ SYSTEM_RESOLVER=$(cat /etc/resolv.conf | grep -im 1 '^nameserver' | cut -d ' ' -
 export SYSTEM_RESOLVER
 
 prepare_config() {
+    # for the catch-all server
+    ensure_self_signed skate-default
+
     # ensure self signed cert exists for base url
     domains=$(grep -R "# anchor::domain" "$CONF_DIR/services" | awk '{print $NF}' | sort -u)
+    # wildcard hosts aren't globs
+    set -f
     for domain in $domains; do
-        ensure_self_signed $domain
+        ensure_self_signed "$domain"
     done
+    set +f
 
     # Format it
     echo "formatting..."
@@ -41,5 +41,17 @@ function _M.go(err_code)
     template.render("error.html", { title = vars["title"], message = vars["message"] })
 end
 
+-- the page for requests no ingress matches, which the cluster's config can replace, eg with a maintenance page
+function _M.default()
+    local status = tonumber(os.getenv("DEFAULT_PAGE_STATUS") or "") or 404
+    local vars = getVars(status)
+    ngx.status = status
+    ngx.header.content_type = "text/html"
+    template.render("error.html", {
+        title = os.getenv("DEFAULT_PAGE_TITLE") or vars["title"],
+        message = os.getenv("DEFAULT_PAGE_MESSAGE") or vars["message"]
+    })
+end
+
 return _M
 
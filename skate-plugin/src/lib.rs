@@ -0,0 +1,184 @@
+// what skate passes the `skate-<name>` executables it runs for the commands it doesn't know, and typed helpers for
+// reading it, so a plugin doesn't need to depend on skate itself or parse its files
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::process::Command;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+
+// commands skate doesn't know are run as `skate-<command>` executables from the PATH, kubectl style
+pub const PLUGIN_PREFIX: &str = "skate-";
+// the name of the context the plugin runs against, also honored by skate's own --context
+pub const CONTEXT_ENV: &str = "SKATE_CONTEXT";
+// the cluster state written by the last refresh
+pub const STATE_FILE_ENV: &str = "SKATE_STATE_FILE";
+// the access the context's profile allows, which the plugin is trusted to respect
+pub const ACCESS_ENV: &str = "SKATE_ACCESS";
+// the skate executable, for running skate commands from the plugin
+pub const SKATE_BIN_ENV: &str = "SKATE_BIN";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error(String);
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+// what a command needs to be allowed to do, in increasing order. it's also what a context's profile allows
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum Access {
+    // only reads the cluster's state
+    ReadOnly,
+    // changes resources, but not the nodes or cluster
+    Deploy,
+    #[default]
+    Admin,
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Access::ReadOnly => "read-only",
+            Access::Deploy => "deploy",
+            Access::Admin => "admin",
+        })
+    }
+}
+
+impl FromStr for Access {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read-only" => Ok(Access::ReadOnly),
+            "deploy" => Ok(Access::Deploy),
+            "admin" => Ok(Access::Admin),
+            _ => Err(Error(format!("unknown access {}, expected read-only, deploy or admin", s))),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub enum NodeStatus {
+    #[default]
+    Unknown,
+    Healthy,
+    Unhealthy,
+}
+
+// the parts of a node's state most plugins need, the rest of it is in ClusterState::raw
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct NodeState {
+    pub node_name: String,
+    pub status: NodeStatus,
+    pub message: Option<String>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct ClusterState {
+    pub cluster_name: String,
+    pub nodes: Vec<NodeState>,
+    // the whole state file, for what isn't typed here
+    pub raw: serde_json::Value,
+}
+
+impl ClusterState {
+    pub fn parse(contents: &str) -> Result<Self, Error> {
+        #[derive(Deserialize)]
+        struct Typed {
+            cluster_name: String,
+            nodes: Vec<NodeState>,
+        }
+        let raw: serde_json::Value = serde_json::from_str(contents).map_err(|e| Error(format!("failed to parse cluster state: {}", e)))?;
+        let typed: Typed = serde_json::from_value(raw.clone()).map_err(|e| Error(format!("failed to parse cluster state: {}", e)))?;
+        Ok(ClusterState { cluster_name: typed.cluster_name, nodes: typed.nodes, raw })
+    }
+}
+
+// what skate passed the plugin
+#[derive(Debug, Clone)]
+pub struct PluginContext {
+    pub context: Option<String>,
+    pub state_file: Option<PathBuf>,
+    pub access: Access,
+    pub skate_bin: PathBuf,
+}
+
+impl PluginContext {
+    // a missing or unknown access is read-only, so a plugin run outside skate can't change the cluster
+    pub fn from_env() -> Self {
+        PluginContext {
+            context: std::env::var(CONTEXT_ENV).ok(),
+            state_file: std::env::var_os(STATE_FILE_ENV).map(PathBuf::from),
+            access: std::env::var(ACCESS_ENV).ok().and_then(|a| a.parse().ok()).unwrap_or(Access::ReadOnly),
+            skate_bin: std::env::var_os(SKATE_BIN_ENV).map(PathBuf::from).unwrap_or(PathBuf::from("skate")),
+        }
+    }
+
+    // the cluster state as of the last refresh, run `skate refresh` first for a current one
+    pub fn state(&self) -> Result<ClusterState, Error> {
+        let path = self.state_file.as_ref().ok_or(Error(format!("no context, {} isn't set", STATE_FILE_ENV)))?;
+        let contents = std::fs::read_to_string(path).map_err(|e| Error(format!("failed to open {}: {}", path.display(), e)))?;
+        ClusterState::parse(&contents)
+    }
+
+    // fails unless the context allows the access, for plugins that change the cluster
+    pub fn require(&self, access: Access) -> Result<(), Error> {
+        match access > self.access {
+            true => Err(Error(format!("context {} has {} access, {} access is needed", self.context.clone().unwrap_or_default(), self.access, access))),
+            false => Ok(()),
+        }
+    }
+
+    // a skate command that runs against the same context
+    pub fn skate(&self) -> Command {
+        let mut cmd = Command::new(&self.skate_bin);
+        if let Some(context) = &self.context {
+            cmd.env(CONTEXT_ENV, context);
+        }
+        cmd
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use crate::{Access, ClusterState, NodeStatus, PluginContext, ACCESS_ENV};
+
+    #[test]
+    fn test_parse_state() {
+        let state = ClusterState::parse(r#"{"cluster_name":"prod","nodes":[{"node_name":"node-1","status":"Healthy","message":null,"host_info":{"hostname":"a"}}]}"#).unwrap();
+        assert_eq!("prod", state.cluster_name);
+        assert_eq!(NodeStatus::Healthy, state.nodes[0].status);
+        assert_eq!("a", state.raw["nodes"][0]["host_info"]["hostname"]);
+        assert!(ClusterState::parse("{}").is_err());
+    }
+
+    #[test]
+    fn test_require() {
+        let context = PluginContext { context: Some("prod".to_string()), state_file: None, access: Access::Deploy, skate_bin: PathBuf::from("skate") };
+        assert!(context.require(Access::ReadOnly).is_ok());
+        assert!(context.require(Access::Deploy).is_ok());
+        assert_eq!("context prod has deploy access, admin access is needed", context.require(Access::Admin).unwrap_err().to_string());
+        assert_eq!(Ok(Access::ReadOnly), "read-only".parse());
+    }
+
+    #[test]
+    fn test_from_env_defaults_to_read_only() {
+        std::env::remove_var(ACCESS_ENV);
+        assert_eq!(Access::ReadOnly, PluginContext::from_env().access);
+        std::env::set_var(ACCESS_ENV, "root");
+        assert_eq!(Access::ReadOnly, PluginContext::from_env().access);
+        std::env::set_var(ACCESS_ENV, "admin");
+        assert_eq!(Access::Admin, PluginContext::from_env().access);
+        std::env::remove_var(ACCESS_ENV);
+    }
+}
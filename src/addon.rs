@@ -0,0 +1,216 @@
+use std::str::FromStr;
+use anyhow::anyhow;
+use clap::{Args, Subcommand};
+use tabled::settings::Style;
+use tabled::{Table, Tabled};
+use crate::apply::{parse_manifests, Apply, ApplyDeps};
+use crate::config::{Access, Cluster, Config};
+use crate::create::node::coredns_manifest;
+use crate::errors::SkateError;
+use crate::ingress_class;
+use crate::lock;
+use crate::refresh::Refresh;
+use crate::resource::{ResourceType, SupportedResources};
+use crate::scheduler::DefaultScheduler;
+use crate::skate::ConfigFileArgs;
+use crate::state::state::{ClusterState, OwnerRef};
+use crate::util::CHECKBOX_EMOJI;
+
+const NODE_EXPORTER_MANIFEST: &str = include_str!("../manifests/addons/node-exporter.yaml");
+
+// manifests built into skate, rendered for the cluster and applied like any other
+pub struct Addon {
+    pub name: &'static str,
+    pub description: &'static str,
+    manifest: fn(&Cluster) -> Result<String, SkateError>,
+}
+
+pub const ADDONS: &[Addon] = &[
+    Addon {
+        name: "dns",
+        description: "coredns on every node, serving the cluster.skate names",
+        manifest: |cluster| Ok(coredns_manifest(cluster)),
+    },
+    Addon {
+        name: "ingress",
+        description: "the default nginx ingress, with the cluster's default page",
+        manifest: |cluster| Ok(ingress_class::default_manifest(cluster.ingress_default_page.as_ref())?),
+    },
+    Addon {
+        name: "metrics-exporter",
+        description: "prometheus' node exporter on every node, on port 9100",
+        manifest: |_| Ok(NODE_EXPORTER_MANIFEST.to_string()),
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Addon> {
+    ADDONS.iter().find(|a| a.name == name)
+}
+
+impl Addon {
+    pub fn resources(&self, cluster: &Cluster) -> Result<Vec<SupportedResources>, SkateError> {
+        Ok(parse_manifests(&(self.manifest)(cluster)?)?)
+    }
+
+    // with the skate.io labels the resources are found by
+    fn fixed_up_resources(&self, cluster: &Cluster) -> Result<Vec<SupportedResources>, SkateError> {
+        Ok(self.resources(cluster)?.into_iter().map(|r| r.fixup()).collect::<Result<Vec<_>, _>>()?)
+    }
+
+    // enabled while any of its pods run
+    fn enabled(&self, cluster: &Cluster, state: &ClusterState) -> Result<bool, SkateError> {
+        Ok(self.fixed_up_resources(cluster)?.iter().filter_map(|r| match r {
+            SupportedResources::DaemonSet(_) => Some(OwnerRef::new(ResourceType::DaemonSet, &r.name())),
+            SupportedResources::Deployment(_) => Some(OwnerRef::new(ResourceType::Deployment, &r.name())),
+            _ => None,
+        }).any(|owner| !state.owned_pods(&owner).is_empty()))
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct AddonArgs {
+    #[command(flatten)]
+    config: ConfigFileArgs,
+    #[command(subcommand)]
+    command: AddonCommands,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AddonCommands {
+    #[command(about = "List the built in addons and whether they're enabled")]
+    List,
+    #[command(about = "Apply addons to the cluster")]
+    Enable(AddonNamesArgs),
+    #[command(about = "Remove addons from the cluster")]
+    Disable(AddonNamesArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct AddonNamesArgs {
+    #[arg(required = true)]
+    names: Vec<String>,
+    #[arg(long, long_help = "Will not affect the cluster if set to true")]
+    dry_run: bool,
+}
+
+#[derive(Tabled)]
+#[tabled(rename_all = "UPPERCASE")]
+struct AddonRow {
+    name: String,
+    enabled: bool,
+    description: String,
+}
+
+pub trait AddonDeps: ApplyDeps {}
+
+pub struct Addons<D: AddonDeps> {
+    pub deps: D,
+}
+
+fn addons(names: &[String]) -> Result<Vec<&'static Addon>, SkateError> {
+    names.iter().map(|name| find(name).ok_or(anyhow!("no addon named {}, expected one of {}", name, ADDONS.iter().map(|a| a.name).collect::<Vec<_>>().join(", ")).into())).collect()
+}
+
+// applies the addons through the scheduler, skate's system manifests aren't subject to user policies
+pub async fn enable<D: ApplyDeps>(deps: &D, config: &Config, names: &[String], dry_run: bool) -> Result<(), SkateError> {
+    let cluster = config.authorized_cluster(config.current_context.clone(), Access::Admin)?;
+    let mut resources = vec![];
+    for addon in addons(names)? {
+        resources.extend(addon.resources(cluster)?);
+    }
+    Apply::<D>::apply_supported_resources(deps, config, resources, dry_run, true, &DefaultScheduler::default()).await?;
+    Ok(())
+}
+
+impl<D: AddonDeps> Addons<D> {
+    pub async fn addon(&self, args: AddonArgs) -> Result<(), SkateError> {
+        let mut config = Config::load(args.config.skateconfig.clone())?;
+        if args.config.context.is_some() {
+            config.current_context = args.config.context.clone();
+        }
+
+        match args.command {
+            AddonCommands::List => self.list(&config).await,
+            AddonCommands::Enable(names_args) => enable(&self.deps, &config, &names_args.names, names_args.dry_run).await,
+            AddonCommands::Disable(names_args) => self.disable(&config, names_args).await,
+        }
+    }
+
+    async fn list(&self, config: &Config) -> Result<(), SkateError> {
+        let cluster = config.authorized_cluster(config.current_context.clone(), Access::ReadOnly)?;
+        let (conns, errors) = self.deps.get().cluster_connect(cluster).await;
+        if let Some(errors) = errors {
+            eprintln!("{}", errors)
+        }
+        let conns = conns.ok_or(anyhow!("failed to connect to any node"))?;
+        let state = Refresh::<D>::refreshed_state(&cluster.name, &conns, config).await?;
+
+        let rows = ADDONS.iter().map(|a| Ok(AddonRow {
+            name: a.name.to_string(),
+            enabled: a.enabled(cluster, &state)?,
+            description: a.description.to_string(),
+        })).collect::<Result<Vec<_>, SkateError>>()?;
+        let mut table = Table::new(rows);
+        table.with(Style::empty());
+        println!("{}", table);
+        Ok(())
+    }
+
+    async fn disable(&self, config: &Config, args: AddonNamesArgs) -> Result<(), SkateError> {
+        let cluster = config.authorized_cluster(config.current_context.clone(), Access::Admin)?;
+        let mut resources = vec![];
+        for addon in addons(&args.names)? {
+            resources.extend(addon.fixed_up_resources(cluster)?);
+        }
+        if args.dry_run {
+            for resource in resources {
+                println!("would remove {} {}", resource, resource.name());
+            }
+            return Ok(());
+        }
+
+        let (conns, errors) = self.deps.get().cluster_connect(cluster).await;
+        if let Some(errors) = errors {
+            eprintln!("{}", errors)
+        }
+        let conns = conns.ok_or(anyhow!("failed to connect to any node"))?;
+
+        let command = format!("addon disable {}", args.names.join(" "));
+        lock::locked(cluster, &conns, &command, async {
+            let mut errors = vec![];
+            for resource in resources.iter() {
+                let name = resource.name();
+                let r_type = ResourceType::from_str(&resource.to_string()).map_err(|e| anyhow!(e).context(format!("unknown kind {}", resource)))?;
+                for conn in conns.clients.iter() {
+                    if let Err(e) = conn.remove_resource(r_type.clone(), &name.name, &name.namespace).await {
+                        errors.push(format!("{} - {}", conn.node_name(), e));
+                    }
+                }
+                println!("{} removed {} {}", CHECKBOX_EMOJI, resource, name);
+            }
+            match errors.is_empty() {
+                true => Ok(()),
+                false => Err(anyhow!("\n{}", errors.join("\n")).context("failed to disable addons").into()),
+            }
+        }).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::addon::{find, ADDONS};
+    use crate::config::Cluster;
+    use crate::resource::SupportedResources;
+
+    #[test]
+    fn test_addon_resources() {
+        let cluster: Cluster = serde_yaml::from_str("name: test\nnodes: []").unwrap();
+        for addon in ADDONS {
+            let resources = addon.fixed_up_resources(&cluster).unwrap();
+            assert!(!resources.is_empty(), "{}", addon.name);
+            assert!(resources.iter().all(|r| r.name().namespace == "skate"), "{}", addon.name);
+        }
+        assert!(matches!(find("metrics-exporter").unwrap().resources(&cluster).unwrap()[0], SupportedResources::DaemonSet(_)));
+        assert!(find("dashboard").is_none());
+    }
+}
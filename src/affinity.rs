@@ -0,0 +1,197 @@
+use std::collections::BTreeMap;
+use k8s_openapi::api::core::v1::{Pod, PodAffinityTerm};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+use crate::skatelet::system::podman::{PodmanPodInfo, PodmanPodStatus};
+use crate::state::state::NodeState;
+
+// every node is its own topology domain, so only keys that are unique per node can be used
+const HOSTNAME_TOPOLOGY_KEYS: &[&str] = &["kubernetes.io/hostname", "skate.io/hostname", "skate.io/nodename"];
+
+pub fn selector_matches(selector: &LabelSelector, labels: &BTreeMap<String, String>) -> bool {
+    let match_labels = selector.match_labels.iter().flatten().all(|(k, v)| labels.get(k) == Some(v));
+    let match_expressions = selector.match_expressions.iter().flatten().all(|e| {
+        let values = e.values.clone().unwrap_or_default();
+        match e.operator.as_str() {
+            "In" => labels.get(&e.key).is_some_and(|v| values.contains(v)),
+            "NotIn" => labels.get(&e.key).is_none_or(|v| !values.contains(v)),
+            "Exists" => labels.contains_key(&e.key),
+            "DoesNotExist" => !labels.contains_key(&e.key),
+            _ => false,
+        }
+    });
+    match_labels && match_expressions
+}
+
+// whether the running pod is one of those the term selects, from the namespaces it lists or the pod's own
+fn term_selects(term: &PodAffinityTerm, namespace: &str, pod: &PodmanPodInfo) -> bool {
+    // namespaces have no labels in skate, so a namespace selector can only select all of them when it's empty
+    let all_namespaces = term.namespace_selector.as_ref()
+        .is_some_and(|s| s.match_labels.as_ref().is_none_or(|l| l.is_empty()) && s.match_expressions.as_ref().is_none_or(|e| e.is_empty()));
+    let pod_namespace = pod.namespace();
+    let in_namespace = all_namespaces || match term.namespaces.as_ref().filter(|n| !n.is_empty()) {
+        Some(namespaces) => namespaces.contains(&pod_namespace),
+        None => pod_namespace == namespace,
+    };
+    in_namespace && term.label_selector.as_ref().is_some_and(|s| selector_matches(s, &pod.labels))
+}
+
+fn hostname_topology(term: &PodAffinityTerm) -> Result<(), String> {
+    match HOSTNAME_TOPOLOGY_KEYS.contains(&term.topology_key.as_str()) {
+        true => Ok(()),
+        false => Err(format!("unsupported topology key {}, only {} is supported", term.topology_key, HOSTNAME_TOPOLOGY_KEYS[0])),
+    }
+}
+
+// the pods on the node that the pod is placed next to, other than an earlier copy of itself that it replaces
+fn node_pods(node: &NodeState, pod_name: &str) -> Vec<PodmanPodInfo> {
+    node.filter_pods(&|p| p.name != pod_name && !matches!(p.status, PodmanPodStatus::Exited | PodmanPodStatus::Stopped | PodmanPodStatus::Dead))
+}
+
+fn pod_ident(pod: &Pod) -> (String, String) {
+    (pod.metadata.name.clone().unwrap_or_default(), pod.metadata.namespace.clone().unwrap_or("default".to_string()))
+}
+
+// why the pod's required anti-affinity keeps it off the node, if it does. running pods' own anti-affinity isn't known to skate,
+// so it's only the pod being scheduled that is checked
+pub fn anti_affinity_violation(pod: &Pod, node: &NodeState) -> Option<String> {
+    let terms = pod.spec.as_ref()?.affinity.as_ref()?.pod_anti_affinity.as_ref()?
+        .required_during_scheduling_ignored_during_execution.as_ref()?;
+    let (name, namespace) = pod_ident(pod);
+    let pods = node_pods(node, &name);
+    terms.iter().find_map(|term| {
+        if let Err(e) = hostname_topology(term) {
+            return Some(format!("pod anti-affinity: {}", e));
+        }
+        pods.iter().find(|p| term_selects(term, &namespace, p))
+            .map(|p| format!("pod anti-affinity: node runs pod {}", p.name))
+    })
+}
+
+fn describe_term(term: &PodAffinityTerm) -> String {
+    let labels = term.label_selector.as_ref().and_then(|s| s.match_labels.as_ref())
+        .map(|l| l.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join(","))
+        .unwrap_or_default();
+    match labels.is_empty() {
+        true => "the selector".to_string(),
+        false => labels,
+    }
+}
+
+// why the pod's required affinity keeps it off the node, if it does. like in kubernetes, a pod that its own term selects
+// can go anywhere while no pod in the cluster matches, so the first of a group of pods can be placed
+pub fn affinity_violation(pod: &Pod, node: &NodeState, cluster_pods: &[PodmanPodInfo]) -> Option<String> {
+    let terms = pod.spec.as_ref()?.affinity.as_ref()?.pod_affinity.as_ref()?
+        .required_during_scheduling_ignored_during_execution.as_ref()?;
+    let (name, namespace) = pod_ident(pod);
+    let pods = node_pods(node, &name);
+    let itself = PodmanPodInfo::from(pod.clone());
+    terms.iter().find_map(|term| {
+        if let Err(e) = hostname_topology(term) {
+            return Some(format!("pod affinity: {}", e));
+        }
+        if pods.iter().any(|p| term_selects(term, &namespace, p)) {
+            return None;
+        }
+        let matched_elsewhere = cluster_pods.iter().any(|p| p.name != name && term_selects(term, &namespace, p));
+        match !matched_elsewhere && term_selects(term, &namespace, &itself) {
+            true => None,
+            false => Some(format!("pod affinity: node runs no pod matching {}", describe_term(term))),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use k8s_openapi::api::core::v1::{Affinity, Pod, PodAffinity, PodAffinityTerm, PodAntiAffinity, PodSpec, PodStatus};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, LabelSelectorRequirement};
+    use crate::affinity::{affinity_violation, anti_affinity_violation, selector_matches};
+    use crate::test_helpers::objects::{node_state, pod, WithPod};
+
+    fn app_pod(name: &str, app: &str, affinity: Option<Affinity>) -> Pod {
+        Pod {
+            spec: Some(PodSpec { affinity, ..Default::default() }),
+            status: Some(PodStatus { phase: Some("Running".to_string()), ..Default::default() }),
+            ..pod(name, "default", &[("app", app)])
+        }
+    }
+
+    fn term(app: &str, topology_key: &str) -> PodAffinityTerm {
+        PodAffinityTerm {
+            label_selector: Some(LabelSelector { match_labels: Some(BTreeMap::from([("app".to_string(), app.to_string())])), ..Default::default() }),
+            topology_key: topology_key.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_selector_matches() {
+        let labels = BTreeMap::from([("app".to_string(), "db".to_string()), ("tier".to_string(), "backend".to_string())]);
+        let expression = |key: &str, operator: &str, values: &[&str]| LabelSelector {
+            match_expressions: Some(vec![LabelSelectorRequirement {
+                key: key.to_string(),
+                operator: operator.to_string(),
+                values: Some(values.iter().map(|v| v.to_string()).collect()),
+            }]),
+            ..Default::default()
+        };
+        assert!(selector_matches(&expression("app", "In", &["db", "cache"]), &labels));
+        assert!(!selector_matches(&expression("app", "NotIn", &["db"]), &labels));
+        assert!(selector_matches(&expression("zone", "DoesNotExist", &[]), &labels));
+        assert!(!selector_matches(&expression("app", "Unknown", &[]), &labels));
+        assert!(selector_matches(&LabelSelector::default(), &labels));
+    }
+
+    #[test]
+    fn test_anti_affinity_violation() {
+        let anti = |app: &str, key: &str| Some(Affinity {
+            pod_anti_affinity: Some(PodAntiAffinity {
+                required_during_scheduling_ignored_during_execution: Some(vec![term(app, key)]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        let node = node_state("node-1").with_pod(&app_pod("db-0", "db", None));
+
+        let violation = anti_affinity_violation(&app_pod("db-1", "db", anti("db", "kubernetes.io/hostname")), &node);
+        assert_eq!(Some("pod anti-affinity: node runs pod db-0.default".to_string()), violation);
+        assert_eq!(None, anti_affinity_violation(&app_pod("web-0", "web", anti("web", "kubernetes.io/hostname")), &node));
+        // a new copy of the same pod replaces the old one
+        assert_eq!(None, anti_affinity_violation(&app_pod("db-0", "db", anti("db", "kubernetes.io/hostname")), &node));
+        // other namespaces aren't selected unless listed
+        let mut other = app_pod("db-1", "db", anti("db", "kubernetes.io/hostname"));
+        other.metadata.namespace = Some("other".to_string());
+        assert_eq!(None, anti_affinity_violation(&other, &node));
+
+        let violation = anti_affinity_violation(&app_pod("db-1", "db", anti("db", "topology.kubernetes.io/zone")), &node).unwrap();
+        assert!(violation.contains("unsupported topology key"), "{}", violation);
+    }
+
+    #[test]
+    fn test_affinity_violation() {
+        let near = |app: &str| Some(Affinity {
+            pod_affinity: Some(PodAffinity {
+                required_during_scheduling_ignored_during_execution: Some(vec![term(app, "kubernetes.io/hostname")]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        let with_api = node_state("node-1").with_pod(&app_pod("api-0", "api", None));
+        let empty = node_state("node-2");
+        let cluster_pods = with_api.filter_pods(&|_| true);
+
+        let cache = app_pod("cache-0", "cache", near("api"));
+        assert_eq!(None, affinity_violation(&cache, &with_api, &cluster_pods));
+        assert_eq!(Some("pod affinity: node runs no pod matching app=api".to_string()), affinity_violation(&cache, &empty, &cluster_pods));
+        // nothing to be near yet
+        assert!(affinity_violation(&cache, &empty, &[]).is_some());
+
+        // the first of pods that select each other goes anywhere, the rest follow it
+        let worker = |name: &str| app_pod(name, "worker", near("worker"));
+        assert_eq!(None, affinity_violation(&worker("worker-0"), &empty, &cluster_pods));
+        let with_worker = node_state("node-3").with_pod(&worker("worker-0"));
+        let cluster_pods = with_worker.filter_pods(&|_| true);
+        assert!(affinity_violation(&worker("worker-1"), &empty, &cluster_pods).is_some());
+        assert_eq!(None, affinity_violation(&worker("worker-1"), &with_worker, &cluster_pods));
+    }
+}
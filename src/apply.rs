@@ -1,24 +1,54 @@
 use anyhow::anyhow;
-use clap::Args;
+use clap::{Args, ValueEnum};
 use std::error::Error;
 use serde_yaml::Value;
 use std::{fs, io};
 use std::io::Read;
-use serde::Deserialize;
-use crate::config::Config;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use tabled::settings::Style;
+use tabled::{Table, Tabled};
+use crate::client::Client;
+use crate::config::{Cluster, Config};
+use crate::conversion;
 use crate::deps::{SshManager, With};
 use crate::errors::SkateError;
+use crate::notify::{notify, Notification, NotificationKind};
+use crate::edit::validate_modified;
+use crate::patch::three_way_merge;
+use crate::policy;
+use crate::priority;
+use crate::host_network;
+use crate::sysctl;
+use crate::kustomize;
+use crate::limit_range;
+use crate::defaults;
+use crate::overlay::Overlay;
+use crate::generate_name;
+use crate::logging;
+use crate::node_pool;
 use crate::refresh::{Refresh, RefreshDeps};
-use crate::resource::SupportedResources;
-use crate::scheduler::{DefaultScheduler, Scheduler};
+use crate::report::StdoutToStderr;
+use crate::resource::{ResourceType, SupportedResources};
+use crate::ingress_class;
+use crate::lock;
+use crate::ssh::SshClients;
+use crate::scheduler::{DefaultScheduler, OpType, ScheduleResult, ScheduledOperation, Scheduler, DEFAULT_MAX_ATTEMPTS};
 
 use crate::skate::ConfigFileArgs;
+use crate::state::state::ClusterState;
+use crate::util::{NamespacedName, LAST_APPLIED_ANNOTATION};
 
 #[derive(Debug, Args)]
 #[command(arg_required_else_help(true))]
 pub struct ApplyArgs {
     #[arg(short, long, long_help = "The files that contain the configurations to apply.")]
     pub filename: Vec<String>,
+    #[arg(short, long, conflicts_with = "filename", long_help = "Apply the kustomization in the directory. Only resources, namespace, commonLabels and \
+images are supported.")]
+    pub kustomize: Option<String>,
     #[arg(long, default_value_t = - 1, long_help = "Period of time in seconds given to the resource to terminate gracefully. Ignored if negative. Set to 1 for \
 immediate shutdown.")]
     pub grace_period: i32,
@@ -26,6 +56,87 @@ immediate shutdown.")]
     pub config: ConfigFileArgs,
     #[arg(long, long_help = "Will not affect the cluster if set to true")]
     pub dry_run: bool,
+    #[arg(long, long_help = "Apply even if the manifests violate the cluster's policies.")]
+    pub override_policy: bool,
+    #[arg(long, default_value_t = DEFAULT_MAX_ATTEMPTS, long_help = "Number of nodes to try a resource on when applying it fails.")]
+    pub max_attempts: usize,
+    #[arg(long, long_help = "Leave the copies of resources on nodes they can no longer run on, eg cordoned ones, and duplicates. Scale downs and \
+replacements still remove pods.")]
+    pub no_cleanup: bool,
+    #[arg(long, short, value_enum, default_value_t = ApplyOutput::Table, long_help = "How to print the result of each object.")]
+    pub output: ApplyOutput,
+    #[arg(long, long_help = "Print the raw output from the nodes.")]
+    pub verbose: bool,
+    #[arg(long, value_name = "RESOURCE_VERSION", long_help = "Only apply if the live object is still at this resourceVersion, as shown by get -o yaml. \
+Requires a single object.")]
+    pub if_match: Option<String>,
+    #[arg(long, long_help = "Break ties between equally loaded nodes by this seed rather than by node name. The same seed and state give the same \
+placements.")]
+    pub seed: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, ValueEnum)]
+pub enum ApplyOutput {
+    Table,
+    Json,
+}
+
+// ApplyResultRow is what happened to an object on a node, the -o json output is a list of them
+#[derive(Tabled, Serialize, Debug, PartialEq)]
+#[tabled(rename_all = "UPPERCASE")]
+pub struct ApplyResultRow {
+    pub object: String,
+    pub kind: String,
+    pub node: String,
+    pub action: String,
+    #[tabled(display_with = "display_duration")]
+    pub duration_ms: Option<u128>,
+    #[tabled(display_with = "display_error")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn display_duration(ms: &Option<u128>) -> String {
+    ms.map(|ms| format!("{:.1}s", Duration::from_millis(ms as u64).as_secs_f64())).unwrap_or("-".to_string())
+}
+
+fn display_error(error: &Option<String>) -> String {
+    error.clone().unwrap_or_default()
+}
+
+impl From<&ScheduledOperation> for ApplyResultRow {
+    fn from(op: &ScheduledOperation) -> Self {
+        let action = match (&op.error, &op.operation) {
+            (Some(_), _) => "failed",
+            (None, OpType::Create) => "created",
+            (None, OpType::Clobber) => "replaced",
+            (None, OpType::Delete) if op.preempted_by.is_some() => "preempted",
+            (None, OpType::Delete) => "deleted",
+            (None, OpType::Unchanged) => "unchanged",
+            (None, OpType::Info) => "info",
+        };
+        // a failed creation never got a node, the last one tried is the most useful to show
+        let node = op.node.as_ref().map(|n| n.node_name.clone())
+            .or(op.attempts.last().map(|a| a.node_name.clone()))
+            .unwrap_or_default();
+
+        ApplyResultRow {
+            object: op.resource.name().to_string(),
+            kind: op.resource.to_string(),
+            node,
+            action: action.to_string(),
+            duration_ms: op.duration.map(|d| d.as_millis()),
+            error: op.error.clone(),
+        }
+    }
+}
+
+// the rows to report, leaving out the internal operations that aren't printed as they happen unless they failed
+pub fn result_rows(result: &ScheduleResult) -> Vec<ApplyResultRow> {
+    result.placements.iter()
+        .filter(|op| !op.silent || op.error.is_some())
+        .map(ApplyResultRow::from)
+        .collect()
 }
 
 pub trait ApplyDeps: With<dyn SshManager> + RefreshDeps{}
@@ -36,47 +147,245 @@ pub struct Apply<D: ApplyDeps>{
 
 impl<D: ApplyDeps> Apply<D> {
     pub async fn apply(deps: &D, args: ApplyArgs) -> Result<(), SkateError> {
-        let config = Config::load(Some(args.config.skateconfig))?;
-        let objects = read_manifests(args.filename)?;
-        Self::apply_supported_resources(deps, &config, objects, args.dry_run).await
+        let config = Config::load(args.config.skateconfig)?;
+        let mut objects = match args.kustomize.as_ref() {
+            Some(dir) => kustomize::build(Path::new(dir))?.into_iter().map(parse_manifest).collect::<Result<Vec<_>, _>>()?,
+            None => read_manifests(args.filename)?,
+        };
+        if let Some(resource_version) = &args.if_match {
+            if objects.len() != 1 {
+                return Err(anyhow!("--if-match requires a single object, got {}", objects.len()).into());
+            }
+            objects = vec![with_resource_version(objects.remove(0), resource_version)?];
+        }
+        let scheduler = DefaultScheduler {
+            max_attempts: args.max_attempts,
+            cleanup: !args.no_cleanup,
+            verbose: args.verbose,
+            seed: args.seed,
+            ..Default::default()
+        };
+
+        // the progress lines go to stderr so stdout holds only the json
+        let redirect = match args.output {
+            ApplyOutput::Json => Some(StdoutToStderr::new()),
+            ApplyOutput::Table => None,
+        };
+        let result = Self::apply_supported_resources(deps, &config, objects, args.dry_run, args.override_policy, &scheduler).await;
+        drop(redirect);
+        let result = result?;
+
+        let rows = result_rows(&result);
+        match args.output {
+            ApplyOutput::Json => println!("{}", serde_json::to_string_pretty(&rows).map_err(|e| anyhow!(e).context("failed to serialize result"))?),
+            ApplyOutput::Table => {
+                let mut table = Table::new(&rows);
+                table.with(Style::empty());
+                println!("{}", table);
+            }
+        }
+
+        Ok(())
     }
     
     pub async fn apply_self(&self, args: ApplyArgs) -> Result<(), SkateError> {
         Self::apply(&self.deps, args).await
     }
 
-    pub(crate) async fn apply_supported_resources(deps: &D, config: &Config, resources: Vec<SupportedResources>, dry_run: bool) -> Result<(), SkateError> {
-        let cluster = config.active_cluster(config.current_context.clone())?;
-        let ssh_manager = deps.get();
-        let (conns, errors) = ssh_manager.cluster_connect(cluster).await;
+    pub(crate) async fn apply_supported_resources(deps: &D, config: &Config, resources: Vec<SupportedResources>, dry_run: bool, override_policy: bool, scheduler: &DefaultScheduler) -> Result<ScheduleResult, SkateError> {
+        let (client, errors) = Client::connect_with(deps, config.clone(), config.current_context.clone()).await?;
         if let Some(e) = errors {
             for e in e.errors {
                 eprintln!("{} - {}", e.node_name, e.error)
             }
         };
+        client.apply_objects(resources, dry_run, override_policy, scheduler).await
+    }
 
-        if conns.is_none() {
-            return Err(anyhow!("failed to create cluster connections").into());
-        };
+    // applies the resources over connections already made to the cluster's nodes
+    pub(crate) async fn apply_connected(cluster: &Cluster, config: &Config, conns: &SshClients, resources: Vec<SupportedResources>, dry_run: bool, override_policy: bool, scheduler: &DefaultScheduler) -> Result<ScheduleResult, SkateError> {
+        let objects = prepare_objects(cluster, resources)?;
+        Self::apply_prepared(cluster, config, conns, objects, dry_run, override_policy, scheduler, "apply").await
+    }
 
-        let objects: Vec<Result<_, _>> = resources.into_iter().map(|sr| sr.fixup()).collect();
-        let objects: Vec<_> = objects.into_iter().map(|sr| sr.unwrap()).collect();
+    // applies objects that have been prepared already, such as stored manifests that were edited or patched, holding the lock as the command
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn apply_prepared(cluster: &Cluster, config: &Config, conns: &SshClients, objects: Vec<SupportedResources>, dry_run: bool, override_policy: bool, scheduler: &DefaultScheduler, command: &str) -> Result<ScheduleResult, SkateError> {
+        cluster.authorize_namespaces(&objects.iter().map(|o| o.name().namespace).collect::<Vec<_>>())?;
+        check_policy(cluster, &objects, override_policy)?;
 
-        let conns = conns.ok_or("no clients".to_string())?;
+        let run = Self::schedule_objects(cluster, config, conns, objects, dry_run, scheduler);
+        match dry_run {
+            true => run.await,
+            false => lock::locked(cluster, conns, command, run).await,
+        }
+    }
 
-        let mut state = Refresh::<D>::refreshed_state(&cluster.name, &conns, config).await.expect("failed to refresh state");
+    async fn schedule_objects(cluster: &Cluster, config: &Config, conns: &SshClients, objects: Vec<SupportedResources>, dry_run: bool, scheduler: &DefaultScheduler) -> Result<ScheduleResult, SkateError> {
+        let mut state = Refresh::<D>::refreshed_state(&cluster.name, conns, config).await?;
 
-        let scheduler = DefaultScheduler {};
-        match scheduler.schedule(&conns, &mut state, objects, dry_run).await {
-            Ok(_) => {}
+        let objects = objects.into_iter().map(|o| merge_last_applied(&state, o)).collect::<Result<Vec<_>, _>>()?;
+
+        match scheduler.schedule(conns, &mut state, objects, dry_run).await {
+            Ok(result) => {
+                let failures = result.placements.iter().filter_map(|op| {
+                    let error = op.error.as_ref()?;
+                    let node = op.node.as_ref().map(|n| n.node_name.as_str());
+                    let subject = format!("{} {}", op.resource, op.resource.name());
+                    Some(Notification::new(NotificationKind::ApplyFailed, &cluster.name, node, &subject, error))
+                });
+                let preemptions = result.placements.iter().filter_map(|op| {
+                    let preempted_by = op.preempted_by.as_ref().filter(|_| op.error.is_none())?;
+                    let node = op.node.as_ref().map(|n| n.node_name.as_str());
+                    let subject = format!("{} {}", op.resource, op.resource.name());
+                    Some(Notification::new(NotificationKind::Preempted, &cluster.name, node, &subject, &format!("evicted to make room for pod {}", preempted_by)))
+                });
+                notify(cluster, &failures.chain(preemptions).collect::<Vec<_>>()).await;
+                Ok(result)
+            }
             Err(e) => {
                 eprintln!("{}", e);
-                return Err(anyhow!("failed to schedule resources").into());
+                notify(cluster, &[Notification::new(NotificationKind::ApplyFailed, &cluster.name, None, "apply", &e.to_string())]).await;
+                Err(anyhow!("failed to schedule resources").into())
             }
         }
+    }
+}
 
-        Ok(())
+// refuses objects that violate the cluster's policies, unless they're overridden
+pub(crate) fn check_policy(cluster: &Cluster, objects: &[SupportedResources], override_policy: bool) -> Result<(), SkateError> {
+    let violations = policy::evaluate(&cluster.policies, objects);
+    if violations.is_empty() {
+        return Ok(());
+    }
+    let violations = violations.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("\n");
+    if !override_policy {
+        return Err(anyhow!("{}\nrefusing to apply due to policy violations, use --override-policy to apply anyway", violations).into());
     }
+    eprintln!("{}\napplying despite policy violations", violations);
+    Ok(())
+}
+
+// the objects as they'd be scheduled, with the cluster's defaults filled in
+pub(crate) fn prepare_objects(cluster: &Cluster, resources: Vec<SupportedResources>) -> Result<Vec<SupportedResources>, SkateError> {
+    if let Some(defaults) = cluster.defaults.as_ref() {
+        defaults::validate(defaults)?;
+    }
+    let resources = match Overlay::load(cluster)? {
+        Some(overlay) => resources.into_iter().map(|r| overlay.resolve(r, cluster.default_namespace())).collect::<Result<Vec<_>, _>>()?,
+        None => resources,
+    };
+    let objects = resources.into_iter().map(|sr| defaults::resolve(cluster.defaults.as_ref(), generate_name::resolve(sr)).fixup()).collect::<Result<Vec<_>, _>>()?;
+
+    ingress_class::validate(&cluster.ingress_classes)?;
+    let objects = objects.into_iter().map(|o| ingress_class::resolve(&cluster.ingress_classes, o)).collect::<Result<Vec<_>, _>>()?;
+    let objects = objects.into_iter().map(|o| priority::resolve(&cluster.priority_classes, o)).collect::<Result<Vec<_>, _>>()?;
+    let objects: Vec<_> = objects.into_iter().map(host_network::resolve).collect();
+    let objects = objects.into_iter().map(|o| sysctl::resolve(&cluster.allowed_unsafe_sysctls, o)).collect::<Result<Vec<_>, _>>()?;
+    limit_range::validate(&cluster.limit_ranges)?;
+    let objects = objects.into_iter().map(|o| limit_range::resolve(&cluster.limit_ranges, o)).collect::<Result<Vec<_>, _>>()?;
+    node_pool::validate(&cluster.node_pools, &cluster.nodes)?;
+    let objects = objects.into_iter().map(|o| node_pool::resolve(&cluster.node_pools, o)).collect::<Result<Vec<_>, _>>()?;
+    if let Some(logging) = cluster.logging.as_ref() {
+        logging::validate(logging)?;
+    }
+    Ok(objects.into_iter().map(|o| logging::resolve(cluster.logging.as_ref(), o)).collect())
+}
+
+// merges the manifest into the live object against the one last applied, see three_way_merge, and records it as the last applied.
+// objects applied before the annotation existed are replaced as a whole. secrets are always replaced, since the annotation
+// would hold a copy of their data. a manifest that already has the annotation is the live object modified, eg by edit or
+// patch, which replaces it as a whole and keeps what was last applied.
+// a resourceVersion in the manifest is a precondition on the live object, and the generation counts the changes to it
+pub(crate) fn merge_last_applied(state: &ClusterState, resource: SupportedResources) -> Result<SupportedResources, SkateError> {
+    let resource_type = ResourceType::from_str(&resource.to_string()).map_err(|e| anyhow!(e))?;
+    let name = resource.name();
+
+    let mut new = resource.manifest()?;
+    let live_item = state.catalogue(None, std::slice::from_ref(&resource_type)).into_iter()
+        .find(|item| item.object.name == name);
+
+    if let Some(expected) = new["metadata"]["resourceVersion"].as_str() {
+        check_resource_version(&resource_type, &name, expected, live_item.as_ref().map(|i| i.object.manifest_hash.as_str()))?;
+    }
+    if let Some(meta) = new["metadata"].as_object_mut() {
+        meta.remove("resourceVersion");
+    }
+
+    if matches!(resource, SupportedResources::Secret(_)) {
+        return validate_modified(&serde_yaml::to_value(&new)?, &name);
+    }
+
+    let live = live_item.and_then(|item| item.object.manifest.clone());
+    let live = live.map(|l| serde_json::to_value(&l)).transpose()?;
+    let last_applied = live.as_ref()
+        .and_then(|l| l["metadata"]["annotations"][LAST_APPLIED_ANNOTATION].as_str())
+        .and_then(|a| serde_json::from_str::<serde_json::Value>(a).ok());
+
+    if new["metadata"]["annotations"][LAST_APPLIED_ANNOTATION].is_string() {
+        new["metadata"]["generation"] = serde_json::Value::from(next_generation(live.as_ref(), &new));
+        return validate_modified(&serde_yaml::to_value(&new)?, &name);
+    }
+
+    let mut merged = match (&live, last_applied) {
+        (Some(live), Some(last_applied)) => three_way_merge(live.clone(), &last_applied, &new),
+        _ => new.clone(),
+    };
+    let annotations = &mut merged["metadata"]["annotations"];
+    if !annotations.is_object() {
+        *annotations = serde_json::json!({});
+    }
+    annotations[LAST_APPLIED_ANNOTATION] = serde_json::Value::String(serde_json::to_string(&new)?);
+    merged["metadata"]["generation"] = serde_json::Value::from(next_generation(live.as_ref(), &merged));
+
+    validate_modified(&serde_yaml::to_value(&merged)?, &name)
+}
+
+// the resourceVersion of an object is the hash of its live manifest, so a manifest saved with get -o yaml, or an --if-match,
+// only applies while the object is as it was when it was fetched
+pub(crate) fn check_resource_version(resource_type: &ResourceType, name: &NamespacedName, expected: &str, live_hash: Option<&str>) -> Result<(), SkateError> {
+    match live_hash {
+        Some(live) if live == expected => Ok(()),
+        Some(live) => Err(anyhow!("conflict: {} {} has changed since resourceVersion {}, it is now {}. fetch it again and reapply your changes",
+            resource_type, name, expected, live).into()),
+        None => Err(anyhow!("conflict: {} {} no longer exists, expected resourceVersion {}", resource_type, name, expected).into()),
+    }
+}
+
+// the live generation, bumped if the merged manifest changes the object. an object applied before generations starts at 1
+fn next_generation(live: Option<&serde_json::Value>, merged: &serde_json::Value) -> i64 {
+    let live = match live {
+        Some(live) => live,
+        None => return 1,
+    };
+    let generation = live["metadata"]["generation"].as_i64();
+    match without_bookkeeping(live) == without_bookkeeping(merged) {
+        true => generation.unwrap_or(1),
+        false => generation.unwrap_or(0) + 1,
+    }
+}
+
+// the manifest without what apply and the scheduler add to it, none of which changes the object
+fn without_bookkeeping(manifest: &serde_json::Value) -> serde_json::Value {
+    let mut manifest = manifest.clone();
+    if let Some(meta) = manifest["metadata"].as_object_mut() {
+        meta.remove("generation");
+        meta.remove("resourceVersion");
+    }
+    if let Some(labels) = manifest["metadata"]["labels"].as_object_mut() {
+        labels.remove("skate.io/hash");
+    }
+    if let Some(annotations) = manifest["metadata"]["annotations"].as_object_mut() {
+        annotations.remove(LAST_APPLIED_ANNOTATION);
+    }
+    manifest
+}
+
+// sets the resourceVersion the object must be at for it to be applied
+pub(crate) fn with_resource_version(resource: SupportedResources, resource_version: &str) -> Result<SupportedResources, SkateError> {
+    let mut value = resource.manifest()?;
+    value["metadata"]["resourceVersion"] = serde_json::Value::from(resource_version);
+    Ok(SupportedResources::try_from(&serde_yaml::to_value(&value)?)?)
 }
 
 pub fn read_manifests(filenames: Vec<String>) -> Result<Vec<SupportedResources>, Box<dyn Error>> {
@@ -84,6 +393,19 @@ pub fn read_manifests(filenames: Vec<String>) -> Result<Vec<SupportedResources>,
 
     let num_filenames = filenames.len();
 
+    // a directory stands for the yaml files in it, in name order
+    let filenames = filenames.into_iter().map(|filename| match Path::new(&filename).is_dir() {
+        true => {
+            let mut files: Vec<_> = fs::read_dir(&filename)?.flatten().map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|e| e == "yaml" || e == "yml"))
+                .map(|p| p.to_string_lossy().to_string())
+                .collect();
+            files.sort();
+            Ok(files)
+        }
+        false => Ok(vec![filename]),
+    }).collect::<Result<Vec<_>, io::Error>>()?.concat();
+
     for filename in filenames {
         let str_file = {
             if num_filenames == 1 && filename == "-" {
@@ -92,15 +414,184 @@ pub fn read_manifests(filenames: Vec<String>) -> Result<Vec<SupportedResources>,
                 stdin.read_to_string(&mut buffer)?;
                 buffer
             } else {
-                fs::read_to_string(filename).expect("failed to read file")
+                fs::read_to_string(&filename).map_err(|e| anyhow!(e).context(format!("failed to read {}", filename)))?
             }
         };
-        for document in serde_yaml::Deserializer::from_str(&str_file) {
-            let value = Value::deserialize(document).expect("failed to read document");
-            if let Value::Mapping(_) = &value {
-                result.push(SupportedResources::try_from(&value)?)
-            }
-        }
+        result.extend(parse_manifests(&str_file)?);
     };
     Ok(result)
-}
\ No newline at end of file
+}
+
+pub fn parse_manifests(yaml: &str) -> Result<Vec<SupportedResources>, Box<dyn Error>> {
+    let mut result = vec![];
+    for document in serde_yaml::Deserializer::from_str(yaml) {
+        let value = Value::deserialize(document).map_err(|e| anyhow!(e).context("failed to read document"))?;
+        if let Value::Mapping(_) = &value {
+            result.push(parse_manifest(value)?)
+        }
+    }
+    Ok(result)
+}
+
+fn parse_manifest(value: Value) -> Result<SupportedResources, Box<dyn Error>> {
+    let (value, conversion) = conversion::to_canonical(value)?;
+    if let Some(c) = conversion {
+        eprintln!("converted {} {} from {} to {}, update the manifest to the new version", c.kind,
+            value["metadata"]["name"].as_str().unwrap_or_default(), c.from, c.to);
+    }
+    SupportedResources::try_from(&value)
+}
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+    use k8s_openapi::api::apps::v1::{Deployment, DeploymentSpec};
+    use k8s_openapi::api::core::v1::Secret;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use serde_json::json;
+    use crate::apply::{check_policy, check_resource_version, merge_last_applied, next_generation, parse_manifests, result_rows, with_resource_version};
+    use crate::config::Cluster;
+    use crate::filestore::ObjectListItem;
+    use crate::state::state::ClusterState;
+    use crate::resource::{ResourceType, SupportedResources};
+    use crate::scheduler::{OpType, ScheduleAttempt, ScheduleResult, ScheduledOperation};
+    use crate::test_helpers;
+    use crate::util::{NamespacedName, LAST_APPLIED_ANNOTATION};
+
+    #[test]
+    fn test_result_rows() {
+        let secret = SupportedResources::Secret(Secret {
+            metadata: ObjectMeta {
+                labels: Some(BTreeMap::from([
+                    ("skate.io/name".to_string(), "foo".to_string()),
+                    ("skate.io/namespace".to_string(), "bar".to_string()),
+                ])),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        let mut created = ScheduledOperation::new(OpType::Create, secret.clone()).node(test_helpers::objects::node_state("node-1"));
+        created.duration = Some(Duration::from_millis(1500));
+        let mut failed = ScheduledOperation::new(OpType::Create, secret.clone()).error("no space left".to_string());
+        failed.attempts = vec![ScheduleAttempt { node_name: "node-2".to_string(), error: Some("no space left".to_string()) }];
+        let silent = ScheduledOperation::new(OpType::Delete, secret.clone()).silent();
+
+        let rows = result_rows(&ScheduleResult { placements: vec![created, failed, silent] });
+        assert_eq!(2, rows.len());
+        assert_eq!(("foo.bar", "Secret", "node-1", "created", Some(1500)), (rows[0].object.as_str(), rows[0].kind.as_str(), rows[0].node.as_str(), rows[0].action.as_str(), rows[0].duration_ms));
+        assert_eq!(("node-2", "failed"), (rows[1].node.as_str(), rows[1].action.as_str()));
+
+        let json = serde_json::to_value(&rows).unwrap();
+        assert_eq!(json[0]["duration_ms"], 1500);
+        assert!(json[0].get("error").is_none());
+        assert_eq!(json[1]["error"], "no space left");
+    }
+
+    #[test]
+    fn test_parse_manifests() {
+        let manifests = parse_manifests("apiVersion: v1\nkind: Secret\nmetadata:\n  name: db\n---\n").unwrap();
+        assert_eq!(1, manifests.len());
+        assert!(parse_manifests("apiVersion: v1\nkind: Secret\n  name: [db\n").is_err());
+    }
+
+    #[test]
+    fn test_merge_last_applied() {
+        let state = ClusterState { cluster_name: "test".to_string(), nodes: vec![test_helpers::objects::node_state("node-1")] };
+        let pod = SupportedResources::Pod(k8s_openapi::api::core::v1::Pod { metadata: ObjectMeta::from(NamespacedName::new("web", "shop")), ..Default::default() });
+
+        let merged = match merge_last_applied(&state, pod).unwrap() {
+            SupportedResources::Pod(p) => p,
+            _ => panic!("not a pod"),
+        };
+        let last_applied: serde_json::Value = serde_json::from_str(&merged.metadata.annotations.unwrap()[LAST_APPLIED_ANNOTATION]).unwrap();
+        assert_eq!(json!("Pod"), last_applied["kind"]);
+        assert_eq!(json!("web"), last_applied["metadata"]["name"]);
+        assert_eq!(Some(1), merged.metadata.generation);
+    }
+
+    #[test]
+    fn test_reapply_stores_bare_manifest() {
+        let deployment = |replicas| SupportedResources::Deployment(Deployment {
+            metadata: ObjectMeta::from(NamespacedName::new("web", "shop")),
+            spec: Some(DeploymentSpec { replicas: Some(replicas), ..Default::default() }),
+            ..Default::default()
+        });
+        let mut state = ClusterState { cluster_name: "test".to_string(), nodes: vec![test_helpers::objects::node_state("node-1")] };
+
+        let mut live = match merge_last_applied(&state, deployment(1)).unwrap() {
+            SupportedResources::Deployment(d) => d,
+            _ => panic!("not a deployment"),
+        };
+        // as the scheduler stores it
+        live.metadata.labels.get_or_insert_default().insert("skate.io/hash".to_string(), "abc".to_string());
+        state.nodes[0].host_info.as_mut().unwrap().system_info.as_mut().unwrap().deployments = Some(vec![ObjectListItem::from(&live)]);
+
+        let merged = match merge_last_applied(&state, deployment(2)).unwrap() {
+            SupportedResources::Deployment(d) => d,
+            _ => panic!("not a deployment"),
+        };
+        let last_applied: serde_json::Value = serde_json::from_str(&merged.metadata.annotations.as_ref().unwrap()[LAST_APPLIED_ANNOTATION]).unwrap();
+        assert_eq!(deployment(2).manifest().unwrap(), last_applied);
+        assert_eq!(json!(2), last_applied["spec"]["replicas"]);
+        assert!(last_applied["metadata"].get("annotations").is_none());
+        assert!(last_applied["metadata"].get("generation").is_none());
+        assert_eq!(Some(2), merged.metadata.generation);
+
+        // edited, the live object is replaced and what was last applied stays
+        let mut edited = merged.clone();
+        edited.spec.as_mut().unwrap().replicas = Some(3);
+        edited.spec.as_mut().unwrap().paused = Some(true);
+        state.nodes[0].host_info.as_mut().unwrap().system_info.as_mut().unwrap().deployments = Some(vec![ObjectListItem::from(&merged)]);
+        let stale = with_resource_version(SupportedResources::Deployment(edited.clone()), "stale").unwrap();
+        assert!(merge_last_applied(&state, stale).unwrap_err().to_string().contains("conflict"));
+        let replaced = match merge_last_applied(&state, SupportedResources::Deployment(edited)).unwrap() {
+            SupportedResources::Deployment(d) => d,
+            _ => panic!("not a deployment"),
+        };
+        assert_eq!((Some(3), Some(true)), (replaced.spec.as_ref().unwrap().replicas, replaced.spec.as_ref().unwrap().paused));
+        assert_eq!(merged.metadata.annotations, replaced.metadata.annotations);
+        assert_eq!(Some(3), replaced.metadata.generation);
+    }
+
+    #[test]
+    fn test_check_policy() {
+        let cluster: Cluster = serde_yaml::from_str("name: test\nnodes: []\npolicies:\n- rule: namespace-pattern\n  pattern: \"team-[a-z]+\"").unwrap();
+        let pod = |namespace| SupportedResources::Pod(k8s_openapi::api::core::v1::Pod { metadata: ObjectMeta::from(NamespacedName::new("web", namespace)), ..Default::default() });
+
+        assert!(check_policy(&cluster, &[pod("team-a")], false).is_ok());
+        let err = check_policy(&cluster, &[pod("shop")], false).unwrap_err().to_string();
+        assert!(err.contains("refusing to apply due to policy violations"), "{}", err);
+        assert!(check_policy(&cluster, &[pod("shop")], true).is_ok());
+    }
+
+    #[test]
+    fn test_check_resource_version() {
+        let name = NamespacedName::new("foo", "bar");
+        assert!(check_resource_version(&ResourceType::Deployment, &name, "abc", Some("abc")).is_ok());
+
+        let err = check_resource_version(&ResourceType::Deployment, &name, "abc", Some("def")).unwrap_err().to_string();
+        assert!(err.contains("has changed since resourceVersion abc, it is now def"), "{}", err);
+
+        let err = check_resource_version(&ResourceType::Deployment, &name, "abc", None).unwrap_err().to_string();
+        assert!(err.contains("no longer exists"), "{}", err);
+    }
+
+    #[test]
+    fn test_next_generation() {
+        let live = json!({
+            "metadata": {"name": "foo", "generation": 3, "labels": {"skate.io/name": "foo", "skate.io/hash": "abc"}, "annotations": {LAST_APPLIED_ANNOTATION: "{}"}},
+            "spec": {"replicas": 1},
+        });
+        let unchanged = json!({"metadata": {"name": "foo", "labels": {"skate.io/name": "foo"}, "annotations": {LAST_APPLIED_ANNOTATION: "{\"spec\":{}}"}}, "spec": {"replicas": 1}});
+        let changed = json!({"metadata": {"name": "foo"}, "spec": {"replicas": 2}});
+
+        assert_eq!(1, next_generation(None, &changed));
+        assert_eq!(3, next_generation(Some(&live), &unchanged));
+        assert_eq!(4, next_generation(Some(&live), &changed));
+
+        let mut before_generations = live.clone();
+        before_generations["metadata"].as_object_mut().unwrap().remove("generation");
+        assert_eq!(1, next_generation(Some(&before_generations), &changed));
+    }
+}
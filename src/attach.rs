@@ -0,0 +1,151 @@
+use anyhow::anyhow;
+use clap::Args;
+use crate::config::{Access, Config};
+use crate::deps::{SshManager, With};
+use crate::errors::SkateError;
+use crate::refresh::{Refresh, RefreshDeps};
+use crate::skate::ConfigFileArgs;
+use crate::skatelet::system::podman::{PodmanPodInfo, PodmanPodStatus};
+use crate::state::state::ClusterState;
+use crate::util::{split_resource_identifier, NamespacedName};
+
+#[derive(Debug, Args)]
+#[command(arg_required_else_help(true))]
+pub struct AttachArgs {
+    #[command(flatten)]
+    config: ConfigFileArgs,
+    #[arg(long, short, long_help = "Filter by resource namespace")]
+    namespace: Option<String>,
+    #[arg(long, short, long_help = "The container to attach to, defaults to the pod's first container.")]
+    container: Option<String>,
+    #[arg(long, short = 'i', long_help = "Send stdin to the container.")]
+    stdin: bool,
+    #[arg(long, short, long_help = "Use a terminal, for containers started with a tty. Detach with ctrl-p ctrl-q.")]
+    tty: bool,
+    #[arg(name = "POD | TYPE/NAME", long_help = "The pod, or a deployment or daemonset to attach to the first running pod of.")]
+    identifier: String,
+}
+
+impl AttachArgs {
+    // with stdin the container may be told to change anything it can
+    pub fn required_access(&self) -> Access {
+        match self.stdin {
+            true => Access::Deploy,
+            false => Access::ReadOnly,
+        }
+    }
+}
+
+pub trait AttachDeps: With<dyn SshManager> {}
+
+pub struct Attach<D: AttachDeps> {
+    pub deps: D,
+}
+
+// the running pod the identifier is for, and the node it's on
+pub(crate) fn find_pod(state: &ClusterState, identifier: &str, namespace: &str) -> Result<(PodmanPodInfo, String), SkateError> {
+    let (kind, name) = identifier.split_once('/').unwrap_or(("pod", identifier));
+    let matches = |pod: &PodmanPodInfo| -> Result<bool, SkateError> {
+        Ok(match kind {
+            "pod" => pod.name == name || pod.name == format!("{}.{}", name, namespace),
+            "deployment" | "daemonset" => pod.namespace() == namespace && pod.labels.get(&format!("skate.io/{}", kind)).is_some_and(|n| n == name),
+            _ => return Err(anyhow!("unexpected resource type {}, expected pod, deployment or daemonset", kind).into()),
+        })
+    };
+
+    let mut found = vec![];
+    for (pod, node) in state.filter_pods(&|_| true) {
+        if matches(&pod)? {
+            found.push((pod, node.node_name.clone()));
+        }
+    }
+    found.sort_by_key(|(p, _)| p.created);
+    match found.iter().find(|(p, _)| p.status == PodmanPodStatus::Running) {
+        Some(found) => Ok(found.clone()),
+        None if found.is_empty() => Err(anyhow!("{} not found in namespace {}", identifier, namespace).into()),
+        None => Err(anyhow!("{} isn't running", identifier).into()),
+    }
+}
+
+// podman names a pod's containers <pod>-<container>
+pub(crate) fn container_name(pod: &PodmanPodInfo, container: Option<&str>) -> Result<String, SkateError> {
+    let containers = pod.app_containers();
+    match container {
+        Some(container) => {
+            let name = format!("{}-{}", pod.name, container);
+            containers.iter().find(|c| c.names == name).map(|c| c.names.clone())
+                .ok_or(anyhow!("pod {} has no container {}", pod.name, container).into())
+        }
+        None => containers.first().map(|c| c.names.clone()).ok_or(anyhow!("pod {} has no containers", pod.name).into()),
+    }
+}
+
+pub(crate) fn attach_command(container: &str, stdin: bool) -> String {
+    match stdin {
+        true => format!("sudo podman attach {}", container),
+        false => format!("sudo podman attach --no-stdin {}", container),
+    }
+}
+
+impl<D: AttachDeps + RefreshDeps> Attach<D> {
+    pub async fn attach(&self, args: AttachArgs) -> Result<(), SkateError> {
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), args.required_access())?;
+        let (conns, errors) = self.deps.get().cluster_connect(cluster).await;
+        if let Some(errors) = errors {
+            eprintln!("{}", errors)
+        }
+        let conns = conns.ok_or(anyhow!("failed to connect to any node"))?;
+        let state = Refresh::<D>::refreshed_state(&cluster.name, &conns, &config).await?;
+
+        let (kind, name) = split_resource_identifier(&args.identifier, &["deployment", "daemonset"]);
+        let name = NamespacedName::from_arg(name, args.namespace.as_deref(), Some(cluster.default_namespace()))?;
+        let (pod, node) = find_pod(&state, &format!("{}/{}", kind, name.name), &name.namespace)?;
+        let container = container_name(&pod, args.container.as_deref())?;
+        let conn = conns.find(&node).ok_or(anyhow!("not connected to node {}", node))?;
+
+        if !args.tty {
+            eprintln!("attached to {} on {}", container, node);
+        }
+        match conn.execute_interactive(&attach_command(&container, args.stdin), args.stdin, args.tty).await? {
+            0 => Ok(()),
+            code => Err(anyhow!("{} exited with code {}", container, code).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Local};
+    use k8s_openapi::api::core::v1::{Pod, PodStatus};
+    use crate::attach::{attach_command, container_name, find_pod};
+    use crate::skatelet::system::podman::PodmanPodStatus;
+    use crate::test_helpers::objects::{cluster_state, node_state, pod, WithPod};
+
+    #[test]
+    fn test_find_pod() {
+        let running = |labels: &[(&str, &str)], name: &str| {
+            Pod { status: Some(PodStatus { phase: Some("Running".to_string()), ..Default::default() }), ..pod(name, "shop", labels) }
+        };
+        let mut node_1 = node_state("node-1").with_pod(&running(&[], "repl")).with_pod(&running(&[("skate.io/deployment", "web")], "web-1"));
+        let node_2 = node_state("node-2").with_pod(&running(&[("skate.io/deployment", "web")], "web-2"));
+        let pods = node_1.host_info.as_mut().unwrap().system_info.as_mut().unwrap().pods.as_mut().unwrap();
+        pods[1].created = Local::now() - Duration::minutes(5);
+        let state = cluster_state(vec![node_1, node_2]);
+
+        let (found, node) = find_pod(&state, "repl", "shop").unwrap();
+        assert_eq!(("repl.shop", "node-1"), (found.name.as_str(), node.as_str()));
+        assert_eq!("repl.shop", find_pod(&state, "pod/repl.shop", "shop").unwrap().0.name);
+        // the oldest running pod
+        assert_eq!("web-1.shop", find_pod(&state, "deployment/web", "shop").unwrap().0.name);
+        assert!(find_pod(&state, "repl", "default").is_err());
+        assert!(find_pod(&state, "service/web", "shop").is_err());
+
+        let mut stopped = state.clone();
+        stopped.nodes[0].host_info.as_mut().unwrap().system_info.as_mut().unwrap().pods.as_mut().unwrap()[0].status = PodmanPodStatus::Exited;
+        assert!(find_pod(&stopped, "repl", "shop").unwrap_err().to_string().contains("isn't running"));
+
+        assert!(container_name(&found, Some("missing")).is_err());
+        assert_eq!("sudo podman attach --no-stdin c", attach_command("c", false));
+    }
+}
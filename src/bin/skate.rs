@@ -12,7 +12,7 @@ async fn main() {
     match res {
         Ok(_) => (),
         Err(e) => {
-            eprintln!("{}", e.to_string());
+            eprintln!("{}", e);
             std::process::exit(1);
         }
     }
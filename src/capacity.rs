@@ -0,0 +1,58 @@
+use k8s_openapi::api::core::v1::Pod;
+use crate::skatelet::system::podman::PodmanPodStatus;
+use crate::state::state::NodeState;
+use crate::util::{quantity_to_bytes, quantity_to_cpus};
+
+// the memory the pod's containers are limited to, or request when they have no limit
+pub fn memory_request(pod: &Pod) -> u64 {
+    pod.spec.iter().flat_map(|s| s.containers.iter()).filter_map(|c| {
+        let resources = c.resources.as_ref()?;
+        let memory = resources.limits.as_ref().and_then(|l| l.get("memory"))
+            .or(resources.requests.as_ref().and_then(|r| r.get("memory")))?;
+        quantity_to_bytes(&memory.0)
+    }).sum()
+}
+
+// the memory the running pods on the node are limited to, what a new pod has to fit next to
+pub fn reserved_memory(node: &NodeState) -> u64 {
+    node.filter_pods(&|p| p.status == PodmanPodStatus::Running).iter()
+        .flat_map(|p| p.app_containers().into_iter().filter_map(|c| c.memory_limit))
+        .sum()
+}
+
+// why the node has no room for a pod limited to the memory, if it hasn't. the node's system reservation isn't available to pods
+pub fn insufficient_memory(node: &NodeState, requested: u64) -> Option<String> {
+    let total = node.host_info.as_ref()?.system_info.as_ref()?.total_memory_mib * 1024 * 1024;
+    if requested == 0 || total == 0 {
+        return None;
+    }
+    let unreserved = total.saturating_sub(node.reserved_system_memory()).saturating_sub(reserved_memory(node));
+    (requested > unreserved).then(|| format!("insufficient memory: {} MiB requested, {} MiB of {} MiB unreserved",
+        requested / 1024 / 1024, unreserved / 1024 / 1024, total / 1024 / 1024))
+}
+
+// the cpus the pod's containers are limited to, or request when they have no limit
+pub fn cpu_request(pod: &Pod) -> f64 {
+    pod.spec.iter().flat_map(|s| s.containers.iter()).filter_map(|c| {
+        let resources = c.resources.as_ref()?;
+        let cpu = resources.limits.as_ref().and_then(|l| l.get("cpu"))
+            .or(resources.requests.as_ref().and_then(|r| r.get("cpu")))?;
+        quantity_to_cpus(&cpu.0)
+    }).sum()
+}
+
+pub fn reserved_cpus(node: &NodeState) -> f64 {
+    node.filter_pods(&|p| p.status == PodmanPodStatus::Running).iter()
+        .flat_map(|p| p.app_containers().into_iter().filter_map(|c| c.cpu_limit))
+        .sum()
+}
+
+// why the node has no room for a pod limited to the cpus, if it hasn't
+pub fn insufficient_cpu(node: &NodeState, requested: f64) -> Option<String> {
+    let total = node.host_info.as_ref()?.system_info.as_ref()?.num_cpus as f64;
+    if requested <= 0.0 || total == 0.0 {
+        return None;
+    }
+    let unreserved = (total - node.reserved_cpus() - reserved_cpus(node)).max(0.0);
+    (requested > unreserved).then(|| format!("insufficient cpu: {} requested, {} of {} unreserved", requested, unreserved, total))
+}
@@ -0,0 +1,143 @@
+use std::marker::PhantomData;
+use anyhow::anyhow;
+use crate::apply::{parse_manifests, result_rows, Apply, ApplyDeps};
+use crate::config::Access;
+use crate::delete::{Delete, DeleteDeps};
+use crate::deps::{Deps, SshManager, With};
+use crate::errors::SkateError;
+use crate::evict::owner_resource;
+use crate::refresh::{Refresh, RefreshDeps};
+use crate::resource::SupportedResources;
+use crate::scheduler::{DefaultScheduler, ScheduleResult};
+use crate::ssh::SshClients;
+use crate::state::state::OwnerRef;
+
+// what the library's users need to call the client, so they don't depend on skate's internal modules
+pub use crate::apply::ApplyResultRow;
+pub use crate::config::{Cluster, Config};
+pub use crate::resource::ResourceType;
+pub use crate::ssh::{SshError, SshErrors};
+pub use crate::state::state::ClusterState;
+pub use crate::util::NamespacedName;
+
+// skate's core operations for programs embedding the crate, on one cluster over connections made once. the cli commands
+// are wrappers around it. each method checks the context's profile allows what it does
+pub struct Client<D = Deps> {
+    config: Config,
+    cluster: Cluster,
+    conns: SshClients,
+    deps: PhantomData<D>,
+}
+
+impl Client {
+    // connects to the nodes of the context's cluster, the current context when none is given. the nodes that couldn't be
+    // reached are returned with the client, which works with the rest
+    pub async fn connect(config: Config, context: Option<String>) -> Result<(Self, Option<SshErrors>), SkateError> {
+        Self::connect_with(&Deps {}, config, context).await
+    }
+}
+
+impl<D: With<dyn SshManager>> Client<D> {
+    pub(crate) async fn connect_with(deps: &D, mut config: Config, context: Option<String>) -> Result<(Self, Option<SshErrors>), SkateError> {
+        let cluster = config.active_cluster(context)?.clone();
+        let (conns, errors) = deps.get().cluster_connect(&cluster).await;
+        let conns = conns.ok_or(anyhow!("failed to connect to any node of cluster {}", cluster.name))?;
+        // the operations below act on the current context
+        config.current_context = Some(cluster.name.clone());
+        Ok((Client { config, cluster, conns, deps: PhantomData }, errors))
+    }
+
+    pub fn cluster(&self) -> &Cluster {
+        &self.cluster
+    }
+}
+
+impl<D: RefreshDeps> Client<D> {
+    // the cluster's state, refreshed from its nodes
+    pub async fn state(&self) -> Result<ClusterState, SkateError> {
+        self.cluster.authorize(Access::ReadOnly)?;
+        Refresh::<D>::refreshed_state(&self.cluster.name, &self.conns, &self.config).await
+    }
+}
+
+impl<D: ApplyDeps> Client<D> {
+    // applies the yaml's manifests as skate apply does, giving a row for each object scheduled
+    pub async fn apply(&self, manifests: &str, dry_run: bool) -> Result<Vec<ApplyResultRow>, SkateError> {
+        let objects = parse_manifests(manifests)?;
+        let result = self.apply_objects(objects, dry_run, false, &DefaultScheduler::default()).await?;
+        Ok(result_rows(&result))
+    }
+
+    pub(crate) async fn apply_objects(&self, objects: Vec<SupportedResources>, dry_run: bool, override_policy: bool, scheduler: &DefaultScheduler) -> Result<ScheduleResult, SkateError> {
+        self.cluster.authorize(Access::Deploy)?;
+        Apply::<D>::apply_connected(&self.cluster, &self.config, &self.conns, objects, dry_run, override_policy, scheduler).await
+    }
+
+    // applies the deployment as it was last applied, with the replicas changed
+    pub async fn scale(&self, deployment: &NamespacedName, replicas: i32) -> Result<Vec<ApplyResultRow>, SkateError> {
+        self.cluster.authorize(Access::Deploy)?;
+        let state = self.state().await?;
+        let mut resource = owner_resource(&state, &OwnerRef::new(ResourceType::Deployment, deployment))?;
+        match &mut resource {
+            SupportedResources::Deployment(d) => d.spec.get_or_insert_with(Default::default).replicas = Some(replicas),
+            _ => return Err(anyhow!("{} isn't a deployment", deployment).into()),
+        }
+        let result = self.apply_objects(vec![resource], false, false, &DefaultScheduler::default()).await?;
+        Ok(result_rows(&result))
+    }
+}
+
+impl<D: DeleteDeps + RefreshDeps> Client<D> {
+    pub async fn delete(&self, resource_type: ResourceType, name: &NamespacedName) -> Result<(), SkateError> {
+        self.cluster.authorize(Access::Deploy)?;
+        self.cluster.authorize_namespaces(&[&name.namespace])?;
+        Delete::<D>::delete_connected(&self.cluster, &self.config, &self.conns, resource_type, name).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::{Client, Config, NamespacedName, ResourceType};
+    use crate::delete::DeleteDeps;
+    use crate::deps::{SshManager, With};
+    use crate::refresh::RefreshDeps;
+    use crate::test_helpers::ssh_mocks::MockSshManager;
+
+    struct TestDeps {}
+
+    impl With<dyn SshManager> for TestDeps {
+        fn get(&self) -> Box<dyn SshManager> {
+            Box::new(MockSshManager { unreachable: vec!["node-2".to_string()], ..Default::default() })
+        }
+    }
+
+    impl RefreshDeps for TestDeps {}
+    impl DeleteDeps for TestDeps {}
+
+    #[tokio::test]
+    async fn test_connect_read_only() {
+        let config: Config = serde_yaml::from_str(r#"
+current-context: prod
+clusters:
+- name: prod
+  default_user: null
+  default_key: null
+  profile:
+    access: read-only
+  nodes:
+  - name: node-1
+    host: 10.0.0.1
+    subnet_cidr: 20.1.0.0/16
+  - name: node-2
+    host: 10.0.0.2
+    subnet_cidr: 20.2.0.0/16
+"#).unwrap();
+
+        let (client, errors) = Client::connect_with(&TestDeps {}, config, None).await.unwrap();
+        assert_eq!("prod", client.cluster().name);
+        assert_eq!(vec!["node-2"], errors.unwrap().errors.iter().map(|e| e.node_name.as_str()).collect::<Vec<_>>());
+
+        let err = client.delete(ResourceType::Deployment, &NamespacedName::new("web", "default")).await.unwrap_err();
+        assert_eq!("Error: context prod has read-only access, deploy access is needed", err.to_string());
+    }
+}
@@ -1,14 +1,36 @@
-use crate::config::Config;
+use std::collections::{BTreeMap, HashSet};
+use crate::config::{validate_system_reserved, Access, Config, Cluster as ClusterConfig, DefaultPage, IngressClass, Node};
+use crate::credentials::{validate_become, Become, CredentialSource};
+use crate::limit_range::{self, LimitRange};
+use crate::logging::{self, Logging};
+use crate::overlay::Overlay;
+use crate::node_pool::{self, NodePool};
+use crate::defaults::{self, ClusterDefaults};
 use crate::skate::ConfigFileArgs;
 use crate::ssh::{SshClients};
 use clap::{Args, Subcommand};
 use std::error::Error;
+use std::path::Path;
+use anyhow::anyhow;
+use futures::StreamExt;
+use itertools::Itertools;
+use k8s_openapi::api::networking::v1::Ingress;
+use serde::Deserialize;
+use crate::addon;
+use crate::apply::{Apply, ApplyArgs, ApplyOutput};
+use crate::create::{topology, CreateDeps};
+use crate::create::node::{install_cluster_manifests, provision_node, ProvisionOptions};
 use crate::deps::{SshManager, With};
 use crate::errors::SkateError;
+use crate::notify::NotificationTarget;
+use crate::policy::Policy;
+use crate::progress::progress;
 use crate::refresh::{Refresh, RefreshDeps};
-use crate::resource::SupportedResources;
-use crate::scheduler::{DefaultScheduler, Scheduler};
-use crate::state::state::ClusterState;
+use crate::resource::{ResourceType, SupportedResources};
+use crate::skatelet::system::podman::PodmanPodStatus;
+use crate::scheduler::{DefaultScheduler, Scheduler, DEFAULT_MAX_ATTEMPTS};
+use crate::state::state::{ClusterState, NodeStatus};
+use crate::util::{version, RE_CIDR, RE_IP};
 
 #[derive(Debug, Args)]
 pub struct ClusterArgs {
@@ -24,6 +46,194 @@ pub enum Commands {
         long_about = "Re-apply all resources in the cluster. Useful after cordon/uncordon or node creation"
     )]
     Reschedule(RescheduleArgs),
+    #[command(
+        long_about = "Create or update a cluster from a cluster.yaml, provisioning all of its nodes and applying any addon manifests"
+    )]
+    Up(UpArgs),
+    #[command(
+        long_about = "Summarize the cluster in one screen: its nodes and their health, versions, workloads, ingress hosts and node subnets"
+    )]
+    Info(InfoArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct InfoArgs {
+    #[command(flatten)]
+    pub config: ConfigFileArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct UpArgs {
+    #[command(flatten)]
+    pub config: ConfigFileArgs,
+    #[arg(short, long, long_help = "The cluster.yaml declaring the cluster.")]
+    pub filename: String,
+}
+
+// ClusterSpec is the declarative form of a cluster, as read from a cluster.yaml
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClusterSpec {
+    pub name: String,
+    pub default_user: Option<String>,
+    pub default_key: Option<String>,
+    pub nodes: Vec<NodeSpec>,
+    #[serde(default)]
+    pub bootstrap: BootstrapOptions,
+    #[serde(default)]
+    pub policies: Vec<Policy>,
+    #[serde(default)]
+    pub notifications: Vec<NotificationTarget>,
+    pub cert_expiry_days: Option<u32>,
+    #[serde(default)]
+    pub firewall: bool,
+    #[serde(default)]
+    pub ingress_classes: Vec<IngressClass>,
+    #[serde(default)]
+    pub priority_classes: BTreeMap<String, i32>,
+    #[serde(default)]
+    pub limit_ranges: Vec<LimitRange>,
+    pub credentials: Option<CredentialSource>,
+    #[serde(rename = "become")]
+    pub become_: Option<Become>,
+    pub logging: Option<Logging>,
+    pub ingress_default_page: Option<DefaultPage>,
+    #[serde(default)]
+    pub node_pools: Vec<NodePool>,
+    pub defaults: Option<ClusterDefaults>,
+    pub overlay: Option<String>,
+    #[serde(default)]
+    pub allowed_unsafe_sysctls: Vec<String>,
+    // manifests applied once all nodes are provisioned, relative to the cluster.yaml, or the names of built in addons
+    #[serde(default)]
+    pub addons: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeSpec {
+    pub name: String,
+    pub host: String,
+    pub peer_host: Option<String>,
+    pub port: Option<u16>,
+    pub user: Option<String>,
+    pub key: Option<String>,
+    pub subnet_cidr: String,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub system_reserved: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BootstrapOptions {
+    #[serde(default = "default_true")]
+    pub upgrade_packages: bool,
+    // how many nodes to provision at once, all of them by default
+    pub concurrency: Option<usize>,
+}
+
+impl Default for BootstrapOptions {
+    fn default() -> Self {
+        BootstrapOptions {
+            upgrade_packages: true,
+            concurrency: None,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl ClusterSpec {
+    pub fn validate(&self) -> Result<(), SkateError> {
+        let mut errors = vec!();
+        if self.nodes.is_empty() {
+            errors.push("no nodes declared".to_string());
+        }
+
+        let mut names = HashSet::new();
+        let mut subnets = HashSet::new();
+        for node in &self.nodes {
+            if !names.insert(&node.name) {
+                errors.push(format!("node {} is declared more than once", node.name));
+            }
+            if !RE_CIDR.is_match(&node.subnet_cidr) {
+                errors.push(format!("node {}: subnetCidr must be a valid ipv4 cidr range", node.name));
+            } else if !subnets.insert(&node.subnet_cidr) {
+                errors.push(format!("node {}: subnetCidr {} is used by another node", node.name, node.subnet_cidr));
+            }
+            if let Err(e) = validate_system_reserved(&node.system_reserved) {
+                errors.push(format!("node {}: systemReserved: {}", node.name, e));
+            }
+            if let Some(peer_host) = &node.peer_host {
+                if !RE_IP.is_match(peer_host) {
+                    errors.push(format!("node {}: peerHost must be a valid ipv4 address", node.name));
+                }
+            }
+        }
+
+        if let Err(e) = limit_range::validate(&self.limit_ranges) {
+            errors.push(e.to_string());
+        }
+        if let Some(Err(e)) = self.logging.as_ref().map(logging::validate) {
+            errors.push(e.to_string());
+        }
+        if let Err(e) = node_pool::validate(&self.node_pools, &self.to_cluster().nodes) {
+            errors.push(e.to_string());
+        }
+        if let Some(Err(e)) = self.defaults.as_ref().map(defaults::validate) {
+            errors.push(e.to_string());
+        }
+        if let Some(Err(e)) = self.become_.as_ref().map(|b| validate_become(b, self.credentials.as_ref())) {
+            errors.push(e);
+        }
+        if let Err(e) = Overlay::load(&self.to_cluster()) {
+            errors.push(e.to_string());
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(anyhow!("invalid cluster spec:\n{}", errors.join("\n")).into()),
+        }
+    }
+
+    pub fn to_cluster(&self) -> ClusterConfig {
+        ClusterConfig {
+            name: self.name.clone(),
+            default_user: self.default_user.clone(),
+            default_key: self.default_key.clone(),
+            nodes: self.nodes.iter().map(|n| Node {
+                name: n.name.clone(),
+                host: n.host.clone(),
+                peer_host: n.peer_host.clone().unwrap_or(n.host.clone()),
+                subnet_cidr: n.subnet_cidr.clone(),
+                port: n.port,
+                user: n.user.clone(),
+                key: n.key.clone(),
+                labels: n.labels.clone(),
+                system_reserved: n.system_reserved.clone(),
+            }).collect(),
+            policies: self.policies.clone(),
+            notifications: self.notifications.clone(),
+            cert_expiry_days: self.cert_expiry_days,
+            profile: None,
+            firewall: self.firewall,
+            ingress_classes: self.ingress_classes.clone(),
+            priority_classes: self.priority_classes.clone(),
+            limit_ranges: self.limit_ranges.clone(),
+            credentials: self.credentials.clone(),
+            become_: self.become_.clone(),
+            logging: self.logging.clone(),
+            ingress_default_page: self.ingress_default_page.clone(),
+            node_pools: self.node_pools.clone(),
+            defaults: self.defaults.clone(),
+            overlay: self.overlay.clone(),
+            allowed_unsafe_sysctls: self.allowed_unsafe_sysctls.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Args)]
@@ -32,10 +242,12 @@ pub struct RescheduleArgs {
     pub config: ConfigFileArgs,
     #[arg(long, long_help = "Will not affect the cluster if set to true")]
     pub dry_run: bool,
+    #[arg(long, long_help = "Leave the copies of moved resources running on their previous nodes.")]
+    pub no_cleanup: bool,
 }
 
 
-pub trait ClusterDeps: With<dyn SshManager> + RefreshDeps {}
+pub trait ClusterDeps: With<dyn SshManager> + RefreshDeps + CreateDeps {}
 
 pub struct Cluster<D: ClusterDeps> {
     pub deps: D,
@@ -49,14 +261,125 @@ impl<D: ClusterDeps> Cluster<D> {
                 args.config = global_args.config;
                 self.reschedule(args).await
             }
+            Commands::Up(args) => {
+                let mut args = args;
+                args.config = global_args.config;
+                self.up(args).await
+            }
+            Commands::Info(args) => {
+                let mut args = args;
+                args.config = global_args.config;
+                self.info(args).await
+            }
         }
     }
 
+    pub async fn up(&self, args: UpArgs) -> Result<(), SkateError> {
+        let spec_yaml = std::fs::read_to_string(&args.filename).map_err(|e| anyhow!(e).context(format!("failed to read {}", args.filename)))?;
+        let spec: ClusterSpec = serde_yaml::from_str(&spec_yaml).map_err(|e| anyhow!(e).context(format!("failed to parse {}", args.filename)))?;
+        spec.validate()?;
+
+        let mut config = Config::load(args.config.skateconfig.clone())?;
+        let mut cluster = spec.to_cluster();
+
+        // the region and zone labels of each node, unless the spec sets them
+        let detected = futures::future::join_all(cluster.nodes.iter().map(|node| async {
+            match self.deps.get().node_connect(&cluster, node).await {
+                Ok(conn) => topology::detect(conn.as_ref()).await,
+                Err(_) => BTreeMap::new(),
+            }
+        })).await;
+        for (node, labels) in cluster.nodes.iter_mut().zip(detected) {
+            topology::merge(&mut node.labels, labels);
+        }
+
+        match config.clusters.iter().any(|c| c.name == cluster.name) {
+            true => config.replace_cluster(&cluster)?,
+            false => config.clusters.push(cluster.clone()),
+        }
+        config.current_context = Some(cluster.name.clone());
+        config.persist(args.config.skateconfig.clone())?;
+        println!("wrote cluster {} to {}", cluster.name, Config::path(args.config.skateconfig.clone()));
+
+        let config_args = ConfigFileArgs {
+            skateconfig: args.config.skateconfig.clone(),
+            context: Some(cluster.name.clone()),
+        };
+        let opts = ProvisionOptions {
+            upgrade_packages: spec.bootstrap.upgrade_packages,
+            cluster_setup: false,
+        };
+        let concurrency = spec.bootstrap.concurrency.unwrap_or(cluster.nodes.len()).max(1);
+
+        let results: Vec<_> = futures::stream::iter(cluster.nodes.iter().map(|node| {
+            let (config_args, config, cluster, opts) = (&config_args, &config, &cluster, &opts);
+            async move {
+                let result = provision_node(&self.deps, config_args, config, cluster, node, opts, &mut vec!()).await;
+                match &result {
+                    Ok(_) => progress().finish(&node.name),
+                    Err(e) => progress().fail(&node.name, &e.to_string()),
+                }
+                (node.name.clone(), result)
+            }
+        })).buffer_unordered(concurrency).collect().await;
+
+        let failures: Vec<_> = results.into_iter()
+            .filter_map(|(name, result)| result.err().map(|e| format!("{}: {}", name, e)))
+            .collect();
+        if !failures.is_empty() {
+            return Err(anyhow!("failed to provision {} of {} nodes:\n{}", failures.len(), cluster.nodes.len(), failures.join("\n")).into());
+        }
+
+        install_cluster_manifests(&self.deps, &config_args, &cluster).await?;
+
+        let (builtin, files): (Vec<_>, Vec<_>) = spec.addons.iter().cloned().partition(|a| addon::find(a).is_some());
+        if !builtin.is_empty() {
+            println!("enabling addons {}", builtin.join(", "));
+            addon::enable(&self.deps, &config, &builtin, false).await?;
+        }
+        if !files.is_empty() {
+            let base = Path::new(&args.filename).parent().unwrap_or(Path::new(""));
+            let filename = files.iter().map(|a| base.join(a).to_string_lossy().to_string()).collect();
+            println!("applying addons");
+            Apply::<D>::apply(&self.deps, ApplyArgs {
+                filename,
+                kustomize: None,
+                grace_period: 0,
+                config: config_args.clone(),
+                dry_run: false,
+                override_policy: false,
+                max_attempts: DEFAULT_MAX_ATTEMPTS,
+                no_cleanup: false,
+                output: ApplyOutput::Table,
+                verbose: false,
+                if_match: None,
+                seed: None,
+            }).await?;
+        }
+
+        println!("cluster {} is up", cluster.name);
+        Ok(())
+    }
+
+
+    pub async fn info(&self, args: InfoArgs) -> Result<(), SkateError> {
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), Access::ReadOnly)?;
+        let (conns, errors) = self.deps.get().cluster_connect(cluster).await;
+        if let Some(errors) = errors {
+            eprintln!("{}", errors)
+        }
+        let conns = conns.ok_or(anyhow!("failed to connect to any node"))?;
+        let state = Refresh::<D>::refreshed_state(&cluster.name, &conns, &config).await?;
+
+        println!("{}", info_lines(cluster, &Config::path(args.config.skateconfig.clone()), &state).join("\n"));
+        Ok(())
+    }
 
     pub async fn reschedule(&self, args: RescheduleArgs) -> Result<(), SkateError> {
-        let config = Config::load(Some(args.config.skateconfig.clone()))?;
+        let config = Config::load(args.config.skateconfig.clone())?;
 
-        let cluster = config.active_cluster(config.current_context.clone())?;
+        let cluster = config.authorized_cluster(config.current_context.clone(), Access::Admin)?;
 
         let ssh_mgr = self.deps.get();
         
@@ -66,12 +389,12 @@ impl<D: ClusterDeps> Cluster<D> {
 
         let state = Refresh::<D>::refreshed_state(&cluster.name, &conns, &config).await?;
 
-        self.propagate_existing_resources( &conns, None, &state, args.dry_run).await?;
+        self.propagate_existing_resources( &conns, None, &state, args.dry_run, !args.no_cleanup).await?;
 
         Ok(())
     }
 
-    async fn propagate_existing_resources(&self, all_conns: &SshClients, exclude_donor_node: Option<&str>, state: &ClusterState, dry_run: bool) -> Result<(), Box<dyn Error>> {
+    async fn propagate_existing_resources(&self, all_conns: &SshClients, exclude_donor_node: Option<&str>, state: &ClusterState, dry_run: bool, cleanup: bool) -> Result<(), Box<dyn Error>> {
         let catalogue = state.catalogue(None, &[]);
 
         let all_manifests: Result<Vec<SupportedResources>, _> = catalogue.iter().map(|item| SupportedResources::try_from(item.object)).collect();
@@ -84,10 +407,142 @@ impl<D: ClusterDeps> Cluster<D> {
 
         println!("rescheduling {} resources across {} nodes", all_manifests.len(), filtered_state.nodes.len());
 
-        let scheduler = DefaultScheduler {};
+        let scheduler = DefaultScheduler { cleanup, ..Default::default() };
 
         scheduler.schedule(all_conns, &mut filtered_state, all_manifests, dry_run).await?;
 
         Ok(())
     }
 }
+
+// the distinct values with how many nodes have each, eg `1.2.0 (2), 1.1.0 (1)`
+fn counted(values: impl Iterator<Item=Option<String>>) -> String {
+    let counts = values.map(|v| v.unwrap_or("none".to_string())).counts();
+    counts.into_iter().sorted_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)))
+        .map(|(v, n)| format!("{} ({})", v, n))
+        .join(", ")
+}
+
+pub(crate) fn info_lines(cluster: &ClusterConfig, config_path: &str, state: &ClusterState) -> Vec<String> {
+    let mut lines = vec![format!("{:<12}{} ({})", "Context:", cluster.name, config_path)];
+
+    let healthy = state.nodes.iter().filter(|n| n.status == NodeStatus::Healthy).count();
+    lines.push(format!("{:<12}{} ({} healthy, {} not)", "Nodes:", state.nodes.len(), healthy, state.nodes.len() - healthy));
+    for node in state.nodes.iter().filter(|n| n.status != NodeStatus::Healthy) {
+        lines.push(format!("  {:<10}{:<10}{}", node.node_name, node.status, node.message.clone().unwrap_or_default()));
+    }
+
+    let host_info = || state.nodes.iter().map(|n| n.host_info.as_ref());
+    lines.push(format!("{:<12}skate {}, skatelet {}, podman {}", "Versions:", version(false),
+        counted(host_info().map(|h| h.and_then(|h| h.skatelet_version.clone()))),
+        counted(host_info().map(|h| h.and_then(|h| h.podman_version.clone())))));
+
+    let pods = state.filter_pods(&|_| true);
+    let running = pods.iter().filter(|(p, _)| p.status == PodmanPodStatus::Running).count();
+    let count = |resource_type: ResourceType| state.catalogue(None, &[resource_type]).len();
+    lines.push(format!("{:<12}{} pods ({} running), {} deployments, {} daemonsets, {} cronjobs", "Workloads:", pods.len(), running,
+        count(ResourceType::Deployment), count(ResourceType::DaemonSet), count(ResourceType::CronJob)));
+    lines.push(format!("{:<12}{} services, {} ingresses", "Network:", count(ResourceType::Service), count(ResourceType::Ingress)));
+
+    let hosts: Vec<_> = state.catalogue(None, &[ResourceType::Ingress]).into_iter()
+        .filter_map(|i| serde_yaml::from_value::<Ingress>(i.object.manifest.clone()?).ok())
+        .flat_map(|i| i.spec.and_then(|s| s.rules).unwrap_or_default())
+        .map(|r| r.host.unwrap_or("*".to_string()))
+        .sorted().dedup().collect();
+    lines.push(format!("{:<12}{}", "Ingress:", match hosts.is_empty() {
+        true => "none".to_string(),
+        false => hosts.join(", "),
+    }));
+
+    lines.push(format!("{:<12}{}", "Subnets:", cluster.nodes.iter().map(|n| format!("{} {}", n.name, n.subnet_cidr)).join(", ")));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cluster::{info_lines, ClusterSpec};
+    use crate::state::state::{ClusterState, NodeStatus};
+    use crate::test_helpers::objects::{node_state, pod, WithPod};
+
+    #[test]
+    fn test_cluster_spec() {
+        let spec: ClusterSpec = serde_yaml::from_str(r#"
+name: prod
+defaultUser: ubuntu
+nodes:
+  - name: node-1
+    host: node-1.example.com
+    peerHost: 10.0.0.1
+    subnetCidr: 20.1.0.0/16
+    labels:
+      zone: a
+  - name: node-2
+    host: 10.0.0.2
+    subnetCidr: 20.2.0.0/16
+bootstrap:
+  concurrency: 1
+addons:
+  - addons/monitoring.yaml
+"#).unwrap();
+
+        assert!(spec.validate().is_ok());
+        assert!(spec.bootstrap.upgrade_packages);
+        assert_eq!(spec.bootstrap.concurrency, Some(1));
+
+        let cluster = spec.to_cluster();
+        assert_eq!(cluster.nodes[0].peer_host, "10.0.0.1");
+        assert_eq!(cluster.nodes[1].peer_host, "10.0.0.2");
+        assert_eq!(cluster.nodes[0].labels.get("zone"), Some(&"a".to_string()));
+        assert_eq!(cluster.default_user, Some("ubuntu".to_string()));
+    }
+
+    #[test]
+    fn test_cluster_spec_invalid() {
+        let spec: ClusterSpec = serde_yaml::from_str(r#"
+name: prod
+nodes:
+  - name: node-1
+    host: 10.0.0.1
+    subnetCidr: 20.1.0.0/16
+  - name: node-1
+    host: 10.0.0.2
+    subnetCidr: 20.1.0.0/16
+  - name: node-3
+    host: 10.0.0.3
+    subnetCidr: not-a-cidr
+"#).unwrap();
+
+        let err = spec.validate().unwrap_err().to_string();
+        assert!(err.contains("node node-1 is declared more than once"), "{}", err);
+        assert!(err.contains("subnetCidr 20.1.0.0/16 is used by another node"), "{}", err);
+        assert!(err.contains("node node-3: subnetCidr must be a valid ipv4 cidr range"), "{}", err);
+    }
+
+    #[test]
+    fn test_info_lines() {
+        let spec: ClusterSpec = serde_yaml::from_str(r#"
+name: prod
+nodes:
+  - name: node-1
+    host: 10.0.0.1
+    subnetCidr: 20.1.0.0/16
+  - name: node-2
+    host: 10.0.0.2
+    subnetCidr: 20.2.0.0/16
+"#).unwrap();
+        let mut node_2 = node_state("node-2");
+        node_2.status = NodeStatus::Unhealthy;
+        node_2.message = Some("Node is cordoned".to_string());
+        node_2.host_info.as_mut().unwrap().skatelet_version = None;
+        let state = ClusterState { cluster_name: "prod".to_string(), nodes: vec![node_state("node-1").with_pod(&pod("web", "shop", &[])), node_2] };
+
+        let lines = info_lines(&spec.to_cluster(), "~/.skate/config.yaml", &state);
+        assert_eq!("Context:    prod (~/.skate/config.yaml)", lines[0]);
+        assert_eq!("Nodes:      2 (1 healthy, 1 not)", lines[1]);
+        assert!(lines[2].contains("node-2") && lines[2].contains("Node is cordoned"), "{}", lines[2]);
+        assert!(lines[3].ends_with("skatelet 1.0.0 (1), none (1), podman 3.6.0 (2)"), "{}", lines[3]);
+        assert_eq!("Workloads:  1 pods (0 running), 0 deployments, 0 daemonsets, 0 cronjobs", lines[4]);
+        assert_eq!("Ingress:    none", lines[6]);
+        assert_eq!("Subnets:    node-1 20.1.0.0/16, node-2 20.2.0.0/16", lines[7]);
+    }
+}
@@ -1,16 +1,42 @@
 use anyhow::anyhow;
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
 use std::path::Path;
 use std::fs;
 use std::fs::{create_dir, File};
 use std::hash::{Hash};
 use crate::errors::SkateError;
+use crate::notify::NotificationTarget;
+use crate::policy::Policy;
+use crate::credentials::{Become, CredentialSource};
+use crate::limit_range::LimitRange;
+use crate::logging::Logging;
+use crate::node_pool::NodePool;
+use crate::defaults::ClusterDefaults;
+use crate::config::{Access, Profile};
+use crate::util::{quantity_to_bytes, quantity_to_cpus};
 
-#[derive(Serialize, Deserialize)]
+// the layout of the config file. a file of an older one is migrated when loaded
+pub const CONFIG_API_VERSION: &str = "skate.io/v1";
+// what files written before the config had a version are taken to be
+const UNVERSIONED: &str = "skate.io/v0";
+
+fn default_api_version() -> String {
+    CONFIG_API_VERSION.to_string()
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct Config {
+    #[serde(default = "default_api_version")]
+    pub api_version: String,
     pub current_context: Option<String>,
     pub clusters: Vec<Cluster>,
+    // the file each cluster was loaded from, when the config was merged from several
+    #[serde(skip)]
+    sources: BTreeMap<String, String>,
 }
 
 #[derive(Serialize, Deserialize, Hash, Clone)]
@@ -19,13 +45,87 @@ pub struct Cluster {
     pub default_user: Option<String>,
     pub default_key: Option<String>,
     pub nodes: Vec<Node>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub policies: Vec<Policy>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notifications: Vec<NotificationTarget>,
+    // how many days before an ingress certificate expires to notify about it, 14 if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert_expiry_days: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<Profile>,
+    // have skatelet manage each node's firewall, opening only the ports the cluster needs
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub firewall: bool,
+    // proxies besides the default nginx one, that ingresses pick with ingressClassName
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ingress_classes: Vec<IngressClass>,
+    // the priority of each priorityClassName pods may use, besides the built in system ones
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub priority_classes: BTreeMap<String, i32>,
+    // default requests and limits for the pods of each namespace
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub limit_ranges: Vec<LimitRange>,
+    // where the nodes' ssh key passphrases and sudo passwords are looked up
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<CredentialSource>,
+    // how the ssh user gets root, sudo without a password if not set
+    #[serde(rename = "become", default, skip_serializing_if = "Option::is_none")]
+    pub become_: Option<Become>,
+    // how the containers' logs are kept, podman's default if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logging: Option<Logging>,
+    // served for requests that no ingress matches, instead of the built in 404 page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ingress_default_page: Option<DefaultPage>,
+    // namespaces whose workloads only run on the nodes with the pool's labels
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub node_pools: Vec<NodePool>,
+    // the namespace, image registry and pull policy for what manifests and commands leave out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub defaults: Option<ClusterDefaults>,
+    // a file of patches merged into the manifests applied to this cluster, see overlay
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overlay: Option<String>,
+    // sysctls besides the safe ones that pods may set, a trailing * allows all with the prefix, eg net.core.*
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_unsafe_sysctls: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DefaultPage {
+    // eg 503 for a maintenance page
+    #[serde(default = "default_page_status")]
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+fn default_page_status() -> u16 {
+    404
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq)]
+pub struct IngressClass {
+    pub name: String,
+    pub http_port: u16,
+    pub https_port: u16,
+    // the internal port the proxy's certificate hooks listen on, https_port + 1 by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hook_port: Option<u16>,
+    // the nodes to run the proxy on, all of them if empty
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub node_selector: BTreeMap<String, String>,
 }
 
 
 fn default_string() -> String {
     "".to_string()
 }
-#[derive(Serialize, Deserialize, Clone, Debug, Hash)]
+#[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq)]
 pub struct Node {
     pub name: String,
     pub host: String,
@@ -38,9 +138,55 @@ pub struct Node {
     pub user: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub key: Option<String>,
+    // added to the labels skate reports for the node, for use in node selectors
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    // cpu and memory kept back for the os, sshd and podman, which the scheduler won't place pods on
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub system_reserved: BTreeMap<String, String>,
+}
+
+impl Node {
+    // the skate network's gateway on the node, the first address of its subnet. the cluster's names are resolved there
+    pub fn gateway(&self) -> String {
+        self.subnet_cidr.split(".").take(3).join(".") + ".1"
+    }
+}
+
+// parses a reservation like `cpu=500m,memory=1Gi`
+pub fn parse_system_reserved(value: &str) -> Result<BTreeMap<String, String>, String> {
+    let reserved: BTreeMap<String, String> = value.split(',').filter(|p| !p.trim().is_empty()).map(|pair| {
+        pair.split_once('=')
+            .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+            .ok_or(format!("expected resource=quantity, got {}", pair))
+    }).collect::<Result<_, _>>()?;
+    validate_system_reserved(&reserved)?;
+    Ok(reserved)
+}
+
+pub fn validate_system_reserved(reserved: &BTreeMap<String, String>) -> Result<(), String> {
+    for (resource, quantity) in reserved {
+        let valid = match resource.as_str() {
+            "cpu" => quantity_to_cpus(quantity).is_some(),
+            "memory" => quantity_to_bytes(quantity).is_some(),
+            _ => return Err(format!("unsupported resource {}, only cpu and memory can be reserved", resource)),
+        };
+        if !valid {
+            return Err(format!("invalid {} quantity {}", resource, quantity));
+        }
+    }
+    Ok(())
 }
 
 impl Config {
+    // the context's cluster, once the context's profile is found to allow the access
+    pub fn authorized_cluster(&self, name: Option<String>, access: Access) -> Result<&Cluster, SkateError> {
+        let cluster = self.active_cluster(name)?;
+        cluster.authorize(access)?;
+        Ok(cluster)
+    }
+
+    // the context's cluster, for code the command already authorized
     pub fn active_cluster(&self, name: Option<String>) -> Result<&Cluster, SkateError> {
         if self.clusters.is_empty() {
             return Err(anyhow!("no clusters in config").into());
@@ -69,12 +215,17 @@ impl Config {
 }
 
 
+// joined as paths so they use the platform's separator, eg C:\Users\NAME\.skate on windows
 pub fn config_dir() -> String {
-    return shellexpand::tilde("~/.skate").to_string();
+    Path::new(shellexpand::tilde("~").as_ref()).join(".skate").to_string_lossy().to_string()
 }
 
 pub fn cache_dir() -> String {
-    config_dir() + "/cache"
+    Path::new(&config_dir()).join("cache").to_string_lossy().to_string()
+}
+
+fn default_config_path() -> String {
+    Path::new(&config_dir()).join("config.yaml").to_string_lossy().to_string()
 }
 
 pub fn ensure_config() -> Result<(), SkateError> {
@@ -93,8 +244,10 @@ pub fn ensure_config() -> Result<(), SkateError> {
     let path = path.join("config.yaml");
 
     let default_config = Config {
+        api_version: default_api_version(),
         current_context: None,
         clusters: vec![],
+        sources: BTreeMap::new(),
     };
 
     if !path.exists() {
@@ -110,19 +263,134 @@ pub fn ensure_config() -> Result<(), SkateError> {
     Ok(())
 }
 
+// a change of the config's layout, from one version to the next
+struct Migration {
+    from: &'static str,
+    to: &'static str,
+    migrate: fn(&mut Mapping),
+}
+
+// in order, each one's `to` is the next one's `from`
+const MIGRATIONS: &[Migration] = &[
+    // the first versioned layout is the one unversioned files already have
+    Migration { from: UNVERSIONED, to: "skate.io/v1", migrate: |_| {} },
+];
+
+// brings the file's contents up to the latest version, returning the version it was at when anything besides the version
+// changed, which is when the file is worth rewriting
+fn migrate_config(config: &mut Value, migrations: &[Migration]) -> Result<Option<String>, SkateError> {
+    let config = config.as_mapping_mut().ok_or(anyhow!("config isn't a mapping"))?;
+    let key = Value::from("api-version");
+    let original = config.get(&key).map(|v| v.as_str().map(|s| s.to_string()).ok_or(anyhow!("api-version isn't a string"))).transpose()?
+        .unwrap_or(UNVERSIONED.to_string());
+    let mut unmigrated = config.clone();
+    unmigrated.remove(&key);
+
+    let mut version = original.clone();
+    while let Some(migration) = migrations.iter().find(|m| m.from == version) {
+        (migration.migrate)(config);
+        version = migration.to.to_string();
+    }
+    if !migrations.iter().any(|m| m.to == version) && version != UNVERSIONED {
+        return Err(anyhow!("unknown config api-version {}, this version of skate supports up to {}, upgrade skate", version, CONFIG_API_VERSION).into());
+    }
+    if version == original {
+        return Ok(None);
+    }
+    // first, so it's what's seen opening the file
+    config.remove(&key);
+    let changed = *config != unmigrated;
+    let mut versioned = Mapping::from_iter([(key, Value::from(version))]);
+    versioned.extend(std::mem::take(config));
+    *config = versioned;
+    Ok(changed.then_some(original))
+}
+
+// the env var listing config files to merge, separated by ':' like KUBECONFIG, or ';' on windows
+pub const SKATECONFIG_ENV: &str = "SKATECONFIG";
+// a config in this directory, or any above it, is used instead of the user's
+const PROJECT_CONFIG: &str = ".skate/config";
+
+// resolves the config files to read, in order of precedence: the path given with --skateconfig, the SKATECONFIG list,
+// the closest project config and finally the user's own config
+fn config_paths(path: Option<String>, env: Option<String>, cwd: Option<&Path>) -> Vec<String> {
+    if let Some(path) = path {
+        return vec![shellexpand::tilde(&path).to_string()];
+    }
+    let from_env: Vec<_> = std::env::split_paths(&env.unwrap_or_default())
+        .filter(|p| !p.as_os_str().is_empty())
+        .map(|p| shellexpand::tilde(&p.to_string_lossy()).to_string())
+        .collect();
+    if !from_env.is_empty() {
+        return from_env;
+    }
+    let project = cwd.and_then(|cwd| cwd.ancestors().map(|d| d.join(PROJECT_CONFIG)).find(|p| p.is_file()));
+    match project {
+        Some(project) => vec![project.to_string_lossy().to_string()],
+        None => vec![default_config_path()],
+    }
+}
+
 impl Config {
-    fn path(path: Option<String>) -> String {
-        let path = match path {
-            Some(path) => path,
-            None => config_dir() + "/config.yaml"
-        };
-        shellexpand::tilde(&path).to_string()
+    fn paths(path: Option<String>) -> Vec<String> {
+        config_paths(path, std::env::var(SKATECONFIG_ENV).ok(), std::env::current_dir().ok().as_deref())
+    }
+
+    // the file that changes are written to, and that new clusters are added to
+    pub fn path(path: Option<String>) -> String {
+        Self::paths(path).remove(0)
+    }
+
+    fn load_file(path: &str) -> Result<Config, SkateError> {
+        Self::load_migrated(path, MIGRATIONS, path == default_config_path())
+    }
+
+    // an older file is migrated when it's read. only the user's own config is rewritten, with the original kept next to it
+    // as <file>.<version>.bak, since rewriting drops its comments. project and SKATECONFIG files are migrated each time
+    fn load_migrated(path: &str, migrations: &[Migration], rewrite: bool) -> Result<Config, SkateError> {
+        let contents = fs::read_to_string(Path::new(path)).map_err(|e| anyhow!(e).context(format!("failed to open config file {}", path)))?;
+        let mut value: Value = serde_yaml::from_str(&contents).map_err(|e| anyhow!(e).context(format!("failed to read config file {}", path)))?;
+        let previous = migrate_config(&mut value, migrations).map_err(|e| anyhow!("failed to migrate config file {}: {}", path, e))?;
+        match previous {
+            Some(previous) if rewrite => {
+                let backup = format!("{}.{}.bak", path, previous.rsplit('/').next().unwrap_or_default());
+                let migrated = serde_yaml::to_string(&value)?;
+                // a file that can't be written is still used, migrated again on the next load
+                match fs::write(&backup, &contents).and_then(|_| fs::write(path, migrated)) {
+                    Ok(_) => eprintln!("migrated config file {} from {} to {}, the original is in {}", path, previous, CONFIG_API_VERSION, backup),
+                    Err(e) => eprintln!("failed to write migrated config file {}: {}", path, e),
+                }
+            }
+            Some(previous) => eprintln!("config file {} is at {}, update it to {}", path, previous, CONFIG_API_VERSION),
+            None => {}
+        }
+        let data: Config = serde_yaml::from_value(value).map_err(|e| anyhow!(e).context(format!("failed to read config file {}", path)))?;
+        Ok(data)
     }
+
+    // with several files, the first to set the current context or to define a cluster wins, and missing files are skipped
     pub fn load(path: Option<String>) -> Result<Config, SkateError> {
-        let path = Config::path(path);
-        let path = Path::new(&path);
-        let f = fs::File::open(path).map_err(|e|anyhow!(e).context("failed to open config file"))?;
-        let mut data: Config = serde_yaml::from_reader(f).map_err(|e|anyhow!(e).context("failed to read config file"))?;
+        Self::load_paths(Self::paths(path))
+    }
+
+    fn load_paths(paths: Vec<String>) -> Result<Config, SkateError> {
+        let mut data = match paths.len() {
+            1 => Self::load_file(&paths[0])?,
+            _ => {
+                let mut merged = Config { api_version: default_api_version(), current_context: None, clusters: vec![], sources: BTreeMap::new() };
+                for path in paths.iter().filter(|p| Path::new(p).exists()) {
+                    let config = Self::load_file(path)?;
+                    merged.current_context = merged.current_context.or(config.current_context);
+                    for cluster in config.clusters {
+                        if !merged.clusters.iter().any(|c| c.name == cluster.name) {
+                            merged.sources.insert(cluster.name.clone(), path.clone());
+                            merged.clusters.push(cluster);
+                        }
+                    }
+                }
+                merged
+            }
+        };
         data.enrich();
         Ok(data)
     }
@@ -138,10 +406,163 @@ impl Config {
     }
 
 
+    // each cluster is written back to the file it came from, new ones and the current context to the first file.
+    // a file's entries that were shadowed by a same-named cluster in an earlier file are kept as they are
     pub fn persist(&self, path: Option<String>) -> Result<(), SkateError> {
-        let path = Config::path(path);
-        let state_file = File::create(Path::new(&path)).map_err(|e| anyhow!(e).context("unable to read config file"))?;
-        serde_yaml::to_writer(state_file, self).map_err(|e|anyhow!(e).context("failed to write config file"))?;
+        self.persist_paths(&Self::paths(path))
+    }
+
+    fn persist_paths(&self, paths: &[String]) -> Result<(), SkateError> {
+        let primary = &paths[0];
+        let owned_by = |name: &str, path: &String| self.sources.get(name).unwrap_or(primary) == path;
+
+        for path in paths {
+            let existing = match (path == primary, Self::load_file(path)) {
+                (_, Ok(existing)) => Some(existing),
+                (true, Err(_)) => None,
+                // nothing was loaded from it, so there's nothing to write back
+                (false, Err(_)) => continue,
+            };
+            let mut clusters: Vec<_> = existing.as_ref().map(|e| e.clusters.iter()
+                .filter_map(|c| match owned_by(&c.name, path) {
+                    true => self.clusters.iter().find(|ours| ours.name == c.name).cloned(),
+                    false => Some(c.clone()),
+                })
+                .collect()).unwrap_or_default();
+            clusters.extend(self.clusters.iter()
+                .filter(|c| owned_by(&c.name, path) && !clusters.iter().any(|written| written.name == c.name))
+                .cloned()
+                .collect::<Vec<_>>());
+            let current_context = match path == primary {
+                true => self.current_context.clone(),
+                false => existing.and_then(|e| e.current_context),
+            };
+            let config = Config { api_version: default_api_version(), current_context, clusters, sources: BTreeMap::new() };
+
+            let state_file = File::create(Path::new(path)).map_err(|e| anyhow!(e).context("unable to read config file"))?;
+            serde_yaml::to_writer(state_file, &config).map_err(|e|anyhow!(e).context("failed to write config file"))?;
+        }
         Ok(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::collections::BTreeMap;
+    use std::path::Path;
+    use serde_yaml::Value;
+    use crate::config::config::{config_paths, default_config_path, migrate_config, parse_system_reserved, Config, Migration, CONFIG_API_VERSION, MIGRATIONS};
+    use crate::test_helpers::temp_dir::TempDir;
+
+    #[test]
+    fn test_parse_system_reserved() {
+        assert_eq!(BTreeMap::from([("cpu".to_string(), "500m".to_string()), ("memory".to_string(), "1Gi".to_string())]),
+            parse_system_reserved("cpu=500m, memory=1Gi").unwrap());
+        assert!(parse_system_reserved("disk=10Gi").unwrap_err().contains("unsupported resource disk"));
+        assert!(parse_system_reserved("memory=lots").is_err());
+        assert!(parse_system_reserved("cpu").is_err());
+    }
+
+    #[test]
+    fn test_config_paths() {
+        let dir = TempDir::new("config");
+        let nested = dir.join("project/src");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir_all(dir.join("project/.skate")).unwrap();
+        fs::write(dir.join("project/.skate/config"), "clusters: []").unwrap();
+
+        assert_eq!(vec!["/tmp/a.yaml"], config_paths(Some("/tmp/a.yaml".to_string()), Some("/tmp/b.yaml".to_string()), Some(&nested)));
+        #[cfg(unix)]
+        assert_eq!(vec!["/tmp/b.yaml", "/tmp/c.yaml"], config_paths(None, Some("/tmp/b.yaml::/tmp/c.yaml".to_string()), Some(&nested)));
+        #[cfg(windows)]
+        assert_eq!(vec![r"C:\b.yaml", r"D:\c.yaml"], config_paths(None, Some(r"C:\b.yaml;;D:\c.yaml".to_string()), Some(&nested)));
+        assert_eq!(vec![dir.join("project/.skate/config").to_string_lossy().to_string()], config_paths(None, Some("".to_string()), Some(&nested)));
+        assert_eq!(vec![default_config_path()], config_paths(None, None, Some(&dir)));
+    }
+
+    #[test]
+    fn test_migrate_config() {
+        // only the version changes, nothing to rewrite
+        let mut config: Value = serde_yaml::from_str("current-context: prod\nclusters: []\n").unwrap();
+        assert_eq!(None, migrate_config(&mut config, MIGRATIONS).unwrap());
+        assert_eq!(Some(CONFIG_API_VERSION), config["api-version"].as_str());
+        assert!(serde_yaml::to_string(&config).unwrap().starts_with("api-version:"));
+        assert_eq!(None, migrate_config(&mut config, MIGRATIONS).unwrap());
+
+        // later layouts are migrated through in order
+        let migrations = [
+            Migration { from: "skate.io/v0", to: "skate.io/v1", migrate: |_| {} },
+            Migration { from: "skate.io/v1", to: "skate.io/v2", migrate: |c| {
+                let context = c.remove("current-context").unwrap();
+                c.insert(Value::from("renamed"), context);
+            } },
+        ];
+        let mut config: Value = serde_yaml::from_str("current-context: prod\nclusters: []\n").unwrap();
+        assert_eq!(Some("skate.io/v0".to_string()), migrate_config(&mut config, &migrations).unwrap());
+        assert_eq!(Some("skate.io/v2"), config["api-version"].as_str());
+        assert_eq!(Some("prod"), config["renamed"].as_str());
+
+        let mut newer: Value = serde_yaml::from_str("api-version: skate.io/v9\nclusters: []\n").unwrap();
+        assert!(migrate_config(&mut newer, MIGRATIONS).is_err());
+    }
+
+    #[test]
+    fn test_load_migrates_file() {
+        let dir = TempDir::new("config-migrate");
+        let path = dir.join("config.yaml").to_string_lossy().to_string();
+        let original = "# mine\ncurrent-context: prod\nclusters: []\n";
+        let migrations = [
+            Migration { from: "skate.io/v0", to: CONFIG_API_VERSION, migrate: |c| {
+                c.insert(Value::from("clusters"), Value::Sequence(vec![]));
+            } },
+        ];
+        let renamed = [
+            Migration { from: "skate.io/v0", to: CONFIG_API_VERSION, migrate: |c| {
+                let context = c.remove("current-context").unwrap();
+                c.insert(Value::from("current-context"), Value::from(format!("{}-renamed", context.as_str().unwrap())));
+            } },
+        ];
+
+        // a version bump alone leaves the file as it is
+        fs::write(&path, original).unwrap();
+        let config = Config::load_migrated(&path, &migrations, true).unwrap();
+        assert_eq!(CONFIG_API_VERSION, config.api_version);
+        assert_eq!(Some("prod".to_string()), config.current_context);
+        assert_eq!(original, fs::read_to_string(&path).unwrap());
+        assert!(!Path::new(&format!("{}.v0.bak", path)).exists());
+
+        // a project or SKATECONFIG file isn't rewritten
+        let config = Config::load_migrated(&path, &renamed, false).unwrap();
+        assert_eq!(Some("prod-renamed".to_string()), config.current_context);
+        assert_eq!(original, fs::read_to_string(&path).unwrap());
+        assert!(!Path::new(&format!("{}.v0.bak", path)).exists());
+
+        let config = Config::load_migrated(&path, &renamed, true).unwrap();
+        assert_eq!(Some("prod-renamed".to_string()), config.current_context);
+        assert!(fs::read_to_string(&path).unwrap().starts_with(&format!("api-version: {}", CONFIG_API_VERSION)));
+        assert_eq!(original, fs::read_to_string(format!("{}.v0.bak", path)).unwrap());
+    }
+
+    #[test]
+    fn test_persist_keeps_shadowed_clusters() {
+        let dir = TempDir::new("config-persist");
+        let first = dir.join("first.yaml").to_string_lossy().to_string();
+        let second = dir.join("second.yaml").to_string_lossy().to_string();
+        fs::write(&first, "current-context: prod\nclusters:\n- name: prod\n  default_user: first\n  nodes: []\n").unwrap();
+        fs::write(&second, "clusters:\n- name: prod\n  default_user: second\n  nodes: []\n- name: dev\n  nodes: []\n").unwrap();
+        let paths = vec![first.clone(), second.clone()];
+
+        let mut config = Config::load_paths(paths.clone()).unwrap();
+        assert_eq!(Some("first".to_string()), config.clusters.iter().find(|c| c.name == "prod").unwrap().default_user);
+        config.clusters.retain(|c| c.name != "dev");
+        config.persist_paths(&paths).unwrap();
+
+        // the shadowed prod stays in the second file, only the deleted dev is gone from it
+        let written = Config::load_file(&second).unwrap();
+        assert_eq!(vec!["prod"], written.clusters.iter().map(|c| c.name.as_str()).collect::<Vec<_>>());
+        assert_eq!(Some("second".to_string()), written.clusters[0].default_user);
+        let written = Config::load_file(&first).unwrap();
+        assert_eq!(Some("first".to_string()), written.clusters[0].default_user);
+        assert_eq!(Some("prod".to_string()), written.current_context);
+    }
+}
@@ -1,4 +1,7 @@
 
+#[allow(clippy::module_inception)]
 mod config;
+mod profile;
 
-pub use config::*;
\ No newline at end of file
+pub use config::*;
+pub use profile::*;
\ No newline at end of file
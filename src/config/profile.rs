@@ -0,0 +1,97 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use crate::config::Cluster;
+use crate::errors::SkateError;
+
+// what a command needs to be allowed to do, shared with plugins so they can check it themselves
+pub use skate_plugin::Access;
+
+// limits what can be done through a context, so that a config can be shared without handing over the whole cluster.
+// it's enforced by the cli, the nodes are still reachable with the same ssh keys
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Hash, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    #[serde(default)]
+    pub access: Access,
+    // the namespaces a deploy profile may change, all of them when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub namespaces: Vec<String>,
+}
+
+impl Cluster {
+    // fails if the context's profile doesn't allow the access
+    pub fn authorize(&self, access: Access) -> Result<(), SkateError> {
+        let profile = match &self.profile {
+            Some(profile) => profile,
+            None => return Ok(()),
+        };
+        if access > profile.access {
+            return Err(anyhow!("context {} has {} access, {} access is needed", self.name, profile.access, access).into());
+        }
+        Ok(())
+    }
+
+    // fails if the context's profile limits the namespaces and any of them isn't allowed
+    pub fn authorize_namespaces<S: AsRef<str>>(&self, namespaces: &[S]) -> Result<(), SkateError> {
+        let profile = match &self.profile {
+            Some(profile) if profile.access == Access::Deploy && !profile.namespaces.is_empty() => profile,
+            _ => return Ok(()),
+        };
+        let denied: Vec<_> = namespaces.iter().map(|n| n.as_ref())
+            .filter(|n| !profile.namespaces.iter().any(|allowed| allowed == n))
+            .collect();
+        if !denied.is_empty() {
+            return Err(anyhow!("context {} can only deploy to namespaces {}, not {}", self.name, profile.namespaces.join(", "), denied.join(", ")).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use crate::config::{Access, Cluster, Profile};
+
+    fn cluster(profile: Option<Profile>) -> Cluster {
+        Cluster {
+            name: "test".to_string(),
+            default_user: None,
+            default_key: None,
+            nodes: vec![],
+            policies: vec![],
+            notifications: vec![],
+            cert_expiry_days: None,
+            profile,
+            firewall: false,
+            ingress_classes: vec![],
+            priority_classes: BTreeMap::new(),
+            limit_ranges: vec![],
+            credentials: None,
+            become_: None,
+            logging: None,
+            ingress_default_page: None,
+            node_pools: vec![],
+            defaults: None,
+            overlay: None,
+            allowed_unsafe_sysctls: vec![],
+        }
+    }
+
+    #[test]
+    fn test_authorize_namespaces() {
+        let deployer = cluster(Some(Profile { access: Access::Deploy, namespaces: vec!["web".to_string()] }));
+        assert!(deployer.authorize_namespaces(&["web"]).is_ok());
+        assert!(deployer.authorize_namespaces(&["web", "db"]).is_err());
+
+        let admin = cluster(None);
+        assert!(admin.authorize_namespaces(&["db"]).is_ok());
+
+        let viewer: Profile = serde_yaml::from_str("access: read-only").unwrap();
+        assert_eq!(Access::ReadOnly, viewer.access);
+        let viewer = cluster(Some(viewer));
+        assert!(viewer.authorize(Access::ReadOnly).is_ok());
+        assert_eq!("Error: context test has read-only access, deploy access is needed", viewer.authorize(Access::Deploy).unwrap_err().to_string());
+        assert!(admin.authorize(Access::Admin).is_ok());
+        assert!(Access::ReadOnly < Access::Deploy && Access::Deploy < Access::Admin);
+    }
+}
@@ -0,0 +1,67 @@
+use anyhow::anyhow;
+use dialoguer::Confirm;
+use tabled::settings::Style;
+use tabled::{Table, Tabled};
+use crate::errors::SkateError;
+use crate::interactive;
+
+// Target is something a destructive command is about to remove or disrupt, and the node it's on
+#[derive(Tabled, Debug, Clone, PartialEq)]
+#[tabled(rename_all = "UPPERCASE")]
+pub struct Target {
+    pub node: String,
+    pub kind: String,
+    pub name: String,
+}
+
+impl Target {
+    pub fn new(node: &str, kind: &str, name: &str) -> Self {
+        Target {
+            node: node.to_string(),
+            kind: kind.to_string(),
+            name: name.to_string(),
+        }
+    }
+}
+
+// lists the targets and asks whether to go ahead, unless yes is set.
+// without a terminal to ask on it refuses rather than hang, so scripts have to pass --yes
+pub fn confirm(prompt: &str, targets: &[Target], yes: bool) -> Result<bool, SkateError> {
+    confirm_with(prompt, targets, yes, interactive::can_prompt())
+}
+
+fn confirm_with(prompt: &str, targets: &[Target], yes: bool, interactive: bool) -> Result<bool, SkateError> {
+    // stderr, so that the list stays out of any --json output on stdout
+    if !targets.is_empty() {
+        let mut table = Table::new(targets);
+        table.with(Style::empty());
+        eprintln!("{}\n", table);
+    }
+
+    if yes {
+        return Ok(true);
+    }
+    if !interactive {
+        return Err(anyhow!("{} refusing to continue without confirmation, use --yes to skip it", prompt).into());
+    }
+
+    Confirm::new()
+        .with_prompt(prompt)
+        .wait_for_newline(true)
+        .interact()
+        .map_err(|e| anyhow!(e).context("failed to read confirmation").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::confirm::{confirm_with, Target};
+
+    #[test]
+    fn test_confirm_without_terminal() {
+        let targets = vec![Target::new("node-1", "Pod", "foo.default")];
+        assert!(confirm_with("Delete?", &targets, true, false).unwrap());
+
+        let err = confirm_with("Delete?", &targets, false, false).unwrap_err();
+        assert!(err.to_string().contains("--yes"));
+    }
+}
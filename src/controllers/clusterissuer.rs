@@ -35,8 +35,7 @@ impl ClusterIssuerController {
             self.store.write_file("clusterissuer", &ns_name.to_string(), "hash", hash.as_bytes())?;
         }
         // need to retemplate nginx.conf
-        self.ingress_controller.render_nginx_conf()?;
-        self.ingress_controller.reload()?;
+        self.ingress_controller.reload_all()?;
 
         Ok(())
     }
@@ -47,8 +46,7 @@ impl ClusterIssuerController {
         let _ = self.store.remove_object("clusterissuer", &ns_name.to_string())?;
 
         // need to retemplate nginx.conf
-        self.ingress_controller.render_nginx_conf()?;
-        self.ingress_controller.reload()?;
+        self.ingress_controller.reload_all()?;
 
         Ok(())
     }
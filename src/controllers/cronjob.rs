@@ -1,4 +1,4 @@
-use crate::cron::cron_to_systemd;
+use crate::cron::{cron_to_systemd, validate_time_zone, ZONEINFO_DIR};
 use crate::filestore::Store;
 use crate::template;
 use crate::util::metadata_name;
@@ -10,7 +10,10 @@ use std::error::Error;
 use std::fs;
 use std::io::Write;
 use crate::errors::SkateError;
+use crate::controllers::emptydir::EmptyDirs;
+use crate::logging::{self, LOG_DRIVER_ANNOTATION, LOG_MAX_SIZE_ANNOTATION};
 use crate::exec::{ShellExec};
+use crate::skatelet::prune::TTL_LABEL;
 
 pub struct CronjobController {
     store: Box<dyn Store>,
@@ -42,13 +45,16 @@ impl CronjobController {
         let spec = cron_job.spec.clone().unwrap_or_default();
         let timezone = spec.time_zone.unwrap_or_default();
 
+        validate_time_zone(&timezone, ZONEINFO_DIR)?;
         let systemd_timer_schedule = cron_to_systemd(&spec.schedule, &timezone)?;
 
         ////////////////////////////////////////////////////
         // extract pod spec and add file /pod.yaml
         ////////////////////////////////////////////////////
 
-        let pod_template_spec = spec.job_template.spec.unwrap_or_default().template;
+        let job_spec = spec.job_template.spec.unwrap_or_default();
+        let ttl = job_spec.ttl_seconds_after_finished;
+        let pod_template_spec = job_spec.template;
 
         let mut pod = Pod {
             spec: pod_template_spec.spec,
@@ -57,14 +63,30 @@ impl CronjobController {
         };
 
         pod.metadata.name = Some(format!("crn-{}", ns_name));
+        // the pod is made from the cronjob's metadata, which doesn't have the template's logging
+        let template_annotations = pod_template_spec.metadata.and_then(|m| m.annotations).unwrap_or_default();
+        for key in [LOG_DRIVER_ANNOTATION, LOG_MAX_SIZE_ANNOTATION] {
+            if let Some(value) = template_annotations.get(key) {
+                pod.metadata.annotations.get_or_insert_with(Default::default).insert(key.to_string(), value.clone());
+            }
+        }
+        let log_args = logging::play_args(pod.metadata.annotations.as_ref())?;
+        // lets the pods a cronjob ran be listed as its jobs
+        pod.metadata.labels.get_or_insert_with(Default::default).insert("skate.io/cronjob".to_string(), ns_name.name.clone());
+        // skatelet prune pods removes the finished job's pod once this has passed
+        if let Some(ttl) = ttl {
+            pod.metadata.labels.get_or_insert_with(Default::default).insert(TTL_LABEL.to_string(), ttl.to_string());
+        }
         let mut_spec = pod.spec.as_mut().unwrap();
         mut_spec.restart_policy = Some("Never".to_string());
+        EmptyDirs::new(self.execer.as_ref()).prepare(&format!("crn-{}", ns_name), mut_spec)?;
 
         let pod_string = serde_yaml::to_string(&pod).map_err(|e| anyhow!(e).context("failed to serialize manifest to yaml"))?;
         let pod_yaml_path = self.store.write_file("cronjob", &ns_name.to_string(), "pod.yaml", pod_string.as_bytes())?;
 
         // create the pod to test that it's valid
-        self.execer.exec("podman", &["kube", "play", "--start=false", "--replace", &pod_yaml_path]).map_err(|e| anyhow!(e.to_string()).context("failed to create pod"))?;
+        let args = [&["kube", "play", "--start=false", "--replace", &pod_yaml_path], log_args.iter().map(|a| a.as_str()).collect::<Vec<_>>().as_slice()].concat();
+        self.execer.exec("podman", &args).map_err(|e| anyhow!(e.to_string()).context("failed to create pod"))?;
 
         let mut handlebars = template::new();
         ////////////////////////////////////////////////////
@@ -107,8 +129,7 @@ impl CronjobController {
 
 
         self.execer.exec("systemctl", &["daemon-reload"])?;
-        self.execer.exec("systemctl", &["enable", &format!("{}.timer", &unit_name)])?;
-        self.execer.exec("systemctl", &["start", &format!("{}.timer", &unit_name)])?;
+        set_timer(self.execer.as_ref(), &unit_name, spec.suspend.unwrap_or_default())?;
         let _ = self.execer.exec("systemctl", &["reset-failed", &unit_name]);
 
         Ok(())
@@ -131,20 +152,72 @@ impl CronjobController {
         // systemctl reset-failed
         let _ = self.execer.exec("systemctl", &["reset-failed"])?;
         let _ = self.store.remove_object("cronjob", &ns_name.to_string())?;
+        EmptyDirs::new(self.execer.as_ref()).cleanup(&format!("crn-{}", ns_name))?;
         Ok(())
     }
 
     pub fn run(&self, name: &str, ns: &str, wait: bool) -> Result<(), SkateError> {
         let obj = self.store.get_object("cronjob", &format!("{}.{}", name, ns))?;
 
-        let args = &["kube", "play", &format!("{}/pod.yaml", obj.path), "--replace", "--network", "skate"];
+        let pod_yaml_path = format!("{}/pod.yaml", obj.path);
+        let pod: Pod = serde_yaml::from_str(&std::fs::read_to_string(&pod_yaml_path)?)?;
+        let log_args = logging::play_args(pod.metadata.annotations.as_ref())?;
+
+        let args = &["kube", "play", &pod_yaml_path, "--replace", "--network", "skate"];
+        let args = [args.to_vec(), log_args.iter().map(|a| a.as_str()).collect()].concat();
         let args = if wait {
-            [args.to_vec(), vec!["-w"]].concat()
+            [args, vec!["-w"]].concat()
         } else {
-            args.to_vec()
+            args
         };
 
         self.execer.exec_stdout("podman", &args)?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+// a suspended cronjob keeps its units so that it can be resumed, but the timer doesn't run
+fn set_timer(execer: &dyn ShellExec, unit_name: &str, suspend: bool) -> Result<(), Box<dyn Error>> {
+    let timer = format!("{}.timer", unit_name);
+    if suspend {
+        let _ = execer.exec("systemctl", &["disable", "--now", &timer]);
+    } else {
+        execer.exec("systemctl", &["enable", &timer])?;
+        execer.exec("systemctl", &["start", &timer])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::error::Error;
+    use crate::controllers::cronjob::set_timer;
+    use crate::exec::ShellExec;
+
+    #[derive(Default)]
+    struct MockExec {
+        commands: RefCell<Vec<String>>,
+    }
+
+    impl ShellExec for MockExec {
+        fn exec(&self, command: &str, args: &[&str]) -> Result<String, Box<dyn Error>> {
+            self.commands.borrow_mut().push(format!("{} {}", command, args.join(" ")));
+            Ok("".to_string())
+        }
+
+        fn exec_stdout(&self, command: &str, args: &[&str]) -> Result<(), Box<dyn Error>> {
+            self.exec(command, args).map(|_| ())
+        }
+    }
+
+    #[test]
+    fn test_set_timer() {
+        let execer = MockExec::default();
+        set_timer(&execer, "skate-cronjob-backup.shop", true).unwrap();
+        assert_eq!(vec!["systemctl disable --now skate-cronjob-backup.shop.timer"], *execer.commands.borrow());
+
+        let execer = MockExec::default();
+        set_timer(&execer, "skate-cronjob-backup.shop", false).unwrap();
+        assert_eq!(vec!["systemctl enable skate-cronjob-backup.shop.timer", "systemctl start skate-cronjob-backup.shop.timer"], *execer.commands.borrow());
+    }
+}
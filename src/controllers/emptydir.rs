@@ -0,0 +1,140 @@
+use std::error::Error;
+use anyhow::anyhow;
+use k8s_openapi::api::core::v1::{HostPathVolumeSource, PodSpec};
+use crate::exec::ShellExec;
+use crate::util::quantity_to_bytes;
+
+const EMPTY_DIR_PATH: &str = "/var/lib/skate/emptydir";
+
+// podman has no notion of emptyDir, so each one is backed by a directory on the host that is mounted into the pod as a hostPath.
+// medium: Memory volumes are tmpfs mounts, disk volumes with a sizeLimit are loop mounted images so the limit is enforced.
+pub struct EmptyDirs<'a> {
+    execer: &'a dyn ShellExec,
+}
+
+impl<'a> EmptyDirs<'a> {
+    pub fn new(execer: &'a dyn ShellExec) -> Self {
+        EmptyDirs { execer }
+    }
+
+    // prepare replaces the pod's emptyDir volumes with host paths, after creating fresh mounts for them
+    pub fn prepare(&self, pod_name: &str, spec: &mut PodSpec) -> Result<(), Box<dyn Error>> {
+        let commands = empty_dir_commands(pod_name, spec)?;
+        if commands.is_empty() {
+            return Ok(());
+        }
+
+        // emptyDir contents never outlive the pod
+        self.cleanup(pod_name)?;
+
+        for command in commands {
+            self.execer.exec("sh", &["-c", &command]).map_err(|e| anyhow!(e.to_string()).context("failed to create emptyDir volume"))?;
+        }
+        Ok(())
+    }
+
+    // cleanup unmounts and removes all emptyDir volumes created for the pod
+    pub fn cleanup(&self, pod_name: &str) -> Result<(), Box<dyn Error>> {
+        let dir = pod_dir(pod_name);
+        let script = format!(
+            r#"[ -d "{dir}" ] || exit 0; for m in "{dir}"/*; do if mountpoint -q "$m"; then umount "$m"; fi; done; rm -rf "{dir}""#,
+            dir = dir
+        );
+        self.execer.exec("sh", &["-c", &script]).map_err(|e| anyhow!(e.to_string()).context("failed to remove emptyDir volumes"))?;
+        Ok(())
+    }
+}
+
+fn pod_dir(pod_name: &str) -> String {
+    format!("{}/{}", EMPTY_DIR_PATH, pod_name)
+}
+
+// rewrites the spec's emptyDir volumes to host paths, returning the shell commands that create them
+fn empty_dir_commands(pod_name: &str, spec: &mut PodSpec) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut commands = vec!();
+
+    for volume in spec.volumes.iter_mut().flatten() {
+        let empty_dir = match volume.empty_dir.take() {
+            Some(e) => e,
+            None => continue,
+        };
+
+        let path = format!("{}/{}", pod_dir(pod_name), volume.name);
+        let size = match empty_dir.size_limit.as_ref() {
+            Some(q) => Some(quantity_to_bytes(&q.0).ok_or(anyhow!("invalid sizeLimit {} for emptyDir {}", q.0, volume.name))?),
+            None => None,
+        };
+
+        let mut command = format!("mkdir -p '{}'", path);
+        match (empty_dir.medium.as_deref().unwrap_or_default(), size) {
+            ("Memory", Some(size)) => command.push_str(&format!(" && mount -t tmpfs -o size={},mode=1777 tmpfs '{}'", size, path)),
+            ("Memory", None) => command.push_str(&format!(" && mount -t tmpfs -o mode=1777 tmpfs '{}'", path)),
+            ("", Some(size)) => command.push_str(&format!(
+                " && fallocate -l {size} '{path}.img' && mkfs.ext4 -q -F '{path}.img' && mount -o loop '{path}.img' '{path}' && chmod 1777 '{path}'",
+                size = size, path = path
+            )),
+            ("", None) => command.push_str(&format!(" && chmod 1777 '{}'", path)),
+            (medium, _) => return Err(anyhow!("unsupported emptyDir medium {} for volume {}", medium, volume.name).into()),
+        }
+        commands.push(command);
+
+        volume.host_path = Some(HostPathVolumeSource {
+            path,
+            type_: Some("Directory".to_string()),
+        });
+    }
+
+    Ok(commands)
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::{EmptyDirVolumeSource, PodSpec, Volume};
+    use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+    use crate::controllers::emptydir::empty_dir_commands;
+
+    fn empty_dir(name: &str, medium: Option<&str>, size: Option<&str>) -> Volume {
+        Volume {
+            name: name.to_string(),
+            empty_dir: Some(EmptyDirVolumeSource {
+                medium: medium.map(|m| m.to_string()),
+                size_limit: size.map(|s| Quantity(s.to_string())),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_empty_dir_commands() {
+        let mut spec = PodSpec {
+            volumes: Some(vec![
+                empty_dir("cache", Some("Memory"), Some("64Mi")),
+                empty_dir("scratch", None, Some("1Gi")),
+                empty_dir("tmp", None, None),
+                Volume { name: "other".to_string(), ..Default::default() },
+            ]),
+            ..Default::default()
+        };
+
+        let commands = empty_dir_commands("web.default", &mut spec).unwrap();
+        assert_eq!(commands, vec![
+            "mkdir -p '/var/lib/skate/emptydir/web.default/cache' && mount -t tmpfs -o size=67108864,mode=1777 tmpfs '/var/lib/skate/emptydir/web.default/cache'",
+            "mkdir -p '/var/lib/skate/emptydir/web.default/scratch' && fallocate -l 1073741824 '/var/lib/skate/emptydir/web.default/scratch.img' && mkfs.ext4 -q -F '/var/lib/skate/emptydir/web.default/scratch.img' && mount -o loop '/var/lib/skate/emptydir/web.default/scratch.img' '/var/lib/skate/emptydir/web.default/scratch' && chmod 1777 '/var/lib/skate/emptydir/web.default/scratch'",
+            "mkdir -p '/var/lib/skate/emptydir/web.default/tmp' && chmod 1777 '/var/lib/skate/emptydir/web.default/tmp'",
+        ]);
+
+        let volumes = spec.volumes.unwrap();
+        assert!(volumes.iter().all(|v| v.empty_dir.is_none()));
+        assert_eq!(volumes[0].host_path.as_ref().unwrap().path, "/var/lib/skate/emptydir/web.default/cache");
+        assert!(volumes[3].host_path.is_none());
+    }
+
+    #[test]
+    fn test_empty_dir_invalid() {
+        let mut spec = PodSpec { volumes: Some(vec![empty_dir("bad", Some("HugePages"), None)]), ..Default::default() };
+        assert!(empty_dir_commands("web.default", &mut spec).is_err());
+
+        let mut spec = PodSpec { volumes: Some(vec![empty_dir("bad", None, Some("lots"))]), ..Default::default() };
+        assert!(empty_dir_commands("web.default", &mut spec).is_err());
+    }
+}
@@ -1,15 +1,233 @@
 use crate::exec::{ShellExec};
 use crate::filestore::Store;
+use crate::ingress_class;
+use crate::ingress_class::{ClassSettings, INGRESS_DIR};
+use crate::skatelet::system::podman::PodmanSecret;
+use crate::skatelet::apply_progress::{self, ApplyEvent};
 use crate::spec::cert::ClusterIssuer;
-use crate::util::metadata_name;
+use crate::template;
+use crate::util::{metadata_name, NamespacedName};
 use anyhow::anyhow;
 use itertools::Itertools;
-use k8s_openapi::api::networking::v1::Ingress;
-use serde_json::json;
+use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::api::networking::v1::{Ingress, IngressBackend, IngressRule};
+use serde_json::{json, Value};
 use std::error::Error;
 use std::io::Write;
-use std::process::Stdio;
-use std::{fs, process};
+use std::net::IpAddr;
+use std::fs;
+
+// rendered by the skatelet rather than from the copies in the ingress image, so template changes don't wait on a new image
+const SERVICE_TEMPLATE: &str = include_str!("../../images/nginx-ingress/service.conf.tmpl");
+const NGINX_TEMPLATE: &str = include_str!("../../images/nginx-ingress/nginx.conf.tmpl");
+
+pub const CANARY_WEIGHT_ANNOTATION: &str = "skate.io/canary-weight";
+pub const CANARY_SERVICE_ANNOTATION: &str = "skate.io/canary-service";
+pub const LIMIT_RPS_ANNOTATION: &str = "skate.io/limit-rps";
+pub const LIMIT_BURST_ANNOTATION: &str = "skate.io/limit-burst";
+// the same annotations as ingress-nginx, the secret has the htpasswd file under the auth key
+pub const AUTH_TYPE_ANNOTATION: &str = "nginx.ingress.kubernetes.io/auth-type";
+pub const AUTH_SECRET_ANNOTATION: &str = "nginx.ingress.kubernetes.io/auth-secret";
+pub const AUTH_REALM_ANNOTATION: &str = "nginx.ingress.kubernetes.io/auth-realm";
+pub const ALLOWLIST_ANNOTATION: &str = "nginx.ingress.kubernetes.io/whitelist-source-range";
+// the newer name of the allowlist annotation
+pub const ALLOWLIST_V2_ANNOTATION: &str = "nginx.ingress.kubernetes.io/allowlist-source-range";
+pub const DENYLIST_ANNOTATION: &str = "nginx.ingress.kubernetes.io/denylist-source-range";
+
+// the template values for sending a share of the ingress's traffic to the canary service, on the same port as each path's backend.
+// requests are split by request id, so a client isn't pinned to either side
+fn canary_settings(ingress: &Ingress, port: u16) -> Result<Option<Value>, Box<dyn Error>> {
+    let annotations = ingress.metadata.annotations.clone().unwrap_or_default();
+    let weight = match annotations.get(CANARY_WEIGHT_ANNOTATION) {
+        Some(w) => w.trim().parse::<u8>().ok().filter(|w| *w <= 100)
+            .ok_or(anyhow!("{} must be a whole number from 0 to 100, got {}", CANARY_WEIGHT_ANNOTATION, w))?,
+        None => return Ok(None),
+    };
+    let service = annotations.get(CANARY_SERVICE_ANNOTATION)
+        .ok_or(anyhow!("{} requires the {} annotation", CANARY_WEIGHT_ANNOTATION, CANARY_SERVICE_ANNOTATION))?;
+    if weight == 0 {
+        return Ok(None);
+    }
+
+    // nginx variables are shared by every server, so it's unique per ingress and port
+    let variable = format!("canary_{}_{}", metadata_name(ingress).to_string().replace(|c: char| !c.is_ascii_alphanumeric(), "_"), port);
+    Ok(Some(json!({
+        "variable": variable,
+        "weight": weight,
+        "service": service,
+    })))
+}
+
+fn upstream(backend: &IngressBackend) -> Option<String> {
+    let service = backend.service.as_ref()?;
+    Some(format!("http://{}.svc.cluster.skate:{}", service.name, service.port.as_ref()?.number?))
+}
+
+fn is_hostless(rule: &IngressRule) -> bool {
+    rule.host.as_deref().unwrap_or_default().is_empty()
+}
+
+// the locations of the class's catch-all server, for requests that match no host. these come from the prefix paths of rules
+// without a host, and the default backend of ingresses without any host rules. where ingresses have the same path the first
+// by name wins
+fn catch_all_locations(ingresses: &[Ingress]) -> Vec<Value> {
+    let mut locations: Vec<(String, String)> = vec![];
+    for ingress in ingresses.iter().sorted_by_key(|i| (i.metadata.namespace.clone(), i.metadata.name.clone())) {
+        let spec = match ingress.spec.as_ref() {
+            Some(spec) => spec,
+            None => continue,
+        };
+        let rules = spec.rules.clone().unwrap_or_default();
+        let paths = rules.iter().filter(|r| is_hostless(r))
+            .flat_map(|r| r.http.iter().flat_map(|h| h.paths.iter()))
+            .filter(|p| p.path_type == "Prefix")
+            .filter_map(|p| Some((p.path.clone().unwrap_or("/".to_string()), upstream(&p.backend)?)));
+        let default = spec.default_backend.as_ref()
+            .filter(|_| rules.iter().all(is_hostless))
+            .and_then(upstream)
+            .map(|u| ("/".to_string(), u));
+        for (path, upstream) in paths.chain(default) {
+            if !locations.iter().any(|(p, _)| *p == path) {
+                locations.push((path, upstream));
+            }
+        }
+    }
+    locations.into_iter().map(|(path, upstream)| json!({"path": path, "upstream": upstream})).collect()
+}
+
+// the service template's values for the ingress. rules without a host are served by the catch-all server instead, and
+// rules that don't have a / prefix get the default backend for the rest of their paths
+fn service_template_values(ingress: &Ingress) -> Result<Value, Box<dyn Error>> {
+    let mut json_ingress = serde_json::to_value(ingress).map_err(|e| anyhow!(e).context("failed to serialize manifest to json"))?;
+    let has_default = ingress.spec.as_ref().is_some_and(|s| s.default_backend.as_ref().and_then(upstream).is_some());
+    if let Some(rules) = json_ingress["spec"]["rules"].as_array_mut() {
+        for rule in rules {
+            let paths = rule["http"]["paths"].as_array().cloned().unwrap_or_default();
+            let has_root = paths.iter().any(|p| p["pathType"] == "Prefix" && p["path"] == "/");
+            rule["defaultBackend"] = json!(has_default && !has_root);
+        }
+    }
+    Ok(json_ingress)
+}
+
+fn is_cidr(range: &str) -> bool {
+    let (ip, prefix) = match range.split_once('/') {
+        Some((ip, prefix)) => (ip, Some(prefix)),
+        None => (range, None),
+    };
+    let max = match ip.parse::<IpAddr>() {
+        Ok(IpAddr::V4(_)) => 32,
+        Ok(IpAddr::V6(_)) => 128,
+        Err(_) => return false,
+    };
+    prefix.is_none_or(|p| p.parse::<u8>().is_ok_and(|p| p <= max))
+}
+
+fn source_ranges(ingress: &Ingress, keys: &[&str]) -> Result<Vec<String>, Box<dyn Error>> {
+    let annotations = ingress.metadata.annotations.clone().unwrap_or_default();
+    let mut ranges = vec![];
+    for (key, value) in keys.iter().filter_map(|k| annotations.get(*k).map(|v| (k, v))) {
+        for range in value.split(',').map(|r| r.trim()).filter(|r| !r.is_empty()) {
+            if !is_cidr(range) {
+                return Err(anyhow!("{}: {} isn't an ip address or cidr range", key, range).into());
+            }
+            ranges.push(range.to_string());
+        }
+    }
+    Ok(ranges)
+}
+
+// the secret with the htpasswd file, when the ingress asks for basic auth. it has to be in the ingress's namespace, like
+// any other secret a namespace's objects use, so naming it as namespace/name only works for that same namespace
+fn auth_secret(ingress: &Ingress) -> Result<Option<NamespacedName>, Box<dyn Error>> {
+    let annotations = ingress.metadata.annotations.clone().unwrap_or_default();
+    match annotations.get(AUTH_TYPE_ANNOTATION).map(|t| t.as_str()) {
+        None => return Ok(None),
+        Some("basic") => {}
+        Some(other) => return Err(anyhow!("{} {} isn't supported, only basic", AUTH_TYPE_ANNOTATION, other).into()),
+    }
+    let secret = annotations.get(AUTH_SECRET_ANNOTATION)
+        .ok_or(anyhow!("{} requires the {} annotation", AUTH_TYPE_ANNOTATION, AUTH_SECRET_ANNOTATION))?;
+    let namespace = metadata_name(ingress).namespace;
+    let name = match secret.split_once('/') {
+        Some((ns, _)) if ns != namespace => return Err(anyhow!("{} {} isn't in the ingress's namespace {}", AUTH_SECRET_ANNOTATION, secret, namespace).into()),
+        Some((_, name)) => name,
+        None => secret,
+    };
+    Ok(Some(NamespacedName::new(name, &namespace)))
+}
+
+// the proxy's workers run as nobody and only need to read it
+#[cfg(unix)]
+const HTPASSWD_OWNER: u32 = 65534;
+
+fn write_htpasswd(path: &str, auth: &str) -> Result<(), Box<dyn Error>> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).map_err(|e| anyhow!(e).context("failed to open htpasswd file"))?;
+    file.write_all(auth.as_bytes()).map_err(|e| anyhow!(e).context("failed to write htpasswd file"))?;
+    #[cfg(unix)]
+    std::os::unix::fs::chown(path, Some(HTPASSWD_OWNER), Some(HTPASSWD_OWNER)).map_err(|e| anyhow!(e).context("failed to chown htpasswd file"))?;
+    Ok(())
+}
+
+fn render(template: &str, values: &Value, path: &str) -> Result<(), Box<dyn Error>> {
+    let output = template::new().render_template(template, values).map_err(|e| anyhow!(e).context(format!("failed to render {}", path)))?;
+    fs::write(path, format!("{}\n", output)).map_err(|e| anyhow!(e).context(format!("failed to write {}", path)))?;
+    Ok(())
+}
+
+fn htpasswd(secret: &Secret) -> Result<String, Box<dyn Error>> {
+    let auth = secret.string_data.as_ref().and_then(|d| d.get("auth").cloned())
+        .or(secret.data.as_ref().and_then(|d| d.get("auth")).map(|b| String::from_utf8_lossy(&b.0).to_string()))
+        .filter(|a| !a.trim().is_empty())
+        .ok_or(anyhow!("secret {} has no auth key with the htpasswd file", secret.metadata.name.clone().unwrap_or_default()))?;
+    Ok(auth)
+}
+
+// the template values for the ingress's source ranges and basic auth, the htpasswd file is written next to its confs
+fn access_settings(ingress: &Ingress, auth_file: Option<&str>) -> Result<Value, Box<dyn Error>> {
+    let annotations = ingress.metadata.annotations.clone().unwrap_or_default();
+    let mut access = json!({
+        "allow": source_ranges(ingress, &[ALLOWLIST_ANNOTATION, ALLOWLIST_V2_ANNOTATION])?,
+        "deny": source_ranges(ingress, &[DENYLIST_ANNOTATION])?,
+    });
+    if let Some(auth_file) = auth_file {
+        let realm = annotations.get(AUTH_REALM_ANNOTATION).map(|r| r.replace(['"', '\\'], "")).unwrap_or("Authentication Required".to_string());
+        access["realm"] = json!(realm);
+        access["authFile"] = json!(auth_file);
+    }
+    Ok(access)
+}
+
+// the template values for limiting each client ip to a number of requests a second, with bursts of up to five times that
+// unless the burst annotation says otherwise. requests over the limit get a 429
+fn rate_limit_settings(ingress: &Ingress, port: u16) -> Result<Option<Value>, Box<dyn Error>> {
+    let annotations = ingress.metadata.annotations.clone().unwrap_or_default();
+    let rps = match annotations.get(LIMIT_RPS_ANNOTATION) {
+        Some(rps) => rps.trim().parse::<u32>().ok().filter(|r| *r > 0)
+            .ok_or(anyhow!("{} must be a whole number above 0, got {}", LIMIT_RPS_ANNOTATION, rps))?,
+        None => return Ok(None),
+    };
+    let burst = match annotations.get(LIMIT_BURST_ANNOTATION) {
+        Some(burst) => burst.trim().parse::<u32>()
+            .map_err(|_| anyhow!("{} must be a whole number, got {}", LIMIT_BURST_ANNOTATION, burst))?,
+        None => rps.saturating_mul(5),
+    };
+
+    // zones are shared by every server, like the canary variable
+    let zone = format!("limit_{}_{}", metadata_name(ingress).to_string().replace(|c: char| !c.is_ascii_alphanumeric(), "_"), port);
+    Ok(Some(json!({
+        "zone": zone,
+        "rps": rps,
+        "burst": burst,
+    })))
+}
 
 pub struct IngressController {
     store: Box<dyn Store>,
@@ -27,8 +245,10 @@ impl IngressController {
     pub fn apply(&self, ingress: &Ingress) -> Result<(), Box<dyn Error>> {
         let ingress_string = serde_yaml::to_string(ingress).map_err(|e| anyhow!(e).context("failed to serialize manifest to yaml"))?;
         let name = &metadata_name(ingress).to_string();
+        let class = ClassSettings::from_ingress(ingress)?;
+        let dir = class.dir();
 
-        self.execer.exec("mkdir", &["-p", &format!("/var/lib/skate/ingress/services/{}", name)])?;
+        self.execer.exec("mkdir", &["-p", &format!("{}/services/{}", dir, name)])?;
 
         // manifest goes into store
         self.store.write_file("ingress", name, "manifest.yaml", ingress_string.as_bytes())?;
@@ -39,82 +259,127 @@ impl IngressController {
             self.store.write_file("ingress", name, "hash", hash.as_bytes())?;
         }
 
-        self.render_nginx_conf()?;
+        ingress_class::save_class(&class)?;
+        // in case the ingress was served by another class's proxy before
+        self.remove_service_confs(name, Some(&class))?;
 
-        let _ns_name = metadata_name(ingress);
+        self.render_nginx_conf(&class)?;
+
+        // the proxy sees the class's directory where the default one is
+        let auth_file = match auth_secret(ingress)? {
+            Some(secret) => {
+                let auth = self.htpasswd(&secret)?;
+                write_htpasswd(&format!("{}/services/{}/auth", dir, name), &auth)?;
+                Some(format!("{}/services/{}/auth", INGRESS_DIR, name))
+            }
+            None => None,
+        };
+        let access = access_settings(ingress, auth_file.as_deref())?;
 
         ////////////////////////////////////////////////////
         // Template service nginx confs for http/https
         ////////////////////////////////////////////////////
 
-        for port in [80, 443] {
+        for (port, listen_port) in [80, 443].into_iter().zip(class.ports()) {
             // convert manifest to json
             // set "port" key
-            let mut json_ingress = serde_json::to_value(&ingress).map_err(|e| anyhow!(e).context("failed to serialize manifest to json"))?;
+            let mut json_ingress = service_template_values(ingress)?;
+            json_ingress["access"] = access.clone();
             json_ingress["port"] = json!(port);
-
-            let json_ingress_string = json_ingress.to_string();
-
-
-            let child = process::Command::new("bash")
-                .args(["-c", &format!("skatelet template --file /var/lib/skate/ingress/service.conf.tmpl - > /var/lib/skate/ingress/services/{}/{}.conf", name, port)])
-                .stdin(Stdio::piped())
-                .stdout(Stdio::piped()).spawn()?;
-
-            let _ = child.stdin.as_ref().unwrap().write(json_ingress_string.as_ref()).unwrap();
-
-            let output = child.wait_with_output()
-                .map_err(|e| anyhow!(e).context("failed to apply resource"))?;
-
-            if !output.status.success() {
-                return Err(anyhow!("exit code {}, stderr: {}", output.status.code().unwrap(), String::from_utf8_lossy(&output.stderr).to_string()).into());
+            if !class.is_default() {
+                json_ingress["listenPort"] = json!(listen_port);
+                json_ingress["redirectPort"] = json!(class.https_port);
+            }
+            if let Some(canary) = canary_settings(ingress, port)? {
+                json_ingress["canary"] = canary;
             }
+            if let Some(rate_limit) = rate_limit_settings(ingress, port)? {
+                json_ingress["rateLimit"] = rate_limit;
+            }
+
+            render(SERVICE_TEMPLATE, &json_ingress, &format!("{}/services/{}/{}.conf", dir, name, port))?;
         }
 
-        self.reload()?;
+        self.reload(&class)?;
 
         Ok(())
     }
 
+    // the htpasswd file from the secret, which has to be applied before the ingress
+    fn htpasswd(&self, secret: &NamespacedName) -> Result<String, Box<dyn Error>> {
+        let json = self.execer.exec("podman", &["secret", "inspect", "--showsecret", &secret.to_string()])
+            .map_err(|e| anyhow!("failed to get basic auth secret {}: {}", secret, e))?;
+        let secrets: Vec<PodmanSecret> = serde_json::from_str(&json).map_err(|e| anyhow!(e).context("failed to deserialize secret info"))?;
+        let manifest = secrets.first().ok_or(anyhow!("basic auth secret {} not found", secret))?;
+        let manifest: Secret = serde_yaml::from_str(&manifest.secret_data).map_err(|e| anyhow!(e).context(format!("failed to parse secret {}", secret)))?;
+        htpasswd(&manifest)
+    }
+
     pub fn delete(&self, ingress: &Ingress) -> Result<(), Box<dyn Error>> {
         let ns_name = metadata_name(ingress);
-        let dir = format!("/var/lib/skate/ingress/services/{}", ns_name);
-        let result = fs::remove_dir_all(&dir);
-        if result.is_err() && result.as_ref().unwrap_err().kind() != std::io::ErrorKind::NotFound {
-            return Err(anyhow!(result.unwrap_err()).context(format!("failed to remove directory {}", dir)).into());
-        }
 
         self.store.remove_object("ingress", &ns_name.to_string())?;
 
-        self.reload()?;
+        // the manifest given may not say which class served it, so it's removed from all of them
+        self.remove_service_confs(&ns_name.to_string(), None)?;
 
         Ok(())
     }
 
-    pub fn reload(&self) -> Result<(), Box<dyn Error>> {
+    // removes the ingress's confs from the proxies of every class but the one given, reloading the ones that had it
+    fn remove_service_confs(&self, name: &str, except: Option<&ClassSettings>) -> Result<(), Box<dyn Error>> {
+        for class in ingress_class::node_classes() {
+            if except.is_some_and(|e| e.name == class.name) {
+                continue;
+            }
+            let dir = format!("{}/services/{}", class.dir(), name);
+            match fs::remove_dir_all(&dir) {
+                Ok(_) => self.reload(&class)?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(anyhow!(e).context(format!("failed to remove directory {}", dir)).into()),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn reload(&self, class: &ClassSettings) -> Result<(), Box<dyn Error>> {
 
         // trigger SIGHUP to ingress container
         // sudo bash -c "podman kill --signal HUP \$(podman ps --filter label=skate.io/namespace=skate --filter label=skate.io/daemonset=nginx-ingress -q)"
-        let id = self.execer.exec("podman", &["ps", "--filter", "label=skate.io/namespace=skate", "--filter", "label=skate.io/daemonset=nginx-ingress", "-q"])?;
+        let id = self.execer.exec("podman", &["ps", "--filter", "label=skate.io/namespace=skate", "--filter", &format!("label=skate.io/daemonset={}", class.daemonset_name()), "-q"])?;
 
         if id.is_empty() {
-            return Err(anyhow!("no ingress container found").into());
+            return Err(anyhow!("no ingress container found for ingress class {}", class.name).into());
         }
 
+        apply_progress::report(ApplyEvent::ReloadingProxy { class: class.name.clone() });
         let _ = self.execer.exec("podman", &["kill", "--signal", "HUP", &id.to_string()])?;
         Ok(())
     }
 
-    pub fn render_nginx_conf(&self) -> Result<(), Box<dyn Error>> {
-        let le_allow_domains: Vec<_> = self.store.list_objects("ingress")?.into_iter().filter_map(|i| {
-            match i.manifest {
-                Some(m) => {
-                    let rules = serde_yaml::from_value::<Ingress>(m).ok()?.spec?.rules?;
-                    Some(rules.into_iter().filter_map(|r| r.host).collect::<Vec<String>>())
-                }
-                None => None
-            }
-        }).flatten().unique().collect();
+    // re-renders and reloads the proxy of every class on the node, for changes that affect all of them
+    pub fn reload_all(&self) -> Result<(), Box<dyn Error>> {
+        for class in ingress_class::node_classes() {
+            self.render_nginx_conf(&class)?;
+            self.reload(&class)?;
+        }
+        Ok(())
+    }
+
+    pub fn render_nginx_conf(&self, class: &ClassSettings) -> Result<(), Box<dyn Error>> {
+        let ingresses: Vec<Ingress> = self.store.list_objects("ingress")?.into_iter()
+            .filter_map(|i| serde_yaml::from_value::<Ingress>(i.manifest?).ok())
+            .filter(|i| ClassSettings::from_ingress(i).is_ok_and(|c| c.name == class.name))
+            .collect();
+        let hosts: Vec<String> = ingresses.iter()
+            .flat_map(|i| i.spec.iter().flat_map(|s| s.rules.iter().flatten()).filter_map(|r| r.host.clone()))
+            .filter(|h| !h.is_empty())
+            .unique().collect();
+        // certificates for wildcard hosts are requested for each subdomain as it's first seen, since letsencrypt only
+        // issues wildcard certificates with dns challenges
+        let (wildcards, le_allow_domains): (Vec<_>, Vec<_>) = hosts.into_iter().partition(|h| h.starts_with("*."));
+        let wildcard_suffixes: Vec<_> = wildcards.iter().map(|h| h.trim_start_matches('*').to_string()).collect();
+        let catch_all = catch_all_locations(&ingresses);
 
 
         ////////////////////////////////////////////////////
@@ -140,29 +405,225 @@ impl IngressController {
             endpoint
         };
 
-        let main_template_data = json!({
+        let mut main_template_data = json!({
             "letsEncrypt": {
                 "endpoint": endpoint, //
                 "email": email,
-                "allowDomains": le_allow_domains
+                "allowDomains": le_allow_domains,
+                "allowWildcardDomains": wildcard_suffixes,
             },
+            "catchAllRoot": catch_all.iter().any(|l| l["path"] == "/"),
+            "catchAll": catch_all,
         });
+        if !class.is_default() {
+            main_template_data["httpPort"] = json!(class.http_port);
+            main_template_data["httpsPort"] = json!(class.https_port);
+            main_template_data["hookPort"] = json!(class.hook_port);
+        }
 
-        let child = process::Command::new("bash")
-            .args(["-c", "skatelet template --file /var/lib/skate/ingress/nginx.conf.tmpl - > /var/lib/skate/ingress/nginx.conf"])
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn()?;
+        render(NGINX_TEMPLATE, &main_template_data, &format!("{}/nginx.conf", class.dir()))?;
+        Ok(())
+    }
+}
 
-        let _ = child.stdin.as_ref().unwrap().write(main_template_data.to_string().as_ref()).unwrap();
 
-        let output = child.wait_with_output()
-            .map_err(|e| anyhow!(e).context("failed to apply resource"))?;
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use k8s_openapi::api::networking::v1::Ingress;
+    use serde_json::json;
+    use k8s_openapi::api::core::v1::Secret;
+    use k8s_openapi::ByteString;
+    use crate::controllers::ingress::{access_settings, auth_secret, canary_settings, catch_all_locations, htpasswd, rate_limit_settings, service_template_values, ALLOWLIST_ANNOTATION, AUTH_REALM_ANNOTATION, AUTH_SECRET_ANNOTATION, AUTH_TYPE_ANNOTATION, CANARY_SERVICE_ANNOTATION, CANARY_WEIGHT_ANNOTATION, DENYLIST_ANNOTATION, LIMIT_BURST_ANNOTATION, LIMIT_RPS_ANNOTATION, NGINX_TEMPLATE, SERVICE_TEMPLATE};
+    use crate::util::NamespacedName;
+    use crate::template;
+
+    fn ingress(annotations: &[(&str, &str)]) -> Ingress {
+        let mut ingress: Ingress = serde_json::from_value(json!({
+            "metadata": {"name": "foo.default", "namespace": "default", "labels": {"skate.io/name": "foo", "skate.io/namespace": "default"}},
+            "spec": {"rules": [{"host": "foo.example.com", "http": {"paths": [
+                {"path": "/", "pathType": "Prefix", "backend": {"service": {"name": "foo", "port": {"number": 8080}}}}
+            ]}}]}
+        })).unwrap();
+        ingress.metadata.annotations = Some(annotations.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<BTreeMap<_, _>>());
+        ingress
+    }
 
-        if !output.status.success() {
-            return Err(anyhow!("exit code {}, stderr: {}", output.status.code().unwrap(), String::from_utf8_lossy(&output.stderr).to_string()).into());
-        }
-        Ok(())
+    #[test]
+    fn test_canary_settings() {
+        assert_eq!(None, canary_settings(&ingress(&[]), 80).unwrap());
+        assert_eq!(None, canary_settings(&ingress(&[(CANARY_WEIGHT_ANNOTATION, "0"), (CANARY_SERVICE_ANNOTATION, "foo-canary")]), 80).unwrap());
+        assert!(canary_settings(&ingress(&[(CANARY_WEIGHT_ANNOTATION, "150"), (CANARY_SERVICE_ANNOTATION, "foo-canary")]), 80).is_err());
+        assert!(canary_settings(&ingress(&[(CANARY_WEIGHT_ANNOTATION, "20")]), 80).is_err());
+
+        let canary_ingress = ingress(&[(CANARY_WEIGHT_ANNOTATION, "20"), (CANARY_SERVICE_ANNOTATION, "foo-canary"), ("nginx.ingress.kubernetes.io/ssl-redirect", "false")]);
+        let canary = canary_settings(&canary_ingress, 80).unwrap().unwrap();
+        assert_eq!(canary, json!({"variable": "canary_foo_default_80", "weight": 20, "service": "foo-canary"}));
+
+        let mut values = serde_json::to_value(&canary_ingress).unwrap();
+        values["port"] = json!(80);
+        values["canary"] = canary;
+        let mut handlebars = template::new();
+        handlebars.register_template_string("service", SERVICE_TEMPLATE).unwrap();
+        let conf = handlebars.render("service", &values).unwrap();
+
+        assert!(conf.contains("split_clients \"${request_id}\" $canary_foo_default_80 {\n    20% canary;\n    * stable;\n}"), "{}", conf);
+        assert!(conf.contains("if ($canary_foo_default_80 = \"canary\") {"), "{}", conf);
+        assert!(conf.contains("set $upstream http://foo-canary.svc.cluster.skate:8080;"), "{}", conf);
+    }
+
+    #[test]
+    fn test_class_ports() {
+        let mut handlebars = template::new();
+        handlebars.register_template_string("service", SERVICE_TEMPLATE).unwrap();
+
+        let mut values = serde_json::to_value(ingress(&[])).unwrap();
+        values["port"] = json!(80);
+        let conf = handlebars.render("service", &values).unwrap();
+        assert!(conf.contains("listen 80;"), "{}", conf);
+        assert!(conf.contains("return 301 https://$host$request_uri;"), "{}", conf);
+
+        values["listenPort"] = json!(8080);
+        values["redirectPort"] = json!(8443);
+        let conf = handlebars.render("service", &values).unwrap();
+        assert!(conf.contains("listen 8080;"), "{}", conf);
+        assert!(conf.contains("return 301 https://$host:8443$request_uri;"), "{}", conf);
+
+        values["port"] = json!(443);
+        values["listenPort"] = json!(8443);
+        let conf = handlebars.render("service", &values).unwrap();
+        assert!(conf.contains("listen 8443 ssl;"), "{}", conf);
     }
-}
 
+    fn backend(name: &str) -> serde_json::Value {
+        json!({"service": {"name": name, "port": {"number": 80}}})
+    }
+
+    #[test]
+    fn test_default_backend() {
+        let mut with_default: Ingress = serde_json::from_value(json!({
+            "metadata": {"name": "foo.default", "namespace": "default"},
+            "spec": {"defaultBackend": backend("fallback"), "rules": [
+                {"host": "foo.example.com", "http": {"paths": [{"path": "/api", "pathType": "Prefix", "backend": backend("api")}]}},
+                {"host": "*.example.com", "http": {"paths": [{"path": "/", "pathType": "Prefix", "backend": backend("web")}]}},
+                {"http": {"paths": [{"path": "/health", "pathType": "Prefix", "backend": backend("health")}]}}
+            ]}
+        })).unwrap();
+        with_default.metadata.annotations = Some(BTreeMap::from([("nginx.ingress.kubernetes.io/ssl-redirect".to_string(), "false".to_string())]));
+
+        let mut values = service_template_values(&with_default).unwrap();
+        values["port"] = json!(80);
+        let mut handlebars = template::new();
+        handlebars.register_template_string("service", SERVICE_TEMPLATE).unwrap();
+        let conf = handlebars.render("service", &values).unwrap();
+
+        // the hostless rule is left to the catch-all server
+        assert_eq!(2, conf.matches("server {").count(), "{}", conf);
+        assert!(conf.contains("server_name *.example.com;"), "{}", conf);
+        assert_eq!(1, conf.matches("set $upstream http://fallback.svc.cluster.skate:80;").count(), "{}", conf);
+        assert!(!conf.contains("health"), "{}", conf);
+
+        let catch_all: Ingress = serde_json::from_value(json!({
+            "metadata": {"name": "catch-all.default", "namespace": "default"},
+            "spec": {"defaultBackend": backend("catch-all")}
+        })).unwrap();
+        // the default backend of an ingress with host rules only serves those hosts
+        assert_eq!(vec![json!({"path": "/health", "upstream": "http://health.svc.cluster.skate:80"})], catch_all_locations(&[with_default.clone()]));
+        assert_eq!(vec![
+            json!({"path": "/", "upstream": "http://catch-all.svc.cluster.skate:80"}),
+            json!({"path": "/health", "upstream": "http://health.svc.cluster.skate:80"}),
+        ], catch_all_locations(&[with_default, catch_all]));
+    }
+
+    #[test]
+    fn test_catch_all() {
+        let mut handlebars = template::new();
+        handlebars.register_template_string("nginx", NGINX_TEMPLATE).unwrap();
+
+        let conf = handlebars.render("nginx", &json!({
+            "letsEncrypt": {"endpoint": "", "allowDomains": ["foo.example.com"], "allowWildcardDomains": [".example.com"]},
+            "catchAllRoot": false,
+            "catchAll": [{"path": "/health", "upstream": "http://health.svc.cluster.skate:80"}],
+        })).unwrap();
+        assert!(conf.contains("'.example.com',"), "{}", conf);
+        assert_eq!(2, conf.matches("require(\"error_page\").default()").count(), "{}", conf);
+        assert_eq!(2, conf.matches("set $upstream http://health.svc.cluster.skate:80;").count(), "{}", conf);
+        assert!(conf.contains("listen 443 ssl;"), "{}", conf);
+
+        let conf = handlebars.render("nginx", &json!({
+            "httpPort": 8080,
+            "httpsPort": 8443,
+            "catchAllRoot": true,
+            "catchAll": [{"path": "/", "upstream": "http://catch-all.svc.cluster.skate:80"}],
+        })).unwrap();
+        assert!(!conf.contains("require(\"error_page\").default()"), "{}", conf);
+        assert!(conf.contains("listen 8443 ssl;"), "{}", conf);
+    }
+
+    #[test]
+    fn test_access_settings() {
+        assert_eq!(None, auth_secret(&ingress(&[])).unwrap());
+        assert!(auth_secret(&ingress(&[(AUTH_TYPE_ANNOTATION, "basic")])).is_err());
+        assert!(auth_secret(&ingress(&[(AUTH_TYPE_ANNOTATION, "digest"), (AUTH_SECRET_ANNOTATION, "users")])).is_err());
+        assert_eq!(Some(NamespacedName::new("users", "default")), auth_secret(&ingress(&[(AUTH_TYPE_ANNOTATION, "basic"), (AUTH_SECRET_ANNOTATION, "users")])).unwrap());
+        assert_eq!(Some(NamespacedName::new("users", "default")), auth_secret(&ingress(&[(AUTH_TYPE_ANNOTATION, "basic"), (AUTH_SECRET_ANNOTATION, "default/users")])).unwrap());
+        assert!(auth_secret(&ingress(&[(AUTH_TYPE_ANNOTATION, "basic"), (AUTH_SECRET_ANNOTATION, "auth/users")])).unwrap_err().to_string().contains("isn't in the ingress's namespace"));
+
+        let secret = Secret { data: Some(BTreeMap::from([("auth".to_string(), ByteString(b"foo:$apr1$xyz\n".to_vec()))])), ..Default::default() };
+        assert_eq!("foo:$apr1$xyz\n", htpasswd(&secret).unwrap());
+        assert!(htpasswd(&Secret::default()).is_err());
+
+        assert!(access_settings(&ingress(&[(ALLOWLIST_ANNOTATION, "10.0.0.0/33")]), None).is_err());
+        assert!(access_settings(&ingress(&[(DENYLIST_ANNOTATION, "nope")]), None).is_err());
+
+        let protected = ingress(&[
+            (ALLOWLIST_ANNOTATION, "10.0.0.0/8, 192.168.1.1"),
+            (DENYLIST_ANNOTATION, "10.0.0.1"),
+            (AUTH_REALM_ANNOTATION, "Staff \"only\""),
+            ("nginx.ingress.kubernetes.io/ssl-redirect", "false"),
+        ]);
+        let access = access_settings(&protected, Some("/var/lib/skate/ingress/services/foo.default/auth")).unwrap();
+        assert_eq!(json!({
+            "allow": ["10.0.0.0/8", "192.168.1.1"],
+            "deny": ["10.0.0.1"],
+            "realm": "Staff only",
+            "authFile": "/var/lib/skate/ingress/services/foo.default/auth",
+        }), access);
+
+        let mut values = service_template_values(&protected).unwrap();
+        values["port"] = json!(80);
+        values["access"] = access;
+        let mut handlebars = template::new();
+        handlebars.register_template_string("service", SERVICE_TEMPLATE).unwrap();
+        let conf = handlebars.render("service", &values).unwrap();
+        let access_lines: Vec<_> = conf.lines().map(|l| l.trim()).filter(|l| l.starts_with("allow") || l.starts_with("deny") || l.starts_with("auth_basic")).collect();
+        assert_eq!(vec![
+            "allow all;",
+            "auth_basic off;",
+            "deny 10.0.0.1;",
+            "allow 10.0.0.0/8;",
+            "allow 192.168.1.1;",
+            "deny all;",
+            "auth_basic \"Staff only\";",
+            "auth_basic_user_file /var/lib/skate/ingress/services/foo.default/auth;",
+        ], access_lines);
+    }
+
+    #[test]
+    fn test_rate_limit_settings() {
+        assert_eq!(None, rate_limit_settings(&ingress(&[]), 80).unwrap());
+        assert!(rate_limit_settings(&ingress(&[(LIMIT_RPS_ANNOTATION, "0")]), 80).is_err());
+        assert!(rate_limit_settings(&ingress(&[(LIMIT_RPS_ANNOTATION, "10"), (LIMIT_BURST_ANNOTATION, "lots")]), 80).is_err());
+        assert_eq!(Some(json!({"zone": "limit_foo_default_443", "rps": 10, "burst": 50})), rate_limit_settings(&ingress(&[(LIMIT_RPS_ANNOTATION, "10")]), 443).unwrap());
+
+        let limited = ingress(&[(LIMIT_RPS_ANNOTATION, "10"), (LIMIT_BURST_ANNOTATION, "20"), ("nginx.ingress.kubernetes.io/ssl-redirect", "false")]);
+        let mut values = serde_json::to_value(&limited).unwrap();
+        values["port"] = json!(80);
+        values["rateLimit"] = rate_limit_settings(&limited, 80).unwrap().unwrap();
+        let mut handlebars = template::new();
+        handlebars.register_template_string("service", SERVICE_TEMPLATE).unwrap();
+        let conf = handlebars.render("service", &values).unwrap();
+        assert!(conf.contains("limit_req_zone $binary_remote_addr zone=limit_foo_default_80:10m rate=10r/s;"), "{}", conf);
+        assert!(conf.contains("limit_req zone=limit_foo_default_80 burst=20 nodelay;"), "{}", conf);
+    }
+}
@@ -4,6 +4,9 @@ pub (crate) mod cronjob;
 pub (crate) mod secret;
 pub (crate) mod daemonset;
 pub (crate) mod pod;
+pub (crate) mod emptydir;
+pub (crate) mod secretenv;
 pub (crate) mod deployment;
 pub (crate) mod clusterissuer;
+pub (crate) mod poddisruptionbudget;
 
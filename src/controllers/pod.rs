@@ -1,9 +1,15 @@
 use std::error::Error;
+use std::fs;
+use std::net::IpAddr;
 use anyhow::anyhow;
-use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::api::core::v1::{HostAlias, Pod, PodDNSConfig, PodDNSConfigOption, PodSpec};
 use crate::resource::SupportedResources;
+use crate::controllers::emptydir::EmptyDirs;
+use crate::controllers::secretenv::SecretEnv;
 use crate::exec::{ShellExec};
-use crate::util::apply_play;
+use crate::gpu::{assign_gpus, detect_gpus, gpu_request, GPU_DEVICES_LABEL};
+use crate::skatelet::system::podman::PodmanPodInfo;
+use crate::util::{apply_play, quantity_to_bytes, quantity_to_cpus};
 
 pub struct PodController {
     execer: Box<dyn ShellExec>
@@ -17,7 +23,36 @@ impl PodController {
     }
 
     pub fn apply(&self, pod: &Pod) -> Result<(), Box<dyn Error>> {
-        apply_play(&self.execer, &SupportedResources::Pod(pod.clone()))
+        let mut pod = pod.clone();
+        let mut play_args = vec!();
+        if let (Some(name), Some(spec)) = (pod.metadata.name.clone(), pod.spec.as_mut()) {
+            EmptyDirs::new(self.execer.as_ref()).prepare(&name, spec)?;
+            if let Some(path) = SecretEnv::new(self.execer.as_ref()).prepare(&name, spec)? {
+                play_args.push(format!("--configmap={}", path));
+            }
+            resolve_dns(spec, &NodeResolver::load)?;
+            merge_host_aliases(spec)?;
+        }
+        if gpu_request(&pod) > 0 {
+            assign_gpus(&mut pod, &detect_gpus(), &self.gpu_pods()?)?;
+        }
+        let limits = limit_args(&pod)?;
+        apply_play(self.execer.as_ref(), &SupportedResources::Pod(pod), &play_args)?;
+
+        for (container, args) in limits {
+            let args = [vec!["update"], args.iter().map(|a| a.as_str()).collect(), vec![container.as_str()]].concat();
+            self.execer.exec("podman", &args).map_err(|e| anyhow!(e.to_string()).context(format!("failed to set resource limits on {}", container)))?;
+        }
+        Ok(())
+    }
+
+    // the pods that were given gpus
+    fn gpu_pods(&self) -> Result<Vec<PodmanPodInfo>, Box<dyn Error>> {
+        let output = self.execer.exec("podman", &["pod", "ps", "--filter", &format!("label={}", GPU_DEVICES_LABEL), "--format", "json"])?;
+        match output.as_str() {
+            "" | "null" => Ok(vec!()),
+            _ => Ok(serde_json::from_str(&output).map_err(|e| anyhow!(e).context("failed to deserialize pod list"))?),
+        }
     }
 
     pub fn delete(&self, pod: &Pod, grace_period: Option<usize>) -> Result<(), Box<dyn Error>> {
@@ -27,12 +62,7 @@ impl PodController {
 
     pub fn delete_podman_pods(&self, ids: Vec<&str>, grace_period: Option<usize>) -> Result<(), Box<dyn Error>> {
         let failures: Vec<_> = ids.iter().filter_map(|id| {
-            match self.delete_podman_pod(id, grace_period) {
-                Ok(_) => None,
-                Err(e) => {
-                    Some(e)
-                }
-            }
+            self.delete_podman_pod(id, grace_period).err()
         }).collect();
 
         if !failures.is_empty() {
@@ -53,6 +83,7 @@ impl PodController {
         let grace_str = format!("{}", grace);
         println!("gracefully stopping {}", id);
 
+        let name = self.execer.exec("podman", &["pod", "inspect", id, "--format={{.Name}}"])?.trim().to_string();
         let containers = self.execer.exec("podman", &["pod", "inspect", id, "--format={{range.Containers}}{{.Id}} {{end}}"])?;
         let containers = containers.split_ascii_whitespace().collect();
 
@@ -62,8 +93,8 @@ impl PodController {
         let args = [vec!(&grace_str, "podman", "wait"), containers].concat();
         let result = self.execer.exec("timeout", &args);
 
-        if result.is_err() {
-            eprintln!("failed to stop {}: {}", id, result.unwrap_err());
+        if let Err(e) = result {
+            eprintln!("failed to stop {}: {}", id, e);
         }
 
         println!("removing {}", id);
@@ -79,6 +110,322 @@ impl PodController {
             println!("{}", output);
         }
 
+        if !name.is_empty() {
+            EmptyDirs::new(self.execer.as_ref()).cleanup(&name)?;
+            SecretEnv::new(self.execer.as_ref()).cleanup(&name)?;
+        }
+
         Ok(())
     }
 }
+
+const NETWORK_CONFIG: &str = "/etc/containers/networks/skate.json";
+const MAX_NAMESERVERS: usize = 3;
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+// the upstream servers systemd-resolved forwards to, its stub at 127.0.0.53 is all /etc/resolv.conf has
+const RESOLVED_RESOLV_CONF: &str = "/run/systemd/resolve/resolv.conf";
+
+// what the pod's dns is built from on the node
+#[derive(Debug, Default, PartialEq)]
+struct NodeResolver {
+    // the skate network's gateway, where podman answers for the cluster's names
+    cluster_nameserver: Option<String>,
+    // the node's own resolv.conf, without the nameservers a pod can't reach
+    nameservers: Vec<String>,
+    searches: Vec<String>,
+    options: Vec<String>,
+}
+
+impl NodeResolver {
+    fn load() -> Result<Self, Box<dyn Error>> {
+        let network = fs::read_to_string(NETWORK_CONFIG).map_err(|e| anyhow!(e).context(format!("failed to read {}", NETWORK_CONFIG)))?;
+        let network: serde_json::Value = serde_json::from_str(&network).map_err(|e| anyhow!(e).context(format!("failed to parse {}", NETWORK_CONFIG)))?;
+        let mut resolver = Self::read_resolv_conf(&|path| fs::read_to_string(path).ok());
+        resolver.cluster_nameserver = network["subnets"][0]["gateway"].as_str().map(|g| g.to_string());
+        Ok(resolver)
+    }
+
+    // a pod can't reach the node's loopback, so with only loopback nameservers the ones behind the local resolver are used
+    fn read_resolv_conf(read: &dyn Fn(&str) -> Option<String>) -> Self {
+        let resolver = Self::parse_resolv_conf(&read(RESOLV_CONF).unwrap_or_default());
+        if !resolver.nameservers.is_empty() {
+            return resolver;
+        }
+        match read(RESOLVED_RESOLV_CONF).map(|c| Self::parse_resolv_conf(&c)) {
+            Some(upstream) if !upstream.nameservers.is_empty() => upstream,
+            _ => resolver,
+        }
+    }
+
+    fn parse_resolv_conf(contents: &str) -> Self {
+        let mut resolver = NodeResolver::default();
+        for line in contents.lines().map(|l| l.trim()).filter(|l| !l.starts_with('#') && !l.starts_with(';')) {
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("nameserver") => resolver.nameservers.extend(fields.next()
+                    .filter(|ns| ns.parse::<IpAddr>().is_ok_and(|ip| !ip.is_loopback()))
+                    .map(|ns| ns.to_string())),
+                Some("search") | Some("domain") => resolver.searches = fields.map(|s| s.to_string()).collect(),
+                Some("options") => resolver.options.extend(fields.map(|o| o.to_string())),
+                _ => {}
+            }
+        }
+        resolver
+    }
+}
+
+fn merge_unique(first: Vec<String>, second: Vec<String>) -> Vec<String> {
+    let mut merged: Vec<String> = vec![];
+    for value in first.into_iter().chain(second) {
+        if !merged.contains(&value) {
+            merged.push(value);
+        }
+    }
+    merged
+}
+
+// turns the pod's dnsPolicy into the dnsConfig podman kube play understands, since podman ignores the policy.
+// with ClusterFirst, the default, the cluster's nameserver stays first so that cluster names still resolve next to the
+// pod's own nameservers. Default uses the node's resolv.conf and None only what the pod gives. host network pods already
+// share the node's resolv.conf, which resolves the cluster's names
+fn resolve_dns(spec: &mut PodSpec, node: &dyn Fn() -> Result<NodeResolver, Box<dyn Error>>) -> Result<(), Box<dyn Error>> {
+    let policy = spec.dns_policy.clone().unwrap_or("ClusterFirst".to_string());
+    let config = spec.dns_config.clone().unwrap_or_default();
+    let nameservers = config.nameservers.clone().unwrap_or_default();
+    let options = config.options.clone().unwrap_or_default();
+    let resolver = match policy.as_str() {
+        "None" => {
+            if spec.dns_config.is_none() {
+                return Err(anyhow!("dnsPolicy None requires a dnsConfig").into());
+            }
+            None
+        }
+        "ClusterFirst" | "ClusterFirstWithHostNet" if spec.host_network == Some(true) => None,
+        "ClusterFirst" | "ClusterFirstWithHostNet" if nameservers.is_empty() => None,
+        "ClusterFirst" | "ClusterFirstWithHostNet" => {
+            let node = node()?;
+            Some((merge_unique(node.cluster_nameserver.into_iter().collect(), nameservers.clone()), config.searches.clone().unwrap_or_default(), options.clone()))
+        }
+        "Default" => {
+            let node = node()?;
+            let node_options = node.options.iter().map(|o| {
+                let (name, value) = o.split_once(':').map(|(n, v)| (n, Some(v.to_string()))).unwrap_or((o, None));
+                PodDNSConfigOption { name: Some(name.to_string()), value }
+            });
+            // the pod's options win over the node's of the same name
+            let options = node_options.filter(|o| !options.iter().any(|p| p.name == o.name)).chain(options.clone()).collect();
+            Some((merge_unique(node.nameservers, nameservers.clone()), merge_unique(node.searches, config.searches.clone().unwrap_or_default()), options))
+        }
+        other => return Err(anyhow!("unknown dnsPolicy {}", other).into()),
+    };
+
+    if let Some((nameservers, searches, options)) = resolver {
+        if nameservers.is_empty() {
+            return Err(anyhow!("dnsPolicy {}: the node has no nameserver the pod can reach", policy).into());
+        }
+        spec.dns_config = Some(PodDNSConfig {
+            nameservers: Some(nameservers),
+            searches: Some(searches).filter(|s| !s.is_empty()),
+            options: Some(options).filter(|o: &Vec<_>| !o.is_empty()),
+        });
+    }
+    let count = spec.dns_config.as_ref().and_then(|c| c.nameservers.as_ref()).map(|n| n.len()).unwrap_or_default();
+    if count > MAX_NAMESERVERS {
+        return Err(anyhow!("dnsConfig has {} nameservers, at most {} are used including the cluster's", count, MAX_NAMESERVERS).into());
+    }
+    Ok(())
+}
+
+// checks the pod's hostAliases, which podman kube play adds to the pod's /etc/hosts, merging those for the same ip into
+// one line. podman would otherwise write a bad hosts file rather than refuse the pod
+fn merge_host_aliases(spec: &mut PodSpec) -> Result<(), Box<dyn Error>> {
+    let aliases = match spec.host_aliases.take() {
+        Some(aliases) => aliases,
+        None => return Ok(()),
+    };
+    let mut merged: Vec<HostAlias> = vec![];
+    for alias in aliases {
+        let ip: IpAddr = alias.ip.trim().parse().map_err(|_| anyhow!("hostAliases: invalid ip {:?}", alias.ip))?;
+        let hostnames = alias.hostnames.unwrap_or_default();
+        if hostnames.is_empty() {
+            return Err(anyhow!("hostAliases: {} has no hostnames", ip).into());
+        }
+        if let Some(invalid) = hostnames.iter().find(|h| h.is_empty() || h.contains(char::is_whitespace)) {
+            return Err(anyhow!("hostAliases: invalid hostname {:?} for {}", invalid, ip).into());
+        }
+
+        let ip = ip.to_string();
+        let existing = match merged.iter_mut().find(|a| a.ip == ip) {
+            Some(existing) => existing,
+            None => {
+                merged.push(HostAlias { ip, hostnames: Some(vec![]) });
+                merged.last_mut().expect("was just pushed")
+            }
+        };
+        let names = existing.hostnames.get_or_insert_with(Vec::new);
+        names.extend(hostnames.into_iter().filter(|h| !names.contains(h)).collect::<Vec<_>>());
+    }
+    spec.host_aliases = Some(merged).filter(|m| !m.is_empty());
+    Ok(())
+}
+
+type ContainerLimitArgs = (String, Vec<String>);
+
+// the `podman update` args that enforce each container's resources.limits, keyed by the container name podman gives it.
+// memory limits also cap swap, so a container can't exceed its limit by swapping
+fn limit_args(pod: &Pod) -> Result<Vec<ContainerLimitArgs>, Box<dyn Error>> {
+    let pod_name = pod.metadata.name.clone().unwrap_or_default();
+    let containers = pod.spec.iter().flat_map(|s| s.containers.iter());
+
+    let mut result = vec!();
+    for container in containers {
+        let limits = match container.resources.as_ref().and_then(|r| r.limits.as_ref()) {
+            Some(limits) => limits,
+            None => continue,
+        };
+
+        let mut args = vec!();
+        if let Some(cpu) = limits.get("cpu") {
+            let cpus = quantity_to_cpus(&cpu.0).ok_or(anyhow!("invalid cpu limit {} for container {}", cpu.0, container.name))?;
+            args.push(format!("--cpus={}", cpus));
+        }
+        if let Some(memory) = limits.get("memory") {
+            let bytes = quantity_to_bytes(&memory.0).ok_or(anyhow!("invalid memory limit {} for container {}", memory.0, container.name))?;
+            args.push(format!("--memory={}", bytes));
+            args.push(format!("--memory-swap={}", bytes));
+        }
+
+        if !args.is_empty() {
+            result.push((format!("{}-{}", pod_name, container.name), args));
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use k8s_openapi::api::core::v1::{Container, HostAlias, Pod, PodDNSConfig, PodDNSConfigOption, PodSpec, ResourceRequirements};
+    use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use crate::controllers::pod::{limit_args, merge_host_aliases, resolve_dns, NodeResolver};
+
+    fn container(name: &str, limits: &[(&str, &str)]) -> Container {
+        Container {
+            name: name.to_string(),
+            resources: Some(ResourceRequirements {
+                limits: Some(limits.iter().map(|(k, v)| (k.to_string(), Quantity(v.to_string()))).collect::<BTreeMap<_, _>>()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_limit_args() {
+        let mut pod = Pod {
+            metadata: ObjectMeta { name: Some("web.default".to_string()), ..Default::default() },
+            spec: Some(PodSpec {
+                containers: vec![
+                    container("nginx", &[("cpu", "500m"), ("memory", "128Mi")]),
+                    container("sidecar", &[]),
+                    Container { name: "other".to_string(), ..Default::default() },
+                ],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(limit_args(&pod).unwrap(), vec![
+            ("web.default-nginx".to_string(), vec!["--cpus=0.5".to_string(), "--memory=134217728".to_string(), "--memory-swap=134217728".to_string()]),
+        ]);
+
+        pod.spec.as_mut().unwrap().containers = vec![container("nginx", &[("cpu", "fast")])];
+        assert!(limit_args(&pod).is_err());
+    }
+
+    #[test]
+    fn test_resolve_dns() {
+        let node = || Ok(NodeResolver {
+            cluster_nameserver: Some("10.30.0.1".to_string()),
+            ..NodeResolver::parse_resolv_conf("# generated\nnameserver 127.0.0.53\nnameserver 192.168.1.1\nsearch lan\noptions ndots:1 edns0\n")
+        });
+        let option = |name: &str, value: Option<&str>| PodDNSConfigOption { name: Some(name.to_string()), value: value.map(|v| v.to_string()) };
+        let spec = |policy: Option<&str>, config: Option<PodDNSConfig>| PodSpec { dns_policy: policy.map(|p| p.to_string()), dns_config: config, ..Default::default() };
+        let config = PodDNSConfig {
+            nameservers: Some(vec!["10.0.0.53".to_string()]),
+            searches: Some(vec!["internal.example.com".to_string()]),
+            options: Some(vec![option("ndots", Some("2"))]),
+        };
+
+        // nothing to change, the network's dns is used
+        let mut cluster_first = spec(None, None);
+        resolve_dns(&mut cluster_first, &|| panic!("not needed")).unwrap();
+        assert_eq!(None, cluster_first.dns_config);
+
+        let mut cluster_first = spec(None, Some(config.clone()));
+        resolve_dns(&mut cluster_first, &node).unwrap();
+        assert_eq!(Some(vec!["10.30.0.1".to_string(), "10.0.0.53".to_string()]), cluster_first.dns_config.unwrap().nameservers);
+
+        let mut default = spec(Some("Default"), Some(config.clone()));
+        resolve_dns(&mut default, &node).unwrap();
+        assert_eq!(Some(PodDNSConfig {
+            nameservers: Some(vec!["192.168.1.1".to_string(), "10.0.0.53".to_string()]),
+            searches: Some(vec!["lan".to_string(), "internal.example.com".to_string()]),
+            options: Some(vec![option("edns0", None), option("ndots", Some("2"))]),
+        }), default.dns_config);
+
+        let mut none = spec(Some("None"), Some(config.clone()));
+        resolve_dns(&mut none, &|| panic!("not needed")).unwrap();
+        assert_eq!(Some(config.clone()), none.dns_config);
+
+        assert!(resolve_dns(&mut spec(Some("None"), None), &node).is_err());
+        assert!(resolve_dns(&mut spec(Some("Sometimes"), None), &node).is_err());
+        let too_many = PodDNSConfig { nameservers: Some(vec!["10.0.0.1".to_string(), "10.0.0.2".to_string(), "10.0.0.3".to_string()]), ..Default::default() };
+        assert!(resolve_dns(&mut spec(None, Some(too_many)), &node).is_err());
+    }
+
+    #[test]
+    fn test_read_resolv_conf() {
+        let stub = "nameserver 127.0.0.53\nsearch lan\n";
+        let upstream = "nameserver 192.168.1.1\nsearch lan\n";
+
+        let resolver = NodeResolver::read_resolv_conf(&|path| match path {
+            "/etc/resolv.conf" => Some(stub.to_string()),
+            _ => Some(upstream.to_string()),
+        });
+        assert_eq!(vec!["192.168.1.1".to_string()], resolver.nameservers);
+
+        let resolver = NodeResolver::read_resolv_conf(&|path| match path {
+            "/etc/resolv.conf" => Some("nameserver 10.0.0.2\n".to_string()),
+            _ => panic!("not needed"),
+        });
+        assert_eq!(vec!["10.0.0.2".to_string()], resolver.nameservers);
+
+        // no systemd-resolved
+        let resolver = NodeResolver::read_resolv_conf(&|path| match path {
+            "/etc/resolv.conf" => Some(stub.to_string()),
+            _ => None,
+        });
+        assert_eq!(Vec::<String>::new(), resolver.nameservers);
+        assert_eq!(vec!["lan".to_string()], resolver.searches);
+    }
+
+    #[test]
+    fn test_merge_host_aliases() {
+        let alias = |ip: &str, hostnames: &[&str]| HostAlias { ip: ip.to_string(), hostnames: Some(hostnames.iter().map(|h| h.to_string()).collect()) };
+        let spec = |aliases: Vec<HostAlias>| PodSpec { host_aliases: Some(aliases), ..Default::default() };
+
+        let mut merged = spec(vec![alias("10.0.0.5", &["db.legacy"]), alias(" 10.0.0.6", &["mail.legacy"]), alias("10.0.0.5", &["db.legacy", "db"])]);
+        merge_host_aliases(&mut merged).unwrap();
+        assert_eq!(Some(vec![alias("10.0.0.5", &["db.legacy", "db"]), alias("10.0.0.6", &["mail.legacy"])]), merged.host_aliases);
+
+        let mut none = PodSpec::default();
+        merge_host_aliases(&mut none).unwrap();
+        assert_eq!(None, none.host_aliases);
+
+        assert!(merge_host_aliases(&mut spec(vec![alias("db.legacy", &["db"])])).is_err());
+        assert!(merge_host_aliases(&mut spec(vec![alias("10.0.0.5", &[])])).is_err());
+        assert!(merge_host_aliases(&mut spec(vec![alias("10.0.0.5", &["db legacy"])])).is_err());
+    }
+}
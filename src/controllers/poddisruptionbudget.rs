@@ -0,0 +1,38 @@
+use std::error::Error;
+use anyhow::anyhow;
+use k8s_openapi::api::policy::v1::PodDisruptionBudget;
+use crate::filestore::Store;
+use crate::util::metadata_name;
+
+// budgets are only read by the scheduler when it preempts pods, so the node just keeps them in its store
+pub struct PodDisruptionBudgetController {
+    store: Box<dyn Store>,
+}
+
+impl PodDisruptionBudgetController {
+    pub fn new(store: Box<dyn Store>) -> Self {
+        PodDisruptionBudgetController {
+            store,
+        }
+    }
+
+    pub fn apply(&self, pdb: &PodDisruptionBudget) -> Result<(), Box<dyn Error>> {
+        let manifest = serde_yaml::to_string(pdb).map_err(|e| anyhow!(e).context("failed to serialize manifest to yaml"))?;
+
+        let ns_name = metadata_name(pdb);
+        self.store.write_file("poddisruptionbudget", &ns_name.to_string(), "manifest.yaml", manifest.as_bytes())?;
+
+        let hash = pdb.metadata.labels.as_ref().and_then(|m| m.get("skate.io/hash")).unwrap_or(&"".to_string()).to_string();
+
+        if !hash.is_empty() {
+            self.store.write_file("poddisruptionbudget", &ns_name.to_string(), "hash", hash.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn delete(&self, pdb: &PodDisruptionBudget) -> Result<(), Box<dyn Error>> {
+        let ns_name = metadata_name(pdb);
+        let _ = self.store.remove_object("poddisruptionbudget", &ns_name.to_string())?;
+        Ok(())
+    }
+}
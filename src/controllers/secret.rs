@@ -16,7 +16,7 @@ impl SecretController {
     }
 
     pub fn apply(&self, secret: &Secret) -> Result<(), Box<dyn Error>> {
-        apply_play(&self.execer, &SupportedResources::Secret(secret.clone()))
+        apply_play(self.execer.as_ref(), &SupportedResources::Secret(secret.clone()), &[])
     }
 
 
@@ -0,0 +1,244 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::{ErrorKind, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use anyhow::anyhow;
+use k8s_openapi::api::core::v1::{ConfigMap, ConfigMapKeySelector, PodSpec, Secret};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use crate::exec::ShellExec;
+use crate::skatelet::system::podman::PodmanSecret;
+
+const SECRET_ENV_PATH: &str = "/run/skate/secret-env";
+
+// kube play has no --env-file, so env vars that come from secrets are resolved on the node and handed to it in a ConfigMap
+// file with --configmap instead. the file is on tmpfs, readable by root alone, and removed along with the pod.
+pub struct SecretEnv<'a> {
+    execer: &'a dyn ShellExec,
+    dir: String,
+}
+
+impl<'a> SecretEnv<'a> {
+    pub fn new(execer: &'a dyn ShellExec) -> Self {
+        SecretEnv { execer, dir: SECRET_ENV_PATH.to_string() }
+    }
+
+    // prepare points the pod's secret env vars at a ConfigMap holding their values, returning the file to pass to --configmap
+    pub fn prepare(&self, pod_name: &str, spec: &mut PodSpec) -> Result<Option<String>, Box<dyn Error>> {
+        self.cleanup(pod_name)?;
+
+        let data = secret_env(pod_name, spec, |name| self.secret(name))?;
+        if data.is_empty() {
+            return Ok(None);
+        }
+        let config_map = ConfigMap {
+            metadata: ObjectMeta { name: Some(config_map_name(pod_name)), ..Default::default() },
+            data: Some(data),
+            ..Default::default()
+        };
+
+        let mut dir = fs::DirBuilder::new();
+        dir.recursive(true);
+        #[cfg(unix)]
+        dir.mode(0o700);
+        dir.create(&self.dir).map_err(|e| anyhow!(e).context(format!("failed to create {}", self.dir)))?;
+
+        let path = self.file_path(pod_name);
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(&path).map_err(|e| anyhow!(e).context(format!("failed to create {}", path)))?;
+        file.write_all(serde_yaml::to_string(&config_map)?.as_bytes()).map_err(|e| anyhow!(e).context(format!("failed to write {}", path)))?;
+        Ok(Some(path))
+    }
+
+    // cleanup removes the secret values written for the pod
+    pub fn cleanup(&self, pod_name: &str) -> Result<(), Box<dyn Error>> {
+        let path = self.file_path(pod_name);
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(anyhow!(e).context(format!("failed to remove {}", path)).into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn file_path(&self, pod_name: &str) -> String {
+        format!("{}/{}.yaml", self.dir, pod_name)
+    }
+
+    // the secret as it was applied, podman keeps the whole manifest as the secret's data
+    fn secret(&self, name: &str) -> Result<Option<Secret>, Box<dyn Error>> {
+        if self.execer.exec("podman", &["secret", "exists", name]).is_err() {
+            return Ok(None);
+        }
+        let output = self.execer.exec("podman", &["secret", "inspect", "--showsecret", name])?;
+        let secrets: Vec<PodmanSecret> = serde_json::from_str(&output).map_err(|e| anyhow!(e).context(format!("failed to deserialize secret {}", name)))?;
+        match secrets.first() {
+            Some(secret) => Ok(Some(serde_yaml::from_str(&secret.secret_data).map_err(|e| anyhow!(e).context(format!("failed to read secret {}", name)))?)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn config_map_name(pod_name: &str) -> String {
+    format!("{}-secret-env", pod_name)
+}
+
+// rewrites the containers' secretKeyRef env vars to configMapKeyRefs, returning the values of the ConfigMap they now point at.
+// vars for an optional secret or key that isn't there are dropped, as kubernetes leaves them unset
+fn secret_env<F>(pod_name: &str, spec: &mut PodSpec, lookup: F) -> Result<BTreeMap<String, String>, Box<dyn Error>>
+where
+    F: Fn(&str) -> Result<Option<Secret>, Box<dyn Error>>,
+{
+    let mut data = BTreeMap::new();
+    let mut secrets: BTreeMap<String, Option<Secret>> = BTreeMap::new();
+
+    for container in spec.containers.iter_mut() {
+        let env = match container.env.as_mut() {
+            Some(env) => env,
+            None => continue,
+        };
+        let mut resolved = vec!();
+        for mut var in env.drain(..) {
+            let key_ref = match var.value_from.as_ref().and_then(|v| v.secret_key_ref.clone()) {
+                Some(key_ref) => key_ref,
+                None => {
+                    resolved.push(var);
+                    continue;
+                }
+            };
+            if !secrets.contains_key(&key_ref.name) {
+                secrets.insert(key_ref.name.clone(), lookup(&key_ref.name)?);
+            }
+            let value = secrets[&key_ref.name].as_ref().and_then(|s| secret_value(s, &key_ref.key));
+            let value = match (value, key_ref.optional.unwrap_or_default()) {
+                (Some(value), _) => value,
+                (None, true) => continue,
+                (None, false) => return Err(anyhow!("container {}: env {} refers to key {} of secret {}, which doesn't exist", container.name, var.name, key_ref.key, key_ref.name).into()),
+            };
+
+            let key = format!("{}.{}", container.name, var.name);
+            data.insert(key.clone(), value);
+            var.value_from = var.value_from.map(|mut v| {
+                v.secret_key_ref = None;
+                v.config_map_key_ref = Some(ConfigMapKeySelector { name: config_map_name(pod_name), key, optional: None });
+                v
+            });
+            resolved.push(var);
+        }
+        *env = resolved;
+    }
+    Ok(data)
+}
+
+fn secret_value(secret: &Secret, key: &str) -> Option<String> {
+    secret.string_data.as_ref().and_then(|d| d.get(key).cloned())
+        .or_else(|| secret.data.as_ref().and_then(|d| d.get(key)).map(|v| String::from_utf8_lossy(&v.0).to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::error::Error;
+    use std::fs;
+    use std::path::Path;
+    use k8s_openapi::api::core::v1::{ConfigMap, Container, EnvVar, EnvVarSource, PodSpec, Secret, SecretKeySelector};
+    use k8s_openapi::ByteString;
+    use crate::controllers::secretenv::{secret_env, SecretEnv};
+    use crate::exec::ShellExec;
+    use crate::test_helpers::temp_dir::TempDir;
+
+    const SECRET: &str = r#"[{"ID": "1", "CreatedAt": "2024-01-01T00:00:00Z", "UpdatedAt": "2024-01-01T00:00:00Z",
+        "Spec": {"Name": "db.shop", "Driver": {"Name": "file", "Options": {}}, "Labels": {}},
+        "SecretData": "apiVersion: v1\nkind: Secret\nmetadata:\n  name: db.shop\ndata:\n  password: aHVudGVyMg==\n"}]"#;
+
+    struct MockExec;
+
+    impl ShellExec for MockExec {
+        fn exec(&self, command: &str, args: &[&str]) -> Result<String, Box<dyn Error>> {
+            match (command, args) {
+                ("podman", ["secret", "exists", "db.shop"]) => Ok("".to_string()),
+                ("podman", ["secret", "inspect", "--showsecret", "db.shop"]) => Ok(SECRET.to_string()),
+                _ => Err(format!("unexpected command {} {}", command, args.join(" ")).into()),
+            }
+        }
+
+        fn exec_stdout(&self, command: &str, args: &[&str]) -> Result<(), Box<dyn Error>> {
+            Err(format!("unexpected command {} {}", command, args.join(" ")).into())
+        }
+    }
+
+    fn secret_var(name: &str, secret: &str, key: &str, optional: Option<bool>) -> EnvVar {
+        EnvVar {
+            name: name.to_string(),
+            value_from: Some(EnvVarSource {
+                secret_key_ref: Some(SecretKeySelector { name: secret.to_string(), key: key.to_string(), optional }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn spec(env: Vec<EnvVar>) -> PodSpec {
+        PodSpec {
+            containers: vec![Container { name: "api".to_string(), env: Some(env), ..Default::default() }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_secret_env() {
+        let secret = Secret { data: Some(BTreeMap::from([("password".to_string(), ByteString(b"hunter2".to_vec()))])), ..Default::default() };
+        let lookup = |name: &str| -> Result<Option<Secret>, Box<dyn Error>> {
+            Ok(Some(secret.clone()).filter(|_| name == "db.shop"))
+        };
+        let mut spec = spec(vec![
+            EnvVar { name: "PLAIN".to_string(), value: Some("1".to_string()), ..Default::default() },
+            secret_var("PASSWORD", "db.shop", "password", None),
+            secret_var("TOKEN", "missing.shop", "token", Some(true)),
+        ]);
+
+        let data = secret_env("api.shop", &mut spec, lookup).unwrap();
+        assert_eq!(BTreeMap::from([("api.PASSWORD".to_string(), "hunter2".to_string())]), data);
+
+        let env = spec.containers[0].env.as_ref().unwrap();
+        assert_eq!(vec!["PLAIN", "PASSWORD"], env.iter().map(|e| e.name.as_str()).collect::<Vec<_>>());
+        let value_from = env[1].value_from.as_ref().unwrap();
+        assert!(value_from.secret_key_ref.is_none());
+        let key_ref = value_from.config_map_key_ref.as_ref().unwrap();
+        assert_eq!(("api.shop-secret-env", "api.PASSWORD"), (key_ref.name.as_str(), key_ref.key.as_str()));
+
+        let mut missing = self::spec(vec![secret_var("TOKEN", "db.shop", "token", None)]);
+        assert!(secret_env("api.shop", &mut missing, lookup).is_err());
+    }
+
+    #[test]
+    fn test_prepare_and_cleanup() {
+        let dir = TempDir::new("secret-env");
+        let secret_env = SecretEnv { execer: &MockExec, dir: dir.join("env").to_string_lossy().to_string() };
+
+        let mut plain = spec(vec![EnvVar { name: "PLAIN".to_string(), value: Some("1".to_string()), ..Default::default() }]);
+        assert_eq!(None, secret_env.prepare("web.shop", &mut plain).unwrap());
+
+        let mut spec = spec(vec![secret_var("PASSWORD", "db.shop", "password", None)]);
+        let path = secret_env.prepare("api.shop", &mut spec).unwrap().unwrap();
+        let config_map: ConfigMap = serde_yaml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(Some("api.shop-secret-env".to_string()), config_map.metadata.name);
+        assert_eq!(Some(BTreeMap::from([("api.PASSWORD".to_string(), "hunter2".to_string())])), config_map.data);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(0o600, fs::metadata(&path).unwrap().permissions().mode() & 0o777);
+            assert_eq!(0o700, fs::metadata(dir.join("env")).unwrap().permissions().mode() & 0o777);
+        }
+
+        // applying the pod again writes the file afresh
+        let mut spec = self::spec(vec![secret_var("PASSWORD", "db.shop", "password", None)]);
+        assert_eq!(Some(path.clone()), secret_env.prepare("api.shop", &mut spec).unwrap());
+
+        secret_env.cleanup("api.shop").unwrap();
+        assert!(!Path::new(&path).exists());
+        secret_env.cleanup("api.shop").unwrap();
+    }
+}
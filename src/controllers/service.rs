@@ -109,7 +109,7 @@ impl ServiceController {
         self.execer.exec("systemctl", &["reset-failed", &unit_name])?;
 
         let domain = format!("{}.svc.cluster.skate", name);
-        let dns = DnsService::new(&format!("{}/dns", self.skate_var_path), &self.execer);
+        let dns = DnsService::new(&format!("{}/dns", self.skate_var_path), self.execer.as_ref());
         dns.add_misc_host(ip, domain.clone(), domain)?;
 
         Ok(())
@@ -117,31 +117,31 @@ impl ServiceController {
 
     pub fn delete(&self, service: &Service) -> Result<(), Box<dyn Error>> {
         let ns_name = metadata_name(service);
-        let dns = DnsService::new(&format!("{}/dns", self.skate_var_path), &self.execer);
+        let dns = DnsService::new(&format!("{}/dns", self.skate_var_path), self.execer.as_ref());
         dns.remove(Some(format!("{}.svc.cluster.skate", ns_name)), None )?;
 
         let res = self.execer.exec("systemctl", &["stop", &format!("skate-ipvsmon-{}", &ns_name.to_string())]);
-        if res.is_err() {
-            error!("failed to stop {} ipvsmon: {}", ns_name, res.unwrap_err());
+        if let Err(e) = res {
+            error!("failed to stop {} ipvsmon: {}", ns_name, e);
         }
 
         let res = self.execer.exec("systemctl", &["disable", &format!("skate-ipvsmon-{}", &ns_name.to_string())]);
-        if res.is_err() {
-            error!("failed to disable {} ipvsmon: {}", ns_name, res.unwrap_err());
+        if let Err(e) = res {
+            error!("failed to disable {} ipvsmon: {}", ns_name, e);
         }
 
         let res = self.execer.exec("rm", &[&format!("{}/skate-ipvsmon-{}.service",self.systemd_unit_path, &ns_name.to_string())]);
-        if res.is_err() {
-            error!("failed to remove {} ipvsmon service: {}", ns_name, res.unwrap_err());
+        if let Err(e) = res {
+            error!("failed to remove {} ipvsmon service: {}", ns_name, e);
         }
         let res = self.execer.exec("rm", &[&format!("{}/skate-ipvsmon-{}.timer", self.systemd_unit_path, &ns_name.to_string())]);
-        if res.is_err() {
-            error!("failed to remove {} ipvsmon timer: {}", ns_name, res.unwrap_err());
+        if let Err(e) = res {
+            error!("failed to remove {} ipvsmon timer: {}", ns_name, e);
         }
 
         let res = self.execer.exec("rm", &[&format!("{}/keepalived/{}.conf", self.skate_var_path, &ns_name.to_string())]);
-        if res.is_err() {
-            error!("failed to remove {} keepalived conf: {}", ns_name, res.unwrap_err());
+        if let Err(e) = res {
+            error!("failed to remove {} keepalived conf: {}", ns_name, e);
         }
 
         self.execer.exec("systemctl", &["daemon-reload"])?;
@@ -0,0 +1,194 @@
+use anyhow::anyhow;
+use serde_json::{json, Value as JsonValue};
+use serde_yaml::Value;
+use crate::errors::SkateError;
+
+// the older api versions of each supported kind that skate still reads, and the one they're converted to
+const CONVERSIONS: &[(&str, &[&str], &str)] = &[
+    ("Deployment", &["extensions/v1beta1", "apps/v1beta1", "apps/v1beta2"], "apps/v1"),
+    ("DaemonSet", &["extensions/v1beta1", "apps/v1beta2"], "apps/v1"),
+    ("Ingress", &["extensions/v1beta1", "networking.k8s.io/v1beta1"], "networking.k8s.io/v1"),
+    ("CronJob", &["batch/v1beta1", "batch/v2alpha1"], "batch/v1"),
+    ("ClusterIssuer", &["cert-manager.io/v1alpha2", "cert-manager.io/v1alpha3", "cert-manager.io/v1beta1", "certmanager.k8s.io/v1alpha1"], "cert-manager.io/v1"),
+];
+
+#[derive(Debug, PartialEq)]
+pub struct Conversion {
+    pub kind: String,
+    pub from: String,
+    pub to: String,
+}
+
+// converts a manifest of an older api version to the one skate uses, returning what was converted.
+// manifests of other versions are returned as they are
+pub fn to_canonical(manifest: Value) -> Result<(Value, Option<Conversion>), SkateError> {
+    let kind = manifest["kind"].as_str().unwrap_or_default().to_string();
+    let api_version = manifest["apiVersion"].as_str().unwrap_or_default().to_string();
+
+    let to = match CONVERSIONS.iter().find(|(k, from, _)| *k == kind && from.contains(&api_version.as_str())) {
+        Some((_, _, to)) => *to,
+        None => return Ok((manifest, None)),
+    };
+
+    let mut converted: JsonValue = serde_json::to_value(&manifest).map_err(|e| anyhow!(e).context(format!("failed to convert {} {}", api_version, kind)))?;
+    converted["apiVersion"] = json!(to);
+    match kind.as_str() {
+        "Deployment" | "DaemonSet" => convert_workload(&mut converted),
+        "Ingress" => convert_ingress(&mut converted),
+        _ => {}
+    }
+
+    let converted = serde_yaml::to_value(&converted)?;
+    Ok((converted, Some(Conversion { kind, from: api_version, to: to.to_string() })))
+}
+
+// the beta workloads defaulted the selector to the template's labels, and had fields that were dropped from apps/v1
+fn convert_workload(manifest: &mut JsonValue) {
+    let spec = match manifest["spec"].as_object_mut() {
+        Some(spec) => spec,
+        None => return,
+    };
+    spec.remove("rollbackTo");
+    spec.remove("templateGeneration");
+    if !spec.contains_key("selector") {
+        if let Some(labels) = spec.get("template").and_then(|t| t["metadata"]["labels"].as_object()).cloned() {
+            spec.insert("selector".to_string(), json!({"matchLabels": labels}));
+        }
+    }
+}
+
+// v1 moved the backend's service into its own object, renamed spec.backend and made pathType required
+fn convert_ingress(manifest: &mut JsonValue) {
+    let spec = match manifest["spec"].as_object_mut() {
+        Some(spec) => spec,
+        None => return,
+    };
+    if let Some(mut backend) = spec.remove("backend") {
+        convert_backend(&mut backend);
+        spec.insert("defaultBackend".to_string(), backend);
+    }
+
+    let rules = spec.get_mut("rules").and_then(|r| r.as_array_mut()).into_iter().flatten();
+    let paths = rules.filter_map(|r| r["http"]["paths"].as_array_mut()).flatten();
+    for path in paths {
+        convert_backend(&mut path["backend"]);
+        if path["pathType"].is_null() {
+            path["pathType"] = json!("ImplementationSpecific");
+        }
+    }
+}
+
+fn convert_backend(backend: &mut JsonValue) {
+    let backend = match backend.as_object_mut() {
+        Some(backend) => backend,
+        None => return,
+    };
+    let name = match backend.remove("serviceName") {
+        Some(name) => name,
+        None => return,
+    };
+    let port = match backend.remove("servicePort") {
+        Some(JsonValue::String(port)) => match port.parse::<u16>() {
+            Ok(number) => json!({"number": number}),
+            Err(_) => json!({"name": port}),
+        },
+        Some(port) => json!({"number": port}),
+        None => json!({}),
+    };
+    backend.insert("service".to_string(), json!({"name": name, "port": port}));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::conversion::{to_canonical, Conversion};
+    use crate::resource::SupportedResources;
+
+    #[test]
+    fn test_ingress_to_canonical() {
+        let manifest: serde_yaml::Value = serde_yaml::from_str(r#"
+apiVersion: networking.k8s.io/v1beta1
+kind: Ingress
+metadata:
+  name: foo
+spec:
+  backend:
+    serviceName: default
+    servicePort: 80
+  rules:
+  - host: foo.example.com
+    http:
+      paths:
+      - path: /
+        backend:
+          serviceName: foo
+          servicePort: http
+      - path: /api
+        pathType: Prefix
+        backend:
+          serviceName: api
+          servicePort: "8080"
+"#).unwrap();
+
+        let (converted, conversion) = to_canonical(manifest).unwrap();
+        assert_eq!(Some(Conversion { kind: "Ingress".to_string(), from: "networking.k8s.io/v1beta1".to_string(), to: "networking.k8s.io/v1".to_string() }), conversion);
+
+        let expected: serde_yaml::Value = serde_yaml::from_str(r#"
+apiVersion: networking.k8s.io/v1
+kind: Ingress
+metadata:
+  name: foo
+spec:
+  defaultBackend:
+    service: {name: default, port: {number: 80}}
+  rules:
+  - host: foo.example.com
+    http:
+      paths:
+      - path: /
+        pathType: ImplementationSpecific
+        backend:
+          service: {name: foo, port: {name: http}}
+      - path: /api
+        pathType: Prefix
+        backend:
+          service: {name: api, port: {number: 8080}}
+"#).unwrap();
+        assert_eq!(expected, converted);
+        assert!(SupportedResources::try_from(&converted).is_ok());
+    }
+
+    #[test]
+    fn test_deployment_to_canonical() {
+        let manifest: serde_yaml::Value = serde_yaml::from_str(r#"
+apiVersion: extensions/v1beta1
+kind: Deployment
+metadata:
+  name: foo
+spec:
+  rollbackTo:
+    revision: 1
+  template:
+    metadata:
+      labels:
+        app: foo
+    spec:
+      containers:
+      - name: foo
+        image: foo:1
+"#).unwrap();
+
+        let (converted, conversion) = to_canonical(manifest).unwrap();
+        assert_eq!("apps/v1", conversion.unwrap().to);
+        assert_eq!("foo", converted["spec"]["selector"]["matchLabels"]["app"].as_str().unwrap());
+        assert!(converted["spec"].get("rollbackTo").is_none());
+        assert!(matches!(SupportedResources::try_from(&converted).unwrap(), SupportedResources::Deployment(_)));
+    }
+
+    #[test]
+    fn test_canonical_unchanged() {
+        let manifest: serde_yaml::Value = serde_yaml::from_str("apiVersion: v1\nkind: Pod\nmetadata:\n  name: foo\n").unwrap();
+        let (converted, conversion) = to_canonical(manifest.clone()).unwrap();
+        assert_eq!(manifest, converted);
+        assert_eq!(None, conversion);
+    }
+}
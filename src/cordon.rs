@@ -1,4 +1,4 @@
-use crate::config::Config;
+use crate::config::{Access, Config};
 use crate::skate::ConfigFileArgs;
 use anyhow::anyhow;
 use clap::Args;
@@ -29,9 +29,9 @@ pub struct Cordon<D:CordonDeps> {
 
 impl <D: CordonDeps> Cordon<D> {
     pub async fn cordon(&self, args: CordonArgs) -> Result<(), SkateError> {
-        let config = Config::load(Some(args.config.skateconfig.clone()))?;
+        let config = Config::load(args.config.skateconfig.clone())?;
 
-        let cluster = config.active_cluster(config.current_context.clone())?;
+        let cluster = config.authorized_cluster(config.current_context.clone(), Access::Admin)?;
 
         let node = cluster.nodes.iter().find(|n| n.name == args.node).ok_or("node not found".to_string())?;
 
@@ -45,9 +45,9 @@ impl <D: CordonDeps> Cordon<D> {
 
 
     pub async fn uncordon(&self, args: UncordonArgs) -> Result<(), SkateError> {
-        let config = Config::load(Some(args.config.skateconfig.clone()))?;
+        let config = Config::load(args.config.skateconfig.clone())?;
 
-        let cluster = config.active_cluster(config.current_context.clone())?;
+        let cluster = config.authorized_cluster(config.current_context.clone(), Access::Admin)?;
 
         let node = cluster.nodes.iter().find(|n| n.name == args.node).ok_or("node not found".to_string())?;
 
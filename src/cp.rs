@@ -0,0 +1,94 @@
+use std::path::{Path, PathBuf};
+use anyhow::anyhow;
+use clap::Args;
+use crate::config::{Access, Config};
+use crate::deps::{SshManager, With};
+use crate::errors::SkateError;
+use crate::skate::ConfigFileArgs;
+use crate::util::CHECKBOX_EMOJI;
+
+#[derive(Debug, Args)]
+#[command(arg_required_else_help(true))]
+pub struct CpArgs {
+    #[command(flatten)]
+    config: ConfigFileArgs,
+    #[arg(long, default_value = "644", long_help = "The octal mode of a file copied to a node.")]
+    mode: String,
+    #[arg(name = "SRC", long_help = "A local path, or NODE:PATH on a node")]
+    source: String,
+    #[arg(name = "DEST", long_help = "A local path, or NODE:PATH on a node")]
+    destination: String,
+}
+
+pub trait CpDeps: With<dyn SshManager> {}
+
+pub struct Cp<D: CpDeps> {
+    pub deps: D,
+}
+
+// the node and path of NODE:PATH, when NODE is one of the cluster's nodes
+fn node_path<'a>(arg: &'a str, nodes: &[String]) -> Option<(&'a str, &'a str)> {
+    arg.split_once(':').filter(|(node, path)| !path.is_empty() && nodes.iter().any(|n| n == node))
+}
+
+// copying into a directory keeps the file's name, as cp does
+fn local_destination(local: &Path, remote_path: &str) -> PathBuf {
+    match (local.is_dir(), Path::new(remote_path).file_name()) {
+        (true, Some(name)) => local.join(name),
+        _ => local.to_path_buf(),
+    }
+}
+
+impl<D: CpDeps> Cp<D> {
+    pub async fn cp(&self, args: CpArgs) -> Result<(), SkateError> {
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), Access::Admin)?;
+        let mode = u32::from_str_radix(&args.mode, 8).map_err(|_| anyhow!("--mode {} isn't an octal file mode", args.mode))?;
+
+        let nodes: Vec<_> = cluster.nodes.iter().map(|n| n.name.clone()).collect();
+        let (node_name, remote_path, upload) = match (node_path(&args.source, &nodes), node_path(&args.destination, &nodes)) {
+            (None, Some((node, path))) => (node, path, true),
+            (Some((node, path)), None) => (node, path, false),
+            (Some(_), Some(_)) => return Err(anyhow!("can't copy from one node to another, copy to a local path first").into()),
+            (None, None) => return Err(anyhow!("one of the paths must be NODE:PATH, with NODE one of {}", nodes.join(", ")).into()),
+        };
+
+        let node = cluster.nodes.iter().find(|n| n.name == node_name).ok_or(anyhow!("failed to find node {}", node_name))?;
+        let conn = self.deps.get().node_connect(cluster, node).await?;
+
+        let transfer = match upload {
+            true => {
+                let mut file = tokio::fs::File::open(&args.source).await.map_err(|e| anyhow!(e).context(format!("failed to open {}", args.source)))?;
+                conn.upload(&mut file, remote_path, mode).await?
+            }
+            false => {
+                let local = local_destination(Path::new(&args.destination), remote_path);
+                conn.download(remote_path, &local).await?
+            }
+        };
+
+        let direction = format!("{} to {}", args.source, args.destination);
+        match (transfer.unchanged, transfer.resumed_from) {
+            (true, _) => println!("{} {} is unchanged", CHECKBOX_EMOJI, args.destination),
+            (false, 0) => println!("{} copied {} ({} bytes)", CHECKBOX_EMOJI, direction, transfer.size),
+            (false, resumed_from) => println!("{} copied {} ({} bytes, resumed from {})", CHECKBOX_EMOJI, direction, transfer.size, resumed_from),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cp::node_path;
+
+    #[test]
+    fn test_node_path() {
+        let nodes = vec!["node-1".to_string(), "node-2".to_string()];
+        assert_eq!(Some(("node-1", "/etc/skate/routes.sh")), node_path("node-1:/etc/skate/routes.sh", &nodes));
+        assert_eq!(Some(("node-2", "backup.tar")), node_path("node-2:backup.tar", &nodes));
+        // a colon in a local path isn't a node
+        assert_eq!(None, node_path("./notes:2024.txt", &nodes));
+        assert_eq!(None, node_path("node-3:/tmp/x", &nodes));
+        assert_eq!(None, node_path("node-1:", &nodes));
+    }
+}
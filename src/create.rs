@@ -1,9 +1,10 @@
+use std::collections::BTreeMap;
 use anyhow::anyhow;
 use clap::{Args, Subcommand};
 use itertools::Itertools;
 use node::CreateNodeArgs;
 use crate::apply::ApplyDeps;
-use crate::config::{Cluster, Config};
+use crate::config::{Access, Cluster, Config};
 use crate::deps::{SshManager, With};
 use crate::errors::SkateError;
 use crate::refresh::{Refresh, RefreshDeps};
@@ -12,7 +13,8 @@ use crate::skate::ConfigFileArgs;
 use crate::skatelet::JobArgs;
 use crate::util::NamespacedName;
 
-mod node;
+pub(crate) mod node;
+pub(crate) mod topology;
 
 #[derive(Debug, Args)]
 pub struct CreateArgs {
@@ -42,19 +44,22 @@ pub struct CreateJobArgs {
     config: ConfigFileArgs,
     #[command(flatten)]
     args: JobArgs,
-    #[arg(long, short, long_help = "Namespace of the resource.", default_value_t = String::from("default"))]
-    namespace: String,
+    #[arg(long, short, long_help = "Namespace of the resource, the cluster's default namespace if not given.")]
+    namespace: Option<String>,
 }
 
 #[derive(Debug, Args)]
 pub struct CreateClusterArgs {
-    #[arg(long, long_help = "Configuration for skate.", default_value = "~/.skate/config.yaml")]
-    skateconfig: String,
+    #[arg(long, long_help = "Configuration for skate. Defaults to the files listed in $SKATECONFIG, then the closest .skate/config \
+of the current directory, then ~/.skate/config.yaml.")]
+    skateconfig: Option<String>,
     name: String,
     #[arg(long, long_help = "Default ssh user for connecting to nodes")]
     default_user: Option<String>,
     #[arg(long, long_help = "Default ssh key for connecting to nodes")]
     default_key: Option<String>,
+    #[arg(long, long_help = "Manage the firewall on each node, opening only the ports published by pods, the ingress and ssh.")]
+    firewall: bool,
 }
 
 pub trait CreateDeps: With<dyn SshManager> + RefreshDeps + ApplyDeps {}
@@ -75,33 +80,49 @@ impl<D: CreateDeps> Create<D> {
     }
 
     async fn create_cluster(&self, args: CreateClusterArgs) -> Result<(), SkateError> {
-        let mut config = Config::load(Some(args.skateconfig.clone()))?;
+        let mut config = Config::load(args.skateconfig.clone())?;
 
         let cluster = Cluster {
             default_key: args.default_key,
             default_user: args.default_user,
             name: args.name.clone(),
             nodes: vec!(),
+            policies: vec!(),
+            notifications: vec!(),
+            cert_expiry_days: None,
+            profile: None,
+            firewall: args.firewall,
+            ingress_classes: vec!(),
+            priority_classes: BTreeMap::new(),
+            limit_ranges: vec![],
+            credentials: None,
+            become_: None,
+            logging: None,
+            ingress_default_page: None,
+            node_pools: vec![],
+            defaults: None,
+            overlay: None,
+            allowed_unsafe_sysctls: vec![],
         };
 
         if config.clusters.iter().any(|c| c.name == args.name) {
-            return Err(anyhow!("cluster by name of {} already exists in {}", args.name, args.skateconfig).into());
+            return Err(anyhow!("cluster by name of {} already exists in {}", args.name, Config::path(args.skateconfig.clone())).into());
         }
 
         config.clusters.push(cluster.clone());
         config.current_context = Some(args.name.clone());
 
-        config.persist(Some(args.skateconfig.clone()))?;
+        config.persist(args.skateconfig.clone())?;
 
-        println!("added cluster {} to {}", args.name, args.skateconfig);
+        println!("added cluster {} to {}", args.name, Config::path(args.skateconfig.clone()));
 
         Ok(())
     }
 
     async fn create_cluster_resources(&self, args: CreateClusterResourcesArgs) -> Result<(), SkateError> {
-        let config = Config::load(Some(args.config.skateconfig.clone()))?;
+        let config = Config::load(args.config.skateconfig.clone())?;
 
-        let cluster = config.active_cluster(args.config.context.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), Access::Admin)?;
 
         node::install_cluster_manifests(&self.deps, &args.config, cluster).await?;
         Ok(())
@@ -114,9 +135,10 @@ impl<D: CreateDeps> Create<D> {
             return Err("only cronjob is supported".to_string().into());
         }
 
-        let config = Config::load(Some(args.config.skateconfig.clone()))?;
+        let config = Config::load(args.config.skateconfig.clone())?;
 
-        let cluster = config.active_cluster(args.config.context)?;
+        let cluster = config.authorized_cluster(args.config.context, Access::Deploy)?;
+        let namespace = args.namespace.clone().unwrap_or(cluster.default_namespace().to_string());
 
         let ssh_mgr= self.deps.get();
 
@@ -137,12 +159,12 @@ impl<D: CreateDeps> Create<D> {
         let state = &Refresh::<D>::refreshed_state(&cluster.name, &conns, &config).await.expect("failed to refresh state");
 
 
-        let search_name = NamespacedName { name: from_name.to_string(), namespace: args.namespace.clone() };
+        let search_name = NamespacedName { name: from_name.to_string(), namespace: namespace.clone() };
 
         let cjobs = state.catalogue(None, &[ResourceType::CronJob]).into_iter().filter(|c| c.object.name == search_name).collect_vec();
 
         if cjobs.is_empty() {
-            return Err(anyhow!("no cronjobs found by name of {} in namespace {}", args.args.from, args.namespace).into());
+            return Err(anyhow!("no cronjobs found by name of {} in namespace {}", args.args.from, namespace).into());
         }
 
         let cjob = cjobs.first().unwrap();
@@ -153,7 +175,7 @@ impl<D: CreateDeps> Create<D> {
 
         let wait_flag = if args.args.wait { "--wait" } else { "" };
 
-        let cmd = format!("sudo skatelet create --namespace {} job {} --from {} {}", &args.namespace, &wait_flag, &args.args.from, &args.args.name);
+        let cmd = format!("sudo skatelet create --namespace {} job {} --from {} {}", &namespace, &wait_flag, &args.args.from, &args.args.name);
         conn.execute_stdout(&cmd,false, false).await?;
         Ok(())
     }
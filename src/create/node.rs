@@ -2,27 +2,28 @@ use std::error::Error;
 use anyhow::anyhow;
 use semver::{Version, VersionReq};
 use std::fs::File;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use clap::Args;
 use itertools::Itertools;
 use std::io::Write;
 use std::net::{ToSocketAddrs};
 use validator::Validate;
-use crate::apply::{Apply, ApplyArgs};
-use crate::config::{Cluster, Config, Node};
-use crate::create::CreateDeps;
-use crate::{oci, util};
+use crate::apply::{Apply, ApplyArgs, ApplyOutput};
+use crate::config::{parse_system_reserved, Access, Cluster, Config, Node};
+use crate::create::{topology, CreateDeps};
+use crate::{ingress_class, oci};
 use crate::errors::SkateError;
+use crate::progress::progress;
 use crate::refresh::Refresh;
+use crate::report::{reported, Action, ActionResult};
 use crate::resource::{ResourceType, SupportedResources};
-use crate::scheduler::{DefaultScheduler, Scheduler};
+use crate::scheduler::{DefaultScheduler, Scheduler, DEFAULT_MAX_ATTEMPTS};
 use crate::skate::{ConfigFileArgs, Distribution};
 use crate::ssh::{SshClient, SshClients};
 use crate::state::state::ClusterState;
 use crate::util::{CHECKBOX_EMOJI, CROSS_EMOJI, RE_CIDR, RE_IP};
 
 const COREDNS_MANIFEST: &str = include_str!("../../manifests/coredns.yaml");
-const INGRESS_MANIFEST: &str = include_str!("../../manifests/ingress.yaml");
 
 #[derive(Debug, Args, Validate)]
 pub struct CreateNodeArgs {
@@ -42,16 +43,29 @@ pub struct CreateNodeArgs {
     #[validate(regex(path = *RE_CIDR, message = "subnet-cidr must be a valid ipv4 cidr range"))]
     #[arg(long, long_help = "Subnet cidr for podman network (must be unique range per host)")]
     subnet_cidr: String,
+    #[arg(long, value_parser = parse_system_reserved, long_help = "Cpu and memory to keep free for the os, eg cpu=500m,memory=1Gi. Keeps the existing reservation if not given.")]
+    system_reserved: Option<BTreeMap<String, String>>,
+
+    #[arg(long, long_help = "Print the actions taken as json.")]
+    json: bool,
 
     #[command(flatten)]
     config: ConfigFileArgs,
 }
 
 pub async fn create_node<D: CreateDeps>(deps: &D, args: CreateNodeArgs) -> Result<(), SkateError> {
+    reported("create node", args.json, async {
+        let mut actions = vec!();
+        let result = add_node(deps, args, &mut actions).await;
+        (actions, result)
+    }).await
+}
+
+async fn add_node<D: CreateDeps>(deps: &D, args: CreateNodeArgs, actions: &mut Vec<Action>) -> Result<(), SkateError> {
     args.validate()?;
-    let mut config = Config::load(Some(args.config.skateconfig.clone()))?;
+    let mut config = Config::load(args.config.skateconfig.clone())?;
 
-    let mut cluster = config.active_cluster(args.config.context.clone())?.clone();
+    let mut cluster = config.authorized_cluster(args.config.context.clone(), Access::Admin)?.clone();
 
     let mut nodes_iter = cluster.nodes.clone().into_iter();
 
@@ -60,7 +74,7 @@ pub async fn create_node<D: CreateDeps>(deps: &D, args: CreateNodeArgs) -> Resul
     // will clobber
     // TODO - ask
 
-    let node = Node {
+    let mut node = Node {
         name: args.name.clone(),
         host: args.host.clone(),
         peer_host: args.peer_host.clone().unwrap_or(args.host.clone()),
@@ -68,14 +82,29 @@ pub async fn create_node<D: CreateDeps>(deps: &D, args: CreateNodeArgs) -> Resul
         user: args.user.clone(),
         key: args.key.clone(),
         subnet_cidr: args.subnet_cidr.clone(),
-
+        labels: existing_index.map(|i| cluster.nodes[i].labels.clone()).unwrap_or_default(),
+        system_reserved: args.system_reserved.clone()
+            .or(existing_index.map(|i| cluster.nodes[i].system_reserved.clone()))
+            .unwrap_or_default(),
     };
 
+    // an unreachable node fails provisioning below anyway
+    if let Ok(conn) = deps.get().node_connect(&cluster, &node).await {
+        progress().start(&node.name, "detecting failure domain");
+        topology::merge(&mut node.labels, topology::detect(conn.as_ref()).await);
+    }
+
     match existing_index {
         Some(idx) => {
+            let result = match cluster.nodes[idx] == node {
+                true => ActionResult::Unchanged,
+                false => ActionResult::Updated,
+            };
+            actions.push(Action::new("update config", &node.name, result));
             cluster.nodes[idx] = node.clone();
         }
         None => {
+            actions.push(Action::new("update config", &node.name, ActionResult::Created));
             cluster.nodes.push(node.clone());
         }
     };
@@ -83,23 +112,56 @@ pub async fn create_node<D: CreateDeps>(deps: &D, args: CreateNodeArgs) -> Resul
     config.replace_cluster(&cluster)?;
 
 
-    config.persist(Some(args.config.skateconfig.clone()))?;
-    
+    config.persist(args.config.skateconfig.clone())?;
+
+    let result = provision_node(deps, &args.config, &config, &cluster, &node, &ProvisionOptions::default(), actions).await;
+    match &result {
+        Ok(_) => progress().finish(&node.name),
+        Err(e) => {
+            progress().fail(&node.name, &e.to_string());
+            actions.push(Action::new("provision", &node.name, ActionResult::Failed).with_message(&e.to_string()));
+        }
+    }
+    result
+}
 
-    let conn = deps.get().node_connect(&cluster, &node).await.map_err(|e| -> Box<dyn Error> { anyhow!("{}", e).into() })?;
+pub(crate) struct ProvisionOptions {
+    pub upgrade_packages: bool,
+    // apply the cluster manifests and propagate existing resources once the node is set up.
+    // skipped when provisioning several nodes at once, since it only needs doing after the last one
+    pub cluster_setup: bool,
+}
+
+impl Default for ProvisionOptions {
+    fn default() -> Self {
+        ProvisionOptions {
+            upgrade_packages: true,
+            cluster_setup: true,
+        }
+    }
+}
+
+pub(crate) async fn provision_node<D: CreateDeps>(deps: &D, config_args: &ConfigFileArgs, config: &Config, cluster: &Cluster, node: &Node, opts: &ProvisionOptions, actions: &mut Vec<Action>) -> Result<(), SkateError> {
+    let conn = deps.get().node_connect(cluster, node).await.map_err(|e| -> Box<dyn Error> { anyhow!("{}", e).into() })?;
     let info = conn.get_node_system_info().await?;
 
     println!("{:}", &info.platform);
 
-    conn.execute_stdout("sudo apt-get update && sudo DEBIAN_FRONTEND=noninteractive apt-get -y upgrade", true, true).await?;
+    if opts.upgrade_packages {
+        progress().start(&node.name, "updating packages");
+        conn.execute_stdout("sudo apt-get update && sudo DEBIAN_FRONTEND=noninteractive apt-get -y upgrade", true, true).await?;
+        actions.push(Action::new("update packages", &node.name, ActionResult::Applied));
+    }
 
     match info.skatelet_version.as_ref() {
         None => {
-            // install skatelet
+            // install skatelet, its download, upload and install are reported as they happen
             conn.install_skatelet(info.platform.clone()).await?;
+            actions.push(Action::new("install skatelet", &node.name, ActionResult::Created));
         }
         Some(v) => {
-            println!("skatelet version {} already installed {} ", v, CHECKBOX_EMOJI)
+            println!("skatelet version {} already installed {} ", v, CHECKBOX_EMOJI);
+            actions.push(Action::new("install skatelet", &node.name, ActionResult::Unchanged).with_message(v));
         }
     }
 
@@ -112,10 +174,12 @@ pub async fn create_node<D: CreateDeps>(deps: &D, args: CreateNodeArgs) -> Resul
             if !req.matches(&version) {
                 return Err(anyhow!("podman version too old, must be {}, see https://podman.io/docs/installation", min_podman_ver).into());
             }
-            println!("podman version {} already installed {} ", version, CHECKBOX_EMOJI)
+            println!("podman version {} already installed {} ", version, CHECKBOX_EMOJI);
+            actions.push(Action::new("install podman", &node.name, ActionResult::Unchanged).with_message(&version.to_string()));
         }
         // instruct on installing newer podman version
         None => {
+            progress().start(&node.name, "installing podman");
             let installed = match info.platform.distribution {
                 Distribution::Unknown => false,
                 Distribution::Debian | Distribution::Raspbian | Distribution::Ubuntu => {
@@ -137,14 +201,16 @@ pub async fn create_node<D: CreateDeps>(deps: &D, args: CreateNodeArgs) -> Resul
             if !installed {
                 return Err(anyhow!("podman not installed, see https://podman.io/docs/installation").into());
             }
+            actions.push(Action::new("install podman", &node.name, ActionResult::Created));
         }
     }
 
     // seems to be missing when using kube play
+    progress().start(&node.name, "pulling pause image");
     let cmd = "sudo podman image exists k8s.gcr.io/pause:3.5 || sudo podman pull  k8s.gcr.io/pause:3.5";
     let _ = conn.execute_stdout(cmd, true, true).await;
 
-    let (all_conns, _) = deps.get().cluster_connect(&cluster).await;
+    let (all_conns, _) = deps.get().cluster_connect(cluster).await;
     let all_conns = &all_conns.unwrap_or(SshClients { clients: vec!() });
 
     let skate_dirs = [
@@ -155,25 +221,54 @@ pub async fn create_node<D: CreateDeps>(deps: &D, args: CreateNodeArgs) -> Resul
         "/etc/skate",
     ];
 
+    progress().start(&node.name, "configuring logging");
     conn.execute_stdout(&format!("sudo mkdir -p {}", skate_dirs.join(" ")), true, true).await?;
 
     // copy rsyslog config
-    conn.execute_stdout(&util::transfer_file_cmd(include_str!("../resources/10-skate.conf"),  "/etc/rsyslog.d/10-skate.conf"), true, true).await?;
+    conn.upload_bytes(include_str!("../resources/10-skate.conf").as_bytes(), "/etc/rsyslog.d/10-skate.conf", 0o644).await?;
     conn.execute_stdout("sudo chown syslog:adm /etc/rsyslog.d/10-skate.conf", true, true).await?;
     conn.execute_stdout("sudo touch /var/log/skate.log && sudo chown syslog:adm /var/log/skate.log", true, true).await?;
     // restart rsyslog
     conn.execute_stdout("sudo systemctl restart rsyslog", true, true).await?;
 
-    setup_networking(&conn, all_conns, &cluster, &node).await?;
+    progress().start(&node.name, "setting up networking");
+    setup_networking(conn.as_ref(), all_conns, cluster, node).await?;
+    actions.push(Action::new("setup networking", &node.name, ActionResult::Applied));
+
+    progress().start(&node.name, "configuring firewall");
+    let cmd = match cluster.firewall {
+        true => "sudo skatelet firewall enable",
+        false => "sudo skatelet firewall disable",
+    };
+    conn.execute_stdout(cmd, true, true).await?;
+    actions.push(Action::new("configure firewall", &node.name, ActionResult::Applied));
+    // the other nodes have to let the new peer in
+    sync_firewalls(all_conns).await;
+
+    progress().start(&node.name, "installing image gc");
+    install_gc(conn.as_ref()).await?;
+    actions.push(Action::new("install image gc", &node.name, ActionResult::Applied));
+
+    progress().start(&node.name, "installing skatelet daemon");
+    install_daemon(conn.as_ref()).await?;
+    actions.push(Action::new("install skatelet daemon", &node.name, ActionResult::Applied));
+
+    if !opts.cluster_setup {
+        return Ok(());
+    }
 
-    config.persist(Some(args.config.skateconfig.clone()))?;
+    config.persist(config_args.skateconfig.clone())?;
 
     // Refresh state so that we can apply coredns later
-    let state = Refresh::<D>::refreshed_state(&cluster.name, all_conns, &config).await?;
+    let state = Refresh::<D>::refreshed_state(&cluster.name, all_conns, config).await?;
 
-    install_cluster_manifests(deps, &args.config, &cluster).await?;
+    progress().start(&node.name, "applying cluster manifests");
+    install_cluster_manifests(deps, config_args, cluster).await?;
+    actions.push(Action::new("apply cluster manifests", &cluster.name, ActionResult::Applied));
 
-    propagate_static_resources(&config, all_conns, &node, &state).await?;
+    progress().start(&node.name, "propagating resources");
+    propagate_static_resources(config, all_conns, node, &state).await?;
+    actions.push(Action::new("propagate resources", &node.name, ActionResult::Applied));
 
     Ok(())
 }
@@ -199,7 +294,7 @@ async fn propagate_static_resources(_conf: &Config, all_conns: &SshClients, node
     filtered_state.nodes = vec!(state.nodes.iter().find(|n| n.node_name == node.name).cloned().unwrap());
 
 
-    let scheduler = DefaultScheduler {};
+    let scheduler = DefaultScheduler::default();
 
     // TODO - remove
     scheduler.schedule(all_conns, &mut filtered_state, all_manifests, false).await?;
@@ -207,17 +302,46 @@ async fn propagate_static_resources(_conf: &Config, all_conns: &SshClients, node
     Ok(())
 }
 
-pub async fn install_cluster_manifests<D: CreateDeps>(deps: &D, args: &ConfigFileArgs, config: &Cluster) -> Result<(), Box<dyn Error>> {
-    println!("applying cluster manifests");
-    // COREDNS
-    // coredns listens on port 53 and 5533
-    // port 53 serves .cluster.skate by forwarding to all coredns instances on port 5553
-    // uses fanout plugin
+// points <node>.node.cluster.skate at each node's peer host, on every node.
+// nodes that fail are only warned about since the entries are updated again whenever the cluster changes
+pub(crate) async fn update_node_dns(conns: &SshClients, cluster: &Cluster) {
+    let nodes = cluster.nodes.iter().map(|n| format!("{}={}", n.name, n.peer_host)).join(" ");
+    let cmd = format!("sudo skatelet dns nodes {}", nodes);
+    for (node, result) in conns.execute_batch(&[cmd]).await {
+        if let Err(e) = result.and_then(|r| r.into_iter().try_for_each(|r| r.into_result().map(|_| ()))) {
+            eprintln!("failed to update node dns entries on {}: {}", node, e);
+        }
+    }
+}
 
+// resyncs the firewall on every node, which is a no-op on nodes where it isn't enabled.
+// failures are only warned about since the next apply on the node syncs it again
+pub(crate) async fn sync_firewalls(conns: &SshClients) {
+    for (node, result) in conns.execute_batch(&["sudo skatelet firewall sync".to_string()]).await {
+        if let Err(e) = result.and_then(|r| r.into_iter().try_for_each(|r| r.into_result().map(|_| ()))) {
+            eprintln!("failed to sync firewall on {}: {}", node, e);
+        }
+    }
+}
+
+// COREDNS
+// coredns listens on port 53 and 5533
+// port 53 serves .cluster.skate by forwarding to all coredns instances on port 5553
+// uses fanout plugin
+pub fn coredns_manifest(cluster: &Cluster) -> String {
     // replace forward list in coredns config with that of other hosts
-    let fanout_list = config.nodes.iter().map(|n| n.peer_host.clone() + ":5553").join(" ");
+    let fanout_list = cluster.nodes.iter().map(|n| n.peer_host.clone() + ":5553").join(" ");
+    COREDNS_MANIFEST.replace("%%fanout_list%%", &fanout_list)
+}
+
+pub async fn install_cluster_manifests<D: CreateDeps>(deps: &D, args: &ConfigFileArgs, config: &Cluster) -> Result<(), Box<dyn Error>> {
+    let (conns, _) = deps.get().cluster_connect(config).await;
+    if let Some(conns) = conns {
+        update_node_dns(&conns, config).await;
+    }
 
-    let coredns_yaml = COREDNS_MANIFEST.replace("%%fanout_list%%", &fanout_list);
+    println!("applying cluster manifests");
+    let coredns_yaml = coredns_manifest(config);
 
     let coredns_yaml_path = "/tmp/skate-coredns.yaml".to_string();
     let mut file = File::create(&coredns_yaml_path)?;
@@ -226,34 +350,75 @@ pub async fn install_cluster_manifests<D: CreateDeps>(deps: &D, args: &ConfigFil
 
     Apply::<D>::apply(deps, ApplyArgs {
         filename: vec![coredns_yaml_path],
+        kustomize: None,
         grace_period: 0,
         config: args.clone(),
         dry_run: false,
+        // skate's own system manifests aren't subject to user policies
+        override_policy: true,
+        max_attempts: DEFAULT_MAX_ATTEMPTS,
+        no_cleanup: false,
+        output: ApplyOutput::Table,
+        verbose: false,
+        if_match: None,
+        seed: None,
     }).await?;
 
     // nginx ingress
 
     let nginx_yaml_path = "/tmp/skate-nginx-ingress.yaml".to_string();
     let mut file = File::create(&nginx_yaml_path)?;
-    file.write_all(INGRESS_MANIFEST.as_bytes())?;
+    file.write_all(ingress_class::default_manifest(config.ingress_default_page.as_ref())?.as_bytes())?;
 
 
     Apply::<D>::apply(deps, ApplyArgs {
         filename: vec![nginx_yaml_path],
+        kustomize: None,
         grace_period: 0,
         config: args.clone(),
         dry_run: false,
+        // skate's own system manifests aren't subject to user policies
+        override_policy: true,
+        max_attempts: DEFAULT_MAX_ATTEMPTS,
+        no_cleanup: false,
+        output: ApplyOutput::Table,
+        verbose: false,
+        if_match: None,
+        seed: None,
     }).await?;
 
+    // a proxy for each of the other ingress classes, on the nodes they select
+    ingress_class::validate(&config.ingress_classes)?;
+    for class in &config.ingress_classes {
+        let class_yaml_path = format!("/tmp/skate-nginx-ingress-{}.yaml", class.name);
+        let mut file = File::create(&class_yaml_path)?;
+        file.write_all(ingress_class::daemonset_manifest(class, config.ingress_default_page.as_ref())?.as_bytes())?;
+
+        Apply::<D>::apply(deps, ApplyArgs {
+            filename: vec![class_yaml_path],
+            kustomize: None,
+            grace_period: 0,
+            config: args.clone(),
+            dry_run: false,
+            override_policy: true,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            no_cleanup: false,
+            output: ApplyOutput::Table,
+            verbose: false,
+            if_match: None,
+            seed: None,
+        }).await?;
+    }
+
     Ok(())
 }
 
 // TODO don't run things unless they need to be
-async fn setup_networking(conn: &Box<dyn SshClient>, all_conns: &SshClients, cluster_conf: &Cluster, node: &Node) -> Result<(), Box<dyn Error>> {
+async fn setup_networking(conn: &dyn SshClient, all_conns: &SshClients, cluster_conf: &Cluster, node: &Node) -> Result<(), Box<dyn Error>> {
     let network_backend = "netavark";
 
     conn.execute_stdout("sudo apt-get install -y keepalived", true, true).await?;
-    conn.execute_stdout(&util::transfer_file_cmd(include_str!("../resources/keepalived.conf"),  "/etc/keepalived/keepalived.conf"), true, true).await?;
+    conn.upload_bytes(include_str!("../resources/keepalived.conf").as_bytes(), "/etc/keepalived/keepalived.conf", 0o644).await?;
     conn.execute_stdout("sudo systemctl enable keepalived", true, true).await?;
     conn.execute_stdout("sudo systemctl start keepalived", true, true).await?;
 
@@ -277,7 +442,7 @@ async fn setup_networking(conn: &Box<dyn SshClient>, all_conns: &SshClients, clu
         conn.execute_stdout("sudo podman system reset -f", true, true).await?;
     }
 
-    let gateway = node.subnet_cidr.split(".").take(3).join(".") + ".1";
+    let gateway = node.gateway();
     // only allocate from ip 10 onwards, reserves 1-9 for other stuff
 
     match network_backend {
@@ -303,7 +468,7 @@ async fn setup_networking(conn: &Box<dyn SshClient>, all_conns: &SshClients, clu
 
 
     for conn in &all_conns.clients {
-        create_replace_routes_file(conn, cluster_conf).await?;
+        create_replace_routes_file(conn.as_ref(), cluster_conf).await?;
     }
 
     let cmd = "sudo podman image exists ghcr.io/skateco/coredns || sudo podman pull ghcr.io/skateco/coredns";
@@ -346,7 +511,27 @@ async fn setup_networking(conn: &Box<dyn SshClient>, all_conns: &SshClients, clu
     Ok(())
 }
 
-async fn install_oci_hooks(conn: &Box<dyn SshClient>) -> Result<(), Box<dyn Error>> {
+async fn install_gc(conn: &dyn SshClient) -> Result<(), Box<dyn Error>> {
+    conn.upload_bytes(include_str!("../resources/skate-prune-images.service").as_bytes(), "/etc/systemd/system/skate-prune-images.service", 0o644).await?;
+    conn.upload_bytes(include_str!("../resources/skate-prune-images.timer").as_bytes(), "/etc/systemd/system/skate-prune-images.timer", 0o644).await?;
+    conn.upload_bytes(include_str!("../resources/skate-prune-pods.service").as_bytes(), "/etc/systemd/system/skate-prune-pods.service", 0o644).await?;
+    conn.upload_bytes(include_str!("../resources/skate-prune-pods.timer").as_bytes(), "/etc/systemd/system/skate-prune-pods.timer", 0o644).await?;
+
+    conn.execute_stdout("sudo systemctl daemon-reload", true, true).await?;
+    conn.execute_stdout("sudo systemctl enable --now skate-prune-images.timer", true, true).await?;
+    conn.execute_stdout("sudo systemctl enable --now skate-prune-pods.timer", true, true).await?;
+    Ok(())
+}
+
+// the health loops behind /healthz, restarted by systemd's watchdog when they wedge
+async fn install_daemon(conn: &dyn SshClient) -> Result<(), Box<dyn Error>> {
+    conn.upload_bytes(include_str!("../resources/skatelet.service").as_bytes(), "/etc/systemd/system/skatelet.service", 0o644).await?;
+    conn.execute_stdout("sudo systemctl daemon-reload", true, true).await?;
+    conn.execute_stdout("sudo systemctl enable skatelet.service && sudo systemctl restart skatelet.service", true, true).await?;
+    Ok(())
+}
+
+async fn install_oci_hooks(conn: &dyn SshClient) -> Result<(), Box<dyn Error>> {
     conn.execute_stdout("sudo mkdir -p /usr/share/containers/oci/hooks.d", true, true).await?;
 
     let oci_poststart_hook = oci::HookConfig {
@@ -366,7 +551,7 @@ async fn install_oci_hooks(conn: &Box<dyn SshClient>) -> Result<(), Box<dyn Erro
     // serialize to /usr/share/containers/oci/hooks.d/skatelet-poststart.json
     let serialized = serde_json::to_string(&oci_poststart_hook).unwrap();
     let path = "/usr/share/containers/oci/hooks.d/skatelet-poststart.json";
-    conn.execute_stdout(&util::transfer_file_cmd(serialized.as_str(),  path), true, true).await?;
+    conn.upload_bytes(serialized.as_bytes(), path, 0o644).await?;
 
 
     let oci_poststop = oci::HookConfig {
@@ -385,11 +570,11 @@ async fn install_oci_hooks(conn: &Box<dyn SshClient>) -> Result<(), Box<dyn Erro
     };
     let serialized = serde_json::to_string(&oci_poststop).unwrap();
     let path = "/usr/share/containers/oci/hooks.d/skatelet-poststop.json";
-    conn.execute_stdout(&util::transfer_file_cmd(serialized.as_str(),  path), true, true).await?;
+    conn.upload_bytes(serialized.as_bytes(), path, 0o644).await?;
     Ok(())
 }
 
-async fn setup_netavark(conn: &Box<dyn SshClient>, gateway: String, subnet_cidr: String) -> Result<(), Box<dyn Error>> {
+async fn setup_netavark(conn: &dyn SshClient, gateway: String, subnet_cidr: String) -> Result<(), Box<dyn Error>> {
     println!("installing netavark");
     // // The netavark plugin isn't actually used right now but we'll put it there just in case.
     // // We'll use an oci hook instead.
@@ -406,11 +591,11 @@ async fn setup_netavark(conn: &Box<dyn SshClient>, gateway: String, subnet_cidr:
     let netavark_config = include_str!("../resources/podman-network-netavark.json").replace("%%subnet%%", &subnet_cidr)
         .replace("%%gateway%%", &gateway);
 
-    conn.execute_stdout(&util::transfer_file_cmd(&netavark_config, "/etc/containers/networks/skate.json" ), true, true).await?;
+    conn.upload_bytes(netavark_config.as_bytes(), "/etc/containers/networks/skate.json", 0o644).await?;
     Ok(())
 }
 
-async fn create_replace_routes_file(conn: &Box<dyn SshClient>, cluster_conf: &Cluster) -> Result<(), Box<dyn Error>> {
+async fn create_replace_routes_file(conn: &dyn SshClient, cluster_conf: &Cluster) -> Result<(), Box<dyn Error>> {
     let cmd = "sudo mkdir -p /etc/skate";
     conn.execute_stdout(cmd, true, true).await?;
 
@@ -447,8 +632,8 @@ async fn create_replace_routes_file(conn: &Box<dyn SshClient>, cluster_conf: &Cl
     route_file += "sysctl -p\n";
 
 
-    conn.execute_stdout(&util::transfer_file_cmd(&route_file, "/etc/skate/routes.sh" ), true, true).await?;
-    conn.execute_stdout("sudo chmod +x /etc/skate/routes.sh; sudo /etc/skate/routes.sh", true, true).await?;
+    conn.upload_bytes(route_file.as_bytes(), "/etc/skate/routes.sh", 0o755).await?;
+    conn.execute_stdout("sudo /etc/skate/routes.sh", true, true).await?;
 
 
     // Create systemd unit file to call the skate routes file on startup after internet
@@ -456,7 +641,7 @@ async fn create_replace_routes_file(conn: &Box<dyn SshClient>, cluster_conf: &Cl
     let path = "/etc/systemd/system/skate-routes.service";
     let unit_file = include_str!("../resources/skate-routes.service");
 
-    conn.execute_stdout(&util::transfer_file_cmd(&unit_file, path ), true, true).await?;
+    conn.upload_bytes(unit_file.as_bytes(), path, 0o644).await?;
 
     conn.execute_stdout("sudo systemctl daemon-reload", true, true).await?;
     conn.execute_stdout("sudo systemctl enable skate-routes.service", true, true).await?;
@@ -0,0 +1,103 @@
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use serde::Deserialize;
+use crate::ssh::SshClient;
+use crate::util::shell_quote;
+
+pub const REGION_LABEL: &str = "topology.kubernetes.io/region";
+pub const ZONE_LABEL: &str = "topology.kubernetes.io/zone";
+pub const PROVIDER_LABEL: &str = "skate.io/provider";
+
+const DETECT_SCRIPT: &str = include_str!("../resources/detect-topology.sh");
+
+#[derive(Deserialize)]
+struct AzureCompute {
+    #[serde(default)]
+    location: String,
+    #[serde(default)]
+    zone: String,
+}
+
+// label values are at most 63 alphanumerics, '-', '_' or '.', starting and ending with an alphanumeric
+fn label_value(value: &str) -> Option<String> {
+    let value = value.trim().trim_matches('"');
+    let value: String = value.chars().map(|c| match c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+        true => c,
+        false => '-',
+    }).take(63).collect();
+    let value = value.trim_matches(|c: char| !c.is_ascii_alphanumeric());
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+// the labels for the key=value lines of the detect script
+pub fn topology_labels(output: &str) -> BTreeMap<String, String> {
+    let values: BTreeMap<_, _> = output.lines().filter_map(|l| l.split_once('=')).collect();
+    let mut region = values.get("region").map(|r| r.to_string());
+    let mut zone = values.get("zone").or(values.get("location")).map(|z| z.to_string());
+
+    match values.get("provider").copied() {
+        // gcp's zones are the region with a suffix, eg europe-west1-b
+        Some("gcp") => region = zone.as_ref().and_then(|z| z.rsplit_once('-')).map(|(r, _)| r.to_string()),
+        Some("azure") => {
+            if let Some(compute) = values.get("azure").and_then(|c| serde_json::from_str::<AzureCompute>(c).ok()) {
+                region = Some(compute.location.clone());
+                // azure numbers the zones within the region
+                zone = Some(compute.zone).filter(|z| !z.is_empty()).map(|z| format!("{}-{}", compute.location, z));
+            }
+        }
+        _ => {}
+    }
+
+    [(PROVIDER_LABEL, values.get("provider").map(|p| p.to_string())), (REGION_LABEL, region), (ZONE_LABEL, zone)]
+        .into_iter()
+        .filter_map(|(k, v)| Some((k.to_string(), label_value(&v?)?)))
+        .collect()
+}
+
+// asks the provider's metadata endpoint, or /etc/machine-info, where the node runs. nothing is found off cloud without a LOCATION
+pub async fn detect(conn: &dyn SshClient) -> BTreeMap<String, String> {
+    match conn.execute(&format!("sh -c {}", shell_quote(DETECT_SCRIPT))).await {
+        Ok(output) => topology_labels(&output),
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+// the detected labels the node doesn't already set itself
+pub fn merge(labels: &mut BTreeMap<String, String>, detected: BTreeMap<String, String>) -> bool {
+    let mut changed = false;
+    for (key, value) in detected {
+        if let Entry::Vacant(e) = labels.entry(key) {
+            e.insert(value);
+            changed = true;
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use crate::create::topology::{merge, topology_labels, PROVIDER_LABEL, REGION_LABEL, ZONE_LABEL};
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_topology_labels() {
+        assert_eq!(labels(&[(PROVIDER_LABEL, "aws"), (REGION_LABEL, "eu-north-1"), (ZONE_LABEL, "eu-north-1a")]),
+            topology_labels("provider=aws\nregion=eu-north-1\nzone=eu-north-1a\n"));
+        assert_eq!(labels(&[(PROVIDER_LABEL, "gcp"), (REGION_LABEL, "europe-west1"), (ZONE_LABEL, "europe-west1-b")]),
+            topology_labels("provider=gcp\nzone=europe-west1-b\n"));
+        assert_eq!(labels(&[(PROVIDER_LABEL, "azure"), (REGION_LABEL, "westeurope"), (ZONE_LABEL, "westeurope-2")]),
+            topology_labels(r#"provider=azure
+azure={"location":"westeurope","zone":"2","vmSize":"Standard_B2s"}"#));
+        assert_eq!(labels(&[(ZONE_LABEL, "rack-3")]), topology_labels("location=\"rack 3\"\n"));
+        assert!(topology_labels("").is_empty());
+
+        let mut own = labels(&[(ZONE_LABEL, "a")]);
+        assert!(merge(&mut own, topology_labels("provider=aws\nregion=eu-north-1\nzone=eu-north-1a")));
+        assert_eq!(Some(&"a".to_string()), own.get(ZONE_LABEL));
+        assert!(!merge(&mut own, topology_labels("provider=aws\nregion=eu-north-1")));
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::process::Command;
+use std::sync::Mutex;
+use anyhow::anyhow;
+use dialoguer::Password;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use strum_macros::Display;
+use crate::config::Node;
+use crate::errors::SkateError;
+use crate::interactive;
+use crate::util::shell_command;
+
+// the keychain service the secrets are stored under, with an account of <cluster>/<node>/<kind>
+pub const KEYCHAIN_SERVICE: &str = "skate";
+
+// where ssh key passphrases and sudo passwords come from, rather than keeping unencrypted keys on disk
+#[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CredentialSource {
+    // the macOS keychain, or the secret service (secret-tool) on linux
+    Keychain,
+    // a command printing the secret, run with SKATE_CREDENTIAL, SKATE_CLUSTER, SKATE_NODE, SKATE_HOST and SKATE_USER set
+    Helper(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Display)]
+#[strum(serialize_all = "kebab-case")]
+pub enum CredentialKind {
+    Passphrase,
+    SudoPassword,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, Hash, PartialEq, Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum BecomeMethod {
+    #[default]
+    Sudo,
+    // run as `doas sh -c`, which needs a nopass rule since doas only reads a password from a terminal
+    Doas,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Hash, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BecomePassword {
+    // asked for on the terminal, once per cluster and user
+    Prompt,
+    // the sudo-password of the cluster's credentials
+    Agent,
+}
+
+// how an ssh user that isn't root runs what needs root, for hosts that don't allow root logins
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Hash, PartialEq)]
+pub struct Become {
+    #[serde(default)]
+    pub method: BecomeMethod,
+    // the user escalates without a password when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<BecomePassword>,
+}
+
+impl Display for Become {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.password {
+            Some(BecomePassword::Prompt) => write!(f, "{} with a prompted password", self.method),
+            Some(BecomePassword::Agent) => write!(f, "{} with a password from the credentials", self.method),
+            None => write!(f, "{}, passwordless", self.method),
+        }
+    }
+}
+
+pub fn validate_become(become_: &Become, source: Option<&CredentialSource>) -> Result<(), String> {
+    match (become_.method, become_.password, source) {
+        (BecomeMethod::Doas, Some(_), _) => Err("become with doas can't be given a password, permit the user with nopass".to_string()),
+        (_, Some(BecomePassword::Agent), None) => Err("become password agent needs credentials to look the password up in".to_string()),
+        _ => Ok(()),
+    }
+}
+
+// what the node needs besides its key, missing when the source has nothing for it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodeCredentials {
+    pub passphrase: Option<String>,
+    pub sudo_password: Option<String>,
+    pub become_method: BecomeMethod,
+}
+
+pub fn keychain_account(cluster: &str, node: &str, kind: CredentialKind) -> String {
+    format!("{}/{}/{}", cluster, node, kind)
+}
+
+fn keychain_command(account: &str) -> Command {
+    let mut cmd = match cfg!(target_os = "macos") {
+        true => {
+            let mut cmd = Command::new("security");
+            cmd.args(["find-generic-password", "-s", KEYCHAIN_SERVICE, "-a", account, "-w"]);
+            cmd
+        }
+        false => {
+            let mut cmd = Command::new("secret-tool");
+            cmd.args(["lookup", "service", KEYCHAIN_SERVICE, "account", account]);
+            cmd
+        }
+    };
+    cmd.stdin(std::process::Stdio::null());
+    cmd
+}
+
+fn helper_command(helper: &str, cluster: &str, node: &Node, kind: CredentialKind) -> Command {
+    let mut cmd = shell_command(helper);
+    cmd.env("SKATE_CREDENTIAL", kind.to_string())
+        .env("SKATE_CLUSTER", cluster)
+        .env("SKATE_NODE", &node.name)
+        .env("SKATE_HOST", &node.host)
+        .env("SKATE_USER", node.user.clone().unwrap_or_default());
+    cmd
+}
+
+// only the trailing newline is dropped, passwords may well start or end with spaces
+fn secret(output: &[u8]) -> Option<String> {
+    let secret = String::from_utf8_lossy(output);
+    let secret = secret.strip_suffix('\n').unwrap_or(&secret);
+    let secret = secret.strip_suffix('\r').unwrap_or(secret);
+    (!secret.is_empty()).then(|| secret.to_string())
+}
+
+pub fn lookup(source: &CredentialSource, cluster: &str, node: &Node, kind: CredentialKind) -> Result<Option<String>, SkateError> {
+    if cfg!(windows) && matches!(source, CredentialSource::Keychain) {
+        return Err(anyhow!("the keychain isn't supported on windows, use a credential helper instead").into());
+    }
+    let (mut cmd, name) = match source {
+        CredentialSource::Keychain => (keychain_command(&keychain_account(cluster, &node.name, kind)), "keychain"),
+        CredentialSource::Helper(helper) => (helper_command(helper, cluster, node, kind), "credential helper"),
+    };
+    let output = cmd.output().map_err(|e| anyhow!(e).context(format!("failed to run the {}", name)))?;
+    match (output.status.success(), source) {
+        (true, _) => Ok(secret(&output.stdout)),
+        // the keychains exit non zero when there's no such secret
+        (false, CredentialSource::Keychain) => Ok(None),
+        (false, CredentialSource::Helper(_)) => Err(anyhow!("credential helper failed for the {} of {}: {}", kind, node.name, String::from_utf8_lossy(&output.stderr).trim()).into()),
+    }
+}
+
+// the prompted passwords by cluster and user, so the nodes sharing a user are only asked for once
+static PROMPTED: Lazy<Mutex<HashMap<String, String>>> = Lazy::new(Default::default);
+
+fn prompt_password(cluster: &str, node: &Node, method: BecomeMethod) -> Result<String, SkateError> {
+    let user = node.user.clone().unwrap_or_default();
+    let key = format!("{}/{}", cluster, user);
+    let mut prompted = PROMPTED.lock().unwrap();
+    if let Some(password) = prompted.get(&key) {
+        return Ok(password.clone());
+    }
+    if !interactive::can_prompt() {
+        return Err(anyhow!("no terminal to ask for the {} password of {} on, set become.password to agent instead", method, user).into());
+    }
+    let password = Password::new()
+        .with_prompt(format!("{} password for {} on {}", method, user, cluster))
+        .interact()
+        .map_err(|e| anyhow!(e).context("failed to read password"))?;
+    prompted.insert(key, password.clone());
+    Ok(password)
+}
+
+pub fn node_credentials(source: Option<&CredentialSource>, become_: Option<&Become>, cluster: &str, node: &Node) -> Result<NodeCredentials, SkateError> {
+    // without a key there's nothing to unlock
+    let passphrase = match (source, node.key.as_ref()) {
+        (Some(source), Some(_)) => lookup(source, cluster, node, CredentialKind::Passphrase)?,
+        _ => None,
+    };
+    let become_ = become_.cloned();
+    let sudo_password = match (become_.as_ref().map(|b| b.password), source) {
+        (Some(Some(BecomePassword::Prompt)), _) => Some(prompt_password(cluster, node, become_.as_ref().map(|b| b.method).unwrap_or_default())?),
+        // the credentials are asked for one when become isn't configured
+        (Some(Some(BecomePassword::Agent)) | None, Some(source)) => lookup(source, cluster, node, CredentialKind::SudoPassword)?,
+        _ => None,
+    };
+    Ok(NodeCredentials {
+        passphrase,
+        sudo_password,
+        become_method: become_.map(|b| b.method).unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Node;
+    use crate::credentials::{lookup, node_credentials, validate_become, Become, BecomeMethod, BecomePassword, CredentialKind, CredentialSource};
+
+    fn node() -> Node {
+        Node {
+            name: "node-1".to_string(),
+            host: "10.0.0.1".to_string(),
+            peer_host: "10.0.0.1".to_string(),
+            subnet_cidr: "20.1.0.0/16".to_string(),
+            port: None,
+            user: Some("ubuntu".to_string()),
+            key: Some("~/.ssh/id_ed25519".to_string()),
+            labels: Default::default(),
+            system_reserved: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_helper_lookup() {
+        let helper = CredentialSource::Helper(r#"[ "$SKATE_CREDENTIAL" = sudo-password ] && printf '%s\n' " $SKATE_CLUSTER/$SKATE_NODE/$SKATE_USER""#.to_string());
+        let credentials = node_credentials(Some(&helper), None, "prod", &node()).unwrap_err();
+        assert!(credentials.to_string().contains("credential helper failed for the passphrase of node-1"), "{}", credentials);
+
+        assert_eq!(Some(" prod/node-1/ubuntu".to_string()), lookup(&helper, "prod", &node(), CredentialKind::SudoPassword).unwrap());
+
+        let empty = CredentialSource::Helper("true".to_string());
+        let credentials = node_credentials(Some(&empty), None, "prod", &node()).unwrap();
+        assert_eq!((None, None), (credentials.passphrase, credentials.sudo_password));
+        assert_eq!(None, node_credentials(None, None, "prod", &node()).unwrap().sudo_password);
+    }
+
+    #[test]
+    fn test_become() {
+        let helper = CredentialSource::Helper(r#"[ "$SKATE_CREDENTIAL" = sudo-password ] && echo hunter2 || true"#.to_string());
+        let become_ = |method, password| Become { method, password };
+
+        let credentials = node_credentials(Some(&helper), Some(&become_(BecomeMethod::Sudo, Some(BecomePassword::Agent))), "prod", &node()).unwrap();
+        assert_eq!(Some("hunter2".to_string()), credentials.sudo_password);
+        // configured as passwordless, the credentials aren't asked
+        let credentials = node_credentials(Some(&helper), Some(&become_(BecomeMethod::Doas, None)), "prod", &node()).unwrap();
+        assert_eq!((None, BecomeMethod::Doas), (credentials.sudo_password, credentials.become_method));
+        // there's no terminal in tests
+        let err = node_credentials(None, Some(&become_(BecomeMethod::Sudo, Some(BecomePassword::Prompt))), "prod", &node()).unwrap_err();
+        assert!(err.to_string().contains("no terminal to ask for the sudo password of ubuntu"), "{}", err);
+
+        assert!(validate_become(&become_(BecomeMethod::Doas, Some(BecomePassword::Prompt)), None).is_err());
+        assert!(validate_become(&become_(BecomeMethod::Sudo, Some(BecomePassword::Agent)), None).is_err());
+        assert!(validate_become(&become_(BecomeMethod::Sudo, Some(BecomePassword::Agent)), Some(&helper)).is_ok());
+        assert_eq!("doas, passwordless", become_(BecomeMethod::Doas, None).to_string());
+    }
+}
@@ -1,4 +1,5 @@
 use std::error::Error;
+use std::path::Path;
 use std::str::FromStr;
 use anyhow::anyhow;
 use cron::{Schedule, TimeUnitSpec};
@@ -19,7 +20,25 @@ pub(crate) fn cron_to_systemd(cron_expr: &str, time_zone: &str) -> Result<String
         time_zone,
     );
 
-    return Ok(timer_format.trim().to_string());
+    Ok(timer_format.trim().to_string())
+}
+
+pub(crate) const ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
+
+// systemd resolves the time zone of a timer from the node's tz database, so an IANA name it doesn't have would
+// only fail once the timer is loaded. an empty zone means the node's local time
+pub(crate) fn validate_time_zone(time_zone: &str, zoneinfo_dir: &str) -> Result<(), Box<dyn Error>> {
+    if time_zone.is_empty() {
+        return Ok(());
+    }
+    let valid_name = time_zone.split('/').all(|part| !part.is_empty() && part != ".." && part.chars().all(|c| c.is_ascii_alphanumeric() || "_-+".contains(c)));
+    if !valid_name {
+        return Err(anyhow!("invalid time zone {}", time_zone).into());
+    }
+    if !Path::new(zoneinfo_dir).join(time_zone).is_file() {
+        return Err(anyhow!("unknown time zone {}, not found in {}", time_zone, zoneinfo_dir).into());
+    }
+    Ok(())
 }
 
 fn linearize_time_unit(input: &(impl TimeUnitSpec + Sized), star: &str) -> String
@@ -39,7 +58,9 @@ fn linearize_time_unit(input: &(impl TimeUnitSpec + Sized), star: &str) -> Strin
 
 #[cfg(test)]
 mod tests {
-    use crate::cron::cron_to_systemd;
+    use std::fs;
+    use crate::cron::{cron_to_systemd, validate_time_zone};
+    use crate::test_helpers::temp_dir::TempDir;
 
     #[test]
     fn test_cron_to_systemd() {
@@ -65,4 +86,21 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_time_zone() {
+        assert_eq!("*-*-* 9:30:00 Europe/Stockholm", cron_to_systemd("30 9 * * *", "Europe/Stockholm").unwrap());
+
+        let dir = TempDir::new("zoneinfo");
+        fs::create_dir_all(dir.join("Europe")).unwrap();
+        fs::write(dir.join("Europe/Stockholm"), "").unwrap();
+        let dir_str = dir.to_string_lossy();
+
+        assert!(validate_time_zone("", &dir_str).is_ok());
+        assert!(validate_time_zone("Europe/Stockholm", &dir_str).is_ok());
+        assert!(validate_time_zone("Europe/Nowhere", &dir_str).is_err());
+        assert!(validate_time_zone("Europe", &dir_str).is_err());
+        assert!(validate_time_zone("../etc/passwd", &dir_str).is_err());
+        assert!(validate_time_zone("Europe/Stockholm Mon", &dir_str).is_err());
+    }
 }
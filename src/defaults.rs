@@ -0,0 +1,175 @@
+use anyhow::anyhow;
+use k8s_openapi::api::core::v1::Container;
+use serde::{Deserialize, Serialize};
+use crate::config::Cluster;
+use crate::errors::SkateError;
+use crate::pod_spec::pod_spec_mut;
+use crate::resource::SupportedResources;
+use crate::skatelet::prune::TTL_LABEL;
+use crate::util::NamespacedName;
+
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+const PULL_POLICIES: &[&str] = &["Always", "IfNotPresent", "Never"];
+
+// what the cluster's manifests and commands get when they don't say
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Hash, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ClusterDefaults {
+    // for manifests without a namespace, and commands run without --namespace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    // where images that don't name a registry are pulled from, eg registry.example.com/mirror
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_registry: Option<String>,
+    // for containers without an imagePullPolicy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_pull_policy: Option<String>,
+    // for jobs without their own, and pods that aren't restarted once they finish
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_seconds_after_finished: Option<i32>,
+}
+
+impl Cluster {
+    // the namespace commands use without --namespace
+    pub fn default_namespace(&self) -> &str {
+        self.configured_namespace().unwrap_or(DEFAULT_NAMESPACE)
+    }
+
+    // the default namespace if the cluster sets one, for commands that otherwise look in every namespace
+    pub fn configured_namespace(&self) -> Option<&str> {
+        self.defaults.as_ref().and_then(|d| d.namespace.as_deref())
+    }
+}
+
+pub fn validate(defaults: &ClusterDefaults) -> Result<(), SkateError> {
+    let mut errors = vec![];
+    if let Some(namespace) = defaults.namespace.as_ref() {
+        if let Err(e) = NamespacedName::new("default", namespace).validate() {
+            errors.push(e);
+        }
+    }
+    if let Some(registry) = defaults.image_registry.as_ref() {
+        if registry.is_empty() || registry.contains("://") || registry.ends_with('/') {
+            errors.push(format!("imageRegistry {} must be a registry host and optional path, without a scheme or trailing /", registry));
+        }
+    }
+    if defaults.ttl_seconds_after_finished.is_some_and(|t| t < 0) {
+        errors.push("ttlSecondsAfterFinished can't be negative".to_string());
+    }
+    if let Some(policy) = defaults.image_pull_policy.as_ref() {
+        if !PULL_POLICIES.contains(&policy.as_str()) {
+            errors.push(format!("imagePullPolicy {} must be one of {}", policy, PULL_POLICIES.join(", ")));
+        }
+    }
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(anyhow!("invalid defaults:\n{}", errors.join("\n")).into()),
+    }
+}
+
+// like docker, the first part of the image names a registry if it looks like a host
+fn has_registry(image: &str) -> bool {
+    match image.split_once('/') {
+        Some((first, _)) => first.contains('.') || first.contains(':') || first == "localhost",
+        None => false,
+    }
+}
+
+pub fn qualify_image(registry: &str, image: &str) -> String {
+    match has_registry(image) {
+        true => image.to_string(),
+        false => format!("{}/{}", registry, image),
+    }
+}
+
+fn resolve_container(defaults: &ClusterDefaults, container: &mut Container) {
+    if let (Some(registry), Some(image)) = (defaults.image_registry.as_ref(), container.image.as_mut()) {
+        *image = qualify_image(registry, image);
+    }
+    if container.image_pull_policy.is_none() {
+        container.image_pull_policy = defaults.image_pull_policy.clone();
+    }
+}
+
+// fills in what the resource leaves out, before it's fixed up with its namespace
+pub fn resolve(defaults: Option<&ClusterDefaults>, mut resource: SupportedResources) -> SupportedResources {
+    let defaults = match defaults {
+        Some(defaults) => defaults,
+        None => return resource,
+    };
+
+    // cluster issuers are cluster scoped
+    if let (Some(namespace), false) = (defaults.namespace.as_ref(), matches!(resource, SupportedResources::ClusterIssuer(_))) {
+        resource.metadata_mut().namespace.get_or_insert_with(|| namespace.clone());
+    }
+
+    if let Some(ttl) = defaults.ttl_seconds_after_finished {
+        match resource {
+            SupportedResources::CronJob(ref mut c) => {
+                if let Some(job_spec) = c.spec.as_mut().and_then(|s| s.job_template.spec.as_mut()) {
+                    job_spec.ttl_seconds_after_finished.get_or_insert(ttl);
+                }
+            }
+            SupportedResources::Pod(ref mut p) if p.spec.as_ref().is_some_and(|s| s.restart_policy.as_deref().is_some_and(|r| r != "Always")) => {
+                p.metadata.labels.get_or_insert_with(Default::default).entry(TTL_LABEL.to_string()).or_insert(ttl.to_string());
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(spec) = pod_spec_mut(&mut resource) {
+        spec.containers.iter_mut().for_each(|c| resolve_container(defaults, c));
+        spec.init_containers.iter_mut().flatten().for_each(|c| resolve_container(defaults, c));
+    }
+    resource
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::{Container, Pod, PodSpec};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use crate::defaults::{qualify_image, resolve, validate, ClusterDefaults};
+    use crate::resource::SupportedResources;
+    use crate::skatelet::prune::TTL_LABEL;
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!("registry.example.com/nginx:1.27", qualify_image("registry.example.com", "nginx:1.27"));
+        assert_eq!("registry.example.com/team/app", qualify_image("registry.example.com", "team/app"));
+        assert_eq!("ghcr.io/team/app", qualify_image("registry.example.com", "ghcr.io/team/app"));
+        assert_eq!("localhost:5000/app", qualify_image("registry.example.com", "localhost:5000/app"));
+
+        let defaults = ClusterDefaults {
+            namespace: Some("shop".to_string()),
+            image_registry: Some("registry.example.com".to_string()),
+            image_pull_policy: Some("Always".to_string()),
+            ttl_seconds_after_finished: Some(600),
+        };
+        validate(&defaults).unwrap();
+        assert!(validate(&ClusterDefaults { image_pull_policy: Some("Sometimes".to_string()), ..Default::default() }).is_err());
+        assert!(validate(&ClusterDefaults { image_registry: Some("https://registry.example.com".to_string()), ..Default::default() }).is_err());
+
+        let container = |image: &str, policy: Option<&str>| Container {
+            name: "app".to_string(),
+            image: Some(image.to_string()),
+            image_pull_policy: policy.map(|p| p.to_string()),
+            ..Default::default()
+        };
+        let pod = SupportedResources::Pod(Pod {
+            metadata: ObjectMeta { name: Some("web".to_string()), ..Default::default() },
+            spec: Some(PodSpec { containers: vec![container("nginx", None), container("quay.io/app", Some("Never"))], restart_policy: Some("Never".to_string()), ..Default::default() }),
+            ..Default::default()
+        });
+
+        let pod = match resolve(Some(&defaults), pod) {
+            SupportedResources::Pod(p) => p,
+            _ => panic!("not a pod"),
+        };
+        assert_eq!(Some("shop".to_string()), pod.metadata.namespace);
+        assert_eq!(Some(&"600".to_string()), pod.metadata.labels.as_ref().unwrap().get(TTL_LABEL));
+        let containers = pod.spec.unwrap().containers;
+        assert_eq!(container("registry.example.com/nginx", Some("Always")), containers[0]);
+        assert_eq!(container("quay.io/app", Some("Never")), containers[1]);
+    }
+}
@@ -1,13 +1,21 @@
-use crate::config::Config;
+use crate::client::Client;
+use crate::config::{Access, Cluster, Config};
 use anyhow::anyhow;
 use clap::{Args, Subcommand};
-use dialoguer::Confirm;
 use itertools::Itertools;
+use crate::confirm::{confirm, Target};
+use crate::create::node::update_node_dns;
 use crate::deps::{SshManager, With};
 use crate::errors::SkateError;
-use crate::resource::ResourceType;
+use crate::lock;
+use crate::refresh::{Refresh, RefreshDeps};
+use crate::resource::{ResourceType, SupportedResources};
 use crate::skate::ConfigFileArgs;
-use crate::util::CHECKBOX_EMOJI;
+use crate::skatelet::system::podman::PodmanPodInfo;
+use crate::ssh::SshClients;
+use crate::report::{reported, Action, ActionResult};
+use crate::state::state::{NodeState, OwnerRef};
+use crate::util::{NamespacedName, CHECKBOX_EMOJI};
 
 #[derive(Debug, Args)]
 pub struct DeleteArgs {
@@ -19,7 +27,7 @@ pub struct DeleteArgs {
 
 #[derive(Debug, Subcommand)]
 pub enum DeleteCommands {
-    Node(DeleteResourceArgs),
+    Node(DeleteNodeArgs),
     Ingress(DeleteResourceArgs),
     Cronjob(DeleteResourceArgs),
     Secret(DeleteResourceArgs),
@@ -27,22 +35,38 @@ pub enum DeleteCommands {
     Daemonset(DeleteResourceArgs),
     Service(DeleteResourceArgs),
     ClusterIssuer(DeleteResourceArgs),
+    #[command(alias = "pdb")]
+    PodDisruptionBudget(DeleteResourceArgs),
     Cluster(DeleteClusterArgs),
 }
 
 #[derive(Debug, Args)]
 pub struct DeleteResourceArgs {
+    #[arg(long_help = "Name of the resource, <name>.<namespace> or <namespace>/<name> without --namespace, in the cluster's default namespace otherwise.")]
     name: String,
     #[arg(long, short, long_help = "Namespace of the resource.")]
-    namespace: String,
+    namespace: Option<String>,
     #[command(flatten)]
     config: ConfigFileArgs,
 }
 
+#[derive(Debug, Args)]
+pub struct DeleteNodeArgs {
+    name: String,
+    #[arg(long, long_help = "Print the actions taken as json.")]
+    json: bool,
+    #[command(flatten)]
+    config: ConfigFileArgs,
+    #[arg(long, short, long_help = "Answer yes to confirmation")]
+    pub yes: bool,
+}
 
 #[derive(Debug, Args)]
 pub struct DeleteClusterArgs {
     name: String,
+    #[arg(long, long_help = "Also remove skate from the nodes: the skatelet and its services, the stored objects, the network config, the ingress \
+config and the pods skate runs. Installed packages and dns settings are left. The cluster stays in the config if a node couldn't be purged.")]
+    purge_nodes: bool,
     #[command(flatten)]
     config: ConfigFileArgs,
     #[arg(long, short, long_help = "Answer yes to confirmation")]
@@ -53,11 +77,11 @@ pub struct DeleteClusterArgs {
 
 pub trait DeleteDeps: With<dyn SshManager> {}
 
-pub struct Delete<D: DeleteDeps> {
+pub struct Delete<D: DeleteDeps + RefreshDeps> {
     pub deps: D,
 }
 
-impl<D: DeleteDeps> Delete<D> {
+impl<D: DeleteDeps + RefreshDeps> Delete<D> {
     pub async fn delete(&self, args: DeleteArgs) -> Result<(), SkateError> {
         match args.command {
             DeleteCommands::Node(args) => self.delete_node(args).await?,
@@ -68,6 +92,7 @@ impl<D: DeleteDeps> Delete<D> {
             DeleteCommands::Secret(args) => self.delete_resource(ResourceType::Secret, args).await?,
             DeleteCommands::Service(args) => self.delete_resource(ResourceType::Service, args).await?,
             DeleteCommands::ClusterIssuer(args) => self.delete_resource(ResourceType::ClusterIssuer, args).await?,
+            DeleteCommands::PodDisruptionBudget(args) => self.delete_resource(ResourceType::PodDisruptionBudget, args).await?,
             DeleteCommands::Cluster(args) => self.delete_cluster(args).await?,
         }
         Ok(())
@@ -76,80 +101,212 @@ impl<D: DeleteDeps> Delete<D> {
     async fn delete_resource(&self, r_type: ResourceType, args: DeleteResourceArgs) -> Result<(), SkateError> {
         // fetch state for resource type from nodes
 
-        let config = Config::load(Some(args.config.skateconfig.clone()))?;
-        let ssh_mgr= self.deps.get();
-        let (conns, errors) = ssh_mgr.cluster_connect(config.active_cluster(args.config.context)?).await;
-        if errors.is_some() {
-            eprintln!("{}", errors.unwrap())
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let default_namespace = config.active_cluster(args.config.context.clone())?.default_namespace().to_string();
+        let name = NamespacedName::from_arg(&args.name, args.namespace.as_deref(), Some(&default_namespace))?;
+        let (client, errors) = Client::<D>::connect_with(&self.deps, config, args.config.context.clone()).await?;
+        if let Some(errors) = errors {
+            eprintln!("{}", errors)
         }
+        client.delete(r_type, &name).await
+    }
 
-        if conns.is_none() {
-            return Ok(());
-        }
-
-        let conns = conns.unwrap();
-
-        let mut results = vec!();
-        let mut errors = vec!();
+    // deletes the resource from every node over connections already made, along with the pods it leaves behind
+    pub(crate) async fn delete_connected(cluster: &Cluster, config: &Config, conns: &SshClients, r_type: ResourceType, name: &NamespacedName) -> Result<(), SkateError> {
+        let command = format!("delete {} {}", r_type, name);
+        lock::locked(cluster, conns, &command, async {
+            let mut results = vec!();
+            let mut errors = vec!();
 
-        for conn in conns.clients {
-            match conn.remove_resource(r_type.clone(), &args.name, &args.namespace).await {
-                Ok(result) => {
-                    if !result.0.is_empty() {
-                        result.0.trim().split("\n").map(|line| format!("{} - {}", conn.node_name(), line)).for_each(|line| println!("{}", line))
+            for conn in conns.clients.iter() {
+                match conn.remove_resource(r_type.clone(), &name.name, &name.namespace).await {
+                    Ok(result) => {
+                        if !result.0.is_empty() {
+                            result.0.trim().split("\n").map(|line| format!("{} - {}", conn.node_name(), line)).for_each(|line| println!("{}", line))
+                        }
+                        results.push(result)
                     }
-                    results.push(result)
+                    Err(e) => errors.push(e.to_string())
                 }
-                Err(e) => errors.push(e.to_string())
             }
-        }
 
-        match errors.is_empty() {
-            false => Err(anyhow!("\n{}", errors.join("\n")).into()),
-            true => {
-                println!("{} deleted {} {}.{}", CHECKBOX_EMOJI, r_type, args.name, args.namespace);
-                Ok(())
+            // pods are tracked by their owner label, so remove any that were left behind, eg on a node that didn't have the manifest
+            if matches!(r_type, ResourceType::Deployment | ResourceType::DaemonSet | ResourceType::CronJob) {
+                let state = Refresh::<D>::refreshed_state(&cluster.name, conns, config).await?;
+                let owner = OwnerRef::new(r_type.clone(), name);
+                let remaining = state.owned_pods(&owner);
+                errors.extend(remove_pods(conns, &remaining).await);
             }
-        }
+
+            match errors.is_empty() {
+                false => Err(anyhow!("\n{}", errors.join("\n")).into()),
+                true => {
+                    println!("{} deleted {} {}", CHECKBOX_EMOJI, r_type, name);
+                    Ok(())
+                }
+            }
+        }).await
+    }
+
+    async fn delete_node(&self, args: DeleteNodeArgs) -> Result<(), SkateError> {
+        reported("delete node", args.json, async {
+            let mut actions = vec!();
+            let result = self.remove_node(&args, &mut actions).await;
+            (actions, result)
+        }).await
     }
 
-    async fn delete_node(&self, args: DeleteResourceArgs) -> Result<(), SkateError> {
-        let mut config = Config::load(Some(args.config.skateconfig.clone()))?;
+    async fn remove_node(&self, args: &DeleteNodeArgs, actions: &mut Vec<Action>) -> Result<(), SkateError> {
+        let mut config = Config::load(args.config.skateconfig.clone())?;
 
 
-        let mut cluster = config.active_cluster(args.config.context.clone())?.clone();
+        let mut cluster = config.authorized_cluster(args.config.context.clone(), Access::Admin)?.clone();
 
         let find_result = cluster.nodes.iter().find_position(|n| n.name == args.name);
 
         match find_result {
             Some((p, _)) => {
+                let targets = self.node_targets(&config, &cluster, &args.name).await;
+                let prompt = format!("Remove node {} from cluster {}? What runs on it is left running, but no longer managed.", args.name, cluster.name);
+                if !confirm(&prompt, &targets, args.yes)? {
+                    actions.push(Action::new("update config", &args.name, ActionResult::Unchanged).with_message("not confirmed"));
+                    return Ok(());
+                }
+
                 cluster.nodes.remove(p);
                 config.replace_cluster(&cluster)?;
-                config.persist(Some(args.config.skateconfig))
+                config.persist(args.config.skateconfig.clone())?;
+                actions.push(Action::new("update config", &args.name, ActionResult::Deleted));
+
+                let (conns, _) = self.deps.get().cluster_connect(&cluster).await;
+                if let Some(conns) = conns {
+                    update_node_dns(&conns, &cluster).await;
+                }
+                Ok(())
             }
             None => {
+                actions.push(Action::new("update config", &args.name, ActionResult::NotFound));
                 Ok(())
             }
         }
     }
 
+    // the node and the pods skate runs on it, as far as they can be found
+    async fn node_targets(&self, config: &Config, cluster: &Cluster, node_name: &str) -> Vec<Target> {
+        let mut targets = vec![Target::new(node_name, "Node", node_name)];
+        let (conns, _) = self.deps.get().cluster_connect(cluster).await;
+        let state = match conns {
+            Some(conns) => Refresh::<D>::refreshed_state(&cluster.name, &conns, config).await.ok(),
+            None => None,
+        };
+        if let Some(state) = state {
+            targets.extend(state.filter_pods(&|_| true).into_iter()
+                .filter(|(_, n)| n.node_name == node_name)
+                .map(|(pod, _)| Target::new(node_name, "Pod", &pod.name)));
+        }
+        targets
+    }
+
     async fn delete_cluster(&self, args: DeleteClusterArgs) -> Result<(), SkateError> {
-        let mut config = Config::load(Some(args.config.skateconfig.clone()))?;
+        let mut config = Config::load(args.config.skateconfig.clone())?;
         let cluster = config.clusters.iter().find(|c| c.name == args.name).ok_or(anyhow!("cluster not found"))?;
+        cluster.authorize(Access::Admin)?;
 
-        if ! args.yes{
-            let confirmation = Confirm::new()
-                .with_prompt(format!("Are you sure you want to delete cluster {}?", args.name))
-                .wait_for_newline(true)
-                .interact()
-                .unwrap();
-
-            if !confirmation {
-                return Ok(())
+        let targets: Vec<_> = cluster.nodes.iter().map(|n| Target::new(&n.name, "Node", &n.host)).collect();
+        let prompt = match args.purge_nodes {
+            true => format!("Delete cluster {} from the config and remove skate from its nodes? Everything skate runs on them is removed.", args.name),
+            false => format!("Delete cluster {} from the config? Its nodes are left as they are.", args.name),
+        };
+        if !confirm(&prompt, &targets, args.yes)? {
+            return Ok(());
+        }
+        if args.purge_nodes {
+            let (conns, errors) = self.deps.get().cluster_connect(cluster).await;
+            let mut failed: Vec<_> = errors.map(|e| e.errors).unwrap_or_default().into_iter()
+                .map(|e| format!("{} - failed to connect: {}", e.node_name, e.error))
+                .collect();
+            if let Some(conns) = conns {
+                failed.extend(purge_nodes(&conns).await);
+            }
+            if !failed.is_empty() {
+                return Err(anyhow!("failed to purge nodes, cluster {} was kept in the config: {}", args.name, failed.join(", ")).into());
             }
-
         }
         config.delete_cluster(&cluster.clone())?;
-        config.persist(Some(args.config.skateconfig))
+        config.persist(args.config.skateconfig)
     }
-}
\ No newline at end of file
+}
+
+// what node creation put on a node, its services stopped first so nothing is recreated while it's removed. the pods are
+// found by the label skate gives all of them. the firewall is disabled while skatelet and its state are still there
+const PURGE_NODE_SCRIPT: &str = "sudo bash -c '\
+for unit in skatelet.service skate-routes.service skate-prune-images.timer skate-prune-pods.timer $(cd /etc/systemd/system && ls skate-cronjob-* skate-ipvsmon-* 2>/dev/null); do \
+systemctl disable --now \"$unit\" >/dev/null 2>&1; done; \
+skatelet firewall disable >/dev/null 2>&1; \
+podman pod ls -q --filter label=skate.io/namespace | xargs -r podman pod rm -f >/dev/null; \
+rm -f /etc/systemd/system/skatelet.service /etc/systemd/system/skate-*.service /etc/systemd/system/skate-*.timer; \
+rm -f /usr/share/containers/oci/hooks.d/skatelet-poststart.json /usr/share/containers/oci/hooks.d/skatelet-poststop.json; \
+rm -f /etc/containers/networks/skate.json /etc/rsyslog.d/10-skate.conf /usr/local/bin/skatelet; \
+rm -rf /var/lib/skate /etc/skate; \
+systemctl daemon-reload'";
+
+// removes skate from each node, returning the errors for those it couldn't be removed from
+pub(crate) async fn purge_nodes(conns: &SshClients) -> Vec<String> {
+    let mut errors = vec!();
+    for conn in conns.clients.iter() {
+        match conn.execute_stdout(PURGE_NODE_SCRIPT, false, true).await {
+            Ok(_) => println!("{} {} - removed skate", CHECKBOX_EMOJI, conn.node_name()),
+            Err(e) => errors.push(format!("{} - {}", conn.node_name(), e)),
+        }
+    }
+    errors
+}
+
+// removes each pod from the node it runs on, returning the errors for those that couldn't be removed
+pub(crate) async fn remove_pods(conns: &SshClients, pods: &[(PodmanPodInfo, &NodeState)]) -> Vec<String> {
+    let mut errors = vec!();
+    for (pod, node) in pods {
+        let conn = match conns.find(&node.node_name) {
+            Some(conn) => conn,
+            None => {
+                errors.push(format!("{} - no connection to node, pod {} not removed", node.node_name, pod.name));
+                continue;
+            }
+        };
+        let manifest = match serde_yaml::to_string(&SupportedResources::Pod(pod.clone().into())) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                errors.push(format!("{} - failed to serialize pod {}: {}", node.node_name, pod.name, e));
+                continue;
+            }
+        };
+        match conn.remove_resource_by_manifest(&manifest).await {
+            Ok(_) => println!("{} - removed pod {}", node.node_name, pod.name),
+            Err(e) => errors.push(format!("{} - failed to remove pod {}: {}", node.node_name, pod.name, e)),
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::delete::{purge_nodes, PURGE_NODE_SCRIPT};
+    use crate::test_helpers::ssh_mocks::MockSshManager;
+
+    #[tokio::test]
+    async fn test_purge_nodes() {
+        let purged = MockSshManager { responses: vec![("sudo bash".to_string(), "".to_string())], ..Default::default() };
+        let failing = MockSshManager::default();
+        let mut conns = purged.clients(&["node-1"]);
+        conns.clients.extend(failing.clients(&["node-2"]).clients);
+
+        let errors = purge_nodes(&conns).await;
+        assert_eq!(vec!["node-2 - not implemented"], errors);
+        assert_eq!(vec![("node-1".to_string(), PURGE_NODE_SCRIPT.to_string())], *purged.commands.lock().unwrap());
+        assert!(!PURGE_NODE_SCRIPT[14..PURGE_NODE_SCRIPT.len() - 1].contains('\''));
+        // the firewall can only be disabled while skatelet and its state are still there
+        let disable = PURGE_NODE_SCRIPT.find("skatelet firewall disable").unwrap();
+        assert!(disable < PURGE_NODE_SCRIPT.find("/usr/local/bin/skatelet").unwrap());
+        assert!(disable < PURGE_NODE_SCRIPT.find("rm -rf /var/lib/skate").unwrap());
+    }
+}
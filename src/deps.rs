@@ -3,8 +3,10 @@ use async_trait::async_trait;
 use futures::stream::FuturesUnordered;
 use itertools::{Either, Itertools};
 use crate::config::{Cluster, Node};
+use crate::credentials::node_credentials;
 use crate::exec::{RealExec, ShellExec};
 use crate::filestore::{FileStore, Store};
+use crate::progress::progress;
 use crate::ssh::{RealSsh, SshClient, SshClients, SshError, SshErrors};
 
 pub trait With<T: ?Sized> {
@@ -12,7 +14,7 @@ pub trait With<T: ?Sized> {
 }
 
 pub trait WithRef<'a, T: ?Sized> {
-    fn get_ref(&'a self) -> &'a Box<T>;
+    fn get_ref(&'a self) -> &'a T;
 }
 
 
@@ -49,9 +51,22 @@ pub struct RealSshManager {}
 impl RealSshManager {
     async fn _node_connect(cluster: &Cluster, node: &Node) -> Result<Box<dyn SshClient>, SshError> {
         let node = node.with_cluster_defaults(cluster);
-        match RealSsh::connect(&node).await {
-            Ok(c) => Ok(Box::new(c)),
-            Err(e) => Err(e)
+        progress().start(&node.name, "connecting");
+        let credentials = node_credentials(cluster.credentials.as_ref(), cluster.become_.as_ref(), &cluster.name, &node)
+            .map_err(|e| SshError { node_name: node.name.clone(), error: e.to_string(), elapsed: None });
+        let result = match credentials {
+            Ok(credentials) => RealSsh::connect_with_credentials(&node, credentials).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(c) => {
+                progress().finish(&node.name);
+                Ok(Box::new(c))
+            }
+            Err(e) => {
+                progress().fail(&node.name, &e.error);
+                Err(e)
+            }
         }
     }
 }
@@ -1,13 +1,16 @@
 use anyhow::anyhow;
 use clap::{Args, Subcommand};
 use k8s_openapi::api::core::v1::Node as K8sNode;
-use crate::config::Config;
+use serde::Serialize;
+use crate::config::{Access, Config};
 use crate::deps::{SshManager, With};
 use crate::errors::SkateError;
 use crate::refresh::{Refresh};
 use crate::skate::ConfigFileArgs;
 use crate::refresh;
+use crate::skatelet::system::podman::PodmanPodInfo;
 use crate::state::state::{ClusterState, NodeState};
+use crate::util::NamespacedName;
 
 #[derive(Debug, Clone, Args)]
 pub struct DescribeArgs {
@@ -43,14 +46,15 @@ pub enum DescribeCommands {
 
 
 pub trait Describer<T> {
-    fn find(&self, filters: &DescribeObjectArgs, state: &ClusterState) -> Option<T>;
+    // names without a namespace are looked for in the default namespace
+    fn find(&self, filters: &DescribeObjectArgs, state: &ClusterState, default_namespace: &str) -> Option<T>;
     fn print(&self, item: T);
 }
 
 struct NodeDescriber {}
 
 impl Describer<NodeState> for NodeDescriber {
-    fn find(&self, filters: &DescribeObjectArgs, state: &ClusterState) -> Option<NodeState> {
+    fn find(&self, filters: &DescribeObjectArgs, state: &ClusterState, _default_namespace: &str) -> Option<NodeState> {
         let id = filters.id.as_ref().and_then(|cmd| match cmd {
             IdCommand::Id(ids) => ids.first().map(|id| (*id).clone()),
         });
@@ -70,6 +74,77 @@ impl Describer<NodeState> for NodeDescriber {
     }
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PodDescription {
+    name: String,
+    namespace: String,
+    node: String,
+    phase: String,
+    containers: Vec<ContainerDescription>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ContainerDescription {
+    name: String,
+    status: String,
+    ready: bool,
+    restarts: usize,
+    // the limits podman enforces
+    limits: ContainerLimits,
+}
+
+#[derive(Serialize)]
+struct ContainerLimits {
+    cpu: String,
+    memory: String,
+}
+
+struct PodDescriber {}
+
+impl Describer<(PodmanPodInfo, String)> for PodDescriber {
+    fn find(&self, filters: &DescribeObjectArgs, state: &ClusterState, default_namespace: &str) -> Option<(PodmanPodInfo, String)> {
+        let id = filters.id.as_ref().and_then(|cmd| match cmd {
+            IdCommand::Id(ids) => ids.first().cloned(),
+        })?;
+        let name = match NamespacedName::from_arg(&id, filters.namespace.as_deref(), Some(default_namespace)) {
+            Ok(name) => name,
+            Err(e) => {
+                eprintln!("{}", e);
+                return None;
+            }
+        };
+
+        state.nodes.iter().find_map(|n| {
+            let pods = n.host_info.as_ref()?.system_info.as_ref()?.pods.as_ref()?;
+            pods.iter()
+                .find(|p| (p.name() == name.name || p.name == name.name || p.id.starts_with(&id)) && p.namespace() == name.namespace)
+                .map(|p| (p.clone(), n.node_name.clone()))
+        })
+    }
+
+    fn print(&self, (pod, node_name): (PodmanPodInfo, String)) {
+        let description = PodDescription {
+            name: pod.name(),
+            namespace: pod.namespace(),
+            node: node_name,
+            phase: pod.phase().to_string(),
+            containers: pod.app_containers().into_iter().map(|c| ContainerDescription {
+                name: c.names.clone(),
+                status: c.status.clone(),
+                ready: c.is_ready(),
+                restarts: c.restart_count.unwrap_or_default(),
+                limits: ContainerLimits {
+                    cpu: c.cpu_limit.map(|c| c.to_string()).unwrap_or("unlimited".to_string()),
+                    memory: c.memory_limit.map(|m| m.to_string()).unwrap_or("unlimited".to_string()),
+                },
+            }).collect(),
+        };
+        println!("{}", serde_yaml::to_string(&description).unwrap());
+    }
+}
+
 pub trait DescribeDeps: With<dyn SshManager> {}
 
 pub struct Describe<D: DescribeDeps> {
@@ -80,7 +155,7 @@ impl<D: DescribeDeps + refresh::RefreshDeps> Describe<D> {
     pub async fn describe(&self,args: DescribeArgs) -> Result<(), SkateError> {
         let global_args = args.clone();
         match args.commands {
-            DescribeCommands::Pod(_p_args) => Ok(()),
+            DescribeCommands::Pod(p_args) => self.describe_object(global_args, p_args, &PodDescriber {}).await,
             DescribeCommands::Deployment(_d_args) => Ok(()),
             DescribeCommands::Node(n_args) => self.describe_node(global_args, n_args).await
         }
@@ -91,22 +166,23 @@ impl<D: DescribeDeps + refresh::RefreshDeps> Describe<D> {
     }
 
     async fn describe_object<T>(&self, _global_args: DescribeArgs, args: DescribeObjectArgs, inspector: &dyn Describer<T>) -> Result<(), SkateError> {
-        let config = Config::load(Some(args.config.skateconfig.clone()))?;
-        let cluster = config.active_cluster(args.config.context.clone())?;
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), Access::ReadOnly)?;
         let mgr= self.deps.get();
         let (conns, errs) = mgr.cluster_connect(cluster).await;
-        if errs.is_some() && conns.as_ref().map(|c| c.clients.len()).unwrap_or(0) == 0 {
-            return Err(anyhow!("failed to connect to any hosts: {}", errs.unwrap()).into());
-        }
+        let conns = match conns {
+            Some(conns) if !conns.clients.is_empty() => conns,
+            _ => return Err(anyhow!("failed to connect to any hosts: {}", errs.map(|e| e.to_string()).unwrap_or_default()).into()),
+        };
 
-        let state = Refresh::<D>::refreshed_state(&cluster.name, &conns.unwrap(), &config).await?;
+        let state = Refresh::<D>::refreshed_state(&cluster.name, &conns, &config).await?;
 
-        let node = inspector.find(&args, &state);
+        let node = inspector.find(&args, &state, cluster.default_namespace());
 
         if let Some(node) = node { inspector.print(node) };
 
-        if errs.is_some() {
-            return Err(anyhow!("failed to connect to some hosts: {}", errs.as_ref().unwrap()).into());
+        if let Some(errs) = errs {
+            return Err(anyhow!("failed to connect to some hosts: {}", errs).into());
         }
         Ok(())
     }
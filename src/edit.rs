@@ -0,0 +1,229 @@
+use std::fs;
+use std::io::{ErrorKind, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::anyhow;
+use clap::Args;
+use serde_yaml::Value;
+use crate::apply::{with_resource_version, Apply, ApplyDeps};
+use crate::config::{Access, Config};
+use crate::errors::SkateError;
+use crate::refresh::Refresh;
+use crate::resource::SupportedResources;
+use crate::rollout::ResourceArg;
+use crate::scheduler::DefaultScheduler;
+use crate::skate::ConfigFileArgs;
+use crate::util::{shell_command, NamespacedName};
+
+#[derive(Debug, Args)]
+#[command(arg_required_else_help(true))]
+pub struct EditArgs {
+    #[command(flatten)]
+    config: ConfigFileArgs,
+    #[arg(name = "TYPE | TYPE/NAME")]
+    resource: ResourceArg,
+    #[arg(name = "NAME")]
+    name: Option<String>,
+    #[arg(long, short, long_help = "Namespace of the resource, the cluster's default namespace if not given.")]
+    namespace: Option<String>,
+    #[arg(long, long_help = "Will not affect the cluster if set to true")]
+    dry_run: bool,
+    #[arg(long, long_help = "Apply the edit even if it violates the cluster's policies.")]
+    override_policy: bool,
+}
+
+pub trait EditDeps: ApplyDeps {}
+
+pub struct Edit<D: EditDeps> {
+    pub deps: D,
+}
+
+impl<D: EditDeps> Edit<D> {
+    pub async fn edit(&self, args: EditArgs) -> Result<(), SkateError> {
+        let (resource_type, name) = args.resource.parse()?;
+        let name = name.or(args.name.clone()).ok_or("resource name is required".to_string())?;
+
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), Access::Deploy)?;
+        let ns_name = NamespacedName::from_arg(&name, args.namespace.as_deref(), Some(cluster.default_namespace()))?;
+        cluster.authorize_namespaces(&[&ns_name.namespace])?;
+
+        let mgr = self.deps.get();
+        let (conns, errors) = mgr.cluster_connect(cluster).await;
+        if let Some(errors) = errors {
+            eprintln!("{}", errors)
+        }
+        let conns = conns.ok_or("failed to get cluster connections".to_string())?;
+
+        let state = Refresh::<D>::refreshed_state(&cluster.name, &conns, &config).await?;
+
+        let (manifest, hash) = state.catalogue(None, std::slice::from_ref(&resource_type)).into_iter()
+            .find(|item| item.object.name == ns_name)
+            .and_then(|item| item.object.manifest.clone().map(|m| (m, item.object.manifest_hash.clone())))
+            .ok_or(anyhow!("{} {} not found", resource_type, ns_name))?;
+
+        let original = serde_yaml::to_string(&manifest)?;
+
+        let edited = match Self::edit_until_valid(&format!("{}-{}", resource_type, ns_name), &original, &ns_name)? {
+            Some(edited) => edited,
+            None => {
+                println!("Edit cancelled, no changes made.");
+                return Ok(());
+            }
+        };
+
+        // someone may have changed the object while it was open in the editor
+        let edited = with_resource_version(edited, &hash)?;
+        // the stored manifest has already been through fixup, the rest is as apply does it
+        Apply::<D>::apply_prepared(cluster, &config, &conns, vec![edited], args.dry_run, args.override_policy, &DefaultScheduler::default(), "edit").await?;
+        Ok(())
+    }
+
+    // opens the manifest in the user's editor, reopening it with the error prepended until it parses or is left unchanged
+    fn edit_until_valid(file_name: &str, original: &str, ns_name: &NamespacedName) -> Result<Option<SupportedResources>, SkateError> {
+        let path = create_temp_file(&std::env::temp_dir(), file_name)?;
+        let mut contents = original.to_string();
+
+        loop {
+            // the file is ours, opening it without create means a replaced path isn't followed somewhere else
+            fs::OpenOptions::new().write(true).truncate(true).open(&path)?.write_all(contents.as_bytes())?;
+            Self::open_editor(&path.to_string_lossy())?;
+            let edited = fs::read_to_string(&path)?;
+
+            let stripped = strip_comments(&edited);
+            if stripped.trim().is_empty() || stripped == strip_comments(&contents) {
+                let _ = fs::remove_file(&path);
+                return Ok(None);
+            }
+
+            let parsed = serde_yaml::from_str::<Value>(&stripped).map_err(SkateError::from).and_then(|v| validate_modified(&v, ns_name));
+            match parsed {
+                Ok(resource) => {
+                    let _ = fs::remove_file(&path);
+                    return Ok(Some(resource));
+                }
+                Err(e) => {
+                    contents = format!("# Please edit the object below. The previous edit failed:\n# {}\n#\n{}", e.to_string().replace('\n', "\n# "), stripped);
+                }
+            }
+        }
+    }
+
+    fn open_editor(path: &str) -> Result<(), SkateError> {
+        let default_editor = match cfg!(windows) {
+            true => "notepad",
+            false => "vi",
+        };
+        let editor = std::env::var("VISUAL").or_else(|_| std::env::var("EDITOR")).unwrap_or(default_editor.to_string());
+        // run via the shell so that editors with arguments, eg `code --wait`, work
+        let mut cmd = match cfg!(windows) {
+            // cmd has no positional arguments, the path is our own temp file so quoting it is enough
+            true => shell_command(&format!("{} \"{}\"", editor, path)),
+            false => {
+                let mut cmd = shell_command(&format!("{} \"$1\"", editor));
+                cmd.args(["sh", path]);
+                cmd
+            }
+        };
+        let status = cmd.status()
+            .map_err(|e| anyhow!(e).context(format!("failed to launch editor {}", editor)))?;
+        if !status.success() {
+            return Err(anyhow!("editor {} exited with {}", editor, status).into());
+        }
+        Ok(())
+    }
+}
+
+// validate_modified checks that a modified stored manifest is still a supported resource with the same identity
+pub(crate) fn validate_modified(value: &Value, ns_name: &NamespacedName) -> Result<SupportedResources, SkateError> {
+    let resource = SupportedResources::try_from(value)?;
+    let labels = &value["metadata"]["labels"];
+    if labels["skate.io/name"].as_str().is_none() || labels["skate.io/namespace"].as_str().is_none() {
+        return Err(anyhow!("the skate.io/name and skate.io/namespace labels must not be removed").into());
+    }
+    if &resource.name() != ns_name {
+        return Err(anyhow!("name and namespace cannot be changed, expected {}", ns_name).into());
+    }
+    Ok(resource)
+}
+
+// create_temp_file creates an empty file only the user can read, failing rather than following anything already at the path
+fn create_temp_file(dir: &Path, file_name: &str) -> Result<PathBuf, SkateError> {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or_default();
+    for attempt in 0..100 {
+        let path = dir.join(format!("skate-edit-{}-{}-{}.yaml", file_name, std::process::id(), nanos.wrapping_add(attempt)));
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        match options.open(&path) {
+            Ok(_) => return Ok(path),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(anyhow!(e).context(format!("failed to create {}", path.display())).into()),
+        }
+    }
+    Err(anyhow!("failed to create a temporary file in {}", dir.display()).into())
+}
+
+fn strip_comments(manifest: &str) -> String {
+    manifest.lines().filter(|l| !l.trim_start().starts_with('#')).collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use serde_yaml::Value;
+    use crate::edit::{create_temp_file, strip_comments, validate_modified};
+    use crate::resource::SupportedResources;
+    use crate::test_helpers::temp_dir::TempDir;
+    use crate::util::NamespacedName;
+
+    #[test]
+    fn test_create_temp_file() {
+        let dir = TempDir::new("edit");
+        let path = create_temp_file(&dir, "pod-web.shop").unwrap();
+        let other = create_temp_file(&dir, "pod-web.shop").unwrap();
+        assert_ne!(path, other);
+        assert_eq!("", fs::read_to_string(&path).unwrap());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(0o600, fs::metadata(&path).unwrap().permissions().mode() & 0o777);
+        }
+    }
+
+    #[test]
+    fn test_strip_comments() {
+        let manifest = "# Please edit the object below. The previous edit failed:\n# bad\n#\napiVersion: v1\nkind: Pod\n  # indented\nmetadata:\n  name: web # trailing\n";
+        assert_eq!("apiVersion: v1\nkind: Pod\nmetadata:\n  name: web # trailing", strip_comments(manifest));
+    }
+
+    #[test]
+    fn test_validate_modified() {
+        let ns_name = NamespacedName::new("web", "shop");
+        let value: Value = serde_yaml::from_str("apiVersion: v1
+kind: Pod
+metadata:
+  name: web
+  namespace: shop
+  labels:
+    skate.io/name: web
+    skate.io/namespace: shop
+spec:
+  containers: []
+").unwrap();
+        assert!(matches!(validate_modified(&value, &ns_name), Ok(SupportedResources::Pod(_))));
+
+        let mut renamed = value.clone();
+        renamed["metadata"]["labels"]["skate.io/name"] = "api".into();
+        assert!(validate_modified(&renamed, &ns_name).unwrap_err().to_string().contains("name and namespace cannot be changed"));
+
+        let mut unlabelled = value.clone();
+        unlabelled["metadata"]["labels"].as_mapping_mut().unwrap().remove("skate.io/namespace");
+        assert!(validate_modified(&unlabelled, &ns_name).unwrap_err().to_string().contains("must not be removed"));
+
+        assert!(validate_modified(&serde_yaml::from_str("kind: Unknown").unwrap(), &ns_name).is_err());
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::BTreeMap;
+use anyhow::anyhow;
+use chrono::{DateTime, Local};
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use strum_macros::Display;
+use crate::config::{Access, Config};
+use crate::deps::{SshManager, With};
+use crate::errors::SkateError;
+use crate::refresh::{Refresh, RefreshDeps};
+use crate::resource::ResourceType;
+use crate::skate::ConfigFileArgs;
+use crate::skatelet::system::podman::{PodmanPodInfo, PodmanPodStatus};
+use crate::skatelet::system::ContainerStage;
+use crate::state::state::{ClusterState, NodeState, NodeStatus};
+use crate::sync::parse_interval;
+
+// the applied resources whose changes are reported, secrets are left out
+const WATCHED_TYPES: &[ResourceType] = &[ResourceType::Deployment, ResourceType::DaemonSet, ResourceType::CronJob, ResourceType::Ingress, ResourceType::Service];
+
+#[derive(Debug, Args)]
+pub struct EventsArgs {
+    #[command(flatten)]
+    config: ConfigFileArgs,
+    #[arg(long, short, long_help = "Filter by resource namespace")]
+    namespace: Option<String>,
+    #[arg(long, short, long_help = "Keep checking the cluster, printing events as they happen. Otherwise prints what changed since the last refresh.")]
+    watch: bool,
+    #[arg(long, default_value = "10s", long_help = "How often the cluster is checked with --watch, eg 10s or 1m.")]
+    interval: String,
+    #[arg(long, short, value_enum, default_value_t = EventsOutput::Text, long_help = "Output format, json prints one event per line.")]
+    output: EventsOutput,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum EventsOutput {
+    Text,
+    Json,
+}
+
+pub trait EventsDeps: With<dyn SshManager> {}
+
+pub struct Events<D: EventsDeps> {
+    pub deps: D,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Display)]
+pub enum EventType {
+    Normal,
+    Warning,
+}
+
+// a change seen between two states of the cluster
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Event {
+    pub time: DateTime<Local>,
+    #[serde(rename = "type")]
+    pub type_: EventType,
+    pub reason: String,
+    pub cluster: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    // kind/name of what the event is about
+    pub object: String,
+    pub message: String,
+}
+
+struct EventBuilder<'a> {
+    time: DateTime<Local>,
+    cluster: &'a str,
+    events: Vec<Event>,
+}
+
+impl EventBuilder<'_> {
+    fn push(&mut self, type_: EventType, reason: &str, node: Option<&str>, namespace: Option<&str>, object: String, message: String) {
+        self.events.push(Event {
+            time: self.time,
+            type_,
+            reason: reason.to_string(),
+            cluster: self.cluster.to_string(),
+            node: node.map(|n| n.to_string()),
+            namespace: namespace.filter(|ns| !ns.is_empty()).map(|ns| ns.to_string()),
+            object,
+            message,
+        });
+    }
+}
+
+fn pods_by_node(state: &ClusterState) -> BTreeMap<(String, String), PodmanPodInfo> {
+    state.filter_pods(&|_| true).into_iter()
+        .map(|(pod, node)| ((node.node_name.clone(), pod.name.clone()), pod))
+        .collect()
+}
+
+fn pod_events(events: &mut EventBuilder, node: &str, previous: Option<&PodmanPodInfo>, current: Option<&PodmanPodInfo>) {
+    let pod = match current.or(previous) {
+        Some(pod) => pod,
+        None => return,
+    };
+    let object = format!("pod/{}", pod.name);
+    let namespace = pod.namespace();
+    let (previous, current) = match (previous, current) {
+        (None, Some(_)) => {
+            events.push(EventType::Normal, "Created", Some(node), Some(&namespace), object, format!("pod created on {}", node));
+            return;
+        }
+        (Some(_), None) => {
+            events.push(EventType::Normal, "Deleted", Some(node), Some(&namespace), object, format!("pod removed from {}", node));
+            return;
+        }
+        (Some(previous), Some(current)) => (previous, current),
+        (None, None) => return,
+    };
+
+    if previous.status != current.status {
+        let (type_, reason) = match current.status {
+            PodmanPodStatus::Running => (EventType::Normal, "Started"),
+            PodmanPodStatus::Exited | PodmanPodStatus::Stopped => (EventType::Normal, "Completed"),
+            PodmanPodStatus::Created => (EventType::Normal, "Pending"),
+            PodmanPodStatus::Degraded => (EventType::Warning, "Unhealthy"),
+            PodmanPodStatus::Dead | PodmanPodStatus::Error => (EventType::Warning, "Failed"),
+        };
+        events.push(type_, reason, Some(node), Some(&namespace), object.clone(), format!("pod went from {} to {}", previous.status, current.status));
+    }
+
+    for container in current.app_containers() {
+        let before = previous.app_containers().into_iter().find(|c| c.names == container.names);
+        let restarts = container.restart_count.unwrap_or_default();
+        if restarts > before.and_then(|c| c.restart_count).unwrap_or_default() {
+            events.push(EventType::Warning, "BackOff", Some(node), Some(&namespace), object.clone(),
+                format!("container {} restarted, {} restarts", container.names, restarts));
+        }
+        if container.oom_killed == Some(true) && before.and_then(|c| c.oom_killed) != Some(true) {
+            events.push(EventType::Warning, "OOMKilled", Some(node), Some(&namespace), object.clone(),
+                format!("container {} was killed for running out of memory", container.names));
+        }
+    }
+}
+
+// the starts and exits recorded by the node's oci hooks since the previous state, at the time they happened
+fn container_events(events: &mut EventBuilder, previous: Option<&NodeState>, current: &NodeState) {
+    let journal = |n: &NodeState| n.host_info.as_ref().and_then(|h| h.system_info.as_ref()).and_then(|i| i.container_events.clone());
+    // a node without an earlier journal would report all of it
+    let since = match previous.and_then(journal) {
+        Some(previous) => previous.last().map(|e| e.time),
+        None => return,
+    };
+    let pods = current.filter_pods(&|_| true);
+    for event in journal(current).unwrap_or_default().into_iter().filter(|e| since.is_none_or(|since| e.time > since)) {
+        let found = pods.iter().find_map(|p| p.app_containers().into_iter()
+            .find(|c| c.id.starts_with(&event.container_id) || event.container_id.starts_with(&c.id))
+            .map(|c| (p, c)));
+        // infra containers, and containers removed since
+        let (pod, container) = match found {
+            Some(found) => found,
+            None => continue,
+        };
+        let (type_, reason, message) = match (event.stage, event.exit_code.unwrap_or_default()) {
+            (ContainerStage::Started, _) => (EventType::Normal, "ContainerStarted", format!("container {} started", container.names)),
+            (ContainerStage::Stopped, 0) => (EventType::Normal, "ContainerExited", format!("container {} exited", container.names)),
+            (ContainerStage::Stopped, code) => (EventType::Warning, "ContainerExited", format!("container {} exited with code {}", container.names, code)),
+        };
+        events.push(type_, reason, Some(&current.node_name), Some(&pod.namespace()), format!("pod/{}", pod.name), message);
+        if let Some(pushed) = events.events.last_mut() {
+            pushed.time = event.time;
+        }
+    }
+}
+
+// the events that explain how the cluster got from the previous state to the current one
+pub fn diff_events(previous: &ClusterState, current: &ClusterState, now: DateTime<Local>) -> Vec<Event> {
+    let mut events = EventBuilder { time: now, cluster: &current.cluster_name, events: vec![] };
+
+    for node in current.nodes.iter() {
+        let before = previous.nodes.iter().find(|n| n.node_name == node.node_name).map(|n| n.status.clone());
+        match (before, &node.status) {
+            (Some(before), status) if before == *status => {}
+            (_, NodeStatus::Healthy) => events.push(EventType::Normal, "NodeReady", Some(&node.node_name), None,
+                format!("node/{}", node.node_name), "node is healthy".to_string()),
+            (_, status) => events.push(EventType::Warning, "NodeNotReady", Some(&node.node_name), None,
+                format!("node/{}", node.node_name), node.message.clone().unwrap_or(format!("node is {}", status))),
+        }
+        let previous_changes = previous.nodes.iter().find(|n| n.node_name == node.node_name).map(|n| n.host_changes.as_slice()).unwrap_or_default();
+        for change in node.host_changes.iter().filter(|c| !previous_changes.contains(c)) {
+            events.push(EventType::Warning, "HostChanged", Some(&node.node_name), None,
+                format!("node/{}", node.node_name), format!("{} since it was last seen", change));
+        }
+        container_events(&mut events, previous.nodes.iter().find(|n| n.node_name == node.node_name), node);
+    }
+
+    let (previous_pods, current_pods) = (pods_by_node(previous), pods_by_node(current));
+    for key in previous_pods.keys().chain(current_pods.keys().filter(|k| !previous_pods.contains_key(*k))) {
+        pod_events(&mut events, &key.0, previous_pods.get(key), current_pods.get(key));
+    }
+
+    // by namespace and kind/name, with the manifest hash
+    let objects = |state: &ClusterState| -> BTreeMap<(String, String), String> {
+        state.catalogue(None, WATCHED_TYPES).into_iter()
+            .map(|i| ((i.object.name.namespace.clone(), format!("{}/{}", i.object.resource_type.to_string().to_lowercase(), i.object.name.name)), i.object.manifest_hash.clone()))
+            .collect()
+    };
+    let (previous_objects, current_objects) = (objects(previous), objects(current));
+    for ((namespace, object), hash) in current_objects.iter() {
+        match previous_objects.get(&(namespace.clone(), object.clone())) {
+            None => events.push(EventType::Normal, "Created", None, Some(namespace), object.clone(), "applied".to_string()),
+            Some(previous_hash) if previous_hash != hash => events.push(EventType::Normal, "Updated", None, Some(namespace), object.clone(), "applied with changes".to_string()),
+            _ => {}
+        }
+    }
+    for ((namespace, object), _) in previous_objects.iter().filter(|(k, _)| !current_objects.contains_key(*k)) {
+        events.push(EventType::Normal, "Deleted", None, Some(namespace), object.clone(), "deleted".to_string());
+    }
+
+    events.events
+}
+
+fn print_event(event: &Event, output: EventsOutput) -> Result<(), SkateError> {
+    match output {
+        EventsOutput::Json => println!("{}", serde_json::to_string(event).map_err(|e| anyhow!(e).context("failed to serialize event"))?),
+        EventsOutput::Text => println!("{}  {:<8} {:<12} {:<40} {}", event.time.format("%Y-%m-%d %H:%M:%S"), event.type_, event.reason, event.object, event.message),
+    }
+    Ok(())
+}
+
+impl<D: EventsDeps + RefreshDeps> Events<D> {
+    pub async fn events(&self, args: EventsArgs) -> Result<(), SkateError> {
+        let interval = parse_interval(&args.interval)?;
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), Access::ReadOnly)?;
+
+        let mut previous = ClusterState::load(&cluster.name)?;
+        loop {
+            match self.check(&config, &cluster.name).await {
+                Ok(current) => {
+                    // without an earlier state everything would look new
+                    let events = match previous.nodes.is_empty() {
+                        true => vec![],
+                        false => diff_events(&previous, &current, Local::now()),
+                    };
+                    for event in events.iter().filter(|e| args.namespace.is_none() || e.namespace == args.namespace) {
+                        print_event(event, args.output)?;
+                    }
+                    previous = current;
+                }
+                Err(e) if args.watch => eprintln!("failed to check the cluster: {}", e),
+                Err(e) => return Err(e),
+            }
+            if !args.watch {
+                return Ok(());
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    async fn check(&self, config: &Config, cluster_name: &str) -> Result<ClusterState, SkateError> {
+        let cluster = config.active_cluster(Some(cluster_name.to_string()))?;
+        let (conns, errors) = self.deps.get().cluster_connect(cluster).await;
+        if let Some(errors) = errors {
+            eprintln!("{}", errors)
+        }
+        let conns = conns.ok_or(anyhow!("failed to connect to any node"))?;
+        Refresh::<D>::refreshed_state(cluster_name, &conns, config).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Local, TimeZone};
+    use crate::events::{diff_events, EventType};
+    use crate::skatelet::system::podman::{PodmanContainerInfo, PodmanPodStatus};
+    use crate::skatelet::system::{ContainerEvent, ContainerStage};
+    use crate::state::state::NodeStatus;
+    use crate::test_helpers::objects::{cluster_state, node_state, pod, WithPod};
+
+    #[test]
+    fn test_diff_events() {
+        let previous = cluster_state(vec![node_state("node-1").with_pod(&pod("web", "shop", &[])).with_pod(&pod("old", "shop", &[])), node_state("node-2")]);
+
+        let mut node_1 = node_state("node-1").with_pod(&pod("web", "shop", &[])).with_pod(&pod("new", "shop", &[]));
+        node_1.host_info.as_mut().unwrap().system_info.as_mut().unwrap().pods.as_mut().unwrap()[0].status = PodmanPodStatus::Dead;
+        let mut node_2 = node_state("node-2");
+        node_2.status = NodeStatus::Unhealthy;
+        let current = cluster_state(vec![node_1, node_2]);
+
+        let events: Vec<_> = diff_events(&previous, &current, Local::now()).into_iter().map(|e| (e.type_, e.reason, e.object)).collect();
+        assert_eq!(vec![
+            (EventType::Warning, "NodeNotReady".to_string(), "node/node-2".to_string()),
+            (EventType::Normal, "Deleted".to_string(), "pod/old.shop".to_string()),
+            (EventType::Warning, "Failed".to_string(), "pod/web.shop".to_string()),
+            (EventType::Normal, "Created".to_string(), "pod/new.shop".to_string()),
+        ], events);
+
+        assert!(diff_events(&current, &current, Local::now()).is_empty());
+    }
+
+    #[test]
+    fn test_container_events() {
+        let pod = pod("web", "shop", &[]);
+        let container = |id: &str, names: &str, exit_code| PodmanContainerInfo {
+            id: id.to_string(),
+            names: names.to_string(),
+            status: "exited".to_string(),
+            restart_count: None,
+            exit_code,
+            health: None,
+            cpu_limit: None,
+            memory_limit: None,
+            oom_killed: None,
+        };
+        let event = |secs, id: &str, stage, exit_code| ContainerEvent { time: Local.timestamp_opt(secs, 0).unwrap(), container_id: id.to_string(), stage, exit_code };
+        let node = |journal: Option<Vec<ContainerEvent>>| {
+            let mut node = node_state("node-1").with_pod(&pod);
+            let info = node.host_info.as_mut().unwrap().system_info.as_mut().unwrap();
+            info.pods.as_mut().unwrap()[0].containers = Some(vec![container("aaaa1111", "web.shop-infra", None), container("bbbb2222", "web.shop-app", Some(137))]);
+            info.container_events = journal;
+            node
+        };
+        let state = |node| cluster_state(vec![node]);
+
+        let previous = state(node(Some(vec![event(100, "bbbb2222", ContainerStage::Started, None)])));
+        // the container has exited again since, with another code
+        let current = state(node(Some(vec![
+            event(100, "bbbb2222", ContainerStage::Started, None),
+            event(110, "aaaa1111", ContainerStage::Stopped, Some(0)),
+            event(120, "bbbb2222", ContainerStage::Stopped, Some(1)),
+        ])));
+
+        let events = diff_events(&previous, &current, Local::now());
+        assert_eq!(vec![(EventType::Warning, "ContainerExited".to_string(), "container web.shop-app exited with code 1".to_string())],
+            events.iter().map(|e| (e.type_, e.reason.clone(), e.message.clone())).collect::<Vec<_>>());
+        assert_eq!(Local.timestamp_opt(120, 0).unwrap(), events[0].time);
+
+        // an older skatelet didn't keep a journal
+        assert!(diff_events(&state(node(None)), &current, Local::now()).is_empty());
+    }
+}
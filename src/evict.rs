@@ -0,0 +1,203 @@
+use anyhow::anyhow;
+use clap::Args;
+use k8s_openapi::api::core::v1::{Pod, PodSpec};
+use crate::apply::check_policy;
+use crate::config::{Access, Config};
+use crate::confirm::{confirm, Target};
+use crate::deps::{SshManager, With};
+use crate::errors::SkateError;
+use crate::lock;
+use crate::pod_spec::pod_spec_mut;
+use crate::refresh::{Refresh, RefreshDeps};
+use crate::resource::{ResourceType, SupportedResources};
+use crate::scheduler::{DefaultScheduler, Scheduler};
+use crate::skate::ConfigFileArgs;
+use crate::skatelet::system::podman::PodmanPodInfo;
+use crate::ssh::SshClient;
+use crate::state::state::{ClusterState, OwnerRef};
+use crate::util::{shell_quote, split_resource_identifier, NamespacedName, CHECKBOX_EMOJI};
+
+// as the skatelet waits when stopping a pod
+const DEFAULT_GRACE_PERIOD: usize = 10;
+
+#[derive(Debug, Args)]
+#[command(arg_required_else_help(true))]
+pub struct EvictArgs {
+    #[command(flatten)]
+    config: ConfigFileArgs,
+    #[arg(long, short, long_help = "The namespace of the pod")]
+    namespace: Option<String>,
+    #[arg(long, long_help = "Seconds to wait for the pod to stop before it's killed, defaults to its terminationGracePeriodSeconds.")]
+    grace_period: Option<usize>,
+    #[arg(long, long_help = "Don't replace a deployment's pod, leaving the deployment a replica short until the next apply.")]
+    no_reschedule: bool,
+    #[arg(long, long_help = "Will not affect the cluster if set to true")]
+    dry_run: bool,
+    #[arg(long, long_help = "Reschedule the pod even if its deployment violates the cluster's policies.")]
+    override_policy: bool,
+    #[arg(long, short, long_help = "Don't ask for confirmation")]
+    yes: bool,
+    #[arg(name = "POD | pod/NAME")]
+    pod: String,
+}
+
+pub trait EvictDeps: With<dyn SshManager> {}
+
+pub struct Evict<D: EvictDeps> {
+    pub deps: D,
+}
+
+// the pod by its name, or the one of its podman name <name>.<namespace>
+fn find_pod(state: &ClusterState, name: &NamespacedName) -> Result<(PodmanPodInfo, String), SkateError> {
+    let podman_name = format!("{}.{}", name.name, name.namespace);
+    state.filter_pods(&|p| p.namespace() == name.namespace && (p.name == name.name || p.name == podman_name))
+        .into_iter().next()
+        .map(|(pod, node)| (pod, node.node_name.clone()))
+        .ok_or(anyhow!("pod {} not found", name).into())
+}
+
+// the resource the pod was created from, as last applied
+pub(crate) fn owner_resource(state: &ClusterState, owner: &OwnerRef) -> Result<SupportedResources, SkateError> {
+    let item = state.catalogue(None, std::slice::from_ref(&owner.resource_type)).into_iter()
+        .find(|i| i.object.name == owner.name && i.object.manifest.is_some())
+        .ok_or(anyhow!("no manifest found for {}", owner))?;
+    Ok(SupportedResources::try_from(item.object)?)
+}
+
+// runs each container's exec preStop hook in the container, cut short when the grace period is up
+fn pre_stop_commands(pod_name: &str, spec: &PodSpec, grace_period: usize) -> Vec<String> {
+    spec.containers.iter().filter_map(|c| {
+        let handler = c.lifecycle.as_ref()?.pre_stop.as_ref()?;
+        match handler.exec.as_ref().and_then(|e| e.command.as_ref()) {
+            Some(command) => {
+                let command = command.iter().map(|a| shell_quote(a)).collect::<Vec<_>>().join(" ");
+                Some(format!("sudo timeout {} podman exec {}-{} {}", grace_period, pod_name, c.name, command))
+            }
+            None => {
+                eprintln!("only exec preStop hooks are supported, skipping the hook of container {}", c.name);
+                None
+            }
+        }
+    }).collect()
+}
+
+// the requested grace period, or the pod's own
+pub(crate) fn grace_period(spec: &PodSpec, requested: Option<usize>) -> usize {
+    requested
+        .or(spec.termination_grace_period_seconds.map(|g| g.max(0) as usize))
+        .unwrap_or(DEFAULT_GRACE_PERIOD)
+}
+
+// runs the preStop hooks then stops the pod, returning it as removed from the node
+pub(crate) async fn evict_pod(conn: &dyn SshClient, pod: &PodmanPodInfo, spec: &PodSpec, grace_period: usize, dry_run: bool) -> Result<SupportedResources, SkateError> {
+    let mut removed: Pod = pod.clone().into();
+    if let Some(removed_spec) = removed.spec.as_mut() {
+        removed_spec.termination_grace_period_seconds = Some(grace_period as i64);
+    }
+    let removed = SupportedResources::Pod(removed);
+
+    if dry_run {
+        println!("would evict pod {} from {}, waiting up to {}s for it to stop", pod.name, conn.node_name(), grace_period);
+        return Ok(removed);
+    }
+    for command in pre_stop_commands(&pod.name, spec, grace_period) {
+        if let Err(e) = conn.execute(&command).await {
+            eprintln!("{} - preStop hook of pod {} failed: {}", conn.node_name(), pod.name, e);
+        }
+    }
+    let manifest = serde_yaml::to_string(&removed).map_err(|e| anyhow!(e).context("failed to serialize pod"))?;
+    conn.remove_resource_by_manifest(&manifest).await?;
+    println!("{} evicted pod {} from {}", CHECKBOX_EMOJI, pod.name, conn.node_name());
+    Ok(removed)
+}
+
+impl<D: EvictDeps + RefreshDeps> Evict<D> {
+    pub async fn evict(&self, args: EvictArgs) -> Result<(), SkateError> {
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), Access::Deploy)?;
+
+        let (_, name) = split_resource_identifier(&args.pod, &[]);
+        let name = NamespacedName::from_arg(name, args.namespace.as_deref(), Some(cluster.default_namespace()))?;
+        cluster.authorize_namespaces(&[&name.namespace])?;
+
+        let (conns, errors) = self.deps.get().cluster_connect(cluster).await;
+        if let Some(errors) = errors {
+            eprintln!("{}", errors)
+        }
+        let conns = conns.ok_or(anyhow!("failed to connect to any node"))?;
+        let mut state = Refresh::<D>::refreshed_state(&cluster.name, &conns, &config).await?;
+
+        let (pod, node) = find_pod(&state, &name)?;
+        let owner = OwnerRef::of(&pod);
+        let mut owner_resource = match &owner {
+            // the daemonset would only put it back on the same node
+            Some(owner) if owner.resource_type == ResourceType::DaemonSet => {
+                return Err(anyhow!("pod {} belongs to {}, which runs a pod on every node", pod.name, owner).into());
+            }
+            Some(owner) => Some(owner_resource(&state, owner)?),
+            None => None,
+        };
+
+        let spec = owner_resource.as_mut().and_then(pod_spec_mut).cloned().unwrap_or_default();
+        let grace_period = grace_period(&spec, args.grace_period);
+
+        let reschedule = match (owner_resource, args.no_reschedule) {
+            (Some(resource @ SupportedResources::Deployment(_)), false) => Some(resource),
+            _ => None,
+        };
+        // the replacement is scheduled like any apply, so it has to pass the policies too
+        check_policy(cluster, reschedule.as_slice(), args.override_policy)?;
+
+        if !args.dry_run && !confirm(&format!("Are you sure you want to evict pod {}?", pod.name), &[Target::new(&node, "Pod", &pod.name)], args.yes)? {
+            return Ok(());
+        }
+
+        let conn = conns.find(&node).ok_or(anyhow!("not connected to node {}", node))?;
+
+        let run = async {
+            let removed = evict_pod(conn, &pod, &spec, grace_period, args.dry_run).await?;
+            state.reconcile_object_deletion(&removed, &node)?;
+
+            // the deployment is a replica short now, the replacement goes to any other node that fits it
+            if let Some(resource) = reschedule {
+                let scheduler = DefaultScheduler { avoid_nodes: vec![node.clone()], ..Default::default() };
+                scheduler.schedule(&conns, &mut state, vec![resource], args.dry_run).await?;
+            }
+            Ok(())
+        };
+        match args.dry_run {
+            true => run.await,
+            false => lock::locked(cluster, &conns, "evict", run).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::{Container, ExecAction, Lifecycle, LifecycleHandler, PodSpec};
+    use crate::evict::pre_stop_commands;
+
+    #[test]
+    fn test_pre_stop_commands() {
+        let container = |name: &str, command: Option<Vec<&str>>| Container {
+            name: name.to_string(),
+            lifecycle: command.map(|c| Lifecycle {
+                pre_stop: Some(LifecycleHandler {
+                    exec: Some(ExecAction { command: Some(c.iter().map(|a| a.to_string()).collect()) }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let spec = PodSpec {
+            containers: vec![container("nginx", Some(vec!["nginx", "-s", "quit"])), container("sidecar", None), container("drain", Some(vec!["sh", "-c", "sleep 5; echo done"]))],
+            ..Default::default()
+        };
+
+        assert_eq!(vec![
+            "sudo timeout 30 podman exec dpl-web-0.shop-nginx 'nginx' '-s' 'quit'".to_string(),
+            "sudo timeout 30 podman exec dpl-web-0.shop-drain 'sh' '-c' 'sleep 5; echo done'".to_string(),
+        ], pre_stop_commands("dpl-web-0.shop", &spec, 30));
+    }
+}
@@ -0,0 +1,521 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
+use anyhow::anyhow;
+use clap::Args;
+use k8s_openapi::api::apps::v1::{DaemonSet, Deployment};
+use k8s_openapi::api::batch::v1::CronJob;
+use k8s_openapi::api::core::v1::{Pod, Secret, Service};
+use k8s_openapi::api::networking::v1::Ingress;
+use k8s_openapi::api::policy::v1::PodDisruptionBudget;
+use k8s_openapi::Resource;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::forward_to_deserialize_any;
+use tabled::settings::Style;
+use tabled::{Table, Tabled};
+use crate::errors::SkateError;
+use crate::resource::ResourceType;
+use crate::spec::cert::ClusterIssuer;
+
+#[derive(Debug, Args)]
+#[command(arg_required_else_help(true))]
+pub struct ExplainArgs {
+    #[arg(name = "TYPE[.FIELD...]", long_help = "The resource type, optionally followed by the path of a field, eg deployment.spec.strategy.")]
+    field: String,
+    #[arg(long, long_help = "List every field below the one explained, not only its direct children.")]
+    recursive: bool,
+}
+
+// how much of a field skate acts on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Support {
+    // skate itself acts on the field
+    Honored,
+    // the pod is handed to podman kube play, which decides
+    Podman,
+    Ignored,
+}
+
+impl fmt::Display for Support {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Support::Honored => write!(f, "honored"),
+            Support::Podman => write!(f, "passed to podman"),
+            Support::Ignored => write!(f, "ignored"),
+        }
+    }
+}
+
+// a path, or with .* only the fields below it, how skate supports them and a note shown on the field itself
+type Rule = (&'static str, Support, &'static str);
+
+const METADATA_RULES: &[Rule] = &[
+    ("metadata", Support::Honored, ""),
+    ("metadata.*", Support::Ignored, ""),
+    ("metadata.name", Support::Honored, ""),
+    ("metadata.namespace", Support::Honored, "defaults to default"),
+    ("metadata.labels", Support::Honored, ""),
+    ("metadata.annotations", Support::Honored, ""),
+    ("metadata.generation", Support::Honored, "set by skate, counts the applies that changed the object"),
+    ("metadata.resourceVersion", Support::Honored, "apply refuses if the live object is at a different one"),
+    ("status", Support::Ignored, "reported by skate, not read from manifests"),
+];
+
+// relative to the pod spec, which is at a different path in each kind
+const POD_SPEC_RULES: &[Rule] = &[
+    ("", Support::Podman, "podman kube play supports most of the pod spec"),
+    ("nodeSelector", Support::Honored, "matched against the node labels when scheduling"),
+    ("hostNetwork", Support::Honored, "two pods binding the same host port are never put on the same node"),
+    ("containers.ports.hostPort", Support::Honored, "scheduled like the ports of hostNetwork pods"),
+    ("dnsPolicy", Support::Honored, "ClusterFirst keeps the cluster's nameserver first, Default uses the node's resolv.conf"),
+    ("dnsConfig", Support::Honored, "at most 3 nameservers, including the cluster's"),
+    ("securityContext.sysctls", Support::Honored, "the safe ones, others only when the cluster.yaml's allowedUnsafeSysctls lists them"),
+    ("hostAliases", Support::Honored, "added to the pod's /etc/hosts, the hostnames of the same ip on one line"),
+    ("volumes.emptyDir", Support::Honored, "created by skate on the node"),
+    ("affinity", Support::Ignored, ""),
+    ("affinity.podAffinity.requiredDuringSchedulingIgnoredDuringExecution", Support::Honored, "only with the kubernetes.io/hostname topology key"),
+    ("affinity.podAntiAffinity.requiredDuringSchedulingIgnoredDuringExecution", Support::Honored, "only with the kubernetes.io/hostname topology key"),
+    ("tolerations", Support::Ignored, ""),
+    ("topologySpreadConstraints", Support::Ignored, ""),
+    ("priority", Support::Honored, "lower priority pods may be preempted when no node has room"),
+    ("priorityClassName", Support::Honored, "resolved from the cluster.yaml's priorityClasses or the system classes"),
+    ("preemptionPolicy", Support::Honored, "Never waits for room instead of preempting"),
+    ("schedulerName", Support::Ignored, ""),
+    ("schedulingGates", Support::Ignored, ""),
+    ("nodeName", Support::Ignored, "the scheduler picks the node"),
+    ("serviceAccount", Support::Ignored, ""),
+    ("serviceAccountName", Support::Ignored, ""),
+    ("automountServiceAccountToken", Support::Ignored, ""),
+    ("runtimeClassName", Support::Ignored, ""),
+    ("readinessGates", Support::Ignored, ""),
+    ("resourceClaims", Support::Ignored, ""),
+    ("overhead", Support::Ignored, ""),
+    ("ephemeralContainers", Support::Ignored, ""),
+];
+
+const DEPLOYMENT_RULES: &[Rule] = &[
+    ("spec", Support::Honored, ""),
+    ("spec.*", Support::Ignored, ""),
+    ("spec.replicas", Support::Honored, ""),
+    ("spec.strategy", Support::Honored, ""),
+    ("spec.strategy.type", Support::Honored, "RollingUpdate or Recreate"),
+    ("spec.strategy.rollingUpdate", Support::Ignored, "maxSurge and maxUnavailable aren't used"),
+    ("spec.template", Support::Honored, ""),
+];
+
+const DAEMONSET_RULES: &[Rule] = &[
+    ("spec", Support::Honored, ""),
+    ("spec.*", Support::Ignored, ""),
+    ("spec.template", Support::Honored, "runs on every schedulable node matching the nodeSelector"),
+];
+
+const CRONJOB_RULES: &[Rule] = &[
+    ("spec", Support::Honored, ""),
+    ("spec.*", Support::Ignored, ""),
+    ("spec.schedule", Support::Honored, "run by a systemd timer on the node"),
+    ("spec.timeZone", Support::Honored, ""),
+    ("spec.suspend", Support::Honored, ""),
+    ("spec.jobTemplate", Support::Honored, ""),
+    ("spec.jobTemplate.spec.*", Support::Ignored, ""),
+    ("spec.jobTemplate.spec.template", Support::Honored, ""),
+];
+
+const SERVICE_RULES: &[Rule] = &[
+    ("spec", Support::Honored, "services get a virtual ip load balancing over the selected pods"),
+    ("spec.*", Support::Ignored, ""),
+    ("spec.selector", Support::Honored, "only app.kubernetes.io/name is used"),
+    ("spec.ports", Support::Honored, ""),
+    ("spec.ports.*", Support::Ignored, ""),
+    ("spec.ports.port", Support::Honored, ""),
+    ("spec.ports.targetPort", Support::Honored, "defaults to port"),
+];
+
+const INGRESS_RULES: &[Rule] = &[
+    ("metadata.annotations", Support::Honored, "nginx.ingress.kubernetes.io/ssl-redirect, proxy-body-size, auth-type basic, auth-secret, auth-realm, whitelist-source-range and denylist-source-range are honored, as are skate.io/limit-rps and limit-burst"),
+    ("spec", Support::Honored, ""),
+    ("spec.*", Support::Ignored, ""),
+    ("spec.ingressClassName", Support::Honored, "one of the cluster's ingress classes"),
+    ("spec.defaultBackend", Support::Honored, "serves its hosts' other paths, or every host when the ingress has no host rules"),
+    ("spec.rules", Support::Honored, ""),
+    ("spec.rules.host", Support::Honored, "wildcards match a single label, rules without a host serve every host"),
+    ("spec.rules.http.paths.backend.service.port.name", Support::Ignored, "use the port number"),
+    ("spec.rules.http.paths.backend.resource", Support::Ignored, ""),
+    ("spec.tls", Support::Ignored, "certificates are issued automatically for every host"),
+];
+
+const SECRET_RULES: &[Rule] = &[
+    ("data", Support::Honored, "stored as a podman secret"),
+    ("stringData", Support::Honored, "stored as a podman secret"),
+    ("type", Support::Ignored, ""),
+    ("immutable", Support::Ignored, ""),
+];
+
+const CLUSTER_ISSUER_RULES: &[Rule] = &[
+    ("spec", Support::Honored, "only acme is supported"),
+];
+
+const POD_DISRUPTION_BUDGET_RULES: &[Rule] = &[
+    ("spec", Support::Honored, "limits the pods preempted for higher priority ones"),
+    ("spec.*", Support::Ignored, ""),
+    ("spec.selector", Support::Honored, ""),
+    ("spec.selector.matchExpressions", Support::Ignored, "only matchLabels is used"),
+    ("spec.minAvailable", Support::Honored, "a number or a percentage of the selected pods"),
+    ("spec.maxUnavailable", Support::Honored, "a number or a percentage of the selected pods"),
+];
+
+// where the pod spec is within a kind, if it has one
+fn pod_spec_path(resource_type: &ResourceType) -> Option<&'static str> {
+    match resource_type {
+        ResourceType::Pod => Some("spec"),
+        ResourceType::Deployment | ResourceType::DaemonSet => Some("spec.template.spec"),
+        ResourceType::CronJob => Some("spec.jobTemplate.spec.template.spec"),
+        _ => None,
+    }
+}
+
+fn kind_rules(resource_type: &ResourceType) -> &'static [Rule] {
+    match resource_type {
+        ResourceType::Pod => &[],
+        ResourceType::Deployment => DEPLOYMENT_RULES,
+        ResourceType::DaemonSet => DAEMONSET_RULES,
+        ResourceType::CronJob => CRONJOB_RULES,
+        ResourceType::Service => SERVICE_RULES,
+        ResourceType::Ingress => INGRESS_RULES,
+        ResourceType::Secret => SECRET_RULES,
+        ResourceType::ClusterIssuer => CLUSTER_ISSUER_RULES,
+        ResourceType::PodDisruptionBudget => POD_DISRUPTION_BUDGET_RULES,
+    }
+}
+
+fn covers(rule: &str, path: &str) -> bool {
+    if let Some(parent) = rule.strip_suffix(".*") {
+        return path.strip_prefix(parent).is_some_and(|rest| rest.starts_with('.'));
+    }
+    path == rule || path.strip_prefix(rule).is_some_and(|rest| rest.starts_with('.'))
+}
+
+// the most specific rule covering the path decides, fields no rule covers are honored along with their parent
+pub fn support(resource_type: &ResourceType, path: &str) -> (Support, &'static str) {
+    let pod_rules = pod_spec_path(resource_type).into_iter().flat_map(|prefix| {
+        POD_SPEC_RULES.iter().map(move |(rule, support, note)| {
+            let rule = match rule.is_empty() {
+                true => prefix.to_string(),
+                false => format!("{}.{}", prefix, rule),
+            };
+            (rule, *support, *note)
+        })
+    });
+    let rules = METADATA_RULES.iter().chain(kind_rules(resource_type).iter())
+        .map(|(rule, support, note)| (rule.to_string(), *support, *note))
+        .chain(pod_rules);
+
+    rules.filter(|(rule, _, _)| covers(rule, path))
+        .max_by_key(|(rule, _, _)| rule.len())
+        .map(|(rule, support, note)| match rule == path {
+            true => (support, note),
+            false => (support, ""),
+        })
+        .unwrap_or((Support::Honored, ""))
+}
+
+type Fields = Rc<RefCell<BTreeMap<String, String>>>;
+
+// Tracer is a deserializer that walks a type's Deserialize impl instead of data, recording the path and type of
+// every field the k8s-openapi structs ask for along the way
+struct Tracer {
+    fields: Fields,
+    path: String,
+    // [] and map[string] for the collections the value is in
+    prefix: String,
+    // the structs above, so recursive types stop
+    parents: Vec<&'static str>,
+}
+
+impl Tracer {
+    fn child(&self, field: &str) -> Tracer {
+        let path = match self.path.is_empty() {
+            true => field.to_string(),
+            false => format!("{}.{}", self.path, field),
+        };
+        Tracer { fields: self.fields.clone(), path, prefix: String::new(), parents: self.parents.clone() }
+    }
+
+    fn wrapped(self, prefix: &str) -> Tracer {
+        Tracer { prefix: format!("{}{}", self.prefix, prefix), ..self }
+    }
+
+    // the outermost type wins, eg Quantity rather than the string inside it
+    fn record(&self, type_name: &str) {
+        if !self.path.is_empty() {
+            self.fields.borrow_mut().entry(self.path.clone()).or_insert(format!("{}{}", self.prefix, type_name));
+        }
+    }
+}
+
+struct Expecting<'a, V>(&'a V);
+
+impl<'de, V: Visitor<'de>> fmt::Display for Expecting<'_, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.expecting(f)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Tracer {
+    type Error = de::value::Error;
+
+    // IntOrString and free form json
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match Expecting(&visitor).to_string().as_str() {
+            "IntOrString" => self.record("IntOrString"),
+            _ => self.record("object"),
+        }
+        visitor.visit_str("")
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.record("boolean");
+        visitor.visit_bool(false)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.record("integer");
+        visitor.visit_i64(0)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.record("integer");
+        visitor.visit_i64(0)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.record("number");
+        visitor.visit_f64(0.0)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.record("string");
+        // times are parsed from the string, so they need a valid one
+        match Expecting(&visitor).to_string().contains("date") {
+            true => visitor.visit_str("1970-01-01T00:00:00Z"),
+            false => visitor.visit_str(""),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        self.record(name);
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(Element { tracer: Some(self.wrapped("[]")) })
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(Entry { key: true, tracer: Some(self.wrapped("map[string]")) })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, name: &'static str, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> {
+        self.record(name);
+        if self.parents.contains(&name) {
+            return visitor.visit_map(StructFields { fields: &[], index: 0, tracer: self });
+        }
+        let mut tracer = self;
+        tracer.parents.push(name);
+        visitor.visit_map(StructFields { fields, index: 0, tracer })
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 u8 u16 u32 u64 f32 char bytes byte_buf unit unit_struct tuple tuple_struct enum identifier
+    }
+}
+
+struct StructFields {
+    fields: &'static [&'static str],
+    index: usize,
+    tracer: Tracer,
+}
+
+impl<'de> MapAccess<'de> for StructFields {
+    type Error = de::value::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error> {
+        // the top level apiVersion and kind have to match the type's, they're recorded rather than traced
+        while self.tracer.path.is_empty() && self.fields.get(self.index).is_some_and(|f| *f == "apiVersion" || *f == "kind") {
+            self.tracer.child(self.fields[self.index]).record("string");
+            self.index += 1;
+        }
+        match self.fields.get(self.index) {
+            Some(field) => seed.deserialize((*field).into_deserializer()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Self::Error> {
+        let field = self.fields[self.index];
+        self.index += 1;
+        seed.deserialize(self.tracer.child(field))
+    }
+}
+
+struct Element {
+    tracer: Option<Tracer>,
+}
+
+impl<'de> SeqAccess<'de> for Element {
+    type Error = de::value::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error> {
+        self.tracer.take().map(|t| seed.deserialize(t)).transpose()
+    }
+}
+
+struct Entry {
+    key: bool,
+    tracer: Option<Tracer>,
+}
+
+impl<'de> MapAccess<'de> for Entry {
+    type Error = de::value::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error> {
+        if !self.key {
+            return Ok(None);
+        }
+        self.key = false;
+        seed.deserialize("key".into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Self::Error> {
+        let tracer = self.tracer.take().ok_or(de::Error::custom("map value requested twice"))?;
+        seed.deserialize(tracer)
+    }
+}
+
+// the path and type of every field of the resource
+fn trace<T: DeserializeOwned>() -> BTreeMap<String, String> {
+    let fields: Fields = Rc::new(RefCell::new(BTreeMap::new()));
+    let tracer = Tracer { fields: fields.clone(), path: String::new(), prefix: String::new(), parents: vec![] };
+    // a field that can't be traced only ends the walk of its struct, what was recorded until then is still good
+    let _ = T::deserialize(tracer);
+    let traced = fields.borrow().clone();
+    traced
+}
+
+fn describe(resource_type: &ResourceType) -> (&'static str, &'static str, BTreeMap<String, String>) {
+    fn of<T: DeserializeOwned + Resource>() -> (&'static str, &'static str, BTreeMap<String, String>) {
+        (T::KIND, T::API_VERSION, trace::<T>())
+    }
+    match resource_type {
+        ResourceType::Pod => of::<Pod>(),
+        ResourceType::Deployment => of::<Deployment>(),
+        ResourceType::DaemonSet => of::<DaemonSet>(),
+        ResourceType::Ingress => of::<Ingress>(),
+        ResourceType::CronJob => of::<CronJob>(),
+        ResourceType::Secret => of::<Secret>(),
+        ResourceType::Service => of::<Service>(),
+        ResourceType::ClusterIssuer => of::<ClusterIssuer>(),
+        ResourceType::PodDisruptionBudget => of::<PodDisruptionBudget>(),
+    }
+}
+
+#[derive(Tabled)]
+#[tabled(rename_all = "UPPERCASE")]
+struct FieldRow {
+    field: String,
+    #[tabled(rename = "TYPE")]
+    type_name: String,
+    skate: String,
+}
+
+fn support_text(resource_type: &ResourceType, path: &str) -> String {
+    match support(resource_type, path) {
+        (support, "") => support.to_string(),
+        (support, note) => format!("{} - {}", support, note),
+    }
+}
+
+pub fn explain(args: ExplainArgs) -> Result<(), SkateError> {
+    let (type_name, path) = args.field.split_once('.').unwrap_or((&args.field, ""));
+    let resource_type = ResourceType::from_str(type_name).map_err(|_| anyhow!("unsupported resource type {}", type_name))?;
+    let (kind, api_version, fields) = describe(&resource_type);
+
+    println!("KIND:     {}", kind);
+    println!("VERSION:  {}", api_version);
+    println!();
+
+    if !path.is_empty() {
+        let field_type = fields.get(path).ok_or(anyhow!("field {} does not exist in {}", path, kind))?;
+        println!("FIELD:    {} <{}>", path, field_type);
+        println!("SKATE:    {}", support_text(&resource_type, path));
+        println!();
+    }
+
+    let prefix = match path.is_empty() {
+        true => String::new(),
+        false => format!("{}.", path),
+    };
+    let rows: Vec<_> = fields.iter()
+        .filter_map(|(p, t)| p.strip_prefix(&prefix).map(|rest| (p, rest, t)))
+        .filter(|(_, rest, _)| args.recursive || !rest.contains('.'))
+        .map(|(p, rest, t)| FieldRow { field: rest.to_string(), type_name: format!("<{}>", t), skate: support_text(&resource_type, p) })
+        .collect();
+
+    if rows.is_empty() {
+        return Ok(());
+    }
+    println!("FIELDS:");
+    let mut table = Table::new(rows);
+    table.with(Style::empty());
+    println!("{}", table);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::apps::v1::Deployment;
+    use crate::explain::{support, trace, Support};
+    use crate::resource::ResourceType;
+
+    #[test]
+    fn test_trace() {
+        let fields = trace::<Deployment>();
+        assert_eq!(Some("string"), fields.get("apiVersion").map(|t| t.as_str()));
+        assert_eq!(Some("DeploymentStrategy"), fields.get("spec.strategy").map(|t| t.as_str()));
+        assert_eq!(Some("string"), fields.get("spec.strategy.type").map(|t| t.as_str()));
+        assert_eq!(Some("IntOrString"), fields.get("spec.strategy.rollingUpdate.maxSurge").map(|t| t.as_str()));
+        assert_eq!(Some("[]Container"), fields.get("spec.template.spec.containers").map(|t| t.as_str()));
+        assert_eq!(Some("string"), fields.get("spec.template.spec.containers.image").map(|t| t.as_str()));
+        assert_eq!(Some("map[string]string"), fields.get("metadata.labels").map(|t| t.as_str()));
+        assert_eq!(Some("map[string]Quantity"), fields.get("spec.template.spec.containers.resources.limits").map(|t| t.as_str()));
+        assert_eq!(Some("Time"), fields.get("metadata.creationTimestamp").map(|t| t.as_str()));
+        // fields after a time are still traced
+        assert!(fields.contains_key("metadata.uid"));
+    }
+
+    #[test]
+    fn test_support() {
+        assert_eq!(Support::Honored, support(&ResourceType::Deployment, "spec.strategy.type").0);
+        assert_eq!(Support::Ignored, support(&ResourceType::Deployment, "spec.strategy.rollingUpdate.maxSurge").0);
+        assert_eq!(Support::Honored, support(&ResourceType::Deployment, "spec.template.spec.nodeSelector").0);
+        assert_eq!(Support::Podman, support(&ResourceType::Deployment, "spec.template.spec.containers.image").0);
+        assert_eq!(Support::Ignored, support(&ResourceType::CronJob, "spec.jobTemplate.spec.template.spec.tolerations").0);
+        assert_eq!(Support::Podman, support(&ResourceType::Pod, "spec.containers").0);
+        assert_eq!(Support::Ignored, support(&ResourceType::Pod, "status.phase").0);
+        assert_eq!(Support::Honored, support(&ResourceType::Service, "spec.ports.targetPort").0);
+        assert_eq!(Support::Ignored, support(&ResourceType::Service, "spec.ports.protocol").0);
+    }
+}
@@ -7,7 +7,9 @@ use anyhow::anyhow;
 use chrono::{DateTime, Local};
 use k8s_openapi::api::networking::v1::Ingress;
 use k8s_openapi::{kind, Metadata};
+use k8s_openapi::api::apps::v1::{DaemonSet, Deployment};
 use k8s_openapi::api::batch::v1::CronJob;
+use k8s_openapi::api::policy::v1::PodDisruptionBudget;
 use k8s_openapi::api::core::v1::{Secret, Service};
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
 use log::{warn};
@@ -134,6 +136,18 @@ impl From<&Ingress> for ObjectListItem {
     }
 }
 
+impl From<&Deployment> for ObjectListItem {
+    fn from(res: &Deployment) -> Self {
+        Self::from_k8s_resource(res, None)
+    }
+}
+
+impl From<&DaemonSet> for ObjectListItem {
+    fn from(res: &DaemonSet) -> Self {
+        Self::from_k8s_resource(res, None)
+    }
+}
+
 impl From<&CronJob> for ObjectListItem {
     fn from(res: &CronJob) -> Self {
         Self::from_k8s_resource(res, None)
@@ -159,6 +173,12 @@ impl From<&ClusterIssuer> for ObjectListItem {
     }
 }
 
+impl From<&PodDisruptionBudget> for ObjectListItem {
+    fn from(res: &PodDisruptionBudget) -> Self {
+        Self::from_k8s_resource(res, None)
+    }
+}
+
 impl FileStore {
     pub fn new() -> Self {
         FileStore {
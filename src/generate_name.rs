@@ -0,0 +1,72 @@
+use std::hash::{BuildHasher, Hasher};
+use std::collections::hash_map::RandomState;
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::resource::SupportedResources;
+
+// as kubernetes' name generator, without vowels so no words are spelled
+const ALPHABET: &[u8] = b"bcdfghjklmnpqrstvwxz2456789";
+const SUFFIX_LEN: usize = 5;
+// names are at most 63 characters once the suffix is added
+const MAX_PREFIX_LEN: usize = 63 - SUFFIX_LEN;
+
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+fn random_suffix() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    let mut value = hasher.finish();
+    (0..SUFFIX_LEN).map(|_| {
+        let c = ALPHABET[(value % ALPHABET.len() as u64) as usize] as char;
+        value /= ALPHABET.len() as u64;
+        c
+    }).collect()
+}
+
+pub fn generate(prefix: &str) -> String {
+    let prefix: String = prefix.chars().take(MAX_PREFIX_LEN).collect();
+    format!("{}{}", prefix, random_suffix())
+}
+
+// names a resource that only has a generateName, so the same manifest can be applied again and again, eg a one off
+// pod from ci, each time creating another
+pub fn resolve(mut resource: SupportedResources) -> SupportedResources {
+    let meta = resource.metadata_mut();
+    if meta.name.as_deref().unwrap_or_default().is_empty() {
+        if let Some(prefix) = meta.generate_name.as_deref().filter(|p| !p.is_empty()) {
+            meta.name = Some(generate(prefix));
+        }
+    }
+    resource
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::Pod;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use crate::generate_name::{generate, resolve, ALPHABET};
+    use crate::resource::SupportedResources;
+
+    fn pod(name: Option<&str>, generate_name: Option<&str>) -> SupportedResources {
+        SupportedResources::Pod(Pod {
+            metadata: ObjectMeta { name: name.map(|n| n.to_string()), generate_name: generate_name.map(|n| n.to_string()), ..Default::default() },
+            ..Default::default()
+        })
+    }
+
+    fn name(mut resource: SupportedResources) -> String {
+        resource.metadata_mut().name.clone().unwrap()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let generated = name(resolve(pod(None, Some("migrate-"))));
+        assert_eq!(13, generated.len());
+        assert!(generated.starts_with("migrate-"));
+        assert!(generated["migrate-".len()..].bytes().all(|c| ALPHABET.contains(&c)));
+        assert_ne!(generated, name(resolve(pod(None, Some("migrate-")))));
+
+        // a name of its own is kept
+        assert_eq!("migrate", name(resolve(pod(Some("migrate"), Some("migrate-")))));
+        assert_eq!(63, generate(&"a".repeat(70)).len());
+    }
+}
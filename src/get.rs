@@ -2,20 +2,32 @@ mod node;
 mod ingress;
 mod deployment;
 mod cronjob;
+mod job;
 mod pod;
 mod lister;
 mod daemonset;
 mod secret;
 mod service;
+mod field_selector;
+pub(crate) mod custom_columns;
 
 
 
 
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use anyhow::anyhow;
+use itertools::Itertools;
 use clap::{Args, Subcommand};
-use tabled::settings::Style;
-use tabled::{Table, Tabled};
+use serde::Serialize;
+use tabled::settings::location::ByColumnName;
+use tabled::settings::object::Rows;
+use tabled::settings::{Disable, Style};
+use tabled::builder::Builder;
+use tabled::Tabled;
+use crate::client::Client;
 use crate::config::Config;
-use crate::refresh::{Refresh};
 
 
 use crate::skate::{ConfigFileArgs};
@@ -23,15 +35,20 @@ use crate::skate::{ConfigFileArgs};
 use crate::refresh;
 use crate::deps::{SshManager, With};
 use crate::errors::SkateError;
+use crate::resource::export_manifest;
 use crate::get::cronjob::CronjobsLister;
+use crate::get::custom_columns::Column;
 use crate::get::daemonset::DaemonsetLister;
 use crate::get::deployment::DeploymentLister;
 use crate::get::ingress::IngressLister;
-use crate::get::lister::{Lister, NameFilters};
+use crate::get::job::JobLister;
+use crate::get::lister::{Lister, NameFilters, ALL_NAMESPACES};
 use crate::get::node::NodeLister;
 use crate::get::pod::PodLister;
 use crate::get::secret::SecretLister;
 use crate::get::service::ServiceLister;
+use crate::state::state::ClusterState;
+use serde_json::json;
 
 #[derive(Debug, Clone, Args)]
 pub struct GetArgs {
@@ -45,8 +62,122 @@ pub struct GetObjectArgs {
     config: ConfigFileArgs,
     #[arg(long, short, long_help = "Filter by resource namespace")]
     namespace: Option<String>,
+    #[arg(long, short = 'A', conflicts_with = "namespace", long_help = "List resources in all namespaces, including skate's own.")]
+    all_namespaces: bool,
     #[arg()]
-    id: Option<String>
+    id: Option<String>,
+    #[arg(long, long_help = "Only list resources whose fields match, eg status.phase=Running,spec.nodeName=node-1 or status.restartCount>0. \
+Supports metadata.name and metadata.namespace, and status.phase, spec.nodeName and status.restartCount for pods.")]
+    field_selector: Option<String>,
+    #[arg(long, short, default_value_t = OutputFormat::Table, long_help = "Output format: table, wide, yaml, json or custom-columns=<header>:<path>,... \
+Wide adds columns to the table, yaml and json print the stored manifests. The custom columns' paths are looked up in the stored manifest and \
+the table's fields, eg custom-columns=NAME:.metadata.name,IMAGES:.spec.template.spec.containers[*].image,READY:.ready")]
+    output: OutputFormat,
+    #[arg(long, long_help = "Print the manifests as they were applied, without the labels and names skate adds. Requires -o yaml or json.")]
+    export: bool,
+    #[arg(long, long_help = "Don't print the table's header row, eg for piping into other commands.")]
+    no_headers: bool,
+    #[arg(long, long_help = "Add a column with all of each resource's labels.")]
+    show_labels: bool,
+    #[arg(long, short = 'L', value_delimiter = ',', long_help = "Add a column with the value of each of these labels, eg -L app,skate.io/arch.")]
+    label_columns: Vec<String>,
+}
+
+impl GetObjectArgs {
+    // the namespace the listers filter on
+    pub(crate) fn namespace_filter(&self) -> String {
+        match self.all_namespaces {
+            true => ALL_NAMESPACES.to_string(),
+            false => self.namespace.clone().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+    Table,
+    Wide,
+    Yaml,
+    Json,
+    CustomColumns(Vec<Column>),
+}
+
+impl OutputFormat {
+    // the listers' rows rather than the stored manifests
+    pub(crate) fn is_table(&self) -> bool {
+        matches!(self, OutputFormat::Table | OutputFormat::Wide | OutputFormat::CustomColumns(_))
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "wide" => Ok(OutputFormat::Wide),
+            "yaml" => Ok(OutputFormat::Yaml),
+            "json" => Ok(OutputFormat::Json),
+            _ => match s.strip_prefix("custom-columns=") {
+                Some(spec) => Ok(OutputFormat::CustomColumns(custom_columns::parse(spec)?)),
+                None => Err(format!("unknown output format {}, expected table, wide, yaml, json or custom-columns=<header>:<path>,...", s)),
+            },
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputFormat::Table => write!(f, "table"),
+            OutputFormat::Wide => write!(f, "wide"),
+            OutputFormat::Yaml => write!(f, "yaml"),
+            OutputFormat::Json => write!(f, "json"),
+            OutputFormat::CustomColumns(columns) => write!(f, "custom-columns={}", columns.iter().map(|c| c.header.as_str()).join(",")),
+        }
+    }
+}
+
+// the header of a -L column is the label's name without its prefix, as kubectl has it
+fn label_header(key: &str) -> String {
+    key.rsplit('/').next().unwrap_or(key).to_uppercase()
+}
+
+fn label_cells(labels: &BTreeMap<String, String>, args: &GetObjectArgs) -> Vec<String> {
+    let mut cells: Vec<_> = args.label_columns.iter().map(|k| labels.get(k).cloned().unwrap_or_default()).collect();
+    if args.show_labels {
+        cells.push(match labels.is_empty() {
+            true => "<none>".to_string(),
+            false => labels.iter().map(|(k, v)| format!("{}={}", k, v)).join(","),
+        });
+    }
+    cells
+}
+
+// the columns are as wide as their longest value
+fn render_table<T: Tabled + NameFilters>(objects: Vec<T>, args: &GetObjectArgs, wide_columns: &[&str]) -> String {
+    let mut builder = Builder::default();
+    let label_headers = args.label_columns.iter().map(|k| label_header(k)).chain(args.show_labels.then(|| "LABELS".to_string()));
+    builder.push_record(T::headers().into_iter().map(|h| h.to_string()).chain(label_headers));
+    for object in objects.iter() {
+        builder.push_record(object.fields().into_iter().map(|f| f.to_string()).chain(label_cells(&object.labels(), args)));
+    }
+    let mut table = builder.build();
+    table.with(Style::empty());
+    // it's the same for every row
+    if args.namespace.is_some() {
+        table.with(Disable::column(ByColumnName::new("NAMESPACE")));
+    }
+    if args.output != OutputFormat::Wide {
+        for column in wide_columns {
+            table.with(Disable::column(ByColumnName::new(*column)));
+        }
+    }
+    // after the columns are disabled, they're found by their header
+    if args.no_headers {
+        table.with(Disable::row(Rows::first()));
+    }
+    table.to_string()
 }
 
 #[derive(Clone, Debug, Subcommand)]
@@ -63,6 +194,8 @@ pub enum GetCommands {
     Ingress(GetObjectArgs),
     #[command(alias("cronjobs"))]
     Cronjob(GetObjectArgs),
+    #[command(alias("jobs"))]
+    Job(GetObjectArgs),
     #[command(alias("secrets"))]
     Secret(GetObjectArgs),
     #[command(alias("services"))]
@@ -85,46 +218,108 @@ impl<D: GetDeps + refresh::RefreshDeps> Get<D> {
             GetCommands::Node(args) => self.get_nodes(global_args, args).await,
             GetCommands::Ingress(args) => self.get_ingress(global_args, args).await,
             GetCommands::Cronjob(args) => self.get_cronjobs(global_args, args).await,
+            GetCommands::Job(args) => self.get_jobs(global_args, args).await,
             GetCommands::Secret(args) => self.get_secrets(global_args, args).await,
             GetCommands::Service(args) => self.get_services(global_args, args).await,
         }
     }
 
 
-    async fn get_objects<T: Tabled + NameFilters>(&self, _global_args: GetArgs, args: GetObjectArgs, lister: &dyn Lister<T>) -> Result<(), SkateError> {
-        let config = Config::load(Some(args.config.skateconfig.clone()))?;
-        let mgr = self.deps.get();
-        let (conns, errors) = mgr.cluster_connect(config.active_cluster(args.config.context.clone())?).await;
-        if errors.is_some() {
-            eprintln!("{}", errors.unwrap())
+    async fn get_objects<T: Tabled + NameFilters + Serialize>(&self, _global_args: GetArgs, mut args: GetObjectArgs, lister: &dyn Lister<T>) -> Result<(), SkateError> {
+        if args.export && args.output.is_table() {
+            return Err(anyhow!("--export requires -o yaml or -o json").into());
         }
-
-        if conns.is_none() {
-            return Ok(());
+        if args.no_headers && !args.output.is_table() {
+            return Err(anyhow!("--no-headers can't be used with -o yaml or -o json").into());
         }
+        if (args.show_labels || !args.label_columns.is_empty()) && !matches!(args.output, OutputFormat::Table | OutputFormat::Wide) {
+            return Err(anyhow!("--show-labels and -L can't be used with -o yaml, json or custom-columns").into());
+        }
+        if args.field_selector.is_some() && !args.output.is_table() {
+            return Err(anyhow!("--field-selector can't be used with -o yaml or -o json").into());
+        }
+        let requirements = field_selector::parse(&args.field_selector.clone().unwrap_or_default())?;
+        let config = Config::load(args.config.skateconfig.clone())?;
+        // a cluster with a default namespace lists only it, otherwise every namespace but skate's is listed
+        if !args.all_namespaces && args.namespace.is_none() {
+            args.namespace = config.active_cluster(args.config.context.clone())?.configured_namespace().map(String::from);
+        }
+        let (client, errors) = Client::<D>::connect_with(&self.deps, config, args.config.context.clone()).await?;
+        if let Some(errors) = errors {
+            eprintln!("{}", errors)
+        }
+        let state = client.state().await?;
 
-        let conns = conns.unwrap();
-
-        let state = Refresh::<D>::refreshed_state(&config.current_context.clone().unwrap_or("".to_string()), &conns, &config).await?;
+        if !args.output.is_table() {
+            return Self::print_manifests(&args, &state, lister);
+        }
 
-        let objects = lister.list(&args, &state);
+        let objects = field_selector::filter(lister.list(&args, &state), &requirements)?;
 
         if objects.is_empty() {
-            if args.namespace.is_some() {
-                println!("No resources found for namespace {}", args.namespace.unwrap());
+            if let Some(ns) = args.namespace {
+                println!("No resources found for namespace {}", ns);
             } else {
                 println!("No resources found");
             }
             return Ok(());
         }
 
-        let mut table = Table::new(objects);
-        table.with(Style::empty());
-        println!("{}", table);
+        if let OutputFormat::CustomColumns(columns) = &args.output {
+            let items = lister.resource_type().map(|t| state.catalogue(None, &[t])).unwrap_or_default();
+            let documents = objects.iter().map(|o| {
+                let manifest = items.iter().find(|i| i.object.name.name == o.name() && i.object.name.namespace == o.namespace()).and_then(|i| i.object.manifest.as_ref());
+                custom_columns::document(o, manifest)
+            }).collect::<Result<Vec<_>, _>>()?;
+            println!("{}", custom_columns::render(&documents, columns, args.no_headers));
+            return Ok(());
+        }
+        println!("{}", render_table(objects, &args, lister.wide_columns()));
         Ok(())
     }
 
 
+    // prints the stored manifests of the matching resources, the objects the lister shows don't have them
+    fn print_manifests<T: Tabled + NameFilters>(args: &GetObjectArgs, state: &ClusterState, lister: &dyn Lister<T>) -> Result<(), SkateError> {
+        let resource_type = lister.resource_type().ok_or(anyhow!("-o {:?} is only supported for resources with manifests", args.output))?;
+        let id = args.id.clone().unwrap_or_default();
+        let ns = args.namespace.clone().unwrap_or_default();
+
+        let manifests: Vec<_> = state.catalogue(None, &[resource_type]).into_iter()
+            .filter(|item| (id.is_empty() || item.object.name.name == id) && (ns.is_empty() || item.object.name.namespace == ns))
+            .filter(|item| args.all_namespaces || !ns.is_empty() || !id.is_empty() || item.object.name.namespace != "skate")
+            .filter_map(|item| item.object.manifest.clone().map(|m| (m, item.object.manifest_hash.clone())))
+            .map(|(mut m, hash)| match args.export {
+                true => export_manifest(&m),
+                false => {
+                    // applying the manifest again then fails if the object changed in the meantime
+                    m["metadata"]["resourceVersion"] = serde_yaml::Value::from(hash);
+                    m
+                }
+            })
+            .collect();
+
+        if manifests.is_empty() {
+            return Err(anyhow!("No resources found").into());
+        }
+
+        match args.output {
+            OutputFormat::Yaml => {
+                let docs: Result<Vec<_>, _> = manifests.iter().map(serde_yaml::to_string).collect();
+                print!("{}", docs.map_err(|e| anyhow!(e).context("failed to serialize manifests"))?.join("---\n"));
+            }
+            OutputFormat::Json => {
+                let output = match manifests.len() {
+                    1 => serde_json::to_value(&manifests[0]),
+                    _ => serde_json::to_value(json!({"apiVersion": "v1", "kind": "List", "items": manifests})),
+                }.map_err(|e| anyhow!(e).context("failed to serialize manifests"))?;
+                println!("{}", serde_json::to_string_pretty(&output).map_err(|e| anyhow!(e).context("failed to serialize manifests"))?);
+            }
+            OutputFormat::Table | OutputFormat::Wide | OutputFormat::CustomColumns(_) => {}
+        }
+        Ok(())
+    }
+
     async fn get_deployment(&self, global_args: GetArgs, args: GetObjectArgs) -> Result<(), SkateError> {
         let lister = DeploymentLister {};
         self.get_objects(global_args, args, &lister).await
@@ -151,6 +346,11 @@ impl<D: GetDeps + refresh::RefreshDeps> Get<D> {
         self.get_objects(global_args, args, &lister).await
     }
 
+    async fn get_jobs(&self, global_args: GetArgs, args: GetObjectArgs) -> Result<(), SkateError> {
+        let lister = JobLister {};
+        self.get_objects(global_args, args, &lister).await
+    }
+
 
     async fn get_nodes(&self,global_args: GetArgs, args: GetObjectArgs) -> Result<(), SkateError> {
         let lister = NodeLister {};
@@ -168,3 +368,73 @@ impl<D: GetDeps + refresh::RefreshDeps> Get<D> {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use tabled::Tabled;
+    use crate::get::lister::NameFilters;
+    use crate::get::{render_table, GetObjectArgs, OutputFormat};
+    use crate::skate::ConfigFileArgs;
+
+    #[derive(Tabled)]
+    #[tabled(rename_all = "UPPERCASE")]
+    struct Row {
+        namespace: String,
+        name: String,
+        image: String,
+    }
+
+    impl NameFilters for Row {
+        fn name(&self) -> String {
+            self.name.clone()
+        }
+        fn namespace(&self) -> String {
+            self.namespace.clone()
+        }
+        fn labels(&self) -> BTreeMap<String, String> {
+            match self.name.as_str() {
+                "web" => BTreeMap::from([("app".to_string(), "web".to_string()), ("skate.io/arch".to_string(), "amd64".to_string())]),
+                _ => BTreeMap::new(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_table() {
+        let rows = || vec![
+            Row { namespace: "shop".to_string(), name: "web".to_string(), image: "nginx".to_string() },
+            Row { namespace: "shop".to_string(), name: "a-much-longer-name".to_string(), image: "postgres".to_string() },
+        ];
+        let args = |namespace: Option<&str>, output: OutputFormat, no_headers: bool| GetObjectArgs {
+            config: ConfigFileArgs { skateconfig: None, context: None },
+            namespace: namespace.map(|n| n.to_string()),
+            all_namespaces: false,
+            id: None,
+            field_selector: None,
+            output,
+            export: false,
+            no_headers,
+            show_labels: false,
+            label_columns: vec![],
+        };
+        let lines = |s: String| s.lines().map(|l| l.trim_end().to_string()).collect::<Vec<_>>();
+
+        assert_eq!(vec![
+            " NAMESPACE  NAME",
+            " shop       web",
+            " shop       a-much-longer-name",
+        ], lines(render_table(rows(), &args(None, OutputFormat::Table, false), &["IMAGE"])));
+        assert_eq!(vec![
+            " web                 nginx",
+            " a-much-longer-name  postgres",
+        ], lines(render_table(rows(), &args(Some("shop"), OutputFormat::Wide, true), &["IMAGE"])));
+
+        let labelled = GetObjectArgs { show_labels: true, label_columns: vec!["skate.io/arch".to_string()], ..args(Some("shop"), OutputFormat::Table, false) };
+        assert_eq!(vec![
+            " NAME                ARCH   LABELS",
+            " web                 amd64  app=web,skate.io/arch=amd64",
+            " a-much-longer-name         <none>",
+        ], lines(render_table(rows(), &labelled, &["IMAGE"])));
+    }
+}
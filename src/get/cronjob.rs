@@ -3,12 +3,15 @@ use crate::get::{Lister};
 use crate::get::lister::NameFilters;
 use crate::skatelet::SystemInfo;
 use crate::util::age;
+use crate::resource::ResourceType;
+use serde::Serialize;
 use tabled::Tabled;
 
 pub(crate) struct CronjobsLister {}
 
-#[derive(Tabled)]
+#[derive(Tabled, Serialize)]
 #[tabled(rename_all = "UPPERCASE")]
+#[serde(rename_all = "camelCase")]
 pub struct CronListItem {
     pub namespace: String,
     pub name: String,
@@ -31,6 +34,10 @@ impl NameFilters for CronListItem {
 }
 
 impl Lister<CronListItem> for CronjobsLister {
+    fn resource_type(&self) -> Option<ResourceType> {
+        Some(ResourceType::CronJob)
+    }
+
     fn selector(&self, si: &SystemInfo, ns: &str, id: &str) -> Vec<CronListItem> {
         si.cronjobs.as_ref().unwrap_or(&vec!()).iter().filter(|j| {
             j.filter_names(id, ns)
@@ -40,18 +47,49 @@ impl Lister<CronListItem> for CronjobsLister {
             let spec = cronjob.spec.unwrap_or_default();
             let schedule = spec.schedule;
             let timezone = spec.time_zone;
-            let created = item.created_at;
-            let age = age(created);
+            let status = si.cronjob_statuses.iter().flatten().find(|s| s.name == item.name);
             CronListItem {
                 namespace: item.name.namespace.clone(),
                 name: item.name.name.clone(),
                 schedule,
                 timezone: timezone.unwrap_or("<none>".to_string()),
-                suspend: "False".to_string(),
-                active: "-".to_string(),
-                last_schedule: "-".to_string(),
-                age,
+                suspend: match spec.suspend.unwrap_or_default() {
+                    true => "True".to_string(),
+                    false => "False".to_string(),
+                },
+                active: status.map(|s| (s.active as usize).to_string()).unwrap_or("-".to_string()),
+                last_schedule: status.and_then(|s| s.last_schedule).map(age).unwrap_or("-".to_string()),
+                age: age(item.created_at),
             }
         }).collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::batch::v1::{CronJob, CronJobSpec};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use crate::filestore::ObjectListItem;
+    use crate::get::cronjob::CronjobsLister;
+    use crate::get::Lister;
+    use crate::skatelet::SystemInfo;
+    use crate::util::NamespacedName;
+
+    #[test]
+    fn test_list_suspended() {
+        let cronjob = |name: &str, suspend: Option<bool>| {
+            let cronjob = CronJob {
+                metadata: ObjectMeta::from(NamespacedName::new(name, "shop")),
+                spec: Some(CronJobSpec { schedule: "0 * * * *".to_string(), suspend, ..Default::default() }),
+                status: None,
+            };
+            let mut item = ObjectListItem::from(&cronjob);
+            item.manifest = Some(serde_yaml::to_value(&cronjob).unwrap());
+            item
+        };
+        let si = SystemInfo { cronjobs: Some(vec![cronjob("backup", Some(true)), cronjob("report", None)]), ..Default::default() };
+
+        let listed: Vec<_> = CronjobsLister {}.selector(&si, "shop", "").into_iter().map(|c| (c.name, c.suspend)).collect();
+        assert_eq!(vec![("backup".to_string(), "True".to_string()), ("report".to_string(), "False".to_string())], listed);
+    }
+}
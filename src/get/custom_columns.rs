@@ -0,0 +1,186 @@
+use anyhow::anyhow;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tabled::builder::Builder;
+use tabled::settings::Style;
+use crate::errors::SkateError;
+use crate::get::lister::NameFilters;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+    // [*], every element
+    All,
+}
+
+// a column of -o custom-columns, eg NAME:.metadata.name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub header: String,
+    path: Vec<Segment>,
+}
+
+// the subset of jsonpath kubectl's custom columns mostly get used with: .a.b, .a[0], .a[*].b and ['skate.io/name'] or
+// skate\.io/name for keys with dots. the braces around the path are optional
+fn parse_path(path: &str) -> Result<Vec<Segment>, String> {
+    let path = path.trim();
+    let path = path.strip_prefix('{').and_then(|p| p.strip_suffix('}')).unwrap_or(path);
+    let mut segments = vec![];
+    let mut chars = path.chars().peekable();
+    let mut key = String::new();
+    let push_key = |key: &mut String, segments: &mut Vec<Segment>| {
+        if !key.is_empty() {
+            segments.push(Segment::Key(std::mem::take(key)));
+        }
+    };
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => key.push(chars.next().ok_or(format!("invalid path {}, it ends with \\", path))?),
+            '.' => push_key(&mut key, &mut segments),
+            '[' => {
+                push_key(&mut key, &mut segments);
+                let mut inner = String::new();
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some(c) => inner.push(c),
+                        None => return Err(format!("invalid path {}, missing ]", path)),
+                    }
+                }
+                let quoted = inner.strip_prefix('\'').and_then(|i| i.strip_suffix('\''))
+                    .or(inner.strip_prefix('"').and_then(|i| i.strip_suffix('"')));
+                segments.push(match (quoted, inner.as_str()) {
+                    (Some(quoted), _) => Segment::Key(quoted.to_string()),
+                    (None, "*") => Segment::All,
+                    (None, index) => Segment::Index(index.parse().map_err(|_| format!("invalid path {}, [{}] isn't an index, * or a quoted key", path, index))?),
+                });
+            }
+            c => key.push(c),
+        }
+    }
+    push_key(&mut key, &mut segments);
+    if segments.is_empty() {
+        return Err(format!("invalid path {:?}", path));
+    }
+    Ok(segments)
+}
+
+// parses NAME:.metadata.name,READY:.ready
+pub fn parse(spec: &str) -> Result<Vec<Column>, String> {
+    let columns: Vec<_> = spec.split(',').filter(|c| !c.trim().is_empty()).map(|column| {
+        let (header, path) = column.split_once(':').ok_or(format!("invalid column {}, expected <header>:<path>", column))?;
+        if header.trim().is_empty() {
+            return Err(format!("invalid column {}, missing the header", column));
+        }
+        Ok(Column { header: header.trim().to_string(), path: parse_path(path)? })
+    }).collect::<Result<_, _>>()?;
+    if columns.is_empty() {
+        return Err("custom-columns needs at least one column, eg custom-columns=NAME:.metadata.name".to_string());
+    }
+    Ok(columns)
+}
+
+fn extract<'a>(value: &'a Value, path: &[Segment]) -> Vec<&'a Value> {
+    let (segment, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return vec![value],
+    };
+    match (segment, value) {
+        (Segment::Key(key), Value::Object(map)) => map.get(key).map(|v| extract(v, rest)).unwrap_or_default(),
+        (Segment::Index(i), Value::Array(items)) => items.get(*i).map(|v| extract(v, rest)).unwrap_or_default(),
+        (Segment::All, Value::Array(items)) => items.iter().flat_map(|v| extract(v, rest)).collect(),
+        _ => vec![],
+    }
+}
+
+// strings as they are and anything else as json, several values separated by commas as kubectl has them
+fn cell(values: Vec<&Value>) -> String {
+    let values: Vec<_> = values.into_iter().filter(|v| !v.is_null()).map(|v| match v {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }).collect();
+    match values.is_empty() {
+        true => "<none>".to_string(),
+        false => values.join(","),
+    }
+}
+
+// what the paths are looked up in: the stored manifest when there is one, the metadata every resource has, and the
+// lister's columns under their field names, eg .ready, where the manifest has nothing by that name
+pub(crate) fn document<T: Serialize + NameFilters>(item: &T, manifest: Option<&serde_yaml::Value>) -> Result<Value, SkateError> {
+    let mut document = match manifest.map(serde_json::to_value).transpose().map_err(|e| anyhow!(e).context("failed to serialize manifest"))? {
+        Some(Value::Object(map)) => map,
+        _ => Map::new(),
+    };
+    let metadata = document.entry("metadata").or_insert(Value::Object(Map::new()));
+    if let Value::Object(metadata) = metadata {
+        metadata.entry("name").or_insert(Value::from(item.name()));
+        if !item.namespace().is_empty() {
+            metadata.entry("namespace").or_insert(Value::from(item.namespace()));
+        }
+        let labels = item.labels();
+        if !labels.is_empty() {
+            metadata.entry("labels").or_insert(serde_json::to_value(labels).map_err(|e| anyhow!(e))?);
+        }
+    }
+    if let Value::Object(fields) = serde_json::to_value(item).map_err(|e| anyhow!(e).context("failed to serialize item"))? {
+        for (k, v) in fields {
+            document.entry(k).or_insert(v);
+        }
+    }
+    Ok(Value::Object(document))
+}
+
+// one row per document, the columns as wide as their longest value like the other tables
+pub(crate) fn render(documents: &[Value], columns: &[Column], no_headers: bool) -> String {
+    let mut builder = Builder::default();
+    if !no_headers {
+        builder.push_record(columns.iter().map(|c| c.header.clone()));
+    }
+    for document in documents {
+        builder.push_record(columns.iter().map(|c| cell(extract(document, &c.path))));
+    }
+    let mut table = builder.build();
+    table.with(Style::empty());
+    table.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::get::custom_columns::{extract, parse, parse_path, render, Segment};
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(vec![Segment::Key("metadata".to_string()), Segment::Key("name".to_string())], parse_path("{.metadata.name}").unwrap());
+        assert_eq!(vec![
+            Segment::Key("spec".to_string()), Segment::Key("containers".to_string()), Segment::All, Segment::Key("image".to_string()),
+        ], parse_path(".spec.containers[*].image").unwrap());
+        assert_eq!(parse_path(".metadata.labels['skate.io/name']").unwrap(), parse_path(r".metadata.labels.skate\.io/name").unwrap());
+        assert_eq!(Segment::Index(0), parse_path(".items[0]").unwrap()[1]);
+        assert!(parse_path(".items[first]").is_err());
+        assert!(parse_path(".items[0").is_err());
+        assert!(parse_path(".").is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let columns = parse("NAME:.metadata.name,IMAGES:.spec.containers[*].image,REPLICAS:.spec.replicas,ARCH:.metadata.labels['skate.io/arch']").unwrap();
+        let documents = vec![
+            json!({"metadata": {"name": "web", "labels": {"skate.io/arch": "amd64"}}, "spec": {"replicas": 3, "containers": [{"image": "nginx"}, {"image": "envoy"}]}}),
+            json!({"metadata": {"name": "db"}, "spec": {"containers": [{"image": "postgres"}]}}),
+        ];
+        let lines = |s: String| s.lines().map(|l| l.trim_end().to_string()).collect::<Vec<_>>();
+        assert_eq!(vec![
+            " NAME  IMAGES       REPLICAS  ARCH",
+            " web   nginx,envoy  3         amd64",
+            " db    postgres     <none>    <none>",
+        ], lines(render(&documents, &columns, false)));
+        assert_eq!(1, extract(&documents[0], &parse_path(".spec.containers[1]").unwrap()).len());
+
+        assert!(parse("NAME").is_err());
+        assert!(parse(":.metadata.name").is_err());
+        assert!(parse("").is_err());
+    }
+}
@@ -1,17 +1,20 @@
 use std::collections::HashMap;
 use chrono::Local;
 use itertools::Itertools;
+use serde::Serialize;
 use tabled::Tabled;
 use crate::get::{GetObjectArgs, Lister};
 use crate::get::lister::NameFilters;
-use crate::skatelet::system::podman::{PodmanPodInfo, PodmanPodStatus};
+use crate::skatelet::system::podman::{PodPhase, PodmanPodInfo};
 use crate::state::state::ClusterState;
-use crate::util::age;
+use crate::util::{age, NamespacedName};
+use crate::resource::ResourceType;
 
 pub(crate) struct DaemonsetLister {}
 
-#[derive(Tabled)]
+#[derive(Tabled, Serialize)]
 #[tabled(rename_all = "UPPERCASE")]
+#[serde(rename_all = "camelCase")]
 pub struct DaemonsetListItem {
     pub namespace: String,
     pub name: String,
@@ -35,6 +38,10 @@ impl NameFilters for DaemonsetListItem {
 }
 
 impl Lister<DaemonsetListItem> for DaemonsetLister {
+    fn resource_type(&self) -> Option<ResourceType> {
+        Some(ResourceType::DaemonSet)
+    }
+
     fn list(&self, args: &GetObjectArgs, state: &ClusterState) -> Vec<DaemonsetListItem> {
         let pods = state.nodes.iter().filter_map(|n| {
             let items: Vec<_> = n.host_info.clone()?.system_info?.pods.unwrap_or_default().into_iter().filter_map(|p| {
@@ -43,13 +50,8 @@ impl Lister<DaemonsetListItem> for DaemonsetLister {
                     return None;
                 }
 
-                let res = {
-                    let pref = &p;
-                    pref.filter_names(&args.id.clone().unwrap_or_default(), &args.namespace.clone().unwrap_or_default())
-                }; if res {
-                    return Some((state.nodes.len(), daemonset, p));
-                }
-                None
+                let pod_ns = p.labels.get("skate.io/namespace").unwrap_or(&"default".to_string()).clone();
+                Some((NamespacedName::new(&daemonset, &pod_ns), p))
             }).collect();
             match items.len() {
                 0 => None,
@@ -57,13 +59,13 @@ impl Lister<DaemonsetListItem> for DaemonsetLister {
             }
         }).flatten();
 
-        let pods = pods.fold(HashMap::<String, Vec<PodmanPodInfo>>::new(), |mut acc, (_num_nodes, depl, pod)| {
+        let pods = pods.fold(HashMap::<NamespacedName, Vec<PodmanPodInfo>>::new(), |mut acc, (depl, pod)| {
             acc.entry(depl).or_default().push(pod);
             acc
         });
 
         pods.iter().map(|(n, pods)| {
-            let health_pods = pods.iter().filter(|p| PodmanPodStatus::Running == p.status).collect_vec().len();
+            let health_pods = pods.iter().filter(|p| p.phase() == PodPhase::Running).collect_vec().len();
             let _all_pods = pods.len();
             let created = pods.iter().fold(Local::now(), |acc, item| {
                 if item.created < acc {
@@ -71,12 +73,11 @@ impl Lister<DaemonsetListItem> for DaemonsetLister {
                 }
                 acc
             });
-            let namespace = pods.first().unwrap().labels.get("skate.io/namespace").unwrap_or(&"default".to_string()).clone();
             let node_selector = pods.first().unwrap().labels.iter().filter(|(k, _)| k.starts_with("nodeselector/")).map(|(k, _v)| k.clone()).collect_vec().join(",");
             
             DaemonsetListItem {
-                namespace,
-                name: n.clone(),
+                namespace: n.namespace.clone(),
+                name: n.name.clone(),
                 desired: state.nodes.len().to_string(),
                 current: pods.len().to_string(),
                 ready: health_pods.to_string(),
@@ -85,6 +86,7 @@ impl Lister<DaemonsetListItem> for DaemonsetLister {
                 node_selector,
                 age: age(created),
             }
-        }).collect()
+        // the name given is the daemonset's, not its pods'
+        }).filter(|d| d.filter_names(&args.id.clone().unwrap_or_default(), &args.namespace_filter())).collect()
     }
 }
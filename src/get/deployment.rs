@@ -1,17 +1,22 @@
 use std::collections::HashMap;
 use chrono::Local;
 use itertools::Itertools;
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
 use crate::get::{GetObjectArgs, Lister};
 use crate::get::lister::NameFilters;
-use crate::skatelet::system::podman::{PodmanPodInfo, PodmanPodStatus};
+use crate::skatelet::system::podman::{PodPhase, PodmanPodInfo};
 use crate::state::state::ClusterState;
 use crate::util::{age, NamespacedName};
+use crate::resource::ResourceType;
+use serde::Serialize;
 use tabled::Tabled;
 
 pub(crate) struct DeploymentLister {}
 
-#[derive(Tabled)]
+#[derive(Tabled, Serialize)]
 #[tabled(rename_all = "UPPERCASE")]
+#[serde(rename_all = "camelCase")]
 pub struct DeploymentListItem {
     pub namespace: String,
     pub name: String,
@@ -19,6 +24,39 @@ pub struct DeploymentListItem {
     pub up_to_date: String,
     pub available: String,
     pub age: String,
+    pub containers: String,
+    pub images: String,
+    pub selector: String,
+}
+
+// as kubectl writes selectors, eg app=web,tier in (frontend)
+fn format_selector(selector: &LabelSelector) -> String {
+    let labels = selector.match_labels.iter().flatten().map(|(k, v)| format!("{}={}", k, v));
+    let expressions = selector.match_expressions.iter().flatten().map(|e| {
+        let values = e.values.clone().unwrap_or_default().join(",");
+        match e.operator.as_str() {
+            "In" => format!("{} in ({})", e.key, values),
+            "NotIn" => format!("{} notin ({})", e.key, values),
+            "DoesNotExist" => format!("!{}", e.key),
+            _ => e.key.clone(),
+        }
+    });
+    labels.chain(expressions).join(",")
+}
+
+// the containers, images and selector of the deployment's stored manifest
+fn manifest_columns(deployment: Option<&Deployment>) -> (String, String, String) {
+    let spec = deployment.and_then(|d| d.spec.as_ref());
+    let containers = spec.and_then(|s| s.template.spec.as_ref()).map(|s| s.containers.clone()).unwrap_or_default();
+    let or_none = |s: String| match s.is_empty() {
+        true => "<none>".to_string(),
+        false => s,
+    };
+    (
+        or_none(containers.iter().map(|c| c.name.clone()).join(",")),
+        or_none(containers.iter().map(|c| c.image.clone().unwrap_or_default()).join(",")),
+        or_none(spec.map(|s| format_selector(&s.selector)).unwrap_or_default()),
+    )
 }
 
 impl NameFilters for DeploymentListItem {
@@ -32,6 +70,14 @@ impl NameFilters for DeploymentListItem {
 }
 
 impl Lister<DeploymentListItem> for DeploymentLister {
+    fn resource_type(&self) -> Option<ResourceType> {
+        Some(ResourceType::Deployment)
+    }
+
+    fn wide_columns(&self) -> &'static [&'static str] {
+        &["CONTAINERS", "IMAGES", "SELECTOR"]
+    }
+
     fn list(&self, args: &GetObjectArgs, state: &ClusterState) -> Vec<DeploymentListItem> {
         let pods = state.nodes.iter().filter_map(|n| {
             let items: Vec<_> = n.host_info.clone()?.system_info?.pods.unwrap_or_default().into_iter().filter_map(|p| {
@@ -40,14 +86,8 @@ impl Lister<DeploymentListItem> for DeploymentLister {
                     return None;
                 }
 
-                let res = {
-                    let pref = &p;
-                    pref.filter_names(&args.id.clone().unwrap_or_default(), &args.namespace.clone().unwrap_or_default())
-                }; if res {
-                    let pod_ns = p.labels.get("skate.io/namespace").unwrap_or(&"default".to_string()).clone();
-                    return Some((NamespacedName::from(format!("{}.{}", deployment, pod_ns).as_str()), p));
-                }
-                None
+                let pod_ns = p.labels.get("skate.io/namespace").unwrap_or(&"default".to_string()).clone();
+                Some((NamespacedName::from(format!("{}.{}", deployment, pod_ns).as_str()), p))
             }).collect();
             match items.len() {
                 0 => None,
@@ -60,8 +100,13 @@ impl Lister<DeploymentListItem> for DeploymentLister {
             acc
         });
 
+        // every node has the manifest, any one of them will do
+        let manifests: HashMap<NamespacedName, Deployment> = state.catalogue(None, &[ResourceType::Deployment]).into_iter()
+            .filter_map(|item| Some((item.object.name.clone(), serde_yaml::from_value(item.object.manifest.clone()?).ok()?)))
+            .collect();
+
         grouped.iter().map(|(name, pods)| {
-            let health_pods = pods.iter().filter(|p| PodmanPodStatus::Running == p.status).collect_vec().len();
+            let health_pods = pods.iter().filter(|p| p.phase() == PodPhase::Running).collect_vec().len();
             let all_pods = pods.len();
             let created = pods.iter().fold(Local::now(), |acc, item| {
                 if item.created < acc {
@@ -72,6 +117,7 @@ impl Lister<DeploymentListItem> for DeploymentLister {
 
             let its_age = age(created);
             let healthy = format!("{}/{}", health_pods, all_pods);
+            let (containers, images, selector) = manifest_columns(manifests.get(name));
             DeploymentListItem {
                 namespace: name.namespace.clone(),
                 name: name.name.clone(),
@@ -79,7 +125,104 @@ impl Lister<DeploymentListItem> for DeploymentLister {
                 up_to_date: all_pods.to_string(),
                 available: health_pods.to_string(),
                 age: its_age,
+                containers,
+                images,
+                selector,
             }
-        }).collect()
+        // the name given is the deployment's, not its pods'
+        }).filter(|d| d.filter_names(&args.id.clone().unwrap_or_default(), &args.namespace_filter())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use k8s_openapi::api::apps::v1::{Deployment, DeploymentSpec};
+    use k8s_openapi::api::core::v1::{Container, PodSpec, PodTemplateSpec};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, LabelSelectorRequirement, ObjectMeta};
+    use crate::filestore::ObjectListItem;
+    use crate::get::deployment::DeploymentLister;
+    use crate::get::lister::Lister;
+    use crate::get::{GetObjectArgs, OutputFormat};
+    use crate::skate::ConfigFileArgs;
+    use crate::test_helpers::objects::{cluster_state, node_state, pod, WithPod};
+    use crate::util::NamespacedName;
+
+    #[test]
+    fn test_list_by_name_and_namespace() {
+        let node = node_state("node-1")
+            .with_pod(&pod("web-1", "shop", &[("skate.io/deployment", "web")]))
+            .with_pod(&pod("web-2", "blog", &[("skate.io/deployment", "web")]))
+            .with_pod(&pod("api-1", "shop", &[("skate.io/deployment", "api")]));
+        let state = cluster_state(vec![node]);
+        let args = |id: Option<&str>, namespace: Option<&str>| GetObjectArgs {
+            config: ConfigFileArgs { skateconfig: None, context: None },
+            namespace: namespace.map(|n| n.to_string()),
+            all_namespaces: false,
+            id: id.map(|i| i.to_string()),
+            field_selector: None,
+            output: OutputFormat::Table,
+            export: false,
+            no_headers: false,
+            show_labels: false,
+            label_columns: vec![],
+        };
+        let listed = |id, namespace| {
+            let mut names: Vec<_> = DeploymentLister {}.list(&args(id, namespace), &state).into_iter().map(|d| format!("{}.{}", d.name, d.namespace)).collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(vec!["api.shop", "web.blog", "web.shop"], listed(None, None));
+        assert_eq!(vec!["web.blog", "web.shop"], listed(Some("web"), None));
+        assert_eq!(vec!["web.shop"], listed(Some("web"), Some("shop")));
+        assert!(listed(Some("web-1"), Some("shop")).is_empty());
+        assert!(listed(Some("api"), Some("blog")).is_empty());
+    }
+
+    #[test]
+    fn test_list_wide_columns() {
+        let mut node = node_state("node-1")
+            .with_pod(&pod("web-1", "shop", &[("skate.io/deployment", "web")]))
+            .with_pod(&pod("api-1", "shop", &[("skate.io/deployment", "api")]));
+
+        let container = |name: &str, image: &str| Container { name: name.to_string(), image: Some(image.to_string()), ..Default::default() };
+        let deployment = Deployment {
+            metadata: ObjectMeta::from(NamespacedName::new("web", "shop")),
+            spec: Some(DeploymentSpec {
+                selector: LabelSelector {
+                    match_labels: Some(BTreeMap::from([("app".to_string(), "web".to_string())])),
+                    match_expressions: Some(vec![LabelSelectorRequirement { key: "tier".to_string(), operator: "In".to_string(), values: Some(vec!["frontend".to_string(), "edge".to_string()]) }]),
+                },
+                template: PodTemplateSpec {
+                    spec: Some(PodSpec { containers: vec![container("nginx", "nginx:1.27"), container("exporter", "nginx/nginx-prometheus-exporter:1.3")], ..Default::default() }),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        node.host_info.as_mut().unwrap().system_info.as_mut().unwrap().deployments = Some(vec![ObjectListItem::from(&deployment)]);
+        let state = cluster_state(vec![node]);
+
+        let args = GetObjectArgs {
+            config: ConfigFileArgs { skateconfig: None, context: None },
+            namespace: None,
+            all_namespaces: false,
+            id: None,
+            field_selector: None,
+            output: OutputFormat::Wide,
+            export: false,
+            no_headers: false,
+            show_labels: false,
+            label_columns: vec![],
+        };
+        let mut listed = DeploymentLister {}.list(&args, &state);
+        listed.sort_by_key(|d| d.name.clone());
+
+        assert_eq!(("api", "<none>", "<none>", "<none>"), (listed[0].name.as_str(), listed[0].containers.as_str(), listed[0].images.as_str(), listed[0].selector.as_str()));
+        assert_eq!("nginx,exporter", listed[1].containers);
+        assert_eq!("nginx:1.27,nginx/nginx-prometheus-exporter:1.3", listed[1].images);
+        assert_eq!("app=web,tier in (frontend,edge)", listed[1].selector);
     }
 }
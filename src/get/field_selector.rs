@@ -0,0 +1,107 @@
+use anyhow::anyhow;
+use crate::errors::SkateError;
+use crate::get::lister::NameFilters;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Operator {
+    Equals,
+    NotEquals,
+    // numeric, for fields such as status.restartCount
+    GreaterThan,
+    LessThan,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Requirement {
+    pub field: String,
+    pub operator: Operator,
+    pub value: String,
+}
+
+// parses selectors like status.phase=Running,spec.nodeName!=node-1, every requirement has to match
+pub(crate) fn parse(selector: &str) -> Result<Vec<Requirement>, SkateError> {
+    selector.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()).map(|requirement| {
+        // longest operators first, so == isn't read as =
+        let (field, operator, value) = [("!=", Operator::NotEquals), ("==", Operator::Equals), ("=", Operator::Equals), (">", Operator::GreaterThan), ("<", Operator::LessThan)]
+            .into_iter()
+            .find_map(|(op, operator)| requirement.split_once(op).map(|(f, v)| (f.trim(), operator, v.trim())))
+            .ok_or(anyhow!("invalid field selector {}, expected <field>=<value>, !=, > or <", requirement))?;
+        if field.is_empty() {
+            return Err(anyhow!("invalid field selector {}, missing the field", requirement).into());
+        }
+        if matches!(operator, Operator::GreaterThan | Operator::LessThan) && value.parse::<f64>().is_err() {
+            return Err(anyhow!("invalid field selector {}, {} needs a number", requirement, field).into());
+        }
+        Ok(Requirement { field: field.to_string(), operator, value: value.to_string() })
+    }).collect()
+}
+
+fn field_value<T: NameFilters>(item: &T, field: &str) -> Option<String> {
+    match field {
+        "metadata.name" => Some(item.name()),
+        "metadata.namespace" => Some(item.namespace()),
+        _ => item.field(field),
+    }
+}
+
+fn requirement_matches<T: NameFilters>(item: &T, requirement: &Requirement) -> Result<bool, SkateError> {
+    let value = field_value(item, &requirement.field)
+        .ok_or(anyhow!("field selector {} is not supported for this resource", requirement.field))?;
+    let number = |v: &str| v.parse::<f64>().ok();
+    Ok(match requirement.operator {
+        Operator::Equals => value == requirement.value,
+        Operator::NotEquals => value != requirement.value,
+        Operator::GreaterThan => number(&value).zip(number(&requirement.value)).is_some_and(|(v, r)| v > r),
+        Operator::LessThan => number(&value).zip(number(&requirement.value)).is_some_and(|(v, r)| v < r),
+    })
+}
+
+pub(crate) fn filter<T: NameFilters>(items: Vec<T>, requirements: &[Requirement]) -> Result<Vec<T>, SkateError> {
+    let mut selected = vec![];
+    for item in items {
+        if requirements.iter().map(|r| requirement_matches(&item, r)).collect::<Result<Vec<_>, _>>()?.into_iter().all(|m| m) {
+            selected.push(item);
+        }
+    }
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::get::field_selector::{filter, parse, Operator};
+    use crate::get::lister::NameFilters;
+
+    struct Item(&'static str, &'static str, usize);
+
+    impl NameFilters for Item {
+        fn name(&self) -> String {
+            self.0.to_string()
+        }
+        fn namespace(&self) -> String {
+            "shop".to_string()
+        }
+        fn field(&self, field: &str) -> Option<String> {
+            match field {
+                "status.phase" => Some(self.1.to_string()),
+                "status.restartCount" => Some(self.2.to_string()),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_filter() {
+        let items = || vec![Item("web", "Running", 0), Item("db", "Running", 3), Item("job", "Succeeded", 0)];
+        let names = |selector: &str| filter(items(), &parse(selector).unwrap()).unwrap().into_iter().map(|i| i.0).collect::<Vec<_>>();
+
+        assert_eq!(vec!["web", "db"], names("status.phase=Running"));
+        assert_eq!(vec!["db"], names("status.phase==Running, status.restartCount>0"));
+        assert_eq!(vec!["job"], names("status.phase!=Running"));
+        assert_eq!(vec!["web"], names("metadata.name=web,metadata.namespace=shop"));
+        assert_eq!(Operator::LessThan, parse("status.restartCount<2").unwrap()[0].operator);
+
+        assert!(parse("status.phase").is_err());
+        assert!(parse("status.restartCount>lots").is_err());
+        assert!(filter(items(), &parse("spec.unknown=x").unwrap()).is_err());
+    }
+}
@@ -1,16 +1,19 @@
 
 
 use k8s_openapi::api::networking::v1::Ingress;
+use serde::Serialize;
 use tabled::Tabled;
 use crate::get::{Lister};
 use crate::get::lister::NameFilters;
 use crate::skatelet::SystemInfo;
 use crate::util::age;
+use crate::resource::ResourceType;
 
 pub(crate) struct IngressLister {}
 
-#[derive(Tabled)]
+#[derive(Tabled, Serialize)]
 #[tabled(rename_all = "UPPERCASE")]
+#[serde(rename_all = "camelCase")]
 pub struct IngressListItem {
     pub namespace: String,
     pub name: String,
@@ -32,6 +35,10 @@ impl NameFilters for IngressListItem {
 }
 
 impl Lister<IngressListItem> for IngressLister {
+    fn resource_type(&self) -> Option<ResourceType> {
+        Some(ResourceType::Ingress)
+    }
+
     fn selector(&self, si: &SystemInfo, ns: &str, id: &str) -> Vec<IngressListItem> {
         si.ingresses.as_ref().unwrap_or(&vec!()).iter().filter(|j| {
             j.filter_names(id, ns)
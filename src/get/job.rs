@@ -0,0 +1,50 @@
+use serde::Serialize;
+use tabled::Tabled;
+use crate::get::Lister;
+use crate::get::lister::NameFilters;
+use crate::skatelet::SystemInfo;
+use crate::skatelet::system::podman::PodPhase;
+use crate::util::age;
+
+pub(crate) struct JobLister {}
+
+#[derive(Tabled, Serialize)]
+#[tabled(rename_all = "UPPERCASE")]
+#[serde(rename_all = "camelCase")]
+pub struct JobListItem {
+    pub namespace: String,
+    pub name: String,
+    pub cronjob: String,
+    pub status: String,
+    pub completions: String,
+    pub age: String,
+}
+
+impl NameFilters for JobListItem {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn namespace(&self) -> String {
+        self.namespace.clone()
+    }
+}
+
+// a job is the pod a cronjob last ran, which is replaced on every run
+impl Lister<JobListItem> for JobLister {
+    fn selector(&self, si: &SystemInfo, ns: &str, id: &str) -> Vec<JobListItem> {
+        si.pods.iter().flatten().filter(|p| !p.cronjob().is_empty()).map(|pod| {
+            let namespace = pod.namespace();
+            let name = pod.name.strip_suffix(&format!(".{}", namespace)).unwrap_or(&pod.name).to_string();
+            let phase = pod.phase();
+            JobListItem {
+                completions: format!("{}/1", (phase == PodPhase::Succeeded) as usize),
+                status: phase.to_string(),
+                namespace,
+                name,
+                cronjob: pod.cronjob(),
+                age: age(pod.created),
+            }
+        }).filter(|j| j.filter_names(id, ns)).collect()
+    }
+}
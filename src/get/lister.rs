@@ -1,18 +1,37 @@
+use std::collections::BTreeMap;
 use itertools::Itertools;
 use tabled::Tabled;
 use crate::filestore::ObjectListItem;
+use crate::resource::ResourceType;
 use crate::get::{GetObjectArgs};
 use crate::skatelet::{SystemInfo};
 use crate::state::state::ClusterState;
 
+// the namespace filter for every namespace, without hiding skate's own
+pub(crate) const ALL_NAMESPACES: &str = "*";
+
 pub(crate) trait NameFilters {
     fn id(&self) -> String {
         self.name()
     }
     fn name(&self) -> String;
     fn namespace(&self) -> String;
+    // what -L and --show-labels print
+    fn labels(&self) -> BTreeMap<String, String> {
+        BTreeMap::new()
+    }
+    // the value of a field selector's field, besides metadata.name and metadata.namespace
+    fn field(&self, _field: &str) -> Option<String> {
+        None
+    }
+    // the name given on the command line can be either the id or the name
+    fn matches_name(&self, name: &str) -> bool {
+        name.is_empty() || self.id() == name || self.name() == name
+    }
+    // both the namespace and the name have to match when given
     fn filter_names(&self, name: &str, ns: &str) -> bool {
-        let ns = match ns.is_empty() {
+        let all_namespaces = ns == ALL_NAMESPACES;
+        let ns = match all_namespaces {
             true => "",
             false => ns
         };
@@ -20,10 +39,10 @@ pub(crate) trait NameFilters {
         if !ns.is_empty() && self.namespace() != ns {
             return false;
         }
-        if !name.is_empty() && (self.id() != name || self.name() != name) {
+        if !self.matches_name(name) {
             return false;
         }
-        if ns.is_empty() && name.is_empty() && self.namespace() == "skate" {
+        if !all_namespaces && ns.is_empty() && name.is_empty() && self.namespace() == "skate" {
             return false;
         }
         true
@@ -35,7 +54,7 @@ impl NameFilters for &ObjectListItem {
         self.name.to_string()
     }
     fn name(&self) -> String {
-        self.name.to_string()
+        self.name.name.clone()
     }
 
     fn namespace(&self) -> String {
@@ -44,6 +63,16 @@ impl NameFilters for &ObjectListItem {
 }
 
 pub(crate) trait Lister<T> {
+    // the type of resource listed, for those that have stored manifests
+    fn resource_type(&self) -> Option<ResourceType> {
+        None
+    }
+
+    // the columns only shown with -o wide
+    fn wide_columns(&self) -> &'static [&'static str] {
+        &[]
+    }
+
     // selects data from each node
     fn selector(&self, _si: &SystemInfo, _ns: &str, _id: &str) -> Vec<T>
     where
@@ -57,7 +86,7 @@ pub(crate) trait Lister<T> {
     where
         T: Tabled + NameFilters,
     {
-        let ns = filters.namespace.clone().unwrap_or_default();
+        let ns = filters.namespace_filter();
         let id = filters.id.clone().unwrap_or("".to_string());
 
 
@@ -76,3 +105,47 @@ pub(crate) trait Lister<T> {
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use crate::get::lister::{NameFilters, ALL_NAMESPACES};
+
+    struct Item(&'static str, &'static str);
+
+    impl NameFilters for Item {
+        fn id(&self) -> String {
+            format!("{}.{}", self.0, self.1)
+        }
+        fn name(&self) -> String {
+            self.0.to_string()
+        }
+        fn namespace(&self) -> String {
+            self.1.to_string()
+        }
+    }
+
+    #[test]
+    fn test_filter_names() {
+        let web = Item("web", "shop");
+        assert!(web.filter_names("web", ""));
+        assert!(web.filter_names("web.shop", ""));
+        assert!(web.filter_names("web", "shop"));
+        assert!(web.filter_names("", "shop"));
+        // the name and namespace both have to match
+        assert!(!web.filter_names("web", "default"));
+        assert!(!web.filter_names("api", "shop"));
+        assert!(!web.filter_names("api", ""));
+        // skate's own are only hidden when listing everything
+        assert!(Item("coredns", "skate").filter_names("coredns", ""));
+    }
+
+    #[test]
+    fn test_filter_all_namespaces() {
+        let (web, dns) = (Item("web", "shop"), Item("coredns", "skate"));
+        assert!(web.filter_names("", ""));
+        assert!(!dns.filter_names("", ""));
+        assert!(web.filter_names("", ALL_NAMESPACES));
+        assert!(dns.filter_names("", ALL_NAMESPACES));
+        assert!(!web.filter_names("", "skate"));
+    }
+}
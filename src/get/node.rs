@@ -1,3 +1,7 @@
+use std::collections::BTreeMap;
+use itertools::Itertools;
+use k8s_openapi::api::core::v1::Node as K8sNode;
+use serde::Serialize;
 use tabled::Tabled;
 use crate::get::{GetObjectArgs, Lister};
 use crate::get::lister::NameFilters;
@@ -7,13 +11,16 @@ use crate::state::state::ClusterState;
 
 pub(crate) struct NodeLister {}
 
-#[derive(Tabled)]
+#[derive(Tabled, Serialize)]
 #[tabled(rename_all = "UPPERCASE")]
+#[serde(rename_all = "camelCase")]
 pub struct NodeListItem {
     pub name: String,
     pub pods: String,
     pub status: String,
     pub message: String,
+    #[tabled(skip)]
+    pub labels: BTreeMap<String, String>,
 }
 
 impl NameFilters for NodeListItem {
@@ -24,6 +31,10 @@ impl NameFilters for NodeListItem {
     fn namespace(&self) -> String {
         "".to_string()
     }
+
+    fn labels(&self) -> BTreeMap<String, String> {
+        self.labels.clone()
+    }
 }
 
 
@@ -33,7 +44,7 @@ impl Lister<NodeListItem> for NodeLister {
     }
 
     fn list(&self, filters: &GetObjectArgs, state: &ClusterState) -> Vec<NodeListItem> {
-        state.nodes.iter().filter(|n| filters.id.is_none() || filters.id.clone().unwrap() == n.node_name).map(|n| {
+        state.nodes.iter().map(|n| {
             let num_pods = match n.host_info.as_ref() {
                 Some(hi) => match hi.system_info.as_ref() {
                     Some(si) => match si.pods.as_ref() {
@@ -44,13 +55,23 @@ impl Lister<NodeListItem> for NodeLister {
                 }
                 _ => 0
             };
+            let status = [
+                Some(n.status.to_string()),
+                n.disk_pressure().map(|_| "DiskPressure".to_string()),
+                n.memory_pressure().map(|_| "MemoryPressure".to_string()),
+            ].into_iter().flatten().join(",");
+
+            // as the node selectors see them
+            let labels = K8sNode::from(n.clone()).metadata.labels.unwrap_or_default();
+
             NodeListItem {
                 name: n.node_name.clone(),
                 pods: num_pods.to_string(),
-                status: n.status.to_string(),
-                message: n.message.clone().unwrap_or_default(),
+                status,
+                message: n.message.iter().cloned().chain(n.host_changes.iter().map(|c| format!("{} since {}", c, c.seen_at.format("%Y-%m-%d %H:%M")))).join(", "),
+                labels,
             }
-        }).collect()
+        }).filter(|n| n.filter_names(&filters.id.clone().unwrap_or_default(), "")).collect()
     }
 
 }
@@ -1,8 +1,12 @@
+use std::collections::BTreeMap;
+use itertools::Itertools;
+use serde::Serialize;
 use tabled::Tabled;
-use crate::get::Lister;
+use crate::get::{GetObjectArgs, Lister};
 use crate::get::lister::NameFilters;
 use crate::skatelet::SystemInfo;
 use crate::skatelet::system::podman::PodmanPodInfo;
+use crate::state::state::ClusterState;
 use crate::util::age;
 
 pub(crate) struct PodLister {}
@@ -20,8 +24,9 @@ impl NameFilters for &PodmanPodInfo {
     }
 }
 
-#[derive(Tabled)]
+#[derive(Tabled, Serialize)]
 #[tabled(rename_all = "UPPERCASE")]
+#[serde(rename_all = "camelCase")]
 pub struct PodListItem {
     pub namespace: String,
     pub name: String,
@@ -29,6 +34,10 @@ pub struct PodListItem {
     pub status: String,
     pub restarts: String,
     pub age: String,
+    #[tabled(skip)]
+    pub node: String,
+    #[tabled(skip)]
+    pub labels: BTreeMap<String, String>,
 }
 
 impl NameFilters for PodListItem {
@@ -39,29 +48,53 @@ impl NameFilters for PodListItem {
     fn namespace(&self) -> String {
         self.namespace.to_string()
     }
+
+    fn labels(&self) -> BTreeMap<String, String> {
+        self.labels.clone()
+    }
+
+    fn field(&self, field: &str) -> Option<String> {
+        match field {
+            "status.phase" => Some(self.status.clone()),
+            "spec.nodeName" => Some(self.node.clone()),
+            "status.restartCount" => Some(self.restarts.clone()),
+            _ => None,
+        }
+    }
 }
 
 
-impl Lister<PodListItem> for PodLister {
-    fn selector(&self, si: &SystemInfo, ns: &str, id: &str) -> Vec<PodListItem> {
-        si.pods.as_ref().unwrap_or(&vec!()).iter().filter(|p| {
-            p.filter_names(id, ns)
-        }).map(|pod| {
-            let num_containers = pod.containers.clone().unwrap_or_default().len();
-            let healthy_containers = pod.containers.clone().unwrap_or_default().iter().filter(|c| {
-                matches!(c.status.as_str(), "running")
-            }).collect::<Vec<_>>().len();
-            let restarts = pod.containers.clone().unwrap_or_default().iter().map(|c| c.restart_count.unwrap_or_default())
-                .reduce(|a, c| a + c).unwrap_or_default();
+// the pods on one node, which the field selectors can select by
+fn node_pods(si: &SystemInfo, node_name: &str, ns: &str, id: &str) -> Vec<PodListItem> {
+    si.pods.as_ref().unwrap_or(&vec!()).iter().filter(|p| {
+        p.filter_names(id, ns)
+    }).map(|pod| {
+        let containers = pod.app_containers();
+        let num_containers = containers.len();
+        let ready_containers = containers.iter().filter(|c| c.is_ready()).count();
+        let restarts: usize = containers.iter().map(|c| c.restart_count.unwrap_or_default()).sum();
+
+        PodListItem {
+            namespace: pod.namespace(),
+            name: pod.name(),
+            ready: format!("{}/{}", ready_containers, num_containers),
+            status: pod.phase().to_string(),
+            restarts: restarts.to_string(),
+            age: age(pod.created),
+            node: node_name.to_string(),
+            labels: pod.labels.clone(),
+        }
+    }).collect()
+}
 
-            PodListItem {
-                namespace: pod.namespace(),
-                name: pod.name(),
-                ready: format!("{}/{}", healthy_containers, num_containers),
-                status: pod.status.to_string(),
-                restarts: restarts.to_string(),
-                age: age(pod.created),
+impl Lister<PodListItem> for PodLister {
+    fn list(&self, filters: &GetObjectArgs, state: &ClusterState) -> Vec<PodListItem> {
+        let (ns, id) = (filters.namespace_filter(), filters.id.clone().unwrap_or_default());
+        state.nodes.iter().flat_map(|node| {
+            match node.host_info.as_ref().and_then(|hi| hi.system_info.as_ref()) {
+                Some(si) => node_pods(si, &node.node_name, &ns, &id),
+                None => vec![],
             }
-        }).collect()
+        }).unique_by(|i| format!("{}.{}", i.name, i.namespace)).collect()
     }
 }
@@ -1,15 +1,18 @@
 use k8s_openapi::api::core::v1::Secret;
+use serde::Serialize;
 use tabled::Tabled;
 use crate::get::{Lister};
 use crate::get::lister::NameFilters;
 use crate::skatelet::{SystemInfo};
 
 use crate::util::age;
+use crate::resource::ResourceType;
 
 pub(crate) struct SecretLister {}
 
-#[derive(Tabled)]
+#[derive(Tabled, Serialize)]
 #[tabled(rename_all = "UPPERCASE")]
+#[serde(rename_all = "camelCase")]
 pub struct SecretListItem {
     pub namespace: String,
     pub name: String,
@@ -28,6 +31,10 @@ impl NameFilters for SecretListItem {
 }
 
 impl Lister<SecretListItem> for SecretLister {
+    fn resource_type(&self) -> Option<ResourceType> {
+        Some(ResourceType::Secret)
+    }
+
     fn selector(&self, si: &SystemInfo, ns: &str, id: &str) -> Vec<SecretListItem> {
         si.secrets.as_ref().unwrap_or(&vec!()).iter().filter(|j| {
             j.filter_names(id, ns)
@@ -1,14 +1,17 @@
 use k8s_openapi::api::core::v1::Service;
+use serde::Serialize;
 use tabled::Tabled;
 use crate::get::{Lister};
 use crate::get::lister::NameFilters;
 use crate::skatelet::SystemInfo;
 use crate::util::age;
+use crate::resource::ResourceType;
 
 pub(crate) struct ServiceLister {}
 
-#[derive(Tabled)]
+#[derive(Tabled, Serialize)]
 #[tabled(rename_all = "UPPERCASE")]
+#[serde(rename_all = "camelCase")]
 pub struct ServiceListItem {
     pub namespace: String,
     pub name: String,
@@ -30,6 +33,10 @@ impl NameFilters for ServiceListItem {
 
 
 impl Lister<ServiceListItem> for ServiceLister {
+    fn resource_type(&self) -> Option<ResourceType> {
+        Some(ResourceType::Service)
+    }
+
     fn selector(&self, si: &SystemInfo, ns: &str, id: &str) -> Vec<ServiceListItem> {
         si.services.as_ref().unwrap_or(&vec!()).iter().filter(|j| {
             j.filter_names(id, ns)
@@ -33,6 +33,11 @@ impl Client {
             .json::<Release>()
             .await
     }
+
+    // follows github's redirect to where the asset is stored
+    pub async fn download(&self, url: &str) -> Result<Vec<u8>, reqwest::Error> {
+        Ok(self.reqwest_client.get(url).send().await?.error_for_status()?.bytes().await?.to_vec())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -66,9 +71,7 @@ impl Release {
         
     }
     pub fn find_skatelet_archive(&self, platform: &Platform) -> Option<String> {
-        if self.assets.is_none() {
-            return None;
-        }
+        self.assets.as_ref()?;
         let asset = self.assets.as_ref().unwrap().iter().find(|asset| {
             let (dl_arch, dl_gnu) = match platform.arch.as_str() {
                 "amd64" => ("x86_64", "gnu"),
@@ -94,12 +97,10 @@ impl Release {
                 return false;
             }
 
-            return true;
+            true
         });
 
-        if asset.is_none() {
-            return None;
-        }
+        asset?;
 
         Some(asset.unwrap().browser_download_url.clone().unwrap())
     }
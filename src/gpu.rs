@@ -0,0 +1,262 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs;
+use anyhow::anyhow;
+use k8s_openapi::api::core::v1::{Container, HostPathVolumeSource, Pod, Volume, VolumeMount};
+use crate::skatelet::system::podman::{PodmanPodInfo, PodmanPodStatus};
+use crate::state::state::NodeState;
+
+// what a container puts in its resources.limits to be given gpus, eg skate.io/gpu: 1
+pub const GPU_RESOURCE: &str = "skate.io/gpu";
+// set by the skatelet to the indexes of the gpus it gave the pod, eg 0.2
+pub const GPU_DEVICES_LABEL: &str = "skate.io/gpu-devices";
+// the number of gpus the pod asked for, so a pod that's scheduled but hasn't been given its gpus yet still counts
+pub const GPU_REQUEST_LABEL: &str = "skate.io/gpus";
+// the cdi annotation prefix, the runtime gives the container the devices named in the annotation's value
+const CDI_ANNOTATION_PREFIX: &str = "cdi.k8s.io/";
+// where the nvidia container toolkit writes the node's cdi spec
+const CDI_SPEC_DIRS: &[&str] = &["/etc/cdi", "/var/run/cdi"];
+
+const INTEL_VENDOR_ID: &str = "0x8086";
+// shared by all of the node's nvidia gpus, so every container given one gets them too
+const NVIDIA_CONTROL_DEVICES: &[&str] = &["nvidiactl", "nvidia-uvm", "nvidia-uvm-tools"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpuDevice {
+    pub index: usize,
+    // the device nodes a container given the gpu gets
+    pub paths: Vec<String>,
+    // the gpu's cdi name when the node has a cdi spec for it, which also brings the driver's libraries
+    pub cdi: Option<String>,
+}
+
+// nvidia's /dev/nvidia<n> first, then intel's render nodes, from the names in /dev and each render node's pci vendor.
+// nvidia gpus are given by their cdi name when there's a cdi spec for them
+fn gpu_devices(dev_entries: &[String], render_nodes: &[(String, String)], nvidia_cdi: bool) -> Vec<GpuDevice> {
+    let control: Vec<_> = NVIDIA_CONTROL_DEVICES.iter()
+        .filter(|d| dev_entries.iter().any(|e| e == *d))
+        .map(|d| format!("/dev/{}", d))
+        .collect();
+    let mut nvidia: Vec<usize> = dev_entries.iter().filter_map(|e| e.strip_prefix("nvidia")?.parse().ok()).collect();
+    nvidia.sort();
+    let mut intel: Vec<_> = render_nodes.iter().filter(|(_, vendor)| vendor == INTEL_VENDOR_ID).map(|(name, _)| name.clone()).collect();
+    intel.sort();
+
+    let nvidia = nvidia.into_iter().map(|n| ([vec![format!("/dev/nvidia{}", n)], control.clone()].concat(), nvidia_cdi.then(|| format!("nvidia.com/gpu={}", n))));
+    let intel = intel.into_iter().map(|name| (vec![format!("/dev/dri/{}", name)], None));
+    nvidia.chain(intel).enumerate().map(|(index, (paths, cdi))| GpuDevice { index, paths, cdi }).collect()
+}
+
+pub fn detect_gpus() -> Vec<GpuDevice> {
+    let names = |dir: &str| fs::read_dir(dir).map(|d| d.filter_map(|e| e.ok()).map(|e| e.file_name().to_string_lossy().to_string()).collect::<Vec<_>>()).unwrap_or_default();
+    let render_nodes: Vec<_> = names("/sys/class/drm").into_iter()
+        .filter(|n| n.starts_with("renderD"))
+        .map(|n| {
+            let vendor = fs::read_to_string(format!("/sys/class/drm/{}/device/vendor", n)).unwrap_or_default().trim().to_string();
+            (n, vendor)
+        })
+        .collect();
+    let nvidia_cdi = CDI_SPEC_DIRS.iter().flat_map(|d| names(d)).any(|n| n.starts_with("nvidia"));
+    gpu_devices(&names("/dev"), &render_nodes, nvidia_cdi)
+}
+
+fn container_gpus(container: &Container) -> usize {
+    container.resources.as_ref().and_then(|r| {
+        r.limits.as_ref().and_then(|l| l.get(GPU_RESOURCE))
+            .or(r.requests.as_ref().and_then(|r| r.get(GPU_RESOURCE)))
+    }).and_then(|q| q.0.parse().ok()).unwrap_or_default()
+}
+
+// the gpus the pod's containers are limited to, or request when they have no limit
+pub fn gpu_request(pod: &Pod) -> usize {
+    pod.spec.iter().flat_map(|s| s.containers.iter()).map(container_gpus).sum()
+}
+
+fn assigned_gpus(pod: &PodmanPodInfo) -> Vec<usize> {
+    pod.labels.get(GPU_DEVICES_LABEL).map(|d| d.split('.').filter_map(|i| i.parse().ok()).collect()).unwrap_or_default()
+}
+
+// a pod that's created or stopped may still start again, and keeps its gpus until then
+fn holds_gpus(pod: &PodmanPodInfo) -> bool {
+    !matches!(pod.status, PodmanPodStatus::Exited | PodmanPodStatus::Dead)
+}
+
+// the gpus the pod was given, or asked for when it's only been scheduled
+fn pod_gpus(pod: &PodmanPodInfo) -> usize {
+    let requested = pod.labels.get(GPU_REQUEST_LABEL).and_then(|r| r.parse().ok()).unwrap_or_default();
+    assigned_gpus(pod).len().max(requested)
+}
+
+// why the node can't give the pod its gpus, if it can't
+pub fn insufficient_gpu(node: &NodeState, requested: usize) -> Option<String> {
+    if requested == 0 {
+        return None;
+    }
+    let total = node.host_info.as_ref()
+        .and_then(|h| h.system_info.as_ref())
+        .and_then(|i| i.resources.as_ref())
+        .and_then(|r| r.get(GPU_RESOURCE).copied())
+        .unwrap_or_default();
+    let used: usize = node.filter_pods(&holds_gpus).iter().map(pod_gpus).sum();
+    let free = total.saturating_sub(used);
+    (requested > free).then(|| format!("insufficient gpu: {} requested, {} of {} free", requested, free, total))
+}
+
+fn device_volume_name(path: &str) -> String {
+    format!("gpu-{}", path.trim_start_matches("/dev/").replace('/', "-").to_lowercase())
+}
+
+// gives each container asking for gpus its own, from those the node's other pods weren't given. gpus with a cdi name are
+// given through the cdi annotation, the rest as char device host paths, which podman kube play passes to the container as
+// devices. those need the image to bring the driver's libraries
+pub fn assign_gpus(pod: &mut Pod, devices: &[GpuDevice], running: &[PodmanPodInfo]) -> Result<(), Box<dyn Error>> {
+    let requested = gpu_request(pod);
+    if requested == 0 {
+        return Ok(());
+    }
+    let name = pod.metadata.name.clone().unwrap_or_default();
+    // a pod being replaced gives its gpus back
+    let used: BTreeSet<usize> = running.iter()
+        .filter(|p| holds_gpus(p) && p.name != name)
+        .flat_map(assigned_gpus)
+        .collect();
+    let free: Vec<_> = devices.iter().filter(|d| !used.contains(&d.index)).collect();
+    if requested > free.len() {
+        return Err(anyhow!("pod {} requests {} gpus, the node has {} of {} free", name, requested, free.len(), devices.len()).into());
+    }
+
+    let spec = pod.spec.as_mut().ok_or(anyhow!("pod {} has no spec", name))?;
+    let mut free = free.into_iter();
+    let mut assigned = vec![];
+    let mut volumes: Vec<Volume> = vec![];
+    let mut annotations = BTreeMap::new();
+    for container in spec.containers.iter_mut() {
+        let count = container_gpus(container);
+        // podman has no use for it
+        if let Some(resources) = container.resources.as_mut() {
+            resources.limits.iter_mut().chain(resources.requests.iter_mut()).for_each(|r| {
+                r.remove(GPU_RESOURCE);
+            });
+        }
+        let mut cdi = vec![];
+        for device in free.by_ref().take(count) {
+            assigned.push(device.index.to_string());
+            if let Some(name) = &device.cdi {
+                cdi.push(name.clone());
+                continue;
+            }
+            for path in device.paths.iter() {
+                let volume = device_volume_name(path);
+                let mounts = container.volume_mounts.get_or_insert_with(Vec::new);
+                if !mounts.iter().any(|m| m.name == volume) {
+                    mounts.push(VolumeMount { name: volume.clone(), mount_path: path.clone(), ..Default::default() });
+                }
+                if !volumes.iter().any(|v| v.name == volume) {
+                    volumes.push(Volume {
+                        name: volume,
+                        host_path: Some(HostPathVolumeSource { path: path.clone(), type_: Some("CharDevice".to_string()) }),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+        if !cdi.is_empty() {
+            annotations.insert(format!("{}skate-gpu-{}", CDI_ANNOTATION_PREFIX, container.name), cdi.join(","));
+        }
+    }
+    if !volumes.is_empty() {
+        spec.volumes.get_or_insert_with(Vec::new).extend(volumes);
+    }
+    if !annotations.is_empty() {
+        pod.metadata.annotations.get_or_insert_with(Default::default).extend(annotations);
+    }
+    let labels = pod.metadata.labels.get_or_insert_with(Default::default);
+    labels.insert(GPU_DEVICES_LABEL.to_string(), assigned.join("."));
+    labels.insert(GPU_REQUEST_LABEL.to_string(), requested.to_string());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use chrono::Local;
+    use k8s_openapi::api::core::v1::{Container, Pod, PodSpec, ResourceRequirements};
+    use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use crate::gpu::{assign_gpus, gpu_devices, gpu_request, GpuDevice, GPU_DEVICES_LABEL, GPU_REQUEST_LABEL, GPU_RESOURCE};
+    use crate::skatelet::system::podman::{PodmanPodInfo, PodmanPodStatus};
+
+    #[test]
+    fn test_gpu_devices() {
+        let dev: Vec<_> = ["null", "nvidia1", "nvidia0", "nvidiactl", "nvidia-uvm", "nvidia-modeset"].iter().map(|d| d.to_string()).collect();
+        let render_nodes = vec![("renderD129".to_string(), "0x8086".to_string()), ("renderD128".to_string(), "0x10de".to_string())];
+        assert_eq!(vec![
+            GpuDevice { index: 0, paths: vec!["/dev/nvidia0".to_string(), "/dev/nvidiactl".to_string(), "/dev/nvidia-uvm".to_string()], cdi: None },
+            GpuDevice { index: 1, paths: vec!["/dev/nvidia1".to_string(), "/dev/nvidiactl".to_string(), "/dev/nvidia-uvm".to_string()], cdi: None },
+            GpuDevice { index: 2, paths: vec!["/dev/dri/renderD129".to_string()], cdi: None },
+        ], gpu_devices(&dev, &render_nodes, false));
+        let with_cdi = gpu_devices(&dev, &render_nodes, true);
+        assert_eq!(vec![Some("nvidia.com/gpu=0"), Some("nvidia.com/gpu=1"), None], with_cdi.iter().map(|d| d.cdi.as_deref()).collect::<Vec<_>>());
+        assert!(gpu_devices(&["null".to_string()], &[], false).is_empty());
+    }
+
+    #[test]
+    fn test_assign_gpus() {
+        let container = |name: &str, gpus: Option<&str>| Container {
+            name: name.to_string(),
+            resources: gpus.map(|g| ResourceRequirements {
+                limits: Some(BTreeMap::from([(GPU_RESOURCE.to_string(), Quantity(g.to_string())), ("memory".to_string(), Quantity("1Gi".to_string()))])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut pod = Pod {
+            metadata: ObjectMeta { name: Some("infer.ml".to_string()), ..Default::default() },
+            spec: Some(PodSpec { containers: vec![container("model", Some("1")), container("sidecar", None)], ..Default::default() }),
+            ..Default::default()
+        };
+        assert_eq!(1, gpu_request(&pod));
+
+        let devices: Vec<_> = (0..2).map(|i| GpuDevice { index: i, paths: vec![format!("/dev/nvidia{}", i), "/dev/nvidiactl".to_string()], cdi: None }).collect();
+        let running = PodmanPodInfo {
+            id: "1a2b".to_string(),
+            name: "other.ml".to_string(),
+            status: PodmanPodStatus::Running,
+            created: Local::now(),
+            labels: BTreeMap::from([(GPU_DEVICES_LABEL.to_string(), "0".to_string())]),
+            containers: None,
+            phase: None,
+        };
+        assign_gpus(&mut pod, &devices, std::slice::from_ref(&running)).unwrap();
+
+        assert_eq!(Some(&"1".to_string()), pod.metadata.labels.as_ref().unwrap().get(GPU_DEVICES_LABEL));
+        assert_eq!(Some(&"1".to_string()), pod.metadata.labels.as_ref().unwrap().get(GPU_REQUEST_LABEL));
+        let spec = pod.spec.as_ref().unwrap();
+        let mounts: Vec<_> = spec.containers[0].volume_mounts.iter().flatten().map(|m| (m.name.as_str(), m.mount_path.as_str())).collect();
+        assert_eq!(vec![("gpu-nvidia1", "/dev/nvidia1"), ("gpu-nvidiactl", "/dev/nvidiactl")], mounts);
+        assert!(spec.containers[1].volume_mounts.is_none());
+        assert_eq!(vec![Some("CharDevice".to_string()); 2], spec.volumes.iter().flatten().map(|v| v.host_path.as_ref().unwrap().type_.clone()).collect::<Vec<_>>());
+        let limits = spec.containers[0].resources.as_ref().unwrap().limits.as_ref().unwrap();
+        assert!(!limits.contains_key(GPU_RESOURCE) && limits.contains_key("memory"));
+
+        // both are taken
+        let mut pod = Pod {
+            metadata: ObjectMeta { name: Some("train.ml".to_string()), ..Default::default() },
+            spec: Some(PodSpec { containers: vec![container("model", Some("1"))], ..Default::default() }),
+            ..Default::default()
+        };
+        let taken = PodmanPodInfo { labels: BTreeMap::from([(GPU_DEVICES_LABEL.to_string(), "0.1".to_string())]), ..running.clone() };
+        assert!(assign_gpus(&mut pod.clone(), &devices, &[taken]).is_err());
+        // a stopped pod keeps its gpus, an exited one gives them back
+        let stopped = PodmanPodInfo { status: PodmanPodStatus::Stopped, labels: BTreeMap::from([(GPU_DEVICES_LABEL.to_string(), "0.1".to_string())]), ..running.clone() };
+        assert!(assign_gpus(&mut pod.clone(), &devices, std::slice::from_ref(&stopped)).is_err());
+        assert!(assign_gpus(&mut pod.clone(), &devices, &[PodmanPodInfo { status: PodmanPodStatus::Exited, ..stopped }]).is_ok());
+
+        // with cdi names the gpu goes in the container's cdi annotation instead of being mounted
+        let cdi: Vec<_> = devices.iter().map(|d| GpuDevice { cdi: Some(format!("nvidia.com/gpu={}", d.index)), ..d.clone() }).collect();
+        assign_gpus(&mut pod, &cdi, &[]).unwrap();
+        assert_eq!(Some(&"nvidia.com/gpu=0".to_string()), pod.metadata.annotations.as_ref().unwrap().get("cdi.k8s.io/skate-gpu-model"));
+        let spec = pod.spec.as_ref().unwrap();
+        assert!(spec.volumes.is_none() && spec.containers[0].volume_mounts.is_none());
+    }
+}
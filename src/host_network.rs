@@ -0,0 +1,95 @@
+use std::collections::BTreeSet;
+use k8s_openapi::api::core::v1::{Pod, PodSpec};
+use crate::pod_spec::{pod_labels_mut, pod_spec_mut};
+use crate::resource::SupportedResources;
+use crate::skatelet::system::podman::{PodmanPodInfo, PodmanPodStatus};
+use crate::state::state::NodeState;
+
+// the host ports a pod binds, eg 80-tcp.53-udp, carried over to the podman pod so the scheduler can see what's taken on a node
+pub const HOST_PORTS_LABEL: &str = "skate.io/host-ports";
+
+// every container port of a hostNetwork pod, otherwise only those published with a hostPort
+fn host_ports(spec: &PodSpec) -> BTreeSet<String> {
+    let host_network = spec.host_network.unwrap_or_default();
+    spec.containers.iter().flat_map(|c| c.ports.iter().flatten())
+        .filter_map(|p| match host_network {
+            true => Some(p.container_port),
+            false => p.host_port,
+        }.map(|port| format!("{}-{}", port, p.protocol.as_deref().unwrap_or("TCP").to_lowercase())))
+        .collect()
+}
+
+fn pod_host_ports(pod: &PodmanPodInfo) -> BTreeSet<String> {
+    pod.labels.get(HOST_PORTS_LABEL).map(|p| p.split('.').map(|p| p.to_string()).collect()).unwrap_or_default()
+}
+
+// labels the resource's pods with the host ports they bind
+pub fn resolve(mut resource: SupportedResources) -> SupportedResources {
+    let ports = match pod_spec_mut(&mut resource).map(|s| host_ports(s)) {
+        Some(ports) if !ports.is_empty() => ports,
+        _ => return resource,
+    };
+    if let Some(labels) = pod_labels_mut(&mut resource) {
+        labels.insert(HOST_PORTS_LABEL.to_string(), ports.into_iter().collect::<Vec<_>>().join("."));
+    }
+    resource
+}
+
+// why the pod can't bind its host ports on the node, if it can't. a pod it replaces gives its ports back
+pub fn port_conflict(pod: &Pod, node: &NodeState) -> Option<String> {
+    let ports = host_ports(pod.spec.as_ref()?);
+    if ports.is_empty() {
+        return None;
+    }
+    let name = pod.metadata.name.clone().unwrap_or_default();
+    node.filter_pods(&|p| p.name != name && matches!(p.status, PodmanPodStatus::Running | PodmanPodStatus::Created | PodmanPodStatus::Degraded))
+        .into_iter()
+        .find_map(|p| {
+            let taken: Vec<_> = pod_host_ports(&p).intersection(&ports).cloned().collect();
+            (!taken.is_empty()).then(|| format!("host port {} is taken by pod {}", taken.join(", "), p.name))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::{Container, ContainerPort, Pod, PodSpec, PodStatus};
+    use crate::host_network::{port_conflict, resolve, HOST_PORTS_LABEL};
+    use crate::resource::SupportedResources;
+    use crate::test_helpers::objects::{node_state, pod, WithPod};
+
+    #[test]
+    fn test_port_conflict() {
+        let port = |port: i32, host_port: Option<i32>, protocol: Option<&str>| ContainerPort {
+            container_port: port,
+            host_port,
+            protocol: protocol.map(|p| p.to_string()),
+            ..Default::default()
+        };
+        let port_pod = |name: &str, host_network: bool, ports: Vec<ContainerPort>| Pod {
+            spec: Some(PodSpec {
+                host_network: Some(host_network),
+                containers: vec![Container { name: "app".to_string(), ports: Some(ports), ..Default::default() }],
+                ..Default::default()
+            }),
+            status: Some(PodStatus { phase: Some("Running".to_string()), ..Default::default() }),
+            ..pod(name, "default", &[])
+        };
+        let labelled = |p: Pod| match resolve(SupportedResources::Pod(p)) {
+            SupportedResources::Pod(p) => p,
+            _ => panic!("not a pod"),
+        };
+
+        let dns = labelled(port_pod("dns", true, vec![port(53, None, Some("UDP")), port(8080, None, None)]));
+        assert_eq!(Some(&"53-udp.8080-tcp".to_string()), dns.metadata.labels.as_ref().unwrap().get(HOST_PORTS_LABEL));
+        // only host ports of pods on the pod network
+        let web = labelled(port_pod("web", false, vec![port(80, Some(8080), None), port(9090, None, None)]));
+        assert_eq!(Some(&"8080-tcp".to_string()), web.metadata.labels.as_ref().unwrap().get(HOST_PORTS_LABEL));
+        assert!(!labelled(port_pod("api", false, vec![port(80, None, None)])).metadata.labels.unwrap().contains_key(HOST_PORTS_LABEL));
+
+        let node = node_state("node-1").with_pod(&dns);
+        assert_eq!(Some("host port 8080-tcp is taken by pod dns.default".to_string()), port_conflict(&web, &node));
+        assert_eq!(None, port_conflict(&port_pod("metrics", true, vec![port(53, None, None)]), &node));
+        // replacing it
+        assert_eq!(None, port_conflict(&dns, &node));
+    }
+}
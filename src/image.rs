@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use itertools::Itertools;
+use serde::Deserialize;
+use crate::resource::SupportedResources;
+use crate::ssh::SshClients;
+
+// architectures each image is published for, None when it couldn't be determined
+pub type ImageArchitectures = HashMap<String, Option<Vec<String>>>;
+
+#[derive(Deserialize)]
+struct ManifestList {
+    #[serde(default)]
+    manifests: Vec<ManifestListEntry>,
+}
+
+#[derive(Deserialize)]
+struct ManifestListEntry {
+    platform: Option<ManifestPlatform>,
+}
+
+#[derive(Deserialize)]
+struct ManifestPlatform {
+    #[serde(default)]
+    architecture: String,
+    #[serde(default)]
+    os: String,
+}
+
+// normalize_image_ref expands an image reference to the fully qualified form podman lists, eg nginx -> docker.io/library/nginx:latest
+pub fn normalize_image_ref(image: &str) -> String {
+    let (name, digest) = match image.split_once('@') {
+        Some((name, digest)) => (name, Some(digest)),
+        None => (image, None),
+    };
+
+    let mut name = name.to_string();
+    let first = name.split('/').next().unwrap_or_default();
+    let has_registry = name.contains('/') && (first.contains('.') || first.contains(':') || first == "localhost");
+    if !has_registry {
+        if !name.contains('/') {
+            name = format!("library/{}", name);
+        }
+        name = format!("docker.io/{}", name);
+    }
+
+    let last = name.rsplit('/').next().unwrap_or_default();
+    if digest.is_none() && !last.contains(':') {
+        name = format!("{}:latest", name);
+    }
+
+    match digest {
+        Some(digest) => format!("{}@{}", name, digest),
+        None => name,
+    }
+}
+
+// maps an architecture as reported by `arch` on the node to the name used in image manifest lists
+pub fn oci_arch(arch: &str) -> String {
+    match arch {
+        "x86_64" | "amd64" => "amd64",
+        "aarch64" | "arm64" => "arm64",
+        "armv7l" | "armv7" | "armhf" | "arm" => "arm",
+        "i386" | "i686" | "386" => "386",
+        other => other,
+    }.to_string()
+}
+
+// the linux architectures in the output of `podman manifest inspect`.
+// single arch images have no manifest list, so their architecture can't be known without pulling them
+pub fn parse_manifest_architectures(output: &str) -> Option<Vec<String>> {
+    let list: ManifestList = serde_json::from_str(output).ok()?;
+    let archs: Vec<_> = list.manifests.into_iter()
+        .filter_map(|m| m.platform)
+        // attestation manifests are listed with an unknown platform
+        .filter(|p| (p.os.is_empty() || p.os == "linux") && !p.architecture.is_empty() && p.architecture != "unknown")
+        .map(|p| p.architecture)
+        .unique()
+        .collect();
+
+    match archs.is_empty() {
+        true => None,
+        false => Some(archs),
+    }
+}
+
+pub fn pod_images(resource: &SupportedResources) -> Vec<String> {
+    match resource {
+        SupportedResources::Pod(pod) => pod.spec.as_ref().map(|s| {
+            s.containers.iter().chain(s.init_containers.iter().flatten())
+                .filter_map(|c| c.image.clone())
+                .unique()
+                .collect()
+        }).unwrap_or_default(),
+        _ => vec!(),
+    }
+}
+
+// looks up the manifest list of each image from the first node, since the registry is the same for all of them
+pub async fn image_architectures(conns: &SshClients, images: &[String]) -> ImageArchitectures {
+    let cmds: Vec<_> = images.iter().map(|image| format!("sudo podman manifest inspect '{}'", image)).collect();
+    let results = match conns.clients.first() {
+        Some(conn) => conn.execute_batch(&cmds).await.unwrap_or_default(),
+        None => vec!(),
+    };
+
+    images.iter().enumerate().map(|(i, image)| {
+        let archs = results.get(i).cloned()
+            .and_then(|r| r.into_result().ok())
+            .and_then(|output| parse_manifest_architectures(&output));
+        (image.clone(), archs)
+    }).collect()
+}
+
+// returns why a node of the given architecture can't run the images, if it can't
+pub fn incompatible_images(node_arch: &str, images: &[String], known: &ImageArchitectures) -> Option<String> {
+    if node_arch.is_empty() {
+        return None;
+    }
+    let node_arch = oci_arch(node_arch);
+
+    let reasons: Vec<_> = images.iter().filter_map(|image| {
+        match known.get(image) {
+            Some(Some(archs)) if !archs.contains(&node_arch) => Some(format!("image {} is not available for {} (available: {})", image, node_arch, archs.join(", "))),
+            _ => None,
+        }
+    }).collect();
+
+    match reasons.is_empty() {
+        true => None,
+        false => Some(reasons.join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::image::{incompatible_images, normalize_image_ref, parse_manifest_architectures, ImageArchitectures};
+
+    #[test]
+    fn test_normalize_image_ref() {
+        let conditions = &[
+            ("nginx", "docker.io/library/nginx:latest"),
+            ("nginx:1.27", "docker.io/library/nginx:1.27"),
+            ("bitnami/redis", "docker.io/bitnami/redis:latest"),
+            ("ghcr.io/foo/bar:v1", "ghcr.io/foo/bar:v1"),
+            ("localhost:5000/foo", "localhost:5000/foo:latest"),
+            ("k8s.gcr.io/pause:3.5", "k8s.gcr.io/pause:3.5"),
+            ("nginx@sha256:abc", "docker.io/library/nginx@sha256:abc"),
+        ];
+
+        for (input, expect) in conditions {
+            assert_eq!(normalize_image_ref(input), *expect, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_manifest_architectures() {
+        let output = r#"{
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [
+                {"digest": "sha256:a", "platform": {"architecture": "amd64", "os": "linux"}},
+                {"digest": "sha256:b", "platform": {"architecture": "arm64", "os": "linux", "variant": "v8"}},
+                {"digest": "sha256:c", "platform": {"architecture": "amd64", "os": "windows"}},
+                {"digest": "sha256:d", "platform": {"architecture": "unknown", "os": "unknown"}}
+            ]
+        }"#;
+        assert_eq!(parse_manifest_architectures(output), Some(vec!["amd64".to_string(), "arm64".to_string()]));
+
+        let single = r#"{"schemaVersion": 2, "config": {"digest": "sha256:a"}, "layers": []}"#;
+        assert_eq!(parse_manifest_architectures(single), None);
+        assert_eq!(parse_manifest_architectures("Error: not found"), None);
+    }
+
+    #[test]
+    fn test_incompatible_images() {
+        let known = ImageArchitectures::from([
+            ("nginx".to_string(), Some(vec!["amd64".to_string(), "arm64".to_string()])),
+            ("legacy".to_string(), Some(vec!["amd64".to_string()])),
+            ("local".to_string(), None),
+        ]);
+
+        assert_eq!(incompatible_images("aarch64", &["nginx".to_string(), "local".to_string()], &known), None);
+        assert_eq!(incompatible_images("x86_64", &["legacy".to_string()], &known), None);
+        assert_eq!(
+            incompatible_images("aarch64", &["legacy".to_string()], &known),
+            Some("image legacy is not available for arm64 (available: amd64)".to_string())
+        );
+        assert_eq!(incompatible_images("", &["legacy".to_string()], &known), None);
+    }
+}
@@ -0,0 +1,200 @@
+use std::path::Path;
+use anyhow::anyhow;
+use clap::Args;
+use crate::config::{Access, Cluster, Config};
+use crate::deps::{SshManager, With};
+use crate::errors::SkateError;
+use crate::skate::ConfigFileArgs;
+use crate::ssh::SshClient;
+use crate::util::{shell_quote, CHECKBOX_EMOJI};
+
+#[derive(Debug, Args)]
+#[command(arg_required_else_help(true))]
+pub struct BuildArgs {
+    #[command(flatten)]
+    config: ConfigFileArgs,
+    #[arg(long, short, long_help = "The name and tag of the image, eg registry.example.com/app:1.2")]
+    tag: String,
+    #[arg(long, short, long_help = "The Containerfile, relative to the context. Defaults to podman's Containerfile or Dockerfile.")]
+    file: Option<String>,
+    #[arg(long, long_help = "Build on this node instead of locally, the context is copied to it first.")]
+    on: Option<String>,
+    #[arg(long, long_help = "Push the image to its registry once it's built.")]
+    push: bool,
+    #[arg(long, long_help = "Copy the image to every node with podman save and load, for clusters without a registry.")]
+    distribute: bool,
+    #[arg(name = "CONTEXT", default_value = ".", long_help = "The directory the image is built from.")]
+    context: String,
+}
+
+pub trait BuildDeps: With<dyn SshManager> {}
+
+pub struct Build<D: BuildDeps> {
+    pub deps: D,
+}
+
+// the args of `podman build`, the containerfile is looked up in the context as it would be remotely
+fn build_args(tag: &str, file: Option<&str>, context: &str) -> Vec<String> {
+    let mut args = vec!["build".to_string(), "-t".to_string(), tag.to_string()];
+    if let Some(file) = file {
+        args.extend(["-f".to_string(), Path::new(context).join(file).to_string_lossy().to_string()]);
+    }
+    args.push(context.to_string());
+    args
+}
+
+// uploads are installed as root, so the archives and the unpacked context are only root's
+const REMOTE_BUILD_DIR: &str = "/var/lib/skate/build";
+const ARCHIVE_MODE: u32 = 0o600;
+
+// unpacks the uploaded context on the node and builds it, leaving nothing but the image behind
+fn remote_build_script(archive: &str, dir: &str, tag: &str, file: Option<&str>) -> String {
+    let build = ["sudo".to_string(), "podman".to_string()].into_iter()
+        .chain(build_args(tag, file, dir))
+        .map(|a| shell_quote(&a))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!("sudo mkdir -p {dir} && sudo tar -xzf {archive} -C {dir} && {build}; status=$?; sudo rm -rf {dir} {archive}; exit $status",
+        dir = shell_quote(dir), archive = shell_quote(archive), build = build)
+}
+
+// copies the context to the node and builds it there
+async fn build_remote(conn: &dyn SshClient, local_archive: &str, remote_archive: &str, dir: &str, tag: &str, file: Option<&str>) -> Result<(), SkateError> {
+    let mut archive = tokio::fs::File::open(local_archive).await?;
+    conn.upload(&mut archive, remote_archive, ARCHIVE_MODE).await?;
+    conn.execute_stdout(&remote_build_script(remote_archive, dir, tag, file), false, true).await?;
+    Ok(())
+}
+
+async fn run_local(program: &str, args: &[&str]) -> Result<(), SkateError> {
+    let status = tokio::process::Command::new(program).args(args).status().await
+        .map_err(|e| anyhow!(e).context(format!("failed to run {}", program)))?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(anyhow!("{} {} failed: {}", program, args.join(" "), status).into()),
+    }
+}
+
+impl<D: BuildDeps> Build<D> {
+    pub async fn build(&self, args: BuildArgs) -> Result<(), SkateError> {
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), Access::Deploy)?;
+        if !Path::new(&args.context).is_dir() {
+            return Err(anyhow!("context {} isn't a directory", args.context).into());
+        }
+
+        let builder = match args.on.as_ref() {
+            Some(name) => {
+                let node = cluster.nodes.iter().find(|n| &n.name == name).ok_or(anyhow!("failed to find node {}", name))?;
+                Some(self.deps.get().node_connect(cluster, node).await?)
+            }
+            None => None,
+        };
+
+        let id = std::process::id();
+        let local_archive = std::env::temp_dir().join(format!("skate-build-{}.tar", id));
+        let local_archive = local_archive.to_string_lossy().to_string();
+        let remote_archive = format!("{}/{}.tar", REMOTE_BUILD_DIR, id);
+
+        match builder.as_ref() {
+            Some(conn) => {
+                run_local("tar", &["-czf", &local_archive, "-C", &args.context, "."]).await?;
+                let dir = format!("{}/{}", REMOTE_BUILD_DIR, id);
+                let built = build_remote(conn.as_ref(), &local_archive, &remote_archive, &dir, &args.tag, args.file.as_deref()).await;
+                let _ = std::fs::remove_file(&local_archive);
+                built?;
+                if args.push {
+                    conn.execute_stdout(&format!("sudo podman push {}", shell_quote(&args.tag)), false, true).await?;
+                }
+            }
+            None => {
+                let build_args = build_args(&args.tag, args.file.as_deref(), &args.context);
+                run_local("podman", &build_args.iter().map(|a| a.as_str()).collect::<Vec<_>>()).await?;
+                if args.push {
+                    run_local("podman", &["push", &args.tag]).await?;
+                }
+            }
+        }
+        println!("{} built {}", CHECKBOX_EMOJI, args.tag);
+
+        if args.distribute {
+            self.distribute(cluster, builder.as_deref(), &args.tag, &local_archive, &remote_archive).await?;
+        }
+        Ok(())
+    }
+
+    // saves the image where it was built and loads it on every other node
+    async fn distribute(&self, cluster: &Cluster, builder: Option<&dyn SshClient>, tag: &str, local_archive: &str, remote_archive: &str) -> Result<(), SkateError> {
+        let (conns, errors) = self.deps.get().cluster_connect(cluster).await;
+        if let Some(errors) = errors {
+            eprintln!("{}", errors)
+        }
+        let conns = conns.ok_or(anyhow!("failed to connect to any node"))?;
+
+        match builder {
+            Some(conn) => {
+                conn.execute(&format!("sudo podman save -o {archive} {tag} && sudo chmod {mode:o} {archive}", archive = shell_quote(remote_archive), tag = shell_quote(tag), mode = ARCHIVE_MODE)).await?;
+                let downloaded = conn.download(remote_archive, Path::new(local_archive)).await;
+                let _ = conn.execute(&format!("sudo rm -f {}", shell_quote(remote_archive))).await;
+                downloaded?;
+            }
+            None => run_local("podman", &["save", "-o", local_archive, tag]).await?,
+        }
+
+        let builder = builder.map(|b| b.node_name());
+        let mut failures = vec![];
+        for conn in conns.clients.iter().filter(|c| Some(c.node_name()) != builder) {
+            let loaded = async {
+                let mut file = tokio::fs::File::open(local_archive).await?;
+                conn.upload(&mut file, remote_archive, ARCHIVE_MODE).await?;
+                conn.execute(&format!("sudo podman load -i {archive}; status=$?; sudo rm -f {archive}; exit $status", archive = shell_quote(remote_archive))).await?;
+                Ok::<_, SkateError>(())
+            };
+            match loaded.await {
+                Ok(_) => println!("{} loaded {} on {}", CHECKBOX_EMOJI, tag, conn.node_name()),
+                Err(e) => failures.push(format!("{}: {}", conn.node_name(), e)),
+            }
+        }
+        let _ = std::fs::remove_file(local_archive);
+
+        match failures.is_empty() {
+            true => Ok(()),
+            false => Err(anyhow!("failed to load {} on some nodes:\n{}", tag, failures.join("\n")).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::image_build::{build_args, build_remote, remote_build_script};
+    use crate::test_helpers::ssh_mocks::MockSshClient;
+    use crate::test_helpers::temp_dir::TempDir;
+
+    #[test]
+    fn test_remote_build_script() {
+        assert_eq!(vec!["build", "-t", "app:dev", "."], build_args("app:dev", None, "."));
+        assert_eq!(vec!["build", "-t", "app:dev", "-f", "web/docker/Containerfile", "web"], build_args("app:dev", Some("docker/Containerfile"), "web"));
+
+        assert_eq!(
+            "sudo mkdir -p '/var/lib/skate/build/b' && sudo tar -xzf '/var/lib/skate/build/b.tar' -C '/var/lib/skate/build/b' && 'sudo' 'podman' 'build' '-t' 'app:dev' '-f' '/var/lib/skate/build/b/Containerfile.prod' '/var/lib/skate/build/b'; status=$?; sudo rm -rf '/var/lib/skate/build/b' '/var/lib/skate/build/b.tar'; exit $status",
+            remote_build_script("/var/lib/skate/build/b.tar", "/var/lib/skate/build/b", "app:dev", Some("Containerfile.prod")),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_build_remote() {
+        let dir = TempDir::new("build");
+        let archive = dir.join("context.tar").to_string_lossy().to_string();
+        std::fs::write(&archive, "context").unwrap();
+        let mut conn = MockSshClient::new("node-1", false);
+        conn.responses = vec![("sudo ".to_string(), "".to_string())];
+
+        build_remote(&conn, &archive, "/var/lib/skate/build/1.tar", "/var/lib/skate/build/1", "app:dev", None).await.unwrap();
+        let commands: Vec<_> = conn.commands.lock().unwrap().iter().map(|(_, c)| c.clone()).collect();
+        // the archive is root's alone, so everything that touches it runs as root
+        assert_eq!("upload 600 /var/lib/skate/build/1.tar", commands[0]);
+        let steps: Vec<_> = commands[1].split(['&', ';']).map(str::trim).filter(|s| s.contains("build/1")).collect();
+        assert_eq!(4, steps.len());
+        assert!(steps.iter().all(|s| s.starts_with("sudo ") || s.starts_with("'sudo'")), "{}", commands[1]);
+    }
+}
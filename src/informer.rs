@@ -0,0 +1,163 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+use anyhow::anyhow;
+use futures::Stream;
+use log::warn;
+use crate::deps::{Deps, SshManager, With};
+use crate::errors::SkateError;
+use crate::refresh::Refresh;
+use crate::ssh::SshClients;
+
+// what a rust program watching the cluster needs, so it can build on skate rather than run it and parse its output
+pub use crate::config::Config;
+pub use crate::skatelet::system::podman::{PodmanContainerInfo, PodmanPodInfo, PodmanPodStatus};
+pub use crate::state::state::{ClusterState, NodeState};
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PodEvent {
+    Added { node: String, pod: PodmanPodInfo },
+    // the pod's status, containers or labels changed
+    Modified { node: String, previous: PodmanPodInfo, pod: PodmanPodInfo },
+    Deleted { node: String, pod: PodmanPodInfo },
+}
+
+// the pods seen so far, by node and pod name
+type KnownPods = BTreeMap<(String, String), PodmanPodInfo>;
+
+// the events that turn the known pods into those of the state, updating them. the pods of a node missing from the state
+// aren't known to be gone, so they're kept until the node is seen again
+fn diff_pods(known: &mut KnownPods, current: &ClusterState) -> Vec<PodEvent> {
+    let mut events = vec![];
+    for node in current.nodes.iter() {
+        if node.host_info.as_ref().and_then(|h| h.system_info.as_ref()).is_none() {
+            continue;
+        }
+        let pods = node.filter_pods(&|_| true);
+
+        let deleted: Vec<_> = known.keys()
+            .filter(|(n, name)| *n == node.node_name && !pods.iter().any(|p| p.name == *name))
+            .cloned()
+            .collect();
+        for key in deleted {
+            if let Some(pod) = known.remove(&key) {
+                events.push(PodEvent::Deleted { node: key.0, pod });
+            }
+        }
+
+        for pod in pods {
+            let key = (node.node_name.clone(), pod.name.clone());
+            match known.insert(key, pod.clone()) {
+                None => events.push(PodEvent::Added { node: node.node_name.clone(), pod }),
+                Some(previous) if previous != pod => events.push(PodEvent::Modified { node: node.node_name.clone(), previous, pod }),
+                Some(_) => {}
+            }
+        }
+    }
+    events
+}
+
+struct Watch {
+    config: Config,
+    cluster: String,
+    interval: Duration,
+    conns: Option<SshClients>,
+    known: KnownPods,
+    pending: VecDeque<PodEvent>,
+    refreshed: bool,
+}
+
+impl Watch {
+    async fn refresh(&mut self) -> Result<ClusterState, SkateError> {
+        if self.conns.is_none() {
+            let cluster = self.config.active_cluster(Some(self.cluster.clone()))?;
+            let (conns, errors) = With::<dyn SshManager>::get(&Deps {}).cluster_connect(cluster).await;
+            if let Some(errors) = errors {
+                warn!("{}", errors);
+            }
+            self.conns = Some(conns.ok_or(anyhow!("failed to connect to any node"))?);
+        }
+        let conns = self.conns.as_ref().expect("should have had connections");
+        Refresh::<Deps>::refreshed_state(&self.cluster, conns, &self.config).await
+    }
+}
+
+async fn next_event(mut watch: Watch) -> Option<(PodEvent, Watch)> {
+    loop {
+        if let Some(event) = watch.pending.pop_front() {
+            return Some((event, watch));
+        }
+        if watch.refreshed {
+            tokio::time::sleep(watch.interval).await;
+        }
+        watch.refreshed = true;
+        match watch.refresh().await {
+            Ok(state) => {
+                let events = diff_pods(&mut watch.known, &state);
+                watch.pending.extend(events);
+            }
+            Err(e) => {
+                warn!("failed to refresh cluster {}: {}", watch.cluster, e);
+                // connect again next time, in case a node came back
+                watch.conns = None;
+            }
+        }
+    }
+}
+
+// the cluster's pod changes, refreshing its state every interval. the pods already running come first, as added. a failed
+// refresh is logged and retried at the next interval, so the stream doesn't end
+pub fn watch_pods(config: Config, cluster: &str, interval: Duration) -> impl Stream<Item = PodEvent> {
+    let watch = Watch {
+        config,
+        cluster: cluster.to_string(),
+        interval,
+        conns: None,
+        known: BTreeMap::new(),
+        pending: VecDeque::new(),
+        refreshed: false,
+    };
+    futures::stream::unfold(watch, next_event)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use k8s_openapi::api::core::v1::{Pod, PodStatus};
+    use crate::informer::{diff_pods, PodEvent};
+    use crate::test_helpers::objects::{cluster_state, node_state, pod, WithPod};
+
+    fn phased(name: &str, phase: &str) -> Pod {
+        Pod { status: Some(PodStatus { phase: Some(phase.to_string()), ..Default::default() }), ..pod(name, "default", &[]) }
+    }
+
+    fn summary(events: &[PodEvent]) -> Vec<String> {
+        events.iter().map(|e| match e {
+            PodEvent::Added { node, pod } => format!("added {} {}", node, pod.name),
+            PodEvent::Modified { node, pod, .. } => format!("modified {} {}", node, pod.name),
+            PodEvent::Deleted { node, pod } => format!("deleted {} {}", node, pod.name),
+        }).collect()
+    }
+
+    #[test]
+    fn test_diff_pods() {
+        let mut known = BTreeMap::new();
+        let first = cluster_state(vec![
+            node_state("node-1").with_pod(&phased("web", "Running")).with_pod(&phased("api", "Running")),
+            node_state("node-2").with_pod(&phased("db", "Running")),
+        ]);
+        assert_eq!(vec!["added node-1 web.default", "added node-1 api.default", "added node-2 db.default"], summary(&diff_pods(&mut known, &first)));
+        assert!(diff_pods(&mut known, &first).is_empty());
+
+        // node-2 couldn't be reached, its pod isn't taken to be gone
+        let mut unreachable = node_state("node-2");
+        unreachable.host_info = None;
+        let node_1 = node_state("node-1").with_pod(&phased("web", "Failed"));
+        let second = cluster_state(vec![node_1.clone(), unreachable]);
+        assert_eq!(vec!["deleted node-1 api.default", "modified node-1 web.default"], summary(&diff_pods(&mut known, &second)));
+
+        let third = cluster_state(vec![node_1, node_state("node-2")]);
+        assert_eq!(vec!["deleted node-2 db.default"], summary(&diff_pods(&mut known, &third)));
+    }
+}
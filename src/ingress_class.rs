@@ -0,0 +1,332 @@
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fs;
+use anyhow::anyhow;
+use itertools::Itertools;
+use k8s_openapi::api::apps::v1::DaemonSet;
+use k8s_openapi::api::core::v1::EnvVar;
+use k8s_openapi::api::networking::v1::Ingress;
+use serde::{Deserialize, Serialize};
+use crate::config::{DefaultPage, IngressClass};
+use crate::errors::SkateError;
+use crate::resource::SupportedResources;
+
+// the proxy every node runs, which serves ingresses that don't name a class
+pub const DEFAULT_INGRESS_CLASS: &str = "nginx";
+// set on ingresses of the other classes when they're applied, so that skatelet knows which proxy to configure
+pub const INGRESS_CLASS_ANNOTATION: &str = "skate.io/ingress-class";
+pub const INGRESS_PORTS_ANNOTATION: &str = "skate.io/ingress-ports";
+pub const INGRESS_NODE_SELECTOR_ANNOTATION: &str = "skate.io/ingress-node-selector";
+// the class annotation from before ingressClassName existed
+const LEGACY_CLASS_ANNOTATION: &str = "kubernetes.io/ingress.class";
+
+// also where every class's proxy has its config directory mounted
+pub(crate) const INGRESS_DIR: &str = "/var/lib/skate/ingress";
+const CLASS_FILE: &str = "class.json";
+const INGRESS_MANIFEST: &str = include_str!("../manifests/ingress.yaml");
+
+// the ports and paths of the proxy that serves an ingress on a node
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ClassSettings {
+    pub name: String,
+    pub http_port: u16,
+    pub https_port: u16,
+    pub hook_port: u16,
+}
+
+impl Default for ClassSettings {
+    fn default() -> Self {
+        ClassSettings {
+            name: DEFAULT_INGRESS_CLASS.to_string(),
+            http_port: 80,
+            https_port: 443,
+            hook_port: 8999,
+        }
+    }
+}
+
+impl From<&IngressClass> for ClassSettings {
+    fn from(class: &IngressClass) -> Self {
+        ClassSettings {
+            name: class.name.clone(),
+            http_port: class.http_port,
+            https_port: class.https_port,
+            hook_port: class.hook_port.unwrap_or(class.https_port.saturating_add(1)),
+        }
+    }
+}
+
+impl ClassSettings {
+    pub fn is_default(&self) -> bool {
+        self.name == DEFAULT_INGRESS_CLASS
+    }
+
+    // the proxy's config directory on the node
+    pub fn dir(&self) -> String {
+        match self.is_default() {
+            true => INGRESS_DIR.to_string(),
+            false => format!("{}-{}", INGRESS_DIR, self.name),
+        }
+    }
+
+    pub fn daemonset_name(&self) -> String {
+        match self.is_default() {
+            true => "nginx-ingress".to_string(),
+            false => format!("nginx-ingress-{}", self.name),
+        }
+    }
+
+    pub fn ports(&self) -> [u16; 2] {
+        [self.http_port, self.https_port]
+    }
+
+    // the settings skate stamped on the ingress when applying it, the default class if there are none
+    pub fn from_ingress(ingress: &Ingress) -> Result<Self, Box<dyn Error>> {
+        let annotations = ingress.metadata.annotations.clone().unwrap_or_default();
+        let name = match annotations.get(INGRESS_CLASS_ANNOTATION) {
+            Some(name) if name != DEFAULT_INGRESS_CLASS => name.clone(),
+            _ => return Ok(ClassSettings::default()),
+        };
+        let ports = annotations.get(INGRESS_PORTS_ANNOTATION)
+            .ok_or(anyhow!("ingress class {} is missing the {} annotation", name, INGRESS_PORTS_ANNOTATION))?;
+        let ports: Vec<u16> = ports.split(',').map(|p| p.trim().parse::<u16>()).collect::<Result<_, _>>()
+            .map_err(|e| anyhow!(e).context(format!("invalid {} annotation", INGRESS_PORTS_ANNOTATION)))?;
+        match ports.as_slice() {
+            [http_port, https_port, hook_port] => Ok(ClassSettings { name, http_port: *http_port, https_port: *https_port, hook_port: *hook_port }),
+            _ => Err(anyhow!("{} must be the http, https and hook ports, got {:?}", INGRESS_PORTS_ANNOTATION, ports).into()),
+        }
+    }
+}
+
+// the name of the class the ingress asks for
+pub fn class_name(ingress: &Ingress) -> String {
+    ingress.spec.as_ref().and_then(|s| s.ingress_class_name.clone())
+        .or_else(|| ingress.metadata.annotations.as_ref().and_then(|a| a.get(LEGACY_CLASS_ANNOTATION).cloned()))
+        .unwrap_or(DEFAULT_INGRESS_CLASS.to_string())
+}
+
+// classes need unique names, and ports that don't clash with each other's or the default proxy's, since they all use the host network
+pub fn validate(classes: &[IngressClass]) -> Result<(), SkateError> {
+    let mut names = HashSet::from([DEFAULT_INGRESS_CLASS.to_string()]);
+    let default = ClassSettings::default();
+    let mut ports = HashSet::from([default.http_port, default.https_port, default.hook_port]);
+    for class in classes {
+        if !names.insert(class.name.clone()) {
+            return Err(anyhow!("ingress class {} is defined more than once, or is the name of the default class", class.name).into());
+        }
+        let settings = ClassSettings::from(class);
+        for port in [settings.http_port, settings.https_port, settings.hook_port] {
+            if !ports.insert(port) {
+                return Err(anyhow!("ingress class {}: port {} is used by another ingress class", class.name, port).into());
+            }
+        }
+    }
+    Ok(())
+}
+
+// stamps an ingress with the settings of the class it asks for. ingresses of the default class are left without them,
+// so that their hash doesn't change
+pub fn resolve(classes: &[IngressClass], resource: SupportedResources) -> Result<SupportedResources, SkateError> {
+    let mut ingress = match resource {
+        SupportedResources::Ingress(ingress) => ingress,
+        _ => return Ok(resource),
+    };
+    let name = class_name(&ingress);
+
+    let annotations = ingress.metadata.annotations.get_or_insert_with(BTreeMap::new);
+    for key in [INGRESS_CLASS_ANNOTATION, INGRESS_PORTS_ANNOTATION, INGRESS_NODE_SELECTOR_ANNOTATION] {
+        annotations.remove(key);
+    }
+
+    if name != DEFAULT_INGRESS_CLASS {
+        let class = classes.iter().find(|c| c.name == name)
+            .ok_or(anyhow!("ingress {} uses ingress class {}, which isn't in the cluster's ingress_classes", ingress.metadata.name.clone().unwrap_or_default(), name))?;
+        let settings = ClassSettings::from(class);
+        annotations.insert(INGRESS_CLASS_ANNOTATION.to_string(), settings.name.clone());
+        annotations.insert(INGRESS_PORTS_ANNOTATION.to_string(), format!("{},{},{}", settings.http_port, settings.https_port, settings.hook_port));
+        if !class.node_selector.is_empty() {
+            annotations.insert(INGRESS_NODE_SELECTOR_ANNOTATION.to_string(), class.node_selector.iter().map(|(k, v)| format!("{}={}", k, v)).join(","));
+        }
+    }
+    if annotations.is_empty() {
+        ingress.metadata.annotations = None;
+    }
+    Ok(SupportedResources::Ingress(ingress))
+}
+
+// the labels a node needs to serve the ingress, as stamped by resolve
+pub fn node_selector(ingress: &Ingress) -> BTreeMap<String, String> {
+    ingress.metadata.annotations.as_ref().and_then(|a| a.get(INGRESS_NODE_SELECTOR_ANNOTATION))
+        .map(|s| s.split(',').filter_map(|kv| kv.split_once('=')).map(|(k, v)| (k.to_string(), v.to_string())).collect())
+        .unwrap_or_default()
+}
+
+// the env the proxy's error page reads the cluster's default page from
+fn set_default_page(daemonset: &mut DaemonSet, page: &DefaultPage) {
+    let mut env = vec![EnvVar { name: "DEFAULT_PAGE_STATUS".to_string(), value: Some(page.status.to_string()), ..Default::default() }];
+    if let Some(title) = &page.title {
+        env.push(EnvVar { name: "DEFAULT_PAGE_TITLE".to_string(), value: Some(title.clone()), ..Default::default() });
+    }
+    if let Some(message) = &page.message {
+        env.push(EnvVar { name: "DEFAULT_PAGE_MESSAGE".to_string(), value: Some(message.clone()), ..Default::default() });
+    }
+    let containers = daemonset.spec.iter_mut().flat_map(|s| s.template.spec.iter_mut()).flat_map(|s| s.containers.iter_mut());
+    for container in containers {
+        container.env.get_or_insert_with(Vec::new).extend(env.clone());
+    }
+}
+
+// the default proxy's daemonset. without a default page it's the manifest as it is, so its hash doesn't change
+pub fn default_manifest(page: Option<&DefaultPage>) -> Result<String, Box<dyn Error>> {
+    let page = match page {
+        Some(page) => page,
+        None => return Ok(INGRESS_MANIFEST.to_string()),
+    };
+    let mut daemonset: DaemonSet = serde_yaml::from_str(INGRESS_MANIFEST)?;
+    set_default_page(&mut daemonset, page);
+    Ok(serde_yaml::to_string(&daemonset)?)
+}
+
+// the proxy daemonset for a class, the default one with its own name, config directory and node selector
+pub fn daemonset_manifest(class: &IngressClass, page: Option<&DefaultPage>) -> Result<String, Box<dyn Error>> {
+    let settings = ClassSettings::from(class);
+    let mut daemonset: DaemonSet = serde_yaml::from_str(INGRESS_MANIFEST)?;
+    if let Some(page) = page {
+        set_default_page(&mut daemonset, page);
+    }
+    let name = settings.daemonset_name();
+
+    daemonset.metadata.name = Some(name.clone());
+    daemonset.metadata.labels = Some(BTreeMap::from([("app".to_string(), name.clone())]));
+    let spec = daemonset.spec.as_mut().ok_or("ingress manifest has no spec")?;
+    spec.selector.match_labels = Some(BTreeMap::from([("app".to_string(), name.clone())]));
+    spec.template.metadata.get_or_insert_with(Default::default).labels = Some(BTreeMap::from([("app".to_string(), name.clone())]));
+
+    let pod_spec = spec.template.spec.as_mut().ok_or("ingress manifest has no pod spec")?;
+    if !class.node_selector.is_empty() {
+        pod_spec.node_selector = Some(class.node_selector.clone());
+    }
+    for volume in pod_spec.volumes.iter_mut().flatten() {
+        if let Some(host_path) = volume.host_path.as_mut() {
+            host_path.path = host_path.path.replacen(INGRESS_DIR, &settings.dir(), 1);
+            host_path.type_ = Some("DirectoryOrCreate".to_string());
+        }
+    }
+    Ok(serde_yaml::to_string(&daemonset)?)
+}
+
+// the classes whose proxy has ingresses on this node, always including the default one
+pub fn node_classes() -> Vec<ClassSettings> {
+    let mut classes = vec![ClassSettings::default()];
+    let dirs = fs::read_dir("/var/lib/skate").map(|d| d.filter_map(|e| e.ok()).collect::<Vec<_>>()).unwrap_or_default();
+    for dir in dirs {
+        if !dir.file_name().to_string_lossy().starts_with("ingress-") {
+            continue;
+        }
+        if let Some(settings) = fs::read_to_string(dir.path().join(CLASS_FILE)).ok().and_then(|s| serde_json::from_str::<ClassSettings>(&s).ok()) {
+            classes.push(settings);
+        }
+    }
+    classes
+}
+
+// records the class's settings in its directory, for node_classes
+pub fn save_class(settings: &ClassSettings) -> Result<(), Box<dyn Error>> {
+    if settings.is_default() {
+        return Ok(());
+    }
+    fs::create_dir_all(settings.dir())?;
+    fs::write(format!("{}/{}", settings.dir(), CLASS_FILE), serde_json::to_string(settings)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use k8s_openapi::api::apps::v1::DaemonSet;
+    use k8s_openapi::api::networking::v1::Ingress;
+    use serde_json::json;
+    use crate::config::{DefaultPage, IngressClass};
+    use crate::ingress_class::{daemonset_manifest, default_manifest, node_selector, resolve, validate, ClassSettings, INGRESS_CLASS_ANNOTATION};
+    use crate::resource::SupportedResources;
+
+    fn internal() -> IngressClass {
+        IngressClass {
+            name: "internal".to_string(),
+            http_port: 8080,
+            https_port: 8443,
+            hook_port: None,
+            node_selector: BTreeMap::from([("zone".to_string(), "private".to_string())]),
+        }
+    }
+
+    fn ingress(class: Option<&str>) -> Ingress {
+        serde_json::from_value(json!({
+            "metadata": {"name": "foo", "namespace": "default"},
+            "spec": {"ingressClassName": class, "rules": []}
+        })).unwrap()
+    }
+
+    fn resolved(class: Option<&str>) -> Ingress {
+        match resolve(&[internal()], SupportedResources::Ingress(ingress(class))).unwrap() {
+            SupportedResources::Ingress(ingress) => ingress,
+            _ => panic!("not an ingress"),
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let default = resolved(None);
+        assert_eq!(None, default.metadata.annotations);
+        assert_eq!(ClassSettings::default(), ClassSettings::from_ingress(&default).unwrap());
+        assert_eq!(None, resolved(Some("nginx")).metadata.annotations);
+
+        let internal_ingress = resolved(Some("internal"));
+        assert_eq!(Some(&"internal".to_string()), internal_ingress.metadata.annotations.as_ref().unwrap().get(INGRESS_CLASS_ANNOTATION));
+        let settings = ClassSettings::from_ingress(&internal_ingress).unwrap();
+        assert_eq!(ClassSettings { name: "internal".to_string(), http_port: 8080, https_port: 8443, hook_port: 8444 }, settings);
+        assert_eq!("/var/lib/skate/ingress-internal", settings.dir());
+        assert_eq!(BTreeMap::from([("zone".to_string(), "private".to_string())]), node_selector(&internal_ingress));
+
+        assert!(resolve(&[internal()], SupportedResources::Ingress(ingress(Some("public")))).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&[internal()]).is_ok());
+        assert!(validate(&[internal(), internal()]).is_err());
+
+        let mut clash = internal();
+        clash.name = "other".to_string();
+        clash.http_port = 443;
+        assert!(validate(&[internal(), clash]).is_err());
+    }
+
+    #[test]
+    fn test_daemonset_manifest() {
+        let daemonset: DaemonSet = serde_yaml::from_str(&daemonset_manifest(&internal(), None).unwrap()).unwrap();
+        assert_eq!(Some("nginx-ingress-internal".to_string()), daemonset.metadata.name);
+        let pod_spec = daemonset.spec.unwrap().template.spec.unwrap();
+        assert_eq!(Some(BTreeMap::from([("zone".to_string(), "private".to_string())])), pod_spec.node_selector);
+        let paths: Vec<_> = pod_spec.volumes.unwrap().into_iter().filter_map(|v| v.host_path).map(|h| h.path).collect();
+        assert_eq!(vec!["/var/lib/skate/ingress-internal", "/var/lib/skate/ingress-internal/letsencrypt_storage"], paths);
+        assert_eq!(None, pod_spec.containers[0].env);
+    }
+
+    #[test]
+    fn test_default_page() {
+        assert_eq!(include_str!("../manifests/ingress.yaml"), default_manifest(None).unwrap());
+
+        let page = DefaultPage { status: 503, title: Some("Down for maintenance".to_string()), message: None };
+        for manifest in [default_manifest(Some(&page)).unwrap(), daemonset_manifest(&internal(), Some(&page)).unwrap()] {
+            let daemonset: DaemonSet = serde_yaml::from_str(&manifest).unwrap();
+            let env: Vec<_> = daemonset.spec.unwrap().template.spec.unwrap().containers[0].env.clone().unwrap().into_iter()
+                .map(|e| (e.name, e.value.unwrap())).collect();
+            assert_eq!(vec![
+                ("DEFAULT_PAGE_STATUS".to_string(), "503".to_string()),
+                ("DEFAULT_PAGE_TITLE".to_string(), "Down for maintenance".to_string()),
+            ], env);
+        }
+    }
+}
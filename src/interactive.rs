@@ -0,0 +1,31 @@
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// set by --non-interactive, for cron and ci: no prompts, spinners, emoji or color, whatever the terminals are
+static NON_INTERACTIVE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_non_interactive(non_interactive: bool) {
+    NON_INTERACTIVE.store(non_interactive, Ordering::Relaxed);
+    if non_interactive {
+        colored::control::set_override(false);
+    }
+}
+
+pub fn non_interactive() -> bool {
+    NON_INTERACTIVE.load(Ordering::Relaxed)
+}
+
+// the question goes to stderr and the answer comes from stdin, so both have to be terminals
+pub fn can_prompt() -> bool {
+    !non_interactive() && std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+// spinners and marks on stderr, where the progress is written
+pub fn animated() -> bool {
+    !non_interactive() && std::io::stderr().is_terminal()
+}
+
+// emoji on stdout, piped or redirected output gets plain text instead
+pub fn decorated() -> bool {
+    !non_interactive() && std::io::stdout().is_terminal()
+}
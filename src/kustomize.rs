@@ -0,0 +1,272 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use anyhow::anyhow;
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+use crate::errors::SkateError;
+
+const KUSTOMIZATION_FILES: &[&str] = &["kustomization.yaml", "kustomization.yml", "Kustomization"];
+
+// the fields of the subset of kustomize that skate evaluates itself, enough for most public bases
+const SUPPORTED_FIELDS: &[&str] = &["apiVersion", "kind", "resources", "namespace", "commonLabels", "images"];
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Kustomization {
+    #[serde(default)]
+    resources: Vec<String>,
+    namespace: Option<String>,
+    #[serde(default)]
+    common_labels: BTreeMap<String, String>,
+    #[serde(default)]
+    images: Vec<ImageOverride>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct ImageOverride {
+    name: String,
+    new_name: Option<String>,
+    new_tag: Option<String>,
+    digest: Option<String>,
+}
+
+// the kinds whose objects aren't namespaced
+const CLUSTER_SCOPED_KINDS: &[&str] = &["ClusterIssuer"];
+
+// the mapping at the path, with the ones missing along it created when create is set. only labels are added where
+// there were none, a selector that isn't there has to stay missing, a service without one selects nothing itself
+fn mapping_mut<'a>(value: &'a mut Value, path: &[&str], create: bool) -> Option<&'a mut Mapping> {
+    let mut value = value;
+    for key in path {
+        let mapping = value.as_mapping_mut()?;
+        value = match create {
+            true => mapping.entry(Value::from(*key)).or_insert(Value::Mapping(Mapping::new())),
+            false => mapping.get_mut(*key)?,
+        };
+    }
+    value.as_mapping_mut()
+}
+
+// fails on what skate doesn't evaluate, rather than applying the manifests without it
+fn check_supported(kustomization: &Value) -> Result<(), String> {
+    let unsupported: Vec<_> = kustomization.as_mapping().into_iter().flat_map(|m| m.keys())
+        .filter_map(|k| k.as_str())
+        .filter(|k| !SUPPORTED_FIELDS.contains(k))
+        .collect();
+    match unsupported.is_empty() {
+        true => Ok(()),
+        false => Err(format!("{} isn't supported, only {} are", unsupported.join(", "), SUPPORTED_FIELDS[2..].join(", "))),
+    }
+}
+
+// where the pod template's metadata and spec are, by kind
+fn pod_template_path(kind: &str) -> Option<&'static [&'static str]> {
+    match kind {
+        "Deployment" | "DaemonSet" | "Job" => Some(&["spec", "template"]),
+        "CronJob" => Some(&["spec", "jobTemplate", "spec", "template"]),
+        "Pod" => Some(&[]),
+        _ => None,
+    }
+}
+
+// splits an image into its name and what follows it, the tag or digest
+fn split_image(image: &str) -> (&str, &str) {
+    if let Some(at) = image.find('@') {
+        return image.split_at(at);
+    }
+    match image.rfind(':') {
+        Some(colon) if !image[colon..].contains('/') => image.split_at(colon),
+        _ => (image, ""),
+    }
+}
+
+fn override_image(image: &str, overrides: &[ImageOverride]) -> Option<String> {
+    let (name, suffix) = split_image(image);
+    let o = overrides.iter().find(|o| o.name == name)?;
+    let name = o.new_name.as_deref().unwrap_or(name);
+    let suffix = match (o.digest.as_ref(), o.new_tag.as_ref()) {
+        (Some(digest), _) => format!("@{}", digest),
+        (None, Some(tag)) => format!(":{}", tag),
+        (None, None) => suffix.to_string(),
+    };
+    Some(format!("{}{}", name, suffix))
+}
+
+impl Kustomization {
+    fn apply(&self, manifest: &mut Value) {
+        let kind = manifest["kind"].as_str().unwrap_or_default().to_string();
+        if let (Some(namespace), false) = (self.namespace.as_ref(), CLUSTER_SCOPED_KINDS.contains(&kind.as_str())) {
+            if let Some(meta) = mapping_mut(manifest, &["metadata"], true) {
+                meta.insert(Value::from("namespace"), Value::from(namespace.as_str()));
+            }
+        }
+
+        // like kustomize, the labels also go in the selectors so they only match the labelled pods
+        let mut label_paths: Vec<(Vec<&str>, bool)> = vec![(vec!["metadata", "labels"], true)];
+        match kind.as_str() {
+            "Deployment" | "DaemonSet" => label_paths.push((vec!["spec", "selector", "matchLabels"], false)),
+            "Service" => label_paths.push((vec!["spec", "selector"], false)),
+            _ => {}
+        }
+        let template = pod_template_path(&kind);
+        if let Some(template) = template.filter(|t| !t.is_empty()) {
+            label_paths.push(([template, &["metadata", "labels"]].concat(), true));
+        }
+        if !self.common_labels.is_empty() {
+            for (path, create) in label_paths {
+                if let Some(labels) = mapping_mut(manifest, &path, create) {
+                    for (k, v) in self.common_labels.iter() {
+                        labels.insert(Value::from(k.as_str()), Value::from(v.as_str()));
+                    }
+                }
+            }
+        }
+
+        let spec = match template {
+            Some(template) => [template, &["spec"]].concat(),
+            None => return,
+        };
+        if self.images.is_empty() {
+            return;
+        }
+        if let Some(spec) = mapping_mut(manifest, &spec, false) {
+            for key in ["containers", "initContainers"] {
+                for container in spec.get_mut(key).and_then(|c| c.as_sequence_mut()).into_iter().flatten() {
+                    let image = container["image"].as_str().and_then(|i| override_image(i, &self.images));
+                    if let (Some(image), Some(container)) = (image, container.as_mapping_mut()) {
+                        container.insert(Value::from("image"), Value::from(image));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn read_documents(path: &Path) -> Result<Vec<Value>, SkateError> {
+    let contents = fs::read_to_string(path).map_err(|e| anyhow!(e).context(format!("failed to read {}", path.display())))?;
+    let mut documents = vec![];
+    for document in serde_yaml::Deserializer::from_str(&contents) {
+        let value = Value::deserialize(document).map_err(|e| anyhow!(e).context(format!("failed to parse {}", path.display())))?;
+        if value.is_mapping() {
+            documents.push(value);
+        }
+    }
+    Ok(documents)
+}
+
+// the manifests of the kustomization in the directory, with its bases built first
+pub fn build(dir: &Path) -> Result<Vec<Value>, SkateError> {
+    let file = KUSTOMIZATION_FILES.iter().map(|f| dir.join(f)).find(|f| f.is_file())
+        .ok_or(anyhow!("no kustomization.yaml in {}", dir.display()))?;
+    let contents = fs::read_to_string(&file).map_err(|e| anyhow!(e).context(format!("failed to read {}", file.display())))?;
+    let value: Value = serde_yaml::from_str(&contents).map_err(|e| anyhow!(e).context(format!("failed to parse {}", file.display())))?;
+    check_supported(&value).map_err(|e| anyhow!("{}: {}", file.display(), e))?;
+    let kustomization: Kustomization = serde_yaml::from_value(value).map_err(|e| anyhow!(e).context(format!("failed to parse {}", file.display())))?;
+
+    let mut manifests = vec![];
+    for resource in kustomization.resources.iter() {
+        if resource.contains("://") {
+            return Err(anyhow!("{}: remote resource {} isn't supported, download it first", file.display(), resource).into());
+        }
+        let path = dir.join(resource);
+        match path.is_dir() {
+            true => manifests.extend(build(&path)?),
+            false => manifests.extend(read_documents(&path)?),
+        }
+    }
+    manifests.iter_mut().for_each(|m| kustomization.apply(m));
+    Ok(manifests)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::kustomize::{build, override_image, ImageOverride};
+    use crate::test_helpers::temp_dir::TempDir;
+
+    #[test]
+    fn test_override_image() {
+        let overrides = vec![
+            ImageOverride { name: "nginx".to_string(), new_tag: Some("1.27".to_string()), ..Default::default() },
+            ImageOverride { name: "localhost:5000/app".to_string(), new_name: Some("registry.example.com/app".to_string()), ..Default::default() },
+        ];
+        assert_eq!(Some("nginx:1.27".to_string()), override_image("nginx:1.25", &overrides));
+        assert_eq!(Some("registry.example.com/app:2".to_string()), override_image("localhost:5000/app:2", &overrides));
+        assert_eq!(Some("registry.example.com/app".to_string()), override_image("localhost:5000/app", &overrides));
+        assert_eq!(None, override_image("redis:7", &overrides));
+    }
+
+    #[test]
+    fn test_build() {
+        let dir = TempDir::new("kustomize");
+        fs::create_dir_all(dir.join("base")).unwrap();
+        fs::create_dir_all(dir.join("prod")).unwrap();
+        fs::write(dir.join("base/kustomization.yaml"), "resources: [web.yaml]\ncommonLabels:\n  app: web\n").unwrap();
+        fs::write(dir.join("base/web.yaml"), r#"
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: web
+spec:
+  selector:
+    matchLabels:
+      tier: frontend
+  template:
+    spec:
+      containers:
+        - name: web
+          image: nginx:1.25
+---
+apiVersion: v1
+kind: Service
+metadata:
+  name: web
+spec:
+  selector:
+    tier: frontend
+---
+apiVersion: v1
+kind: Service
+metadata:
+  name: upstream
+spec:
+  type: ExternalName
+  externalName: upstream.example.com
+"#).unwrap();
+        fs::write(dir.join("prod/kustomization.yaml"), r#"
+apiVersion: kustomize.config.k8s.io/v1beta1
+kind: Kustomization
+resources: [../base]
+namespace: shop
+commonLabels:
+  env: prod
+images:
+  - name: nginx
+    newTag: "1.27"
+"#).unwrap();
+
+        fs::create_dir_all(dir.join("patched")).unwrap();
+        fs::write(dir.join("patched/kustomization.yaml"), "resources: [../base]\npatches:\n  - path: replicas.yaml\n").unwrap();
+
+        let manifests = build(&dir.join("prod")).unwrap();
+        let patched = build(&dir.join("patched"));
+
+        assert!(patched.unwrap_err().to_string().contains("patches isn't supported"));
+        assert_eq!(3, manifests.len());
+        let deployment = &manifests[0];
+        assert_eq!("shop", deployment["metadata"]["namespace"].as_str().unwrap());
+        assert_eq!("web", deployment["metadata"]["labels"]["app"].as_str().unwrap());
+        assert_eq!("prod", deployment["metadata"]["labels"]["env"].as_str().unwrap());
+        assert_eq!("prod", deployment["spec"]["selector"]["matchLabels"]["env"].as_str().unwrap());
+        assert_eq!("frontend", deployment["spec"]["selector"]["matchLabels"]["tier"].as_str().unwrap());
+        assert_eq!("web", deployment["spec"]["template"]["metadata"]["labels"]["app"].as_str().unwrap());
+        assert_eq!("nginx:1.27", deployment["spec"]["template"]["spec"]["containers"][0]["image"].as_str().unwrap());
+        assert_eq!("prod", manifests[1]["spec"]["selector"]["env"].as_str().unwrap());
+        assert_eq!("prod", manifests[2]["metadata"]["labels"]["env"].as_str().unwrap());
+        assert!(manifests[2]["spec"].get("selector").is_none());
+
+        assert!(build(&dir.join("missing")).is_err());
+    }
+}
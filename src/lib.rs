@@ -29,10 +29,56 @@ mod rollout;
 mod resource;
 mod exec;
 pub mod deps;
+#[cfg(test)]
 mod test_helpers;
 mod upgrade;
 mod github;
 mod node_shell;
+mod image_build;
+mod cp;
+mod rebalance;
+mod evict;
+mod addon;
+mod schedule;
+mod snapshot;
+mod attach;
+mod events;
+mod lock;
+mod node;
+mod run;
+mod notify;
+mod sync;
+mod ps;
+mod metrics;
+mod patch;
+mod edit;
+mod image;
+mod policy;
+mod progress;
+mod report;
+mod confirm;
+mod interactive;
+mod ingress_class;
+mod explain;
+mod conversion;
+mod priority;
+mod capacity;
+mod pod_spec;
+mod credentials;
+mod affinity;
+mod limit_range;
+mod logging;
+mod node_pool;
+mod defaults;
+mod overlay;
+mod gpu;
+mod host_network;
+mod sysctl;
+mod kustomize;
+mod generate_name;
+mod plugin;
+pub mod informer;
+pub mod client;
 
 pub use skate::skate;
 pub use skate::AllDeps;
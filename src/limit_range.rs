@@ -0,0 +1,201 @@
+use std::collections::{BTreeMap, HashSet};
+use anyhow::anyhow;
+use k8s_openapi::api::core::v1::{Container, ResourceRequirements};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use serde::{Deserialize, Serialize};
+use crate::errors::SkateError;
+use crate::pod_spec::pod_spec_mut;
+use crate::resource::SupportedResources;
+use crate::util::{quantity_to_bytes, quantity_to_cpus};
+
+const RESOURCES: &[&str] = &["cpu", "memory"];
+
+// the default requests and limits for pods in a namespace that don't set their own, and the bounds they have to stay within
+#[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LimitRange {
+    pub namespace: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub default_request: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub default_limit: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub min: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub max: BTreeMap<String, String>,
+}
+
+// cpus and bytes are compared as floats, which is precise enough for either
+fn parse(resource: &str, quantity: &str) -> Option<f64> {
+    match resource {
+        "cpu" => quantity_to_cpus(quantity),
+        _ => quantity_to_bytes(quantity).map(|b| b as f64),
+    }
+}
+
+pub fn validate(ranges: &[LimitRange]) -> Result<(), SkateError> {
+    let mut errors = vec![];
+    let mut namespaces = HashSet::new();
+    for range in ranges {
+        for (field, values) in [("defaultRequest", &range.default_request), ("defaultLimit", &range.default_limit), ("min", &range.min), ("max", &range.max)] {
+            for (resource, quantity) in values {
+                if !RESOURCES.contains(&resource.as_str()) {
+                    errors.push(format!("limit range {}: {}: unsupported resource {}, expected cpu or memory", range.namespace, field, resource));
+                } else if parse(resource, quantity).is_none() {
+                    errors.push(format!("limit range {}: {}: invalid {} quantity {}", range.namespace, field, resource, quantity));
+                }
+            }
+        }
+        if !namespaces.insert(&range.namespace) {
+            errors.push(format!("limit range {} is declared more than once", range.namespace));
+        }
+    }
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(anyhow!("invalid limit ranges:\n{}", errors.join("\n")).into()),
+    }
+}
+
+// fills in the container's missing requests and limits, a missing request defaults to the limit like kubernetes does
+fn apply_defaults(range: &LimitRange, resources: &mut ResourceRequirements) {
+    for resource in RESOURCES {
+        let limits = resources.limits.get_or_insert_with(Default::default);
+        if let (None, Some(default)) = (limits.get(*resource), range.default_limit.get(*resource)) {
+            limits.insert(resource.to_string(), Quantity(default.clone()));
+        }
+        let limit = limits.get(*resource).cloned();
+        let requests = resources.requests.get_or_insert_with(Default::default);
+        if requests.get(*resource).is_none() {
+            if let Some(default) = range.default_request.get(*resource).map(|d| Quantity(d.clone())).or(limit) {
+                requests.insert(resource.to_string(), default);
+            }
+        }
+    }
+    // no empty maps, so containers without any resources hash the same as before
+    resources.limits = resources.limits.take().filter(|l| !l.is_empty());
+    resources.requests = resources.requests.take().filter(|r| !r.is_empty());
+}
+
+fn check_container(range: &LimitRange, container: &Container) -> Vec<String> {
+    let mut errors = vec![];
+    let resources = container.resources.clone().unwrap_or_default();
+    for resource in RESOURCES {
+        let get = |values: &Option<BTreeMap<String, Quantity>>| values.as_ref().and_then(|v| v.get(*resource)).and_then(|q| parse(resource, &q.0));
+        let bound = |values: &BTreeMap<String, String>| values.get(*resource).map(|v| (v.clone(), parse(resource, v).unwrap_or_default()));
+        let (request, limit) = (get(&resources.requests), get(&resources.limits));
+
+        if let Some((max, max_value)) = bound(&range.max) {
+            match limit {
+                None => errors.push(format!("container {} has no {} limit, the namespace's maximum is {}", container.name, resource, max)),
+                Some(limit) if limit > max_value => errors.push(format!("container {} {} limit is over the namespace's maximum of {}", container.name, resource, max)),
+                _ => {}
+            }
+        }
+        if let Some((min, min_value)) = bound(&range.min) {
+            if request.unwrap_or_default() < min_value {
+                errors.push(format!("container {} {} request is under the namespace's minimum of {}", container.name, resource, min));
+            }
+        }
+        if let (Some(request), Some(limit)) = (request, limit) {
+            if request > limit {
+                errors.push(format!("container {} {} request is more than its limit", container.name, resource));
+            }
+        }
+    }
+    errors
+}
+
+// applies the namespace's limit range to the resource's pods, failing if their containers are outside of its bounds
+pub fn resolve(ranges: &[LimitRange], mut resource: SupportedResources) -> Result<SupportedResources, SkateError> {
+    let name = resource.name();
+    let range = match ranges.iter().find(|r| r.namespace == name.namespace) {
+        Some(range) => range,
+        None => return Ok(resource),
+    };
+    let spec = match pod_spec_mut(&mut resource) {
+        Some(spec) => spec,
+        None => return Ok(resource),
+    };
+
+    let mut errors = vec![];
+    for container in spec.containers.iter_mut().chain(spec.init_containers.iter_mut().flatten()) {
+        let mut resources = container.resources.clone().unwrap_or_default();
+        apply_defaults(range, &mut resources);
+        container.resources = Some(resources).filter(|r| r != &ResourceRequirements::default());
+        errors.extend(check_container(range, container));
+    }
+    match errors.is_empty() {
+        true => Ok(resource),
+        false => Err(anyhow!("{}: {}", name, errors.join(", ")).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use k8s_openapi::api::core::v1::{Container, Pod, PodSpec, ResourceRequirements};
+    use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use crate::limit_range::{resolve, validate, LimitRange};
+    use crate::resource::SupportedResources;
+    use crate::util::NamespacedName;
+
+    fn pod(namespace: &str, limits: &[(&str, &str)], requests: &[(&str, &str)]) -> SupportedResources {
+        let quantities = |values: &[(&str, &str)]| Some(values.iter().map(|(k, v)| (k.to_string(), Quantity(v.to_string()))).collect::<BTreeMap<_, _>>()).filter(|m| !m.is_empty());
+        SupportedResources::Pod(Pod {
+            metadata: ObjectMeta::from(NamespacedName::new("web", namespace)),
+            spec: Some(PodSpec {
+                containers: vec![Container {
+                    name: "app".to_string(),
+                    resources: Some(ResourceRequirements { limits: quantities(limits), requests: quantities(requests), ..Default::default() }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    }
+
+    fn resources(resource: &SupportedResources) -> ResourceRequirements {
+        match resource {
+            SupportedResources::Pod(p) => p.spec.clone().unwrap().containers[0].resources.clone().unwrap_or_default(),
+            _ => panic!("not a pod"),
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let ranges = vec![LimitRange {
+            namespace: "shop".to_string(),
+            default_request: BTreeMap::from([("cpu".to_string(), "100m".to_string())]),
+            default_limit: BTreeMap::from([("cpu".to_string(), "500m".to_string()), ("memory".to_string(), "256Mi".to_string())]),
+            min: BTreeMap::new(),
+            max: BTreeMap::from([("memory".to_string(), "1Gi".to_string())]),
+        }];
+        validate(&ranges).unwrap();
+
+        let resolved = resources(&resolve(&ranges, pod("shop", &[], &[])).unwrap());
+        assert_eq!("500m", resolved.limits.as_ref().unwrap()["cpu"].0);
+        assert_eq!("100m", resolved.requests.as_ref().unwrap()["cpu"].0);
+        // the request defaults to the limit without a default request
+        assert_eq!("256Mi", resolved.requests.as_ref().unwrap()["memory"].0);
+
+        // what the pod sets is kept
+        let resolved = resources(&resolve(&ranges, pod("shop", &[("memory", "512Mi")], &[])).unwrap());
+        assert_eq!("512Mi", resolved.limits.unwrap()["memory"].0);
+
+        let err = resolve(&ranges, pod("shop", &[("memory", "2Gi")], &[])).unwrap_err().to_string();
+        assert!(err.contains("memory limit is over the namespace's maximum of 1Gi"), "{}", err);
+        let err = resolve(&ranges, pod("shop", &[("cpu", "200m")], &[("cpu", "1")])).unwrap_err().to_string();
+        assert!(err.contains("cpu request is more than its limit"), "{}", err);
+
+        // other namespaces aren't touched
+        assert_eq!(ResourceRequirements::default(), resources(&resolve(&ranges, pod("default", &[], &[])).unwrap()));
+
+        let mut invalid = ranges.clone();
+        invalid[0].max.insert("gpu".to_string(), "1".to_string());
+        invalid.push(ranges[0].clone());
+        let err = validate(&invalid).unwrap_err().to_string();
+        assert!(err.contains("unsupported resource gpu") && err.contains("declared more than once"), "{}", err);
+    }
+}
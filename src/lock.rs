@@ -0,0 +1,329 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use anyhow::anyhow;
+use clap::{Args, Subcommand};
+use sysinfo::System;
+use tabled::settings::Style;
+use tabled::{Table, Tabled};
+use crate::config::{Access, Cluster, Config};
+use crate::confirm::{confirm, Target};
+use crate::deps::{SshManager, With};
+use crate::errors::SkateError;
+use crate::skate::ConfigFileArgs;
+use crate::skatelet::lock::{AcquireResult, LockInfo};
+use crate::ssh::{SshClient, SshClients};
+use crate::util::shell_quote;
+
+// overrides who the lock is taken by, eg with the identity from an sso login
+pub const LOCK_OWNER_ENV: &str = "SKATE_LOCK_OWNER";
+// long enough for a slow apply, short enough that a crashed one doesn't block others for long
+const LOCK_TTL_SECS: i64 = 1800;
+
+// commands run others, eg create node applies the cluster manifests, so only the outermost takes the lock
+static HELD: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Args)]
+pub struct LockArgs {
+    #[command(subcommand)]
+    command: LockCommands,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum LockCommands {
+    #[command(long_about = "Show who holds the cluster lock")]
+    Status(StatusArgs),
+    #[command(long_about = "Remove the cluster lock, eg after a command holding it was killed")]
+    Break(BreakArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct StatusArgs {
+    #[command(flatten)]
+    config: ConfigFileArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct BreakArgs {
+    #[arg(short, long, long_help = "Don't ask for confirmation.")]
+    yes: bool,
+    #[command(flatten)]
+    config: ConfigFileArgs,
+}
+
+pub trait LockDeps: With<dyn SshManager> {}
+
+pub struct Lock<D: LockDeps> {
+    pub deps: D,
+}
+
+#[derive(Tabled, Debug)]
+#[tabled(rename_all = "UPPERCASE")]
+struct LockRow {
+    node: String,
+    owner: String,
+    command: String,
+    acquired: String,
+    expires: String,
+}
+
+fn owner() -> String {
+    let user = std::env::var("USER").unwrap_or("unknown".to_string());
+    let owner = std::env::var(LOCK_OWNER_ENV).unwrap_or(format!("{}@{}", user, System::host_name().unwrap_or_default()));
+    // the pid tells apart two runs by the same person
+    format!("{} (pid {})", owner, std::process::id())
+}
+
+// the connected nodes, in the order of the cluster's config so that two runs try them in the same order
+fn lock_nodes<'a>(cluster: &Cluster, conns: &'a SshClients) -> Vec<&'a dyn SshClient> {
+    cluster.nodes.iter().filter_map(|n| conns.find(&n.name)).collect()
+}
+
+// skatelets from before the lock don't know the subcommand, which is in the stderr behind the failed command's context
+fn unsupported(err: &(dyn std::error::Error + 'static)) -> bool {
+    std::iter::successors(Some(err), |e| e.source()).any(|e| e.to_string().contains("unrecognized subcommand"))
+}
+
+// runs f while holding the cluster lock, failing straight away if someone else holds it
+pub async fn locked<T, F>(cluster: &Cluster, conns: &SshClients, command: &str, f: F) -> Result<T, SkateError>
+where
+    F: Future<Output=Result<T, SkateError>>,
+{
+    if HELD.swap(true, Ordering::SeqCst) {
+        return f.await;
+    }
+    let result = match acquire(cluster, conns, command).await {
+        Ok(nodes) => {
+            let result = f.await;
+            release(&nodes).await;
+            result
+        }
+        Err(e) => Err(e),
+    };
+    HELD.store(false, Ordering::SeqCst);
+    result
+}
+
+async fn release(nodes: &[&dyn SshClient]) {
+    let cmd = format!("sudo skatelet lock release --owner {}", shell_quote(&owner()));
+    for node in nodes {
+        if let Err(e) = node.execute(&cmd).await {
+            eprintln!("failed to release cluster lock on {}: {}", node.node_name(), e);
+        }
+    }
+}
+
+// takes the lock on every connected node, which has to be a majority of the nodes that have the lock so that two runs
+// that can each reach only part of the cluster can't both hold it. nodes whose skatelet predates the lock don't count
+async fn acquire<'a>(cluster: &Cluster, conns: &'a SshClients, command: &str) -> Result<Vec<&'a dyn SshClient>, SkateError> {
+    let cmd = format!("sudo skatelet lock acquire --owner {} --command {} --ttl {}", shell_quote(&owner()), shell_quote(command), LOCK_TTL_SECS);
+
+    let mut held = vec![];
+    let mut unsupported_nodes = vec![];
+    let mut failed = vec![];
+    for node in lock_nodes(cluster, conns) {
+        let output = match node.execute(&cmd).await {
+            Ok(output) => output,
+            Err(e) if unsupported(e.as_ref()) => {
+                unsupported_nodes.push(node.node_name().to_string());
+                continue;
+            }
+            Err(e) => {
+                failed.push(format!("{}: {}", node.node_name(), e));
+                continue;
+            }
+        };
+        let result: Result<AcquireResult, _> = serde_json::from_str(output.trim());
+        match result {
+            Ok(result) if result.acquired => held.push(node),
+            Ok(result) => {
+                release(&held).await;
+                return Err(locked_error(&cluster.name, &result.lock));
+            }
+            Err(e) => failed.push(format!("{}: failed to read cluster lock: {}", node.node_name(), e)),
+        }
+    }
+
+    if !unsupported_nodes.is_empty() {
+        eprintln!("WARNING: the skatelet on {} doesn't support the cluster lock, upgrade it with skate upgrade", unsupported_nodes.join(", "));
+    }
+    let lockable = cluster.nodes.len() - unsupported_nodes.len();
+    if lockable == 0 {
+        eprintln!("WARNING: no node supports the cluster lock, running {} WITHOUT it", command);
+        return Ok(held);
+    }
+    if held.len() * 2 <= lockable {
+        release(&held).await;
+        return Err(anyhow!("failed to take the cluster lock on a majority of cluster {}'s nodes, got {} of {}. {}",
+            cluster.name, held.len(), lockable, failed.join(", ")).into());
+    }
+    Ok(held)
+}
+
+fn locked_error(cluster: &str, lock: &LockInfo) -> SkateError {
+    anyhow!("cluster {} is locked by {} running {} since {}, until {}. wait for it to finish, or remove the lock with skate lock break",
+        cluster, lock.owner, lock.command, lock.acquired_at.format("%Y-%m-%d %H:%M:%S"), lock.expires_at.format("%H:%M:%S")).into()
+}
+
+impl<D: LockDeps> Lock<D> {
+    pub async fn lock(&self, args: LockArgs) -> Result<(), SkateError> {
+        match args.command {
+            LockCommands::Status(args) => self.status(args).await,
+            LockCommands::Break(args) => self.break_lock(args).await,
+        }
+    }
+
+    // the lock as each connected node has it, leaving out the nodes where it isn't held
+    async fn current(&self, config_args: &ConfigFileArgs, config: &Config, access: Access) -> Result<(SshClients, Vec<(String, LockInfo)>), SkateError> {
+        let cluster = config.authorized_cluster(config_args.context.clone(), access)?;
+        let (conns, _) = self.deps.get().cluster_connect(cluster).await;
+        let conns = conns.ok_or(anyhow!("failed to connect to any node"))?;
+
+        let mut locks = vec![];
+        for node in lock_nodes(cluster, &conns) {
+            let output = match node.execute("sudo skatelet lock status").await {
+                Ok(output) => output,
+                Err(e) if unsupported(e.as_ref()) => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let lock: Option<LockInfo> = serde_json::from_str(output.trim()).map_err(|e| anyhow!(e).context("failed to read cluster lock"))?;
+            if let Some(lock) = lock {
+                locks.push((node.node_name().to_string(), lock));
+            }
+        }
+        Ok((conns, locks))
+    }
+
+    async fn status(&self, args: StatusArgs) -> Result<(), SkateError> {
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let (_, locks) = self.current(&args.config, &config, Access::ReadOnly).await?;
+        if locks.is_empty() {
+            println!("cluster is not locked");
+            return Ok(());
+        }
+        let mut table = Table::new(locks.into_iter().map(|(node, lock)| LockRow {
+            node,
+            owner: lock.owner,
+            command: lock.command,
+            acquired: lock.acquired_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            expires: lock.expires_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+        }));
+        table.with(Style::empty());
+        println!("{}", table);
+        Ok(())
+    }
+
+    async fn break_lock(&self, args: BreakArgs) -> Result<(), SkateError> {
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let (conns, locks) = self.current(&args.config, &config, Access::Admin).await?;
+        if locks.is_empty() {
+            println!("cluster is not locked");
+            return Ok(());
+        }
+
+        let targets: Vec<_> = locks.iter().map(|(node, lock)| Target::new(node, "Lock", &format!("{} ({})", lock.owner, lock.command))).collect();
+        if !confirm("Are you sure you want to remove the cluster lock?", &targets, args.yes)? {
+            return Ok(());
+        }
+
+        for (node, lock) in locks {
+            let conn = conns.find(&node).ok_or(anyhow!("failed to connect to {}", node))?;
+            conn.execute("sudo skatelet lock break").await?;
+            println!("removed the cluster lock held by {} on {}", lock.owner, node);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Local};
+    use crate::config::Config;
+    use anyhow::anyhow;
+    use crate::lock::{acquire, locked_error, unsupported};
+    use crate::skatelet::lock::{AcquireResult, LockInfo};
+    use crate::ssh::{SshClient, SshClients};
+    use crate::test_helpers::ssh_mocks::{CommandLog, MockSshClient};
+
+    fn lock_info(owner: &str) -> LockInfo {
+        let now = Local::now();
+        LockInfo { owner: owner.to_string(), command: "apply".to_string(), acquired_at: now, expires_at: now + Duration::seconds(1800) }
+    }
+
+    fn node(name: &str, acquired: bool, commands: &CommandLog) -> Box<dyn SshClient> {
+        let result = AcquireResult { acquired, lock: lock_info("someone") };
+        let mut client = MockSshClient::new(name, false);
+        client.commands = commands.clone();
+        client.responses = vec![
+            ("sudo skatelet lock acquire".to_string(), serde_json::to_string(&result).unwrap()),
+            ("sudo skatelet lock release".to_string(), "".to_string()),
+        ];
+        Box::new(client)
+    }
+
+    fn released(commands: &CommandLog) -> Vec<String> {
+        commands.lock().unwrap().iter().filter(|(_, c)| c.starts_with("sudo skatelet lock release")).map(|(n, _)| n.clone()).collect()
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let err: Box<dyn std::error::Error> = anyhow!("error: unrecognized subcommand 'lock'").context("sudo skatelet lock status failed").into();
+        assert!(unsupported(err.as_ref()));
+        let err: Box<dyn std::error::Error> = anyhow!("permission denied").context("sudo skatelet lock status failed").into();
+        assert!(!unsupported(err.as_ref()));
+    }
+
+    #[tokio::test]
+    async fn test_acquire_majority() {
+        let config: Config = serde_yaml::from_str(r#"
+clusters:
+- name: prod
+  default_user: null
+  default_key: null
+  nodes:
+  - name: node-1
+    host: 10.0.0.1
+    subnet_cidr: 20.1.0.0/16
+  - name: node-2
+    host: 10.0.0.2
+    subnet_cidr: 20.2.0.0/16
+  - name: node-3
+    host: 10.0.0.3
+    subnet_cidr: 20.3.0.0/16
+"#).unwrap();
+        let cluster = &config.clusters[0];
+
+        // 2 of 3 is a majority, the unreachable node doesn't stop it
+        let commands = CommandLog::default();
+        let conns = SshClients { clients: vec![node("node-1", true, &commands), node("node-2", true, &commands)] };
+        let held = acquire(cluster, &conns, "apply").await.unwrap();
+        assert_eq!(vec!["node-1", "node-2"], held.iter().map(|n| n.node_name()).collect::<Vec<_>>());
+
+        // 1 of 3 isn't, and what was taken is given back
+        let commands = CommandLog::default();
+        let conns = SshClients { clients: vec![node("node-1", true, &commands)] };
+        let err = acquire(cluster, &conns, "apply").await.err().unwrap().to_string();
+        assert!(err.contains("got 1 of 3"), "{}", err);
+        assert_eq!(vec!["node-1"], released(&commands));
+
+        // someone else holding it on any node fails straight away
+        let commands = CommandLog::default();
+        let conns = SshClients { clients: vec![node("node-1", true, &commands), node("node-2", false, &commands), node("node-3", true, &commands)] };
+        let err = acquire(cluster, &conns, "apply").await.err().unwrap().to_string();
+        assert!(err.contains("cluster prod is locked by someone"), "{}", err);
+        assert_eq!(vec!["node-1"], released(&commands));
+    }
+
+    #[test]
+    fn test_locked_error() {
+        let now = Local::now();
+        let lock = LockInfo {
+            owner: "alice@laptop (pid 42)".to_string(),
+            command: "apply".to_string(),
+            acquired_at: now,
+            expires_at: now + Duration::seconds(1800),
+        };
+        let message = locked_error("prod", &lock).to_string();
+        assert!(message.contains("cluster prod is locked by alice@laptop (pid 42) running apply"), "{}", message);
+        assert!(message.contains("skate lock break"));
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+use crate::errors::SkateError;
+use crate::pod_spec::pod_template_mut;
+use crate::resource::SupportedResources;
+use crate::util::quantity_to_bytes;
+
+// set on pods from the cluster's logging defaults, a pod can set its own to override them
+pub const LOG_DRIVER_ANNOTATION: &str = "skate.io/log-driver";
+pub const LOG_MAX_SIZE_ANNOTATION: &str = "skate.io/log-max-size";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, Hash, PartialEq, Display, EnumString)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum LogDriver {
+    // rotated by journald's own limits
+    #[default]
+    Journald,
+    // a file per container, rotated once it reaches maxSize
+    JsonFile,
+}
+
+impl LogDriver {
+    fn podman_driver(&self) -> &'static str {
+        match self {
+            LogDriver::Journald => "journald",
+            LogDriver::JsonFile => "k8s-file",
+        }
+    }
+}
+
+// how podman keeps the logs of the cluster's containers
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Hash, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Logging {
+    #[serde(default)]
+    pub driver: LogDriver,
+    // eg 10Mi, only for json-file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<String>,
+}
+
+fn check(driver: LogDriver, max_size: Option<&str>) -> Result<Option<u64>, String> {
+    match (driver, max_size) {
+        (_, None) => Ok(None),
+        (LogDriver::Journald, Some(_)) => Err("maxSize only applies to the json-file driver, journald rotates its own logs".to_string()),
+        (LogDriver::JsonFile, Some(max_size)) => match quantity_to_bytes(max_size) {
+            Some(bytes) if bytes > 0 => Ok(Some(bytes)),
+            _ => Err(format!("invalid maxSize {}", max_size)),
+        },
+    }
+}
+
+pub fn validate(logging: &Logging) -> Result<(), SkateError> {
+    check(logging.driver, logging.max_size.as_deref()).map_err(|e| anyhow!("invalid logging: {}", e))?;
+    Ok(())
+}
+
+// annotates the resource's pods with the cluster's logging, unless they have their own
+pub fn resolve(logging: Option<&Logging>, mut resource: SupportedResources) -> SupportedResources {
+    let logging = match logging {
+        Some(logging) => logging,
+        None => return resource,
+    };
+    let meta = match resource {
+        SupportedResources::Pod(ref mut p) => &mut p.metadata,
+        _ => match pod_template_mut(&mut resource) {
+            Some(template) => template.metadata.get_or_insert_with(Default::default),
+            None => return resource,
+        },
+    };
+    let annotations = meta.annotations.get_or_insert_with(Default::default);
+    if !annotations.contains_key(LOG_DRIVER_ANNOTATION) {
+        annotations.insert(LOG_DRIVER_ANNOTATION.to_string(), logging.driver.to_string());
+        if let Some(max_size) = logging.max_size.as_ref() {
+            annotations.insert(LOG_MAX_SIZE_ANNOTATION.to_string(), max_size.clone());
+        }
+    }
+    resource
+}
+
+// the podman kube play arguments for the pod's annotations, podman's own default without any
+pub fn play_args(annotations: Option<&BTreeMap<String, String>>) -> Result<Vec<String>, SkateError> {
+    let annotations = match annotations {
+        Some(annotations) => annotations,
+        None => return Ok(vec![]),
+    };
+    let driver = match annotations.get(LOG_DRIVER_ANNOTATION) {
+        Some(driver) => driver.parse::<LogDriver>().map_err(|_| anyhow!("invalid {} {}, expected journald or json-file", LOG_DRIVER_ANNOTATION, driver))?,
+        None => return Ok(vec![]),
+    };
+    let max_size = check(driver, annotations.get(LOG_MAX_SIZE_ANNOTATION).map(|s| s.as_str())).map_err(|e| anyhow!("{}: {}", LOG_MAX_SIZE_ANNOTATION, e))?;
+
+    let mut args = vec![format!("--log-driver={}", driver.podman_driver())];
+    if let Some(max_size) = max_size {
+        args.push(format!("--log-opt=max-size={}", max_size));
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use k8s_openapi::api::core::v1::Pod;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use crate::logging::{play_args, resolve, validate, LogDriver, Logging, LOG_DRIVER_ANNOTATION};
+    use crate::resource::SupportedResources;
+    use crate::util::NamespacedName;
+
+    fn annotations(resource: &SupportedResources) -> BTreeMap<String, String> {
+        match resource {
+            SupportedResources::Pod(p) => p.metadata.annotations.clone().unwrap_or_default(),
+            _ => panic!("not a pod"),
+        }
+    }
+
+    #[test]
+    fn test_play_args() {
+        let logging = Logging { driver: LogDriver::JsonFile, max_size: Some("10Mi".to_string()) };
+        validate(&logging).unwrap();
+        let pod = SupportedResources::Pod(Pod { metadata: ObjectMeta::from(NamespacedName::new("web", "shop")), ..Default::default() });
+
+        let resolved = annotations(&resolve(Some(&logging), pod.clone()));
+        assert_eq!(vec!["--log-driver=k8s-file", "--log-opt=max-size=10485760"], play_args(Some(&resolved)).unwrap());
+
+        // the pod's own driver is kept
+        let mut own = pod.clone();
+        if let SupportedResources::Pod(p) = &mut own {
+            p.metadata.annotations = Some(BTreeMap::from([(LOG_DRIVER_ANNOTATION.to_string(), "journald".to_string())]));
+        }
+        let resolved = annotations(&resolve(Some(&logging), own));
+        assert_eq!(vec!["--log-driver=journald"], play_args(Some(&resolved)).unwrap());
+
+        assert!(play_args(Some(&annotations(&resolve(None, pod)))).unwrap().is_empty());
+        assert!(play_args(Some(&BTreeMap::from([(LOG_DRIVER_ANNOTATION.to_string(), "syslog".to_string())]))).is_err());
+        assert!(validate(&Logging { driver: LogDriver::Journald, max_size: Some("10Mi".to_string()) }).is_err());
+    }
+}
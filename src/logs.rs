@@ -1,14 +1,14 @@
 use anyhow::anyhow;
 use clap::Args;
 use futures::stream::FuturesUnordered;
-use crate::config::Config;
+use crate::config::{Access, Config};
 use crate::skate::ConfigFileArgs;
-use crate::ssh;
 use futures::StreamExt;
 use crate::deps::{SshManager, With};
 use crate::errors::SkateError;
 use crate::resource::ResourceType;
 use crate::ssh::{SshClients};
+use crate::util::{shell_quote, split_resource_identifier, NamespacedName};
 
 #[derive(Debug, Args)]
 #[command(arg_required_else_help(true))]
@@ -28,6 +28,22 @@ pub struct LogArgs {
 }
 
 impl LogArgs {
+    // skatelet reads the pods' logs the same way whichever log driver they use
+    pub fn to_skatelet_logs_args(&self, identifier: &str, ns: &str) -> Vec<String> {
+        let mut cmd: Vec<_> = ["sudo", "skatelet", "logs"].map(String::from).to_vec();
+
+        if self.follow {
+            cmd.push("--follow".to_string());
+        }
+        if self.tail > 0 {
+            cmd.push(format!("--tail {}", &self.tail));
+        }
+        cmd.push(format!("--namespace {}", shell_quote(ns)));
+        cmd.push(shell_quote(identifier));
+        cmd
+    }
+
+    // what skate ran before skatelet could read logs, for nodes with an older skatelet
     pub fn to_podman_log_args(&self) -> Vec<String> {
         let mut cmd: Vec<_> = ["sudo", "podman", "pod", "logs", "--names", "--timestamps"].map(String::from).to_vec();
 
@@ -35,12 +51,24 @@ impl LogArgs {
             cmd.push("--follow".to_string());
         }
         if self.tail > 0 {
-            let tail = format!("--tail {}", &self.tail);
-            cmd.push(tail);
+            cmd.push(format!("--tail {}", &self.tail));
         }
         cmd
     }
 
+    pub fn to_podman_fallback(&self, resource_type: &str, name: &str, ns: &str) -> String {
+        let log_cmd = self.to_podman_log_args().join(" ");
+        match resource_type {
+            "pod" => format!("{} {}", log_cmd, shell_quote(&format!("{}.{}", name, ns))),
+            _ => format!("for id in $(sudo podman pod ls --filter label=skate.io/{}={} --filter label=skate.io/namespace={} -q); do {} $id & done; wait", resource_type, shell_quote(name), shell_quote(ns), log_cmd),
+        }
+    }
+
+    pub fn to_pod_logs_command(&self, resource_type: &str, name: &str, ns: &str) -> String {
+        let skatelet = self.to_skatelet_logs_args(&format!("{}/{}", resource_type, name), ns).join(" ");
+        format!("if sudo skatelet logs --help >/dev/null 2>&1; then {}; else {}; fi", skatelet, self.to_podman_fallback(resource_type, name, ns))
+    }
+
     pub fn to_journalctl_args(&self) -> Vec<String> {
         let mut cmd: Vec<_> = ["sudo", "journalctl", "--output", "short-iso-precise", "--quiet"].map(String::from).to_vec();
 
@@ -65,44 +93,36 @@ pub struct Logs<D: LogsDeps> {
 
 impl<D:LogsDeps> Logs<D> {
     pub async fn logs(&self, args: LogArgs) -> Result<(), SkateError> {
-        let config = Config::load(Some(args.config.skateconfig.clone()))?;
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), Access::ReadOnly)?;
         let mgr = self.deps.get();
-        let (conns, errors) = mgr.cluster_connect(config.active_cluster(args.config.context.clone())?).await;
+        let (conns, errors) = mgr.cluster_connect(cluster).await;
 
 
         if conns.is_none() {
-            if errors.is_some() {
-                return Err(anyhow!(errors.unwrap().to_string()).into());
+            if let Some(errors) = errors {
+                return Err(anyhow!(errors.to_string()).into());
             }
             println!("No connections found");
             return Ok(());
         }
 
-        if errors.is_some() {
-            eprintln!("{}", errors.as_ref().unwrap())
+        if let Some(errors) = errors {
+            eprintln!("{}", errors)
         }
 
         let conns = conns.unwrap();
 
-        let name = args.identifier.clone();
-
-        let ns = args.namespace.clone().unwrap_or("default".to_string());
-
-        let (resource_type, name) = name.split_once("/").unwrap_or(("pod", &name));
-
+        let (resource_type, name) = split_resource_identifier(&args.identifier, &["deployment", "daemonset", "cronjob"]);
+        let name = NamespacedName::from_arg(name, args.namespace.as_deref(), Some(cluster.default_namespace()))?;
+        let ns = name.namespace.clone();
 
         match resource_type {
-            "pod" => {
-                self.log_pod(&conns, name, ns, &args).await
-            }
-            "deployment" => {
-                self.log_child_pods(&conns, ResourceType::Deployment, name, ns, &args).await
-            }
-            "daemonset" => {
-                self.log_child_pods(&conns, ResourceType::DaemonSet, name, ns, &args).await
+            "pod" | "deployment" | "daemonset" => {
+                self.log_pods(&conns, resource_type, &name.name, ns, &args).await
             }
             "cronjob" => {
-                self.log_journalctl(&conns, ResourceType::CronJob, name, ns, &args).await
+                self.log_journalctl(&conns, ResourceType::CronJob, &name.name, ns, &args).await
             }
             _ => {
                 Err(anyhow!("Unexpected resource type {}", resource_type).into())
@@ -110,32 +130,8 @@ impl<D:LogsDeps> Logs<D> {
         }
     }
 
-    pub async fn log_pod(&self, conns: &ssh::SshClients, name: &str, _ns: String, args: &LogArgs) -> Result<(), SkateError> {
-        let mut cmd = args.to_podman_log_args();
-
-        cmd.push(name.to_string());
-
-        let cmd = cmd.join(" ");
-
-        let fut: FuturesUnordered<_> = conns.clients.iter().map(|c| c.execute_stdout(&cmd, false, false)).collect();
-
-        let result: Vec<_> = fut.collect().await;
-
-        if result.iter().all(|r| r.is_err()) {
-            return Err(format!("{:?}", result.into_iter().map(|r| r.err().unwrap().to_string()).collect::<Vec<String>>()).into());
-        }
-
-        for res in result {
-            if let Err(e) = res { eprintln!("{}", e) }
-        }
-
-        Ok(())
-    }
-
-    pub async fn log_child_pods(&self, conns: &SshClients, resource_type: ResourceType, name: &str, ns: String, args: &LogArgs) -> Result<(), SkateError> {
-        let log_cmd = args.to_podman_log_args().join(" ");
-
-        let cmd = format!("for id in $(sudo podman pod ls --filter label=skate.io/{}={} --filter label=skate.io/namespace={} -q); do {} $id & done; wait;", resource_type.to_string().to_lowercase(), name, ns, log_cmd);
+    pub async fn log_pods(&self, conns: &SshClients, resource_type: &str, name: &str, ns: String, args: &LogArgs) -> Result<(), SkateError> {
+        let cmd = args.to_pod_logs_command(resource_type, name, &ns);
 
         let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(100);
 
@@ -161,7 +157,7 @@ impl<D:LogsDeps> Logs<D> {
     }
     pub async fn log_journalctl(&self, conns: &SshClients, resource_type: ResourceType, name: &str, ns: String, args: &LogArgs) -> Result<(), SkateError> {
         let mut cmd = args.to_journalctl_args();
-        cmd.push(format!("skate-{}-{}.{}.service", resource_type.to_string(), name, ns));
+        cmd.push(format!("skate-{}-{}.{}.service", resource_type, name, ns));
 
         let cmd = cmd.join(" ");
 
@@ -189,3 +185,58 @@ impl<D:LogsDeps> Logs<D> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use crate::deps::{SshManager, With};
+    use crate::logs::{LogArgs, Logs, LogsDeps};
+    use crate::test_helpers::ssh_mocks::MockSshManager;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: LogArgs,
+    }
+
+    #[test]
+    fn test_pod_logs_command() {
+        let args = Cli::parse_from(["logs", "--follow", "--tail", "10", "x"]).args;
+        assert_eq!(
+            "if sudo skatelet logs --help >/dev/null 2>&1; then sudo skatelet logs --follow --tail 10 --namespace 'shop' 'pod/web'; \
+else sudo podman pod logs --names --timestamps --follow --tail 10 'web.shop'; fi",
+            args.to_pod_logs_command("pod", "web", "shop")
+        );
+        assert_eq!(
+            "for id in $(sudo podman pod ls --filter label=skate.io/deployment='api' --filter label=skate.io/namespace='shop' -q); do sudo podman pod logs --names --timestamps --follow --tail 10 $id & done; wait",
+            args.to_podman_fallback("deployment", "api", "shop")
+        );
+    }
+
+    struct TestDeps {}
+
+    impl With<dyn SshManager> for TestDeps {
+        fn get(&self) -> Box<dyn SshManager> {
+            Box::new(MockSshManager::default())
+        }
+    }
+
+    impl LogsDeps for TestDeps {}
+
+    #[tokio::test]
+    async fn test_log_pods() {
+        let args = Cli::parse_from(["logs", "x"]).args;
+        let logs = Logs { deps: TestDeps {} };
+        let cmd = args.to_pod_logs_command("pod", "web", "shop");
+
+        // a node without the pod doesn't fail the others
+        let running = MockSshManager { responses: vec![(cmd.clone(), "hello\nworld".to_string())], ..Default::default() };
+        let mut conns = running.clients(&["node-1"]);
+        conns.clients.extend(MockSshManager::default().clients(&["node-2"]).clients);
+        assert!(logs.log_pods(&conns, "pod", "web", "shop".to_string(), &args).await.is_ok());
+        assert_eq!(vec![("node-1".to_string(), cmd)], *running.commands.lock().unwrap());
+
+        let conns = MockSshManager::default().clients(&["node-1", "node-2"]);
+        assert!(logs.log_pods(&conns, "pod", "web", "shop".to_string(), &args).await.is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use clap::{Args, Subcommand};
+use k8s_openapi::api::apps::v1::Deployment;
+use crate::config::{Access, Config};
+use crate::deps::{SshManager, With};
+use crate::errors::SkateError;
+use crate::refresh::{Refresh, RefreshDeps};
+use crate::resource::ResourceType;
+use crate::skate::ConfigFileArgs;
+use crate::skatelet::system::podman::PodmanPodStatus;
+use crate::state::state::{ClusterState, NodeStatus};
+use crate::util::NamespacedName;
+
+const BYTES_IN_MIB: f64 = 1024.0 * 1024.0;
+
+#[derive(Debug, Args)]
+pub struct MetricsArgs {
+    #[command(subcommand)]
+    command: MetricsCommands,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MetricsCommands {
+    #[command(about = "print the cluster state in OpenMetrics text format")]
+    Export(ExportArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    #[command(flatten)]
+    config: ConfigFileArgs,
+}
+
+pub trait MetricsDeps: With<dyn SshManager> {}
+
+pub struct Metrics<D: MetricsDeps + RefreshDeps> {
+    pub deps: D,
+}
+
+impl<D: MetricsDeps + RefreshDeps> Metrics<D> {
+    pub async fn metrics(&self, args: MetricsArgs) -> Result<(), SkateError> {
+        match args.command {
+            MetricsCommands::Export(args) => self.export(args).await,
+        }
+    }
+
+    async fn export(&self, args: ExportArgs) -> Result<(), SkateError> {
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), Access::ReadOnly)?;
+
+        let mgr = self.deps.get();
+        let (conns, errors) = mgr.cluster_connect(cluster).await;
+        if let Some(errors) = errors {
+            eprintln!("{}", errors)
+        }
+        let conns = conns.ok_or("failed to get cluster connections".to_string())?;
+
+        let state = Refresh::<D>::refreshed_state(&cluster.name, &conns, &config).await?;
+
+        print!("{}", render_openmetrics(&state));
+        Ok(())
+    }
+}
+
+struct MetricFamily {
+    name: &'static str,
+    help: &'static str,
+    samples: Vec<(Vec<(&'static str, String)>, f64)>,
+}
+
+impl MetricFamily {
+    fn new(name: &'static str, help: &'static str) -> Self {
+        MetricFamily { name, help, samples: vec!() }
+    }
+
+    fn sample(&mut self, labels: Vec<(&'static str, String)>, value: f64) {
+        self.samples.push((labels, value));
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// render_openmetrics renders node and deployment gauges for the cluster, terminated by the `# EOF` marker
+pub fn render_openmetrics(state: &ClusterState) -> String {
+    let mut healthy = MetricFamily::new("skate_node_healthy", "Whether the node is reachable and healthy.");
+    let mut schedulable = MetricFamily::new("skate_node_schedulable", "Whether new workloads can be scheduled on the node.");
+    let mut pods = MetricFamily::new("skate_node_pods", "Number of pods on the node.");
+    let mut memory_total = MetricFamily::new("skate_node_memory_total_bytes", "Total memory of the node.");
+    let mut memory_used = MetricFamily::new("skate_node_memory_used_bytes", "Used memory of the node.");
+    let mut pod_cpu = MetricFamily::new("skate_pod_cpu_percent", "Cpu used by the pod's containers, in percent of one cpu.");
+    let mut pod_memory = MetricFamily::new("skate_pod_memory_used_bytes", "Memory used by the pod's containers.");
+
+    for node in &state.nodes {
+        let labels = vec![("node", node.node_name.clone())];
+        healthy.sample(labels.clone(), (node.status == NodeStatus::Healthy) as u8 as f64);
+        schedulable.sample(labels.clone(), node.schedulable() as u8 as f64);
+
+        let si = node.host_info.as_ref().and_then(|h| h.system_info.as_ref());
+        pods.sample(labels.clone(), si.and_then(|si| si.pods.as_ref()).map(|p| p.len()).unwrap_or(0) as f64);
+        if let Some(si) = si {
+            memory_total.sample(labels.clone(), si.total_memory_mib as f64 * BYTES_IN_MIB);
+            memory_used.sample(labels, si.used_memory_mib as f64 * BYTES_IN_MIB);
+            for pod in si.pods.iter().flatten() {
+                if let Some(stats) = si.pod_stats(&pod.id) {
+                    let labels = vec![("node", node.node_name.clone()), ("namespace", pod.namespace()), ("pod", pod.name.clone())];
+                    pod_cpu.sample(labels.clone(), stats.cpu_percent);
+                    pod_memory.sample(labels, stats.memory_bytes as f64);
+                }
+            }
+        }
+    }
+
+    let mut replicas = MetricFamily::new("skate_deployment_replicas", "Number of replicas requested by the deployment.");
+    let mut ready = MetricFamily::new("skate_deployment_ready_replicas", "Number of running pods of the deployment.");
+    let mut ready_ratio = MetricFamily::new("skate_deployment_ready_ratio", "Ratio of running pods to requested replicas.");
+
+    // desired replicas come from the stored manifests, readiness from the pods across all nodes
+    let mut deployments: BTreeMap<NamespacedName, (u32, u32)> = state.catalogue(None, &[ResourceType::Deployment]).into_iter().map(|item| {
+        let desired = item.object.manifest.clone()
+            .and_then(|m| serde_yaml::from_value::<Deployment>(m).ok())
+            .and_then(|d| d.spec?.replicas)
+            .unwrap_or(0);
+        (item.object.name.clone(), (desired.max(0) as u32, 0))
+    }).collect();
+
+    for (pod, _) in state.filter_pods(&|p| !p.deployment().is_empty() && p.status == PodmanPodStatus::Running) {
+        if let Some((_, running)) = deployments.get_mut(&NamespacedName::new(&pod.deployment(), &pod.namespace())) {
+            *running += 1;
+        }
+    }
+
+    for (name, (desired, running)) in deployments {
+        let labels = vec![("namespace", name.namespace.clone()), ("deployment", name.name.clone())];
+        replicas.sample(labels.clone(), desired as f64);
+        ready.sample(labels.clone(), running as f64);
+        ready_ratio.sample(labels, match desired {
+            0 => 1.0,
+            _ => running as f64 / desired as f64,
+        });
+    }
+
+    let families = [healthy, schedulable, pods, memory_total, memory_used, pod_cpu, pod_memory, replicas, ready, ready_ratio];
+
+    let mut out = String::new();
+    for family in families {
+        let _ = writeln!(out, "# TYPE {} gauge", family.name);
+        let _ = writeln!(out, "# HELP {} {}", family.name, family.help);
+        for (labels, value) in family.samples {
+            let labels = labels.iter().map(|(k, v)| format!("{}=\"{}\"", k, escape_label_value(v))).collect::<Vec<_>>().join(",");
+            let _ = writeln!(out, "{}{{{}}} {}", family.name, labels, value);
+        }
+    }
+    out.push_str("# EOF\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::apps::v1::{Deployment, DeploymentSpec};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use crate::filestore::ObjectListItem;
+    use crate::metrics::render_openmetrics;
+    use crate::skatelet::system::podman::PodmanPodStatus;
+    use crate::skatelet::system::PodStats;
+    use crate::state::state::NodeStatus;
+    use crate::test_helpers::objects::{cluster_state, node_state, pod, WithPod};
+    use crate::util::NamespacedName;
+
+    #[test]
+    fn test_render_openmetrics() {
+        let mut node1 = node_state("node-1").with_pod(&pod("dpl-web-0", "default", &[("skate.io/deployment", "web")]));
+        let si = node1.host_info.as_mut().unwrap().system_info.as_mut().unwrap();
+        si.pods.as_mut().unwrap()[0].status = PodmanPodStatus::Running;
+        si.pod_stats = Some(vec![PodStats { pod_id: si.pods.as_ref().unwrap()[0].id.clone(), cpu_percent: 12.5, memory_bytes: 1024 }]);
+        let deployment = Deployment {
+            metadata: ObjectMeta::from(NamespacedName::new("web", "default")),
+            spec: Some(DeploymentSpec { replicas: Some(2), ..Default::default() }),
+            status: None,
+        };
+        let mut item = ObjectListItem::from(&deployment);
+        item.manifest = Some(serde_yaml::to_value(&deployment).unwrap());
+        si.deployments = Some(vec![item]);
+
+        let mut node2 = node_state("node-2");
+        node2.status = NodeStatus::Unhealthy;
+        node2.host_info = None;
+
+        let state = cluster_state(vec![node1, node2]);
+        let output = render_openmetrics(&state);
+
+        assert!(output.contains("# TYPE skate_node_healthy gauge\n"));
+        assert!(output.contains("skate_node_healthy{node=\"node-1\"} 1\n"));
+        assert!(output.contains("skate_node_healthy{node=\"node-2\"} 0\n"));
+        assert!(output.contains("skate_node_pods{node=\"node-1\"} 1\n"));
+        assert!(output.contains("skate_node_pods{node=\"node-2\"} 0\n"));
+        assert!(output.contains("skate_pod_cpu_percent{node=\"node-1\",namespace=\"default\",pod=\"dpl-web-0.default\"} 12.5\n"), "{}", output);
+        assert!(output.contains("skate_pod_memory_used_bytes{node=\"node-1\",namespace=\"default\",pod=\"dpl-web-0.default\"} 1024\n"));
+        assert!(output.contains("skate_deployment_replicas{namespace=\"default\",deployment=\"web\"} 2\n"));
+        assert!(output.contains("skate_deployment_ready_replicas{namespace=\"default\",deployment=\"web\"} 1\n"));
+        assert!(output.contains("skate_deployment_ready_ratio{namespace=\"default\",deployment=\"web\"} 0.5\n"));
+        assert!(output.ends_with("# EOF\n"));
+    }
+}
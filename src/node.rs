@@ -0,0 +1,592 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use anyhow::anyhow;
+use clap::{Args, Subcommand};
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use tabled::settings::Style;
+use tabled::{Table, Tabled};
+use crate::apply::check_policy;
+use crate::config::{Access, Cluster, Config};
+use crate::confirm::{confirm, Target};
+use crate::credentials::Become;
+use crate::deps::{SshManager, With};
+use crate::errors::SkateError;
+use crate::evict::{evict_pod, grace_period, owner_resource};
+use crate::lock;
+use crate::pod_spec::pod_spec_mut;
+use crate::refresh::{Refresh, RefreshDeps};
+use crate::resource::ResourceType;
+use crate::scheduler::{DefaultScheduler, Scheduler};
+use crate::skate::ConfigFileArgs;
+use crate::skatelet::HEALTHZ_SOCKET;
+use crate::skatelet::system::podman::PodmanPodInfo;
+use crate::ssh::{BatchResult, SshClient};
+use crate::state::state::{ClusterState, OwnerRef};
+use crate::util::{shell_quote, CHECKBOX_EMOJI};
+
+// clock skew past which certificates, cron schedules and the age of resources can't be trusted
+const MAX_CLOCK_SKEW_SECS: f64 = 2.0;
+
+#[derive(Debug, Args)]
+pub struct NodeArgs {
+    #[command(subcommand)]
+    command: NodeCommands,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum NodeCommands {
+    #[command(long_about = "Check that nodes are reachable and set up to run skate")]
+    Ping(PingArgs),
+    #[command(long_about = "Patch the nodes' OS one node at a time: cordon, drain, upgrade, reboot, wait for it and uncordon")]
+    UpdateOs(UpdateOsArgs),
+    #[command(long_about = "Restart the skatelet, cluster dns and ingress on nodes, checking each is healthy before the next")]
+    RestartServices(RestartServicesArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct PingArgs {
+    #[command(flatten)]
+    config: ConfigFileArgs,
+    #[arg(long_help = "Nodes to check, all of them if none are given.")]
+    nodes: Vec<String>,
+}
+
+// apt on debian and ubuntu, dnf on fedora and the rhel family. apt keeps the installed config files, like the ones
+// skate writes, rather than stopping to ask whether to replace them
+const DEFAULT_UPDATE_COMMAND: &str = "if command -v apt-get >/dev/null; then \
+DEBIAN_FRONTEND=noninteractive apt-get update && DEBIAN_FRONTEND=noninteractive apt-get -y -o Dpkg::Options::=--force-confold upgrade; \
+elif command -v dnf >/dev/null; then dnf -y upgrade; \
+else echo 'no apt-get or dnf found, pass --command' >&2; exit 1; fi";
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum RebootPolicy {
+    Always,
+    // when the upgrade says so, /var/run/reboot-required on debian and ubuntu, needs-restarting -r on dnf systems
+    IfRequired,
+    Never,
+}
+
+#[derive(Debug, Args)]
+pub struct UpdateOsArgs {
+    #[command(flatten)]
+    config: ConfigFileArgs,
+    #[arg(long_help = "Nodes to update, in the order given, all of them if none are given.")]
+    nodes: Vec<String>,
+    #[arg(long, long_help = "The command that upgrades the node, run as root. Defaults to apt-get or dnf upgrade, whichever the node has.")]
+    command: Option<String>,
+    #[arg(long, value_enum, default_value_t = RebootPolicy::Always, long_help = "When to reboot a node after upgrading it.")]
+    reboot: RebootPolicy,
+    #[arg(long, default_value_t = 600, long_help = "Seconds to wait for a rebooted node to come back.")]
+    timeout: u64,
+    #[arg(long, long_help = "Seconds to wait for each drained pod to stop, defaults to its terminationGracePeriodSeconds.")]
+    grace_period: Option<usize>,
+    #[arg(long, long_help = "Will not affect the cluster if set to true")]
+    dry_run: bool,
+    #[arg(long, long_help = "Reschedule the drained pods even if their deployments violate the cluster's policies.")]
+    override_policy: bool,
+    #[arg(long, short, long_help = "Don't ask for confirmation")]
+    yes: bool,
+}
+
+// the node's pods to move elsewhere before it goes down, and those left to come back with it: daemonsets run on every node
+// anyway, and nothing would reschedule cronjob runs or bare pods
+fn drain_plan(state: &ClusterState, node: &str) -> (Vec<(PodmanPodInfo, OwnerRef)>, Vec<String>) {
+    let mut evict = vec![];
+    let mut stay = vec![];
+    for (pod, _) in state.filter_pods(&|_| true).into_iter().filter(|(_, n)| n.node_name == node) {
+        match OwnerRef::of(&pod) {
+            Some(owner) if owner.resource_type == ResourceType::Deployment => evict.push((pod, owner)),
+            _ => stay.push(pod.name),
+        }
+    }
+    (evict, stay)
+}
+
+fn update_script(command: Option<&str>, reboot: RebootPolicy) -> String {
+    let update = command.unwrap_or(DEFAULT_UPDATE_COMMAND);
+    let reboot = match reboot {
+        RebootPolicy::Always => "echo reboot",
+        RebootPolicy::IfRequired => "if [ -f /var/run/reboot-required ]; then echo reboot; \
+elif command -v needs-restarting >/dev/null && ! needs-restarting -r >/dev/null; then echo reboot; \
+else echo no-reboot; fi",
+        RebootPolicy::Never => "echo no-reboot",
+    };
+    format!("sudo sh -c {} >&2 && {}", shell_quote(update), reboot)
+}
+
+// in the order they're restarted: the skatelet first, then dns, which the ingress resolves its upstreams with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+pub enum SkateService {
+    Skatelet,
+    Dns,
+    Ingress,
+}
+
+#[derive(Debug, Args)]
+pub struct RestartServicesArgs {
+    #[command(flatten)]
+    config: ConfigFileArgs,
+    #[arg(long_help = "Nodes to restart services on, one at a time, all of them if none are given.")]
+    nodes: Vec<String>,
+    #[arg(long, value_enum, value_delimiter = ',', long_help = "The services to restart, all of them if none are given.")]
+    services: Vec<SkateService>,
+    #[arg(long, default_value_t = 60, long_help = "Seconds to wait for each service to be healthy again.")]
+    timeout: u64,
+    #[arg(long, long_help = "Will not affect the cluster if set to true")]
+    dry_run: bool,
+    #[arg(long, short, long_help = "Don't ask for confirmation")]
+    yes: bool,
+}
+
+#[derive(Debug, PartialEq)]
+struct RestartStep {
+    service: SkateService,
+    // the systemd unit or podman pod restarted
+    kind: &'static str,
+    target: String,
+    restart: String,
+    check: String,
+}
+
+// the skatelet's unit and the node's pods of the coredns and ingress daemonsets, in restart order. the skatelet is healthy
+// when its /healthz says so, dns when the cluster's resolver on the node answers for the node's name
+fn restart_steps(services: &[SkateService], pods: &[PodmanPodInfo], node: &crate::config::Node) -> Vec<RestartStep> {
+    let mut services = services.to_vec();
+    services.sort();
+    services.dedup();
+
+    let pod_step = |service: SkateService, pod: &PodmanPodInfo, check: Option<String>| {
+        let running = format!("test \"$(sudo podman pod inspect {} --format '{{{{.State}}}}')\" = Running", pod.name);
+        RestartStep {
+            service,
+            kind: "Pod",
+            target: pod.name.clone(),
+            restart: format!("sudo podman pod restart {}", pod.name),
+            check: match check {
+                Some(check) => format!("{} && {{ {}; }}", running, check),
+                None => running,
+            },
+        }
+    };
+    let name = format!("{}.node.cluster.skate", node.name);
+    let gateway = node.gateway();
+    let dns_check = format!("{{ command -v dig >/dev/null && dig +short +time=2 +tries=1 @{gateway} {name} | grep -q .; }} || nslookup -timeout=2 {name} {gateway} >/dev/null",
+        gateway = gateway, name = name);
+    let system_pods = |f: &dyn Fn(&str) -> bool| pods.iter().filter(|p| p.namespace() == "skate" && f(&p.daemonset())).collect::<Vec<_>>();
+
+    services.into_iter().flat_map(|service| match service {
+        SkateService::Skatelet => vec![RestartStep {
+            service,
+            kind: "Unit",
+            target: "skatelet.service".to_string(),
+            restart: "sudo systemctl restart skatelet.service".to_string(),
+            check: format!("sudo curl -sf --unix-socket {} http://localhost/healthz >/dev/null", HEALTHZ_SOCKET),
+        }],
+        SkateService::Dns => system_pods(&|d| d == "coredns").into_iter().map(|p| pod_step(service, p, Some(dns_check.clone()))).collect(),
+        SkateService::Ingress => system_pods(&|d| d.starts_with("nginx-ingress")).into_iter().map(|p| pod_step(service, p, None)).collect(),
+    }).collect()
+}
+
+const BOOT_ID_COMMAND: &str = "cat /proc/sys/kernel/random/boot_id";
+// a moment's delay lets the ssh command return before the connection drops
+const REBOOT_COMMAND: &str = "sudo systemd-run --on-active=3 systemctl reboot";
+const READY_COMMAND: &str = "sudo podman info >/dev/null && sudo skatelet system generation >/dev/null";
+
+pub trait NodeDeps: With<dyn SshManager> {}
+
+pub struct Node<D: NodeDeps> {
+    pub deps: D,
+}
+
+#[derive(Tabled, Debug, PartialEq)]
+#[tabled(rename_all = "UPPERCASE")]
+struct CheckRow {
+    node: String,
+    check: String,
+    status: String,
+    detail: String,
+    hint: String,
+}
+
+impl CheckRow {
+    fn new(node: &str, check: &str, passed: bool, detail: &str, hint: &str) -> Self {
+        CheckRow {
+            node: node.to_string(),
+            check: check.to_string(),
+            status: match passed {
+                true => "ok".to_string(),
+                false => "FAIL".to_string(),
+            },
+            detail: detail.to_string(),
+            hint: match passed {
+                true => "".to_string(),
+                false => hint.to_string(),
+            },
+        }
+    }
+
+    fn passed(&self) -> bool {
+        self.status == "ok"
+    }
+}
+
+fn unix_secs(t: SystemTime) -> f64 {
+    t.duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or_default()
+}
+
+// the commands run on each node, in the order check_rows expects their results
+fn ping_commands() -> Vec<String> {
+    vec![
+        "sudo -n true".to_string(),
+        "podman --version|awk '{print $NF}'".to_string(),
+        "skatelet -V|awk '{print $NF}'".to_string(),
+        "date +%s.%N".to_string(),
+    ]
+}
+
+// turns the results of ping_commands into a row per check, given the ssh round trip, the local time halfway through the batch and
+// how the user becomes root
+fn check_rows(node: &str, latency: Duration, results: &[BatchResult], local_midpoint: f64, become_: &Become) -> Vec<CheckRow> {
+    let output = |i: usize| results.get(i).filter(|r| r.exit_status == 0).map(|r| r.stdout.trim().to_string()).filter(|s| !s.is_empty());
+    let error = |i: usize| results.get(i).map(|r| r.stderr.trim().to_string()).unwrap_or_default();
+
+    let mut rows = vec![CheckRow::new(node, "ssh", true, &format!("{}ms round trip", latency.as_millis()), "")];
+
+    rows.push(match results.first().is_some_and(|r| r.exit_status == 0) {
+        true => CheckRow::new(node, "sudo", true, &become_.to_string(), ""),
+        false => CheckRow::new(node, "sudo", false, &error(0), &match become_.password {
+            Some(_) => format!("check the {} password, and that the ssh user may use {}", become_.method, become_.method),
+            None => format!("allow the ssh user to run {} without a password, or set become.password in the cluster config", become_.method),
+        }),
+    });
+
+    rows.push(match output(1) {
+        Some(version) => CheckRow::new(node, "podman", true, &version, ""),
+        None => CheckRow::new(node, "podman", false, "not found", "install podman, or re-run skate create node"),
+    });
+
+    let local_version = env!("CARGO_PKG_VERSION");
+    rows.push(match output(2) {
+        Some(version) if version == local_version => CheckRow::new(node, "skatelet", true, &version, ""),
+        Some(version) => CheckRow::new(node, "skatelet", false, &format!("{}, skate is {}", version, local_version), &format!("run skate upgrade node {}", node)),
+        None => CheckRow::new(node, "skatelet", false, "not found", "re-run skate create node to install skatelet"),
+    });
+
+    rows.push(match output(3).and_then(|o| o.parse::<f64>().ok()) {
+        Some(remote) => {
+            let skew = remote - local_midpoint;
+            CheckRow::new(node, "clock", skew.abs() <= MAX_CLOCK_SKEW_SECS, &format!("{:+.3}s skew", skew), "enable time sync on the node, eg with timedatectl set-ntp true")
+        }
+        None => CheckRow::new(node, "clock", false, "could not read the node's time", "check that date is available on the node"),
+    });
+    rows
+}
+
+impl<D: NodeDeps + RefreshDeps> Node<D> {
+    pub async fn node(&self, args: NodeArgs) -> Result<(), SkateError> {
+        match args.command {
+            NodeCommands::Ping(args) => self.ping(args).await,
+            NodeCommands::UpdateOs(args) => self.update_os(args).await,
+            NodeCommands::RestartServices(args) => self.restart_services(args).await,
+        }
+    }
+
+    async fn ping_node(&self, cluster: &Cluster, node: &crate::config::Node) -> Vec<CheckRow> {
+        let conn = match self.deps.get().node_connect(cluster, node).await {
+            Ok(conn) => conn,
+            Err(e) => return vec![CheckRow::new(&node.name, "ssh", false, &e.to_string(),
+                &format!("check that {}:{} is reachable and the user and key in the config are right", node.host, node.port.unwrap_or(22)))],
+        };
+
+        let start = Instant::now();
+        if let Err(e) = conn.execute("true").await {
+            return vec![CheckRow::new(&node.name, "ssh", false, &e.to_string(), "check that the ssh user has a working shell")];
+        }
+        let latency = start.elapsed();
+
+        let before = unix_secs(SystemTime::now());
+        let results = match conn.execute_batch(&ping_commands()).await {
+            Ok(results) => results,
+            Err(e) => return vec![CheckRow::new(&node.name, "ssh", false, &e.to_string(), "check that the ssh user has a working shell")],
+        };
+        let after = unix_secs(SystemTime::now());
+
+        check_rows(&node.name, latency, &results, (before + after) / 2.0, &cluster.become_.clone().unwrap_or_default())
+    }
+
+    async fn ping(&self, args: PingArgs) -> Result<(), SkateError> {
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), Access::ReadOnly)?;
+
+        if let Some(unknown) = args.nodes.iter().find(|n| !cluster.nodes.iter().any(|cn| &cn.name == *n)) {
+            return Err(anyhow!("no node {} in cluster {}", unknown, cluster.name).into());
+        }
+        let nodes: Vec<_> = cluster.nodes.iter().filter(|n| args.nodes.is_empty() || args.nodes.contains(&n.name)).collect();
+
+        let fut: FuturesUnordered<_> = nodes.iter().map(|n| self.ping_node(cluster, n)).collect();
+        let mut rows: Vec<CheckRow> = fut.collect::<Vec<_>>().await.into_iter().flatten().collect();
+        // keep each node's checks together, in the order they were made
+        rows.sort_by_key(|r| nodes.iter().position(|n| n.name == r.node));
+
+        let failed = rows.iter().filter(|r| !r.passed()).count();
+
+        let mut table = Table::new(rows);
+        table.with(Style::empty());
+        println!("{}", table);
+
+        if failed > 0 {
+            return Err(anyhow!("{} checks failed", failed).into());
+        }
+        Ok(())
+    }
+
+    // evicts the node's deployment pods, each replacement going to any other node that fits it
+    async fn drain(&self, cluster: &Cluster, config: &Config, node: &str, args: &UpdateOsArgs) -> Result<(), SkateError> {
+        let (conns, errors) = self.deps.get().cluster_connect(cluster).await;
+        if let Some(errors) = errors {
+            eprintln!("{}", errors)
+        }
+        let conns = conns.ok_or(anyhow!("failed to connect to any node"))?;
+        let mut state = Refresh::<D>::refreshed_state(&cluster.name, &conns, config).await?;
+        let conn = conns.find(node).ok_or(anyhow!("not connected to node {}", node))?;
+
+        let (evict, stay) = drain_plan(&state, node);
+        if !stay.is_empty() {
+            println!("leaving {} on {}, they'll be back after it restarts", stay.join(", "), node);
+        }
+        // the replacements are scheduled like any apply, so they have to pass the policies too
+        let owners = evict.iter().map(|(_, owner)| owner_resource(&state, owner)).collect::<Result<Vec<_>, _>>()?;
+        check_policy(cluster, &owners, args.override_policy)?;
+        let run = async {
+            for ((pod, _), mut resource) in evict.into_iter().zip(owners) {
+                let spec = pod_spec_mut(&mut resource).cloned().unwrap_or_default();
+                let removed = evict_pod(conn, &pod, &spec, grace_period(&spec, args.grace_period), args.dry_run).await?;
+                state.reconcile_object_deletion(&removed, node)?;
+
+                let scheduler = DefaultScheduler { avoid_nodes: vec![node.to_string()], ..Default::default() };
+                scheduler.schedule(&conns, &mut state, vec![resource], args.dry_run).await?;
+            }
+            Ok(())
+        };
+        match args.dry_run {
+            true => run.await,
+            false => lock::locked(cluster, &conns, "update-os", run).await,
+        }
+    }
+
+    // waits for the node to come back from a reboot, with a different boot id and podman and the skatelet working
+    async fn wait_ready(&self, cluster: &Cluster, node: &crate::config::Node, boot_id: &str, timeout: Duration) -> Result<(), SkateError> {
+        let start = Instant::now();
+        loop {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            if let Ok(conn) = self.deps.get().node_connect(cluster, node).await {
+                let rebooted = conn.execute(BOOT_ID_COMMAND).await.map(|id| id.trim() != boot_id).unwrap_or(false);
+                if rebooted && conn.execute(READY_COMMAND).await.is_ok() {
+                    return Ok(());
+                }
+            }
+            if start.elapsed() > timeout {
+                return Err(anyhow!("node {} wasn't ready {}s after rebooting", node.name, timeout.as_secs()).into());
+            }
+        }
+    }
+
+    async fn update_node(&self, cluster: &Cluster, config: &Config, node: &crate::config::Node, args: &UpdateOsArgs) -> Result<(), SkateError> {
+        let conn = self.deps.get().node_connect(cluster, node).await?;
+        match args.dry_run {
+            true => println!("would cordon {}", node.name),
+            false => conn.execute_stdout("sudo skatelet cordon", false, false).await?,
+        }
+        self.drain(cluster, config, &node.name, args).await?;
+
+        let script = update_script(args.command.as_deref(), args.reboot);
+        if args.dry_run {
+            println!("would run on {}: {}", node.name, script);
+            println!("would reboot {} if needed, wait for it and uncordon it", node.name);
+            return Ok(());
+        }
+        println!("upgrading {}", node.name);
+        let reboot = conn.execute(&script).await?.trim() == "reboot";
+
+        let conn: Box<dyn SshClient> = match reboot {
+            true => {
+                let boot_id = conn.execute(BOOT_ID_COMMAND).await?.trim().to_string();
+                conn.execute(REBOOT_COMMAND).await?;
+                println!("rebooting {}", node.name);
+                self.wait_ready(cluster, node, &boot_id, Duration::from_secs(args.timeout)).await?;
+                self.deps.get().node_connect(cluster, node).await?
+            }
+            false => conn,
+        };
+        conn.execute_stdout("sudo skatelet uncordon", false, false).await?;
+        println!("{} updated {}", CHECKBOX_EMOJI, node.name);
+        Ok(())
+    }
+
+    async fn update_os(&self, args: UpdateOsArgs) -> Result<(), SkateError> {
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), Access::Admin)?;
+
+        if let Some(unknown) = args.nodes.iter().find(|n| !cluster.nodes.iter().any(|cn| &cn.name == *n)) {
+            return Err(anyhow!("no node {} in cluster {}", unknown, cluster.name).into());
+        }
+        let nodes: Vec<_> = match args.nodes.is_empty() {
+            true => cluster.nodes.iter().collect(),
+            false => args.nodes.iter().filter_map(|n| cluster.nodes.iter().find(|cn| &cn.name == n)).collect(),
+        };
+
+        let targets: Vec<_> = nodes.iter().map(|n| Target::new(&n.name, "Node", &n.name)).collect();
+        if !args.dry_run && !confirm(&format!("Are you sure you want to update the OS of {} nodes, one at a time?", nodes.len()), &targets, args.yes)? {
+            return Ok(());
+        }
+
+        // one node at a time, so the others carry the workloads. a node that fails is left cordoned for a look
+        for node in nodes {
+            self.update_node(cluster, &config, node, &args).await
+                .map_err(|e| anyhow!("updating node {} failed, it's left cordoned: {}", node.name, e))?;
+        }
+        Ok(())
+    }
+
+    async fn restart_node_services(&self, cluster: &Cluster, node: &crate::config::Node, steps: &[RestartStep], timeout: Duration) -> Result<(), SkateError> {
+        let conn = self.deps.get().node_connect(cluster, node).await?;
+        for step in steps {
+            conn.execute(&step.restart).await.map_err(|e| anyhow!("failed to restart {}: {}", step.target, e))?;
+
+            let start = Instant::now();
+            while conn.execute(&step.check).await.is_err() {
+                if start.elapsed() > timeout {
+                    return Err(anyhow!("{} wasn't healthy {}s after restarting", step.target, timeout.as_secs()).into());
+                }
+                tokio::time::sleep(Duration::from_secs(2)).await;
+            }
+            println!("{} restarted {} on {}", CHECKBOX_EMOJI, step.target, node.name);
+        }
+        Ok(())
+    }
+
+    async fn restart_services(&self, args: RestartServicesArgs) -> Result<(), SkateError> {
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), Access::Admin)?;
+
+        if let Some(unknown) = args.nodes.iter().find(|n| !cluster.nodes.iter().any(|cn| &cn.name == *n)) {
+            return Err(anyhow!("no node {} in cluster {}", unknown, cluster.name).into());
+        }
+        let nodes: Vec<_> = cluster.nodes.iter().filter(|n| args.nodes.is_empty() || args.nodes.contains(&n.name)).collect();
+        let services = match args.services.is_empty() {
+            true => vec![SkateService::Skatelet, SkateService::Dns, SkateService::Ingress],
+            false => args.services.clone(),
+        };
+
+        let (conns, errors) = self.deps.get().cluster_connect(cluster).await;
+        if let Some(errors) = errors {
+            eprintln!("{}", errors)
+        }
+        let conns = conns.ok_or(anyhow!("failed to connect to any node"))?;
+        let state = Refresh::<D>::refreshed_state(&cluster.name, &conns, &config).await?;
+
+        let plan: Vec<_> = nodes.into_iter().map(|n| {
+            let pods: Vec<_> = state.nodes.iter().find(|s| s.node_name == n.name).map(|s| s.filter_pods(&|_| true)).unwrap_or_default();
+            (n, restart_steps(&services, &pods, n))
+        }).collect();
+
+        if args.dry_run {
+            for (node, steps) in plan.iter() {
+                steps.iter().for_each(|s| println!("would restart {} on {}", s.target, node.name));
+            }
+            return Ok(());
+        }
+        let targets: Vec<_> = plan.iter().flat_map(|(n, steps)| steps.iter().map(|s| Target::new(&n.name, s.kind, &s.target))).collect();
+        if !confirm(&format!("Are you sure you want to restart {} services?", targets.len()), &targets, args.yes)? {
+            return Ok(());
+        }
+
+        // a node at a time, stopping at the first service that doesn't come back so the rest keep serving
+        for (node, steps) in plan {
+            self.restart_node_services(cluster, node, &steps, Duration::from_secs(args.timeout)).await
+                .map_err(|e| anyhow!("restarting services on {} failed: {}", node.name, e))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::credentials::{Become, BecomeMethod};
+    use crate::node::{check_rows, drain_plan, restart_steps, update_script, RebootPolicy, SkateService};
+    use crate::ssh::BatchResult;
+    use crate::resource::ResourceType;
+    use crate::state::state::OwnerRef;
+    use crate::test_helpers::objects::{cluster_state, node_state, pod, WithPod};
+    use crate::util::NamespacedName;
+
+    fn ok(stdout: &str) -> BatchResult {
+        BatchResult { exit_status: 0, stdout: stdout.to_string(), stderr: "".to_string() }
+    }
+
+    #[test]
+    fn test_check_rows() {
+        let version = env!("CARGO_PKG_VERSION");
+        let results = vec![ok(""), ok("4.9.3\n"), ok(&format!("{}\n", version)), ok("1000.5\n")];
+        let rows = check_rows("node-1", Duration::from_millis(20), &results, 1000.0, &Become::default());
+        assert!(rows.iter().all(|r| r.passed()), "{:?}", rows);
+        assert_eq!("+0.500s skew", rows[4].detail);
+        assert_eq!("sudo, passwordless", rows[1].detail);
+
+        let results = vec![
+            BatchResult { exit_status: 1, stdout: "".to_string(), stderr: "sudo: a password is required".to_string() },
+            BatchResult { exit_status: 127, stdout: "".to_string(), stderr: "".to_string() },
+            ok("0.0.1\n"),
+            ok("1010\n"),
+        ];
+        let rows = check_rows("node-1", Duration::from_millis(20), &results, 1000.0, &Become { method: BecomeMethod::Doas, password: None });
+        let failed: Vec<_> = rows.iter().filter(|r| !r.passed()).map(|r| r.check.as_str()).collect();
+        assert_eq!(vec!["sudo", "podman", "skatelet", "clock"], failed);
+        assert_eq!("run skate upgrade node node-1", rows[3].hint);
+        assert_eq!("allow the ssh user to run doas without a password, or set become.password in the cluster config", rows[1].hint);
+    }
+
+    #[test]
+    fn test_drain_plan() {
+        let node_1 = node_state("node-1")
+            .with_pod(&pod("web-0", "default", &[("skate.io/deployment", "web")]))
+            .with_pod(&pod("logs-0", "default", &[("skate.io/daemonset", "logs")]))
+            .with_pod(&pod("debug", "default", &[]));
+        let node_2 = node_state("node-2").with_pod(&pod("web-1", "default", &[("skate.io/deployment", "web")]));
+        let state = cluster_state(vec![node_1, node_2]);
+
+        let (evict, stay) = drain_plan(&state, "node-1");
+        assert_eq!(vec![("web-0.default".to_string(), OwnerRef::new(ResourceType::Deployment, &NamespacedName::new("web", "default")))],
+                   evict.into_iter().map(|(p, o)| (p.name, o)).collect::<Vec<_>>());
+        assert_eq!(vec!["logs-0.default".to_string(), "debug.default".to_string()], stay);
+    }
+
+    #[test]
+    fn test_update_script() {
+        assert_eq!("sudo sh -c 'zypper -n up' >&2 && echo no-reboot", update_script(Some("zypper -n up"), RebootPolicy::Never));
+        assert!(update_script(None, RebootPolicy::Always).contains("apt-get -y -o Dpkg::Options::=--force-confold upgrade"));
+        assert!(update_script(None, RebootPolicy::IfRequired).contains("/var/run/reboot-required"));
+    }
+
+    #[test]
+    fn test_restart_steps() {
+        let node = node_state("node-1")
+            .with_pod(&pod("nginx-ingress-internal-x1", "skate", &[("skate.io/daemonset", "nginx-ingress-internal")]))
+            .with_pod(&pod("coredns-a1", "skate", &[("skate.io/daemonset", "coredns")]))
+            .with_pod(&pod("coredns-b2", "shop", &[("skate.io/daemonset", "coredns")]));
+        let pods = node.filter_pods(&|_| true);
+        let config_node: crate::config::Node = serde_yaml::from_str("{name: node-1, host: 10.0.0.1, subnet_cidr: 20.1.0.0/16}").unwrap();
+
+        let steps = restart_steps(&[SkateService::Ingress, SkateService::Skatelet, SkateService::Dns], &pods, &config_node);
+        assert_eq!(vec!["skatelet.service", "coredns-a1.skate", "nginx-ingress-internal-x1.skate"], steps.iter().map(|s| s.target.as_str()).collect::<Vec<_>>());
+        assert_eq!("sudo curl -sf --unix-socket /run/skatelet/healthz.sock http://localhost/healthz >/dev/null", steps[0].check);
+        assert_eq!("sudo podman pod restart coredns-a1.skate", steps[1].restart);
+        assert_eq!("test \"$(sudo podman pod inspect coredns-a1.skate --format '{{.State}}')\" = Running && \
+{ { command -v dig >/dev/null && dig +short +time=2 +tries=1 @20.1.0.1 node-1.node.cluster.skate | grep -q .; } || \
+nslookup -timeout=2 node-1.node.cluster.skate 20.1.0.1 >/dev/null; }", steps[1].check);
+        assert_eq!("test \"$(sudo podman pod inspect nginx-ingress-internal-x1.skate --format '{{.State}}')\" = Running", steps[2].check);
+
+        assert_eq!(1, restart_steps(&[SkateService::Dns, SkateService::Dns], &pods, &config_node).len());
+    }
+}
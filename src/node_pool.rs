@@ -0,0 +1,125 @@
+use std::collections::{BTreeMap, HashSet};
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use crate::config::Node;
+use crate::errors::SkateError;
+use crate::pod_spec::pod_spec_mut;
+use crate::resource::SupportedResources;
+
+// the nodes a namespace's workloads are kept to, eg a staging and a prod pool on disjoint machines
+#[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NodePool {
+    pub name: String,
+    pub namespaces: Vec<String>,
+    // the labels of the pool's nodes
+    pub node_selector: BTreeMap<String, String>,
+}
+
+fn matches(pool: &NodePool, labels: &BTreeMap<String, String>) -> bool {
+    pool.node_selector.iter().all(|(k, v)| labels.get(k) == Some(v))
+}
+
+pub fn validate(pools: &[NodePool], nodes: &[Node]) -> Result<(), SkateError> {
+    let mut errors = vec![];
+    let (mut names, mut namespaces) = (HashSet::new(), HashSet::new());
+    for pool in pools {
+        if !names.insert(&pool.name) {
+            errors.push(format!("node pool {} is declared more than once", pool.name));
+        }
+        if pool.node_selector.is_empty() {
+            errors.push(format!("node pool {}: nodeSelector can't be empty", pool.name));
+        } else if !nodes.iter().any(|n| matches(pool, &n.labels)) {
+            errors.push(format!("node pool {}: no node has the labels of its nodeSelector", pool.name));
+        }
+        for namespace in pool.namespaces.iter() {
+            if !namespaces.insert(namespace) {
+                errors.push(format!("node pool {}: namespace {} is already bound to another pool", pool.name, namespace));
+            }
+        }
+    }
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(anyhow!("invalid node pools:\n{}", errors.join("\n")).into()),
+    }
+}
+
+// adds the pool's labels to the nodeSelector of the resource's pods, so the scheduler only considers the pool's nodes
+pub fn resolve(pools: &[NodePool], mut resource: SupportedResources) -> Result<SupportedResources, SkateError> {
+    let name = resource.name();
+    let pool = match pools.iter().find(|p| p.namespaces.contains(&name.namespace)) {
+        Some(pool) => pool,
+        None => return Ok(resource),
+    };
+    let spec = match pod_spec_mut(&mut resource) {
+        Some(spec) => spec,
+        None => return Ok(resource),
+    };
+    let selector = spec.node_selector.get_or_insert_with(Default::default);
+    for (key, value) in pool.node_selector.iter() {
+        match selector.get(key) {
+            Some(own) if own != value => {
+                return Err(anyhow!("{}: nodeSelector {}={} is outside node pool {} of namespace {}", name, key, own, pool.name, name.namespace).into());
+            }
+            _ => {
+                selector.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    Ok(resource)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use k8s_openapi::api::core::v1::{Pod, PodSpec};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use crate::config::Node;
+    use crate::node_pool::{resolve, validate, NodePool};
+    use crate::resource::SupportedResources;
+    use crate::util::NamespacedName;
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn pod(namespace: &str, node_selector: &[(&str, &str)]) -> SupportedResources {
+        SupportedResources::Pod(Pod {
+            metadata: ObjectMeta::from(NamespacedName::new("web", namespace)),
+            spec: Some(PodSpec { node_selector: Some(labels(node_selector)).filter(|s| !s.is_empty()), ..Default::default() }),
+            ..Default::default()
+        })
+    }
+
+    fn node_selector(resource: SupportedResources) -> Option<BTreeMap<String, String>> {
+        match resource {
+            SupportedResources::Pod(p) => p.spec.unwrap().node_selector,
+            _ => panic!("not a pod"),
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let pools = vec![NodePool { name: "prod".to_string(), namespaces: vec!["shop".to_string()], node_selector: labels(&[("pool", "prod")]) }];
+
+        assert_eq!(Some(labels(&[("disk", "ssd"), ("pool", "prod")])), node_selector(resolve(&pools, pod("shop", &[("disk", "ssd")])).unwrap()));
+        assert_eq!(None, node_selector(resolve(&pools, pod("staging", &[])).unwrap()));
+        assert!(resolve(&pools, pod("shop", &[("pool", "staging")])).is_err());
+
+        let node = |pool: &str| Node {
+            name: format!("node-{}", pool),
+            host: "10.0.0.1".to_string(),
+            peer_host: "10.0.0.1".to_string(),
+            subnet_cidr: "20.1.0.0/16".to_string(),
+            port: None,
+            user: None,
+            key: None,
+            labels: labels(&[("pool", pool)]),
+            system_reserved: Default::default(),
+        };
+        validate(&pools, &[node("prod")]).unwrap();
+        assert!(validate(&pools, &[node("staging")]).is_err());
+        let overlapping = vec![pools[0].clone(), NodePool { name: "staging".to_string(), ..pools[0].clone() }];
+        assert!(validate(&overlapping, &[node("prod")]).is_err());
+    }
+}
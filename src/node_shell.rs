@@ -1,5 +1,5 @@
 use clap::Args;
-use crate::config::Config;
+use crate::config::{Access, Config};
 use crate::deps::{SshManager, With};
 use crate::errors::SkateError;
 use crate::skate::ConfigFileArgs;
@@ -23,8 +23,8 @@ pub struct NodeShell<D: NodeShellDeps> {
 impl<D: NodeShellDeps> NodeShell<D> {
     pub async fn node_shell(&self, args: NodeShellArgs) -> Result<(), SkateError> {
         let ssh_mgr = self.deps.get();
-        let config = Config::load(Some(args.config.skateconfig.clone()))?;
-        let cluster = config.active_cluster(args.config.context.clone())?;
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), Access::Admin)?;
         let node = cluster.nodes.iter().find(|n| n.name == args.node_name).ok_or("failed to find node".to_string())?;
         let conn = ssh_mgr.node_connect(cluster, node).await?;
         conn.execute_stdout(&args.cmd.join(" "), false, false).await?;
@@ -0,0 +1,343 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::config::{cache_dir, Cluster};
+use crate::state::state::{ClusterState, NodeStatus};
+use crate::util::slugify;
+
+// restarts after which a container that isn't ready is considered crash looping
+const CRASH_LOOP_RESTARTS: usize = 3;
+// how many days before an ingress certificate expires it's notified, unless the cluster sets cert-expiry-days
+const CERT_EXPIRY_DAYS: u32 = 14;
+
+// where notifications for a cluster are sent
+#[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NotificationTarget {
+    // the notification is POSTed as json
+    Webhook { url: String },
+    Slack {
+        #[serde(rename = "webhookUrl")]
+        webhook_url: String,
+    },
+    // sent with the local sendmail
+    Email {
+        to: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        from: Option<String>,
+    },
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotificationKind {
+    CrashLoop,
+    OomKilled,
+    ApplyFailed,
+    NodeDown,
+    Preempted,
+    CertExpiring,
+}
+
+impl Display for NotificationKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            NotificationKind::CrashLoop => "crash loop",
+            NotificationKind::OomKilled => "oom killed",
+            NotificationKind::ApplyFailed => "apply failed",
+            NotificationKind::NodeDown => "node down",
+            NotificationKind::Preempted => "preempted",
+            NotificationKind::CertExpiring => "cert expiring",
+        })
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub kind: NotificationKind,
+    pub cluster: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node: Option<String>,
+    // the node, pod or resource the notification is about
+    pub subject: String,
+    pub message: String,
+}
+
+impl Notification {
+    pub fn new(kind: NotificationKind, cluster: &str, node: Option<&str>, subject: &str, message: &str) -> Self {
+        Notification {
+            kind,
+            cluster: cluster.to_string(),
+            node: node.map(|n| n.to_string()),
+            subject: subject.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    // identifies the condition, so that an ongoing one is only notified once
+    fn key(&self) -> String {
+        format!("{}/{}/{}", self.kind, self.node.clone().unwrap_or_default(), self.subject)
+    }
+
+    fn summary(&self) -> String {
+        format!("[{}] {}: {} - {}", self.cluster, self.kind, self.subject, self.message)
+    }
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, notification: &Notification) -> Result<(), Box<dyn Error>>;
+}
+
+struct WebhookNotifier {
+    url: String,
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<(), Box<dyn Error>> {
+        reqwest::Client::new().post(&self.url).json(notification).send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+struct SlackNotifier {
+    webhook_url: String,
+}
+
+#[async_trait]
+impl Notifier for SlackNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<(), Box<dyn Error>> {
+        let body = json!({ "text": notification.summary() });
+        reqwest::Client::new().post(&self.webhook_url).json(&body).send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+struct EmailNotifier {
+    to: Vec<String>,
+    from: Option<String>,
+}
+
+#[async_trait]
+impl Notifier for EmailNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<(), Box<dyn Error>> {
+        let mut mail = format!("To: {}\n", self.to.join(", "));
+        if let Some(from) = &self.from {
+            mail.push_str(&format!("From: {}\n", from));
+        }
+        mail.push_str(&format!("Subject: {}\n\n{}\n", notification.summary(), notification.message));
+
+        let mut child = Command::new("sendmail").arg("-t").stdin(Stdio::piped()).spawn()
+            .map_err(|e| anyhow!(e).context("failed to run sendmail"))?;
+        child.stdin.take().ok_or(anyhow!("failed to open sendmail stdin"))?.write_all(mail.as_bytes())?;
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(anyhow!("sendmail exited with {}", status).into());
+        }
+        Ok(())
+    }
+}
+
+pub fn notifiers(cluster: &Cluster) -> Vec<Box<dyn Notifier>> {
+    cluster.notifications.iter().map(|t| -> Box<dyn Notifier> {
+        match t {
+            NotificationTarget::Webhook { url } => Box::new(WebhookNotifier { url: url.clone() }),
+            NotificationTarget::Slack { webhook_url } => Box::new(SlackNotifier { webhook_url: webhook_url.clone() }),
+            NotificationTarget::Email { to, from } => Box::new(EmailNotifier { to: to.clone(), from: from.clone() }),
+        }
+    }).collect()
+}
+
+// sends the notifications to all of the cluster's targets. failing to notify never fails the command that noticed the problem
+pub async fn notify(cluster: &Cluster, notifications: &[Notification]) {
+    let notifiers = notifiers(cluster);
+    for notification in notifications {
+        for notifier in notifiers.iter() {
+            if let Err(e) = notifier.notify(notification).await {
+                eprintln!("failed to send notification: {}", e);
+            }
+        }
+    }
+}
+
+// the problems seen in the current state of the cluster, given the configured nodes that couldn't be reached.
+// certificates are notified when they expire within cert_expiry_days of now
+pub fn detect_conditions(state: &ClusterState, unreachable: &[String], cert_expiry_days: u32, now: DateTime<Local>) -> Vec<Notification> {
+    let cluster = state.cluster_name.as_str();
+    let mut result: Vec<_> = unreachable.iter()
+        .map(|n| Notification::new(NotificationKind::NodeDown, cluster, Some(n), n, "node could not be reached"))
+        .collect();
+
+    for node in state.nodes.iter() {
+        if node.status == NodeStatus::Unhealthy && !unreachable.contains(&node.node_name) {
+            result.push(Notification::new(NotificationKind::NodeDown, cluster, Some(&node.node_name), &node.node_name, "node is unhealthy"));
+        }
+
+        let pods = node.host_info.iter().flat_map(|h| h.system_info.iter()).flat_map(|si| si.pods.iter().flatten());
+        for pod in pods {
+            for container in pod.app_containers() {
+                if container.oom_killed == Some(true) {
+                    result.push(Notification::new(NotificationKind::OomKilled, cluster, Some(&node.node_name), &container.names,
+                        &format!("container {} of pod {} was killed for running out of memory", container.names, pod.name)));
+                }
+                let restarts = container.restart_count.unwrap_or_default();
+                if restarts >= CRASH_LOOP_RESTARTS && !container.is_ready() {
+                    result.push(Notification::new(NotificationKind::CrashLoop, cluster, Some(&node.node_name), &container.names,
+                        &format!("container {} of pod {} restarted {} times and is not ready", container.names, pod.name, restarts)));
+                }
+            }
+        }
+
+        let certs = node.host_info.iter().flat_map(|h| h.system_info.iter()).flat_map(|si| si.ingress_certificates.iter().flatten());
+        for cert in certs.filter(|c| c.expires_at - now < Duration::days(cert_expiry_days.into())) {
+            let message = match cert.expires_at > now {
+                true => format!("ingress certificate for {} expires on {}", cert.domain, cert.expires_at.format("%Y-%m-%d %H:%M")),
+                false => format!("ingress certificate for {} expired on {}", cert.domain, cert.expires_at.format("%Y-%m-%d %H:%M")),
+            };
+            result.push(Notification::new(NotificationKind::CertExpiring, cluster, Some(&node.node_name), &cert.domain, &message));
+        }
+    }
+    result
+}
+
+// the conditions that were notified and haven't cleared since, with the node each was seen on
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+struct ActiveConditions {
+    keys: BTreeMap<String, String>,
+}
+
+impl ActiveConditions {
+    fn path(cluster_name: &str) -> String {
+        Path::new(&cache_dir()).join(format!("{}.notifications", slugify(cluster_name))).to_string_lossy().to_string()
+    }
+
+    fn load(cluster_name: &str) -> Self {
+        fs::read_to_string(Self::path(cluster_name)).ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    fn persist(&self, cluster_name: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(Self::path(cluster_name), serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    // returns the conditions that are new, and keeps only the current ones active.
+    // conditions on unreachable nodes can't be seen to clear, so they stay active
+    fn update(&mut self, current: Vec<Notification>, unreachable: &[String]) -> Vec<Notification> {
+        let mut keys: BTreeMap<_, _> = self.keys.iter()
+            .filter(|(_, node)| unreachable.contains(node))
+            .map(|(k, n)| (k.clone(), n.clone()))
+            .collect();
+        keys.extend(current.iter().map(|n| (n.key(), n.node.clone().unwrap_or_default())));
+
+        let new = current.into_iter().filter(|n| !self.keys.contains_key(&n.key())).collect();
+        self.keys = keys;
+        new
+    }
+}
+
+// notifies the conditions that started since the last time the cluster's state was checked
+pub async fn notify_conditions(cluster: &Cluster, state: &ClusterState, unreachable: &[String]) {
+    if cluster.notifications.is_empty() {
+        return;
+    }
+    let mut active = ActiveConditions::load(&cluster.name);
+    let conditions = detect_conditions(state, unreachable, cluster.cert_expiry_days.unwrap_or(CERT_EXPIRY_DAYS), Local::now());
+    let new = active.update(conditions, unreachable);
+    notify(cluster, &new).await;
+    if let Err(e) = active.persist(&cluster.name) {
+        eprintln!("failed to save notification state: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Local};
+    use crate::notify::{detect_conditions, ActiveConditions, NotificationKind};
+    use crate::skatelet::system::IngressCertificate;
+    use crate::skatelet::system::podman::{PodmanContainerInfo, PodmanPodInfo, PodmanPodStatus};
+    use crate::test_helpers::objects::{cluster_state, node_state};
+
+    #[test]
+    fn test_detect_conditions() {
+        let container = |name: &str, restarts: usize, oom: bool| PodmanContainerInfo {
+            id: name.to_string(),
+            names: name.to_string(),
+            status: "exited".to_string(),
+            restart_count: Some(restarts),
+            exit_code: Some(137),
+            health: None,
+            cpu_limit: None,
+            memory_limit: None,
+            oom_killed: Some(oom),
+        };
+
+        let mut node = node_state("node-1");
+        let si = node.host_info.as_mut().unwrap().system_info.as_mut().unwrap();
+        si.pods = Some(vec![PodmanPodInfo {
+            id: "abc".to_string(),
+            name: "web".to_string(),
+            status: PodmanPodStatus::Degraded,
+            created: Local::now(),
+            labels: Default::default(),
+            containers: Some(vec![container("web-nginx", 5, true), container("web-sidecar", 1, false)]),
+            phase: None,
+        }]);
+        let state = cluster_state(vec![node]);
+
+        let conditions = detect_conditions(&state, &["node-2".to_string()], 14, Local::now());
+        let kinds: Vec<_> = conditions.iter().map(|n| (n.kind, n.subject.as_str())).collect();
+        assert_eq!(kinds, vec![
+            (NotificationKind::NodeDown, "node-2"),
+            (NotificationKind::OomKilled, "web-nginx"),
+            (NotificationKind::CrashLoop, "web-nginx"),
+        ]);
+
+        // ongoing conditions are only notified once, and again after they clear
+        let mut active = ActiveConditions::default();
+        assert_eq!(3, active.update(conditions.clone(), &[]).len());
+        assert_eq!(0, active.update(conditions.clone(), &[]).len());
+
+        // node-1 going down doesn't clear its conditions
+        assert_eq!(1, active.update(detect_conditions(&cluster_state(vec![]), &["node-1".to_string()], 14, Local::now()), &["node-1".to_string()]).len());
+        assert_eq!(1, active.update(conditions.clone(), &[]).len());
+
+        assert_eq!(0, active.update(vec![], &[]).len());
+        assert_eq!(3, active.update(conditions, &[]).len());
+    }
+
+    #[test]
+    fn test_detect_expiring_certificates() {
+        let now = Local::now();
+        let cert = |domain: &str, days: i64| IngressCertificate { domain: domain.to_string(), expires_at: now + Duration::days(days) };
+
+        let mut node = node_state("node-1");
+        let si = node.host_info.as_mut().unwrap().system_info.as_mut().unwrap();
+        si.ingress_certificates = Some(vec![cert("expired.example.com", -1), cert("soon.example.com", 5), cert("later.example.com", 30)]);
+        let state = cluster_state(vec![node]);
+
+        let conditions = detect_conditions(&state, &[], 14, now);
+        let kinds: Vec<_> = conditions.iter().map(|n| (n.kind, n.subject.as_str())).collect();
+        assert_eq!(kinds, vec![
+            (NotificationKind::CertExpiring, "expired.example.com"),
+            (NotificationKind::CertExpiring, "soon.example.com"),
+        ]);
+        assert!(conditions[0].message.contains("expired on"));
+        assert!(conditions[1].message.contains("expires on"));
+
+        // the window is configurable
+        assert_eq!(3, detect_conditions(&state, &[], 60, now).len());
+        assert_eq!(1, detect_conditions(&state, &[], 0, now).len());
+    }
+}
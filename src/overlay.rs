@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::str::FromStr;
+use anyhow::anyhow;
+use serde_json::Value;
+use crate::config::Cluster;
+use crate::errors::SkateError;
+use crate::patch::{apply_patch, PatchType};
+use crate::resource::{ResourceType, SupportedResources};
+use crate::util::NamespacedName;
+
+// a cluster's overlay file holds strategic merge patches keyed by <type>/<name>, or <type>/<name>.<namespace> outside the
+// cluster's default namespace, eg
+//
+//   deployment/web.shop:
+//     spec:
+//       replicas: 3
+//
+// so the same manifests can be applied to clusters that differ in replicas, env vars and the like
+pub struct Overlay {
+    patches: Vec<(ResourceType, NamespacedName, Value)>,
+}
+
+fn parse_key(key: &str, default_namespace: &str) -> Result<(ResourceType, NamespacedName), SkateError> {
+    let (resource_type, name) = key.split_once('/').ok_or(anyhow!("overlay key {} must be <type>/<name>", key))?;
+    let resource_type = ResourceType::from_str(resource_type).map_err(|_| anyhow!("overlay key {}: unknown type {}", key, resource_type))?;
+    let name = NamespacedName::from_arg(name, None, Some(default_namespace)).map_err(|e| anyhow!("overlay key {}: {}", key, e))?;
+    Ok((resource_type, name))
+}
+
+impl Overlay {
+    pub fn parse(contents: &str, default_namespace: &str) -> Result<Self, SkateError> {
+        let patches: BTreeMap<String, Value> = serde_yaml::from_str(contents).map_err(|e| anyhow!(e).context("failed to parse overlay"))?;
+        let patches = patches.into_iter()
+            .map(|(key, patch)| parse_key(&key, default_namespace).map(|(t, n)| (t, n, patch)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Overlay { patches })
+    }
+
+    // the cluster's overlay, when it has one
+    pub fn load(cluster: &Cluster) -> Result<Option<Self>, SkateError> {
+        let path = match cluster.overlay.as_ref() {
+            Some(path) => shellexpand::tilde(path).to_string(),
+            None => return Ok(None),
+        };
+        let contents = fs::read_to_string(&path).map_err(|e| anyhow!(e).context(format!("failed to read overlay {}", path)))?;
+        Self::parse(&contents, cluster.default_namespace()).map_err(|e| anyhow!("{}: {}", path, e).into()).map(Some)
+    }
+
+    // merges in the resource's patch, before the cluster's defaults are filled in
+    pub fn resolve(&self, mut resource: SupportedResources, default_namespace: &str) -> Result<SupportedResources, SkateError> {
+        let resource_type = ResourceType::from_str(&resource.to_string()).map_err(|e| anyhow!(e))?;
+        // it doesn't have skate's name labels yet
+        let meta = resource.metadata_mut();
+        let name = NamespacedName::new(meta.name.as_deref().unwrap_or_default(), meta.namespace.as_deref().unwrap_or(default_namespace));
+        let patch = match self.patches.iter().find(|(t, n, _)| *t == resource_type && *n == name) {
+            Some((_, _, patch)) => patch,
+            None => return Ok(resource),
+        };
+
+        let patched = apply_patch(resource.manifest()?, patch, &PatchType::Strategic)?;
+        let patched = serde_yaml::to_value(&patched).map_err(|e| anyhow!(e).context("failed to serialize patched manifest"))?;
+        SupportedResources::try_from(&patched).map_err(|e| anyhow!("overlay of {} {}: {}", resource_type, name, e).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::apps::v1::Deployment;
+    use crate::apply::parse_manifests;
+    use crate::overlay::Overlay;
+    use crate::resource::SupportedResources;
+
+    #[test]
+    fn test_resolve() {
+        let overlay = Overlay::parse(r#"
+deployment/web:
+  spec:
+    replicas: 3
+    template:
+      spec:
+        containers:
+          - name: app
+            env:
+              - name: LOG_LEVEL
+                value: warn
+deployment/web.shop:
+  spec:
+    replicas: 5
+"#, "default").unwrap();
+
+        let manifests = parse_manifests(r#"
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: web
+spec:
+  replicas: 1
+  selector:
+    matchLabels:
+      app: web
+  template:
+    spec:
+      containers:
+        - name: app
+          image: nginx
+          env:
+            - name: LOG_LEVEL
+              value: debug
+            - name: PORT
+              value: "80"
+---
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: web
+  namespace: shop
+spec:
+  selector: {}
+  template: {}
+---
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: api
+spec:
+  replicas: 1
+  selector: {}
+  template: {}
+"#).unwrap();
+        let resolved: Vec<_> = manifests.into_iter().map(|m| match overlay.resolve(m, "default").unwrap() {
+            SupportedResources::Deployment(d) => d,
+            _ => panic!("not a deployment"),
+        }).collect();
+
+        let spec = |d: &Deployment| d.spec.clone().unwrap();
+        assert_eq!(Some(3), spec(&resolved[0]).replicas);
+        let container = &spec(&resolved[0]).template.spec.unwrap().containers[0];
+        assert_eq!(Some("nginx".to_string()), container.image);
+        let env: Vec<_> = container.env.clone().unwrap().into_iter().map(|e| (e.name, e.value.unwrap_or_default())).collect();
+        assert_eq!(vec![("LOG_LEVEL".to_string(), "warn".to_string()), ("PORT".to_string(), "80".to_string())], env);
+
+        assert_eq!(Some(5), spec(&resolved[1]).replicas);
+        assert_eq!(Some(1), spec(&resolved[2]).replicas);
+
+        assert!(Overlay::parse("web: {}", "default").is_err());
+        assert!(Overlay::parse("volume/web: {}", "default").is_err());
+    }
+}
@@ -0,0 +1,352 @@
+use anyhow::anyhow;
+use clap::{Args, ValueEnum};
+use serde_json::{json, Map, Value};
+use crate::apply::{with_resource_version, Apply, ApplyDeps};
+use crate::config::{Access, Config};
+use crate::errors::SkateError;
+use crate::refresh::Refresh;
+use crate::edit::validate_modified;
+use crate::resource::ResourceType;
+use crate::rollout::ResourceArg;
+use crate::scheduler::DefaultScheduler;
+use crate::skate::ConfigFileArgs;
+use crate::util::NamespacedName;
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum PatchType {
+    // RFC 7386
+    Merge,
+    // like merge, but lists of named objects (containers, env, ...) are merged by key rather than replaced
+    Strategic,
+    // RFC 6902
+    Json,
+}
+
+#[derive(Debug, Args)]
+#[command(arg_required_else_help(true))]
+pub struct PatchArgs {
+    #[command(flatten)]
+    config: ConfigFileArgs,
+    #[arg(name = "TYPE | TYPE/NAME")]
+    resource: ResourceArg,
+    #[arg(name = "NAME")]
+    name: Option<String>,
+    #[arg(long, short, long_help = "Namespace of the resource, the cluster's default namespace if not given.")]
+    namespace: Option<String>,
+    #[arg(long, short, required_unless_present = "suspend", conflicts_with = "suspend", long_help = "The patch to apply, as json or yaml.")]
+    patch: Option<String>,
+    #[arg(long, long_help = "Suspend or resume a cronjob, shorthand for --patch '{\"spec\":{\"suspend\":...}}'.")]
+    suspend: Option<bool>,
+    #[arg(long = "type", value_enum, default_value_t = PatchType::Strategic, long_help = "The type of patch.")]
+    patch_type: PatchType,
+    #[arg(long, long_help = "Will not affect the cluster if set to true")]
+    dry_run: bool,
+    #[arg(long, long_help = "Apply the patch even if it violates the cluster's policies.")]
+    override_policy: bool,
+}
+
+pub trait PatchDeps: ApplyDeps {}
+
+pub struct Patch<D: PatchDeps> {
+    pub deps: D,
+}
+
+impl<D: PatchDeps> Patch<D> {
+    pub async fn patch(&self, args: PatchArgs) -> Result<(), SkateError> {
+        let (resource_type, name) = args.resource.parse()?;
+        let name = name.or(args.name.clone()).ok_or("resource name is required".to_string())?;
+
+        let patch = patch_value(&resource_type, args.patch.as_deref(), args.suspend)?;
+
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), Access::Deploy)?;
+        let ns_name = NamespacedName::from_arg(&name, args.namespace.as_deref(), Some(cluster.default_namespace()))?;
+        cluster.authorize_namespaces(&[&ns_name.namespace])?;
+
+        let mgr = self.deps.get();
+        let (conns, errors) = mgr.cluster_connect(cluster).await;
+        if let Some(errors) = errors {
+            eprintln!("{}", errors)
+        }
+        let conns = conns.ok_or("failed to get cluster connections".to_string())?;
+
+        let state = Refresh::<D>::refreshed_state(&cluster.name, &conns, &config).await?;
+
+        let (manifest, hash) = state.catalogue(None, std::slice::from_ref(&resource_type)).into_iter()
+            .find(|item| item.object.name == ns_name)
+            .and_then(|item| item.object.manifest.clone().map(|m| (m, item.object.manifest_hash.clone())))
+            .ok_or(anyhow!("{} {} not found", resource_type, ns_name))?;
+
+        let patched = apply_patch(serde_json::to_value(&manifest)?, &patch, &args.patch_type)?;
+        // fails rather than overwrite a change made since the object was read
+        let patched = with_resource_version(validate_modified(&serde_yaml::to_value(&patched)?, &ns_name)?, &hash)?;
+
+        // the stored manifest has already been through fixup, the rest is as apply does it
+        Apply::<D>::apply_prepared(cluster, &config, &conns, vec![patched], args.dry_run, args.override_policy, &DefaultScheduler::default(), "patch").await?;
+        Ok(())
+    }
+}
+
+// the patch given with --patch, or the one --suspend is shorthand for
+fn patch_value(resource_type: &ResourceType, patch: Option<&str>, suspend: Option<bool>) -> Result<Value, SkateError> {
+    match (patch, suspend) {
+        (_, Some(suspend)) => {
+            if resource_type != &ResourceType::CronJob {
+                return Err(anyhow!("--suspend only applies to cronjobs").into());
+            }
+            Ok(json!({"spec": {"suspend": suspend}}))
+        }
+        // yaml is a superset of json so this handles both
+        (Some(patch), None) => Ok(serde_yaml::from_str(patch).map_err(|e| anyhow!(e).context("failed to parse patch"))?),
+        (None, None) => Err(anyhow!("one of --patch or --suspend is required").into()),
+    }
+}
+
+pub fn apply_patch(target: Value, patch: &Value, patch_type: &PatchType) -> Result<Value, SkateError> {
+    match patch_type {
+        PatchType::Merge => Ok(merge_patch(target, patch, None)),
+        PatchType::Strategic => Ok(merge_patch(target, patch, Some(""))),
+        PatchType::Json => json_patch(target, patch),
+    }
+}
+
+// the key used to match list items when strategically merging a list under the given field
+fn strategic_merge_key(field: &str) -> Option<&'static str> {
+    match field {
+        "containers" | "initContainers" | "env" | "volumes" | "imagePullSecrets" | "hostAliases" => Some("name"),
+        "ports" => Some("containerPort"),
+        "volumeMounts" => Some("mountPath"),
+        _ => None,
+    }
+}
+
+// merge_patch implements RFC 7386, with lists merged by key when `field` is set (strategic mode)
+fn merge_patch(target: Value, patch: &Value, field: Option<&str>) -> Value {
+    let patch_map = match patch {
+        Value::Object(m) => m,
+        Value::Array(items) => {
+            return match (field.and_then(strategic_merge_key), target) {
+                (Some(key), Value::Array(existing)) => merge_list(existing, items, key),
+                _ => patch.clone(),
+            };
+        }
+        _ => return patch.clone(),
+    };
+
+    let mut target = match target {
+        Value::Object(m) => m,
+        _ => Map::new(),
+    };
+
+    for (k, v) in patch_map {
+        if v.is_null() {
+            target.remove(k);
+            continue;
+        }
+        let existing = target.remove(k).unwrap_or(Value::Null);
+        target.insert(k.clone(), merge_patch(existing, v, field.map(|_| k.as_str())));
+    }
+    Value::Object(target)
+}
+
+// three_way_merge merges a newly applied manifest into the live object, given the manifest applied before it. fields that were
+// in the last applied manifest but are gone from the new one are removed, while fields only the live object has, eg those set
+// by patch or edit, are kept. lists are replaced as a whole
+pub fn three_way_merge(live: Value, last_applied: &Value, new: &Value) -> Value {
+    let (mut live, new_map) = match (live, new) {
+        (Value::Object(live), Value::Object(new)) => (live, new),
+        _ => return new.clone(),
+    };
+
+    if let Value::Object(last) = last_applied {
+        for k in last.keys().filter(|k| !new_map.contains_key(*k)) {
+            live.remove(k);
+        }
+    }
+
+    for (k, v) in new_map {
+        let existing = live.remove(k).unwrap_or(Value::Null);
+        let last = last_applied.get(k).unwrap_or(&Value::Null);
+        live.insert(k.clone(), three_way_merge(existing, last, v));
+    }
+    Value::Object(live)
+}
+
+fn merge_list(existing: Vec<Value>, patch: &[Value], key: &str) -> Value {
+    let mut result = existing;
+    for item in patch {
+        let item_key = item.get(key).cloned();
+        let pos = item_key.as_ref().and_then(|ik| result.iter().position(|e| e.get(key) == Some(ik)));
+        let delete = item.get("$patch").and_then(Value::as_str) == Some("delete");
+
+        match (pos, delete) {
+            (Some(pos), true) => {
+                result.remove(pos);
+            }
+            (None, true) => {}
+            (Some(pos), false) => {
+                let merged = merge_patch(result[pos].clone(), item, Some(""));
+                result[pos] = merged;
+            }
+            (None, false) => result.push(item.clone()),
+        }
+    }
+    Value::Array(result)
+}
+
+// json_patch implements the add, remove, replace, move, copy and test operations of RFC 6902
+fn json_patch(target: Value, patch: &Value) -> Result<Value, SkateError> {
+    let ops = patch.as_array().ok_or(anyhow!("json patch must be a list of operations"))?;
+    let mut doc = target;
+
+    for op in ops {
+        let kind = op["op"].as_str().ok_or(anyhow!("json patch operation is missing 'op'"))?;
+        let path = op["path"].as_str().ok_or(anyhow!("json patch operation is missing 'path'"))?;
+        match kind {
+            "add" => pointer_add(&mut doc, path, op.get("value").cloned().ok_or(anyhow!("add requires a value"))?)?,
+            "remove" => {
+                pointer_remove(&mut doc, path)?;
+            }
+            "replace" => {
+                pointer_remove(&mut doc, path)?;
+                pointer_add(&mut doc, path, op.get("value").cloned().ok_or(anyhow!("replace requires a value"))?)?
+            }
+            "move" => {
+                let from = op["from"].as_str().ok_or(anyhow!("move requires 'from'"))?;
+                let value = pointer_remove(&mut doc, from)?;
+                pointer_add(&mut doc, path, value)?
+            }
+            "copy" => {
+                let from = op["from"].as_str().ok_or(anyhow!("copy requires 'from'"))?;
+                let value = doc.pointer(from).cloned().ok_or(anyhow!("path {} does not exist", from))?;
+                pointer_add(&mut doc, path, value)?
+            }
+            "test" => {
+                if doc.pointer(path) != op.get("value") {
+                    return Err(anyhow!("test failed for path {}", path).into());
+                }
+            }
+            _ => return Err(anyhow!("unsupported json patch operation {}", kind).into()),
+        }
+    }
+    Ok(doc)
+}
+
+fn split_pointer(path: &str) -> Result<(&str, String), SkateError> {
+    let (parent, last) = path.rsplit_once('/').ok_or(anyhow!("invalid json pointer {}", path))?;
+    Ok((parent, last.replace("~1", "/").replace("~0", "~")))
+}
+
+fn pointer_add(doc: &mut Value, path: &str, value: Value) -> Result<(), SkateError> {
+    if path.is_empty() {
+        *doc = value;
+        return Ok(());
+    }
+    let (parent, last) = split_pointer(path)?;
+    match doc.pointer_mut(parent) {
+        Some(Value::Object(m)) => {
+            m.insert(last, value);
+        }
+        Some(Value::Array(a)) => {
+            if last == "-" {
+                a.push(value)
+            } else {
+                let idx: usize = last.parse().map_err(|_| anyhow!("invalid array index {}", last))?;
+                if idx > a.len() {
+                    return Err(anyhow!("array index {} out of bounds", idx).into());
+                }
+                a.insert(idx, value)
+            }
+        }
+        _ => return Err(anyhow!("path {} does not exist", parent).into()),
+    }
+    Ok(())
+}
+
+fn pointer_remove(doc: &mut Value, path: &str) -> Result<Value, SkateError> {
+    let (parent, last) = split_pointer(path)?;
+    let removed = match doc.pointer_mut(parent) {
+        Some(Value::Object(m)) => m.remove(&last),
+        Some(Value::Array(a)) => last.parse::<usize>().ok().filter(|i| *i < a.len()).map(|i| a.remove(i)),
+        _ => None,
+    };
+    removed.ok_or(anyhow!("path {} does not exist", path).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::patch::{apply_patch, patch_value, three_way_merge, PatchType};
+    use crate::resource::ResourceType;
+
+    #[test]
+    fn test_merge_patch() {
+        let target = json!({"spec": {"replicas": 1, "paused": true, "template": {"spec": {"containers": [{"name": "a", "image": "a:1"}]}}}});
+        let patch = json!({"spec": {"replicas": 3, "paused": null, "template": {"spec": {"containers": [{"name": "b", "image": "b:1"}]}}}});
+
+        let result = apply_patch(target, &patch, &PatchType::Merge).unwrap();
+        assert_eq!(result, json!({"spec": {"replicas": 3, "template": {"spec": {"containers": [{"name": "b", "image": "b:1"}]}}}}));
+    }
+
+    #[test]
+    fn test_strategic_patch() {
+        let target = json!({"spec": {"containers": [
+            {"name": "a", "image": "a:1", "env": [{"name": "FOO", "value": "1"}]},
+            {"name": "b", "image": "b:1"},
+        ]}});
+        let patch = json!({"spec": {"containers": [
+            {"name": "a", "image": "a:2", "env": [{"name": "BAR", "value": "2"}]},
+            {"name": "b", "$patch": "delete"},
+            {"name": "c", "image": "c:1"},
+        ]}});
+
+        let result = apply_patch(target, &patch, &PatchType::Strategic).unwrap();
+        assert_eq!(result, json!({"spec": {"containers": [
+            {"name": "a", "image": "a:2", "env": [{"name": "FOO", "value": "1"}, {"name": "BAR", "value": "2"}]},
+            {"name": "c", "image": "c:1"},
+        ]}}));
+    }
+
+    #[test]
+    fn test_json_patch() {
+        let target = json!({"spec": {"replicas": 1, "containers": [{"name": "a"}]}});
+        let patch = json!([
+            {"op": "test", "path": "/spec/replicas", "value": 1},
+            {"op": "replace", "path": "/spec/replicas", "value": 2},
+            {"op": "add", "path": "/spec/containers/-", "value": {"name": "b"}},
+            {"op": "remove", "path": "/spec/containers/0"},
+        ]);
+
+        let result = apply_patch(target.clone(), &patch, &PatchType::Json).unwrap();
+        assert_eq!(result, json!({"spec": {"replicas": 2, "containers": [{"name": "b"}]}}));
+
+        let failing = json!([{"op": "test", "path": "/spec/replicas", "value": 5}]);
+        assert!(apply_patch(target, &failing, &PatchType::Json).is_err());
+    }
+
+    #[test]
+    fn test_three_way_merge() {
+        let last = json!({"metadata": {"labels": {"app": "a", "tier": "web"}}, "spec": {"replicas": 2, "paused": true}});
+        // replicas was patched since, and the hash label is skate's own
+        let live = json!({"metadata": {"labels": {"app": "a", "tier": "web", "skate.io/hash": "123"}}, "spec": {"replicas": 5, "paused": true, "ports": [1, 2]}});
+        let new = json!({"metadata": {"labels": {"app": "b"}}, "spec": {"replicas": 2, "ports": [3]}});
+
+        let merged = three_way_merge(live.clone(), &last, &new);
+        assert_eq!(merged, json!({"metadata": {"labels": {"app": "b", "skate.io/hash": "123"}}, "spec": {"replicas": 2, "ports": [3]}}));
+
+        // with nothing applied before, only what the new manifest sets changes
+        let merged = three_way_merge(live, &json!(null), &json!({"spec": {"paused": false}}));
+        assert_eq!(merged["spec"], json!({"replicas": 5, "paused": false, "ports": [1, 2]}));
+    }
+
+    #[test]
+    fn test_suspend_patch() {
+        let cronjob = json!({"kind": "CronJob", "spec": {"schedule": "0 * * * *"}});
+        let patch = patch_value(&ResourceType::CronJob, None, Some(true)).unwrap();
+        assert_eq!(json!({"kind": "CronJob", "spec": {"schedule": "0 * * * *", "suspend": true}}), apply_patch(cronjob, &patch, &PatchType::Strategic).unwrap());
+
+        assert!(patch_value(&ResourceType::Deployment, None, Some(true)).is_err());
+        assert!(patch_value(&ResourceType::CronJob, None, None).is_err());
+        assert_eq!(json!({"spec": {"replicas": 2}}), patch_value(&ResourceType::Deployment, Some("spec: {replicas: 2}"), None).unwrap());
+    }
+}
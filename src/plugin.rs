@@ -0,0 +1,87 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use anyhow::anyhow;
+use skate_plugin::{ACCESS_ENV, CONTEXT_ENV, PLUGIN_PREFIX, SKATE_BIN_ENV, STATE_FILE_ENV};
+use crate::config::Config;
+use crate::errors::SkateError;
+use crate::state::state::ClusterState;
+
+// plugins written in rust can read what's passed to them with the skate-plugin crate
+
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata().map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0).unwrap_or(false)
+    }
+    #[cfg(not(unix))]
+    path.is_file()
+}
+
+// the first executable called skate-<name> in the PATH
+fn find_plugin(name: &str, path: &OsStr) -> Option<PathBuf> {
+    std::env::split_paths(path)
+        .map(|dir| dir.join(format!("{}{}", PLUGIN_PREFIX, name)))
+        .find(|p| is_executable(p))
+}
+
+fn plugin_env() -> Vec<(&'static str, String)> {
+    let mut env = vec![];
+    if let Ok(exe) = std::env::current_exe() {
+        env.push((SKATE_BIN_ENV, exe.to_string_lossy().to_string()));
+    }
+    // without a config the plugin still runs, eg to set one up
+    let config = match Config::load(None) {
+        Ok(config) => config,
+        Err(_) => return env,
+    };
+    if let Ok(cluster) = config.active_cluster(std::env::var(CONTEXT_ENV).ok()) {
+        env.push((CONTEXT_ENV, cluster.name.clone()));
+        env.push((STATE_FILE_ENV, ClusterState::path(&cluster.name)));
+        env.push((ACCESS_ENV, cluster.profile.as_ref().map(|p| p.access).unwrap_or_default().to_string()));
+    }
+    env
+}
+
+// runs the plugin for the command with the rest of the arguments, exiting with its exit code
+pub(crate) fn run(args: Vec<String>) -> Result<(), SkateError> {
+    let (name, rest) = args.split_first().ok_or(anyhow!("no command given"))?;
+    let plugin = std::env::var_os("PATH").and_then(|p| find_plugin(name, &p))
+        .ok_or(anyhow!("unknown command {}, and no {}{} plugin was found in the PATH", name, PLUGIN_PREFIX, name))?;
+
+    let status = Command::new(&plugin).args(rest).envs(plugin_env()).status()
+        .map_err(|e| anyhow!(e).context(format!("failed to run {}", plugin.display())))?;
+    match status.code() {
+        Some(0) => Ok(()),
+        Some(code) => std::process::exit(code),
+        None => Err(anyhow!("{} was killed by a signal", plugin.display()).into()),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::ffi::OsString;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use crate::plugin::find_plugin;
+    use crate::test_helpers::temp_dir::TempDir;
+
+    #[test]
+    fn test_find_plugin() {
+        let dir = TempDir::new("plugins");
+        let (first, second) = (dir.join("a"), dir.join("b"));
+        fs::create_dir_all(&first).unwrap();
+        fs::create_dir_all(&second).unwrap();
+
+        // not executable, so skipped for the next one in the path
+        fs::write(first.join("skate-foo"), "#!/bin/sh\n").unwrap();
+        fs::write(second.join("skate-foo"), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(second.join("skate-foo"), fs::Permissions::from_mode(0o755)).unwrap();
+
+        let path = std::env::join_paths([&first, &second]).unwrap();
+        assert_eq!(Some(second.join("skate-foo")), find_plugin("foo", &path));
+        assert_eq!(None, find_plugin("bar", &path));
+        assert_eq!(None, find_plugin("foo", &OsString::new()));
+    }
+}
@@ -0,0 +1,28 @@
+use std::collections::BTreeMap;
+use k8s_openapi::api::core::v1::{PodSpec, PodTemplateSpec};
+use crate::resource::SupportedResources;
+
+// the template the resource makes its pods from, none for a bare pod or a resource without pods
+pub(crate) fn pod_template_mut(resource: &mut SupportedResources) -> Option<&mut PodTemplateSpec> {
+    match resource {
+        SupportedResources::Deployment(d) => Some(&mut d.spec.as_mut()?.template),
+        SupportedResources::DaemonSet(d) => Some(&mut d.spec.as_mut()?.template),
+        SupportedResources::CronJob(c) => Some(&mut c.spec.as_mut()?.job_template.spec.as_mut()?.template),
+        _ => None,
+    }
+}
+
+pub(crate) fn pod_spec_mut(resource: &mut SupportedResources) -> Option<&mut PodSpec> {
+    match resource {
+        SupportedResources::Pod(p) => p.spec.as_mut(),
+        _ => pod_template_mut(resource)?.spec.as_mut(),
+    }
+}
+
+pub(crate) fn pod_labels_mut(resource: &mut SupportedResources) -> Option<&mut BTreeMap<String, String>> {
+    let meta = match resource {
+        SupportedResources::Pod(p) => &mut p.metadata,
+        _ => pod_template_mut(resource)?.metadata.get_or_insert_with(Default::default),
+    };
+    Some(meta.labels.get_or_insert_with(Default::default))
+}
@@ -0,0 +1,214 @@
+use std::fmt::{Display, Formatter};
+use regex::Regex;
+use k8s_openapi::api::core::v1::PodSpec;
+use serde::{Deserialize, Serialize};
+use crate::image::normalize_image_ref;
+use crate::resource::SupportedResources;
+
+// a rule evaluated against every manifest before it is scheduled
+#[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq)]
+#[serde(tag = "rule", rename_all = "kebab-case")]
+pub enum PolicyRule {
+    DenyPrivileged,
+    DenyHostNetwork,
+    RequireResourceLimits,
+    DenyLatestTag,
+    NamespacePattern { pattern: String },
+    AllowedRegistries { registries: Vec<String> },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Policy {
+    #[serde(flatten)]
+    pub rule: PolicyRule,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_namespaces: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub resource: String,
+    pub rule: String,
+    pub message: String,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} violates {}: {}", self.resource, self.rule, self.message)
+    }
+}
+
+impl PolicyRule {
+    fn key(&self) -> &'static str {
+        match self {
+            PolicyRule::DenyPrivileged => "deny-privileged",
+            PolicyRule::DenyHostNetwork => "deny-host-network",
+            PolicyRule::RequireResourceLimits => "require-resource-limits",
+            PolicyRule::DenyLatestTag => "deny-latest-tag",
+            PolicyRule::NamespacePattern { .. } => "namespace-pattern",
+            PolicyRule::AllowedRegistries { .. } => "allowed-registries",
+        }
+    }
+
+    fn check(&self, resource: &SupportedResources) -> Vec<String> {
+        let specs = pod_specs(resource);
+        let containers = || specs.iter().flat_map(|s| s.containers.iter().chain(s.init_containers.iter().flatten()));
+
+        match self {
+            PolicyRule::DenyPrivileged => containers()
+                .filter(|c| c.security_context.as_ref().and_then(|s| s.privileged).unwrap_or(false))
+                .map(|c| format!("container {} is privileged", c.name))
+                .collect(),
+            PolicyRule::DenyHostNetwork => specs.iter()
+                .filter(|s| s.host_network.unwrap_or(false))
+                .map(|_| "pod uses the host network".to_string())
+                .collect(),
+            PolicyRule::RequireResourceLimits => containers().filter_map(|c| {
+                let limits = c.resources.as_ref().and_then(|r| r.limits.clone()).unwrap_or_default();
+                let missing: Vec<_> = ["cpu", "memory"].into_iter().filter(|l| !limits.contains_key(*l)).collect();
+                match missing.is_empty() {
+                    true => None,
+                    false => Some(format!("container {} has no {} limit", c.name, missing.join(" or "))),
+                }
+            }).collect(),
+            PolicyRule::DenyLatestTag => containers()
+                .filter(|c| c.image.as_deref().map(|i| normalize_image_ref(i).ends_with(":latest")).unwrap_or(false))
+                .map(|c| format!("container {} uses the latest tag", c.name))
+                .collect(),
+            PolicyRule::NamespacePattern { pattern } => {
+                let namespace = resource.name().namespace;
+                match Regex::new(&format!("^(?:{})$", pattern)) {
+                    Ok(re) if re.is_match(&namespace) => vec!(),
+                    Ok(_) => vec!(format!("namespace {} does not match {}", namespace, pattern)),
+                    Err(e) => vec!(format!("invalid pattern {}: {}", pattern, e)),
+                }
+            }
+            PolicyRule::AllowedRegistries { registries } => containers().filter_map(|c| {
+                let image = c.image.as_deref()?;
+                let normalized = normalize_image_ref(image);
+                let registry = normalized.split('/').next().unwrap_or_default();
+                match registries.iter().any(|r| normalize_registry(r) == registry) {
+                    true => None,
+                    false => Some(format!("container {} uses image {} from registry {}", c.name, image, registry)),
+                }
+            }).collect(),
+        }
+    }
+}
+
+fn normalize_registry(registry: &str) -> &str {
+    match registry {
+        "docker.io" | "index.docker.io" | "registry-1.docker.io" => "docker.io",
+        other => other,
+    }
+}
+
+fn pod_specs(resource: &SupportedResources) -> Vec<&PodSpec> {
+    let spec = match resource {
+        SupportedResources::Pod(p) => p.spec.as_ref(),
+        SupportedResources::Deployment(d) => d.spec.as_ref().and_then(|s| s.template.spec.as_ref()),
+        SupportedResources::DaemonSet(d) => d.spec.as_ref().and_then(|s| s.template.spec.as_ref()),
+        SupportedResources::CronJob(c) => c.spec.as_ref().and_then(|s| s.job_template.spec.as_ref()).and_then(|s| s.template.spec.as_ref()),
+        _ => None,
+    };
+    spec.into_iter().collect()
+}
+
+// evaluate returns every violation of the policies by the resources
+pub fn evaluate(policies: &[Policy], resources: &[SupportedResources]) -> Vec<Violation> {
+    resources.iter().flat_map(|resource| {
+        let name = resource.name();
+        policies.iter()
+            .filter(move |p| !p.exclude_namespaces.contains(&name.namespace))
+            .flat_map(move |p| p.rule.check(resource).into_iter().map(move |message| Violation {
+                resource: format!("{} {}", resource, resource.name()),
+                rule: p.rule.key().to_string(),
+                message,
+            }))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use k8s_openapi::api::apps::v1::{Deployment, DeploymentSpec};
+    use k8s_openapi::api::core::v1::{Container, PodSpec, PodTemplateSpec, ResourceRequirements, SecurityContext};
+    use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use crate::policy::{evaluate, Policy};
+    use crate::resource::SupportedResources;
+    use crate::util::NamespacedName;
+
+    fn deployment(namespace: &str, containers: Vec<Container>) -> SupportedResources {
+        SupportedResources::Deployment(Deployment {
+            metadata: ObjectMeta::from(NamespacedName::new("web", namespace)),
+            spec: Some(DeploymentSpec {
+                template: PodTemplateSpec {
+                    metadata: None,
+                    spec: Some(PodSpec { containers, ..Default::default() }),
+                },
+                ..Default::default()
+            }),
+            status: None,
+        })
+    }
+
+    #[test]
+    fn test_parse_policies() {
+        let yaml = r#"
+- rule: deny-privileged
+- rule: namespace-pattern
+  pattern: "team-[a-z]+"
+  excludeNamespaces: [skate]
+- rule: allowed-registries
+  registries: [ghcr.io]
+"#;
+        let policies: Vec<Policy> = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(policies.len(), 3);
+        assert_eq!(policies[1].exclude_namespaces, vec!["skate".to_string()]);
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let policies: Vec<Policy> = serde_yaml::from_str(r#"
+- rule: deny-privileged
+- rule: require-resource-limits
+- rule: deny-latest-tag
+- rule: namespace-pattern
+  pattern: "team-[a-z]+"
+  excludeNamespaces: [skate]
+- rule: allowed-registries
+  registries: [ghcr.io, docker.io]
+"#).unwrap();
+
+        let compliant = Container {
+            name: "app".to_string(),
+            image: Some("ghcr.io/foo/app:1.0".to_string()),
+            resources: Some(ResourceRequirements {
+                limits: Some(BTreeMap::from([
+                    ("cpu".to_string(), Quantity("100m".to_string())),
+                    ("memory".to_string(), Quantity("64Mi".to_string())),
+                ])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(evaluate(&policies, &[deployment("team-a", vec![compliant.clone()])]).is_empty());
+
+        let offending = Container {
+            name: "bad".to_string(),
+            image: Some("quay.io/foo/bad".to_string()),
+            security_context: Some(SecurityContext { privileged: Some(true), ..Default::default() }),
+            ..Default::default()
+        };
+        let violations = evaluate(&policies, &[deployment("other", vec![compliant, offending])]);
+        let rules: Vec<_> = violations.iter().map(|v| v.rule.as_str()).collect();
+        assert_eq!(rules, vec!["deny-privileged", "require-resource-limits", "deny-latest-tag", "namespace-pattern", "allowed-registries"]);
+        assert_eq!(violations[1].message, "container bad has no cpu or memory limit");
+
+        // excluded namespaces skip the rule
+        let violations = evaluate(&policies, &[deployment("skate", vec![])]);
+        assert!(violations.is_empty());
+    }
+}
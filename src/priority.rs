@@ -0,0 +1,288 @@
+use std::collections::{BTreeMap, HashMap};
+use anyhow::anyhow;
+use itertools::Itertools;
+use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::api::policy::v1::PodDisruptionBudget;
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use crate::errors::SkateError;
+use crate::pod_spec::{pod_labels_mut, pod_spec_mut};
+use crate::resource::{ResourceType, SupportedResources};
+use crate::skatelet::system::podman::{PodmanPodInfo, PodmanPodStatus};
+use crate::state::state::{ClusterState, NodeState, OwnerRef};
+
+// the resolved priority of a pod, carried over to the podman pod so that running pods can be compared
+pub const PRIORITY_LABEL: &str = "skate.io/priority";
+
+// the classes kubernetes has built in, for the cluster's own workloads
+const SYSTEM_PRIORITY_CLASSES: &[(&str, i32)] = &[
+    ("system-cluster-critical", 2000000000),
+    ("system-node-critical", 2000001000),
+];
+
+// sets the priority of the resource's pods from their priorityClassName, and labels them with it
+pub fn resolve(classes: &BTreeMap<String, i32>, mut resource: SupportedResources) -> Result<SupportedResources, SkateError> {
+    let name = resource.name();
+    let spec = match pod_spec_mut(&mut resource) {
+        Some(spec) => spec,
+        None => return Ok(resource),
+    };
+    if let Some(class) = &spec.priority_class_name {
+        let value = classes.get(class).copied()
+            .or(SYSTEM_PRIORITY_CLASSES.iter().find(|(n, _)| n == class).map(|(_, v)| *v))
+            .ok_or(anyhow!("{}: unknown priorityClassName {}, it isn't one of the cluster's priority classes", name, class))?;
+        spec.priority = Some(value);
+    }
+    // pods without a priority are left as they are, so their hash doesn't change
+    if let Some(priority) = spec.priority.filter(|p| *p != 0) {
+        if let Some(labels) = pod_labels_mut(&mut resource) {
+            labels.insert(PRIORITY_LABEL.to_string(), priority.to_string());
+        }
+    }
+    Ok(resource)
+}
+
+pub fn running_pod_priority(pod: &PodmanPodInfo) -> i32 {
+    pod.labels.get(PRIORITY_LABEL).and_then(|p| p.parse().ok()).unwrap_or(0)
+}
+
+pub fn pod_priority(pod: &Pod) -> i32 {
+    pod.spec.as_ref().and_then(|s| s.priority).unwrap_or(0)
+}
+
+// pods with a preemptionPolicy of Never wait for room rather than making it
+pub fn can_preempt(pod: &Pod) -> bool {
+    pod.spec.as_ref().and_then(|s| s.preemption_policy.as_deref()) != Some("Never")
+}
+
+// a pod disruption budget and how many more of its pods may be evicted
+struct Budget {
+    namespace: String,
+    match_labels: BTreeMap<String, String>,
+    allowed: i32,
+}
+
+impl Budget {
+    fn selects(&self, pod: &PodmanPodInfo) -> bool {
+        pod.labels.get("skate.io/namespace") == Some(&self.namespace)
+            && self.match_labels.iter().all(|(k, v)| pod.labels.get(k) == Some(v))
+    }
+}
+
+// a number, or a percentage of the total rounded up as kubernetes does
+fn scaled(value: &IntOrString, total: usize) -> i32 {
+    match value {
+        IntOrString::Int(i) => *i,
+        IntOrString::String(s) => s.trim_end_matches('%').parse::<i32>().map(|p| (p * total as i32 + 99) / 100).unwrap_or(0),
+    }
+}
+
+// the cluster's budgets with the evictions each still allows, counted over the pods they select on every node.
+// a budget without a selector selects nothing, as in kubernetes' policy/v1
+fn disruption_budgets(state: &ClusterState) -> Vec<Budget> {
+    let pods = state.filter_pods(&|_| true);
+    state.catalogue(None, &[ResourceType::PodDisruptionBudget]).into_iter()
+        .unique_by(|c| c.object.name.clone())
+        .filter_map(|c| serde_yaml::from_value::<PodDisruptionBudget>(c.object.manifest.clone()?).ok().map(|pdb| (c.object.name.namespace.clone(), pdb)))
+        .filter_map(|(namespace, pdb)| {
+            let spec = pdb.spec?;
+            let match_labels = spec.selector?.match_labels.unwrap_or_default();
+            let mut budget = Budget { namespace, match_labels, allowed: 0 };
+            let selected: Vec<_> = pods.iter().filter(|(p, _)| budget.selects(p)).collect();
+            let healthy = selected.iter().filter(|(p, _)| p.status == PodmanPodStatus::Running).count() as i32;
+            budget.allowed = match (&spec.min_available, &spec.max_unavailable) {
+                (Some(min), _) => healthy - scaled(min, selected.len()),
+                (None, Some(max)) => scaled(max, selected.len()) - (selected.len() as i32 - healthy),
+                (None, None) => return None,
+            };
+            Some(budget)
+        })
+        .collect()
+}
+
+// the running pods on the node that a pod of the priority may preempt, in the order they'd be evicted: lowest priority first,
+// then newest. pods selected by disruption budgets are only evicted while every one of them allows it, other workloads are
+// never left without a running pod. pods without an owner aren't evicted since nothing would recreate them
+pub fn preemption_candidates(state: &ClusterState, node: &NodeState, priority: i32) -> Vec<PodmanPodInfo> {
+    let owners = state.ownership_index();
+    let mut running: HashMap<OwnerRef, usize> = owners.iter()
+        .map(|(owner, pods)| (owner.clone(), pods.iter().filter(|(p, _)| p.status == PodmanPodStatus::Running).count()))
+        .collect();
+    let mut budgets = disruption_budgets(state);
+
+    node.filter_pods(&|p| p.status == PodmanPodStatus::Running && running_pod_priority(p) < priority).into_iter()
+        .sorted_by_key(|p| (running_pod_priority(p), std::cmp::Reverse(p.created)))
+        .filter(|p| {
+            let owner = match OwnerRef::of(p) {
+                // daemonset pods would only be recreated on the same node
+                Some(owner) if owner.resource_type != ResourceType::DaemonSet => owner,
+                _ => return false,
+            };
+            let mut covering: Vec<_> = budgets.iter_mut().filter(|b| b.selects(p)).collect();
+            if !covering.is_empty() {
+                if covering.iter().any(|b| b.allowed <= 0) {
+                    return false;
+                }
+                covering.iter_mut().for_each(|b| b.allowed -= 1);
+                return true;
+            }
+            let remaining = running.entry(owner).or_default();
+            if *remaining <= 1 {
+                return false;
+            }
+            *remaining -= 1;
+            true
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use chrono::{Duration, Local};
+    use k8s_openapi::api::apps::v1::{Deployment, DeploymentSpec};
+    use k8s_openapi::api::core::v1::{PodSpec, PodTemplateSpec};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use k8s_openapi::api::policy::v1::{PodDisruptionBudget, PodDisruptionBudgetSpec};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+    use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+    use crate::filestore::ObjectListItem;
+    use crate::priority::{preemption_candidates, resolve, PRIORITY_LABEL};
+    use crate::resource::SupportedResources;
+    use crate::skatelet::system::podman::{PodmanPodInfo, PodmanPodStatus};
+    use crate::test_helpers::objects::{cluster_state, node_state};
+
+    fn deployment(class: Option<&str>) -> SupportedResources {
+        SupportedResources::Deployment(Deployment {
+            metadata: ObjectMeta {
+                name: Some("foo".to_string()),
+                namespace: Some("bar".to_string()),
+                labels: Some(BTreeMap::from([
+                    ("skate.io/name".to_string(), "foo".to_string()),
+                    ("skate.io/namespace".to_string(), "bar".to_string()),
+                ])),
+                ..Default::default()
+            },
+            spec: Some(DeploymentSpec {
+                template: PodTemplateSpec {
+                    metadata: None,
+                    spec: Some(PodSpec { priority_class_name: class.map(|c| c.to_string()), ..Default::default() }),
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    }
+
+    fn template(resource: &SupportedResources) -> PodTemplateSpec {
+        match resource {
+            SupportedResources::Deployment(d) => d.spec.clone().unwrap().template,
+            _ => panic!("not a deployment"),
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let classes = BTreeMap::from([("high".to_string(), 1000)]);
+
+        let resolved = template(&resolve(&classes, deployment(Some("high"))).unwrap());
+        assert_eq!(Some(1000), resolved.spec.unwrap().priority);
+        assert_eq!("1000", resolved.metadata.unwrap().labels.unwrap()[PRIORITY_LABEL]);
+
+        let resolved = template(&resolve(&classes, deployment(Some("system-node-critical"))).unwrap());
+        assert_eq!(Some(2000001000), resolved.spec.unwrap().priority);
+
+        let resolved = template(&resolve(&classes, deployment(None)).unwrap());
+        assert_eq!(None, resolved.metadata);
+
+        let err = resolve(&classes, deployment(Some("missing"))).unwrap_err().to_string();
+        assert!(err.contains("unknown priorityClassName missing"), "{}", err);
+    }
+
+    fn pod(name: &str, deployment: &str, priority: i32, age_secs: i64) -> PodmanPodInfo {
+        PodmanPodInfo {
+            id: name.to_string(),
+            name: name.to_string(),
+            status: PodmanPodStatus::Running,
+            created: Local::now() - Duration::seconds(age_secs),
+            labels: BTreeMap::from([
+                ("skate.io/namespace".to_string(), "default".to_string()),
+                ("skate.io/deployment".to_string(), deployment.to_string()),
+                (PRIORITY_LABEL.to_string(), priority.to_string()),
+            ]),
+            containers: None,
+            phase: None,
+        }
+    }
+
+    #[test]
+    fn test_preemption_candidates() {
+        let mut node = node_state("node-1");
+        node.host_info.as_mut().unwrap().system_info.as_mut().unwrap().pods = Some(vec![
+            pod("low-old", "low", 0, 60),
+            pod("low-new", "low", 0, 10),
+            pod("mid", "mid", 500, 10),
+            pod("single", "single", 0, 10),
+            pod("high", "high", 2000, 10),
+            pod("high-2", "high", 2000, 10),
+        ]);
+        let mut other = node_state("node-2");
+        other.host_info.as_mut().unwrap().system_info.as_mut().unwrap().pods = Some(vec![pod("mid-2", "mid", 500, 10)]);
+        let state = cluster_state(vec![node.clone(), other]);
+
+        // one of the low pods stays, the only single pod isn't touched, nor are pods of equal or higher priority
+        let names: Vec<_> = preemption_candidates(&state, &node, 1000).into_iter().map(|p| p.name).collect();
+        assert_eq!(vec!["low-new", "mid"], names);
+
+        assert!(preemption_candidates(&state, &node, 0).is_empty());
+    }
+
+    fn budget(selected: &str, min_available: Option<IntOrString>, max_unavailable: Option<IntOrString>) -> ObjectListItem {
+        let mut pdb = PodDisruptionBudget {
+            metadata: ObjectMeta {
+                name: Some(selected.to_string()),
+                namespace: Some("default".to_string()),
+                labels: Some(BTreeMap::from([
+                    ("skate.io/name".to_string(), selected.to_string()),
+                    ("skate.io/namespace".to_string(), "default".to_string()),
+                ])),
+                ..Default::default()
+            },
+            spec: Some(PodDisruptionBudgetSpec {
+                min_available,
+                max_unavailable,
+                selector: Some(LabelSelector { match_labels: Some(BTreeMap::from([("skate.io/deployment".to_string(), selected.to_string())])), ..Default::default() }),
+                ..Default::default()
+            }),
+            status: None,
+        };
+        pdb.metadata.name = Some(format!("{}.default", selected));
+        ObjectListItem::from(&pdb)
+    }
+
+    #[test]
+    fn test_preemption_candidates_with_budgets() {
+        let mut node = node_state("node-1");
+        let si = node.host_info.as_mut().unwrap().system_info.as_mut().unwrap();
+        si.pods = Some(vec![
+            pod("low-old", "low", 0, 60),
+            pod("low-new", "low", 0, 10),
+            pod("single", "single", 0, 15),
+            pod("half-1", "half", 0, 30),
+            pod("half-2", "half", 0, 20),
+            pod("half-3", "half", 0, 10),
+            pod("half-4", "half", 0, 5),
+        ]);
+        si.pod_disruption_budgets = Some(vec![
+            // keeps both low pods
+            budget("low", Some(IntOrString::Int(2)), None),
+            // allows the only pod to go
+            budget("single", None, Some(IntOrString::Int(1))),
+            // half of 4 rounds up to 2 that may go
+            budget("half", None, Some(IntOrString::String("50%".to_string()))),
+        ]);
+        let state = cluster_state(vec![node.clone()]);
+
+        let names: Vec<_> = preemption_candidates(&state, &node, 1000).into_iter().map(|p| p.name).collect();
+        assert_eq!(vec!["half-4", "half-3", "single"], names);
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use colored::Colorize;
+use once_cell::sync::Lazy;
+use crate::interactive;
+use crate::util::{CHECKBOX_EMOJI, CROSS_EMOJI};
+
+const SPINNER: char = '⠿';
+
+static PROGRESS: Lazy<Progress> = Lazy::new(|| Progress::new(interactive::animated(), Box::new(std::io::stderr())));
+
+// progress returns the reporter shared by all commands, which writes to stderr so that stdout stays parseable
+pub fn progress() -> &'static Progress {
+    &PROGRESS
+}
+
+// reports the phases of long running commands per node, with timings.
+// on a terminal phases are colored and marked, otherwise they're printed as plain lines
+pub struct Progress {
+    tty: bool,
+    out: Mutex<Box<dyn Write + Send>>,
+    active: Mutex<BTreeMap<String, (String, Instant)>>,
+}
+
+impl Progress {
+    pub fn new(tty: bool, out: Box<dyn Write + Send>) -> Self {
+        Progress {
+            tty,
+            out: Mutex::new(out),
+            active: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    // start begins a phase for the node, completing its previous phase if there was one
+    pub fn start(&self, node: &str, phase: &str) {
+        let previous = self.active.lock().unwrap().insert(node.to_string(), (phase.to_string(), Instant::now()));
+        if let Some((previous, started)) = previous {
+            self.completed(node, &previous, started.elapsed(), None);
+        }
+
+        let line = match self.tty {
+            true => format!("{} {} - {}", SPINNER.to_string().cyan(), node, phase),
+            false => format!("{} - {}", node, phase),
+        };
+        self.write_line(&line);
+    }
+
+    // finish completes the node's current phase
+    pub fn finish(&self, node: &str) {
+        if let Some((phase, started)) = self.active.lock().unwrap().remove(node) {
+            self.completed(node, &phase, started.elapsed(), None);
+        }
+    }
+
+    // fail completes the node's current phase with an error
+    pub fn fail(&self, node: &str, err: &str) {
+        if let Some((phase, started)) = self.active.lock().unwrap().remove(node) {
+            self.completed(node, &phase, started.elapsed(), Some(err));
+        }
+    }
+
+    fn completed(&self, node: &str, phase: &str, elapsed: Duration, err: Option<&str>) {
+        let took = format_duration(elapsed);
+        let line = match (self.tty, err) {
+            (true, None) => format!("{} {} - {} {}", CHECKBOX_EMOJI, node, phase, format!("({})", took).dimmed()),
+            (true, Some(err)) => format!("{} {} - {} {}: {}", CROSS_EMOJI, node, phase, format!("({})", took).dimmed(), err.red()),
+            (false, None) => format!("{} - {} done in {}", node, phase, took),
+            (false, Some(err)) => format!("{} - {} failed after {}: {}", node, phase, took, err),
+        };
+        self.write_line(&line);
+    }
+
+    fn write_line(&self, line: &str) {
+        let mut out = self.out.lock().unwrap();
+        let _ = writeln!(out, "{}", line);
+        let _ = out.flush();
+    }
+}
+
+fn format_duration(d: Duration) -> String {
+    match d.as_millis() {
+        ms if ms < 1000 => format!("{}ms", ms),
+        ms if ms < 60_000 => format!("{:.1}s", ms as f64 / 1000.0),
+        ms => format!("{}m{}s", ms / 60_000, (ms % 60_000) / 1000),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use crate::progress::{format_duration, Progress};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_plain_output() {
+        let buffer = Buffer::default();
+        let progress = Progress::new(false, Box::new(buffer.clone()));
+
+        progress.start("node-1", "connecting");
+        progress.start("node-1", "fetching system info");
+        progress.fail("node-1", "timed out");
+        // finishing a node without an active phase is a no-op
+        progress.finish("node-1");
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "node-1 - connecting");
+        assert!(lines[1].starts_with("node-1 - connecting done in "));
+        assert_eq!(lines[2], "node-1 - fetching system info");
+        assert!(lines[3].starts_with("node-1 - fetching system info failed after "));
+        assert!(lines[3].ends_with(": timed out"));
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(250)), "250ms");
+        assert_eq!(format_duration(Duration::from_millis(2500)), "2.5s");
+        assert_eq!(format_duration(Duration::from_secs(125)), "2m5s");
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::{BTreeMap, HashMap};
+use chrono::{DateTime, Local};
+use clap::Args;
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use serde::Deserialize;
+use tabled::settings::Style;
+use tabled::{Table, Tabled};
+use crate::config::{Access, Config};
+use crate::deps::{SshManager, With};
+use crate::errors::SkateError;
+use crate::skate::ConfigFileArgs;
+use crate::util::age;
+
+#[derive(Debug, Clone, Args)]
+pub struct PsArgs {
+    #[command(flatten)]
+    config: ConfigFileArgs,
+    #[arg(long, short, long_help = "Filter by resource namespace")]
+    namespace: Option<String>,
+    #[arg(long, long_help = "Only list the containers on this node")]
+    node: Option<String>,
+    #[arg(long, short, long_help = "Include stopped containers")]
+    all: bool,
+}
+
+pub trait PsDeps: With<dyn SshManager> {}
+
+pub struct Ps<D: PsDeps> {
+    pub deps: D,
+}
+
+// a container as listed by `podman ps --format json`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PodmanPsEntry {
+    id: String,
+    #[serde(default)]
+    names: Vec<String>,
+    #[serde(default)]
+    image: String,
+    #[serde(default)]
+    state: String,
+    #[serde(default)]
+    started_at: i64,
+    #[serde(default)]
+    labels: Option<BTreeMap<String, String>>,
+    #[serde(default)]
+    pod_name: String,
+    #[serde(default)]
+    is_infra: bool,
+}
+
+// a running container's usage as listed by `podman stats --format json`
+#[derive(Debug, Clone, Deserialize)]
+struct PodmanStatsEntry {
+    id: String,
+    #[serde(default)]
+    cpu_percent: String,
+    #[serde(default)]
+    mem_usage: String,
+}
+
+#[derive(Tabled, Debug, Clone, PartialEq)]
+#[tabled(rename_all = "UPPERCASE")]
+struct ContainerRow {
+    node: String,
+    namespace: String,
+    pod: String,
+    container: String,
+    image: String,
+    state: String,
+    cpu: String,
+    memory: String,
+    uptime: String,
+}
+
+impl<D: PsDeps> Ps<D> {
+    pub async fn ps(&self, args: PsArgs) -> Result<(), SkateError> {
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), Access::ReadOnly)?;
+
+        let mgr = self.deps.get();
+        let (conns, errors) = mgr.cluster_connect(cluster).await;
+        if let Some(errors) = errors {
+            eprintln!("{}", errors)
+        }
+        let conns = conns.ok_or("failed to get cluster connections".to_string())?;
+
+        let all_flag = if args.all { " --all" } else { "" };
+        let cmds = vec![
+            format!("sudo podman ps{} --format json", all_flag),
+            "sudo podman stats --no-stream --format json".to_string(),
+        ];
+
+        let fut: FuturesUnordered<_> = conns.clients.iter()
+            .filter(|c| args.node.as_ref().is_none_or(|n| *n == c.node_name()))
+            .map(|c| async {
+                let (containers, stats) = match c.execute_batch(&cmds).await {
+                    Ok(mut results) => {
+                        // stats fail when no containers are running, in which case there is no usage to show
+                        let stats = results.pop().and_then(|r| r.into_result().ok()).unwrap_or_default();
+                        let containers = results.pop().ok_or("no result".to_string()).and_then(|r| r.into_result().map_err(|e| e.to_string()));
+                        (containers, stats)
+                    }
+                    Err(e) => (Err(e.to_string()), "".to_string()),
+                };
+                (c.node_name(), containers, stats)
+            }).collect();
+        let results: Vec<_> = fut.collect().await;
+
+        let mut rows = vec!();
+        for (node_name, containers, stats) in results {
+            match containers.and_then(|c| container_rows(&node_name, &c, &stats)) {
+                Ok(node_rows) => rows.extend(node_rows),
+                Err(e) => eprintln!("failed to list containers on {}: {}", node_name, e),
+            }
+        }
+
+        rows.retain(|r| args.namespace.as_ref().is_none_or(|ns| *ns == r.namespace));
+        rows.sort_by(|a, b| (&a.node, &a.namespace, &a.pod, &a.container).cmp(&(&b.node, &b.namespace, &b.pod, &b.container)));
+
+        if rows.is_empty() {
+            println!("No containers found");
+            return Ok(());
+        }
+
+        let mut table = Table::new(rows);
+        table.with(Style::empty());
+        println!("{}", table);
+        Ok(())
+    }
+}
+
+// joins the containers on a node with their usage, leaving out pod infra containers
+fn container_rows(node_name: &str, ps_output: &str, stats_output: &str) -> Result<Vec<ContainerRow>, String> {
+    let containers: Vec<PodmanPsEntry> = serde_json::from_str(ps_output).map_err(|e| e.to_string())?;
+    let stats: HashMap<String, PodmanStatsEntry> = serde_json::from_str::<Vec<PodmanStatsEntry>>(stats_output)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|s| (s.id.clone(), s))
+        .collect();
+
+    Ok(containers.into_iter().filter(|c| !c.is_infra).map(|c| {
+        // stats report the short id
+        let usage = stats.iter().find(|(id, _)| c.id.starts_with(id.as_str())).map(|(_, s)| s);
+        let running = c.state.eq_ignore_ascii_case("running");
+        let labels = c.labels.unwrap_or_default();
+
+        ContainerRow {
+            node: node_name.to_string(),
+            namespace: labels.get("skate.io/namespace").cloned().unwrap_or("-".to_string()),
+            pod: match c.pod_name.is_empty() {
+                true => "-".to_string(),
+                false => c.pod_name,
+            },
+            container: c.names.first().cloned().unwrap_or(c.id.chars().take(12).collect()),
+            image: c.image,
+            state: c.state,
+            cpu: usage.map(|s| s.cpu_percent.clone()).unwrap_or("-".to_string()),
+            memory: usage.map(|s| s.mem_usage.split('/').next().unwrap_or_default().trim().to_string()).unwrap_or("-".to_string()),
+            uptime: match (running, DateTime::from_timestamp(c.started_at, 0)) {
+                (true, Some(started)) if c.started_at > 0 => age(started.with_timezone(&Local)),
+                _ => "-".to_string(),
+            },
+        }
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ps::container_rows;
+
+    #[test]
+    fn test_container_rows() {
+        let ps = r#"[
+            {"Id": "aaaaaaaaaaaa1111", "Names": ["web-infra"], "Image": "localhost/podman-pause:4.9", "State": "running", "StartedAt": 1700000000, "PodName": "web", "IsInfra": true},
+            {"Id": "bbbbbbbbbbbb2222", "Names": ["web-nginx"], "Image": "docker.io/library/nginx:latest", "State": "running", "StartedAt": 1700000000, "Labels": {"skate.io/namespace": "shop"}, "PodName": "web", "IsInfra": false},
+            {"Id": "cccccccccccc3333", "Names": ["backup"], "Image": "docker.io/library/alpine:latest", "State": "exited", "StartedAt": 1700000000, "Labels": null, "PodName": "", "IsInfra": false}
+        ]"#;
+        let stats = r#"[{"id": "bbbbbbbbbbbb", "name": "web-nginx", "cpu_percent": "1.25%", "mem_usage": "12.5MB / 2.1GB"}]"#;
+
+        let rows = container_rows("node-1", ps, stats).unwrap();
+        assert_eq!(2, rows.len());
+
+        assert_eq!("shop", rows[0].namespace);
+        assert_eq!("web", rows[0].pod);
+        assert_eq!("web-nginx", rows[0].container);
+        assert_eq!("1.25%", rows[0].cpu);
+        assert_eq!("12.5MB", rows[0].memory);
+        assert_ne!("-", rows[0].uptime);
+
+        assert_eq!("-", rows[1].namespace);
+        assert_eq!("-", rows[1].pod);
+        assert_eq!("-", rows[1].cpu);
+        assert_eq!("-", rows[1].uptime);
+
+        assert!(container_rows("node-1", "Error: no such thing", "").is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use anyhow::anyhow;
+use clap::Args;
+use tabled::settings::Style;
+use tabled::{Table, Tabled};
+use crate::apply::check_policy;
+use crate::config::{Access, Config};
+use crate::confirm::{confirm, Target};
+use crate::deps::{SshManager, With};
+use crate::errors::SkateError;
+use crate::evict::{evict_pod, grace_period};
+use crate::image::{image_architectures, pod_images};
+use crate::lock;
+use crate::refresh::{Refresh, RefreshDeps};
+use crate::resource::{ResourceType, SupportedResources};
+use crate::scheduler::{DefaultScheduler, RebalanceMove, Scheduler};
+use crate::skate::ConfigFileArgs;
+
+#[derive(Debug, Args)]
+pub struct RebalanceArgs {
+    #[command(flatten)]
+    config: ConfigFileArgs,
+    #[arg(long, short, long_help = "Only move the pods of deployments in this namespace")]
+    namespace: Option<String>,
+    #[arg(long, default_value_t = 5, long_help = "The most pods to move.")]
+    max_moves: usize,
+    #[arg(long, long_help = "Only print the planned moves, nothing is changed on the nodes.")]
+    dry_run: bool,
+    #[arg(long, long_help = "Move the pods even if their deployments violate the cluster's policies.")]
+    override_policy: bool,
+    #[arg(long, short, long_help = "Don't ask for confirmation")]
+    yes: bool,
+}
+
+pub trait RebalanceDeps: With<dyn SshManager> {}
+
+pub struct Rebalance<D: RebalanceDeps> {
+    pub deps: D,
+}
+
+#[derive(Tabled)]
+#[tabled(rename_all = "UPPERCASE")]
+struct MoveRow {
+    pod: String,
+    deployment: String,
+    from: String,
+    to: String,
+}
+
+impl<D: RebalanceDeps + RefreshDeps> Rebalance<D> {
+    pub async fn rebalance(&self, args: RebalanceArgs) -> Result<(), SkateError> {
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), Access::Deploy)?;
+
+        let (conns, errors) = self.deps.get().cluster_connect(cluster).await;
+        if let Some(errors) = errors {
+            eprintln!("{}", errors)
+        }
+        let conns = conns.ok_or(anyhow!("failed to connect to any node"))?;
+        let mut state = Refresh::<D>::refreshed_state(&cluster.name, &conns, &config).await?;
+
+        // only the deployments the user may deploy to are moved
+        let deployments: Vec<_> = state.catalogue(None, &[ResourceType::Deployment]).into_iter()
+            .filter_map(|item| match SupportedResources::try_from(item.object) {
+                Ok(SupportedResources::Deployment(d)) => Some(d),
+                _ => None,
+            })
+            .filter(|d| {
+                let namespace = d.metadata.namespace.clone().unwrap_or_default();
+                args.namespace.as_ref().is_none_or(|n| *n == namespace) && cluster.authorize_namespaces(&[&namespace]).is_ok()
+            })
+            .collect();
+
+        let images: Vec<_> = deployments.iter().flat_map(|d| pod_images(&SupportedResources::Deployment(d.clone()))).collect();
+        let image_archs = image_architectures(&conns, &images).await;
+
+        let moves = DefaultScheduler::plan_rebalance(&state, &deployments, args.max_moves, &image_archs);
+        if moves.is_empty() {
+            println!("the cluster is balanced, no pods to move");
+            return Ok(());
+        }
+
+        let mut table = Table::new(moves.iter().map(|m| MoveRow {
+            pod: m.pod.clone(),
+            deployment: m.deployment.to_string(),
+            from: m.from.clone(),
+            to: m.to.clone(),
+        }));
+        table.with(Style::empty());
+        println!("{}\n", table);
+
+        // the moved pods are scheduled like any apply, so their deployments have to pass the policies too
+        let moved: Vec<_> = deployments.iter()
+            .filter(|d| moves.iter().any(|m| d.metadata.name.as_ref() == Some(&m.deployment.name) && d.metadata.namespace.as_ref() == Some(&m.deployment.namespace)))
+            .map(|d| SupportedResources::Deployment(d.clone()))
+            .collect();
+        check_policy(cluster, &moved, args.override_policy)?;
+
+        if args.dry_run {
+            return Ok(());
+        }
+
+        let targets: Vec<_> = moves.iter().map(|m| Target::new(&m.from, "Pod", &m.pod)).collect();
+        if !confirm(&format!("Are you sure you want to move these {} pods?", moves.len()), &targets, args.yes)? {
+            return Ok(());
+        }
+
+        lock::locked(cluster, &conns, "rebalance", async {
+            for RebalanceMove { pod, deployment, from, to } in moves.iter() {
+                let conn = conns.find(from).ok_or(anyhow!("not connected to node {}", from))?;
+                let pod = state.filter_pods(&|p| p.name == *pod).into_iter()
+                    .find(|(_, n)| n.node_name == *from)
+                    .map(|(p, _)| p)
+                    .ok_or(anyhow!("pod {} is no longer on {}", pod, from))?;
+                let deployment = deployments.iter()
+                    .find(|d| d.metadata.name.as_ref() == Some(&deployment.name) && d.metadata.namespace.as_ref() == Some(&deployment.namespace))
+                    .cloned()
+                    .ok_or(anyhow!("no manifest found for deployment {}", deployment))?;
+
+                let spec = deployment.spec.as_ref().and_then(|s| s.template.spec.clone()).unwrap_or_default();
+                let removed = evict_pod(conn, &pod, &spec, grace_period(&spec, None), false).await?;
+                state.reconcile_object_deletion(&removed, from)?;
+
+                // steer the replacement to the planned node, anywhere it fits if that one doesn't anymore
+                let avoid_nodes = state.nodes.iter().map(|n| n.node_name.clone()).filter(|n| n != to).collect();
+                let scheduler = DefaultScheduler { avoid_nodes, ..Default::default() };
+                scheduler.schedule(&conns, &mut state, vec![SupportedResources::Deployment(deployment)], false).await?;
+            }
+            Ok(())
+        }).await
+    }
+}
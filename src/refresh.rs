@@ -1,12 +1,15 @@
 use anyhow::anyhow;
+use chrono::Local;
 use clap::Args;
 use itertools::{Either, Itertools};
-use crate::config::Config;
+use crate::config::{Access, Config};
+use crate::delete::remove_pods;
 use crate::deps::{SshManager, With};
 use crate::errors::SkateError;
+use crate::notify::notify_conditions;
 use crate::skate::ConfigFileArgs;
 use crate::ssh::SshClients;
-use crate::state::state::{NodeStatus, ClusterState};
+use crate::state::state::{NodeStatus, ClusterState, RefreshTiming};
 use crate::util::{CHECKBOX_EMOJI, CROSS_EMOJI};
 
 #[derive(Debug, Args)]
@@ -15,6 +18,25 @@ pub struct RefreshArgs {
     pub config: ConfigFileArgs,
     #[arg(long, long_help = "print state as json to stdout")]
     pub json: bool,
+    #[arg(long, long_help = "Remove pods whose deployment, daemonset or cronjob no longer exists.")]
+    pub clean_orphans: bool,
+    #[arg(long, long_help = "Collect the state of every node, even those that haven't changed since the last refresh.")]
+    pub full: bool,
+    #[arg(long, default_value_t = 2000, long_help = "Warn about nodes whose connect and info gathering took longer than this many milliseconds.")]
+    pub slow_ms: u64,
+}
+
+// the node's refresh durations, and whether they're over the threshold
+fn timing_summary(timing: &RefreshTiming, slow_ms: u64) -> (String, bool) {
+    if let Some(error) = &timing.connect_error {
+        return (format!("failed to connect after {}ms: {}", timing.connect_ms.unwrap_or_default(), error), timing.total_ms() > slow_ms);
+    }
+    let connect = timing.connect_ms.map(|ms| format!("connect {}ms, ", ms)).unwrap_or_default();
+    let info = match timing.cached {
+        true => format!("unchanged, checked in {}ms", timing.info_ms),
+        false => format!("info {}ms", timing.info_ms),
+    };
+    (format!("{}{}", connect, info), timing.total_ms() > slow_ms)
 }
 
 
@@ -26,16 +48,23 @@ pub struct Refresh<D: RefreshDeps> {
 
 impl<D: RefreshDeps> Refresh<D> {
     pub async fn refresh(&self, args: RefreshArgs) -> Result<(), SkateError> {
-        let config = Config::load(Some(args.config.skateconfig))?;
-        let cluster = config.active_cluster(args.config.context)?;
+        let config = Config::load(args.config.skateconfig)?;
+        // cleaning orphans deletes them
+        let access = match args.clean_orphans {
+            true => Access::Deploy,
+            false => Access::ReadOnly,
+        };
+        let cluster = config.authorized_cluster(args.config.context, access)?;
 
 
         let mgr = self.deps.get();
         let (clients, errors) = mgr.cluster_connect(cluster).await;
+        let connect_errors = errors.map(|e| e.errors).unwrap_or_default();
 
-        if errors.is_some() {
+        // otherwise they're shown with the nodes' timings
+        if !connect_errors.is_empty() && (args.json || clients.is_none()) {
             eprintln!();
-            eprintln!("{}", errors.expect("should have had errors"))
+            connect_errors.iter().for_each(|e| eprintln!("{}", e));
         }
 
         if clients.is_none() {
@@ -43,7 +72,17 @@ impl<D: RefreshDeps> Refresh<D> {
         }
         let clients = clients.expect("should have had clients");
 
-        let state = Self::refreshed_state(&cluster.name, &clients, &config).await.expect("failed to refresh state");
+        let mut state = Self::refreshed_state_since(&cluster.name, &clients, &config, !args.full).await.expect("failed to refresh state");
+        // the nodes that couldn't be connected to are timed too, they're often the slowest
+        for error in connect_errors {
+            if let Some(node) = state.nodes.iter_mut().find(|n| n.node_name == error.node_name) {
+                node.refresh_timing = Some(RefreshTiming {
+                    connect_ms: error.elapsed.map(|d| d.as_millis() as u64),
+                    connect_error: Some(error.error),
+                    ..Default::default()
+                });
+            }
+        }
 
 
         if args.json {
@@ -52,47 +91,124 @@ impl<D: RefreshDeps> Refresh<D> {
             for node in &(state.nodes) {
                 let emoji = match node.status {
                     NodeStatus::Unhealthy => {
-                        CROSS_EMOJI
+                        CROSS_EMOJI.to_string()
                     }
                     NodeStatus::Healthy => {
-                        CHECKBOX_EMOJI
+                        CHECKBOX_EMOJI.to_string()
                     }
                     NodeStatus::Unknown => {
-                        ' '
+                        " ".to_string()
                     }
                 };
-                println!("node {} {} - {} ", node.node_name, node.status, emoji)
+                match node.refresh_timing.as_ref().map(|t| timing_summary(t, args.slow_ms)) {
+                    Some((summary, slow)) => {
+                        println!("node {} {} - {} ({})", node.node_name, node.status, emoji, summary);
+                        if slow {
+                            eprintln!("warning: node {} took over {}ms to refresh, it slows down every command", node.node_name, args.slow_ms);
+                        }
+                    }
+                    None => println!("node {} {} - {} ", node.node_name, node.status, emoji),
+                }
             }
         }
 
+        Self::handle_orphans(&state, &clients, args.clean_orphans).await?;
 
         Ok(())
     }
 
 
+    // pods left behind by a deleted deployment, daemonset or cronjob are reported, and removed if requested
+    async fn handle_orphans(state: &ClusterState, clients: &SshClients, clean: bool) -> Result<(), SkateError> {
+        let orphans = state.orphaned_pods();
+        if orphans.is_empty() {
+            return Ok(());
+        }
+
+        if !clean {
+            eprintln!("found {} orphaned pods, use --clean-orphans to remove them:", orphans.len());
+            for (owner, pod, node) in &orphans {
+                eprintln!("  {} - pod {} ({} no longer exists)", node.node_name, pod.name, owner);
+            }
+            return Ok(());
+        }
+
+        // a missing manifest on an unreachable node would make its pods look orphaned
+        if let Some(node) = state.nodes.iter().find(|n| n.status == NodeStatus::Unknown) {
+            return Err(anyhow!("node {} is unreachable, refusing to clean orphaned pods", node.node_name).into());
+        }
+
+        let pods: Vec<_> = orphans.into_iter().map(|(_, pod, node)| (pod, node)).collect();
+        let errors = remove_pods(clients, &pods).await;
+        if !errors.is_empty() {
+            return Err(anyhow!("\n{}", errors.join("\n")).into());
+        }
+        Ok(())
+    }
+
     pub async fn refreshed_state( cluster_name: &str, conns: &SshClients, config: &Config) -> Result<ClusterState, SkateError> {
-        let host_infos = conns.get_nodes_system_info().await;
+        Self::refreshed_state_since(cluster_name, conns, config, true).await
+    }
+
+    // with delta set, nodes whose generation matches the last refresh aren't queried in full
+    async fn refreshed_state_since(cluster_name: &str, conns: &SshClients, config: &Config, delta: bool) -> Result<ClusterState, SkateError> {
+        let previous = ClusterState::load(cluster_name)?;
+        let host_infos = match delta {
+            true => conns.get_nodes_system_info_since(&previous).await,
+            false => conns.get_nodes_system_info().await,
+        };
+        let timings: Vec<_> = host_infos.iter().map(|r| (r.node_name.clone(), r.timing.clone())).collect();
         let (healthy_host_infos, errors): (Vec<_>, Vec<SkateError>) = host_infos.into_iter().partition_map(|r|
-            match r {
+            match r.result {
                 Ok(r) => Either::Left(r),
                 Err(e) => Either::Right(e.into()),
             }
         );
 
+        let cluster = config.active_cluster(Some(cluster_name.to_string()))?;
+        let unreachable: Vec<_> = cluster.nodes.iter().map(|n| n.name.clone())
+            .filter(|n| !healthy_host_infos.iter().any(|h| h.node_name == *n))
+            .collect();
+
         if !errors.is_empty() {
+            notify_conditions(cluster, &ClusterState { cluster_name: cluster_name.to_string(), nodes: vec![] }, &unreachable).await;
             return Err(SkateError::Multi(errors));
         }
 
-
-        let mut state = match ClusterState::load(cluster_name) {
-            Ok(state) => state,
-            Err(_) => ClusterState {
-                cluster_name: cluster_name.to_string(),
-                nodes: vec![],
-            }
+        // built from scratch rather than on top of the last state, so nodes that were unreachable have no stale objects
+        let mut state = ClusterState {
+            cluster_name: cluster_name.to_string(),
+            nodes: vec![],
         };
 
         let _ = state.reconcile_all_nodes(cluster_name, config, &healthy_host_infos)?;
+        for node in state.nodes.iter_mut() {
+            node.refresh_timing = timings.iter().find(|(n, _)| *n == node.node_name).map(|(_, t)| t.clone());
+        }
+        state.track_host_changes(&previous, Local::now());
+        if let Err(e) = state.persist() {
+            eprintln!("failed to save cluster state: {}", e);
+        }
+        notify_conditions(cluster, &state, &unreachable).await;
         Ok(state)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::refresh::timing_summary;
+    use crate::state::state::RefreshTiming;
+
+    #[test]
+    fn test_timing_summary() {
+        let timing = RefreshTiming { connect_ms: Some(120), info_ms: 340, cached: false, connect_error: None };
+        assert_eq!(("connect 120ms, info 340ms".to_string(), false), timing_summary(&timing, 2000));
+        assert!(timing_summary(&timing, 400).1);
+
+        let cached = RefreshTiming { connect_ms: None, info_ms: 30, cached: true, connect_error: None };
+        assert_eq!(("unchanged, checked in 30ms".to_string(), false), timing_summary(&cached, 2000));
+
+        let failed = RefreshTiming { connect_ms: Some(5000), connect_error: Some("timeout".to_string()), ..Default::default() };
+        assert_eq!(("failed to connect after 5000ms: timeout".to_string(), true), timing_summary(&failed, 2000));
+    }
+}
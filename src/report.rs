@@ -0,0 +1,157 @@
+use std::io::Write;
+use serde::Serialize;
+use crate::errors::SkateError;
+
+// the outcome of a single action. the codes are stable so that wrappers can tell whether a run changed anything
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionResult {
+    Created,
+    Updated,
+    // an idempotent step that was run, without knowing whether it changed anything
+    Applied,
+    Unchanged,
+    Deleted,
+    NotFound,
+    Failed,
+}
+
+impl ActionResult {
+    pub fn changed(&self) -> bool {
+        matches!(self, ActionResult::Created | ActionResult::Updated | ActionResult::Applied | ActionResult::Deleted)
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Action {
+    pub action: String,
+    pub target: String,
+    pub result: ActionResult,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl Action {
+    pub fn new(action: &str, target: &str, result: ActionResult) -> Self {
+        Action {
+            action: action.to_string(),
+            target: target.to_string(),
+            result,
+            message: None,
+        }
+    }
+
+    pub fn with_message(mut self, message: &str) -> Self {
+        self.message = Some(message.to_string());
+        self
+    }
+}
+
+// Report is the --json output of commands that change the cluster's nodes
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Report {
+    pub command: String,
+    pub success: bool,
+    pub changed: bool,
+    pub actions: Vec<Action>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Report {
+    pub fn new(command: &str, actions: Vec<Action>, result: &Result<(), SkateError>) -> Self {
+        Report {
+            command: command.to_string(),
+            success: result.is_ok(),
+            changed: actions.iter().any(|a| a.result.changed()),
+            actions,
+            error: result.as_ref().err().map(|e| e.to_string()),
+        }
+    }
+}
+
+// runs the command, printing the report of its actions as json when asked to.
+// everything else the command prints goes to stderr meanwhile, so stdout holds only the report
+pub async fn reported<F>(command: &str, json: bool, f: F) -> Result<(), SkateError>
+where
+    F: std::future::Future<Output=(Vec<Action>, Result<(), SkateError>)>,
+{
+    if !json {
+        return f.await.1;
+    }
+
+    let redirect = StdoutToStderr::new();
+    let (actions, result) = f.await;
+    drop(redirect);
+
+    let report = Report::new(command, actions, &result);
+    println!("{}", serde_json::to_string_pretty(&report).map_err(|e| anyhow::anyhow!(e).context("failed to serialize report"))?);
+    result
+}
+
+// sends everything printed to stdout to stderr while in scope, so machine readable output can be printed on its own
+pub(crate) struct StdoutToStderr {
+    #[cfg(unix)]
+    saved: Option<libc::c_int>,
+}
+
+impl StdoutToStderr {
+    pub(crate) fn new() -> Self {
+        let _ = std::io::stdout().flush();
+        #[cfg(unix)]
+        {
+            // SAFETY: only duplicates the process's standard file descriptors
+            let saved = unsafe {
+                let saved = libc::dup(libc::STDOUT_FILENO);
+                match saved >= 0 && libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) >= 0 {
+                    true => Some(saved),
+                    false => None,
+                }
+            };
+            StdoutToStderr { saved }
+        }
+        #[cfg(not(unix))]
+        StdoutToStderr {}
+    }
+}
+
+impl Drop for StdoutToStderr {
+    fn drop(&mut self) {
+        let _ = std::io::stdout().flush();
+        #[cfg(unix)]
+        if let Some(saved) = self.saved {
+            // SAFETY: restores the descriptor saved in new
+            unsafe {
+                libc::dup2(saved, libc::STDOUT_FILENO);
+                libc::close(saved);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::SkateError;
+    use crate::report::{Action, ActionResult, Report};
+
+    #[test]
+    fn test_report() {
+        let actions = vec![
+            Action::new("install skatelet", "node-1", ActionResult::Unchanged),
+            Action::new("update config", "node-1", ActionResult::Unchanged),
+        ];
+        let report = Report::new("create node", actions.clone(), &Ok(()));
+        assert!(report.success);
+        assert!(!report.changed);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["actions"][0]["result"], "unchanged");
+        assert!(json.get("error").is_none());
+
+        let failed: Result<(), SkateError> = Err("boom".to_string().into());
+        let report = Report::new("create node", vec![Action::new("install podman", "node-1", ActionResult::Created)], &failed);
+        assert!(!report.success);
+        assert!(report.changed);
+        assert!(report.error.unwrap().contains("boom"));
+    }
+}
@@ -4,6 +4,7 @@ use k8s_openapi::api::core::v1::{Pod, PodTemplateSpec, Secret, Service};
 use k8s_openapi::api::apps::v1::{DaemonSet, Deployment};
 use k8s_openapi::api::networking::v1::Ingress;
 use k8s_openapi::api::batch::v1::CronJob;
+use k8s_openapi::api::policy::v1::PodDisruptionBudget;
 use serde_yaml::Value;
 use std::error::Error;
 use anyhow::anyhow;
@@ -14,9 +15,12 @@ use crate::filestore::ObjectListItem;
 use crate::spec::cert::ClusterIssuer;
 use crate::ssh::{SshClients};
 use crate::state::state::NodeState;
-use crate::util::{metadata_name, NamespacedName};
+use crate::host_network::HOST_PORTS_LABEL;
+use crate::priority::PRIORITY_LABEL;
+use crate::skatelet::prune::TTL_LABEL;
+use crate::util::{metadata_name, NamespacedName, LAST_APPLIED_ANNOTATION};
 
-#[derive(Debug, Serialize, Deserialize, Display, Clone, EnumString, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Display, Clone, EnumString, PartialEq, Eq, Hash)]
 #[strum(ascii_case_insensitive)]
 pub enum ResourceType {
     #[strum(serialize = "pods", serialize = "pod", to_string = "pod")]
@@ -35,9 +39,12 @@ pub enum ResourceType {
     Service,
     #[strum(serialize = "clusterissuers", serialize = "clusterissuer", to_string = "clusterissuer")]
     ClusterIssuer,
+    #[strum(serialize = "poddisruptionbudgets", serialize = "poddisruptionbudget", serialize = "pdb", to_string = "poddisruptionbudget")]
+    PodDisruptionBudget,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Display, Clone)]
+#[allow(clippy::large_enum_variant)]
 pub enum SupportedResources {
     #[strum(serialize = "Pod")]
     Pod(Pod),
@@ -55,7 +62,8 @@ pub enum SupportedResources {
     Service(Service),
     #[strum(serialize = "ClusterIssuer")]
     ClusterIssuer(ClusterIssuer),
-
+    #[strum(serialize = "PodDisruptionBudget")]
+    PodDisruptionBudget(PodDisruptionBudget),
 }
 
 impl TryFrom<&ObjectListItem> for SupportedResources {
@@ -123,6 +131,11 @@ impl TryFrom<&Value> for SupportedResources {
                     kind == ClusterIssuer::KIND {
                     let clusterissuer: ClusterIssuer = serde::Deserialize::deserialize(value)?;
                     Ok(SupportedResources::ClusterIssuer(clusterissuer))
+                } else if
+                api_version == PodDisruptionBudget::API_VERSION &&
+                    kind == PodDisruptionBudget::KIND {
+                    let pdb: PodDisruptionBudget = serde::Deserialize::deserialize(value)?;
+                    Ok(SupportedResources::PodDisruptionBudget(pdb))
                 } else {
                     Err(anyhow!(format!("version: {}, kind {}", api_version, kind)).context("unsupported resource type").into())
                 }
@@ -145,6 +158,36 @@ impl SupportedResources {
             SupportedResources::Secret(s) => metadata_name(s),
             SupportedResources::Service(s) => metadata_name(s),
             SupportedResources::ClusterIssuer(c) => metadata_name(c),
+            SupportedResources::PodDisruptionBudget(p) => metadata_name(p),
+        }
+    }
+
+    pub fn metadata_mut(&mut self) -> &mut ObjectMeta {
+        match self {
+            SupportedResources::Pod(r) => &mut r.metadata,
+            SupportedResources::Deployment(r) => &mut r.metadata,
+            SupportedResources::DaemonSet(r) => &mut r.metadata,
+            SupportedResources::Ingress(r) => &mut r.metadata,
+            SupportedResources::CronJob(r) => &mut r.metadata,
+            SupportedResources::Secret(s) => &mut s.metadata,
+            SupportedResources::Service(s) => &mut s.metadata,
+            SupportedResources::ClusterIssuer(c) => &mut c.metadata,
+            SupportedResources::PodDisruptionBudget(p) => &mut p.metadata,
+        }
+    }
+
+    // the object as its manifest, serializing the enum would wrap it in the variant's name
+    pub fn manifest(&self) -> Result<serde_json::Value, serde_json::Error> {
+        match self {
+            SupportedResources::Pod(r) => serde_json::to_value(r),
+            SupportedResources::Deployment(r) => serde_json::to_value(r),
+            SupportedResources::DaemonSet(r) => serde_json::to_value(r),
+            SupportedResources::Ingress(r) => serde_json::to_value(r),
+            SupportedResources::CronJob(r) => serde_json::to_value(r),
+            SupportedResources::Secret(s) => serde_json::to_value(s),
+            SupportedResources::Service(s) => serde_json::to_value(s),
+            SupportedResources::ClusterIssuer(c) => serde_json::to_value(c),
+            SupportedResources::PodDisruptionBudget(p) => serde_json::to_value(p),
         }
     }
 
@@ -206,6 +249,7 @@ impl SupportedResources {
             SupportedResources::Secret(_) => false,
             SupportedResources::Service(_) => false,
             SupportedResources::ClusterIssuer(_) => false,
+            SupportedResources::PodDisruptionBudget(_) => false,
         }
     }
     fn fixup_pod_template(template: PodTemplateSpec, ns: &str) -> Result<PodTemplateSpec, Box<dyn Error>> {
@@ -215,16 +259,10 @@ impl SupportedResources {
                 // first do env-var secrets
                 spec.containers = spec.containers.clone().into_iter().map(|mut container| {
                     container.env = container.env.map(|env_list| env_list.into_iter().map(|mut e| {
-                        match e.value_from.as_mut() {
-                            Some(value) => match value.secret_key_ref.as_mut() {
-                                Some(key_ref) =>  {
-                                    // the secret names have to be suffixed with .<namespace> in order for them not to be available across namespace
-                                    key_ref.name = format!("{}.{}", &key_ref.name, &ns);
-                                },
-                                _ => {}
-                            },
-                            _ => {},
-                        };
+                        if let Some(value) = e.value_from.as_mut() { if let Some(key_ref) = value.secret_key_ref.as_mut() {
+                            // the secret names have to be suffixed with .<namespace> in order for them not to be available across namespace
+                            key_ref.name = format!("{}.{}", &key_ref.name, &ns);
+                        } };
                         e
                     }).collect());
                     container
@@ -438,15 +476,87 @@ impl SupportedResources {
                 issuer.metadata.name = Some(format!("{}", metadata_name(issuer)));
                 resource
             }
+            SupportedResources::PodDisruptionBudget(ref mut pdb) => {
+                if pdb.metadata.name.is_none() {
+                    return Err(anyhow!("metadata.name is empty").into());
+                }
+                if pdb.metadata.namespace.is_none() {
+                    return Err(anyhow!("metadata.namespace is empty").into());
+                }
+                pdb.metadata = Self::fixup_metadata(pdb.metadata.clone(), None)?;
+                pdb.metadata.name = Some(format!("{}", metadata_name(pdb)));
+                resource
+            }
         };
         Ok(resource)
     }
 }
+// metadata that the cluster or skate sets, rather than the user
+const EXPORT_STRIPPED_METADATA: &[&str] = &["uid", "resourceVersion", "creationTimestamp", "generation", "managedFields", "selfLink"];
+
+// strips what fixup and the scheduler added to a stored manifest, so that it can be applied to another cluster
+pub fn export_manifest(manifest: &Value) -> Value {
+    let mut manifest = manifest.clone();
+    if let Value::Mapping(m) = &mut manifest {
+        m.remove("status");
+    }
+
+    // fixup renames resources to name.namespace, the original name is kept in a label
+    let original_name = manifest["metadata"]["labels"]["skate.io/name"].as_str().map(|n| n.to_string());
+    if let (Some(name), Value::Mapping(meta)) = (original_name, &mut manifest["metadata"]) {
+        meta.insert(Value::from("name"), Value::from(name));
+        for key in EXPORT_STRIPPED_METADATA {
+            meta.remove(*key);
+        }
+    }
+    strip_skate_metadata(&mut manifest);
+    manifest
+}
+
+// the labels and annotations skate sets itself. the skate.io ones users write, like canary-weight or limit-rps, are kept
+const SKATE_INTERNAL_METADATA: &[&str] = &[
+    "io.skate",
+    "skate.io/name",
+    "skate.io/namespace",
+    "skate.io/hash",
+    LAST_APPLIED_ANNOTATION,
+    "skate.io/deployment",
+    "skate.io/daemonset",
+    "skate.io/cronjob",
+    "skate.io/replica",
+    PRIORITY_LABEL,
+    HOST_PORTS_LABEL,
+    TTL_LABEL,
+];
+
+// removes skate's internal labels and annotations from every metadata in the manifest, including pod templates
+fn strip_skate_metadata(value: &mut Value) {
+    match value {
+        Value::Mapping(m) => {
+            for key in ["labels", "annotations"] {
+                let empty = match m.get_mut(key) {
+                    Some(Value::Mapping(entries)) => {
+                        entries.retain(|k, _| k.as_str().is_some_and(|k| !SKATE_INTERNAL_METADATA.contains(&k)));
+                        entries.is_empty()
+                    }
+                    _ => false,
+                };
+                if empty {
+                    m.remove(key);
+                }
+            }
+            m.iter_mut().for_each(|(_, v)| strip_skate_metadata(v));
+        }
+        Value::Sequence(items) => items.iter_mut().for_each(strip_skate_metadata),
+        _ => {}
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
 
-    use crate::resource::ResourceType;
+    use crate::resource::{export_manifest, ResourceType};
 
     #[test]
     fn test_resource_type_from_str() {
@@ -472,4 +582,93 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_export_manifest() {
+        let stored: serde_yaml::Value = serde_yaml::from_str(r#"
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: web.default
+  namespace: default
+  uid: abc
+  labels:
+    app: web
+    skate.io/name: web
+    skate.io/namespace: default
+    skate.io/hash: "123"
+  annotations:
+    io.skate: "true"
+spec:
+  replicas: 2
+  template:
+    metadata:
+      labels:
+        app: web
+        skate.io/deployment: web
+      annotations:
+        io.skate: "true"
+    spec:
+      containers:
+        - name: nginx
+          image: nginx
+status:
+  replicas: 2
+"#).unwrap();
+
+        let expected: serde_yaml::Value = serde_yaml::from_str(r#"
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: web
+  namespace: default
+  labels:
+    app: web
+spec:
+  replicas: 2
+  template:
+    metadata:
+      labels:
+        app: web
+    spec:
+      containers:
+        - name: nginx
+          image: nginx
+"#).unwrap();
+
+        assert_eq!(expected, export_manifest(&stored));
+    }
+
+    #[test]
+    fn test_export_manifest_keeps_user_annotations() {
+        let stored: serde_yaml::Value = serde_yaml::from_str(r#"
+apiVersion: networking.k8s.io/v1
+kind: Ingress
+metadata:
+  name: web.default
+  namespace: default
+  labels:
+    skate.io/name: web
+    skate.io/namespace: default
+  annotations:
+    skate.io/canary-service: web-canary
+    skate.io/canary-weight: "10"
+    skate.io/limit-rps: "20"
+    skate.io/last-applied-configuration: "{}"
+"#).unwrap();
+
+        let expected: serde_yaml::Value = serde_yaml::from_str(r#"
+apiVersion: networking.k8s.io/v1
+kind: Ingress
+metadata:
+  name: web
+  namespace: default
+  annotations:
+    skate.io/canary-service: web-canary
+    skate.io/canary-weight: "10"
+    skate.io/limit-rps: "20"
+"#).unwrap();
+
+        assert_eq!(expected, export_manifest(&stored));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,40 @@
+# prints what the provider's metadata says about where the node runs, as key=value lines
+md() { curl -fsS -m 2 "$@" 2>/dev/null; }
+if command -v curl >/dev/null 2>&1; then
+  token=$(md -X PUT -H 'X-aws-ec2-metadata-token-ttl-seconds: 60' http://169.254.169.254/latest/api/token)
+  if [ -n "$token" ]; then
+    echo "provider=aws"
+    echo "region=$(md -H "X-aws-ec2-metadata-token: $token" http://169.254.169.254/latest/meta-data/placement/region)"
+    echo "zone=$(md -H "X-aws-ec2-metadata-token: $token" http://169.254.169.254/latest/meta-data/placement/availability-zone)"
+    exit 0
+  fi
+  zone=$(md -H 'Metadata-Flavor: Google' http://metadata.google.internal/computeMetadata/v1/instance/zone)
+  if [ -n "$zone" ]; then
+    echo "provider=gcp"
+    echo "zone=${zone##*/}"
+    exit 0
+  fi
+  compute=$(md -H 'Metadata: true' 'http://169.254.169.254/metadata/instance/compute?api-version=2021-02-01&format=json')
+  if [ -n "$compute" ]; then
+    echo "provider=azure"
+    echo "azure=$compute"
+    exit 0
+  fi
+  zone=$(md http://169.254.169.254/hetzner/v1/metadata/availability-zone)
+  if [ -n "$zone" ]; then
+    echo "provider=hetzner"
+    echo "region=$(md http://169.254.169.254/hetzner/v1/metadata/region)"
+    echo "zone=$zone"
+    exit 0
+  fi
+  region=$(md http://169.254.169.254/metadata/v1/region)
+  if [ -n "$region" ]; then
+    echo "provider=digitalocean"
+    echo "region=$region"
+    exit 0
+  fi
+fi
+if [ -f /etc/machine-info ]; then
+  sed -n 's/^LOCATION=/location=/p' /etc/machine-info
+fi
+exit 0
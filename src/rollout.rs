@@ -3,19 +3,22 @@ use std::ffi::OsString;
 use std::ops::Deref;
 use std::str::FromStr;
 use anyhow::anyhow;
-use crate::config::Config;
+use crate::config::{Access, Config};
 use crate::skate::ConfigFileArgs;
 use clap::{Args, Subcommand};
-use dialoguer::Confirm;
 use itertools::Itertools;
 use serde_yaml::Value;
+use crate::confirm::{confirm, Target};
+use crate::controllers::ingress::{CANARY_SERVICE_ANNOTATION, CANARY_WEIGHT_ANNOTATION};
 use crate::deps::{SshManager, With};
+use crate::edit::validate_modified;
 use crate::errors::SkateError;
+use crate::lock;
 use crate::refresh::{Refresh, RefreshDeps};
 use crate::resource::{ResourceType, SupportedResources};
 use crate::scheduler::{DefaultScheduler, Scheduler};
 use crate::skatelet::system::podman::PodmanPodInfo;
-use crate::state::state::ClusterState;
+use crate::state::state::{ClusterState, OwnerRef};
 use crate::util::NamespacedName;
 
 #[derive(Debug, Args)]
@@ -32,6 +35,10 @@ pub enum Commands {
         long_about = "Resource rollout will be restarted"
     )]
     Restart(RestartArgs),
+    #[command(
+        long_about = "Shift an ingress's traffic to its canary service"
+    )]
+    Promote(PromoteArgs),
 }
 
 #[derive(Debug, Args)]
@@ -41,12 +48,25 @@ pub struct RestartArgs {
     #[arg(long, long_help = "Will not affect the cluster if set to true")]
     pub dry_run: bool,
     pub resource: ResourceArg,
-    #[arg(long, short, long_help = "Namespace of the resource.", default_value_t = String::from("default"))]
-    namespace: String,
+    #[arg(long, short, long_help = "Namespace of the resource, the cluster's default namespace if not given.")]
+    namespace: Option<String>,
     #[arg(long, short, long_help = "Answer yes to confirmation")]
     pub yes: bool,
 }
 
+#[derive(Debug, Args)]
+pub struct PromoteArgs {
+    #[command(flatten)]
+    pub config: ConfigFileArgs,
+    #[arg(long, long_help = "Will not affect the cluster if set to true")]
+    pub dry_run: bool,
+    #[arg(name = "ingress/NAME")]
+    pub resource: ResourceArg,
+    #[arg(long, short, long_help = "Namespace of the resource, the cluster's default namespace if not given.")]
+    namespace: Option<String>,
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100), default_value_t = 100, long_help = "Percentage of requests to send to the canary service.")]
+    pub weight: u8,
+}
 
 pub trait RolloutDeps: With<dyn SshManager> {}
 
@@ -63,6 +83,11 @@ impl<D: RolloutDeps + RefreshDeps> Rollout<D> {
                 args.config = global_args.config;
                 self.restart(args).await
             }
+            Commands::Promote(args) => {
+                let mut args = args;
+                args.config = global_args.config;
+                self.promote(args).await
+            }
         }
     }
 
@@ -77,20 +102,17 @@ impl<D: RolloutDeps + RefreshDeps> Rollout<D> {
         state.nodes.iter_mut().for_each(|n|
             if let Some(hi) = n.host_info.as_mut() {
                 if let Some(si) = hi.system_info.as_mut() {
-                    match &mut si.pods {
-                        Some(v) => {
-                            v.iter_mut().for_each(|item|
-                                for name in &names {
-                                    if &name_selector(item) != name {
-                                        return
-                                    }
-                                    let mut labels=  item.labels.clone();
-                                    labels.insert("skate.io/hash".to_string(), "".to_string());
-                                    item.labels = labels;
+                    if let Some(v) = &mut si.pods {
+                        v.iter_mut().for_each(|item|
+                            for name in &names {
+                                if &name_selector(item) != name {
+                                    return
                                 }
-                            );
-                        }
-                        None => {}
+                                let mut labels=  item.labels.clone();
+                                labels.insert("skate.io/hash".to_string(), "".to_string());
+                                item.labels = labels;
+                            }
+                        );
                     }
                 };
 
@@ -101,7 +123,7 @@ impl<D: RolloutDeps + RefreshDeps> Rollout<D> {
 
 
     pub async fn restart(&self, args: RestartArgs) -> Result<(), SkateError> {
-        let (resource_type, _) = args.resource.parse()?;
+        let (resource_type, name) = args.resource.parse()?;
 
         match resource_type {
             ResourceType::Deployment => {},
@@ -109,9 +131,11 @@ impl<D: RolloutDeps + RefreshDeps> Rollout<D> {
             _ => return Err("resource type not supported".to_string().into())
         }
 
-        let config = Config::load(Some(args.config.skateconfig.clone()))?;
+        let config = Config::load(args.config.skateconfig.clone())?;
 
-        let cluster = config.active_cluster(config.current_context.clone())?;
+        let cluster = config.authorized_cluster(config.current_context.clone(), Access::Deploy)?;
+        let namespace = args.namespace.clone().unwrap_or(cluster.default_namespace().to_string());
+        cluster.authorize_namespaces(&[&namespace])?;
 
         let mgr = self.deps.get();
         let (conns, _) = mgr.cluster_connect(cluster).await;
@@ -123,7 +147,8 @@ impl<D: RolloutDeps + RefreshDeps> Rollout<D> {
         let mut catalogue = state.catalogue_mut(None, &[]);
 
         let resources = catalogue.iter_mut().filter_map(|item| {
-            if item.object.resource_type == resource_type && item.object.manifest.is_some() {
+            let selected = item.object.name.namespace == namespace && name.as_ref().is_none_or(|n| *n == item.object.name.name);
+            if item.object.resource_type == resource_type && selected && item.object.manifest.is_some() {
                 let deserialized =  SupportedResources::try_from(item.object.deref()).ok();
                 // invalidate current state hash
                 item.object.manifest_hash = "".to_string();
@@ -161,29 +186,77 @@ impl<D: RolloutDeps + RefreshDeps> Rollout<D> {
             }
 
 
-            if !args.dry_run && ! args.yes{
-
-                let confirmation = Confirm::new()
-                    .with_prompt(format!("Are you sure you want to redeploy these {} resources?", resources.len()))
-                    .wait_for_newline(true)
-                    .interact()
-                    .unwrap();
+            if !args.dry_run {
+                // every pod of the resources is replaced
+                let targets: Vec<_> = resources.iter().flat_map(|r| {
+                    state.owned_pods(&OwnerRef::new(resource_type.clone(), &r.name())).into_iter()
+                        .map(|(pod, node)| Target::new(&node.node_name, "Pod", &pod.name))
+                        .collect::<Vec<_>>()
+                }).collect();
 
-                if !confirmation {
+                if !confirm(&format!("Are you sure you want to redeploy these {} resources?", resources.len()), &targets, args.yes)? {
                     return Ok(())
                 }
-
             }
 
-            let scheduler = DefaultScheduler{};
+            let scheduler = DefaultScheduler::default();
 
-            let _ = scheduler.schedule(&conns, state, resources, args.dry_run).await?;
+            let run = async {
+                scheduler.schedule(&conns, state, resources, args.dry_run).await?;
+                Ok(())
+            };
+            match args.dry_run {
+                true => run.await?,
+                false => lock::locked(cluster, &conns, "rollout restart", run).await?,
+            }
         }
 
 
         Ok(())
     }
 
+    // sets the canary weight of an ingress, which must already have a canary service
+    pub async fn promote(&self, args: PromoteArgs) -> Result<(), SkateError> {
+        let (resource_type, name) = args.resource.parse()?;
+        if resource_type != ResourceType::Ingress {
+            return Err(anyhow!("only ingresses can be promoted").into());
+        }
+        let name = name.ok_or(anyhow!("ingress name is required"))?;
+
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), Access::Deploy)?;
+        let ns_name = NamespacedName::from_arg(&name, args.namespace.as_deref(), Some(cluster.default_namespace()))?;
+        cluster.authorize_namespaces(&[&ns_name.namespace])?;
+
+        let mgr = self.deps.get();
+        let (conns, _) = mgr.cluster_connect(cluster).await;
+        let conns = conns.ok_or("failed to get cluster connections".to_string())?;
+
+        let mut state = Refresh::<D>::refreshed_state(&cluster.name, &conns, &config).await?;
+
+        let mut manifest = state.catalogue(None, &[ResourceType::Ingress]).into_iter()
+            .find(|item| item.object.name == ns_name)
+            .and_then(|item| item.object.manifest.clone())
+            .ok_or(anyhow!("ingress {} not found", ns_name))?;
+
+        if manifest["metadata"]["annotations"][CANARY_SERVICE_ANNOTATION].as_str().is_none() {
+            return Err(anyhow!("ingress {} has no {} annotation", ns_name, CANARY_SERVICE_ANNOTATION).into());
+        }
+        manifest["metadata"]["annotations"][CANARY_WEIGHT_ANNOTATION] = Value::from(args.weight.to_string());
+
+        let resource = validate_modified(&manifest, &ns_name)?;
+        println!("sending {}% of requests for ingress {} to its canary", args.weight, ns_name);
+
+        let scheduler = DefaultScheduler::default();
+        let run = async {
+            scheduler.schedule(&conns, &mut state, vec![resource], args.dry_run).await?;
+            Ok(())
+        };
+        match args.dry_run {
+            true => run.await,
+            false => lock::locked(cluster, &conns, "rollout promote", run).await,
+        }
+    }
 }
 #[derive( Clone, Debug)]
 pub struct ResourceArg(String);
@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+use anyhow::anyhow;
+use clap::{Args, ValueEnum};
+use k8s_openapi::api::core::v1::{Container, ContainerPort, EnvVar, Pod, PodSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use strum_macros::Display;
+use crate::apply::{Apply, ApplyDeps};
+use crate::attach::attach_command;
+use crate::config::Config;
+use crate::errors::SkateError;
+use crate::resource::SupportedResources;
+use crate::scheduler::{DefaultScheduler, OpType};
+use crate::skate::ConfigFileArgs;
+use crate::util::CHECKBOX_EMOJI;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Display)]
+pub enum RestartPolicy {
+    Always,
+    OnFailure,
+    Never,
+}
+
+#[derive(Debug, Args)]
+pub struct RunArgs {
+    #[arg(long_help = "Name of the pod.")]
+    pub name: String,
+    #[arg(long, long_help = "Image to run.")]
+    pub image: String,
+    #[arg(long, short, long_help = "Namespace of the pod, defaults to the cluster's default namespace.")]
+    pub namespace: Option<String>,
+    #[arg(long, long_help = "Port the container exposes.")]
+    pub port: Option<i32>,
+    #[arg(long, short, long_help = "Environment variables to set, as KEY=VALUE.")]
+    pub env: Vec<String>,
+    #[arg(long, short, long_help = "Labels to set on the pod, as KEY=VALUE.")]
+    pub labels: Vec<String>,
+    #[arg(long, value_enum, default_value_t = RestartPolicy::Always, long_help = "Restart policy of the pod. With Never the pod's output is streamed until it exits.")]
+    pub restart: RestartPolicy,
+    #[arg(long, short = 'i', long_help = "Keep the container's stdin open and attach to it once it's started.")]
+    pub stdin: bool,
+    #[arg(long, short, long_help = "Give the container a tty, usually with --stdin.")]
+    pub tty: bool,
+    #[arg(long, long_help = "Delete the pod once it exits, requires --restart=Never.")]
+    pub rm: bool,
+    #[arg(long, long_help = "Will not affect the cluster if set to true")]
+    pub dry_run: bool,
+    #[arg(allow_hyphen_values = true, last = true, long_help = "Command to run instead of the image's entrypoint.")]
+    pub command: Vec<String>,
+    #[command(flatten)]
+    pub config: ConfigFileArgs,
+}
+
+pub trait RunDeps: ApplyDeps {}
+
+pub struct Run<D: RunDeps> {
+    pub deps: D,
+}
+
+impl<D: RunDeps> Run<D> {
+    pub async fn run(&self, args: RunArgs) -> Result<(), SkateError> {
+        if args.rm && args.restart != RestartPolicy::Never {
+            return Err(anyhow!("--rm requires --restart=Never").into());
+        }
+
+        let mut config = Config::load(args.config.skateconfig.clone())?;
+        if args.config.context.is_some() {
+            config.current_context = args.config.context.clone();
+        }
+
+        let pod = pod_from_args(&args)?;
+        let result = Apply::<D>::apply_supported_resources(&self.deps, &config, vec![SupportedResources::Pod(pod)], args.dry_run, false, &DefaultScheduler::default()).await?;
+
+        let interactive = args.stdin || args.tty;
+        if args.dry_run || (args.restart != RestartPolicy::Never && !interactive) {
+            return Ok(());
+        }
+
+        let placed = result.placements.into_iter()
+            .find(|op| matches!(op.operation, OpType::Create | OpType::Clobber) && op.error.is_none())
+            .ok_or(anyhow!("failed to schedule pod {}", args.name))?;
+        let node_name = placed.node.as_ref().map(|n| n.node_name.clone()).ok_or(anyhow!("pod {} has no node", args.name))?;
+        let pod_name = match &placed.resource {
+            SupportedResources::Pod(p) => p.metadata.name.clone().unwrap_or_default(),
+            _ => return Err(anyhow!("expected a pod").into()),
+        };
+
+        let cluster = config.active_cluster(config.current_context.clone())?;
+        let node = cluster.nodes.iter().find(|n| n.name == node_name).ok_or(anyhow!("no node {} in config", node_name))?;
+        let conn = self.deps.get().node_connect(cluster, node).await?;
+
+        // podman names a pod's containers <pod>-<container>, and logs -f returns once the container exits
+        let container = format!("{}-{}", pod_name, args.name);
+        let exit_code = match interactive {
+            // output from before attaching isn't shown, with a tty a prompt is redrawn on the next key
+            true => conn.execute_interactive(&attach_command(&container, args.stdin), args.stdin, args.tty).await?.to_string(),
+            false => {
+                conn.execute_stdout(&format!("sudo podman logs -f {}", container), false, false).await?;
+                conn.execute(&format!("sudo podman wait {}", container)).await?.trim().to_string()
+            }
+        };
+        // detached from a container that's still running
+        if interactive && args.restart != RestartPolicy::Never {
+            return Ok(());
+        }
+
+        if args.rm {
+            let manifest = serde_yaml::to_string(&placed.resource).map_err(|e| anyhow!(e).context("failed to serialize pod"))?;
+            conn.remove_resource_by_manifest(&manifest).await?;
+            println!("{} removed pod {}", CHECKBOX_EMOJI, pod_name);
+        }
+
+        match exit_code.as_str() {
+            "0" => Ok(()),
+            code => Err(anyhow!("pod {} exited with code {}", args.name, code).into()),
+        }
+    }
+}
+
+fn key_values(values: &[String], what: &str) -> Result<Vec<(String, String)>, SkateError> {
+    values.iter().map(|v| {
+        v.split_once('=')
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .ok_or(anyhow!("invalid {} {}, expected KEY=VALUE", what, v).into())
+    }).collect()
+}
+
+fn pod_from_args(args: &RunArgs) -> Result<Pod, SkateError> {
+    let env = key_values(&args.env, "env")?.into_iter()
+        .map(|(name, value)| EnvVar { name, value: Some(value), value_from: None })
+        .collect::<Vec<_>>();
+    let labels: BTreeMap<_, _> = key_values(&args.labels, "label")?.into_iter().collect();
+
+    Ok(Pod {
+        metadata: ObjectMeta {
+            name: Some(args.name.clone()),
+            namespace: args.namespace.clone(),
+            labels: match labels.is_empty() {
+                true => None,
+                false => Some(labels),
+            },
+            ..Default::default()
+        },
+        spec: Some(PodSpec {
+            containers: vec![Container {
+                name: args.name.clone(),
+                image: Some(args.image.clone()),
+                args: match args.command.is_empty() {
+                    true => None,
+                    false => Some(args.command[1..].to_vec()),
+                },
+                command: args.command.first().map(|c| vec![c.clone()]),
+                env: match env.is_empty() {
+                    true => None,
+                    false => Some(env),
+                },
+                ports: args.port.map(|p| vec![ContainerPort { container_port: p, ..Default::default() }]),
+                stdin: args.stdin.then_some(true),
+                tty: args.tty.then_some(true),
+                ..Default::default()
+            }],
+            restart_policy: Some(args.restart.to_string()),
+            ..Default::default()
+        }),
+        status: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use crate::run::{pod_from_args, RunArgs};
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: RunArgs,
+    }
+
+    #[test]
+    fn test_pod_from_args() {
+        let cli = Cli::parse_from(["run", "hello", "--image", "busybox", "-n", "test", "--port", "80", "-e", "A=1", "--restart", "never", "--", "sh", "-c", "echo hi"]);
+        let pod = pod_from_args(&cli.args).unwrap();
+
+        assert_eq!(Some("hello".to_string()), pod.metadata.name);
+        assert_eq!(Some("test".to_string()), pod.metadata.namespace);
+        let spec = pod.spec.unwrap();
+        assert_eq!(Some("Never".to_string()), spec.restart_policy);
+
+        let container = &spec.containers[0];
+        assert_eq!(Some("busybox".to_string()), container.image);
+        assert_eq!(Some(vec!["sh".to_string()]), container.command);
+        assert_eq!(Some(vec!["-c".to_string(), "echo hi".to_string()]), container.args);
+        assert_eq!(80, container.ports.as_ref().unwrap()[0].container_port);
+        assert_eq!("A", container.env.as_ref().unwrap()[0].name);
+
+        assert_eq!(None, container.stdin);
+
+        let cli = Cli::parse_from(["run", "repl", "--image", "python", "-it", "--restart", "never", "--", "python"]);
+        let container = &pod_from_args(&cli.args).unwrap().spec.unwrap().containers[0];
+        assert_eq!((Some(true), Some(true)), (container.stdin, container.tty));
+
+        let cli = Cli::parse_from(["run", "hello", "--image", "busybox", "-e", "nope"]);
+        assert!(pod_from_args(&cli.args).is_err());
+    }
+}
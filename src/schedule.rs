@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use anyhow::anyhow;
+use clap::Args;
+use crate::apply::{merge_last_applied, prepare_objects, read_manifests};
+use crate::config::{Access, Config};
+use crate::deps::{SshManager, With};
+use crate::errors::SkateError;
+use crate::get::{custom_columns, OutputFormat};
+use crate::image::{image_architectures, pod_images};
+use crate::refresh::{Refresh, RefreshDeps};
+use crate::scheduler::{DefaultScheduler, OpType, SimulatedPlacement};
+use crate::skate::ConfigFileArgs;
+use crate::snapshot::load_snapshot;
+use crate::util::CROSS_EMOJI;
+
+#[derive(Debug, Args)]
+pub struct ScheduleArgs {
+    #[arg(short, long, required = true, long_help = "The files or directories that contain the manifests to place.")]
+    filename: Vec<String>,
+    #[arg(long, long_help = "Only work out the placements, nothing is changed on the nodes. Required, nothing else is supported yet.")]
+    dry_run: bool,
+    #[arg(long, long_help = "Place against a saved snapshot of the cluster's state rather than refreshing it, without connecting to the nodes.")]
+    snapshot: Option<String>,
+    #[arg(long, short, default_value_t = OutputFormat::Table, long_help = "Output format: table, wide, yaml, json or custom-columns=<header>:<path>,... over the \
+placements, eg custom-columns=NAME:.name,NODE:.node")]
+    output: OutputFormat,
+    #[arg(long, long_help = "Break ties between equally loaded nodes by this seed rather than by node name. The same seed gives the same placements.")]
+    seed: Option<u64>,
+    #[command(flatten)]
+    config: ConfigFileArgs,
+}
+
+pub trait ScheduleDeps: With<dyn SshManager> {}
+
+pub struct Schedule<D: ScheduleDeps> {
+    pub deps: D,
+}
+
+fn print_placement(placement: &SimulatedPlacement) {
+    let node = placement.node.as_deref().unwrap_or("-");
+    match (&placement.error, &placement.operation) {
+        (Some(err), _) => println!("{} {} {}: {}", CROSS_EMOJI, placement.kind, placement.name, err),
+        (None, OpType::Delete) => println!("{} {} {} deleted from {}", placement.operation.symbol(), placement.kind, placement.name, node),
+        (None, OpType::Unchanged) => println!("{} {} {} unchanged on {}", placement.operation.symbol(), placement.kind, placement.name, node),
+        (None, _) => println!("{} {} {} on {}", placement.operation.symbol(), placement.kind, placement.name, node),
+    }
+    for score in placement.scores.iter() {
+        println!("    {} {} pods", score.node_name, score.pods);
+    }
+    for rejected in placement.rejected.iter() {
+        println!("    {} rejected: {}", rejected.node_name, rejected.reason);
+    }
+    for pod in placement.preempts.iter() {
+        println!("    preempts {}", pod);
+    }
+}
+
+impl<D: ScheduleDeps + RefreshDeps> Schedule<D> {
+    pub async fn schedule(&self, args: ScheduleArgs) -> Result<(), SkateError> {
+        if !args.dry_run {
+            return Err(anyhow!("skate schedule only simulates placements, pass --dry-run or use skate apply").into());
+        }
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), Access::ReadOnly)?;
+        let objects = prepare_objects(cluster, read_manifests(args.filename)?)?;
+
+        let (mut state, image_archs) = match args.snapshot.as_ref() {
+            // the images can't be inspected without a node, so architectures aren't checked
+            Some(name) => (load_snapshot(&cluster.name, name)?, HashMap::new()),
+            None => {
+                let (conns, errors) = self.deps.get().cluster_connect(cluster).await;
+                if let Some(errors) = errors {
+                    eprintln!("{}", errors)
+                }
+                let conns = conns.ok_or(anyhow!("failed to connect to any node"))?;
+                let state = Refresh::<D>::refreshed_state(&cluster.name, &conns, &config).await?;
+                let images: Vec<_> = objects.iter().flat_map(pod_images).collect();
+                let archs = image_architectures(&conns, &images).await;
+                (state, archs)
+            }
+        };
+
+        let objects = objects.into_iter().map(|o| merge_last_applied(&state, o)).collect::<Result<Vec<_>, _>>()?;
+        let placements = DefaultScheduler::simulate(&mut state, objects, &image_archs, args.seed);
+
+        match args.output {
+            OutputFormat::Table | OutputFormat::Wide => placements.iter().for_each(print_placement),
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&placements).map_err(|e| anyhow!(e))?),
+            OutputFormat::Yaml => println!("{}", serde_yaml::to_string(&placements).map_err(|e| anyhow!(e))?),
+            OutputFormat::CustomColumns(columns) => {
+                let documents = placements.iter().map(serde_json::to_value).collect::<Result<Vec<_>, _>>().map_err(|e| anyhow!(e))?;
+                println!("{}", custom_columns::render(&documents, &columns, false));
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
+use std::time::{Duration, Instant};
 use anyhow::anyhow;
 use async_trait::async_trait;
 use colored::Colorize;
@@ -10,11 +11,21 @@ use k8s_openapi::api::apps::v1::{DaemonSet, Deployment, RollingUpdateDeployment}
 use k8s_openapi::api::batch::v1::CronJob;
 use k8s_openapi::api::core::v1::{Node as K8sNode, Pod, Secret, Service};
 use k8s_openapi::api::networking::v1::Ingress;
+use k8s_openapi::api::policy::v1::PodDisruptionBudget;
 use k8s_openapi::Metadata;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 
 
+use crate::affinity;
+use crate::gpu;
+use crate::host_network;
+use crate::image::{image_architectures, incompatible_images, pod_images, ImageArchitectures};
+use crate::ingress_class;
+use crate::priority;
+use crate::capacity;
 use crate::resource::SupportedResources;
-use crate::skatelet::system::podman::PodmanPodStatus;
+use crate::skatelet::system::podman::{PodmanPodInfo, PodmanPodStatus};
 use crate::spec::cert::ClusterIssuer;
 use crate::ssh::{SshClients};
 use crate::state::state::{ClusterState, NodeState};
@@ -31,10 +42,36 @@ pub trait Scheduler {
     async fn schedule(&self, conns: &SshClients, state: &mut ClusterState, objects: Vec<SupportedResources>, dry_run: bool) -> Result<ScheduleResult, Box<dyn Error>>;
 }
 
-pub struct DefaultScheduler {}
+// how many nodes a resource is tried on before giving up
+pub const DEFAULT_MAX_ATTEMPTS: usize = 3;
+
+pub struct DefaultScheduler {
+    pub max_attempts: usize,
+    // remove the copies of resources left on nodes they can't stay on, and duplicates
+    pub cleanup: bool,
+    // print the raw output of the nodes' skatelet runs
+    pub verbose: bool,
+    // nodes new pods only go to when no other node fits them, eg the node a pod was evicted from
+    pub avoid_nodes: Vec<String>,
+    // breaks ties between equally loaded nodes by a hash of it rather than by node name, still the same for every run
+    pub seed: Option<u64>,
+}
+
+impl Default for DefaultScheduler {
+    fn default() -> Self {
+        DefaultScheduler {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            cleanup: true,
+            verbose: false,
+            avoid_nodes: vec![],
+            seed: None,
+        }
+    }
+}
 
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum OpType {
     Info,
     Create,
@@ -55,6 +92,12 @@ impl OpType {
     }
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct ScheduleAttempt {
+    pub node_name: String,
+    pub error: Option<String>,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct ScheduledOperation {
     pub resource: SupportedResources,
@@ -62,6 +105,15 @@ pub struct ScheduledOperation {
     pub operation: OpType,
     pub error: Option<String>,
     pub silent: bool,
+    // every node the operation was tried on, in order
+    pub attempts: Vec<ScheduleAttempt>,
+    // how long carrying out the operation took, None if it wasn't carried out
+    pub duration: Option<Duration>,
+    // the pod that a deleted pod made room for
+    pub preempted_by: Option<NamespacedName>,
+    // a delete of a copy the object no longer has a place for, on a node it can't stay on or a duplicate. unlike scale downs
+    // and replacements, these are skipped without cleanup
+    pub orphaned: bool,
 }
 
 impl ScheduledOperation {
@@ -72,6 +124,10 @@ impl ScheduledOperation {
             operation: op,
             error: None,
             silent: false,
+            attempts: vec![],
+            duration: None,
+            preempted_by: None,
+            orphaned: false,
         }
     }
     pub fn silent(mut self) -> Self {
@@ -82,6 +138,10 @@ impl ScheduledOperation {
         self.node = Some(n);
         self
     }
+    pub fn orphaned(mut self, orphaned: bool) -> Self {
+        self.orphaned = orphaned;
+        self
+    }
     pub fn error(mut self, err: String) -> Self {
         self.error = Some(err);
         self
@@ -93,14 +153,51 @@ pub struct ApplyPlan {
     pub actions: HashMap<NamespacedName, Vec<ScheduledOperation>>,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct RejectedNode {
     pub node_name: String,
     pub reason: String,
 }
 
+// a node the object fits on, the one running the fewest pods is chosen. ties go to the first by name, or by seed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeScore {
+    pub node_name: String,
+    pub pods: usize,
+}
+
 pub struct NodeSelection {
     pub selected: Option<NodeState>,
     pub rejected: Vec<RejectedNode>,
+    pub scores: Vec<NodeScore>,
+}
+
+// where an operation of the plan would go, worked out from the state alone
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulatedPlacement {
+    pub kind: String,
+    pub name: String,
+    pub operation: OpType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub scores: Vec<NodeScore>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rejected: Vec<RejectedNode>,
+    // the pods that would be evicted to make room
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub preempts: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// a deployment's pod to evict from one node and recreate on another
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RebalanceMove {
+    pub pod: String,
+    pub deployment: NamespacedName,
+    pub from: String,
+    pub to: String,
 }
 
 // 3 types of planning:
@@ -108,7 +205,32 @@ pub struct NodeSelection {
 // maybe > 0 per node (daemonset)
 // distributed (pod, cron)
 impl DefaultScheduler {
-    fn choose_node(nodes: Vec<NodeState>, object: &SupportedResources) -> NodeSelection {
+    // the avoided nodes are only chosen from when none of the others fit
+    fn choose_preferred_node(&self, state: &ClusterState, nodes: Vec<NodeState>, object: &SupportedResources, image_archs: &ImageArchitectures) -> NodeSelection {
+        if self.avoid_nodes.is_empty() {
+            return Self::choose_node(state, nodes, object, image_archs, self.seed);
+        }
+        let (avoided, preferred): (Vec<_>, Vec<_>) = nodes.iter().cloned().partition(|n| self.avoid_nodes.contains(&n.node_name));
+        let selection = Self::choose_node(state, preferred, object, image_archs, self.seed);
+        match (&selection.selected, avoided.is_empty()) {
+            (None, false) => Self::choose_node(state, nodes, object, image_archs, self.seed),
+            _ => selection,
+        }
+    }
+
+    // where the node comes among those running as many pods. the seeded order also depends on the object, so each replica
+    // of a deployment doesn't land on the same node of a tie
+    fn tie_break(seed: Option<u64>, object: &SupportedResources, node_name: &str) -> (u64, String) {
+        let rank = seed.map(|seed| {
+            let mut hasher = Sha256::new();
+            hasher.update(format!("{}/{}/{}", seed, object.name(), node_name));
+            let digest = hasher.finalize();
+            u64::from_be_bytes(digest[..8].try_into().expect("sha256 is 32 bytes"))
+        }).unwrap_or_default();
+        (rank, node_name.to_string())
+    }
+
+    fn choose_node(state: &ClusterState, nodes: Vec<NodeState>, object: &SupportedResources, image_archs: &ImageArchitectures, seed: Option<u64>) -> NodeSelection {
         // filter nodes based on resource requirements  - cpu, memory, etc
 
         let node_selector = match object {
@@ -120,7 +242,14 @@ impl DefaultScheduler {
             _ => None
         }.unwrap_or(BTreeMap::new());
 
+        let images = pod_images(object);
+        let (memory, cpus, gpus) = match object {
+            SupportedResources::Pod(pod) => (capacity::memory_request(pod), capacity::cpu_request(pod), gpu::gpu_request(pod)),
+            _ => (0, 0.0, 0),
+        };
+
         let mut rejected_nodes: Vec<RejectedNode> = vec!();
+        let cluster_pods: Vec<_> = state.filter_pods(&|_| true).into_iter().map(|(p, _)| p).collect();
 
 
         let filtered_nodes = nodes.iter().filter(|n| {
@@ -139,6 +268,56 @@ impl DefaultScheduler {
                 return false;
             }
 
+            // avoid nodes that are running out of disk or memory
+            if let Some(reason) = n.disk_pressure() {
+                rejected_nodes.push(RejectedNode {
+                    node_name: n.node_name.clone(),
+                    reason: format!("node has disk pressure: {}", reason),
+                });
+                return false;
+            }
+            if let Some(reason) = n.memory_pressure() {
+                rejected_nodes.push(RejectedNode {
+                    node_name: n.node_name.clone(),
+                    reason: format!("node has memory pressure: {}", reason),
+                });
+                return false;
+            }
+
+            // only nodes with room for the pod next to what the others there are limited to and the system's reservation
+            if let Some(reason) = capacity::insufficient_memory(n, memory)
+                .or_else(|| capacity::insufficient_cpu(n, cpus))
+                .or_else(|| gpu::insufficient_gpu(n, gpus)) {
+                rejected_nodes.push(RejectedNode {
+                    node_name: n.node_name.clone(),
+                    reason,
+                });
+                return false;
+            }
+
+            // only nodes whose architecture the images are published for
+            if let Some(reason) = incompatible_images(&Self::node_arch(n), &images, image_archs) {
+                rejected_nodes.push(RejectedNode {
+                    node_name: n.node_name.clone(),
+                    reason,
+                });
+                return false;
+            }
+
+            // only nodes that the pod's required affinity and anti-affinity allow, and where its host ports are free
+            if let Some(reason) = match object {
+                SupportedResources::Pod(pod) => affinity::anti_affinity_violation(pod, n)
+                    .or_else(|| affinity::affinity_violation(pod, n, &cluster_pods))
+                    .or_else(|| host_network::port_conflict(pod, n)),
+                _ => None,
+            } {
+                rejected_nodes.push(RejectedNode {
+                    node_name: n.node_name.clone(),
+                    reason,
+                });
+                return false;
+            }
+
             // only nodes that match the nodeselectors
             node_selector.iter().all(|(k, v)| {
                 let matches = node_labels.get(k).unwrap_or(&"".to_string()) == v;
@@ -153,30 +332,19 @@ impl DefaultScheduler {
         }).collect::<Vec<_>>();
 
 
-        let feasible_node = filtered_nodes.into_iter().fold(None, |maybe_prev_node, node| {
-            let node_pods = node.clone().host_info.and_then(|h| {
-                h.system_info.and_then(|si| {
-                    si.pods.map(|p| p.len())
-                })
-            }).unwrap_or(0);
-
-            maybe_prev_node.and_then(|prev_node: NodeState| {
-                prev_node.host_info.clone().and_then(|h| {
-                    h.system_info.and_then(|si| {
-                        si.pods.map(|prev_pods| {
-                            match prev_pods.len().cmp(&node_pods) {
-                                Ordering::Less => prev_node.clone(),
-                                Ordering::Equal => node.clone(),
-                                Ordering::Greater => node.clone(),
-                            }
-                        })
-                    })
-                })
-            }).or_else(|| Some(node.clone()))
-        });
+        let scores = filtered_nodes.iter().map(|n| NodeScore {
+            node_name: n.node_name.clone(),
+            pods: n.host_info.as_ref().and_then(|h| h.system_info.as_ref()).and_then(|si| si.pods.as_ref()).map(|p| p.len()).unwrap_or(0),
+        }).collect();
 
+        // the same nodes give the same choice whatever order they're in
+        let feasible_node = filtered_nodes.into_iter().min_by_key(|n| (Self::pod_count(n), Self::tie_break(seed, object, &n.node_name))).cloned();
+
+        NodeSelection { selected: feasible_node, rejected: rejected_nodes, scores }
+    }
 
-        NodeSelection { selected: feasible_node, rejected: rejected_nodes }
+    fn node_arch(node: &NodeState) -> String {
+        node.host_info.as_ref().map(|h| h.platform.arch.clone()).unwrap_or_default()
     }
 
     fn plan_daemonset(state: &ClusterState, ds: &DaemonSet) -> Result<ApplyPlan, Box<dyn Error>> {
@@ -211,7 +379,7 @@ impl DefaultScheduler {
             for pod_info in existing_pods {
                 let pod: Pod = pod_info.into();
                 let name = NamespacedName::from(pod.metadata.name.clone().unwrap_or_default().as_str());
-                let op = ScheduledOperation::new(OpType::Delete, SupportedResources::Pod(pod)).node(node.clone());
+                let op = ScheduledOperation::new(OpType::Delete, SupportedResources::Pod(pod)).node(node.clone()).orphaned(true);
                 match actions.get_mut(&name) {
                     Some(ops) => ops.push(op),
                     None => {
@@ -381,29 +549,8 @@ impl DefaultScheduler {
 
 
         for i in 0..replicas {
-            let pod_spec = d.spec.clone().map(|s| s.template).and_then(|t| t.spec).unwrap_or_default();
-
-            // inherit deployment labels
-            let mut meta = d.spec.as_ref().and_then(|s| s.template.metadata.clone()).unwrap_or_default();
-            // name format needs to be <type>.<fqn>.<replica>
-            let name = format!("dpl-{}-{}", deployment_name, i);
-            let ns_name = NamespacedName { name: name.clone(), namespace: ns.clone() };
-            // needs to be the fqn for kube play, since that's what it'll call the pod
-            meta.name = Some(ns_name.to_string());
-            meta.namespace = Some(ns.clone());
-
-            let mut labels = meta.labels.unwrap_or_default();
-            labels.insert("skate.io/name".to_string(), name);
-            labels.insert("skate.io/deployment".to_string(), deployment_name.clone());
-            labels.insert("skate.io/replica".to_string(), i.to_string());
-            meta.labels = Some(labels);
-
-            let pod = Pod {
-                metadata: meta,
-                spec: Some(pod_spec),
-                status: None,
-            };
-
+            let pod = Self::deployment_replica(&d, i as u32);
+            let ns_name = NamespacedName { name: format!("dpl-{}-{}", deployment_name, i), namespace: ns.clone() };
 
             let result = Self::plan_pod(state, &pod)?;
 
@@ -422,6 +569,34 @@ impl DefaultScheduler {
         })
     }
 
+    // the pod of the deployment's replica
+    fn deployment_replica(d: &Deployment, replica: u32) -> Pod {
+        let deployment_name = d.metadata.name.clone().unwrap_or_default();
+        let ns = d.metadata.namespace.clone().unwrap_or_default();
+        let pod_spec = d.spec.clone().map(|s| s.template).and_then(|t| t.spec).unwrap_or_default();
+
+        // inherit deployment labels
+        let mut meta = d.spec.as_ref().and_then(|s| s.template.metadata.clone()).unwrap_or_default();
+        // name format needs to be <type>.<fqn>.<replica>
+        let name = format!("dpl-{}-{}", deployment_name, replica);
+        let ns_name = NamespacedName { name: name.clone(), namespace: ns.clone() };
+        // needs to be the fqn for kube play, since that's what it'll call the pod
+        meta.name = Some(ns_name.to_string());
+        meta.namespace = Some(ns);
+
+        let mut labels = meta.labels.unwrap_or_default();
+        labels.insert("skate.io/name".to_string(), name);
+        labels.insert("skate.io/deployment".to_string(), deployment_name);
+        labels.insert("skate.io/replica".to_string(), replica.to_string());
+        meta.labels = Some(labels);
+
+        Pod {
+            metadata: meta,
+            spec: Some(pod_spec),
+            status: None,
+        }
+    }
+
     fn plan_pod(state: &ClusterState, object: &Pod) -> Result<Vec<ScheduledOperation>, Box<dyn Error>> {
         let mut new_pod = object.clone();
         //let feasible_node = Self::choose_node(state.nodes.clone(), &SupportedResources::Pod(object.clone())).ok_or("failed to find feasible node")?;
@@ -454,6 +629,7 @@ impl DefaultScheduler {
             _ => existing_pods.as_slice()[1..].iter().map(|(pod_info, node)|
                 ScheduledOperation::new(OpType::Delete, SupportedResources::Pod(pod_info.clone().into()))
                     .node((**node).clone())
+                    .orphaned(true)
             ).collect(),
         };
 
@@ -474,6 +650,8 @@ impl DefaultScheduler {
             None => vec!((OpType::Create, None))
         };
 
+        // a pod on a node it can't stay on moves, one whose hash changed is replaced
+        let moved = existing_pod.is_some_and(|(_, node)| !node.schedulable());
         let actions = op_types.into_iter().map(|(op, node)|
             ScheduledOperation {
                 resource: SupportedResources::Pod(new_pod.clone()),
@@ -481,6 +659,10 @@ impl DefaultScheduler {
                 operation: op,
                 error: None,
                 silent: false,
+                attempts: vec![],
+                duration: None,
+                preempted_by: None,
+                orphaned: moved,
             }
         ).collect();
 
@@ -504,6 +686,7 @@ impl DefaultScheduler {
             si.clone().cronjobs
         }, Some(&name.name), Some(&name.namespace)).first().cloned();
 
+        let moved = existing_cron.as_ref().is_some_and(|c| !c.1.schedulable());
         let op_types = match existing_cron {
             Some(c) => {
                 if c.0.manifest_hash == new_hash && c.1.schedulable() {
@@ -523,6 +706,10 @@ impl DefaultScheduler {
                 node,
                 error: None,
                 silent: false,
+                attempts: vec![],
+                duration: None,
+                preempted_by: None,
+                orphaned: moved,
             })
         );
 
@@ -580,7 +767,7 @@ impl DefaultScheduler {
                 ScheduledOperation::new(
                     op,
                     SupportedResources::Secret(new_secret.clone()),
-                ).node(node.clone())
+                ).node(node.clone()).orphaned(!node.schedulable())
             );
         }
 
@@ -627,7 +814,7 @@ impl DefaultScheduler {
                 actions.push(ScheduledOperation::new(
                     op_type,
                     SupportedResources::Service(new_service.clone()),
-                ).node(node.clone()))
+                ).node(node.clone()).orphaned(!node.schedulable()))
             );
         }
 
@@ -637,6 +824,52 @@ impl DefaultScheduler {
         })
     }
 
+    // like services, budgets are kept on every node so that the state has them however many nodes are reachable
+    fn plan_pod_disruption_budget(state: &ClusterState, pdb: &PodDisruptionBudget) -> Result<ApplyPlan, Box<dyn Error>> {
+        let name = metadata_name(pdb);
+
+        let mut actions = vec!();
+
+        let mut new_pdb = pdb.clone();
+
+        let new_hash = hash_k8s_resource(&mut new_pdb);
+
+        for node in state.nodes.iter() {
+            let existing = state.locate_objects(Some(&node.node_name), |si| {
+                si.clone().pod_disruption_budgets
+            }, Some(&name.name), Some(&name.namespace)).first().cloned();
+
+            let op_types = match existing {
+                Some(c) => {
+                    if !c.1.schedulable() {
+                        vec![OpType::Delete]
+                    } else if c.0.manifest_hash == new_hash {
+                        vec![OpType::Unchanged]
+                    } else {
+                        vec![OpType::Delete, OpType::Create]
+                    }
+                }
+                None => {
+                    if node.schedulable() {
+                        vec![OpType::Create]
+                    } else {
+                        vec![]
+                    }
+                }
+            };
+            op_types.into_iter().for_each(|op_type|
+                actions.push(ScheduledOperation::new(
+                    op_type,
+                    SupportedResources::PodDisruptionBudget(new_pdb.clone()),
+                ).node(node.clone()).orphaned(!node.schedulable()))
+            );
+        }
+
+        Ok(ApplyPlan {
+            actions: HashMap::from([(name, actions)]),
+        })
+    }
+
     fn plan_ingress(state: &ClusterState, ingress: &Ingress) -> Result<ApplyPlan, Box<dyn Error>> {
 
         // TODO - warn about unsupported settings
@@ -648,14 +881,20 @@ impl DefaultScheduler {
 
         let name = metadata_name(ingress);
 
+        // ingresses of other classes only go to the nodes running their proxy
+        let node_selector = ingress_class::node_selector(ingress);
+
         for node in state.nodes.iter() {
             let existing_ingress = state.locate_objects(Some(&node.node_name), |si| {
                 si.clone().ingresses
             }, Some(&name.name), Some(&name.namespace)).first().cloned();
 
+            let node_labels = K8sNode::from(node.clone()).metadata.labels.unwrap_or_default();
+            let serves_class = node_selector.iter().all(|(k, v)| node_labels.get(k) == Some(v));
+
             let op_types = match existing_ingress {
                 Some(c) => {
-                    if !c.1.schedulable() {
+                    if !c.1.schedulable() || !serves_class {
                         vec![OpType::Delete]
                     } else if c.0.manifest_hash == new_hash {
                         vec![OpType::Unchanged]
@@ -664,7 +903,7 @@ impl DefaultScheduler {
                     }
                 }
                 None => {
-                    if node.schedulable() {
+                    if node.schedulable() && serves_class {
                         vec![OpType::Create]
                     } else {
                         vec![]
@@ -675,7 +914,7 @@ impl DefaultScheduler {
                 actions.push(ScheduledOperation::new(
                     op_type,
                     SupportedResources::Ingress(new_ingress.clone()),
-                ).node(node.clone()))
+                ).node(node.clone()).orphaned(!node.schedulable() || !serves_class))
             );
         }
 
@@ -722,7 +961,7 @@ impl DefaultScheduler {
                 actions.push(ScheduledOperation::new(
                     op_type,
                     SupportedResources::ClusterIssuer(new_cluster_issuer.clone()),
-                ).node(node.clone()))
+                ).node(node.clone()).orphaned(!node.schedulable()))
             );
         }
 
@@ -748,6 +987,7 @@ impl DefaultScheduler {
             SupportedResources::Secret(secret) => Self::plan_secret(state, secret),
             SupportedResources::Service(service) => Self::plan_service(state, service),
             SupportedResources::ClusterIssuer(issuer) => Self::plan_cluster_issuer(state, issuer),
+            SupportedResources::PodDisruptionBudget(pdb) => Self::plan_pod_disruption_budget(state, pdb),
         }
     }
 
@@ -768,98 +1008,169 @@ impl DefaultScheduler {
     }
 
 
-    async fn apply(plan: ApplyPlan, conns: &SshClients, state: &mut ClusterState, dry_run: bool) -> Result<Vec<ScheduledOperation>, Box<dyn Error>> {
+    // the nodes' skatelet output is only of interest when debugging, errors reach the result through the exit status
+    fn print_remote_output(&self, node_name: &str, stdout: &str, stderr: &str) {
+        if !self.verbose {
+            return;
+        }
+        stdout.trim().lines().filter(|l| !l.is_empty()).for_each(|line| eprintln!("{} - {}", node_name, line));
+        stderr.trim().lines().filter(|l| !l.is_empty()).for_each(|line| eprintln!("{} - ERROR: {}", node_name, line));
+    }
+
+    async fn apply(&self, plan: ApplyPlan, conns: &SshClients, state: &mut ClusterState, dry_run: bool) -> Result<Vec<ScheduledOperation>, Box<dyn Error>> {
         let mut result: Vec<ScheduledOperation> = vec!();
 
+        let images: Vec<_> = plan.actions.values().flatten()
+            .filter(|op| matches!(op.operation, OpType::Create | OpType::Clobber))
+            .flat_map(|op| pod_images(&op.resource))
+            .unique()
+            .collect();
+        let image_archs = image_architectures(conns, &images).await;
+
         for (_name, ops) in plan.actions {
             for mut op in ops {
+                let start = Instant::now();
                 match op.operation {
                     OpType::Delete => {
                         let node_name = op.node.clone().unwrap().node_name;
+                        if !self.cleanup && op.orphaned {
+                            if !op.silent {
+                                println!("{} {} {} left on node {} (no cleanup)", OpType::Info.symbol(), op.resource, op.resource.name(), node_name);
+                            }
+                            continue;
+                        }
                         if dry_run {
                             let _ = state.reconcile_object_deletion(&op.resource, &node_name)?;
                             if !op.silent {
                                 println!("{} {} {} deleted on node {} ", op.operation.symbol(), op.resource, op.resource.name(), node_name);
                             }
+                            result.push(op.clone());
                             continue;
                         }
 
                         match Self::remove_existing(conns, op.clone()).await {
-                            Ok((_, stderr)) => {
-                                // println!("{}", stdout.trim());
-                                if !stderr.is_empty() {
-                                    eprintln!("{}", stderr.trim())
-                                }
+                            Ok((stdout, stderr)) => {
+                                self.print_remote_output(&node_name, &stdout, &stderr);
 
                                 let _ = state.reconcile_object_deletion(&op.resource, &node_name)?;
                                 if !op.silent {
                                     println!("{} {} {} deleted on node {} ", op.operation.symbol(), op.resource, op.resource.name(), node_name);
                                 }
-                                result.push(op.clone());
                             }
                             Err(err) => {
                                 op.error = Some(err.to_string());
                                 println!("{} failed to delete {} on node {}: {}", CROSS_EMOJI, op.resource.name(), node_name, err);
-                                result.push(op.clone());
                             }
                         }
+                        op.duration = Some(start.elapsed());
+                        result.push(op.clone());
                     }
                     OpType::Create | OpType::Clobber => {
-                        let selection = match op.node.clone() {
-                            // some things like ingress have the node already set
-                            Some(n) => NodeSelection {
-                                selected: Some(n),
-                                rejected: vec![],
-                            },
-                            // anything else and things with node selectors go here
-                            None => Self::choose_node(state.nodes.clone(), &op.resource)
-                        };
-                        if selection.selected.is_none() {
-                            let reasons = selection.rejected.iter().map(|r| format!("{} - {}", r.node_name, r.reason)).collect::<Vec<_>>().join(", ");
-                            let reasons = if reasons.is_empty() {
-                                "<none>".to_string()
-                            } else {
-                                reasons
-                            };
-
-                            return Err(anyhow!("failed to find feasible node ({} rejected): {}", selection.rejected.len(),  reasons).into());
-                        }
-
-                        let node_name = selection.selected.unwrap().node_name.clone();
-
-                        if dry_run {
-                            let _ = state.reconcile_object_creation(&op.resource, &node_name)?;
-                            if !op.silent {
-                                println!("{} {} {} created on node {}", op.operation.symbol(), op.resource, &op.resource.name(), node_name);
+                        // some things like ingress have the node already set, those only get the one attempt
+                        let pinned = op.node.clone();
+                        if let Some(n) = &pinned {
+                            // daemonset pods are pinned to their node, so fail rather than hit an exec format error at runtime
+                            if let Some(reason) = incompatible_images(&Self::node_arch(n), &pod_images(&op.resource), &image_archs) {
+                                println!("{} {} {} cannot run on node {}: {}", CROSS_EMOJI, op.resource, op.resource.name(), n.node_name, reason);
+                                op.error = Some(reason);
+                                result.push(op.clone());
+                                continue;
                             }
-                            continue;
                         }
 
-
-                        let client = conns.find(&node_name).unwrap();
+                        let max_attempts = match pinned {
+                            Some(_) => 1,
+                            None => self.max_attempts.max(1),
+                        };
                         let serialized = serde_yaml::to_string(&op.resource).expect("failed to serialize object");
 
-                        match client.apply_resource(&serialized).await {
-                            Ok((_, stderr)) => {
-                                // if !stdout.trim().is_empty() {
-                                //     stdout.trim().split("\n").for_each(|line| println!("{} - {}", node_name, line));
-                                // }
-                                if !stderr.is_empty() {
-                                    stderr.trim().split("\n").for_each(|line| eprintln!("{} - ERROR: {}", node_name, line));
+                        while op.attempts.len() < max_attempts {
+                            let selection = match pinned.clone() {
+                                Some(n) => NodeSelection {
+                                    selected: Some(n),
+                                    rejected: vec![],
+                                    scores: vec![],
+                                },
+                                // anything else and things with node selectors go here, skipping nodes that already failed
+                                None => {
+                                    let candidates: Vec<_> = state.nodes.iter().filter(|n| !op.attempts.iter().any(|a| a.node_name == n.node_name)).cloned().collect();
+                                    self.choose_preferred_node(state, candidates, &op.resource, &image_archs)
                                 }
-                                let _ = state.reconcile_object_creation(&op.resource, &node_name)?;
+                            };
 
+                            let scores = selection.scores.clone();
+                            let preemption = match (&selection.selected, op.attempts.is_empty()) {
+                                (None, true) => Self::plan_preemption(state, &op.resource, &image_archs),
+                                _ => None,
+                            };
+                            let node_name = match selection.selected {
+                                Some(n) => n.node_name,
+                                None if !op.attempts.is_empty() => break,
+                                None if preemption.is_some() => {
+                                    let (node, victims) = preemption.unwrap();
+                                    let preempted = self.preempt(conns, state, &op.resource, &node, victims, dry_run).await?;
+                                    result.extend(preempted);
+                                    node.node_name
+                                }
+                                None => {
+                                    let reasons = selection.rejected.iter().map(|r| format!("{} - {}", r.node_name, r.reason)).collect::<Vec<_>>().join(", ");
+                                    let reasons = if reasons.is_empty() {
+                                        "<none>".to_string()
+                                    } else {
+                                        reasons
+                                    };
+
+                                    return Err(anyhow!("failed to find feasible node ({} rejected): {}", selection.rejected.len(), reasons).into());
+                                }
+                            };
+
+                            if dry_run {
+                                let _ = state.reconcile_object_creation(&op.resource, &node_name)?;
                                 if !op.silent {
                                     println!("{} {} {} created on node {}", op.operation.symbol(), op.resource, &op.resource.name(), node_name);
+                                    // what the choice was made from, for planning capacity
+                                    if scores.len() > 1 {
+                                        let scores = scores.iter().map(|s| format!("{}={}", s.node_name, s.pods)).join(", ");
+                                        let tie_break = self.seed.map(|s| format!("seed {}", s)).unwrap_or("node name".to_string());
+                                        println!("    pods per node: {} (ties broken by {})", scores, tie_break);
+                                    }
                                 }
-                                result.push(op.clone());
+                                op.node = state.nodes.iter().find(|n| n.node_name == node_name).cloned();
+                                break;
                             }
-                            Err(err) => {
-                                op.error = Some(err.to_string());
-                                println!("{} {} {} creation failed on node {}: {}", CROSS_EMOJI, op.resource, op.resource.name().name, node_name, err);
-                                result.push(op.clone());
+
+                            let client = conns.find(&node_name).unwrap();
+
+                            match client.apply_resource(&serialized).await {
+                                Ok((stdout, stderr)) => {
+                                    self.print_remote_output(&node_name, &stdout, &stderr);
+                                    let _ = state.reconcile_object_creation(&op.resource, &node_name)?;
+
+                                    if !op.silent {
+                                        println!("{} {} {} created on node {}", op.operation.symbol(), op.resource, &op.resource.name(), node_name);
+                                    }
+                                    op.error = None;
+                                    op.node = state.nodes.iter().find(|n| n.node_name == node_name).cloned();
+                                    op.attempts.push(ScheduleAttempt { node_name, error: None });
+                                    break;
+                                }
+                                Err(err) => {
+                                    op.error = Some(err.to_string());
+                                    println!("{} {} {} creation failed on node {}: {}", CROSS_EMOJI, op.resource, op.resource.name().name, node_name, err);
+                                    op.attempts.push(ScheduleAttempt { node_name, error: Some(err.to_string()) });
+
+                                    if op.attempts.len() < max_attempts {
+                                        // don't leave a half created resource behind on the node before trying the next one
+                                        let _ = client.remove_resource_by_manifest(&serialized).await;
+                                    }
+                                }
                             }
                         }
+
+                        if !dry_run {
+                            op.duration = Some(start.elapsed());
+                        }
+                        result.push(op.clone());
                     }
                     OpType::Info => {
                         let node_name = op.node.clone().unwrap().node_name;
@@ -875,6 +1186,7 @@ impl DefaultScheduler {
                         if !op.silent {
                             println!("{} {} {} unchanged on {}", op.operation.symbol(), op.resource, op.resource.name(), node_name);
                         }
+                        result.push(op.clone());
                     }
                 }
             }
@@ -883,13 +1195,193 @@ impl DefaultScheduler {
     }
 
 
-    async fn schedule_one(conns: &SshClients, state: &mut ClusterState, object: SupportedResources, dry_run: bool) -> Result<Vec<ScheduledOperation>, Box<dyn Error>> {
+    // the node whose lower priority pods can make room for the pod when it fits on none of them as they are, and those pods.
+    // the node needing the fewest evictions wins, then the one evicting the lowest priorities
+    fn plan_preemption(state: &ClusterState, object: &SupportedResources, image_archs: &ImageArchitectures) -> Option<(NodeState, Vec<PodmanPodInfo>)> {
+        let pod = match object {
+            SupportedResources::Pod(pod) if priority::can_preempt(pod) => pod,
+            _ => return None,
+        };
+        let pod_priority = priority::pod_priority(pod);
+        if pod_priority <= 0 {
+            return None;
+        }
+
+        state.nodes.iter().filter_map(|node| {
+            let candidates = priority::preemption_candidates(state, node, pod_priority);
+            // evict one candidate after another until the pod fits
+            let mut remaining = node.clone();
+            for (evicted, victim) in candidates.iter().enumerate() {
+                if let Some(pods) = remaining.host_info.as_mut().and_then(|h| h.system_info.as_mut()).and_then(|si| si.pods.as_mut()) {
+                    pods.retain(|p| p.id != victim.id);
+                }
+                if Self::choose_node(state, vec![remaining.clone()], object, image_archs, None).selected.is_some() {
+                    return Some((node.clone(), candidates[..=evicted].to_vec()));
+                }
+            }
+            None
+        }).min_by_key(|(node, victims)| (
+            victims.len(),
+            victims.iter().map(priority::running_pod_priority).max().unwrap_or_default(),
+            node.node_name.clone(),
+        ))
+    }
+
+    // evicts the pods to make room for the object on the node
+    async fn preempt(&self, conns: &SshClients, state: &mut ClusterState, object: &SupportedResources, node: &NodeState, victims: Vec<PodmanPodInfo>, dry_run: bool) -> Result<Vec<ScheduledOperation>, Box<dyn Error>> {
+        let mut result = vec![];
+        for victim in victims {
+            let start = Instant::now();
+            let mut op = ScheduledOperation::new(OpType::Delete, SupportedResources::Pod(victim.clone().into())).node(node.clone());
+            op.preempted_by = Some(object.name());
+
+            if !dry_run {
+                match Self::remove_existing(conns, op.clone()).await {
+                    Ok((stdout, stderr)) => self.print_remote_output(&node.node_name, &stdout, &stderr),
+                    Err(err) => {
+                        println!("{} failed to preempt pod {} on node {}: {}", CROSS_EMOJI, victim.name, node.node_name, err);
+                        op.error = Some(err.to_string());
+                        op.duration = Some(start.elapsed());
+                        result.push(op);
+                        continue;
+                    }
+                }
+                op.duration = Some(start.elapsed());
+            }
+            let _ = state.reconcile_object_deletion(&op.resource, &node.node_name)?;
+            println!("{} pod {} preempted on node {} by {} {}", op.operation.symbol(), victim.name, node.node_name, object, object.name());
+            result.push(op);
+        }
+        Ok(result)
+    }
+
+    // plans the objects one after the other and places them the way apply would, changing only the state.
+    // nothing is tried on the nodes, so an operation that would fail there shows as placed
+    pub fn simulate(state: &mut ClusterState, objects: Vec<SupportedResources>, image_archs: &ImageArchitectures, seed: Option<u64>) -> Vec<SimulatedPlacement> {
+        let mut placements = vec![];
+        for object in objects {
+            let placement = |op: &ScheduledOperation| SimulatedPlacement {
+                kind: op.resource.to_string(),
+                name: op.resource.name().to_string(),
+                operation: op.operation.clone(),
+                node: op.node.as_ref().map(|n| n.node_name.clone()),
+                scores: vec![],
+                rejected: vec![],
+                preempts: vec![],
+                error: None,
+            };
+            let plan = match Self::plan(state, &object) {
+                Ok(plan) => plan,
+                Err(e) => {
+                    placements.push(SimulatedPlacement { error: Some(e.to_string()), ..placement(&ScheduledOperation::new(OpType::Create, object)) });
+                    continue;
+                }
+            };
+            // in name order, so the same state gives the same placements
+            let mut actions: Vec<_> = plan.actions.into_iter().collect();
+            actions.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            for op in actions.into_iter().flat_map(|(_, ops)| ops).filter(|op| !op.silent) {
+                let mut simulated = placement(&op);
+                match (&op.operation, &op.node) {
+                    (OpType::Delete, Some(node)) => {
+                        let _ = state.reconcile_object_deletion(&op.resource, &node.node_name);
+                    }
+                    (OpType::Create | OpType::Clobber, pinned) => {
+                        let selection = match pinned {
+                            Some(n) => NodeSelection { selected: Some(n.clone()), rejected: vec![], scores: vec![] },
+                            None => Self::choose_node(state, state.nodes.clone(), &op.resource, image_archs, seed),
+                        };
+                        simulated.scores = selection.scores;
+                        simulated.rejected = selection.rejected;
+                        let node_name = match (selection.selected, Self::plan_preemption(state, &op.resource, image_archs)) {
+                            (Some(n), _) => Some(n.node_name),
+                            (None, Some((node, victims))) => {
+                                for victim in victims {
+                                    let _ = state.reconcile_object_deletion(&SupportedResources::Pod(victim.clone().into()), &node.node_name);
+                                    simulated.preempts.push(victim.name);
+                                }
+                                Some(node.node_name)
+                            }
+                            (None, None) => None,
+                        };
+                        match node_name {
+                            Some(node_name) => {
+                                let _ = state.reconcile_object_creation(&op.resource, &node_name);
+                                simulated.node = Some(node_name);
+                            }
+                            None => simulated.error = Some(format!("no feasible node ({} rejected)", simulated.rejected.len())),
+                        }
+                    }
+                    _ => {}
+                }
+                placements.push(simulated);
+            }
+        }
+        placements
+    }
+
+    fn pod_count(node: &NodeState) -> usize {
+        node.host_info.as_ref().and_then(|h| h.system_info.as_ref()).and_then(|si| si.pods.as_ref()).map(|p| p.len()).unwrap_or(0)
+    }
+
+    // one move after another, each taking a deployment's pod off the busiest node it can, to where the scorer would
+    // put it now. only moves that leave the node it goes to with fewer pods than the one it leaves are made
+    pub fn plan_rebalance(state: &ClusterState, deployments: &[Deployment], max_moves: usize, image_archs: &ImageArchitectures) -> Vec<RebalanceMove> {
+        let mut state = state.clone();
+        let mut moves = vec![];
+
+        while moves.len() < max_moves {
+            let mut sources: Vec<_> = state.nodes.iter().filter(|n| n.schedulable()).cloned().collect();
+            sources.sort_by(|a, b| Self::pod_count(b).cmp(&Self::pod_count(a)).then(a.node_name.cmp(&b.node_name)));
+
+            let next = sources.iter().find_map(|source| {
+                let mut pods: Vec<_> = state.filter_pods(&|p| !p.deployment().is_empty()).into_iter()
+                    .filter(|(_, n)| n.node_name == source.node_name)
+                    .map(|(p, _)| p)
+                    .collect();
+                pods.sort_by(|a, b| a.name.cmp(&b.name));
+
+                pods.into_iter().find_map(|pod| {
+                    let deployment = deployments.iter().find(|d| d.metadata.name.as_deref() == Some(&pod.deployment()) && d.metadata.namespace.as_deref() == Some(&pod.namespace()))?;
+                    let replica = pod.labels.get("skate.io/replica").and_then(|r| r.parse().ok()).unwrap_or(0);
+                    let object = SupportedResources::Pod(Self::deployment_replica(deployment, replica));
+
+                    let mut trial = state.clone();
+                    trial.reconcile_object_deletion(&SupportedResources::Pod(pod.clone().into()), &source.node_name).ok()?;
+                    let candidates = trial.nodes.iter().filter(|n| n.node_name != source.node_name).cloned().collect();
+                    let target = Self::choose_node(&trial, candidates, &object, image_archs, None).selected?;
+                    if Self::pod_count(&target) + 1 >= Self::pod_count(source) {
+                        return None;
+                    }
+                    trial.reconcile_object_creation(&object, &target.node_name).ok()?;
+                    Some((trial, RebalanceMove {
+                        pod: pod.name.clone(),
+                        deployment: NamespacedName::new(&pod.deployment(), &pod.namespace()),
+                        from: source.node_name.clone(),
+                        to: target.node_name,
+                    }))
+                })
+            });
+
+            match next {
+                Some((next_state, next_move)) => {
+                    state = next_state;
+                    moves.push(next_move);
+                }
+                None => break,
+            }
+        }
+        moves
+    }
+
+    async fn schedule_one(&self, conns: &SshClients, state: &mut ClusterState, object: SupportedResources, dry_run: bool) -> Result<Vec<ScheduledOperation>, Box<dyn Error>> {
         let plan = Self::plan(state, &object)?;
         if plan.actions.is_empty() {
             return Err(anyhow!("failed to schedule resources, no planned actions").into());
         }
 
-        Self::apply(plan, conns, state, dry_run).await
+        self.apply(plan, conns, state, dry_run).await
     }
 }
 
@@ -898,7 +1390,7 @@ impl Scheduler for DefaultScheduler {
     async fn schedule(&self, conns: &SshClients, state: &mut ClusterState, objects: Vec<SupportedResources>, dry_run: bool) -> Result<ScheduleResult, Box<dyn Error>> {
         let mut results = ScheduleResult { placements: vec![] };
         for object in objects {
-            match Self::schedule_one(conns, state, object.clone(), dry_run).await {
+            match self.schedule_one(conns, state, object.clone(), dry_run).await {
                 Ok(placements) => {
                     results.placements = [results.placements, placements].concat();
                 }
@@ -922,9 +1414,380 @@ mod tests {
     use k8s_openapi::api::core::v1::{Container, PodSpec, PodTemplateSpec};
     use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
     use crate::test_helpers;
-    use crate::test_helpers::objects::WithPod;
+    use crate::test_helpers::objects::{cluster_state, WithPod};
+    use crate::test_helpers::ssh_mocks::{MockSshClient, MockSshManager};
     use super::*;
 
+    #[test]
+    fn test_choose_node_avoids_pressure() {
+        let ns_name = NamespacedName { name: "foo".to_string(), namespace: "foo-namespace".to_string() };
+        let (pods, _) = create_deployment_fixtures(&ns_name, 1, 1, "Recreate");
+
+        let healthy = test_helpers::objects::node_state("node-1");
+
+        let mut full_disk = test_helpers::objects::node_state("node-2");
+        full_disk.host_info.as_mut().unwrap().system_info.as_mut().unwrap().root_disk.as_mut().unwrap().available_space_mib = 100;
+
+        let mut low_memory = test_helpers::objects::node_state("node-3");
+        low_memory.host_info.as_mut().unwrap().system_info.as_mut().unwrap().used_memory_mib = 990;
+
+        let selection = DefaultScheduler::choose_node(&ClusterState::default(), vec![full_disk, low_memory, healthy], &SupportedResources::Pod(pods[0].clone()), &ImageArchitectures::new(), None);
+
+        assert_eq!("node-1", selection.selected.unwrap().node_name);
+        assert_eq!(2, selection.rejected.len());
+        assert!(selection.rejected[0].reason.starts_with("node has disk pressure"));
+        assert!(selection.rejected[1].reason.starts_with("node has memory pressure"));
+    }
+
+    #[test]
+    fn test_choose_node_breaks_ties() {
+        let ns_name = NamespacedName { name: "foo".to_string(), namespace: "foo-namespace".to_string() };
+        let (pods, _) = create_deployment_fixtures(&ns_name, 1, 1, "Recreate");
+        let pod = SupportedResources::Pod(pods[0].clone());
+        let nodes: Vec<_> = ["node-3", "node-1", "node-2"].iter().map(|n| test_helpers::objects::node_state(n)).collect();
+        let reversed: Vec<_> = nodes.iter().rev().cloned().collect();
+        let choose = |nodes: &[NodeState], seed| DefaultScheduler::choose_node(&ClusterState::default(), nodes.to_vec(), &pod, &ImageArchitectures::new(), seed).selected.unwrap().node_name;
+
+        assert_eq!("node-1", choose(&nodes, None));
+        assert_eq!("node-1", choose(&reversed, None));
+        // a seed picks the same node whatever the order, and some seed picks another
+        for seed in 0..10 {
+            assert_eq!(choose(&nodes, Some(seed)), choose(&reversed, Some(seed)));
+        }
+        assert!((0..10).any(|seed| choose(&nodes, Some(seed)) != "node-1"));
+    }
+
+    #[test]
+    fn test_choose_preferred_node_avoids_nodes() {
+        let ns_name = NamespacedName { name: "foo".to_string(), namespace: "foo-namespace".to_string() };
+        let (pods, _) = create_deployment_fixtures(&ns_name, 1, 1, "Recreate");
+        let pod = SupportedResources::Pod(pods[0].clone());
+        let scheduler = DefaultScheduler { avoid_nodes: vec!["node-1".to_string()], ..Default::default() };
+
+        let nodes = vec![test_helpers::objects::node_state("node-1"), test_helpers::objects::node_state("node-2")];
+        let selection = scheduler.choose_preferred_node(&ClusterState::default(), nodes.clone(), &pod, &ImageArchitectures::new());
+        assert_eq!("node-2", selection.selected.unwrap().node_name);
+
+        // the avoided node is still used when it's the only one that fits
+        let mut full_disk = nodes[1].clone();
+        full_disk.host_info.as_mut().unwrap().system_info.as_mut().unwrap().root_disk.as_mut().unwrap().available_space_mib = 100;
+        let selection = scheduler.choose_preferred_node(&ClusterState::default(), vec![nodes[0].clone(), full_disk], &pod, &ImageArchitectures::new());
+        assert_eq!("node-1", selection.selected.unwrap().node_name);
+    }
+
+    fn running_pod(name: &str, deployment: &str, priority: i32, memory_mib: u64) -> PodmanPodInfo {
+        PodmanPodInfo {
+            id: name.to_string(),
+            name: name.to_string(),
+            status: PodmanPodStatus::Running,
+            created: chrono::Local::now(),
+            labels: BTreeMap::from([
+                ("skate.io/namespace".to_string(), "default".to_string()),
+                ("skate.io/deployment".to_string(), deployment.to_string()),
+                (priority::PRIORITY_LABEL.to_string(), priority.to_string()),
+            ]),
+            containers: Some(vec![crate::skatelet::system::podman::PodmanContainerInfo {
+                id: name.to_string(),
+                names: name.to_string(),
+                status: "running".to_string(),
+                restart_count: None,
+                exit_code: None,
+                health: None,
+                cpu_limit: None,
+                memory_limit: Some(memory_mib * 1024 * 1024),
+                oom_killed: None,
+            }]),
+            phase: None,
+        }
+    }
+
+    #[test]
+    fn test_plan_preemption() {
+        let mut node_1 = test_helpers::objects::node_state("node-1");
+        node_1.host_info.as_mut().unwrap().system_info.as_mut().unwrap().pods = Some(vec![
+            running_pod("low-1", "low", 0, 400),
+            running_pod("low-2", "low", 0, 400),
+        ]);
+        let mut node_2 = test_helpers::objects::node_state("node-2");
+        node_2.host_info.as_mut().unwrap().system_info.as_mut().unwrap().pods = Some(vec![
+            running_pod("low-3", "low", 0, 400),
+            running_pod("critical", "critical", 2000, 500),
+        ]);
+        let state = cluster_state(vec![node_2, node_1]);
+
+        let mut pod = Pod {
+            metadata: ObjectMeta { name: Some("important".to_string()), namespace: Some("default".to_string()), ..Default::default() },
+            spec: Some(PodSpec {
+                priority: Some(1000),
+                containers: vec![Container {
+                    name: "app".to_string(),
+                    resources: Some(k8s_openapi::api::core::v1::ResourceRequirements {
+                        limits: Some(BTreeMap::from([("memory".to_string(), k8s_openapi::apimachinery::pkg::api::resource::Quantity("500Mi".to_string()))])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let selection = DefaultScheduler::choose_node(&state, state.nodes.clone(), &SupportedResources::Pod(pod.clone()), &ImageArchitectures::new(), None);
+        assert!(selection.selected.is_none());
+        assert!(selection.rejected.iter().all(|r| r.reason.starts_with("insufficient memory")));
+
+        let (node, victims) = DefaultScheduler::plan_preemption(&state, &SupportedResources::Pod(pod.clone()), &ImageArchitectures::new()).unwrap();
+        assert_eq!("node-1", node.node_name);
+        assert_eq!(1, victims.len());
+
+        pod.spec.as_mut().unwrap().preemption_policy = Some("Never".to_string());
+        assert!(DefaultScheduler::plan_preemption(&state, &SupportedResources::Pod(pod.clone()), &ImageArchitectures::new()).is_none());
+    }
+
+    #[test]
+    fn test_choose_node_system_reserved() {
+        let pod = Pod {
+            metadata: ObjectMeta { name: Some("app".to_string()), namespace: Some("default".to_string()), ..Default::default() },
+            spec: Some(PodSpec {
+                containers: vec![Container {
+                    name: "app".to_string(),
+                    resources: Some(k8s_openapi::api::core::v1::ResourceRequirements {
+                        limits: Some(BTreeMap::from([
+                            ("memory".to_string(), k8s_openapi::apimachinery::pkg::api::resource::Quantity("300Mi".to_string())),
+                            ("cpu".to_string(), k8s_openapi::apimachinery::pkg::api::resource::Quantity("500m".to_string())),
+                        ])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let mut memory_reserved = test_helpers::objects::node_state("node-1");
+        memory_reserved.system_reserved = BTreeMap::from([("memory".to_string(), "800Mi".to_string())]);
+        let mut cpu_reserved = test_helpers::objects::node_state("node-2");
+        cpu_reserved.system_reserved = BTreeMap::from([("cpu".to_string(), "600m".to_string())]);
+        let mut small_reservation = test_helpers::objects::node_state("node-3");
+        small_reservation.system_reserved = BTreeMap::from([("cpu".to_string(), "500m".to_string()), ("memory".to_string(), "500Mi".to_string())]);
+
+        let selection = DefaultScheduler::choose_node(&ClusterState::default(), vec![memory_reserved, cpu_reserved, small_reservation], &SupportedResources::Pod(pod), &ImageArchitectures::new(), None);
+        assert_eq!("node-3", selection.selected.unwrap().node_name);
+        assert!(selection.rejected[0].reason.starts_with("insufficient memory"), "{}", selection.rejected[0].reason);
+        assert!(selection.rejected[1].reason.starts_with("insufficient cpu"), "{}", selection.rejected[1].reason);
+    }
+
+    #[test]
+    fn test_choose_node_gpus() {
+        let pod = Pod {
+            metadata: ObjectMeta { name: Some("infer".to_string()), namespace: Some("default".to_string()), ..Default::default() },
+            spec: Some(PodSpec {
+                containers: vec![Container {
+                    name: "model".to_string(),
+                    resources: Some(k8s_openapi::api::core::v1::ResourceRequirements {
+                        limits: Some(BTreeMap::from([(gpu::GPU_RESOURCE.to_string(), k8s_openapi::apimachinery::pkg::api::resource::Quantity("1".to_string()))])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let with_gpus = |name: &str| {
+            let mut node = test_helpers::objects::node_state(name);
+            node.host_info.as_mut().unwrap().system_info.as_mut().unwrap().resources = Some(BTreeMap::from([(gpu::GPU_RESOURCE.to_string(), 1)]));
+            node
+        };
+        let gpu_taken = Pod {
+            metadata: ObjectMeta {
+                name: Some("train.default".to_string()),
+                labels: Some(BTreeMap::from([(gpu::GPU_DEVICES_LABEL.to_string(), "0".to_string())])),
+                ..Default::default()
+            },
+            status: Some(k8s_openapi::api::core::v1::PodStatus { phase: Some("Running".to_string()), ..Default::default() }),
+            ..Default::default()
+        };
+
+        let nodes = vec![test_helpers::objects::node_state("node-1"), with_gpus("node-2").with_pod(&gpu_taken), with_gpus("node-3")];
+        let selection = DefaultScheduler::choose_node(&ClusterState::default(), nodes, &SupportedResources::Pod(pod.clone()), &ImageArchitectures::new(), None);
+        assert_eq!("node-3", selection.selected.unwrap().node_name);
+        assert_eq!(vec!["insufficient gpu: 1 requested, 0 of 0 free", "insufficient gpu: 1 requested, 0 of 1 free"],
+            selection.rejected.iter().map(|r| r.reason.as_str()).collect::<Vec<_>>());
+
+        // a replica scheduled before it in the same apply hasn't been given its gpu yet, but has it all the same
+        let mut scheduled = with_gpus("node-4");
+        scheduled.host_info.as_mut().unwrap().system_info.as_mut().unwrap().pods = Some(vec![]);
+        scheduled.reconcile_object_creation(&SupportedResources::Pod(Pod { metadata: ObjectMeta { name: Some("infer-0.default".to_string()), ..pod.metadata.clone() }, ..pod.clone() })).unwrap();
+        let selection = DefaultScheduler::choose_node(&ClusterState::default(), vec![scheduled], &SupportedResources::Pod(pod), &ImageArchitectures::new(), None);
+        assert!(selection.selected.is_none());
+    }
+
+    #[test]
+    fn test_choose_node_matches_image_arch() {
+        let ns_name = NamespacedName { name: "foo".to_string(), namespace: "foo-namespace".to_string() };
+        let (mut pods, _) = create_deployment_fixtures(&ns_name, 1, 1, "Recreate");
+        pods[0].spec.as_mut().unwrap().containers[0].image = Some("legacy:1".to_string());
+
+        let mut arm = test_helpers::objects::node_state("node-1");
+        arm.host_info.as_mut().unwrap().platform.arch = "aarch64".to_string();
+        let mut amd = test_helpers::objects::node_state("node-2");
+        amd.host_info.as_mut().unwrap().platform.arch = "x86_64".to_string();
+
+        let image_archs = ImageArchitectures::from([("legacy:1".to_string(), Some(vec!["amd64".to_string()]))]);
+
+        let selection = DefaultScheduler::choose_node(&ClusterState::default(), vec![arm.clone(), amd], &SupportedResources::Pod(pods[0].clone()), &image_archs, None);
+        assert_eq!("node-2", selection.selected.unwrap().node_name);
+        assert_eq!(1, selection.rejected.len());
+        assert_eq!("image legacy:1 is not available for arm64 (available: amd64)", selection.rejected[0].reason);
+
+        let selection = DefaultScheduler::choose_node(&ClusterState::default(), vec![arm], &SupportedResources::Pod(pods[0].clone()), &image_archs, None);
+        assert!(selection.selected.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_apply_falls_back_to_next_node() {
+        let ns_name = NamespacedName { name: "foo".to_string(), namespace: "foo-namespace".to_string() };
+        let (_, deployment) = create_deployment_fixtures(&ns_name, 1, 0, "Recreate");
+        let plan = DefaultScheduler::plan_deployment(&cluster_state(vec![]), &deployment).unwrap();
+
+        let mut state = ClusterState {
+            cluster_name: "test".to_string(),
+            nodes: vec![test_helpers::objects::node_state("node-1"), test_helpers::objects::node_state("node-2")],
+        };
+        let conns = SshClients {
+            clients: vec![
+                Box::new(MockSshClient::new("node-1", true)),
+                Box::new(MockSshClient::new("node-2", false)),
+            ],
+        };
+
+        let result = DefaultScheduler::default().apply(plan, &conns, &mut state, false).await.unwrap();
+        let created: Vec<_> = result.iter().filter(|op| op.operation == OpType::Create && matches!(op.resource, SupportedResources::Pod(_))).collect();
+        assert_eq!(1, created.len());
+
+        let op = created[0];
+        assert_eq!(None, op.error);
+        assert_eq!(vec![
+            ScheduleAttempt { node_name: "node-1".to_string(), error: Some("failed to pull image".to_string()) },
+            ScheduleAttempt { node_name: "node-2".to_string(), error: None },
+        ], op.attempts);
+
+        // a single attempt gives up on the first failure
+        let plan = DefaultScheduler::plan_deployment(&cluster_state(vec![]), &deployment).unwrap();
+        let result = DefaultScheduler { max_attempts: 1, ..Default::default() }.apply(plan, &conns, &mut state, false).await.unwrap();
+        let failed: Vec<_> = result.iter().filter(|op| matches!(op.resource, SupportedResources::Pod(_)) && op.error.is_some()).collect();
+        assert_eq!(1, failed.len());
+        assert_eq!(1, failed[0].attempts.len());
+    }
+
+    #[tokio::test]
+    async fn test_schedule_on_fake_runtime() {
+        let ns_name = NamespacedName { name: "foo".to_string(), namespace: "foo-namespace".to_string() };
+        let (_, deployment) = create_deployment_fixtures(&ns_name, 2, 0, "Recreate");
+        let ssh = MockSshManager::default();
+        let conns = ssh.clients(&["node-1", "node-2"]);
+        let mut state = ClusterState {
+            cluster_name: "test".to_string(),
+            nodes: vec![test_helpers::objects::node_state("node-1"), test_helpers::objects::node_state("node-2")],
+        };
+
+        DefaultScheduler::default().schedule(&conns, &mut state, vec![SupportedResources::Deployment(deployment.clone())], false).await.unwrap();
+        let pods = |node: &str| ssh.runtime.pods(node).len();
+        assert_eq!(2, pods("node-1") + pods("node-2"));
+
+        // the nodes report what was applied to them, so a refreshed state has the pods and scheduling again changes nothing
+        let infos: Vec<_> = conns.get_nodes_system_info().await.into_iter().map(|r| r.result.unwrap()).collect();
+        assert_eq!(2, infos.iter().map(|i| i.system_info.as_ref().unwrap().pods.as_ref().unwrap().len()).sum::<usize>());
+        let mut state = cluster_state(infos.into_iter().map(NodeState::from).collect());
+        let applied = ssh.runtime.resources("node-1").len() + ssh.runtime.resources("node-2").len();
+        let result = DefaultScheduler::default().schedule(&conns, &mut state, vec![SupportedResources::Deployment(deployment)], false).await.unwrap();
+        assert!(result.placements.iter().filter(|op| matches!(op.resource, SupportedResources::Pod(_))).all(|op| op.operation == OpType::Unchanged));
+        assert_eq!(applied, ssh.runtime.resources("node-1").len() + ssh.runtime.resources("node-2").len());
+        assert!(["node-1", "node-2"].iter().all(|n| ssh.runtime.resources(n).iter().any(|r| matches!(r, SupportedResources::Deployment(_)))));
+    }
+
+    #[test]
+    fn test_simulate() {
+        let ns_name = NamespacedName { name: "foo".to_string(), namespace: "foo-namespace".to_string() };
+        let (_, deployment) = create_deployment_fixtures(&ns_name, 2, 0, "Recreate");
+        let busy = test_helpers::objects::node_state("node-1").with_pod(&Pod { metadata: ObjectMeta::from(NamespacedName::new("web", "shop")), ..Default::default() });
+        let mut idle = test_helpers::objects::node_state("node-2");
+        idle.host_info.as_mut().unwrap().system_info.as_mut().unwrap().pods = Some(vec![]);
+        let mut state = cluster_state(vec![busy, idle]);
+
+        let placements = DefaultScheduler::simulate(&mut state, vec![SupportedResources::Deployment(deployment)], &ImageArchitectures::new(), None);
+        let pods: Vec<_> = placements.iter().filter(|p| p.kind == "Pod").collect();
+        assert_eq!(2, pods.len());
+        assert!(pods.iter().all(|p| p.error.is_none() && p.operation == OpType::Create));
+
+        // the first pod goes to the idle node, the second sees it there and ties, going to the first by name
+        assert_eq!(Some("node-2"), pods[0].node.as_deref());
+        assert_eq!(vec![
+            NodeScore { node_name: "node-1".to_string(), pods: 1 },
+            NodeScore { node_name: "node-2".to_string(), pods: 0 },
+        ], pods[0].scores);
+        assert_eq!(vec![1, 1], pods[1].scores.iter().map(|s| s.pods).collect::<Vec<_>>());
+        assert_eq!(Some("node-1"), pods[1].node.as_deref());
+        assert_eq!(3, state.filter_pods(&|_| true).len());
+    }
+
+    #[test]
+    fn test_plan_rebalance() {
+        let ns_name = NamespacedName { name: "foo".to_string(), namespace: "foo-namespace".to_string() };
+        let (pods, deployment) = create_deployment_fixtures(&ns_name, 4, 4, "Recreate");
+        let mut busy = test_helpers::objects::node_state("node-1");
+        for (i, mut pod) in pods.into_iter().enumerate() {
+            pod.metadata.labels.as_mut().unwrap().insert("skate.io/replica".to_string(), i.to_string());
+            busy = busy.with_pod(&pod);
+        }
+        let mut added = test_helpers::objects::node_state("node-2");
+        added.host_info.as_mut().unwrap().system_info.as_mut().unwrap().pods = Some(vec![]);
+        let state = cluster_state(vec![busy, added]);
+
+        // 4 and 0 become 2 and 2, moving a third would only make it uneven the other way
+        let moves = DefaultScheduler::plan_rebalance(&state, std::slice::from_ref(&deployment), 5, &ImageArchitectures::new());
+        assert_eq!(vec!["dpl-foo-0.foo-namespace", "dpl-foo-1.foo-namespace"], moves.iter().map(|m| m.pod.as_str()).collect::<Vec<_>>());
+        assert!(moves.iter().all(|m| m.from == "node-1" && m.to == "node-2" && m.deployment == ns_name));
+
+        assert_eq!(1, DefaultScheduler::plan_rebalance(&state, &[deployment], 1, &ImageArchitectures::new()).len());
+        assert!(DefaultScheduler::plan_rebalance(&state, &[], 5, &ImageArchitectures::new()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_apply_no_cleanup_leaves_old_copy() {
+        let ns_name = NamespacedName { name: "foo".to_string(), namespace: "foo-namespace".to_string() };
+        let (pods, _) = create_deployment_fixtures(&ns_name, 1, 1, "Recreate");
+        let pod = pods[0].clone();
+        let node1 = test_helpers::objects::node_state("node-1");
+        let node2 = test_helpers::objects::node_state("node-2");
+
+        let plan = |orphaned: bool| ApplyPlan {
+            actions: HashMap::from([(ns_name.clone(), vec![
+                ScheduledOperation::new(OpType::Delete, SupportedResources::Pod(pod.clone())).node(node1.clone()).orphaned(orphaned),
+                ScheduledOperation::new(OpType::Create, SupportedResources::Pod(pod.clone())).node(node2.clone()),
+            ])]),
+        };
+        let mut state = cluster_state(vec![node1.clone(), node2.clone()]);
+        let conns = SshClients {
+            clients: vec![
+                Box::new(MockSshClient::new("node-1", false)),
+                Box::new(MockSshClient::new("node-2", false)),
+            ],
+        };
+
+        let result = DefaultScheduler::default().apply(plan(true), &conns, &mut state, false).await.unwrap();
+        assert_eq!(vec![OpType::Delete, OpType::Create], result.iter().map(|op| op.operation.clone()).collect::<Vec<_>>());
+
+        let result = DefaultScheduler { cleanup: false, ..Default::default() }.apply(plan(true), &conns, &mut state, false).await.unwrap();
+        assert_eq!(vec![OpType::Create], result.iter().map(|op| op.operation.clone()).collect::<Vec<_>>());
+
+        // replacements and scale downs still happen
+        let result = DefaultScheduler { cleanup: false, ..Default::default() }.apply(plan(false), &conns, &mut state, false).await.unwrap();
+        assert_eq!(vec![OpType::Delete, OpType::Create], result.iter().map(|op| op.operation.clone()).collect::<Vec<_>>());
+    }
+
     #[test]
     fn test_plan_deployment_clean_slate_recreate() {
         let ns_name = NamespacedName { name: "foo".to_string(), namespace: "foo-namespace".to_string() };
@@ -944,34 +1807,28 @@ mod tests {
 
 
         let result = DefaultScheduler::plan_deployment(&state, &deployment);
-        assert_eq!(true, result.is_ok());
+        assert!(result.is_ok());
 
 
         let result = &result.unwrap();
         assert_eq!(1, result.actions.len());
 
         let ops = result.actions.get(&ns_name);
-        assert_eq!(true, ops.is_some());
+        assert!(ops.is_some());
 
         let ops = ops.unwrap();
         assert_eq!(4, ops.len());
 
-        let deployment_ops = ops.iter().filter(|o| match o.resource {
-            SupportedResources::Deployment(_) => true,
-            _ => false,
-        }).collect_vec();
+        let deployment_ops = ops.iter().filter(|o| matches!(o.resource, SupportedResources::Deployment(_))).collect_vec();
         assert_eq!(2, deployment_ops.len());
-        assert_eq!(true, deployment_ops.iter().all(|o|
+        assert!(deployment_ops.iter().all(|o|
             o.operation == OpType::Create
                 && o.node.is_some() && !o.node.as_ref().unwrap().node_name.is_empty()
         ));
 
-        let pod_ops = ops.iter().filter(|o| match o.resource {
-            SupportedResources::Pod(_) => true,
-            _ => false,
-        }).collect_vec();
+        let pod_ops = ops.iter().filter(|o| matches!(o.resource, SupportedResources::Pod(_))).collect_vec();
         assert_eq!(2, pod_ops.len());
-        assert_eq!(true, pod_ops.iter().all(|o|
+        assert!(pod_ops.iter().all(|o|
             o.operation == OpType::Create
                 && o.node.is_none()
         ));
@@ -1035,7 +1892,7 @@ mod tests {
 
         let sup_deployment = SupportedResources::Deployment(deployment);
         let sup_deployment = sup_deployment.fixup();
-        assert_eq!(true, sup_deployment.is_ok());
+        assert!(sup_deployment.is_ok());
         let sup_deployment = sup_deployment.unwrap();
 
         let deployment = match sup_deployment {
@@ -1059,9 +1916,9 @@ mod tests {
 
         let mut nodes = [node1, node2];
 
-        for i in 0..existing_replicas {
+        for (i, pod) in pods.iter().enumerate().take(existing_replicas) {
             let node_index: usize = (i + 1) % 2; // 0 or 1 alternating
-            nodes[node_index] = nodes[node_index].clone().with_pod(&pods[i])
+            nodes[node_index] = nodes[node_index].clone().with_pod(pod)
         }
 
         let state = ClusterState {
@@ -1084,31 +1941,27 @@ mod tests {
         assert_eq!(1, result.actions.len());
 
         let ops = result.actions.get(&ns_name);
-        assert_eq!(true, ops.is_some());
+        assert!(ops.is_some());
 
         let ops = ops.unwrap();
         assert_eq!(2 + existing_replicas + requested_replicas, ops.len());
 
-        let deployment_ops = ops.iter().filter(|o| match o.resource {
-            SupportedResources::Deployment(_) => true,
-            _ => false,
-        }).collect_vec();
+        let deployment_ops = ops.iter().filter(|o| matches!(o.resource, SupportedResources::Deployment(_))).collect_vec();
         assert_eq!(2, deployment_ops.len());
-        assert_eq!(true, deployment_ops.iter().all(|o|
+        assert!(deployment_ops.iter().all(|o|
             o.operation == OpType::Create
                 && o.node.is_some() && !o.node.as_ref().unwrap().node_name.is_empty()
         ));
 
-        let pod_ops = ops.iter().filter(|o| match o.resource {
-            SupportedResources::Pod(_) => true,
-            _ => false,
-        }).collect_vec();
+        let pod_ops = ops.iter().filter(|o| matches!(o.resource, SupportedResources::Pod(_))).collect_vec();
         assert_eq!(4, pod_ops.len());
 
         assert_eq!(OpType::Delete, pod_ops[0].operation);
         assert_eq!(OpType::Delete, pod_ops[1].operation);
         assert_eq!(OpType::Create, pod_ops[2].operation);
         assert_eq!(OpType::Create, pod_ops[3].operation);
+        // recreating replaces the pods, it doesn't orphan them
+        assert!(pod_ops.iter().all(|o| !o.orphaned));
     }
 
     #[test]
@@ -1125,9 +1978,9 @@ mod tests {
         
         let mut nodes = [node1, node2];
 
-        for i in 0..existing_replicas {
+        for (i, pod) in pods.iter().enumerate().take(existing_replicas) {
             let node_index: usize = (i + 1) % 2; // 0 or 1 alternating
-            nodes[node_index] = nodes[node_index].clone().with_pod(&pods[i as usize])
+            nodes[node_index] = nodes[node_index].clone().with_pod(pod)
         }
 
         let state = ClusterState {
@@ -1150,22 +2003,19 @@ mod tests {
         assert_eq!(1+max(requested_replicas, existing_replicas) as usize, result.actions.len());
 
 
-        let deployment_ops = result.actions.get(&ns_name).unwrap().iter().filter(|o| match o.resource {
-            SupportedResources::Deployment(_) => true,
-            _ => false,
-        }).collect_vec();
+        let deployment_ops = result.actions.get(&ns_name).unwrap().iter().filter(|o| matches!(o.resource, SupportedResources::Deployment(_))).collect_vec();
 
         assert_eq!(2, deployment_ops.len());
 
-        assert_eq!(true, deployment_ops.iter().all(|o|
+        assert!(deployment_ops.iter().all(|o|
             o.operation == OpType::Create
                 && o.node.is_some() && !o.node.as_ref().unwrap().node_name.is_empty()
         ));
 
-        for i in 0..existing_replicas {
-            let pod_name = metadata_name(&pods[i as usize]);
+        for pod in pods.iter().take(existing_replicas) {
+            let pod_name = metadata_name(pod);
             let pod_ops = result.actions.get(&pod_name);
-            assert_eq!(true, pod_ops.is_some());
+            assert!(pod_ops.is_some());
 
             let pod_ops = pod_ops.unwrap();
             assert_eq!(2, pod_ops.len());
@@ -1,6 +1,7 @@
 #![allow(unused)]
 
 use crate::util;
+use crate::interactive;
 use clap::{Args, Parser, Subcommand};
 use serde::{Deserialize, Serialize};
 use crate::apply::{Apply, ApplyArgs, ApplyDeps};
@@ -8,6 +9,7 @@ use crate::refresh::{Refresh, RefreshArgs, RefreshDeps};
 use strum_macros::Display;
 use std::fmt::{Display, Formatter};
 use crate::config;
+use crate::config::Access;
 use crate::cluster::{Cluster, ClusterArgs, ClusterDeps};
 use crate::config_cmd::ConfigArgs;
 use crate::cordon::{Cordon, CordonArgs, CordonDeps, UncordonArgs};
@@ -22,6 +24,24 @@ use crate::node_shell::{NodeShell, NodeShellArgs, NodeShellDeps};
 use crate::rollout::{Rollout, RolloutArgs, RolloutDeps};
 use crate::skate::Distribution::{Debian, Raspbian, Ubuntu, Unknown};
 use crate::upgrade::{Upgrade, UpgradeArgs, UpgradeDeps};
+use crate::image_build::{Build, BuildArgs, BuildDeps};
+use crate::cp::{Cp, CpArgs, CpDeps};
+use crate::rebalance::{Rebalance, RebalanceArgs, RebalanceDeps};
+use crate::evict::{Evict, EvictArgs, EvictDeps};
+use crate::addon::{Addons, AddonArgs, AddonDeps};
+use crate::schedule::{Schedule, ScheduleArgs, ScheduleDeps};
+use crate::snapshot::{Snapshots, StateArgs, SnapshotDeps};
+use crate::attach::{Attach, AttachArgs, AttachDeps};
+use crate::events::{Events, EventsArgs, EventsDeps};
+use crate::explain::ExplainArgs;
+use crate::lock::{Lock, LockArgs, LockDeps};
+use crate::node::{Node, NodeArgs, NodeDeps};
+use crate::run::{Run, RunArgs, RunDeps};
+use crate::sync::{Sync, SyncArgs, SyncDeps};
+use crate::ps::{Ps, PsArgs, PsDeps};
+use crate::metrics::{Metrics, MetricsArgs, MetricsDeps};
+use crate::patch::{Patch, PatchArgs, PatchDeps};
+use crate::edit::{Edit, EditArgs, EditDeps};
 
 
 
@@ -33,6 +53,9 @@ use crate::upgrade::{Upgrade, UpgradeArgs, UpgradeDeps};
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    #[arg(long, global = true, env = "SKATE_NON_INTERACTIVE", long_help = "Never prompt, and print without spinners, emoji or color. \
+For cron and ci, where the output is parsed.")]
+    non_interactive: bool,
 }
 
 #[derive(Debug, Subcommand)]
@@ -64,14 +87,54 @@ enum Commands {
     #[command(long_about = "Upgrade actions")]
     Upgrade(UpgradeArgs),
     #[command(long_about = "Start a shell on a node")]
-    NodeShell(NodeShellArgs)
+    NodeShell(NodeShellArgs),
+    #[command(long_about = "Edit a resource in place")]
+    Edit(EditArgs),
+    #[command(long_about = "Update fields of a resource")]
+    Patch(PatchArgs),
+    #[command(long_about = "Export cluster metrics")]
+    Metrics(MetricsArgs),
+    #[command(long_about = "List the containers on every node")]
+    Ps(PsArgs),
+    #[command(long_about = "Apply the manifests in a git repository")]
+    Sync(SyncArgs),
+    #[command(long_about = "Run a pod from an image")]
+    Run(RunArgs),
+    #[command(long_about = "Node diagnostics")]
+    Node(NodeArgs),
+    #[command(long_about = "Cluster lock actions")]
+    Lock(LockArgs),
+    #[command(long_about = "Describe the fields of a resource type, and which of them skate honors")]
+    Explain(ExplainArgs),
+    #[command(long_about = "Show what changed in the cluster, pods starting, failing and restarting, nodes going down and resources being applied")]
+    Events(EventsArgs),
+    #[command(long_about = "Attach to the stdout, stderr and optionally stdin of a pod's container.")]
+    Attach(AttachArgs),
+    #[command(long_about = "Save snapshots of the cluster state and compare them")]
+    State(StateArgs),
+    #[command(long_about = "Simulate where manifests would be placed, without changing the cluster")]
+    Schedule(ScheduleArgs),
+    #[command(long_about = "Enable, disable and list the addons built into skate")]
+    Addon(AddonArgs),
+    #[command(long_about = "Gracefully stop a pod, a deployment's pod is replaced on another node")]
+    Evict(EvictArgs),
+    #[command(long_about = "Move deployment pods off the busiest nodes to even out the cluster")]
+    Rebalance(RebalanceArgs),
+    #[command(long_about = "Copy a file between the local machine and a node")]
+    Cp(CpArgs),
+    #[command(long_about = "Build an image locally or on a node, then push it or copy it to every node.")]
+    Build(BuildArgs),
+    // anything else runs the skate-<command> plugin
+    #[command(external_subcommand)]
+    Plugin(Vec<String>),
 }
 
 #[derive(Debug, Clone, Args)]
 pub struct ConfigFileArgs {
-    #[arg(long, long_help = "Configuration for skate.", default_value = "~/.skate/config.yaml")]
-    pub skateconfig: String,
-    #[arg(long, long_help = "Name of the context to use.")]
+    #[arg(long, long_help = "Configuration for skate. Defaults to the files listed in $SKATECONFIG, then the closest .skate/config \
+of the current directory, then ~/.skate/config.yaml.")]
+    pub skateconfig: Option<String>,
+    #[arg(long, env = "SKATE_CONTEXT", long_help = "Name of the context to use.")]
     pub context: Option<String>,
 }
 
@@ -90,11 +153,46 @@ impl UpgradeDeps for Deps{}
 
 impl NodeShellDeps for Deps{}
 
-pub trait AllDeps: ApplyDeps + ClusterDeps + CreateDeps + DeleteDeps + CordonDeps + RefreshDeps + GetDeps + DescribeDeps + LogsDeps + RolloutDeps + UpgradeDeps + NodeShellDeps{}
+impl EditDeps for Deps{}
+
+impl PatchDeps for Deps{}
+
+impl MetricsDeps for Deps{}
+
+impl PsDeps for Deps{}
+
+impl SyncDeps for Deps{}
+
+impl RunDeps for Deps{}
+
+impl NodeDeps for Deps{}
+
+impl LockDeps for Deps{}
+
+impl EventsDeps for Deps{}
+
+impl AttachDeps for Deps{}
+
+impl SnapshotDeps for Deps{}
+
+impl ScheduleDeps for Deps{}
+
+impl AddonDeps for Deps{}
+
+impl EvictDeps for Deps{}
+
+impl RebalanceDeps for Deps{}
+
+impl CpDeps for Deps{}
+
+impl BuildDeps for Deps{}
+
+pub trait AllDeps: ApplyDeps + ClusterDeps + CreateDeps + DeleteDeps + CordonDeps + RefreshDeps + GetDeps + DescribeDeps + LogsDeps + RolloutDeps + UpgradeDeps + NodeShellDeps + EditDeps + PatchDeps + MetricsDeps + PsDeps + SyncDeps + RunDeps + NodeDeps + LockDeps + EventsDeps + AttachDeps + SnapshotDeps + ScheduleDeps + AddonDeps + EvictDeps + RebalanceDeps + CpDeps + BuildDeps{}
 
 impl AllDeps for Deps{}
 
 async fn skate_with_args<D: AllDeps>(deps: D, args: Cli) -> Result<(), SkateError> {
+    interactive::set_non_interactive(args.non_interactive);
     config::ensure_config();
     match args.command {
         Commands::Create(args) => {
@@ -127,6 +225,7 @@ async fn skate_with_args<D: AllDeps>(deps: D, args: Cli) -> Result<(), SkateErro
             logs.logs(args).await
         },
         Commands::Config(args) => crate::config_cmd::config(args),
+        Commands::Explain(args) => crate::explain::explain(args),
         Commands::Cordon(args) => {
             let cordon = Cordon {deps};
             cordon.cordon(args).await
@@ -151,6 +250,75 @@ async fn skate_with_args<D: AllDeps>(deps: D, args: Cli) -> Result<(), SkateErro
             let node_shell = NodeShell{deps};
             node_shell.node_shell(args).await
         }
+        Commands::Edit(args) => {
+            let edit = Edit { deps };
+            edit.edit(args).await
+        }
+        Commands::Patch(args) => {
+            let patch = Patch { deps };
+            patch.patch(args).await
+        }
+        Commands::Metrics(args) => {
+            let metrics = Metrics { deps };
+            metrics.metrics(args).await
+        }
+        Commands::Ps(args) => {
+            let ps = Ps { deps };
+            ps.ps(args).await
+        }
+        Commands::Sync(args) => {
+            let sync = Sync { deps };
+            sync.sync(args).await
+        }
+        Commands::Run(args) => {
+            let run = Run { deps };
+            run.run(args).await
+        }
+        Commands::Node(args) => {
+            let node = Node { deps };
+            node.node(args).await
+        }
+        Commands::Lock(args) => {
+            let lock = Lock { deps };
+            lock.lock(args).await
+        }
+        Commands::Events(args) => {
+            let events = Events { deps };
+            events.events(args).await
+        }
+        Commands::Attach(args) => {
+            let attach = Attach { deps };
+            attach.attach(args).await
+        }
+        Commands::State(args) => {
+            let state = Snapshots { deps };
+            state.state(args).await
+        }
+        Commands::Schedule(args) => {
+            let schedule = Schedule { deps };
+            schedule.schedule(args).await
+        }
+        Commands::Addon(args) => {
+            let addon = Addons { deps };
+            addon.addon(args).await
+        }
+        Commands::Evict(args) => {
+            let evict = Evict { deps };
+            evict.evict(args).await
+        }
+        Commands::Rebalance(args) => {
+            let rebalance = Rebalance { deps };
+            rebalance.rebalance(args).await
+        }
+        Commands::Cp(args) => {
+            let cp = Cp { deps };
+            cp.cp(args).await
+        }
+        Commands::Build(args) => {
+            let build = Build { deps };
+            build.build(args).await
+        }
+        Commands::Plugin(args) => crate::plugin::run(args),
     }?;
     Ok(())
 }
@@ -195,6 +363,7 @@ impl From<&str> for Distribution {
 
 #[cfg(test)]
 mod tests {
+    use clap::Parser;
     use crate::{skate, AllDeps};
     use crate::apply::ApplyDeps;
     use crate::cluster::ClusterDeps;
@@ -212,12 +381,29 @@ mod tests {
     use crate::skate::Commands::Refresh;
     use crate::test_helpers::ssh_mocks::MockSshManager;
     use crate::upgrade::UpgradeDeps;
+    use crate::image_build::BuildDeps;
+    use crate::cp::CpDeps;
+    use crate::rebalance::RebalanceDeps;
+    use crate::evict::EvictDeps;
+    use crate::addon::AddonDeps;
+    use crate::schedule::ScheduleDeps;
+    use crate::snapshot::SnapshotDeps;
+    use crate::attach::AttachDeps;
+    use crate::events::EventsDeps;
+    use crate::lock::LockDeps;
+    use crate::node::NodeDeps;
+    use crate::run::RunDeps;
+    use crate::sync::SyncDeps;
+    use crate::ps::PsDeps;
+    use crate::metrics::MetricsDeps;
+    use crate::patch::PatchDeps;
+    use crate::edit::EditDeps;
 
     struct TestDeps{}
 
     impl With<dyn SshManager> for TestDeps{
         fn get(&self) -> Box<dyn SshManager> {
-            Box::new(MockSshManager{}) as Box<dyn SshManager>
+            Box::new(MockSshManager::default()) as Box<dyn SshManager>
         }
     }
 
@@ -233,6 +419,23 @@ mod tests {
     impl RolloutDeps for TestDeps {}
     impl UpgradeDeps for TestDeps {}
     impl NodeShellDeps for TestDeps {}
+    impl EditDeps for TestDeps {}
+    impl PatchDeps for TestDeps {}
+    impl MetricsDeps for TestDeps {}
+    impl PsDeps for TestDeps {}
+    impl SyncDeps for TestDeps {}
+    impl RunDeps for TestDeps {}
+    impl NodeDeps for TestDeps {}
+    impl LockDeps for TestDeps {}
+    impl EventsDeps for TestDeps {}
+    impl AttachDeps for TestDeps {}
+    impl SnapshotDeps for TestDeps {}
+    impl ScheduleDeps for TestDeps {}
+    impl AddonDeps for TestDeps {}
+    impl EvictDeps for TestDeps {}
+    impl RebalanceDeps for TestDeps {}
+    impl CpDeps for TestDeps {}
+    impl BuildDeps for TestDeps {}
 
     impl AllDeps for TestDeps{}
 
@@ -242,12 +445,23 @@ mod tests {
 
         skate_with_args(deps, Cli{ command: Refresh(RefreshArgs{
             json: false,
+            clean_orphans: false,
+            full: false,
+            slow_ms: 2000,
             config: ConfigFileArgs{
-                skateconfig: "".to_string(),
+                skateconfig: Some("".to_string()),
                 context: None,
             },
-        }) }).await;
+        }), non_interactive: false }).await;
+
+    }
 
+    #[test]
+    fn test_non_interactive() {
+        // it's global, so it can go after the subcommand
+        assert!(Cli::parse_from(["skate", "get", "pods", "--non-interactive"]).non_interactive);
+        assert!(Cli::parse_from(["skate", "--non-interactive", "refresh"]).non_interactive);
+        assert!(!Cli::parse_from(["skate", "refresh"]).non_interactive);
     }
 }
 
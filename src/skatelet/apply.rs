@@ -6,6 +6,7 @@ use std::{io};
 use std::io::{Read};
 use crate::deps::With;
 use crate::controllers::clusterissuer::ClusterIssuerController;
+use crate::controllers::poddisruptionbudget::PodDisruptionBudgetController;
 use crate::controllers::cronjob::CronjobController;
 use crate::controllers::daemonset::DaemonSetController;
 use crate::controllers::deployment::DeploymentController;
@@ -16,6 +17,9 @@ use crate::controllers::service::ServiceController;
 use crate::errors::SkateError;
 use crate::exec::ShellExec;
 use crate::filestore::Store;
+use crate::skatelet::{apply_progress, firewall};
+use crate::skatelet::apply_progress::ApplyEvent;
+use log::warn;
 use crate::resource::SupportedResources;
 
 #[derive(Debug, Args)]
@@ -27,6 +31,8 @@ pub struct ApplyArgs {
         )
     )]
     prune: bool,
+    #[arg(long, long_help = "Report progress as json lines on stderr, for the client to render.")]
+    progress: bool,
     #[command(subcommand)]
     command: StdinCommand,
 }
@@ -41,6 +47,9 @@ pub enum StdinCommand {
 pub trait ApplyDeps: With<dyn Store> + With<dyn ShellExec>{}
 
 pub fn apply<D: ApplyDeps>(deps: D, apply_args: ApplyArgs) -> Result<(), SkateError> {
+    if apply_args.progress {
+        apply_progress::enable();
+    }
     let manifest = match apply_args.command {
         StdinCommand::Stdin {} => {
             let mut stdin = io::stdin();
@@ -52,7 +61,14 @@ pub fn apply<D: ApplyDeps>(deps: D, apply_args: ApplyArgs) -> Result<(), SkateEr
 
 
     let object: SupportedResources = serde_yaml::from_str(&manifest).expect("failed to deserialize manifest");
-    apply_supported_resource(deps, &object)
+    let execer = With::<dyn ShellExec>::get(&deps);
+    apply_progress::report(ApplyEvent::Applying { kind: object.to_string(), name: object.name().to_string() });
+    let result = apply_supported_resource(deps, &object);
+    // published ports may have changed either way
+    if let Err(e) = firewall::sync(execer.as_ref()) {
+        warn!("failed to sync firewall: {}", e);
+    }
+    result
 }
 
 fn apply_supported_resource< D: ApplyDeps>(deps: D, object: &SupportedResources) -> Result<(),SkateError> {
@@ -96,6 +112,10 @@ fn apply_supported_resource< D: ApplyDeps>(deps: D, object: &SupportedResources)
             let ctrl = ClusterIssuerController::new(store(&deps), ingress_ctrl);
             ctrl.apply(issuer)?;
         }
+        SupportedResources::PodDisruptionBudget(pdb) => {
+            let ctrl = PodDisruptionBudgetController::new(store(&deps));
+            ctrl.apply(pdb)?;
+        }
     }
     Ok(())
 }
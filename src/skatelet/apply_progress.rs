@@ -0,0 +1,75 @@
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use serde::{Deserialize, Serialize};
+
+// apply --progress reports what it's doing as one json object per line on stderr,
+// prefixed so the lines are told apart from podman's own output and error messages
+pub const PROGRESS_PREFIX: &str = "@skate-progress ";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ApplyEvent {
+    Applying { kind: String, name: String },
+    PullingImage { image: String },
+    CreatingPod { name: String },
+    ReloadingProxy { class: String },
+}
+
+impl Display for ApplyEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApplyEvent::Applying { kind, name } => write!(f, "applying {} {}", kind.to_lowercase(), name),
+            ApplyEvent::PullingImage { image } => write!(f, "pulling image {}", image),
+            ApplyEvent::CreatingPod { name } => write!(f, "creating pod {}", name),
+            ApplyEvent::ReloadingProxy { class } => write!(f, "reloading ingress proxy {}", class),
+        }
+    }
+}
+
+impl ApplyEvent {
+    pub fn to_line(&self) -> String {
+        format!("{}{}", PROGRESS_PREFIX, serde_json::to_string(self).expect("failed to serialize progress event"))
+    }
+
+    // None for any line that isn't an event, eg from an older skatelet
+    pub fn parse(line: &str) -> Option<ApplyEvent> {
+        serde_json::from_str(line.strip_prefix(PROGRESS_PREFIX)?).ok()
+    }
+}
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn report(event: ApplyEvent) {
+    if enabled() {
+        eprintln!("{}", event.to_line());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::skatelet::apply_progress::{ApplyEvent, PROGRESS_PREFIX};
+
+    #[test]
+    fn test_event_lines() {
+        let event = ApplyEvent::PullingImage { image: "nginx:1.27".to_string() };
+        let line = event.to_line();
+        assert_eq!(r#"@skate-progress {"event":"pulling_image","image":"nginx:1.27"}"#, line);
+        assert_eq!(Some(event.clone()), ApplyEvent::parse(&line));
+        assert_eq!("pulling image nginx:1.27", event.to_string());
+
+        let event = ApplyEvent::Applying { kind: "Deployment".to_string(), name: "web.shop".to_string() };
+        assert_eq!(r#"@skate-progress {"event":"applying","kind":"Deployment","name":"web.shop"}"#, event.to_line());
+        assert_eq!("applying deployment web.shop", event.to_string());
+
+        assert_eq!(None, ApplyEvent::parse("Error: no such image"));
+        assert_eq!(None, ApplyEvent::parse(&format!("{}{{\"event\":\"unknown\"}}", PROGRESS_PREFIX)));
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram, UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::{env, fs, thread};
+use anyhow::anyhow;
+use chrono::Local;
+use clap::Args;
+use log::{info, warn};
+use serde::Serialize;
+use crate::deps::With;
+use crate::errors::SkateError;
+use crate::exec::ShellExec;
+use crate::filestore::Store;
+
+use crate::skatelet::skatelet::HEALTHZ_SOCKET;
+// a loop that hasn't finished a run within this many of its intervals is wedged
+const MISSED_INTERVALS: i64 = 3;
+
+#[derive(Debug, Args)]
+pub struct DaemonArgs {
+    #[arg(long, default_value = HEALTHZ_SOCKET, long_help = "The unix socket /healthz is served on.")]
+    socket: String,
+    #[arg(long, default_value_t = 30, long_help = "Seconds between the runs of each loop.")]
+    interval: u64,
+}
+
+type Check<T> = fn(&T) -> Result<(), SkateError>;
+type LoopsHealth = Mutex<BTreeMap<&'static str, LoopHealth>>;
+
+pub trait DaemonDeps: With<dyn ShellExec> + With<dyn Store> + Sync {}
+
+pub struct Daemon<D: DaemonDeps> {
+    pub deps: D,
+}
+
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct LoopHealth {
+    interval_secs: i64,
+    started_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_ok: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_error: Option<String>,
+}
+
+impl LoopHealth {
+    // since its last good run, or since the daemon started if it hasn't had one
+    fn healthy(&self, now: i64) -> bool {
+        now - self.last_ok.unwrap_or(self.started_at) <= self.interval_secs * MISSED_INTERVALS
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Health<'a> {
+    healthy: bool,
+    loops: &'a BTreeMap<&'static str, LoopHealth>,
+}
+
+fn healthy(loops: &BTreeMap<&'static str, LoopHealth>, now: i64) -> bool {
+    loops.values().all(|l| l.healthy(now))
+}
+
+fn healthz_response(loops: &BTreeMap<&'static str, LoopHealth>, now: i64) -> String {
+    let healthy = healthy(loops, now);
+    let body = serde_json::to_string(&Health { healthy, loops }).expect("failed to serialize health");
+    let status = match healthy {
+        true => "200 OK",
+        false => "503 Service Unavailable",
+    };
+    format!("HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body)
+}
+
+// sends a state, eg READY=1, to systemd. does nothing unless run from a unit with NotifyAccess
+fn sd_notify(state: &str) -> Result<(), SkateError> {
+    let path = match env::var("NOTIFY_SOCKET") {
+        Ok(path) => path,
+        Err(_) => return Ok(()),
+    };
+    let addr = match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(&path)?,
+    };
+    let socket = UnixDatagram::unbound()?;
+    socket.send_to_addr(state.as_bytes(), &addr)?;
+    Ok(())
+}
+
+// half of what the unit's WatchdogSec is, so a ping is never late
+fn watchdog_interval() -> Option<Duration> {
+    let usec: u64 = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    Some(Duration::from_micros(usec / 2))
+}
+
+impl<D: DaemonDeps> Daemon<D> {
+    // podman answering is what everything else the skatelet does depends on
+    fn check_podman(&self) -> Result<(), SkateError> {
+        With::<dyn ShellExec>::get(&self.deps).exec("podman", &["pod", "ps", "--format", "json"])?;
+        Ok(())
+    }
+
+    fn check_store(&self) -> Result<(), SkateError> {
+        With::<dyn Store>::get(&self.deps).list_objects("deployment")?;
+        Ok(())
+    }
+
+    fn run_loop(&self, name: &'static str, interval: Duration, health: &LoopsHealth, check: Check<Self>) {
+        loop {
+            let result = check(self);
+            let now = Local::now().timestamp();
+            if let Some(h) = health.lock().unwrap().get_mut(name) {
+                match result {
+                    Ok(_) => {
+                        h.last_ok = Some(now);
+                        h.last_error = None;
+                    }
+                    Err(e) => {
+                        warn!("{} check failed: {}", name, e);
+                        h.last_error = Some(e.to_string());
+                    }
+                }
+            }
+            thread::sleep(interval);
+        }
+    }
+
+    fn serve(&self, listener: UnixListener, health: &LoopsHealth) {
+        for stream in listener.incoming() {
+            let mut stream: UnixStream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("failed to accept healthz connection: {}", e);
+                    continue;
+                }
+            };
+            // any request gets the health, the request line is only read so the client doesn't see a reset
+            let _ = stream.set_read_timeout(Some(Duration::from_secs(2)));
+            let mut line = String::new();
+            let _ = BufReader::new(&stream).read_line(&mut line);
+            let response = healthz_response(&health.lock().unwrap(), Local::now().timestamp());
+            let _ = stream.write_all(response.as_bytes());
+        }
+    }
+
+    pub fn daemon(&self, args: DaemonArgs) -> Result<(), SkateError> {
+        let interval = Duration::from_secs(args.interval.max(1));
+        let loops: [(&'static str, Check<Self>); 2] = [("podman", Self::check_podman), ("store", Self::check_store)];
+        let started_at = Local::now().timestamp();
+        let health: LoopsHealth = Mutex::new(loops.iter().map(|(name, _)| (*name, LoopHealth {
+            interval_secs: interval.as_secs() as i64,
+            started_at,
+            ..Default::default()
+        })).collect::<BTreeMap<_, _>>());
+
+        let socket = Path::new(&args.socket);
+        if let Some(dir) = socket.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // left behind by a previous run
+        let _ = fs::remove_file(socket);
+        let listener = UnixListener::bind(socket).map_err(|e| anyhow!(e).context(format!("failed to listen on {}", args.socket)))?;
+        info!("serving /healthz on {}", args.socket);
+
+        thread::scope(|s| {
+            for (name, check) in loops {
+                let health = &health;
+                s.spawn(move || self.run_loop(name, interval, health, check));
+            }
+            s.spawn(|| self.serve(listener, &health));
+
+            // outside of systemd, or with the notify socket gone, the loops and /healthz are still worth running
+            if let Err(e) = sd_notify("READY=1") {
+                warn!("failed to notify systemd: {}", e);
+            }
+            // systemd restarts the skatelet once the pings stop, which they do while any loop is wedged
+            let ping = watchdog_interval().unwrap_or(interval);
+            loop {
+                thread::sleep(ping);
+                match healthy(&health.lock().unwrap(), Local::now().timestamp()) {
+                    true => if let Err(e) = sd_notify("WATCHDOG=1") {
+                        warn!("failed to ping the watchdog: {}", e);
+                    },
+                    false => warn!("not pinging the watchdog, a loop is unhealthy"),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use crate::skatelet::daemon::{healthz_response, LoopHealth};
+
+    #[test]
+    fn test_healthz_response() {
+        let mut loops = BTreeMap::from([
+            ("podman", LoopHealth { interval_secs: 30, started_at: 1000, last_ok: Some(1060), last_error: None }),
+            ("store", LoopHealth { interval_secs: 30, started_at: 1000, last_ok: None, last_error: None }),
+        ]);
+
+        let response = healthz_response(&loops, 1090);
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.ends_with(r#"{"healthy":true,"loops":{"podman":{"intervalSecs":30,"startedAt":1000,"lastOk":1060},"store":{"intervalSecs":30,"startedAt":1000}}}"#), "{}", response);
+
+        // the store never had a good run in 3 intervals
+        assert!(healthz_response(&loops, 1091).starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+
+        loops.get_mut("store").unwrap().last_ok = Some(1080);
+        loops.get_mut("podman").unwrap().last_error = Some("exit code 125".to_string());
+        assert!(healthz_response(&loops, 1150).starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(healthz_response(&loops, 1151).starts_with("HTTP/1.1 503"));
+    }
+}
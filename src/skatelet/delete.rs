@@ -3,7 +3,7 @@ use std::io;
 use std::io::Read;
 use clap::{Args, Subcommand};
 use crate::resource::SupportedResources;
-use crate::resource::SupportedResources::{ClusterIssuer, CronJob, Ingress, Service};
+use crate::resource::SupportedResources::{ClusterIssuer, CronJob, Ingress, PodDisruptionBudget, Service};
 use crate::skatelet::apply::StdinCommand;
 
 use k8s_openapi::api::batch::v1::CronJob as K8sCronJob;
@@ -11,8 +11,10 @@ use k8s_openapi::api::core::v1::Secret;
 
 use k8s_openapi::api::networking::v1::Ingress as K8sIngress;
 use k8s_openapi::api::core::v1::Service as K8sService;
+use k8s_openapi::api::policy::v1::PodDisruptionBudget as K8sPodDisruptionBudget;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
 use crate::controllers::clusterissuer::ClusterIssuerController;
+use crate::controllers::poddisruptionbudget::PodDisruptionBudgetController;
 use crate::controllers::cronjob::CronjobController;
 use crate::controllers::daemonset::DaemonSetController;
 use crate::controllers::deployment::DeploymentController;
@@ -24,6 +26,8 @@ use crate::deps::With;
 use crate::errors::SkateError;
 use crate::exec::ShellExec;
 use crate::filestore::Store;
+use crate::skatelet::firewall;
+use log::warn;
 use crate::spec;
 
 #[derive(Debug, Args, Clone)]
@@ -45,6 +49,7 @@ pub enum DeleteResourceCommands {
     Daemonset(DeleteResourceArgs),
     Service(DeleteResourceArgs),
     Clusterissuer(DeleteResourceArgs),
+    Poddisruptionbudget(DeleteResourceArgs),
 }
 
 
@@ -72,6 +77,15 @@ impl<D: DeleteDeps> Deleter<D> {
         With::<dyn ShellExec>::get(&self.deps)
     }
     pub fn delete(&self, args: DeleteArgs) -> Result<(), SkateError> {
+        let result = self.delete_resource(args);
+        // closes the ports of whatever was removed
+        if let Err(e) = firewall::sync(self.execer().as_ref()) {
+            warn!("failed to sync firewall: {}", e);
+        }
+        result
+    }
+
+    fn delete_resource(&self, args: DeleteArgs) -> Result<(), SkateError> {
         match &args.command {
             DeleteResourceCommands::Ingress(resource_args) => self.delete_ingress(args.clone(), resource_args.clone()),
             DeleteResourceCommands::StdinCommand(_) => self.delete_stdin(args),
@@ -80,20 +94,22 @@ impl<D: DeleteDeps> Deleter<D> {
             DeleteResourceCommands::Daemonset(resource_args) => self.delete_daemonset(args.clone(), resource_args.clone()),
             DeleteResourceCommands::Deployment(resource_args) => self.delete_deployment(args.clone(), resource_args.clone()),
             DeleteResourceCommands::Service(resource_args) => self.delete_service(args.clone(), resource_args.clone()),
-            DeleteResourceCommands::Clusterissuer(resource_args) => self.delete_cluster_issuer(args.clone(), resource_args.clone())
+            DeleteResourceCommands::Clusterissuer(resource_args) => self.delete_cluster_issuer(args.clone(), resource_args.clone()),
+            DeleteResourceCommands::Poddisruptionbudget(resource_args) => self.delete_pod_disruption_budget(args.clone(), resource_args.clone()),
         }
     }
 
 
     fn deletion_metadata(resource_args: DeleteResourceArgs) -> ObjectMeta {
-        let mut meta = ObjectMeta::default();
-        meta.name = Some(resource_args.name.clone());
-        meta.namespace = Some(resource_args.namespace.clone());
-        meta.labels = Some(BTreeMap::from([
-            ("skate.io/name".to_string(), resource_args.name),
-            ("skate.io/namespace".to_string(), resource_args.namespace),
-        ]));
-        meta
+        ObjectMeta {
+            name: Some(resource_args.name.clone()),
+            namespace: Some(resource_args.namespace.clone()),
+            labels: Some(BTreeMap::from([
+                ("skate.io/name".to_string(), resource_args.name),
+                ("skate.io/namespace".to_string(), resource_args.namespace),
+            ])),
+            ..Default::default()
+        }
     }
 
     fn delete_ingress(&self, delete_args: DeleteArgs, resource_args: DeleteResourceArgs) -> Result<(), SkateError> {
@@ -119,6 +135,14 @@ impl<D: DeleteDeps> Deleter<D> {
         }), delete_args.termination_grace_period)
     }
 
+    fn delete_pod_disruption_budget(&self, delete_args: DeleteArgs, resource_args: DeleteResourceArgs) -> Result<(), SkateError> {
+        self.manifest_delete(&PodDisruptionBudget(K8sPodDisruptionBudget {
+            metadata: Self::deletion_metadata(resource_args),
+            spec: None,
+            status: None,
+        }), delete_args.termination_grace_period)
+    }
+
     fn delete_cronjob(&self, delete_args: DeleteArgs, resource_args: DeleteResourceArgs) -> Result<(), SkateError> {
         self.manifest_delete(&CronJob(K8sCronJob {
             metadata: Self::deletion_metadata(resource_args),
@@ -171,6 +195,8 @@ impl<D: DeleteDeps> Deleter<D> {
         match object {
             SupportedResources::Pod(p) => {
                 let ctrl = PodController::new(With::<dyn ShellExec>::get(&self.deps));
+                // evictions send the grace period with the pod
+                let grace_period = grace_period.or(p.spec.as_ref().and_then(|s| s.termination_grace_period_seconds).map(|g| g.max(0) as usize));
                 ctrl.delete(p, grace_period)?;
             }
             SupportedResources::Deployment(d) => {
@@ -204,6 +230,10 @@ impl<D: DeleteDeps> Deleter<D> {
                 let ctrl = ClusterIssuerController::new(self.store(), ingress_controller);
                 ctrl.delete(issuer)?;
             }
+            SupportedResources::PodDisruptionBudget(pdb) => {
+                let ctrl = PodDisruptionBudgetController::new(self.store());
+                ctrl.delete(pdb)?;
+            }
         }
         Ok(())
     }
@@ -1,4 +1,5 @@
 use std::panic;
+use anyhow::anyhow;
 use clap::{Args, Subcommand};
 use crate::deps::With;
 use crate::errors::SkateError;
@@ -11,6 +12,7 @@ pub enum Command {
     Add(AddArgs),
     Remove(RemoveArgs),
     Enable(EnableArgs),
+    Nodes(NodesArgs),
     Reload,
 }
 
@@ -47,6 +49,12 @@ pub struct RemoveArgs {
 }
 
 
+#[derive(Debug, Args)]
+pub struct NodesArgs {
+    #[arg(long_help = "The cluster's nodes, as NAME=IP")]
+    pub nodes: Vec<String>,
+}
+
 impl<D: DnsDeps> Dns<D> {
     
     pub fn dns(&self, args: DnsArgs) -> Result<(), SkateError> {
@@ -55,11 +63,17 @@ impl<D: DnsDeps> Dns<D> {
         }));
         
         let execer = With::<dyn ShellExec>::get(&self.deps);
-        let svc = DnsService::new("/var/lib/skate/dns", &execer);
+        let svc = DnsService::new("/var/lib/skate/dns", execer.as_ref());
         match args.command {
             Command::Add(add_args) => svc.add(add_args.container_id, add_args.ip),
             Command::Remove(remove_args) => svc.remove(remove_args.container_id, remove_args.pod_id),
             Command::Enable(enable_args) => svc.wait_and_enable_healthy(enable_args.container_id),
+            Command::Nodes(nodes_args) => {
+                let nodes = nodes_args.nodes.iter()
+                    .map(|n| n.split_once('=').map(|(name, ip)| (name.to_string(), ip.to_string())).ok_or(anyhow!("invalid node {}, expected NAME=IP", n)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                svc.set_nodes(nodes)
+            }
             Command::Reload => svc.reload()
         }
     }
@@ -0,0 +1,310 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use anyhow::anyhow;
+use clap::{Args, Subcommand};
+use itertools::Itertools;
+use log::info;
+use serde::{Deserialize, Serialize};
+use crate::deps::With;
+use crate::errors::SkateError;
+use crate::exec::ShellExec;
+use crate::ingress_class;
+use crate::skatelet::skatelet::VAR_PATH;
+
+const STATE_FILE: &str = "firewall.json";
+const NFT_FILE: &str = "firewall.nft";
+const NFT_TABLE: &str = "skate";
+const ROUTES_FILE: &str = "/etc/skate/routes.sh";
+const DEFAULT_SSH_PORT: u16 = 22;
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum Commands {
+    #[command(about = "start managing the node's firewall, and open the ports it needs")]
+    Enable,
+    #[command(about = "stop managing the node's firewall, removing the rules skate added")]
+    Disable,
+    #[command(about = "open the ports the node currently needs and close the rest, if enabled")]
+    Sync,
+    #[command(about = "print the rules the node currently needs")]
+    Show,
+}
+
+#[derive(Debug, Args)]
+pub struct FirewallArgs {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Backend {
+    // a table of skate's own, replaced as a whole on every sync
+    Nftables,
+    // used when ufw is already active, since its chains would drop what the skate table accepts
+    Ufw,
+}
+
+// what was applied last, so that rules no longer needed can be removed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct FirewallState {
+    backend: Backend,
+    #[serde(default)]
+    ufw_rules: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rules {
+    pub tcp: BTreeSet<u16>,
+    pub udp: BTreeSet<u16>,
+    // peer hosts and their pod subnets, for the overlay routes, cluster dns and services
+    pub trusted: BTreeSet<String>,
+}
+
+pub trait FirewallDeps: With<dyn ShellExec> {}
+
+pub struct Firewall<D: FirewallDeps> {
+    pub deps: D,
+}
+
+impl<D: FirewallDeps> Firewall<D> {
+    pub fn firewall(&self, args: FirewallArgs) -> Result<(), SkateError> {
+        let execer = self.deps.get();
+        match args.command {
+            Commands::Enable => enable(execer.as_ref()),
+            Commands::Disable => disable(execer.as_ref()),
+            Commands::Sync => sync(execer.as_ref()),
+            Commands::Show => {
+                let rules = required_rules(execer.as_ref())?;
+                println!("{}", render_nftables(&rules));
+                Ok(())
+            }
+        }
+    }
+}
+
+fn state_path() -> PathBuf {
+    PathBuf::from(VAR_PATH).join(STATE_FILE)
+}
+
+fn load_state() -> Result<Option<FirewallState>, SkateError> {
+    let path = state_path();
+    if !path.exists() {
+        return Ok(None);
+    }
+    let state = serde_json::from_str(&fs::read_to_string(path)?).map_err(|e| anyhow!(e).context("failed to read firewall state"))?;
+    Ok(Some(state))
+}
+
+fn save_state(state: &FirewallState) -> Result<(), SkateError> {
+    let contents = serde_json::to_string(state).map_err(|e| anyhow!(e).context("failed to serialize firewall state"))?;
+    fs::write(state_path(), contents)?;
+    Ok(())
+}
+
+fn ufw_active(execer: &dyn ShellExec) -> bool {
+    execer.exec("ufw", &["status"]).is_ok_and(|o| o.lines().next().is_some_and(|l| l.trim() == "Status: active"))
+}
+
+fn enable(execer: &dyn ShellExec) -> Result<(), SkateError> {
+    if load_state()?.is_none() {
+        let backend = match ufw_active(execer) {
+            true => Backend::Ufw,
+            false => Backend::Nftables,
+        };
+        save_state(&FirewallState { backend, ufw_rules: vec![] })?;
+    }
+    sync(execer)
+}
+
+fn disable(execer: &dyn ShellExec) -> Result<(), SkateError> {
+    let state = match load_state()? {
+        Some(state) => state,
+        None => return Ok(()),
+    };
+    match state.backend {
+        Backend::Nftables => {
+            let _ = execer.exec("nft", &["delete", "table", "inet", NFT_TABLE]);
+        }
+        Backend::Ufw => {
+            for rule in &state.ufw_rules {
+                ufw_delete(execer, rule)?;
+            }
+        }
+    }
+    fs::remove_file(state_path())?;
+    info!("firewall disabled");
+    Ok(())
+}
+
+// makes the firewall match what the node needs now. does nothing unless the firewall was enabled
+pub(crate) fn sync(execer: &dyn ShellExec) -> Result<(), SkateError> {
+    let mut state = match load_state()? {
+        Some(state) => state,
+        None => return Ok(()),
+    };
+    let rules = required_rules(execer)?;
+
+    match state.backend {
+        Backend::Nftables => {
+            let path = PathBuf::from(VAR_PATH).join(NFT_FILE);
+            fs::write(&path, render_nftables(&rules))?;
+            execer.exec("nft", &["-f", &path.to_string_lossy()])?;
+        }
+        Backend::Ufw => {
+            let wanted = ufw_rules(&rules);
+            for rule in state.ufw_rules.iter().filter(|r| !wanted.contains(r)) {
+                ufw_delete(execer, rule)?;
+            }
+            for rule in wanted.iter().filter(|r| !state.ufw_rules.contains(r)) {
+                let mut args: Vec<_> = rule.split_whitespace().collect();
+                args.extend(["comment", "skate"]);
+                execer.exec("ufw", &args)?;
+            }
+            state.ufw_rules = wanted;
+        }
+    }
+    save_state(&state)?;
+    info!("firewall synced, tcp {:?}, udp {:?}", rules.tcp, rules.udp);
+    Ok(())
+}
+
+fn ufw_delete(execer: &dyn ShellExec, rule: &str) -> Result<(), SkateError> {
+    let mut args = vec!["delete"];
+    args.extend(rule.split_whitespace());
+    execer.exec("ufw", &args)?;
+    Ok(())
+}
+
+// the ports published by the node's pods, ssh and ingress, and the cluster peers to trust
+fn required_rules(execer: &dyn ShellExec) -> Result<Rules, SkateError> {
+    let mut rules = Rules::default();
+
+    let ssh_port = execer.exec("sshd", &["-T"]).ok().and_then(|o| parse_sshd_port(&o)).unwrap_or(DEFAULT_SSH_PORT);
+    rules.tcp.insert(ssh_port);
+    // the default ingress runs on every node, the other classes only where they have ingresses
+    for class in ingress_class::node_classes() {
+        rules.tcp.extend(class.ports());
+    }
+
+    let ports = execer.exec("podman", &["ps", "--filter", "label=skate.io/namespace", "--format", "{{.Ports}}"])?;
+    for (port, proto) in parse_published_ports(&ports) {
+        match proto.as_str() {
+            "udp" => rules.udp.insert(port),
+            _ => rules.tcp.insert(port),
+        };
+    }
+
+    // missing until the node's networking is set up, in which case there are no peers yet
+    if let Ok(routes) = fs::read_to_string(ROUTES_FILE) {
+        rules.trusted = parse_route_peers(&routes);
+    }
+    Ok(rules)
+}
+
+fn parse_sshd_port(output: &str) -> Option<u16> {
+    output.lines().find_map(|l| l.strip_prefix("port ").and_then(|p| p.trim().parse().ok()))
+}
+
+// parses podman's ports column, eg "0.0.0.0:8080->80/tcp, 0.0.0.0:9000-9001->9000-9001/udp", into host ports.
+// ports that are only exposed and not published on the host are left out
+fn parse_published_ports(output: &str) -> Vec<(u16, String)> {
+    output.lines().flat_map(|l| l.split(',')).filter_map(|entry| {
+        let (host, container) = entry.trim().split_once("->")?;
+        let proto = container.split_once('/').map(|(_, p)| p).unwrap_or("tcp").to_string();
+        let ports = host.rsplit(':').next()?;
+        let (start, end) = match ports.split_once('-') {
+            Some((start, end)) => (start.parse::<u16>().ok()?, end.parse::<u16>().ok()?),
+            None => {
+                let port = ports.parse::<u16>().ok()?;
+                (port, port)
+            }
+        };
+        Some((start..=end).map(move |p| (p, proto.clone())))
+    }).flatten().unique().collect()
+}
+
+fn valid_source(source: &str) -> bool {
+    let (ip, prefix) = source.split_once('/').unwrap_or((source, "32"));
+    ip.parse::<Ipv4Addr>().is_ok() && prefix.parse::<u8>().is_ok_and(|p| p <= 32)
+}
+
+// the routes file has a line of "ip route add <subnet> via <peer host>" per other node in the cluster
+fn parse_route_peers(routes: &str) -> BTreeSet<String> {
+    routes.lines().filter_map(|l| {
+        let parts: Vec<_> = l.split_whitespace().collect();
+        match parts.as_slice() {
+            ["ip", "route", "add", subnet, "via", peer, ..] => Some([subnet.to_string(), peer.to_string()]),
+            _ => None,
+        }
+    }).flatten().filter(|s| valid_source(s)).collect()
+}
+
+fn render_nftables(rules: &Rules) -> String {
+    let mut lines = vec![
+        "ct state established,related accept".to_string(),
+        "ct state invalid drop".to_string(),
+        "iifname \"lo\" accept".to_string(),
+        // local pods reach cluster dns and services through the podman bridge
+        "iifname \"podman*\" accept".to_string(),
+        "meta l4proto { icmp, ipv6-icmp } accept".to_string(),
+    ];
+    if !rules.trusted.is_empty() {
+        lines.push(format!("ip saddr {{ {} }} accept", rules.trusted.iter().join(", ")));
+    }
+    if !rules.tcp.is_empty() {
+        lines.push(format!("tcp dport {{ {} }} accept", rules.tcp.iter().join(", ")));
+    }
+    if !rules.udp.is_empty() {
+        lines.push(format!("udp dport {{ {} }} accept", rules.udp.iter().join(", ")));
+    }
+
+    // declaring the table before deleting it means the first load doesn't fail, and the whole file is applied atomically
+    format!("table inet {table}\ndelete table inet {table}\ntable inet {table} {{\n    chain input {{\n        type filter hook input priority 0; policy drop;\n{rules}\n    }}\n}}\n",
+            table = NFT_TABLE,
+            rules = lines.iter().map(|l| format!("        {}", l)).join("\n"))
+}
+
+fn ufw_rules(rules: &Rules) -> Vec<String> {
+    let mut out = vec!["allow in on podman1".to_string()];
+    out.extend(rules.trusted.iter().map(|s| format!("allow from {}", s)));
+    out.extend(rules.tcp.iter().map(|p| format!("allow {}/tcp", p)));
+    out.extend(rules.udp.iter().map(|p| format!("allow {}/udp", p)));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use crate::skatelet::firewall::{parse_published_ports, parse_route_peers, parse_sshd_port, render_nftables, ufw_rules, Rules};
+
+    #[test]
+    fn test_parse_inputs() {
+        let ports = parse_published_ports("0.0.0.0:8080->80/tcp, [::]:8080->80/tcp\n\n0.0.0.0:5000-5001->53-54/udp, 9090/tcp\n");
+        assert_eq!(vec![(8080, "tcp".to_string()), (5000, "udp".to_string()), (5001, "udp".to_string())], ports);
+
+        assert_eq!(Some(2222), parse_sshd_port("permitrootlogin no\nport 2222\n"));
+        assert_eq!(None, parse_sshd_port(""));
+
+        let routes = "#!/bin/bash\nip route add 10.1.0.0/16 via 192.168.1.11\nip route add garbage via nowhere\nmodprobe -- ip_vs\n";
+        assert_eq!(BTreeSet::from(["10.1.0.0/16".to_string(), "192.168.1.11".to_string()]), parse_route_peers(routes));
+    }
+
+    #[test]
+    fn test_render_rules() {
+        let rules = Rules {
+            tcp: BTreeSet::from([22, 80, 443, 8080]),
+            udp: BTreeSet::new(),
+            trusted: BTreeSet::from(["10.1.0.0/16".to_string(), "192.168.1.11".to_string()]),
+        };
+        let nft = render_nftables(&rules);
+        assert!(nft.starts_with("table inet skate\ndelete table inet skate\n"));
+        assert!(nft.contains("policy drop;"));
+        assert!(nft.contains("        tcp dport { 22, 80, 443, 8080 } accept\n"));
+        assert!(nft.contains("        ip saddr { 10.1.0.0/16, 192.168.1.11 } accept\n"));
+        assert!(!nft.contains("udp dport"));
+
+        assert_eq!(vec!["allow in on podman1", "allow from 10.1.0.0/16", "allow from 192.168.1.11", "allow 22/tcp", "allow 80/tcp", "allow 443/tcp", "allow 8080/tcp"], ufw_rules(&rules));
+    }
+}
@@ -51,8 +51,10 @@ pub struct SyncArgs {
 }
 
 
+#[allow(clippy::upper_case_acronyms)]
 pub trait IPVSDeps: With<dyn ShellExec>{}
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct IPVS<D: IPVSDeps> {
     pub deps: D,
 }
@@ -0,0 +1,156 @@
+use std::fs;
+use std::path::PathBuf;
+use anyhow::anyhow;
+use chrono::{DateTime, Duration, Local};
+use clap::{Args, Subcommand};
+use serde::{Deserialize, Serialize};
+use crate::errors::SkateError;
+use crate::skatelet::skatelet::VAR_PATH;
+use crate::util;
+
+const LOCK_FILE: &str = "LOCK";
+// held while the lock file is read and written, so two acquires can't both see it free
+const GUARD_FILE: &str = "LOCK.guard";
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum Commands {
+    #[command(about = "take the cluster lock, printing the result as json")]
+    Acquire(AcquireArgs),
+    #[command(about = "release the cluster lock, if held by the owner")]
+    Release(ReleaseArgs),
+    #[command(about = "print the cluster lock as json, null if it isn't held")]
+    Status,
+    #[command(about = "remove the cluster lock, whoever holds it")]
+    Break,
+}
+
+#[derive(Debug, Args)]
+pub struct LockArgs {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Clone, Debug, Args)]
+pub struct AcquireArgs {
+    #[arg(long, long_help = "Who is taking the lock.")]
+    owner: String,
+    #[arg(long, long_help = "The command the lock is taken for.")]
+    command: String,
+    #[arg(long, long_help = "Seconds after which the lock expires, in case it's never released.")]
+    ttl: i64,
+}
+
+#[derive(Clone, Debug, Args)]
+pub struct ReleaseArgs {
+    #[arg(long, long_help = "Who took the lock.")]
+    owner: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LockInfo {
+    pub owner: String,
+    pub command: String,
+    pub acquired_at: DateTime<Local>,
+    pub expires_at: DateTime<Local>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AcquireResult {
+    pub acquired: bool,
+    // the lock as it stands afterwards, ours or the holder's
+    pub lock: LockInfo,
+}
+
+pub fn lock(args: LockArgs) -> Result<(), SkateError> {
+    let result = match args.command {
+        Commands::Acquire(args) => serde_json::to_string(&with_guard(move || acquire(args, Local::now()))?),
+        Commands::Release(args) => {
+            with_guard(move || release(&args.owner))?;
+            return Ok(());
+        }
+        Commands::Status => serde_json::to_string(&read_lock(Local::now())?),
+        Commands::Break => {
+            with_guard(remove_lock)?;
+            return Ok(());
+        }
+    };
+    println!("{}", result.map_err(|e| anyhow!(e).context("failed to serialize lock"))?);
+    Ok(())
+}
+
+fn lock_path() -> PathBuf {
+    PathBuf::from(VAR_PATH).join(LOCK_FILE)
+}
+
+fn with_guard<T: 'static>(f: impl FnOnce() -> Result<T, SkateError> + 'static) -> Result<T, SkateError> {
+    let guard = PathBuf::from(VAR_PATH).join(GUARD_FILE);
+    Ok(util::lock_file(&guard.to_string_lossy(), Box::new(move || f().map_err(|e| e.into())))?)
+}
+
+// the current lock, expired ones counting as not held
+fn read_lock(now: DateTime<Local>) -> Result<Option<LockInfo>, SkateError> {
+    let path = lock_path();
+    if !path.exists() {
+        return Ok(None);
+    }
+    let lock: LockInfo = serde_json::from_str(&fs::read_to_string(path)?).map_err(|e| anyhow!(e).context("failed to read lock"))?;
+    Ok(live_lock(lock, now))
+}
+
+fn live_lock(lock: LockInfo, now: DateTime<Local>) -> Option<LockInfo> {
+    match lock.expires_at > now {
+        true => Some(lock),
+        false => None,
+    }
+}
+
+// takes the lock unless someone else holds it. the owner taking it again extends it
+fn acquire(args: AcquireArgs, now: DateTime<Local>) -> Result<AcquireResult, SkateError> {
+    if let Some(lock) = read_lock(now)? {
+        if lock.owner != args.owner {
+            return Ok(AcquireResult { acquired: false, lock });
+        }
+    }
+    let lock = LockInfo {
+        owner: args.owner,
+        command: args.command,
+        acquired_at: now,
+        expires_at: now + Duration::seconds(args.ttl),
+    };
+    let contents = serde_json::to_string(&lock).map_err(|e| anyhow!(e).context("failed to serialize lock"))?;
+    fs::write(lock_path(), contents)?;
+    Ok(AcquireResult { acquired: true, lock })
+}
+
+fn release(owner: &str) -> Result<(), SkateError> {
+    match read_lock(Local::now())? {
+        Some(lock) if lock.owner != owner => Err(anyhow!("lock is held by {}", lock.owner).into()),
+        _ => remove_lock(),
+    }
+}
+
+fn remove_lock() -> Result<(), SkateError> {
+    match fs::remove_file(lock_path()) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(anyhow!(e).context("failed to remove lock").into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Local};
+    use crate::skatelet::lock::{live_lock, LockInfo};
+
+    #[test]
+    fn test_live_lock() {
+        let now = Local::now();
+        let lock = LockInfo {
+            owner: "alice@laptop".to_string(),
+            command: "apply".to_string(),
+            acquired_at: now - Duration::seconds(60),
+            expires_at: now + Duration::seconds(60),
+        };
+        assert_eq!(Some(lock.clone()), live_lock(lock.clone(), now));
+        assert_eq!(None, live_lock(lock, now + Duration::seconds(61)));
+    }
+}
@@ -0,0 +1,166 @@
+use anyhow::anyhow;
+use clap::Args;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use crate::deps::With;
+use crate::errors::SkateError;
+use crate::exec::ShellExec;
+
+#[derive(Debug, Args)]
+pub struct LogsArgs {
+    #[arg(short, long, long_help = "Stream the logs.")]
+    pub follow: bool,
+    #[arg(long, default_value_t = -1, long_help = "Lines of recent logs to print per container, all of them if not positive.")]
+    pub tail: i32,
+    #[arg(long, short, default_value = "default", long_help = "The namespace of the pod or resource.")]
+    pub namespace: String,
+    #[arg(name = "POD | TYPE/NAME")]
+    pub identifier: String,
+}
+
+pub trait LogsDeps: With<dyn ShellExec> {}
+
+pub struct Logs<D: LogsDeps> {
+    pub deps: D,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PodmanContainer {
+    id: String,
+    names: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PodmanPod {
+    name: String,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default)]
+    infra_id: String,
+    #[serde(default)]
+    containers: Vec<PodmanContainer>,
+}
+
+// the containers of this node's pods the identifier is for, from podman pod ls --format json, without their infra containers
+pub(crate) fn select_containers(pods_json: &str, identifier: &str, namespace: &str) -> Result<Vec<String>, SkateError> {
+    let pods: Vec<PodmanPod> = serde_json::from_str(pods_json).map_err(|e| anyhow!(e).context("failed to parse podman pods"))?;
+    let (kind, name) = identifier.split_once('/').unwrap_or(("pod", identifier));
+    let label = match kind {
+        "pod" => None,
+        "deployment" | "daemonset" | "cronjob" => Some(format!("skate.io/{}", kind)),
+        _ => return Err(anyhow!("unexpected resource type {}, expected pod, deployment, daemonset or cronjob", kind).into()),
+    };
+    let full_name = format!("{}.{}", name, namespace);
+    Ok(pods.into_iter()
+        .filter(|p| p.labels.get("skate.io/namespace").is_some_and(|ns| ns == namespace))
+        .filter(|p| match &label {
+            Some(label) => p.labels.get(label).is_some_and(|n| n == name),
+            None => p.name == name || p.name == full_name,
+        })
+        .flat_map(|p| {
+            let infra_id = p.infra_id;
+            p.containers.into_iter().filter(move |c| c.id != infra_id).map(|c| c.names)
+        })
+        .collect())
+}
+
+// podman reads the logs back the same way whether they went to journald or a json file, and interleaves the containers'
+// when following
+pub(crate) fn logs_args(containers: &[String], follow: bool, tail: i32) -> Vec<String> {
+    let mut args: Vec<_> = ["logs", "--names", "--timestamps"].map(String::from).to_vec();
+    if follow {
+        args.push("--follow".to_string());
+    }
+    if tail > 0 {
+        args.push(format!("--tail={}", tail));
+    }
+    args.extend(containers.iter().cloned());
+    args
+}
+
+impl<D: LogsDeps> Logs<D> {
+    pub fn logs(&self, args: LogsArgs) -> Result<(), SkateError> {
+        let execer = With::<dyn ShellExec>::get(&self.deps);
+        let pods_json = execer.exec("podman", &["pod", "ls", "--format", "json"]).map_err(|e| anyhow!(e.to_string()).context("failed to list pods"))?;
+        let containers = select_containers(&pods_json, &args.identifier, &args.namespace)?;
+        // the pods may be on other nodes
+        if containers.is_empty() {
+            return Ok(());
+        }
+
+        let logs_args = logs_args(&containers, args.follow, args.tail);
+        execer.exec_stdout("podman", &logs_args.iter().map(|a| a.as_str()).collect::<Vec<_>>())
+            .map_err(|e| anyhow!(e.to_string()).context(format!("failed to read the logs of {}", containers.join(", "))).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::sync::{Arc, Mutex};
+    use crate::deps::With;
+    use crate::exec::ShellExec;
+    use crate::skatelet::logs::{logs_args, select_containers, Logs, LogsArgs, LogsDeps};
+
+    const PODS: &str = r#"[
+        {"Name": "web.shop", "InfraId": "i1", "Labels": {"skate.io/namespace": "shop"}, "Containers": [{"Id": "i1", "Names": "web-infra"}, {"Id": "c1", "Names": "web.shop-nginx"}]},
+        {"Name": "dpl-api-1.shop", "Labels": {"skate.io/namespace": "shop", "skate.io/deployment": "api"}, "Containers": [{"Id": "c2", "Names": "dpl-api-1.shop-api"}]},
+        {"Name": "dpl-api-2.shop", "Labels": {"skate.io/namespace": "shop", "skate.io/deployment": "api"}, "Containers": [{"Id": "c3", "Names": "dpl-api-2.shop-api"}]},
+        {"Name": "dpl-api-1.blog", "Labels": {"skate.io/namespace": "blog", "skate.io/deployment": "api"}, "Containers": [{"Id": "c4", "Names": "dpl-api-1.blog-api"}]},
+        {"Name": "infra"}
+    ]"#;
+
+    // runs the listing, and records the commands given the terminal
+    #[derive(Clone, Default)]
+    struct MockExec {
+        stdout_commands: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl ShellExec for MockExec {
+        fn exec(&self, command: &str, args: &[&str]) -> Result<String, Box<dyn Error>> {
+            match (command, args) {
+                ("podman", ["pod", "ls", "--format", "json"]) => Ok(PODS.to_string()),
+                _ => Err(format!("unexpected command {} {}", command, args.join(" ")).into()),
+            }
+        }
+
+        fn exec_stdout(&self, command: &str, args: &[&str]) -> Result<(), Box<dyn Error>> {
+            self.stdout_commands.lock().unwrap().push(format!("{} {}", command, args.join(" ")));
+            Ok(())
+        }
+    }
+
+    struct TestDeps {
+        exec: MockExec,
+    }
+
+    impl With<dyn ShellExec> for TestDeps {
+        fn get(&self) -> Box<dyn ShellExec> {
+            Box::new(self.exec.clone())
+        }
+    }
+
+    impl LogsDeps for TestDeps {}
+
+    #[test]
+    fn test_select_containers() {
+        assert_eq!(vec!["web.shop-nginx"], select_containers(PODS, "web", "shop").unwrap());
+        assert_eq!(vec!["web.shop-nginx"], select_containers(PODS, "pod/web.shop", "shop").unwrap());
+        assert_eq!(vec!["dpl-api-1.shop-api", "dpl-api-2.shop-api"], select_containers(PODS, "deployment/api", "shop").unwrap());
+        assert!(select_containers(PODS, "web", "blog").unwrap().is_empty());
+        assert!(select_containers(PODS, "service/web", "shop").is_err());
+
+        assert_eq!(vec!["logs", "--names", "--timestamps", "--follow", "--tail=10", "a", "b"], logs_args(&["a".to_string(), "b".to_string()], true, 10));
+    }
+
+    #[test]
+    fn test_logs() {
+        let exec = MockExec::default();
+        let logs = Logs { deps: TestDeps { exec: exec.clone() } };
+        logs.logs(LogsArgs { follow: true, tail: -1, namespace: "shop".to_string(), identifier: "deployment/api".to_string() }).unwrap();
+        logs.logs(LogsArgs { follow: false, tail: 5, namespace: "blog".to_string(), identifier: "web".to_string() }).unwrap();
+        assert_eq!(vec!["podman logs --names --timestamps --follow dpl-api-1.shop-api dpl-api-2.shop-api"], *exec.stdout_commands.lock().unwrap());
+    }
+}
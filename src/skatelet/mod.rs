@@ -1,17 +1,26 @@
+#[allow(clippy::module_inception)]
 mod skatelet;
 mod apply;
+pub(crate) mod apply_progress;
 
 pub(crate) mod system;
 mod template;
 mod delete;
 pub(crate) mod dns;
-mod oci;
+pub(crate) mod oci;
 mod ipvs;
+mod firewall;
+pub(crate) mod lock;
 mod create;
 mod cordon;
+pub(crate) mod prune;
+mod logs;
+// the skatelet only runs on linux nodes, the rest of it builds everywhere for the client
+#[cfg(target_os = "linux")]
+mod daemon;
 pub(crate) mod services;
 
-pub use skatelet::skatelet;
+pub use skatelet::{skatelet, HEALTHZ_SOCKET};
 pub use system::SystemInfo;
 pub use create::JobArgs;
 
@@ -1,17 +1,37 @@
-use std::panic;
+use std::{fs, panic, thread};
+use std::time::Duration;
+use anyhow::anyhow;
+use chrono::Local;
 use clap::{Args, Subcommand};
-use log::{error, info};
+use log::{error, info, warn};
 use strum_macros::EnumString;
 use crate::errors::SkateError;
 use crate::exec::{RealExec, ShellExec};
 use crate::skatelet::services::dns::DnsService;
 use crate::skatelet::skatelet::log_panic;
-use crate::util::spawn_orphan_process;
+use crate::skatelet::system::{ContainerEvent, ContainerStage};
+use crate::util::{lock_file, spawn_orphan_process};
+
+const CONTAINER_EVENTS_FILE: &str = "/var/lib/skate/container-events.jsonl";
+const CONTAINER_EVENTS_LOCK: &str = "/var/lib/skate/container-events.lock";
+// the journal only has to cover the time between two refreshes
+const MAX_CONTAINER_EVENTS: usize = 500;
+// conmon writes the exit code here before podman cleans the container up and runs the poststop hooks
+const EXITS_DIR: &str = "/run/libpod/exits";
+// how long podman gets to restart a container itself before the skatelet does
+const RESTART_GRACE: Duration = Duration::from_secs(5);
 
 #[derive(EnumString, Debug, Subcommand)]
 pub enum Commands {
     Poststart,
     Poststop,
+    #[command(about = "Start a stopped container again if its restart policy asks for it and podman didn't")]
+    Restart(RestartArgs),
+}
+
+#[derive(Debug, Args, Default)]
+pub struct RestartArgs {
+    container_id: String,
 }
 #[derive(Debug, Args)]
 pub struct OciArgs {
@@ -26,6 +46,7 @@ pub(crate) fn oci(args: OciArgs) -> Result<(),SkateError> {
     let result = match args.commands {
         Commands::Poststart => post_start(),
         Commands::Poststop => post_stop(),
+        Commands::Restart(args) => restart(&RealExec{}, &args.container_id),
     };
 
     match result {
@@ -43,6 +64,7 @@ pub(crate) fn oci(args: OciArgs) -> Result<(),SkateError> {
 fn post_start() -> Result<(), SkateError> {
     info!("poststart");
     let id = container_id()?;
+    record_container_event(&id, ContainerStage::Started, None);
     spawn_orphan_process("skatelet", ["dns", "add", &id]);
     Ok(())
 }
@@ -50,14 +72,112 @@ fn post_start() -> Result<(), SkateError> {
 fn post_stop() -> Result<(), SkateError> {
     info!("poststop");
     let id = container_id()?;
+    let exit_code = fs::read_to_string(format!("{}/{}", EXITS_DIR, id)).ok().and_then(|c| c.trim().parse().ok());
+    record_container_event(&id, ContainerStage::Stopped, exit_code);
+    // podman holds the container's lock while the hook runs, so the restart waits in its own process
+    spawn_orphan_process("skatelet", ["oci", "restart", &id]);
     let execer: Box<dyn ShellExec> = Box::new(RealExec{});
-    let dns = DnsService::new("/var/lib/skate/dns", &execer);
+    let dns = DnsService::new("/var/lib/skate/dns", execer.as_ref());
     dns.remove(Some(id), None)
 }
 
+// whether a container in the state podman inspect gives as `<status> <exit code> <restart policy> <stopped by user>` should
+// be started again. containers stopped on purpose, eg while their pod is removed, are left stopped
+fn should_restart(inspected: &str) -> bool {
+    match inspected.split_whitespace().collect::<Vec<_>>()[..] {
+        ["exited", _, "always", "false"] => true,
+        ["exited", code, "on-failure", "false"] => code != "0",
+        _ => false,
+    }
+}
+
+// podman restarts containers itself when it handles the exit, this starts the ones left exited, eg after a failed cleanup
+fn restart(execer: &dyn ShellExec, container_id: &str) -> Result<(), SkateError> {
+    thread::sleep(RESTART_GRACE);
+    // a container removed since, eg with its pod, has nothing to restart. podman versions that don't say whether the
+    // container was stopped on purpose fail the format too
+    let inspected = match execer.exec("podman", &["inspect", container_id, "--format", "{{.State.Status}} {{.State.ExitCode}} {{.HostConfig.RestartPolicy.Name}} {{.State.StoppedByUser}}"]) {
+        Ok(inspected) => inspected,
+        Err(_) => return Ok(()),
+    };
+    if !should_restart(&inspected) {
+        return Ok(());
+    }
+    info!("restarting container {}", container_id);
+    execer.exec("podman", &["start", container_id]).map_err(|e| anyhow!(e.to_string()).context(format!("failed to restart container {}", container_id)))?;
+    Ok(())
+}
+
 fn container_id() -> Result<String, SkateError> {
     let cwd = std::env::current_dir()?;
     let container_dir = cwd.parent().ok_or("no parent dir".to_string())?;
     let container_id = container_dir.file_name().ok_or("no dir name".to_string())?;
     Ok(container_id.to_string_lossy().to_string())
-}
\ No newline at end of file
+}
+
+// the journal with the event added, dropping the oldest once it's full
+fn append_container_event(journal: &str, event: &ContainerEvent) -> Result<String, SkateError> {
+    let line = serde_json::to_string(event).map_err(|e| anyhow!(e).context("failed to serialize container event"))?;
+    let lines: Vec<_> = journal.lines().filter(|l| !l.is_empty()).chain([line.as_str()]).collect();
+    let skip = lines.len().saturating_sub(MAX_CONTAINER_EVENTS);
+    Ok(lines[skip..].iter().map(|l| format!("{}\n", l)).collect())
+}
+
+// a line cut short by a crash is skipped rather than losing the rest
+fn parse_container_events(journal: &str) -> Vec<ContainerEvent> {
+    journal.lines().filter_map(|l| serde_json::from_str(l).ok()).collect()
+}
+
+// a failure here mustn't fail the hook, podman would fail the container along with it
+fn record_container_event(container_id: &str, stage: ContainerStage, exit_code: Option<i32>) {
+    let event = ContainerEvent { time: Local::now(), container_id: container_id.to_string(), stage, exit_code };
+    let result = lock_file(CONTAINER_EVENTS_LOCK, Box::new(move || {
+        let journal = fs::read_to_string(CONTAINER_EVENTS_FILE).unwrap_or_default();
+        fs::write(CONTAINER_EVENTS_FILE, append_container_event(&journal, &event)?)?;
+        Ok(())
+    }));
+    if let Err(e) = result {
+        warn!("failed to record container event: {}", e);
+    }
+}
+
+pub(crate) fn read_container_events() -> Vec<ContainerEvent> {
+    parse_container_events(&fs::read_to_string(CONTAINER_EVENTS_FILE).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+    use crate::skatelet::oci::{append_container_event, parse_container_events, should_restart, MAX_CONTAINER_EVENTS};
+    use crate::skatelet::system::{ContainerEvent, ContainerStage};
+
+    #[test]
+    fn test_container_events_journal() {
+        let event = |id: &str, stage| ContainerEvent { time: Local::now(), container_id: id.to_string(), stage, exit_code: None };
+
+        let journal = append_container_event("", &event("1a2b", ContainerStage::Started)).unwrap();
+        let journal = append_container_event(&format!("{}{{\"time\":", journal), &event("1a2b", ContainerStage::Stopped)).unwrap();
+        let events = parse_container_events(&journal);
+        assert_eq!(vec![("1a2b", ContainerStage::Started), ("1a2b", ContainerStage::Stopped)],
+            events.iter().map(|e| (e.container_id.as_str(), e.stage)).collect::<Vec<_>>());
+
+        let mut journal = String::new();
+        for i in 0..MAX_CONTAINER_EVENTS + 10 {
+            journal = append_container_event(&journal, &event(&i.to_string(), ContainerStage::Started)).unwrap();
+        }
+        let events = parse_container_events(&journal);
+        assert_eq!(MAX_CONTAINER_EVENTS, events.len());
+        assert_eq!("10", events[0].container_id);
+    }
+
+    #[test]
+    fn test_should_restart() {
+        assert!(should_restart("exited 0 always false"));
+        assert!(should_restart("exited 137 on-failure false"));
+        assert!(!should_restart("exited 0 on-failure false"));
+        assert!(!should_restart("exited 137 always true"));
+        assert!(!should_restart("exited 1 no false"));
+        assert!(!should_restart("exited 1  false"));
+        assert!(!should_restart("running 0 always false"));
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashSet;
+use anyhow::anyhow;
+use chrono::Local;
+use clap::{Args, Subcommand};
+use k8s_openapi::api::apps::v1::{DaemonSet, Deployment};
+use k8s_openapi::api::batch::v1::CronJob;
+use k8s_openapi::api::core::v1::PodSpec;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sysinfo::Disks;
+use crate::deps::With;
+use crate::errors::SkateError;
+use crate::exec::ShellExec;
+use crate::filestore::Store;
+use crate::image::normalize_image_ref;
+use crate::controllers::pod::PodController;
+use crate::skatelet::system::disk_info_for_path;
+use crate::skatelet::system::podman::{PodmanPodInfo, PodmanPodStatus};
+
+const BYTES_IN_MIB: u64 = (2u64).pow(20);
+const CONTAINERS_PATH: &str = "/var/lib/containers";
+
+// seconds a finished pod is kept before prune pods removes it, from a job's ttlSecondsAfterFinished or the cluster default
+pub const TTL_LABEL: &str = "skate.io/ttl-seconds-after-finished";
+
+#[derive(Debug, Args)]
+pub struct PruneArgs {
+    #[command(subcommand)]
+    command: PruneCommands,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PruneCommands {
+    #[command(about = "remove images not referenced by any stored manifest or container")]
+    Images(PruneImagesArgs),
+    #[command(about = "remove finished pods whose ttl has passed")]
+    Pods(PrunePodsArgs),
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct PrunePodsArgs {
+    #[arg(long, long_help = "Print the pods that would be removed without removing them.")]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct PruneImagesArgs {
+    #[arg(long, default_value_t = 0, long_help = "Number of most recently created unreferenced images to keep.")]
+    pub keep_last: usize,
+    #[arg(long, long_help = "Only prune when free space for /var/lib/containers is below this many MiB.")]
+    pub min_free_disk_mib: Option<u64>,
+    #[arg(long, long_help = "Print the images that would be removed without removing them.")]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct PodmanImage {
+    pub id: String,
+    #[serde(default)]
+    pub names: Option<Vec<String>>,
+    #[serde(default)]
+    pub created: i64,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub containers: u64,
+}
+
+pub trait PruneDeps: With<dyn ShellExec> + With<dyn Store> {}
+
+pub struct Pruner<D: PruneDeps> {
+    pub deps: D,
+}
+
+impl<D: PruneDeps> Pruner<D> {
+    pub fn prune(&self, args: PruneArgs) -> Result<(), SkateError> {
+        match args.command {
+            PruneCommands::Images(args) => self.prune_images(args),
+            PruneCommands::Pods(args) => self.prune_pods(args),
+        }
+    }
+
+    fn prune_pods(&self, args: PrunePodsArgs) -> Result<(), SkateError> {
+        let execer = With::<dyn ShellExec>::get(&self.deps);
+        let output = execer.exec("podman", &["pod", "ps", "--filter", &format!("label={}", TTL_LABEL), "--format", "json"])?;
+        let pods: Vec<PodmanPodInfo> = match output.as_str() {
+            "" | "null" => vec!(),
+            _ => serde_json::from_str(&output).map_err(|e| anyhow!(e).context("failed to deserialize pod list"))?
+        };
+
+        let now = Local::now().timestamp();
+        let mut failures = vec!();
+        for pod in pods.iter().filter(|p| matches!(p.status, PodmanPodStatus::Exited | PodmanPodStatus::Dead)) {
+            let ttl = match pod.labels.get(TTL_LABEL).and_then(|t| t.parse::<i64>().ok()) {
+                Some(ttl) => ttl,
+                None => {
+                    warn!("pod {} has an invalid {} label", pod.name, TTL_LABEL);
+                    continue;
+                }
+            };
+            let ids: Vec<_> = pod.app_containers().iter().map(|c| c.id.as_str()).collect();
+            if ids.is_empty() {
+                continue;
+            }
+            let finished_at = execer.exec("podman", &[vec!["container", "inspect", "--format", "{{.State.FinishedAt.Unix}}"], ids].concat())?;
+            let finished_at = match finished_at.lines().map(|l| l.trim().parse::<i64>()).collect::<Result<Vec<_>, _>>() {
+                Ok(times) => times.into_iter().max(),
+                Err(e) => {
+                    warn!("failed to read when pod {} finished: {}", pod.name, e);
+                    continue;
+                }
+            };
+            if !finished_at.is_some_and(|f| expired(f, ttl, now)) {
+                continue;
+            }
+
+            if args.dry_run {
+                println!("would remove pod {}", pod.name);
+                continue;
+            }
+            match PodController::new(With::<dyn ShellExec>::get(&self.deps)).delete_podman_pods(vec![&pod.id], Some(0)) {
+                Ok(_) => println!("removed pod {}", pod.name),
+                Err(e) => {
+                    warn!("failed to remove pod {}: {}", pod.name, e);
+                    failures.push(format!("{}: {}", pod.name, e));
+                }
+            }
+        }
+
+        if !failures.is_empty() {
+            return Err(anyhow!("failed to remove pods: {}", failures.join(", ")).into());
+        }
+        Ok(())
+    }
+
+    fn prune_images(&self, args: PruneImagesArgs) -> Result<(), SkateError> {
+        if let Some(min_free) = args.min_free_disk_mib {
+            match available_space_mib(CONTAINERS_PATH) {
+                Some(available) if available >= min_free => {
+                    info!("{} MiB free on {} (threshold {} MiB), skipping image prune", available, CONTAINERS_PATH, min_free);
+                    return Ok(());
+                }
+                Some(available) => info!("{} MiB free on {} is below {} MiB, pruning images", available, CONTAINERS_PATH, min_free),
+                None => warn!("failed to determine free space for {}, pruning anyway", CONTAINERS_PATH),
+            }
+        }
+
+        let execer = With::<dyn ShellExec>::get(&self.deps);
+
+        let referenced = self.referenced_images()?;
+
+        let output = execer.exec("podman", &["images", "--format", "json"])?;
+        let images: Vec<PodmanImage> = match output.as_str() {
+            "" | "null" => vec!(),
+            _ => serde_json::from_str(&output).map_err(|e| anyhow!(e).context("failed to deserialize image list"))?
+        };
+
+        let to_remove = images_to_remove(images, &referenced, args.keep_last);
+
+        if to_remove.is_empty() {
+            info!("no images to prune");
+            return Ok(());
+        }
+
+        let mut failures = vec!();
+        for image in to_remove {
+            let display = image.names.as_ref().and_then(|n| n.first().cloned()).unwrap_or(image.id.clone());
+            if args.dry_run {
+                println!("would remove image {} ({} MiB)", display, image.size / BYTES_IN_MIB);
+                continue;
+            }
+            info!("removing image {}", display);
+            match execer.exec("podman", &["rmi", &image.id]) {
+                Ok(_) => println!("removed image {} ({} MiB)", display, image.size / BYTES_IN_MIB),
+                Err(e) => {
+                    warn!("failed to remove image {}: {}", display, e);
+                    failures.push(format!("{}: {}", display, e));
+                }
+            }
+        }
+
+        if !failures.is_empty() {
+            return Err(anyhow!("failed to remove images: {}", failures.join(", ")).into());
+        }
+
+        Ok(())
+    }
+
+    // returns the normalized image references of all containers in manifests held in the store
+    fn referenced_images(&self) -> Result<HashSet<String>, SkateError> {
+        let store = With::<dyn Store>::get(&self.deps);
+        let mut specs: Vec<PodSpec> = vec!();
+
+        for item in store.list_objects("deployment")? {
+            if let Some(spec) = item.manifest.and_then(|m| serde_yaml::from_value::<Deployment>(m).ok()).and_then(|d| d.spec?.template.spec) {
+                specs.push(spec)
+            }
+        }
+        for item in store.list_objects("daemonset")? {
+            if let Some(spec) = item.manifest.and_then(|m| serde_yaml::from_value::<DaemonSet>(m).ok()).and_then(|d| d.spec?.template.spec) {
+                specs.push(spec)
+            }
+        }
+        for item in store.list_objects("cronjob")? {
+            if let Some(spec) = item.manifest.and_then(|m| serde_yaml::from_value::<CronJob>(m).ok()).and_then(|c| c.spec?.job_template.spec?.template.spec) {
+                specs.push(spec)
+            }
+        }
+
+        Ok(specs.iter().flat_map(|s| {
+            s.containers.iter().chain(s.init_containers.iter().flatten()).filter_map(|c| c.image.as_deref().map(normalize_image_ref))
+        }).collect())
+    }
+}
+
+// images in use by a container or referenced by a manifest are always kept, as are the `keep_last` most recent of the rest
+fn images_to_remove(images: Vec<PodmanImage>, referenced: &HashSet<String>, keep_last: usize) -> Vec<PodmanImage> {
+    let mut candidates: Vec<_> = images.into_iter().filter(|i| {
+        i.containers == 0 && !i.names.iter().flatten().any(|n| referenced.contains(&normalize_image_ref(n)))
+    }).collect();
+
+    candidates.sort_by_key(|i| std::cmp::Reverse(i.created));
+    candidates.into_iter().skip(keep_last).collect()
+}
+
+fn expired(finished_at: i64, ttl: i64, now: i64) -> bool {
+    now - finished_at >= ttl
+}
+
+fn available_space_mib(path: &str) -> Option<u64> {
+    disk_info_for_path(&Disks::new_with_refreshed_list(), path).map(|d| d.available_space_mib)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::image::normalize_image_ref;
+    use crate::skatelet::prune::{expired, images_to_remove, PodmanImage};
+
+    #[test]
+    fn test_images_to_remove() {
+        let image = |id: &str, name: &str, created: i64, containers: u64| PodmanImage {
+            id: id.to_string(),
+            names: Some(vec![name.to_string()]),
+            created,
+            size: 0,
+            containers,
+        };
+
+        let images = vec![
+            image("1", "docker.io/library/nginx:1.0", 1, 0),
+            image("2", "docker.io/library/nginx:1.1", 2, 0),
+            image("3", "docker.io/library/nginx:1.2", 3, 0),
+            image("4", "docker.io/library/redis:latest", 4, 1),
+            image("5", "docker.io/library/postgres:16", 5, 0),
+        ];
+        let referenced = HashSet::from([normalize_image_ref("postgres:16")]);
+
+        let removed: Vec<_> = images_to_remove(images.clone(), &referenced, 0).into_iter().map(|i| i.id).collect();
+        assert_eq!(removed, vec!["3", "2", "1"]);
+
+        let removed: Vec<_> = images_to_remove(images, &referenced, 2).into_iter().map(|i| i.id).collect();
+        assert_eq!(removed, vec!["1"]);
+    }
+
+    #[test]
+    fn test_expired() {
+        assert!(!expired(1000, 60, 1059));
+        assert!(expired(1000, 60, 1060));
+        assert!(expired(1000, 0, 1000));
+    }
+}
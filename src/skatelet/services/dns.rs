@@ -13,13 +13,13 @@ use crate::util::{lock_file, spawn_orphan_process};
 
 pub struct DnsService<'a> {
     conf_path: String,
-    execer: &'a Box<dyn ShellExec>,
+    execer: &'a dyn ShellExec,
 }
 
 
 impl<'a> DnsService<'a>{
 
-    pub fn new(conf_path: &str, execer: &'a Box<dyn ShellExec>) -> Self{
+    pub fn new(conf_path: &str, execer: &'a dyn ShellExec) -> Self{
         DnsService{
             conf_path: conf_path.to_string(),
             execer,
@@ -137,25 +137,24 @@ impl<'a> DnsService<'a>{
         let labels = json["Labels"].as_object().unwrap();
         let ns = labels["skate.io/namespace"].as_str().ok_or_else(|| anyhow!("missing skate.io/namespace label"))?;
 
-        // only add for daemonsets or deployments
-        let parent_resource = {
+        // daemonsets and deployments resolve by their own name, other pods by the pod's name
+        let name_label = {
             if labels.contains_key("skate.io/daemonset") {
-                Some("daemonset")
+                "skate.io/daemonset"
             } else if labels.contains_key("skate.io/deployment") {
-                Some("deployment")
+                "skate.io/deployment"
             } else {
-                None
+                "skate.io/name"
             }
         };
 
-        if parent_resource.is_none() {
-            info!("not a daemonset or deployment, skipping");
-            return Ok(());
-        }
-
-        let parent_identifier_label = format!("skate.io/{}", parent_resource.unwrap());
-
-        let app = labels.get(&parent_identifier_label).unwrap().as_str().unwrap();
+        let app = match labels.get(name_label).and_then(|l| l.as_str()) {
+            Some(app) => app,
+            None => {
+                info!("{} no {} label, skipping", log_tag, name_label);
+                return Ok(());
+            }
+        };
 
         let domain = format!("{}.{}.pod.cluster.skate", app, ns);
         let addnhosts_path = Path::new(&self.conf_path).join("addnhosts");
@@ -235,7 +234,7 @@ impl<'a> DnsService<'a>{
                 c["State"]["Health"]["Status"].as_str().unwrap()
             ).collect();
 
-            if containers.iter().any(|c| *c == "unhealthy") {
+            if containers.contains(&"unhealthy") {
                 debug!("{} at least one container unhealthy",log_tag);
                 // do nothing
                 return Ok(());
@@ -301,11 +300,11 @@ impl<'a> DnsService<'a>{
     // remove prints the ip of any dns entry that the container or pod had
     pub fn remove(&self, container_id: Option<String>, pod_id: Option<String>) -> Result<(), SkateError> {
         let tag = {
-            if container_id.is_some() {
-               container_id.unwrap()
-            } else if pod_id.is_some() {
+            if let Some(container_id) = container_id {
+               container_id
+            } else if let Some(pod_id) = pod_id {
                 // get infra container
-                let output = self.execer.exec("podman", &["pod", "inspect", &pod_id.unwrap()])?;
+                let output = self.execer.exec("podman", &["pod", "inspect", &pod_id])?;
                 let json: serde_json::Value = serde_json::from_str(&output).map_err(|e| anyhow!("failed to parse podman inspect output: {}", e))?;
                 let infra_container_id = json["InfraContainerID"].as_str().ok_or_else(|| anyhow!("no infra container found"))?;
                 infra_container_id.to_string()
@@ -362,6 +361,29 @@ impl<'a> DnsService<'a>{
         }))
     }
 
+    // replaces the entries for the cluster's nodes, so that <node>.node.cluster.skate resolves to the node's peer host
+    pub fn set_nodes(&self, nodes: Vec<(String, String)>) -> Result<(), SkateError> {
+        self.ensure_skatelet_dns_conf_dir();
+        let log_tag = "set_nodes";
+        info!("{} setting dns entries for {} nodes", log_tag, nodes.len());
+
+        let addnhosts_path = Path::new(&self.conf_path).join("addnhosts");
+        let newaddnhosts_path = Path::new(&self.conf_path).join("addnhosts-new");
+
+        self.lock(Box::new(move || {
+            let existing = fs::read_to_string(&addnhosts_path).unwrap_or_default();
+            let updated = replace_node_entries(&existing, &nodes);
+            if updated == existing {
+                debug!("{} node entries unchanged", log_tag);
+                return Ok(());
+            }
+            fs::write(&newaddnhosts_path, updated)?;
+            debug!("{} replacing hosts file", log_tag);
+            fs::rename(&newaddnhosts_path, &addnhosts_path)?;
+            Ok(())
+        }))
+    }
+
     pub fn reload(&self) -> Result<(), SkateError> {
         let id = self.execer.exec("podman", &["ps", "--filter", "label=skate.io/namespace=skate", "--filter", "label=skate.io/daemonset=coredns", "-q"])?;
 
@@ -373,4 +395,34 @@ impl<'a> DnsService<'a>{
         let _ = self.execer.exec("podman", &["kill", "--signal", "HUP", &id])?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+const NODE_TAG_PREFIX: &str = "node/";
+
+// the hosts file with the node entries swapped for the given (name, ip) pairs, other entries are kept as they are
+fn replace_node_entries(hosts: &str, nodes: &[(String, String)]) -> String {
+    let mut lines: Vec<String> = hosts.lines()
+        .filter(|l| !l.rsplit_once("# ").is_some_and(|(_, tag)| tag.starts_with(NODE_TAG_PREFIX)))
+        .map(|l| l.to_string())
+        .collect();
+    lines.extend(nodes.iter().map(|(name, ip)| format!("{} {}.node.cluster.skate # {}{}", ip, name, NODE_TAG_PREFIX, name)));
+    lines.iter().map(|l| format!("{}\n", l)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::skatelet::services::dns::replace_node_entries;
+
+    #[test]
+    fn test_replace_node_entries() {
+        let hosts = "10.30.0.2 nginx.web.pod.cluster.skate # abc123\n192.168.1.5 old.node.cluster.skate # node/old\n";
+        let nodes = vec![("node-1".to_string(), "192.168.1.10".to_string()), ("node-2".to_string(), "192.168.1.11".to_string())];
+
+        let updated = replace_node_entries(hosts, &nodes);
+        assert_eq!(updated, "10.30.0.2 nginx.web.pod.cluster.skate # abc123\n\
+192.168.1.10 node-1.node.cluster.skate # node/node-1\n\
+192.168.1.11 node-2.node.cluster.skate # node/node-2\n");
+
+        assert_eq!(updated, replace_node_entries(&updated, &nodes));
+        assert_eq!("", replace_node_entries("", &[]));
+    }
+}
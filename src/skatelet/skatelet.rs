@@ -3,15 +3,21 @@ use crate::skatelet::apply;
 use crate::skatelet::apply::{ApplyArgs, ApplyDeps};
 use crate::skatelet::cordon::{cordon, uncordon, CordonArgs, UncordonArgs};
 use crate::skatelet::create::{create, CreateArgs, CreateDeps};
+#[cfg(target_os = "linux")]
+use crate::skatelet::daemon::{Daemon, DaemonArgs, DaemonDeps};
 use crate::skatelet::delete::{DeleteArgs, DeleteDeps, Deleter};
 use crate::skatelet::dns::{Dns, DnsArgs, DnsDeps};
+use crate::skatelet::firewall::{Firewall, FirewallArgs, FirewallDeps};
 use crate::skatelet::ipvs::{IPVSDeps, IpvsArgs, IPVS};
+use crate::skatelet::lock::{lock, LockArgs};
+use crate::skatelet::logs::{Logs, LogsArgs, LogsDeps};
 use crate::skatelet::oci::{oci, OciArgs};
+use crate::skatelet::prune::{PruneArgs, PruneDeps, Pruner};
 use crate::skatelet::system::{system, SystemArgs, SystemDeps};
 use crate::skatelet::template::{template, TemplateArgs};
 use clap::{Parser, Subcommand};
 use log::{error, LevelFilter};
-use std::panic::PanicInfo;
+use std::panic::PanicHookInfo;
 use std::{process, thread};
 use anyhow::anyhow;
 use strum_macros::IntoStaticStr;
@@ -20,6 +26,8 @@ use crate::deps::{Deps};
 use crate::errors::SkateError;
 
 pub const VAR_PATH: &str = "/var/lib/skate";
+// where the daemon serves /healthz
+pub const HEALTHZ_SOCKET: &str = "/run/skatelet/healthz.sock";
 
 #[derive(Debug, Parser)]
 #[command(name = "skatelet")]
@@ -42,9 +50,15 @@ enum Commands {
     Create(CreateArgs),
     Cordon(CordonArgs),
     Uncordon(UncordonArgs),
+    Prune(PruneArgs),
+    Firewall(FirewallArgs),
+    Lock(LockArgs),
+    Logs(LogsArgs),
+    #[cfg(target_os = "linux")]
+    Daemon(DaemonArgs),
 }
 
-pub fn log_panic(info: &PanicInfo) {
+pub fn log_panic(info: &PanicHookInfo) {
 
     let thread = thread::current();
     let thread = thread.name().unwrap_or("<unnamed>");
@@ -82,6 +96,11 @@ impl CreateDeps for Deps{}
 impl DeleteDeps for Deps{}
 impl DnsDeps for Deps{}
 impl IPVSDeps for Deps{}
+impl PruneDeps for Deps{}
+impl FirewallDeps for Deps{}
+impl LogsDeps for Deps{}
+#[cfg(target_os = "linux")]
+impl DaemonDeps for Deps{}
 
 pub async fn skatelet() -> Result<(), SkateError> {
 
@@ -129,6 +148,24 @@ pub async fn skatelet() -> Result<(), SkateError> {
         Commands::Create(args) => create(deps, args),
         Commands::Cordon(args) => cordon(args),
         Commands::Uncordon(args) => uncordon(args),
+        Commands::Prune(args) => {
+            let pruner = Pruner{deps};
+            pruner.prune(args)
+        },
+        Commands::Firewall(args) => {
+            let firewall = Firewall{deps};
+            firewall.firewall(args)
+        },
+        Commands::Lock(args) => lock(args),
+        Commands::Logs(args) => {
+            let logs = Logs{deps};
+            logs.logs(args)
+        },
+        #[cfg(target_os = "linux")]
+        Commands::Daemon(args) => {
+            let daemon = Daemon{deps};
+            daemon.daemon(args)
+        },
         // _ => Ok(())
     };
     match result {
@@ -1,27 +1,36 @@
 pub(crate) mod podman;
 
+use std::collections::{BTreeMap, HashMap};
 use std::env::consts::ARCH;
-use sysinfo::{CpuRefreshKind, DiskKind, Disks, MemoryRefreshKind, RefreshKind, System};
+use sysinfo::{CpuRefreshKind, Disk, DiskKind, Disks, MemoryRefreshKind, RefreshKind, System};
+use std::fs;
+use std::path::Path;
 use std::error::Error;
 
 
 use anyhow::anyhow;
+use itertools::Itertools;
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
 use clap::{Args, Subcommand};
 
 use k8s_openapi::api::core::v1::Secret;
 use log::error;
 use serde::{Deserialize, Serialize};
 
-use podman::PodmanPodInfo;
+use podman::{PodmanPodInfo, PodmanPodStatus};
 use crate::deps::With;
 use crate::errors::SkateError;
 use crate::exec::ShellExec;
+use crate::gpu::{detect_gpus, GPU_RESOURCE};
+use crate::ingress_class::node_classes;
 use crate::filestore::{FileStore, ObjectListItem, Store};
 use crate::resource::ResourceType;
 use crate::skate::{Distribution, Platform};
 use crate::skatelet::cordon::is_cordoned;
+use crate::skatelet::oci::read_container_events;
+use crate::skatelet::skatelet::VAR_PATH;
 use crate::skatelet::system::podman::PodmanSecret;
-use crate::util::NamespacedName;
+use crate::util::{hash_string, NamespacedName};
 
 
 #[derive(Debug, Args)]
@@ -35,13 +44,16 @@ pub struct SystemArgs {
 pub enum SystemCommands {
     #[command(about = "report system information")]
     Info,
+    #[command(about = "report a fingerprint of the node's skate state, which changes whenever system info would")]
+    Generation,
 }
 
 pub trait SystemDeps: With<dyn ShellExec>{}
 
 pub async fn system<D: SystemDeps>(deps: D, args: SystemArgs) -> Result<(), SkateError> {
     match args.command {
-        SystemCommands::Info => info(With::<dyn ShellExec>::get(&deps)).await?
+        SystemCommands::Info => info(With::<dyn ShellExec>::get(&deps)).await?,
+        SystemCommands::Generation => println!("{}", state_generation(With::<dyn ShellExec>::get(&deps).as_ref(), &FileStore::new())?),
     }
     Ok(())
 }
@@ -62,12 +74,24 @@ pub struct SystemInfo {
     pub used_swap_mib: u64,
     pub num_cpus: usize,
     pub root_disk: Option<DiskInfo>,
+    // filesystem holding /var/lib/containers
+    #[serde(default)]
+    pub containers_disk: Option<DiskInfo>,
+    // filesystem holding /var/lib/skate
+    #[serde(default)]
+    pub skate_disk: Option<DiskInfo>,
     pub pods: Option<Vec<PodmanPodInfo>>,
     pub ingresses: Option<Vec<ObjectListItem>>,
     pub cronjobs: Option<Vec<ObjectListItem>>,
+    // the systemd timer state of each cronjob, missing when reported by older versions
+    #[serde(default)]
+    pub cronjob_statuses: Option<Vec<CronjobStatus>>,
     pub secrets: Option<Vec<ObjectListItem>>,
     pub services: Option<Vec<ObjectListItem>>,
     pub cluster_issuers: Option<Vec<ObjectListItem>>,
+    // missing when reported by older versions
+    #[serde(default)]
+    pub pod_disruption_budgets: Option<Vec<ObjectListItem>>,
     pub deployments: Option<Vec<ObjectListItem>>,
     pub daemonsets: Option<Vec<ObjectListItem>>,
     pub cpu_freq_mhz: u64,
@@ -78,6 +102,165 @@ pub struct SystemInfo {
     pub hostname: String,
     #[serde(default)]
     pub cordoned: bool,
+    // the state generation when the info was collected, lets skate skip collecting it again while it's unchanged
+    #[serde(default)]
+    pub generation: Option<String>,
+    // the pods' usage over a short sampling window, missing when reported by older versions
+    #[serde(default)]
+    pub pod_stats: Option<Vec<PodStats>>,
+    // the latest container starts and exits seen by the oci hooks, oldest first
+    #[serde(default)]
+    pub container_events: Option<Vec<ContainerEvent>>,
+    // countable resources beyond cpu and memory, eg skate.io/gpu
+    #[serde(default)]
+    pub resources: Option<BTreeMap<String, usize>>,
+    // the certificates the ingress proxies got from letsencrypt, missing when reported by older versions
+    #[serde(default)]
+    pub ingress_certificates: Option<Vec<IngressCertificate>>,
+}
+
+impl SystemInfo {
+    pub fn pod_stats(&self, pod_id: &str) -> Option<&PodStats> {
+        self.pod_stats.iter().flatten().find(|s| s.pod_id == pod_id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PodStats {
+    pub pod_id: String,
+    // of a single cpu, so a pod busy on two cpus reports 200
+    pub cpu_percent: f64,
+    pub memory_bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ContainerStage {
+    Started,
+    Stopped,
+}
+
+// written by the oci hooks as podman starts and stops a container, so exits are known without waiting for a poll
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerEvent {
+    pub time: DateTime<Local>,
+    pub container_id: String,
+    pub stage: ContainerStage,
+    // of a stop, as podman had it when the hook ran
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressCertificate {
+    pub domain: String,
+    pub expires_at: DateTime<Local>,
+}
+
+// the file storage of lua-resty-auto-ssl, mounted into each ingress proxy. a domain's current certificate is kept as json
+// under the uri escaped key <domain>:latest
+const CERTIFICATE_STORAGE: &str = "letsencrypt_storage/file";
+const LATEST_CERTIFICATE_SUFFIX: &str = "%3Alatest";
+
+fn parse_certificate(file_name: &str, contents: &str) -> Option<IngressCertificate> {
+    let domain = file_name.strip_suffix(LATEST_CERTIFICATE_SUFFIX)?;
+    let cert: serde_json::Value = serde_json::from_str(contents).ok()?;
+    let expires_at = Local.timestamp_opt(cert["expiry"].as_i64()?, 0).single()?;
+    Some(IngressCertificate { domain: domain.to_string(), expires_at })
+}
+
+fn ingress_certificates() -> Vec<IngressCertificate> {
+    node_classes().iter().flat_map(|class| {
+        let dir = Path::new(&class.dir()).join(CERTIFICATE_STORAGE);
+        fs::read_dir(dir).map(|d| d.filter_map(|e| e.ok()).collect::<Vec<_>>()).unwrap_or_default()
+    }).filter_map(|entry| {
+        parse_certificate(&entry.file_name().to_string_lossy(), &fs::read_to_string(entry.path()).ok()?)
+    }).sorted_by(|a, b| a.domain.cmp(&b.domain)).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CronjobStatus {
+    pub name: NamespacedName,
+    // whether a job is currently running
+    pub active: bool,
+    pub last_schedule: Option<DateTime<Local>>,
+    pub next_schedule: Option<DateTime<Local>>,
+}
+
+// parses the output of `systemctl show` for several units into the properties of each, by unit name
+fn parse_systemctl_show(output: &str) -> HashMap<String, HashMap<String, String>> {
+    output.split("\n\n").filter_map(|block| {
+        let props: HashMap<_, _> = block.lines()
+            .filter_map(|l| l.split_once('='))
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        props.get("Id").cloned().map(|id| (id, props))
+    }).collect()
+}
+
+// systemd prints timestamps in the host's local time, eg `Mon 2024-01-01 10:00:00 UTC`, and an empty value when there is none
+fn parse_systemd_timestamp(value: &str) -> Option<DateTime<Local>> {
+    let without_zone = value.rsplit_once(' ').map(|(t, _)| t)?;
+    let naive = NaiveDateTime::parse_from_str(without_zone, "%a %Y-%m-%d %H:%M:%S").ok()?;
+    Local.from_local_datetime(&naive).single()
+}
+
+fn cronjob_statuses(execer: &dyn ShellExec, cronjobs: &[ObjectListItem]) -> Result<Vec<CronjobStatus>, Box<dyn Error>> {
+    if cronjobs.is_empty() {
+        return Ok(vec![]);
+    }
+    let units: Vec<_> = cronjobs.iter().flat_map(|c| {
+        let unit = format!("skate-cronjob-{}", c.name);
+        [format!("{}.timer", unit), format!("{}.service", unit)]
+    }).collect();
+
+    let args = [vec!["show", "--property=Id,ActiveState,LastTriggerUSec,NextElapseUSecRealtime"], units.iter().map(|u| u.as_str()).collect()].concat();
+    let output = execer.exec("systemctl", &args)?;
+    let units = parse_systemctl_show(&output);
+
+    Ok(cronjobs.iter().map(|c| {
+        let unit = format!("skate-cronjob-{}", c.name);
+        let timer = units.get(&format!("{}.timer", unit));
+        let service = units.get(&format!("{}.service", unit));
+        let timestamp = |prop: &str| timer.and_then(|t| t.get(prop)).and_then(|v| parse_systemd_timestamp(v));
+
+        CronjobStatus {
+            name: c.name.clone(),
+            active: service.and_then(|s| s.get("ActiveState")).is_some_and(|s| s == "activating" || s == "active"),
+            last_schedule: timestamp("LastTriggerUSec"),
+            next_schedule: timestamp("NextElapseUSecRealtime"),
+        }
+    }).collect())
+}
+
+// the resource types kept in the node's file store
+const STORED_TYPES: [&str; 7] = ["ingress", "cronjob", "service", "clusterissuer", "poddisruptionbudget", "deployment", "daemonset"];
+
+// hashes what the generation is made of, the objects in the store, the pods' container states, the secrets, the latest
+// container event and the cordon flag. pods and secrets are the raw output of podman ps and podman secret ls. the event
+// changes it as soon as a hook runs, even for a container that's back to how podman ps last showed it
+fn generation_of(objects: &[ObjectListItem], pods: &str, secrets: &str, last_event: Option<&ContainerEvent>, cordoned: bool) -> String {
+    let objects: Vec<_> = objects.iter()
+        .map(|o| format!("{} {} {} {}", o.resource_type, o.name, o.manifest_hash, o.updated_at.timestamp_nanos_opt().unwrap_or_default()))
+        .sorted()
+        .collect();
+    let pods: Vec<_> = pods.lines().sorted().collect();
+    let secrets: Vec<_> = secrets.lines().sorted().collect();
+    let last_event = last_event.map(|e| format!("{} {} {:?}", e.time.timestamp_nanos_opt().unwrap_or_default(), e.container_id, e.stage));
+    hash_string((objects, pods, secrets, last_event, cordoned))
+}
+
+// state_generation is much cheaper than info, it doesn't inspect containers, read secrets or measure the system.
+// uptime, memory and disk usage aren't part of it, so skate still collects the full info every so often
+pub(crate) fn state_generation(execer: &dyn ShellExec, store: &dyn Store) -> Result<String, Box<dyn Error>> {
+    let mut objects = vec![];
+    for object_type in STORED_TYPES {
+        objects.extend(store.list_objects(object_type)?);
+    }
+    let pods = execer.exec("sudo", &["podman", "ps", "-a", "--filter", "label=skate.io/namespace", "--format", "{{.ID}} {{.State}} {{.ExitCode}} {{.Restarts}}"])?;
+    let secrets = execer.exec("podman", &["secret", "ls", "--noheading"])?;
+    Ok(generation_of(&objects, &pods, &secrets, read_container_events().last(), is_cordoned()))
 }
 
 // TODO - have more generic ObjectMeta type for explaining existing resources
@@ -124,9 +307,142 @@ fn internal_ip(execer: Box<dyn ShellExec>) -> Result<Option<String>, Box<dyn Err
 
 const BYTES_IN_MIB: u64 = (2u64).pow(20);
 
+impl From<&Disk> for DiskInfo {
+    fn from(d: &Disk) -> Self {
+        DiskInfo {
+            available_space_mib: d.available_space() / BYTES_IN_MIB,
+            total_space_mib: d.total_space() / BYTES_IN_MIB,
+            disk_kind: match d.kind() {
+                DiskKind::HDD => "hdd",
+                DiskKind::SSD => "sdd",
+                DiskKind::Unknown(_) => "unknown"
+            }.to_string(),
+        }
+    }
+}
+
+// disk_info_for_path returns info for the filesystem that the path lives on, ie the disk with the longest mount point containing it
+pub fn disk_info_for_path(disks: &Disks, path: &str) -> Option<DiskInfo> {
+    let path = Path::new(path);
+    disks.iter()
+        .filter(|d| path.starts_with(d.mount_point()))
+        .max_by_key(|d| d.mount_point().as_os_str().len())
+        .map(DiskInfo::from)
+}
+
+// fills in the exit codes, oom kills, healthcheck results and limits that `podman pod ps` doesn't report
+fn inspect_container_states(execer: &dyn ShellExec, pods: &mut [PodmanPodInfo]) -> Result<(), Box<dyn Error>> {
+    let ids: Vec<String> = pods.iter().flat_map(|p| p.app_containers()).map(|c| c.id.clone()).collect();
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    let args = [vec!["podman", "inspect", "--format", "json"], ids.iter().map(|i| i.as_str()).collect()].concat();
+    let output = execer.exec("sudo", &args)?;
+    let json: serde_json::Value = serde_json::from_str(&output).map_err(|e| anyhow!(e).context("failed to parse podman inspect output"))?;
+
+    let states: HashMap<&str, &serde_json::Value> = json.as_array().into_iter().flatten()
+        .filter_map(|c| Some((c["Id"].as_str()?, c)))
+        .collect();
+
+    for container in pods.iter_mut().flat_map(|p| p.containers.iter_mut().flatten()) {
+        // pod ps reports shortened ids
+        let inspected = states.iter().find(|(id, _)| id.starts_with(&container.id)).map(|(_, s)| *s);
+        if let Some(inspected) = inspected {
+            let state = &inspected["State"];
+            let host_config = &inspected["HostConfig"];
+            container.cpu_limit = host_config["NanoCpus"].as_u64().filter(|n| *n > 0).map(|n| n as f64 / 1e9);
+            container.memory_limit = host_config["Memory"].as_u64().filter(|m| *m > 0);
+            container.exit_code = state["ExitCode"].as_i64().map(|c| c as i32);
+            container.oom_killed = state["OOMKilled"].as_bool();
+            container.health = state["Health"]["Status"].as_str()
+                .or_else(|| state["Healthcheck"]["Status"].as_str())
+                .map(|s| s.to_string());
+        }
+    }
+    Ok(())
+}
+
+// the last sample taken, the next info's cpu rates are measured against it rather than sleeping between two samples
+const STATS_SAMPLE_FILE: &str = "stats-sample.json";
+
+// a container's cumulative cpu time, the time it was sampled at and its current memory usage
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ContainerSample {
+    #[serde(rename = "ContainerID")]
+    id: String,
+    #[serde(rename = "CPUNano")]
+    cpu_nano: u64,
+    #[serde(rename = "SystemNano")]
+    system_nano: u64,
+    #[serde(rename = "MemUsage")]
+    memory_bytes: u64,
+    // podman's own rate, since the container started when it has nothing to compare against
+    #[serde(rename = "CPU", default)]
+    cpu_percent: f64,
+}
+
+// parses `podman stats --no-stream --no-trunc --format '{{json .}}'` output, a json object per container, by container id
+fn parse_container_samples(output: &str) -> HashMap<String, ContainerSample> {
+    output.lines().filter_map(|l| serde_json::from_str::<ContainerSample>(l).ok())
+        .map(|s| (s.id.clone(), s))
+        .collect()
+}
+
+// `--format json` only has the sizes and times formatted for people, each row as json has the raw counters
+fn sample_containers(execer: &dyn ShellExec) -> Result<HashMap<String, ContainerSample>, Box<dyn Error>> {
+    let output = execer.exec("sudo", &["podman", "stats", "--no-stream", "--no-trunc", "--format", "{{json .}}"])?;
+    Ok(parse_container_samples(&output))
+}
+
+// sums the usage of each pod's app containers since the previous sample. a single `podman stats --no-stream` only
+// reports the cpu used since the container started, so the rate comes from the difference between the two, or is
+// podman's when the container wasn't in the previous sample
+fn pod_stats(pods: &[PodmanPodInfo], previous: &HashMap<String, ContainerSample>, current: &HashMap<String, ContainerSample>) -> Vec<PodStats> {
+    pods.iter().filter_map(|pod| {
+        // pod ps reports shortened ids
+        let find = |samples: &HashMap<String, ContainerSample>, id: &str| samples.iter().find(|(s, _)| s.starts_with(id)).map(|(_, s)| s.clone());
+        let samples: Vec<_> = pod.app_containers().into_iter().filter_map(|c| {
+            Some((find(previous, &c.id), find(current, &c.id)?))
+        }).collect();
+        if samples.is_empty() {
+            return None;
+        }
+        let cpu_percent = samples.iter().map(|(a, b)| match a {
+            Some(a) if b.system_nano > a.system_nano && b.cpu_nano >= a.cpu_nano =>
+                (b.cpu_nano - a.cpu_nano) as f64 / (b.system_nano - a.system_nano) as f64 * 100.0,
+            _ => b.cpu_percent,
+        }).sum();
+        Some(PodStats {
+            pod_id: pod.id.clone(),
+            cpu_percent,
+            memory_bytes: samples.iter().map(|(_, b)| b.memory_bytes).sum(),
+        })
+    }).collect()
+}
+
+fn sample_pod_stats(execer: &dyn ShellExec, pods: &[PodmanPodInfo]) -> Result<Vec<PodStats>, Box<dyn Error>> {
+    // stats fail when no containers are running
+    if !pods.iter().any(|p| p.status == PodmanPodStatus::Running) {
+        return Ok(vec![]);
+    }
+    let path = Path::new(VAR_PATH).join(STATS_SAMPLE_FILE);
+    let previous: HashMap<String, ContainerSample> = fs::read_to_string(&path).ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default();
+    let current = sample_containers(execer)?;
+    if let Err(e) = fs::write(&path, serde_json::to_string(&current)?) {
+        error!("failed to save the stats sample to {}: {}", path.display(), e);
+    }
+    Ok(pod_stats(pods, &previous, &current))
+}
+
 async fn info(execer: Box<dyn ShellExec>) -> Result<(), Box<dyn Error>> {
-    
-    
+    // taken first, so that anything changing while the info is collected shows up as a new generation next time
+    let generation = state_generation(execer.as_ref(), &FileStore::new()).map_err(|e| {
+        eprintln!("failed to get state generation: {}", e);
+    }).ok();
+
     let sys = System::new_with_specifics(RefreshKind::new()
         .with_cpu(CpuRefreshKind::everything())
         .with_memory(MemoryRefreshKind::everything())
@@ -147,14 +463,29 @@ async fn info(execer: Box<dyn ShellExec>) -> Result<(), Box<dyn Error>> {
         }
     };
 
-    let podman_pod_info: Vec<PodmanPodInfo> = serde_json::from_str(&pod_list_result).map_err(|e| anyhow!(e).context("failed to deserialize pod info"))?;
+    let mut podman_pod_info: Vec<PodmanPodInfo> = serde_json::from_str(&pod_list_result).map_err(|e| anyhow!(e).context("failed to deserialize pod info"))?;
+    if let Err(err) = inspect_container_states(execer.as_ref(), &mut podman_pod_info) {
+        eprintln!("failed to inspect containers: {}", err);
+    }
+    for pod in podman_pod_info.iter_mut() {
+        pod.phase = Some(pod.compute_phase());
+    }
+    let pod_stats = sample_pod_stats(execer.as_ref(), &podman_pod_info).unwrap_or_else(|e| {
+        eprintln!("failed to sample pod stats: {}", e);
+        vec![]
+    });
 
 
     let store = FileStore::new();
     let ingresses = store.list_objects("ingress")?;
     let cronjobs = store.list_objects("cronjob")?;
+    let cronjob_statuses = cronjob_statuses(execer.as_ref(), &cronjobs).unwrap_or_else(|e| {
+        eprintln!("failed to get cronjob statuses: {}", e);
+        vec![]
+    });
     let services = store.list_objects("service")?;
     let cluster_issuers = store.list_objects("clusterissuer")?;
+    let pod_disruption_budgets = store.list_objects("poddisruptionbudget")?;
     let deployments = store.list_objects("deployment")?;
     let daemonsets = store.list_objects("daemonset")?;
 
@@ -222,17 +553,14 @@ async fn info(execer: Box<dyn ShellExec>) -> Result<(), Box<dyn Error>> {
     });
 
 
-    let root_disk = Disks::new_with_refreshed_list().iter().find(|d| d.mount_point().to_string_lossy() == "/").map(|d| DiskInfo {
-        available_space_mib: d.available_space() / BYTES_IN_MIB,
-        total_space_mib: d.total_space() / BYTES_IN_MIB,
-        disk_kind: match d.kind() {
-            DiskKind::HDD => "hdd",
-            DiskKind::SSD => "sdd",
-            DiskKind::Unknown(_) => "unknown"
-        }.to_string(),
-    });
+    let disks = Disks::new_with_refreshed_list();
+    let root_disk = disks.iter().find(|d| d.mount_point().to_string_lossy() == "/").map(DiskInfo::from);
+    let containers_disk = disk_info_for_path(&disks, "/var/lib/containers");
+    let skate_disk = disk_info_for_path(&disks, VAR_PATH);
 
 
+    let gpus = detect_gpus();
+    let ingress_certificates = ingress_certificates();
     let info = SystemInfo {
         platform: Platform {
             arch: ARCH.to_string(),
@@ -248,20 +576,143 @@ async fn info(execer: Box<dyn ShellExec>) -> Result<(), Box<dyn Error>> {
         cpu_brand: sys.global_cpu_info().brand().to_string(),
         cpu_vendor_id: sys.global_cpu_info().vendor_id().to_string(),
         root_disk,
+        containers_disk,
+        skate_disk,
         pods: Some(podman_pod_info),
         ingresses: (!ingresses.is_empty()).then_some(ingresses),
         cronjobs: (!cronjobs.is_empty()).then_some(cronjobs),
+        cronjob_statuses: (!cronjob_statuses.is_empty()).then_some(cronjob_statuses),
         secrets: (!secret_info.is_empty()).then_some(secret_info),
         services: (!services.is_empty()).then_some(services),
         cluster_issuers: (!cluster_issuers.is_empty()).then_some(cluster_issuers),
+        pod_disruption_budgets: (!pod_disruption_budgets.is_empty()).then_some(pod_disruption_budgets),
         deployments: (!deployments.is_empty()).then_some(deployments),
         daemonsets: (!daemonsets.is_empty()).then_some(daemonsets),
         hostname: System::host_name().unwrap_or("".to_string()),
         internal_ip_address: internal_ip_addr,
         cordoned: is_cordoned(),
+        generation,
+        pod_stats: Some(pod_stats),
+        container_events: Some(read_container_events()),
+        resources: (!gpus.is_empty()).then(|| BTreeMap::from([(GPU_RESOURCE.to_string(), gpus.len())])),
+        ingress_certificates: (!ingress_certificates.is_empty()).then_some(ingress_certificates),
     };
     let json = serde_json::to_string(&info)?;
     println!("{}", json);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use chrono::{Datelike, Local, Timelike};
+    use crate::filestore::ObjectListItem;
+    use crate::resource::ResourceType;
+    use crate::skatelet::system::{generation_of, parse_certificate, parse_container_samples, parse_systemctl_show, parse_systemd_timestamp, pod_stats, ContainerEvent, ContainerStage};
+    use crate::skatelet::system::podman::{PodmanContainerInfo, PodmanPodInfo, PodmanPodStatus};
+    use crate::util::NamespacedName;
+
+    #[test]
+    fn test_parse_certificate() {
+        let cert = parse_certificate("shop.example.com%3Alatest", r#"{"fullchain_pem": "", "privkey_pem": "", "expiry": 1704103200}"#).unwrap();
+        assert_eq!("shop.example.com", cert.domain);
+        assert_eq!(1704103200, cert.expires_at.timestamp());
+
+        assert!(parse_certificate("shop.example.com%3Alatest", "{}").is_none());
+        assert!(parse_certificate("shop.example.com%3Afailed", r#"{"expiry": 1704103200}"#).is_none());
+    }
+
+    #[test]
+    fn test_parse_systemctl_show() {
+        let output = "Id=skate-cronjob-backup.default.timer\nActiveState=active\nLastTriggerUSec=Mon 2024-01-01 10:00:00 UTC\nNextElapseUSecRealtime=Tue 2024-01-02 10:00:00 UTC\n\nId=skate-cronjob-backup.default.service\nActiveState=inactive\n";
+        let units = parse_systemctl_show(output);
+
+        assert_eq!(2, units.len());
+        assert_eq!("inactive", units["skate-cronjob-backup.default.service"]["ActiveState"]);
+
+        let last = parse_systemd_timestamp(&units["skate-cronjob-backup.default.timer"]["LastTriggerUSec"]).unwrap();
+        assert_eq!((2024, 1, 1, 10), (last.year(), last.month(), last.day(), last.hour()));
+        assert_eq!(None, parse_systemd_timestamp(""));
+        assert_eq!(None, parse_systemd_timestamp("n/a"));
+    }
+
+    #[test]
+    fn test_generation_of() {
+        let item = ObjectListItem {
+            resource_type: ResourceType::Deployment,
+            name: NamespacedName::new("foo", "default"),
+            manifest_hash: "abc".to_string(),
+            manifest: None,
+            updated_at: Local::now(),
+            created_at: Local::now(),
+            path: "".to_string(),
+        };
+        let pods = "1a2b running 0 0\n3c4d exited 1 2";
+        let generation = generation_of(std::slice::from_ref(&item), pods, "", None, false);
+
+        // order doesn't matter, content does
+        assert_eq!(generation, generation_of(std::slice::from_ref(&item), "3c4d exited 1 2\n1a2b running 0 0", "", None, false));
+        assert_ne!(generation, generation_of(std::slice::from_ref(&item), "1a2b running 0 0\n3c4d running 0 3", "", None, false));
+        assert_ne!(generation, generation_of(&[], pods, "", None, false));
+        assert_ne!(generation, generation_of(std::slice::from_ref(&item), pods, "", None, true));
+
+        let updated = ObjectListItem { manifest_hash: "def".to_string(), ..item.clone() };
+        assert_ne!(generation, generation_of(&[updated], pods, "", None, false));
+
+        let event = ContainerEvent { time: Local::now(), container_id: "1a2b".to_string(), stage: ContainerStage::Stopped, exit_code: Some(1) };
+        assert_ne!(generation, generation_of(std::slice::from_ref(&item), pods, "", Some(&event), false));
+    }
+
+    fn container(id: &str, names: &str) -> PodmanContainerInfo {
+        PodmanContainerInfo {
+            id: id.to_string(),
+            names: names.to_string(),
+            status: "running".to_string(),
+            restart_count: None,
+            exit_code: None,
+            health: None,
+            cpu_limit: None,
+            memory_limit: None,
+            oom_killed: None,
+        }
+    }
+
+    #[test]
+    fn test_pod_stats() {
+        let pod = PodmanPodInfo {
+            id: "pod1".to_string(),
+            name: "web".to_string(),
+            status: PodmanPodStatus::Running,
+            created: Local::now(),
+            labels: Default::default(),
+            containers: Some(vec![container("aaaa", "web-infra"), container("bbbb", "web-nginx"), container("cccc", "web-php")]),
+            phase: None,
+        };
+        let sample = |id: &str, cpu_nano: u64, system_nano: u64, memory: u64, cpu: f64| format!(
+            r#"{{"ContainerID":"{}","Name":"c","CPU":{},"CPUNano":{},"SystemNano":{},"MemUsage":{},"MemLimit":1000000}}"#, id, cpu, cpu_nano, system_nano, memory);
+        let previous = parse_container_samples(&[
+            sample("aaaa1111", 10, 0, 100, 0.0),
+            sample("bbbb2222", 1000000000, 5000000000, 1000, 1.0),
+            sample("cccc3333", 0, 5000000000, 2000, 0.0),
+        ].join("\n"));
+        let current = parse_container_samples(&[
+            sample("aaaa1111", 20, 1000000000, 100, 0.0),
+            sample("bbbb2222", 1500000000, 6000000000, 3000, 1.0),
+            sample("cccc3333", 250000000, 6000000000, 4000, 2.0),
+            "malformed".to_string(),
+        ].join("\n"));
+        assert_eq!(3, current.len());
+
+        let stats = pod_stats(std::slice::from_ref(&pod), &previous, &current);
+        assert_eq!(1, stats.len());
+        assert_eq!("pod1", stats[0].pod_id);
+        // half a cpu and a quarter, the infra container isn't counted
+        assert_eq!(75.0, stats[0].cpu_percent);
+        assert_eq!(7000, stats[0].memory_bytes);
+
+        // nothing to compare against, podman's rates are used
+        let stats = pod_stats(&[pod], &HashMap::new(), &current);
+        assert_eq!(3.0, stats[0].cpu_percent);
+    }
+}
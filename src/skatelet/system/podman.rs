@@ -5,6 +5,7 @@ use k8s_openapi::api::core::v1::{Pod, PodSpec, PodStatus as K8sPodStatus};
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
 use strum_macros::{Display, EnumString};
 use tabled::Tabled;
+use crate::gpu;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
@@ -44,7 +45,13 @@ pub enum PodmanPodStatus {
 }
 
 impl PodmanPodStatus {
-    fn to_pod_phase(&self) -> String {
+    fn to_pod_phase(&self, phase: Option<&PodPhase>) -> String {
+        match phase {
+            // k8s has no ContainerCreating phase, it's a reason on a Pending pod
+            Some(PodPhase::ContainerCreating) => return "Pending".to_string(),
+            Some(PodPhase::Unknown) | None => {}
+            Some(phase) => return phase.to_string(),
+        }
         match self {
             PodmanPodStatus::Running => "Running",
             PodmanPodStatus::Stopped => "Succeeded",
@@ -67,6 +74,18 @@ impl PodmanPodStatus {
     }
 }
 
+// the kubernetes style phase of a pod, computed from the state of its containers rather than podman's pod status
+#[derive(Clone, Debug, Default, EnumString, Display, Serialize, Deserialize, PartialEq)]
+pub enum PodPhase {
+    Pending,
+    ContainerCreating,
+    Running,
+    Succeeded,
+    Failed,
+    #[default]
+    Unknown,
+}
+
 #[derive(Tabled, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 #[tabled(rename_all = "UPPERCASE")]
@@ -79,6 +98,10 @@ pub struct PodmanPodInfo {
     pub labels: BTreeMap<String, String>,
     #[tabled(skip)]
     pub containers: Option<Vec<PodmanContainerInfo>>,
+    // set by skatelet, missing when reported by older versions
+    #[tabled(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phase: Option<PodPhase>,
 }
 
 
@@ -95,18 +118,78 @@ impl PodmanPodInfo {
     pub fn daemonset(&self) -> String {
         self.labels.get("skate.io/daemonset").cloned().unwrap_or("".to_string())
     }
+    pub fn cronjob(&self) -> String {
+        self.labels.get("skate.io/cronjob").cloned().unwrap_or("".to_string())
+    }
+
+    // the containers running the pod's workload, ie not the infra container
+    pub fn app_containers(&self) -> Vec<&PodmanContainerInfo> {
+        self.containers.iter().flatten().filter(|c| !c.is_infra()).collect()
+    }
+
+    pub fn phase(&self) -> PodPhase {
+        self.phase.clone().unwrap_or_else(|| self.compute_phase())
+    }
+
+    pub fn compute_phase(&self) -> PodPhase {
+        let containers = self.app_containers();
+        if containers.is_empty() {
+            return match self.status {
+                PodmanPodStatus::Created => PodPhase::Pending,
+                PodmanPodStatus::Dead | PodmanPodStatus::Error => PodPhase::Failed,
+                _ => PodPhase::Unknown,
+            };
+        }
+
+        let states: Vec<_> = containers.iter().map(|c| c.status.to_lowercase()).collect();
+        let count = |wanted: &[&str]| states.iter().filter(|s| wanted.contains(&s.as_str())).count();
+
+        let created = count(&["created", "configured", "initialized"]);
+        let running = count(&["running", "stopping"]);
+        let exited = count(&["exited", "stopped"]);
+
+        if created + running + exited != states.len() {
+            return PodPhase::Unknown;
+        }
+
+        if running > 0 {
+            return match created {
+                0 => PodPhase::Running,
+                _ => PodPhase::ContainerCreating,
+            };
+        }
+
+        if created == states.len() {
+            return PodPhase::Pending;
+        }
+        if created > 0 {
+            return PodPhase::ContainerCreating;
+        }
+
+        match containers.iter().all(|c| c.exit_code.unwrap_or(0) == 0) {
+            true => PodPhase::Succeeded,
+            false => PodPhase::Failed,
+        }
+    }
 }
 
 
 impl From<Pod> for PodmanPodInfo {
     fn from(value: Pod) -> Self {
+        let gpus = gpu::gpu_request(&value);
+        let mut labels = value.metadata.labels.unwrap_or_default();
+        // as the skatelet labels it once it's given its gpus
+        if gpus > 0 {
+            labels.entry(gpu::GPU_REQUEST_LABEL.to_string()).or_insert(gpus.to_string());
+        }
         PodmanPodInfo {
             id: value.metadata.uid.unwrap_or("".to_string()),
             name: value.metadata.name.unwrap_or("".to_string()),
             status: PodmanPodStatus::from_pod_phase(value.status.and_then(|s| s.phase).unwrap_or("".to_string()).as_str()),
             created: value.metadata.creation_timestamp.map(|ts| DateTime::from(ts.0)).unwrap_or(Local::now()),
-            labels: value.metadata.labels.unwrap_or_default(),
+            labels,
             containers: None, // TODO
+            phase: None,
         }
     }
 }
@@ -197,7 +280,7 @@ impl From<PodmanPodInfo> for Pod {
                 init_container_statuses: None,
                 message: None,
                 nominated_node_name: None,
-                phase: Some(val.status.to_pod_phase()),
+                phase: Some(val.status.to_pod_phase(val.phase.as_ref())),
                 pod_ip: None,
                 pod_ips: None,
                 qos_class: None,
@@ -217,4 +300,88 @@ pub struct PodmanContainerInfo {
     pub names: String,
     pub status: String,
     pub restart_count: Option<usize>,
+    // filled in by skatelet from `podman inspect`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<String>,
+    // the limits podman enforces, none when unlimited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_limit: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_limit: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oom_killed: Option<bool>,
+}
+
+impl PodmanContainerInfo {
+    pub fn is_infra(&self) -> bool {
+        self.names.ends_with("-infra")
+    }
+
+    // ready when running and passing its healthcheck, if it has one
+    pub fn is_ready(&self) -> bool {
+        self.status.eq_ignore_ascii_case("running") && matches!(self.health.as_deref(), None | Some("") | Some("healthy"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+    use crate::skatelet::system::podman::{PodPhase, PodmanContainerInfo, PodmanPodInfo, PodmanPodStatus};
+
+    fn container(name: &str, status: &str, exit_code: Option<i32>) -> PodmanContainerInfo {
+        PodmanContainerInfo {
+            id: name.to_string(),
+            names: name.to_string(),
+            status: status.to_string(),
+            restart_count: None,
+            exit_code,
+            health: None,
+            cpu_limit: None,
+            memory_limit: None,
+            oom_killed: None,
+        }
+    }
+
+    fn pod(status: PodmanPodStatus, containers: Vec<PodmanContainerInfo>) -> PodmanPodInfo {
+        PodmanPodInfo {
+            id: "abc".to_string(),
+            name: "web".to_string(),
+            status,
+            created: Local::now(),
+            labels: Default::default(),
+            containers: Some([vec![container("abc-infra", "running", None)], containers].concat()),
+            phase: None,
+        }
+    }
+
+    #[test]
+    fn test_compute_phase() {
+        let conditions = vec![
+            (PodmanPodStatus::Created, vec![container("app", "created", None)], PodPhase::Pending),
+            (PodmanPodStatus::Degraded, vec![container("app", "running", None), container("sidecar", "created", None)], PodPhase::ContainerCreating),
+            (PodmanPodStatus::Running, vec![container("app", "running", None)], PodPhase::Running),
+            (PodmanPodStatus::Degraded, vec![container("app", "running", None), container("job", "exited", Some(1))], PodPhase::Running),
+            (PodmanPodStatus::Exited, vec![container("app", "exited", Some(0))], PodPhase::Succeeded),
+            (PodmanPodStatus::Exited, vec![container("app", "exited", Some(0)), container("job", "exited", Some(137))], PodPhase::Failed),
+            (PodmanPodStatus::Degraded, vec![container("app", "paused", None)], PodPhase::Unknown),
+        ];
+
+        for (status, containers, expected) in conditions {
+            let pod = pod(status, containers);
+            assert_eq!(pod.compute_phase(), expected, "{:?}", pod.containers);
+        }
+    }
+
+    #[test]
+    fn test_container_ready() {
+        let mut c = container("app", "running", None);
+        assert!(c.is_ready());
+        c.health = Some("starting".to_string());
+        assert!(!c.is_ready());
+        c.health = Some("healthy".to_string());
+        assert!(c.is_ready());
+        assert!(!container("app", "exited", Some(0)).is_ready());
+    }
 }
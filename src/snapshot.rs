@@ -0,0 +1,275 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use anyhow::anyhow;
+use clap::{Args, Subcommand};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use crate::config::{cache_dir, Access, Config};
+use crate::deps::{SshManager, With};
+use crate::errors::SkateError;
+use crate::refresh::{Refresh, RefreshDeps};
+use crate::skate::ConfigFileArgs;
+use crate::state::state::{ClusterState, ConditionStatus, NodeConditionType};
+use crate::util::slugify;
+
+// what diff compares against, the state of the last refresh
+pub const CURRENT: &str = "current";
+
+static RE_SNAPSHOT_NAME: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[a-zA-Z0-9][a-zA-Z0-9._-]*$").unwrap());
+
+#[derive(Debug, Args)]
+pub struct StateArgs {
+    #[command(flatten)]
+    config: ConfigFileArgs,
+    #[command(subcommand)]
+    command: StateCommands,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum StateCommands {
+    #[command(about = "Save the cluster's state under a name")]
+    Snapshot(SnapshotArgs),
+    #[command(about = "List the saved snapshots")]
+    Snapshots,
+    #[command(about = "Show what changed between two snapshots")]
+    Diff(DiffArgs),
+    #[command(about = "Remove a saved snapshot")]
+    DeleteSnapshot(DeleteSnapshotArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct SnapshotArgs {
+    #[arg(long_help = "Name of the snapshot, eg before-upgrade.")]
+    name: String,
+    #[arg(long, long_help = "Save the state of the last refresh instead of refreshing it first.")]
+    cached: bool,
+    #[arg(long, long_help = "Replace an existing snapshot with the same name.")]
+    force: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct DiffArgs {
+    #[arg(long_help = "The snapshot to compare from.")]
+    from: String,
+    #[arg(default_value = CURRENT, long_help = "The snapshot to compare to, the state of the last refresh by default.")]
+    to: String,
+}
+
+#[derive(Debug, Args)]
+pub struct DeleteSnapshotArgs {
+    name: String,
+}
+
+pub trait SnapshotDeps: With<dyn SshManager> {}
+
+pub struct Snapshots<D: SnapshotDeps> {
+    pub deps: D,
+}
+
+fn snapshot_dir(cluster_name: &str) -> PathBuf {
+    Path::new(&cache_dir()).join("snapshots").join(slugify(cluster_name))
+}
+
+fn snapshot_path(cluster_name: &str, name: &str) -> Result<PathBuf, SkateError> {
+    if !RE_SNAPSHOT_NAME.is_match(name) {
+        return Err(anyhow!("invalid snapshot name {}, only letters, digits, '.', '_' and '-' are allowed", name).into());
+    }
+    Ok(snapshot_dir(cluster_name).join(format!("{}.state", name)))
+}
+
+pub(crate) fn load_snapshot(cluster_name: &str, name: &str) -> Result<ClusterState, SkateError> {
+    if name == CURRENT {
+        return Ok(ClusterState::load(cluster_name)?);
+    }
+    let path = snapshot_path(cluster_name, name)?;
+    let file = File::open(&path).map_err(|e| anyhow!(e).context(format!("no snapshot named {}", name)))?;
+    Ok(serde_json::from_reader(file).map_err(|e| anyhow!(e).context(format!("failed to parse snapshot {}", name)))?)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StateChange {
+    NodeAdded(String),
+    NodeRemoved(String),
+    NodeCondition { node: String, type_: NodeConditionType, from: Option<ConditionStatus>, to: Option<ConditionStatus> },
+    PodAdded { pod: String, node: String },
+    PodRemoved { pod: String, node: String },
+    PodMoved { pod: String, from: String, to: String },
+}
+
+impl Display for StateChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let status = |s: &Option<ConditionStatus>| s.map(|s| s.to_string()).unwrap_or("-".to_string());
+        match self {
+            StateChange::NodeAdded(node) => write!(f, "+ node {}", node),
+            StateChange::NodeRemoved(node) => write!(f, "- node {}", node),
+            StateChange::NodeCondition { node, type_, from, to } => write!(f, "~ node {} {}: {} -> {}", node, type_, status(from), status(to)),
+            StateChange::PodAdded { pod, node } => write!(f, "+ pod {} on {}", pod, node),
+            StateChange::PodRemoved { pod, node } => write!(f, "- pod {} from {}", pod, node),
+            StateChange::PodMoved { pod, from, to } => write!(f, "~ pod {} moved from {} to {}", pod, from, to),
+        }
+    }
+}
+
+// the nodes each pod runs on, the same pod can run on more than one while it's being moved
+fn pod_nodes(state: &ClusterState) -> BTreeMap<String, Vec<String>> {
+    let mut pods: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (pod, node) in state.filter_pods(&|_| true) {
+        pods.entry(pod.name.clone()).or_default().push(node.node_name.clone());
+    }
+    pods
+}
+
+pub fn diff_states(from: &ClusterState, to: &ClusterState) -> Vec<StateChange> {
+    let mut changes = vec![];
+
+    for node in to.nodes.iter() {
+        let before = match from.nodes.iter().find(|n| n.node_name == node.node_name) {
+            Some(before) => before,
+            None => {
+                changes.push(StateChange::NodeAdded(node.node_name.clone()));
+                continue;
+            }
+        };
+        let types = before.conditions.iter().chain(node.conditions.iter()).map(|c| c.type_)
+            .fold(vec![], |mut acc, t| {
+                if !acc.contains(&t) {
+                    acc.push(t);
+                }
+                acc
+            });
+        for type_ in types {
+            let (was, is) = (before.condition(type_).map(|c| c.status), node.condition(type_).map(|c| c.status));
+            if was != is {
+                changes.push(StateChange::NodeCondition { node: node.node_name.clone(), type_, from: was, to: is });
+            }
+        }
+    }
+    for node in from.nodes.iter().filter(|n| !to.nodes.iter().any(|t| t.node_name == n.node_name)) {
+        changes.push(StateChange::NodeRemoved(node.node_name.clone()));
+    }
+
+    let (before, after) = (pod_nodes(from), pod_nodes(to));
+    for (pod, nodes) in after.iter() {
+        let was = before.get(pod).cloned().unwrap_or_default();
+        let added: Vec<_> = nodes.iter().filter(|n| !was.contains(n)).collect();
+        let removed: Vec<_> = was.iter().filter(|n| !nodes.contains(n)).collect();
+        // one pod that went from one node to another is shown as a move
+        if let ([to_node], [from_node]) = (added.as_slice(), removed.as_slice()) {
+            changes.push(StateChange::PodMoved { pod: pod.clone(), from: from_node.to_string(), to: to_node.to_string() });
+            continue;
+        }
+        changes.extend(removed.into_iter().map(|n| StateChange::PodRemoved { pod: pod.clone(), node: n.clone() }));
+        changes.extend(added.into_iter().map(|n| StateChange::PodAdded { pod: pod.clone(), node: n.clone() }));
+    }
+    for (pod, nodes) in before.iter().filter(|(p, _)| !after.contains_key(*p)) {
+        changes.extend(nodes.iter().map(|n| StateChange::PodRemoved { pod: pod.clone(), node: n.clone() }));
+    }
+
+    changes
+}
+
+impl<D: SnapshotDeps + RefreshDeps> Snapshots<D> {
+    pub async fn state(&self, args: StateArgs) -> Result<(), SkateError> {
+        let config = Config::load(args.config.skateconfig.clone())?;
+        let cluster = config.authorized_cluster(args.config.context.clone(), Access::ReadOnly)?;
+
+        match args.command {
+            StateCommands::Snapshot(snapshot_args) => self.snapshot(&config, &cluster.name, snapshot_args).await,
+            StateCommands::Snapshots => {
+                let mut names: Vec<_> = std::fs::read_dir(snapshot_dir(&cluster.name)).into_iter().flatten().flatten()
+                    .filter_map(|e| e.file_name().to_string_lossy().strip_suffix(".state").map(|n| n.to_string()))
+                    .collect();
+                names.sort();
+                for name in names {
+                    println!("{}", name);
+                }
+                Ok(())
+            }
+            StateCommands::Diff(diff_args) => {
+                let (from, to) = (load_snapshot(&cluster.name, &diff_args.from)?, load_snapshot(&cluster.name, &diff_args.to)?);
+                let changes = diff_states(&from, &to);
+                if changes.is_empty() {
+                    println!("no changes between {} and {}", diff_args.from, diff_args.to);
+                }
+                for change in changes {
+                    println!("{}", change);
+                }
+                Ok(())
+            }
+            StateCommands::DeleteSnapshot(delete_args) => {
+                let path = snapshot_path(&cluster.name, &delete_args.name)?;
+                std::fs::remove_file(&path).map_err(|e| anyhow!(e).context(format!("failed to remove snapshot {}", delete_args.name)))?;
+                Ok(())
+            }
+        }
+    }
+
+    async fn snapshot(&self, config: &Config, cluster_name: &str, args: SnapshotArgs) -> Result<(), SkateError> {
+        if args.name == CURRENT {
+            return Err(anyhow!("{} is the name of the last refreshed state", CURRENT).into());
+        }
+        let path = snapshot_path(cluster_name, &args.name)?;
+        if path.exists() && !args.force {
+            return Err(anyhow!("snapshot {} already exists, use --force to replace it", args.name).into());
+        }
+
+        let state = match args.cached {
+            true => ClusterState::load(cluster_name)?,
+            false => {
+                let cluster = config.active_cluster(Some(cluster_name.to_string()))?;
+                let (conns, errors) = self.deps.get().cluster_connect(cluster).await;
+                if let Some(errors) = errors {
+                    eprintln!("{}", errors)
+                }
+                let conns = conns.ok_or(anyhow!("failed to connect to any node"))?;
+                Refresh::<D>::refreshed_state(cluster_name, &conns, config).await?
+            }
+        };
+
+        std::fs::create_dir_all(snapshot_dir(cluster_name)).map_err(|e| anyhow!(e).context("failed to create snapshot directory"))?;
+        state.write_to(&path)?;
+        println!("saved snapshot {} of {} nodes", args.name, state.nodes.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+    use crate::snapshot::{diff_states, snapshot_path, StateChange};
+    use crate::state::state::{ConditionStatus, NodeConditionType};
+    use crate::test_helpers::objects::{cluster_state, node_state, pod, WithPod};
+
+    #[test]
+    fn test_diff_states() {
+        let mut node_1 = node_state("node-1").with_pod(&pod("web", "shop", &[])).with_pod(&pod("db", "shop", &[]));
+        node_1.update_conditions(true, Local::now());
+        let before = cluster_state(vec![node_1.clone(), node_state("node-2"), node_state("node-3")]);
+
+        let mut down = node_state("node-1").with_pod(&pod("cache", "shop", &[]));
+        down.conditions = node_1.conditions.clone();
+        down.update_conditions(false, Local::now());
+        let after = cluster_state(vec![down, node_state("node-2").with_pod(&pod("db", "shop", &[])), node_state("node-4")]);
+
+        let changes = diff_states(&before, &after);
+        assert!(changes.contains(&StateChange::NodeCondition {
+            node: "node-1".to_string(),
+            type_: NodeConditionType::Ready,
+            from: node_1.condition(NodeConditionType::Ready).map(|c| c.status),
+            to: Some(ConditionStatus::Unknown),
+        }), "{:?}", changes);
+        let rest: Vec<_> = changes.iter().filter(|c| !matches!(c, StateChange::NodeCondition { .. })).map(|c| c.to_string()).collect();
+        assert_eq!(vec![
+            "+ node node-4",
+            "- node node-3",
+            "+ pod cache.shop on node-1",
+            "~ pod db.shop moved from node-1 to node-2",
+            "- pod web.shop from node-1",
+        ], rest);
+
+        assert!(diff_states(&after, &after).is_empty());
+        assert!(snapshot_path("test", "../escape").is_err());
+    }
+}
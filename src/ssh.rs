@@ -1,27 +1,40 @@
 use anyhow::anyhow;
-use async_ssh2_tokio::client::Client;
+use async_ssh2_tokio::client::{Client, CommandExecutedResult};
 use async_ssh2_tokio::{AuthMethod, ServerCheckMethod};
 use base64::engine::general_purpose;
 use base64::Engine;
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::fmt;
+use std::io::{Read, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 use std::fmt::{Debug, Formatter};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use async_trait::async_trait;
+use chrono::{DateTime, Local};
 use crate::config::{Cluster, Node};
+use crate::credentials::{BecomeMethod, NodeCredentials};
+use crate::util::shell_quote;
 use crate::skate::{Distribution, Platform};
 use crate::skatelet::SystemInfo;
-use crate::state::state::{NodeState, NodeStatus};
+use crate::state::state::{ClusterState, NodeState, RefreshTiming};
+use crate::progress::progress;
 use colored::Colorize;
 use futures::stream::FuturesUnordered;
 use futures::StreamExt;
 use itertools::Itertools;
-use russh::{ChannelMsg, CryptoVec};
+use russh::{Channel, ChannelMsg, CryptoVec};
+use russh::client::Msg;
+use russh_sftp::client::SftpSession;
+use russh_sftp::protocol::OpenFlags;
+use sha2::{Digest, Sha256};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWriteExt};
 use tokio::sync::mpsc;
 use crate::github;
 use crate::resource::ResourceType;
+use crate::skatelet::apply_progress::ApplyEvent;
 
 #[async_trait]
 pub trait SshClient: Send + Sync {
@@ -32,18 +45,166 @@ pub trait SshClient: Send + Sync {
     async fn remove_resource_by_manifest(&self, manifest: &str) -> Result<(String, String), Box<dyn Error>>;
     async fn execute_stdout(&self, cmd: &str, print_command: bool, prefix_output: bool) -> Result<(), Box<dyn Error>>;
     async fn execute_to_sender(&self, cmd: &str, sender: mpsc::Sender<String>) -> Result<(), Box<dyn Error>>;
+    // runs the command with the local terminal's stdin sent to it, returning its exit status
+    async fn execute_interactive(&self, cmd: &str, stdin: bool, tty: bool) -> Result<u32, Box<dyn Error>>;
     // TODO-merge this into execute_stdout
     async fn execute_noisy(&self, cmd: &str) -> Result<String, Box<dyn Error>>;
     async fn execute(&self, cmd: &str) -> Result<String, Box<dyn Error>>;
+    // writes the source to the remote path as root, skipping it when the file there already matches. the data goes over sftp
+    // to a staging file named after its checksum, so an interrupted upload of the same content carries on where it stopped
+    async fn upload(&self, source: &mut dyn UploadSource, remote_path: &str, mode: u32) -> Result<Transfer, Box<dyn Error>>;
+    async fn upload_bytes(&self, contents: &[u8], remote_path: &str, mode: u32) -> Result<Transfer, Box<dyn Error>> {
+        self.upload(&mut std::io::Cursor::new(contents), remote_path, mode).await
+    }
+    // copies the remote file to the local path, carrying on from the <path>.part an interrupted download left
+    async fn download(&self, remote_path: &str, local_path: &Path) -> Result<Transfer, Box<dyn Error>>;
+    // a cheap fingerprint of the node's state, unchanged as long as get_node_system_info would return the same objects
+    async fn get_node_generation(&self) -> Result<String, Box<dyn Error>> {
+        Ok(self.execute("sudo skatelet system generation").await?.trim().to_string())
+    }
+    // runs the commands in order, returning the result of each. a failing command doesn't stop the ones after it
+    async fn execute_batch(&self, cmds: &[String]) -> Result<Vec<BatchResult>, Box<dyn Error>> {
+        let mut results = vec!();
+        for cmd in cmds {
+            results.push(match self.execute(cmd).await {
+                Ok(stdout) => BatchResult { exit_status: 0, stdout, stderr: "".to_string() },
+                Err(e) => BatchResult { exit_status: 1, stdout: "".to_string(), stderr: e.to_string() },
+            });
+        }
+        Ok(results)
+    }
     fn node_name(&self) -> String;
+    // how long the ssh connection took to set up
+    fn connect_duration(&self) -> Option<Duration> {
+        None
+    }
     
     async fn connect(n: &Node) -> Result<Self, SshError> where Self: Sized;
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchResult {
+    pub exit_status: u32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl BatchResult {
+    pub fn into_result(self) -> Result<String, Box<dyn Error>> {
+        match self.exit_status {
+            0 => Ok(self.stdout),
+            _ => Err(anyhow!("exit code {}, {}", self.exit_status, self.stderr.trim()).into()),
+        }
+    }
+}
+
+// what is uploaded, read once for its checksum before it's sent
+pub trait UploadSource: AsyncRead + AsyncSeek + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncSeek + Send + Unpin> UploadSource for T {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transfer {
+    pub size: u64,
+    // the bytes already there from an interrupted transfer
+    pub resumed_from: u64,
+    pub sha256: String,
+    // the file was already there, nothing was sent
+    pub unchanged: bool,
+}
+
+// reads the source to the end for its sha256 and size, leaving it back at the start
+async fn checksum(source: &mut dyn UploadSource) -> Result<(String, u64), Box<dyn Error>> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let n = source.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+    source.seek(SeekFrom::Start(0)).await?;
+    Ok((format!("{:x}", hasher.finalize()), size))
+}
+
+// the checksum from the output of sha256sum
+fn parse_sha256sum(output: &str) -> Option<String> {
+    output.split_whitespace().next()
+        .filter(|s| s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(|s| s.to_lowercase())
+}
+
+// in the ssh user's home, since sftp runs as them, and only readable by them. relative paths are from the home dir
+// for both sftp and commands
+const STAGING_DIR: &str = ".skate-uploads";
+
+fn staging_path(sha256: &str) -> String {
+    format!("{}/{}", STAGING_DIR, &sha256[..16])
+}
+
+// where a manifest is uploaded for the skatelet to read, rather than being passed on the command line
+fn manifest_path(manifest: &str) -> String {
+    format!("/var/lib/skate/incoming/{}.yaml", &format!("{:x}", Sha256::digest(manifest))[..16])
+}
+
+// a partial file bigger than the whole can't be the start of it
+fn resume_offset(partial: Option<u64>, size: u64) -> u64 {
+    partial.filter(|p| *p <= size).unwrap_or(0)
+}
+
+// a skatelet from before apply --progress fails on the flag as clap does for any argument it doesn't know
+fn rejects_progress(exit_status: u32, stderr: &str) -> bool {
+    exit_status == 2 && stderr.contains("unexpected argument '--progress'")
+}
+
+const BATCH_MARKER: &str = "__skate_batch__";
+
+// a single script that runs every command in its own subshell, printing a marker line with the exit status and base64 encoded output of each
+fn batch_script(cmds: &[String]) -> String {
+    let mut script = "dir=$(mktemp -d) || exit 1\n".to_string();
+    for (i, cmd) in cmds.iter().enumerate() {
+        script.push_str(&format!(
+            "(\n{cmd}\n) > \"$dir/out\" 2> \"$dir/err\" < /dev/null; printf '%s %s %s %s\\n' {marker}{i} \"$?\" \"$(base64 -w0 < \"$dir/out\")\" \"$(base64 -w0 < \"$dir/err\")\"\n",
+            cmd = cmd, marker = BATCH_MARKER, i = i
+        ));
+    }
+    script.push_str("rm -rf \"$dir\"\n");
+    script
+}
+
+fn parse_batch_output(output: &str, num_cmds: usize) -> Result<Vec<BatchResult>, Box<dyn Error>> {
+    let decode = |v: Option<&str>| -> Result<String, Box<dyn Error>> {
+        let bytes = general_purpose::STANDARD.decode(v.unwrap_or_default())?;
+        Ok(String::from_utf8_lossy(&bytes).to_string())
+    };
+
+    let mut results = vec!();
+    for (i, line) in output.lines().filter(|l| l.starts_with(BATCH_MARKER)).enumerate() {
+        let mut parts = line.split(' ');
+        if parts.next() != Some(&format!("{}{}", BATCH_MARKER, i)) {
+            return Err(anyhow!("unexpected batch output: {}", line).into());
+        }
+        let exit_status = parts.next().unwrap_or_default().parse()?;
+        results.push(BatchResult { exit_status, stdout: decode(parts.next())?, stderr: decode(parts.next())? });
+    }
+
+    if results.len() != num_cmds {
+        return Err(anyhow!("expected the results of {} commands but got {}", num_cmds, results.len()).into());
+    }
+    Ok(results)
+}
+
 #[derive(Clone)]
 pub struct RealSsh {
     pub node_name: String,
     pub client: Client,
+    // commands are run through sudo -S with this on stdin, for users that can't sudo without a password
+    sudo_password: Option<String>,
+    become_method: BecomeMethod,
+    connect_duration: Duration,
 }
 
 impl Debug for RealSsh {
@@ -67,28 +228,40 @@ pub struct HostInfo {
     pub system_info: Option<SystemInfo>,
     pub podman_version: Option<String>,
     pub ovs_version: Option<String>,
+    #[serde(default)]
+    pub kernel_version: Option<String>,
+    // systemd or cgroupfs, as podman reports it
+    #[serde(default)]
+    pub cgroup_driver: Option<String>,
+    // when skate collected the info, cached info older than MAX_CACHED_INFO_AGE is collected again
+    #[serde(default)]
+    pub fetched_at: Option<DateTime<Local>>,
 }
 
 impl From<HostInfo> for NodeState {
     fn from(val: HostInfo) -> Self {
-        let (status, message) = match val.healthy() {
-            Ok(_) => (NodeStatus::Healthy, None),
-            Err(errs) => (NodeStatus::Unhealthy, Some(errs.join(". ").to_string())),
-        };
-        NodeState {
+        let mut node = NodeState {
             node_name: val.node_name.to_string(),
-            status,
-            message,
             host_info: Some(val),
-        }
+            ..Default::default()
+        };
+        node.update_conditions(true, Local::now());
+        node
     }
 }
 
 impl HostInfo {
+    // the host software whose upgrade can break workloads that ran fine, compared across refreshes
+    pub fn host_versions(&self) -> BTreeMap<String, String> {
+        [("podman", &self.podman_version), ("kernel", &self.kernel_version), ("cgroup driver", &self.cgroup_driver)].into_iter()
+            .filter_map(|(k, v)| Some((k.to_string(), v.clone()?)))
+            .collect()
+    }
+
     pub fn healthy(&self) -> Result<(), Vec<String>> {
         let mut errs = Vec::new();
         // TODO - actual checks for things that matter
-        if !self.skatelet_version.is_some() {
+        if self.skatelet_version.is_none() {
             errs.push("Failed to find skatelet version".to_string());
         }
         let cordoned = self.system_info.as_ref().map(|si| si.cordoned).unwrap_or(false);
@@ -109,7 +282,7 @@ impl RealSsh {
         let binding = data.to_vec();
         let s = String::from_utf8_lossy(&binding);
         sender.send(s.to_string()).await.expect("failed to send");
-        if s.len() > 0 {
+        if !s.is_empty() {
             s.chars().last().unwrap()
         } else {
             prev_last_char
@@ -124,7 +297,7 @@ impl RealSsh {
         } else {
             print!("{}", s);
         }
-        if s.len() > 0 {
+        if !s.is_empty() {
             s.chars().last().unwrap()
         } else {
             prev_last_char
@@ -132,18 +305,98 @@ impl RealSsh {
     }
 }
 
-#[async_trait]
-impl SshClient for RealSsh {
+// the local terminal's columns and rows, for the remote pty
+fn terminal_size() -> (u32, u32) {
+    #[cfg(unix)]
+    {
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        // SAFETY: only reads the size of the terminal on stdout into the struct
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0 && size.ws_col > 0 {
+            return (size.ws_col as u32, size.ws_row as u32);
+        }
+    }
+    (80, 24)
+}
 
-    fn node_name(&self) ->String { self.node_name.clone()}
+// puts the local terminal in raw mode while in scope, so that every key is sent as it's pressed
+struct RawTerminal {
+    #[cfg(unix)]
+    saved: libc::termios,
+}
 
-    async fn connect(node: &Node) -> Result<Self, SshError> {
+impl RawTerminal {
+    // none when stdin isn't a terminal
+    fn new() -> Option<Self> {
+        #[cfg(unix)]
+        {
+            // SAFETY: only reads and sets the attributes of the process's stdin
+            unsafe {
+                let mut saved: libc::termios = std::mem::zeroed();
+                if libc::isatty(libc::STDIN_FILENO) != 1 || libc::tcgetattr(libc::STDIN_FILENO, &mut saved) != 0 {
+                    return None;
+                }
+                let mut raw = saved;
+                libc::cfmakeraw(&mut raw);
+                if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                    return None;
+                }
+                Some(RawTerminal { saved })
+            }
+        }
+        #[cfg(not(unix))]
+        None
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        // SAFETY: restores the attributes saved in new
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved);
+        }
+    }
+}
+
+// ssh refuses private keys other users can read, here it's only a warning since the key still works. windows has acls
+// rather than modes, so there's nothing to check there
+fn key_permissions_warning(key: &Path) -> Result<Option<String>, String> {
+    let metadata = std::fs::metadata(key).map_err(|e| format!("failed to read key {}: {}", key.display(), e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = metadata.permissions().mode() & 0o777;
+        if mode & 0o077 != 0 {
+            return Ok(Some(format!("key {} can be read by other users (mode {:o}), run chmod 600 {}", key.display(), mode, key.display())));
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = metadata;
+    Ok(None)
+}
+
+// commands built from files written or checked out on windows have \r\n line endings, the remote shell would take the \r
+// as part of each line
+fn unix_line_endings(cmd: &str) -> String {
+    cmd.replace("\r\n", "\n")
+}
+
+impl RealSsh {
+    pub async fn connect_with_credentials(node: &Node, credentials: NodeCredentials) -> Result<Self, SshError> {
         let default_key = "";
         let key = node.key.clone().unwrap_or(default_key.to_string());
         let key = shellexpand::tilde(&key).to_string();
+        if node.key.is_some() {
+            match key_permissions_warning(Path::new(&key)) {
+                Ok(Some(warning)) => eprintln!("{} - {}", node.name, warning),
+                Ok(None) => {}
+                Err(error) => return Err(SshError { node_name: node.name.clone(), error, elapsed: None }),
+            }
+        }
         let timeout = Duration::from_secs(5);
 
-        let auth_method = AuthMethod::with_key_file(&key, None);
+        let start = Instant::now();
+        let auth_method = AuthMethod::with_key_file(&key, credentials.passphrase.as_deref());
         let result = tokio::time::timeout(timeout, Client::connect(
             (&*node.host, node.port.unwrap_or(22)),
             node.user.clone().unwrap_or(String::from("")).as_str(),
@@ -156,9 +409,150 @@ impl SshClient for RealSsh {
             _ => Err(anyhow!("timeout").into())
         };
 
-        let ssh_client = result.map_err(|e| SshError{node_name: node.name.clone(), error: e.to_string()})?;
+        let ssh_client = result.map_err(|e| SshError { node_name: node.name.clone(), error: e.to_string(), elapsed: Some(start.elapsed()) })?;
+
+        Ok(RealSsh {
+            node_name: node.name.clone(),
+            client: ssh_client,
+            sudo_password: credentials.sudo_password,
+            become_method: credentials.become_method,
+            connect_duration: start.elapsed(),
+        })
+    }
+
+    // runs the command as root when the become method needs it, with the password on stdin. the commands' own sudo calls
+    // then don't need one
+    async fn exec_on(&self, ch: &mut Channel<Msg>, cmd: &str) -> Result<(), Box<dyn Error>> {
+        let cmd = unix_line_endings(cmd);
+        match become_command(self.become_method, &cmd, self.sudo_password.is_some()) {
+            Some(wrapped) => ch.exec(true, wrapped).await?,
+            None => ch.exec(true, cmd).await?,
+        }
+        if let Some(password) = &self.sudo_password {
+            ch.data(format!("{}\n", password).as_bytes()).await?;
+        }
+        Ok(())
+    }
 
-        Ok(RealSsh { node_name: node.name.clone(), client: ssh_client })
+    async fn open_exec(&self, cmd: &str) -> Result<Channel<Msg>, Box<dyn Error>> {
+        let mut ch = self.client.get_channel().await?;
+        self.exec_on(&mut ch, cmd).await?;
+        ch.eof().await?;
+        Ok(ch)
+    }
+
+    // needs the sftp subsystem enabled in the node's sshd_config, which it is by default on the supported distributions
+    async fn sftp(&self) -> Result<SftpSession, Box<dyn Error>> {
+        let ch = self.client.get_channel().await?;
+        ch.request_subsystem(true, "sftp").await?;
+        Ok(SftpSession::new(ch.into_stream()).await?)
+    }
+
+    // the skatelet of the latest release, fetched here rather than on the node, which may not reach github
+    async fn fetch_and_install_skatelet(&self, platform: Platform) -> Result<(), Box<dyn Error>> {
+        let github_client = github::Client::new();
+        let resp = github_client.get_latest_release().await?;
+        let version = resp.version()?;
+        let download_url = resp.find_skatelet_archive(&platform).ok_or(anyhow!("failed to find skatelet archive for platform"))?;
+
+        progress().start(&self.node_name, &format!("downloading skatelet {}", version));
+        let archive = github_client.download(&download_url).await?;
+
+        progress().start(&self.node_name, "uploading skatelet");
+        let path = "/var/lib/skate/skatelet.tar.gz";
+        self.upload_bytes(&archive, path, 0o644).await?;
+
+        progress().start(&self.node_name, "installing skatelet");
+        let cmd = format!("dir=$(mktemp -d) && tar -xf {path} -C $dir && sudo install -m 755 $dir/skatelet /usr/local/bin/skatelet; rc=$?; rm -rf $dir; exit $rc", path = path);
+        self.execute_stdout(&cmd, true, true).await?;
+
+        Ok(())
+    }
+
+    // like run, while rendering the progress events the command writes to stderr as they arrive
+    async fn run_with_progress(&self, cmd: &str) -> Result<CommandExecutedResult, Box<dyn Error>> {
+        let mut ch = self.open_exec(cmd).await?;
+        let (mut stdout, mut stderr, mut pending) = (vec![], vec![], vec![]);
+        let mut exit_status = None;
+        while let Some(msg) = ch.wait().await {
+            match msg {
+                ChannelMsg::Data { ref data } => stdout.extend_from_slice(data),
+                ChannelMsg::ExtendedData { ref data, ext: 1 } => {
+                    pending.extend_from_slice(data);
+                    report_progress(&self.node_name, &mut pending, &mut stderr, false);
+                }
+                ChannelMsg::ExitStatus { exit_status: status } => exit_status = Some(status),
+                _ => {}
+            }
+        }
+        report_progress(&self.node_name, &mut pending, &mut stderr, true);
+        Ok(CommandExecutedResult {
+            stdout: String::from_utf8_lossy(&stdout).to_string(),
+            stderr: String::from_utf8_lossy(&stderr).to_string(),
+            exit_status: exit_status.ok_or(anyhow!("{} exited without a status", self.node_name))?,
+        })
+    }
+
+    async fn run(&self, cmd: &str) -> Result<CommandExecutedResult, Box<dyn Error>> {
+        let mut ch = self.open_exec(cmd).await?;
+        let (mut stdout, mut stderr) = (vec![], vec![]);
+        let mut exit_status = None;
+        while let Some(msg) = ch.wait().await {
+            match msg {
+                ChannelMsg::Data { ref data } => stdout.extend_from_slice(data),
+                ChannelMsg::ExtendedData { ref data, ext: 1 } => stderr.extend_from_slice(data),
+                ChannelMsg::ExitStatus { exit_status: status } => exit_status = Some(status),
+                _ => {}
+            }
+        }
+        Ok(CommandExecutedResult {
+            stdout: String::from_utf8_lossy(&stdout).to_string(),
+            stderr: String::from_utf8_lossy(&stderr).to_string(),
+            exit_status: exit_status.ok_or(anyhow!("{} exited without a status", self.node_name))?,
+        })
+    }
+}
+
+// the stderr lines that aren't apply progress events, each event is rendered as the node's current phase
+fn report_progress(node_name: &str, pending: &mut Vec<u8>, stderr: &mut Vec<u8>, last: bool) {
+    let mut lines = vec![];
+    while let Some(end) = pending.iter().position(|b| *b == b'\n') {
+        lines.push(pending.drain(..=end).collect::<Vec<_>>());
+    }
+    if last && !pending.is_empty() {
+        lines.push(std::mem::take(pending));
+    }
+    for line in lines {
+        match ApplyEvent::parse(String::from_utf8_lossy(&line).trim_end()) {
+            Some(event) => progress().start(node_name, &event.to_string()),
+            None => stderr.extend_from_slice(&line),
+        }
+    }
+}
+
+// stands in for sudo once the command already runs as root, since doas hosts may not have it. it drops sudo's options
+const SUDO_SHIM: &str = "sudo() { while [ $# -gt 0 ] && [ \"${1#-}\" != \"$1\" ]; do shift; done; \"$@\"; }\n";
+
+// the command wrapped to run as root, none when it's left to call sudo itself
+fn become_command(method: BecomeMethod, cmd: &str, with_password: bool) -> Option<String> {
+    match (method, with_password) {
+        (BecomeMethod::Sudo, true) => Some(format!("sudo -S -p '' sh -c {}", shell_quote(cmd))),
+        (BecomeMethod::Sudo, false) => None,
+        (BecomeMethod::Doas, _) => Some(format!("doas sh -c {}", shell_quote(&format!("{}{}", SUDO_SHIM, cmd)))),
+    }
+}
+
+#[async_trait]
+impl SshClient for RealSsh {
+
+    fn node_name(&self) ->String { self.node_name.clone()}
+
+    fn connect_duration(&self) -> Option<Duration> {
+        Some(self.connect_duration)
+    }
+
+    async fn connect(node: &Node) -> Result<Self, SshError> {
+        Self::connect_with_credentials(node, NodeCredentials::default()).await
     }
     
     async fn get_node_system_info(&self) -> Result<HostInfo, Box<dyn Error>> {
@@ -166,11 +560,13 @@ impl SshClient for RealSsh {
 hostname > /tmp/hostname-$$ &
 arch > /tmp/arch-$$ &
 uname -s > /tmp/os-$$ &
+uname -r > /tmp/kernel-$$ &
 { { cat /etc/issue |head -1|awk '{print $1}'; }  || echo '' ; } > /tmp/distro-$$ &
 skatelet -V|awk '{print $NF}' > /tmp/skatelet-$$ &
 podman --version|awk '{print $NF}' > /tmp/podman-$$ &
 sudo skatelet system info|base64 -w0 > /tmp/sys-$$ &
 ovs-vsctl --version|head -1| awk '{print $NF}' > /tmp/ovs-$$ &
+sudo podman info --format '{{.Host.CgroupManager}}' > /tmp/cgroup-$$ 2>/dev/null &
 
 wait;
 
@@ -182,9 +578,11 @@ echo skatelet="$(cat /tmp/skatelet-$$)";
 echo podman="$(cat /tmp/podman-$$)";
 echo sys="$(cat /tmp/sys-$$)";
 echo ovs="$(cat /tmp/ovs-$$)";
+echo kernel="$(cat /tmp/kernel-$$)";
+echo cgroup="$(cat /tmp/cgroup-$$)";
 "#;
 
-        let result = self.client.execute(command).await?;
+        let result = self.run(command).await?;
 
         if result.exit_status > 0 {
             let mut errlines = result.stderr.lines();
@@ -202,6 +600,9 @@ echo ovs="$(cat /tmp/ovs-$$)";
             system_info: None,
             podman_version: None,
             ovs_version: None,
+            kernel_version: None,
+            cgroup_driver: None,
+            fetched_at: Some(Local::now()),
         };
         let mut arch: Option<String> = None;
         for line in lines {
@@ -214,7 +615,7 @@ echo ovs="$(cat /tmp/ovs-$$)";
                         "" => None,
                         _ => {
                             Some(if v.starts_with("v") {
-                                v.strip_prefix("v").and_then(|v| Some(v.to_string())).unwrap()
+                                v.strip_prefix("v").map(|v| v.to_string()).unwrap()
                             } else {
                                 v.to_string()
                             })
@@ -224,8 +625,8 @@ echo ovs="$(cat /tmp/ovs-$$)";
                         "" => None,
                         _ => Some(v.to_string())
                     },
-                    "sys" => {
-                        if !v.is_empty() {
+                    "sys"
+                        if !v.is_empty() => {
                             match general_purpose::STANDARD.decode(v) {
                                 Ok(v) => {
                                     if let Ok(sys_info) = serde_json::from_slice(&v) {
@@ -237,56 +638,56 @@ echo ovs="$(cat /tmp/ovs-$$)";
                                 }
                             };
                         }
-                    }
                     "ovs" => host_info.ovs_version = match v {
                         "" => None,
                         _ => Some(v.to_string())
                     },
+                    "kernel" if !v.is_empty() => host_info.kernel_version = Some(v.to_string()),
+                    "cgroup" if !v.is_empty() => host_info.cgroup_driver = Some(v.to_string()),
                     _ => {}
                 }
             }
         }
 
-        match &arch {
-            Some(arch) => {
-                host_info.platform.arch = arch.clone();
-                host_info.system_info = host_info.system_info.map(|mut sys_info| {
-                    sys_info.platform.arch = arch.clone();
-                    sys_info
-                })
-            }
-            None => {}
+        if let Some(arch) = &arch {
+            host_info.platform.arch = arch.clone();
+            host_info.system_info = host_info.system_info.map(|mut sys_info| {
+                sys_info.platform.arch = arch.clone();
+                sys_info
+            })
         }
 
 
-        if host_info.skatelet_version.is_some() && host_info.system_info.is_none() {
-            return Err(anyhow!("skatelet installed ({}) but failed to return system info", host_info.skatelet_version.unwrap()).into());
+        if let (Some(version), None) = (&host_info.skatelet_version, &host_info.system_info) {
+            return Err(anyhow!("skatelet installed ({}) but failed to return system info", version).into());
         }
 
         Ok(host_info)
     }
     async fn install_skatelet(&self, platform: Platform) -> Result<(), Box<dyn Error>> {
-
-        let github_client = github::Client::new();
-        
-        let resp = github_client.get_latest_release().await?;
-
-        let version = resp.version()?;
-        
-        let download_url = resp.find_skatelet_archive(&platform).ok_or(anyhow!("failed to find skatelet archive for platform"))?;
-
-        println!("installing skatelet version {}", version);
-
-        let cmd = format!("cd /tmp && wget {} -O skatelet.tar.gz && tar -xvf ./skatelet.tar.gz && chmod +x skatelet && sudo mv skatelet /usr/local/bin", download_url);
-        self.execute_stdout(&cmd, true, true).await?;
-
-        Ok(())
+        let result = self.fetch_and_install_skatelet(platform).await;
+        match &result {
+            Ok(_) => progress().finish(&self.node_name),
+            Err(e) => progress().fail(&self.node_name, &e.to_string()),
+        }
+        result
     }
     async fn apply_resource(&self, manifest: &str) -> Result<(String, String), Box<dyn Error>> {
-        let base64_manifest = general_purpose::STANDARD.encode(manifest);
-        let result = self.client.execute(&format!("echo '{}'| base64 --decode|sudo skatelet apply -", base64_manifest)).await?;
+        let path = manifest_path(manifest);
+        progress().start(&self.node_name, "uploading manifest");
+        if let Err(e) = self.upload_bytes(manifest.as_bytes(), &path, 0o600).await {
+            progress().fail(&self.node_name, &e.to_string());
+            return Err(e);
+        }
+        progress().start(&self.node_name, "applying manifest");
+        let mut result = self.run_with_progress(&format!("sudo cat {} | sudo skatelet apply --progress -", path)).await?;
+        if rejects_progress(result.exit_status, &result.stderr) {
+            result = self.run(&format!("sudo cat {} | sudo skatelet apply -", path)).await?;
+        }
+        let _ = self.execute(&format!("sudo rm -f {}", path)).await;
         match result.exit_status {
             0 => {
+                progress().finish(&self.node_name);
                 Ok((result.stdout.trim().to_string(), result.stderr.trim().to_string()))
             }
             _ => {
@@ -294,12 +695,14 @@ echo ovs="$(cat /tmp/ovs-$$)";
                     0 => result.stdout.trim(),
                     _ => result.stderr.trim(),
                 };
+                // the step that was running is the one that failed
+                progress().fail(&self.node_name, message.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("failed").trim());
                 Err(anyhow!("failed to apply resource: exit code {}, {}", result.exit_status, message).into())
             }
         }
     }
     async fn remove_resource(&self, resource_type: ResourceType, name: &str, namespace: &str) -> Result<(String, String), Box<dyn Error>> {
-        let result = self.client.execute(&format!("sudo skatelet delete {} --name {} --namespace {}", resource_type.to_string().to_lowercase(), name, namespace)).await?;
+        let result = self.run(&format!("sudo skatelet delete {} --name {} --namespace {}", resource_type.to_string().to_lowercase(), name, namespace)).await?;
         match result.exit_status {
             0 => {
                 Ok((result.stdout, result.stderr))
@@ -314,8 +717,9 @@ echo ovs="$(cat /tmp/ovs-$$)";
         }
     }
     async fn remove_resource_by_manifest(&self, manifest: &str) -> Result<(String, String), Box<dyn Error>> {
-        let base64_manifest = general_purpose::STANDARD.encode(manifest);
-        let result = self.client.execute(&format!("echo '{}' |base64  --decode|sudo skatelet delete -", base64_manifest)).await?;
+        let path = manifest_path(manifest);
+        self.upload_bytes(manifest.as_bytes(), &path, 0o600).await?;
+        let result = self.run(&format!("sudo cat {path} | sudo skatelet delete -; rc=$?; sudo rm -f {path}; exit $rc", path = path)).await?;
         match result.exit_status {
             0 => {
                 Ok((result.stdout, result.stderr))
@@ -332,8 +736,7 @@ echo ovs="$(cat /tmp/ovs-$$)";
 
     async fn execute_to_sender(&self, cmd: &str, sender: mpsc::Sender<String>) -> Result<(), Box<dyn Error>> {
 
-        let mut ch = self.client.get_channel().await?;
-        ch.exec(true, cmd).await?;
+        let mut ch = self.open_exec(cmd).await?;
 
         let mut result: Option<_> = None;
         let mut last_char = '\n';
@@ -345,11 +748,9 @@ echo ovs="$(cat /tmp/ovs-$$)";
                 ChannelMsg::Data { ref data } => {
                     last_char = self.write_crypto_vec(data, last_char, &sender).await;
                 }
-                ChannelMsg::ExtendedData { ref data, ext } => {
-                    if ext == 1 {
+                ChannelMsg::ExtendedData { ref data, ext: 1 } => {
                         last_char = self.write_crypto_vec(data, last_char, &sender).await;
                     }
-                }
                 // If we get an exit code report, store it, but crucially don't
                 // assume this message means end of communications. The data might
                 // not be finished yet!
@@ -369,13 +770,88 @@ echo ovs="$(cat /tmp/ovs-$$)";
         Err(anyhow!("exit status {}", result.unwrap()).into())
 
     }
+    async fn execute_interactive(&self, cmd: &str, stdin: bool, tty: bool) -> Result<u32, Box<dyn Error>> {
+        let mut ch = self.client.get_channel().await?;
+        let _raw = match tty {
+            true => {
+                let (cols, rows) = terminal_size();
+                ch.request_pty(true, &std::env::var("TERM").unwrap_or("xterm".to_string()), cols, rows, 0, 0, &[]).await?;
+                // keys like ctrl-c go to the remote side rather than killing skate
+                RawTerminal::new()
+            }
+            false => None,
+        };
+        match tty {
+            // sudo and doas ask on the terminal themselves, the password written ahead of it would be echoed back
+            true => {
+                let cmd = unix_line_endings(cmd);
+                ch.exec(true, become_command(self.become_method, &cmd, false).unwrap_or(cmd)).await?
+            }
+            false => self.exec_on(&mut ch, cmd).await?,
+        }
+
+        // a thread rather than tokio's stdin, which would keep the runtime from shutting down while a read is blocked
+        let (sender, mut input) = mpsc::channel::<Vec<u8>>(16);
+        if stdin {
+            std::thread::spawn(move || {
+                let mut buf = [0u8; 1024];
+                loop {
+                    match std::io::stdin().read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => if sender.blocking_send(buf[..n].to_vec()).is_err() {
+                            break;
+                        },
+                    }
+                }
+            });
+        } else {
+            drop(sender);
+        }
+
+        let mut result = None;
+        let mut signal = None;
+        let mut input_open = stdin;
+        loop {
+            tokio::select! {
+                data = input.recv(), if input_open => match data {
+                    Some(data) => ch.data(&data[..]).await?,
+                    None => {
+                        input_open = false;
+                        ch.eof().await?;
+                    }
+                },
+                msg = ch.wait() => match msg {
+                    Some(ChannelMsg::Data { ref data }) => {
+                        let mut out = std::io::stdout();
+                        out.write_all(data)?;
+                        out.flush()?;
+                    }
+                    Some(ChannelMsg::ExtendedData { ref data, ext: 1 }) => {
+                        let mut err = std::io::stderr();
+                        err.write_all(data)?;
+                        err.flush()?;
+                    }
+                    Some(ChannelMsg::ExitStatus { exit_status }) => result = Some(exit_status),
+                    Some(ChannelMsg::ExitSignal { signal_name, .. }) => signal = Some(signal_name),
+                    Some(_) => {}
+                    None => break,
+                },
+            }
+        }
+        match (result, signal) {
+            (Some(status), _) => Ok(status),
+            (None, Some(signal)) => Err(anyhow!("{} was killed by signal {:?}", cmd, signal).into()),
+            // the connection dropped before the command finished, not knowing how it went isn't a success
+            (None, None) => Err(anyhow!("{} ended without an exit status", cmd).into()),
+        }
+    }
+
     async fn execute_stdout(self: &RealSsh, cmd: &str, print_command: bool, prefix_output: bool) -> Result<(), Box<dyn Error>> {
         if print_command {
             cmd.lines().for_each(|l| println!("{} | > {}", self.node_name, l.green()));
         }
 
-        let mut ch = self.client.get_channel().await?;
-        ch.exec(true, cmd).await?;
+        let mut ch = self.open_exec(cmd).await?;
 
         let mut result: Option<_> = None;
         let mut last_char = '\n';
@@ -387,11 +863,9 @@ echo ovs="$(cat /tmp/ovs-$$)";
                 ChannelMsg::Data { ref data } => {
                     last_char = self.print_crypto_vec(data, prefix_output, last_char);
                 }
-                ChannelMsg::ExtendedData { ref data, ext } => {
-                    if ext == 1 {
+                ChannelMsg::ExtendedData { ref data, ext: 1 } => {
                         last_char = self.print_crypto_vec(data, prefix_output, last_char);
                     }
-                }
                 // If we get an exit code report, store it, but crucially don't
                 // assume this message means end of communications. The data might
                 // not be finished yet!
@@ -415,9 +889,18 @@ echo ovs="$(cat /tmp/ovs-$$)";
         cmd.lines().for_each(|l| println!("{} | > {}", self.node_name, l.green()));
         self.execute(cmd).await
     }
+    async fn execute_batch(&self, cmds: &[String]) -> Result<Vec<BatchResult>, Box<dyn Error>> {
+        if cmds.is_empty() {
+            return Ok(vec!());
+        }
+        let result = self.run(&batch_script(cmds)).await
+            .map_err(|e| anyhow!(e.to_string()).context("batch failed"))?;
+        parse_batch_output(&result.stdout, cmds.len())
+            .map_err(|e| anyhow!(e.to_string()).context(format!("batch failed: {}", result.stderr.trim())).into())
+    }
     async fn execute(self: &RealSsh, cmd: &str) -> Result<String, Box<dyn Error>> {
-        let result = self.client.execute(cmd).await.
-            map_err(|e| anyhow!(e).context(format!("{} failed", cmd)))?;
+        let result = self.run(cmd).await.
+            map_err(|e| anyhow!(e.to_string()).context(format!("{} failed", cmd)))?;
         if result.exit_status > 0 {
             return Err(anyhow!(result.stderr).context(format!("{} failed", cmd)).into());
         }
@@ -426,6 +909,71 @@ echo ovs="$(cat /tmp/ovs-$$)";
         }
         Ok(result.stdout)
     }
+    async fn upload(&self, source: &mut dyn UploadSource, remote_path: &str, mode: u32) -> Result<Transfer, Box<dyn Error>> {
+        let (sha256, size) = checksum(source).await?;
+        let dest = shell_quote(remote_path);
+        let existing = self.execute(&format!("sudo sha256sum {} 2>/dev/null || true", dest)).await?;
+        if parse_sha256sum(&existing).as_deref() == Some(sha256.as_str()) {
+            self.execute(&format!("sudo chmod {:o} {}", mode, dest)).await?;
+            return Ok(Transfer { size, resumed_from: size, sha256, unchanged: true });
+        }
+
+        let staging = staging_path(&sha256);
+        self.execute(&format!("mkdir -p {dir} && chmod 700 {dir}", dir = STAGING_DIR)).await?;
+        let sftp = self.sftp().await?;
+        let resumed_from = resume_offset(sftp.metadata(staging.as_str()).await.ok().and_then(|m| m.size), size);
+        let mut flags = OpenFlags::CREATE | OpenFlags::WRITE;
+        if resumed_from == 0 {
+            flags |= OpenFlags::TRUNCATE;
+        }
+        let mut file = sftp.open_with_flags(staging.as_str(), flags).await?;
+        file.seek(SeekFrom::Start(resumed_from)).await?;
+        source.seek(SeekFrom::Start(resumed_from)).await?;
+        tokio::io::copy(source, &mut file).await?;
+        file.shutdown().await?;
+        let _ = sftp.close().await;
+
+        // a staging file that doesn't match is started over by the next upload
+        let uploaded = self.execute(&format!("sha256sum {}", staging)).await?;
+        if parse_sha256sum(&uploaded).as_deref() != Some(sha256.as_str()) {
+            let _ = self.execute(&format!("rm -f {}", staging)).await;
+            return Err(anyhow!("checksum of {} on {} doesn't match after upload", remote_path, self.node_name).into());
+        }
+        self.execute(&format!("sudo install -D -m {:o} {} {} && rm -f {}", mode, staging, dest, staging)).await?;
+        Ok(Transfer { size, resumed_from, sha256, unchanged: false })
+    }
+    async fn download(&self, remote_path: &str, local_path: &Path) -> Result<Transfer, Box<dyn Error>> {
+        let source = shell_quote(remote_path);
+        let sha256 = parse_sha256sum(&self.execute(&format!("sudo sha256sum {}", source)).await?)
+            .ok_or(anyhow!("failed to checksum {} on {}", remote_path, self.node_name))?;
+
+        let part = PathBuf::from(format!("{}.part", local_path.display()));
+        let resumed_from = tokio::fs::metadata(&part).await.map(|m| m.len()).unwrap_or(0);
+        let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(&part).await?;
+
+        let mut ch = self.open_exec(&format!("sudo tail -c +{} {}", resumed_from + 1, source)).await?;
+        let (mut stderr, mut exit_status) = (vec![], None);
+        while let Some(msg) = ch.wait().await {
+            match msg {
+                ChannelMsg::Data { ref data } => file.write_all(data).await?,
+                ChannelMsg::ExtendedData { ref data, ext: 1 } => stderr.extend_from_slice(data),
+                ChannelMsg::ExitStatus { exit_status: status } => exit_status = Some(status),
+                _ => {}
+            }
+        }
+        file.flush().await?;
+        if exit_status != Some(0) {
+            return Err(anyhow!("failed to read {} on {}: {}", remote_path, self.node_name, String::from_utf8_lossy(&stderr).trim()).into());
+        }
+
+        let (downloaded, size) = checksum(&mut tokio::fs::File::open(&part).await?).await?;
+        if downloaded != sha256 {
+            let _ = tokio::fs::remove_file(&part).await;
+            return Err(anyhow!("checksum of {} from {} doesn't match after download", remote_path, self.node_name).into());
+        }
+        tokio::fs::rename(&part, local_path).await?;
+        Ok(Transfer { size, resumed_from, sha256, unchanged: false })
+    }
 }
 
 
@@ -433,6 +981,8 @@ echo ovs="$(cat /tmp/ovs-$$)";
 pub struct SshError {
     pub node_name: String,
     pub error: String,
+    // how long the connection was tried for before it failed
+    pub elapsed: Option<Duration>,
 }
 
 impl fmt::Display for SshError {
@@ -466,6 +1016,8 @@ impl Node {
             port: self.port.or(Some(22)),
             user: self.user.clone().or(cluster.default_user.clone()),
             key: self.key.clone().or(cluster.default_key.clone()),
+            labels: self.labels.clone(),
+            system_reserved: self.system_reserved.clone(),
         }
     }
 }
@@ -507,8 +1059,8 @@ impl Node {
 
 
 impl SshClients {
-    pub fn find(&self, node_name: &str) -> Option<&Box<dyn SshClient>> {
-        self.clients.iter().find(|c| c.node_name() == node_name)
+    pub fn find(&self, node_name: &str) -> Option<&dyn SshClient> {
+        self.clients.iter().find(|c| c.node_name() == node_name).map(|c| c.as_ref())
     }
 
     pub async fn execute(&self, command: &str) -> Vec<(String, Result<String, Box<dyn Error>>)> {
@@ -523,6 +1075,14 @@ impl SshClients {
         }).collect()
     }
 
+    // runs the commands on every node in a single round trip per node
+    pub async fn execute_batch(&self, cmds: &[String]) -> Vec<(String, Result<Vec<BatchResult>, Box<dyn Error>>)> {
+        let fut: FuturesUnordered<_> = self.clients.iter().map(|c| async move {
+            (c.node_name(), c.execute_batch(cmds).await)
+        }).collect();
+        fut.collect().await
+    }
+
     pub async fn execute_noisy(&self, command: &str, args: &[&str]) -> Vec<(String, Result<String, Box<dyn Error>>)> {
         let concat_command = &format!("{} {}", &command, args.join(" "));
         let fut: FuturesUnordered<_> = self.clients.iter().map(|c| {
@@ -535,12 +1095,208 @@ impl SshClients {
             (node_name, r)
         }).collect()
     }
-    pub async fn get_nodes_system_info(&self) -> Vec<Result<HostInfo, Box<dyn Error>>> {
-        let fut: FuturesUnordered<_> = self.clients.iter().map(|c| {
-            c.get_node_system_info()
+    pub async fn get_nodes_system_info(&self) -> Vec<NodeRefresh> {
+        let fut: FuturesUnordered<_> = self.clients.iter().map(|c| async move {
+            let node_name = c.node_name();
+            let start = Instant::now();
+            progress().start(&node_name, "fetching system info");
+            let result = c.get_node_system_info().await;
+            match &result {
+                Ok(_) => progress().finish(&node_name),
+                Err(e) => progress().fail(&node_name, &e.to_string()),
+            }
+            NodeRefresh::new(c.as_ref(), start, false, result)
+        }).collect();
+
+        fut.collect().await
+    }
+
+    // like get_nodes_system_info, but reuses the cached info of a node when its generation hasn't changed since
+    pub async fn get_nodes_system_info_since(&self, cached: &ClusterState) -> Vec<NodeRefresh> {
+        let now = Local::now();
+        let fut: FuturesUnordered<_> = self.clients.iter().map(|c| async move {
+            let node_name = c.node_name();
+            let start = Instant::now();
+            let cached_info = cached.nodes.iter().find(|n| n.node_name == node_name).and_then(|n| n.host_info.as_ref());
+
+            if let Some((info, generation)) = reusable_host_info(cached_info, now) {
+                progress().start(&node_name, "checking for changes");
+                if c.get_node_generation().await.is_ok_and(|g| g == generation) {
+                    progress().finish(&node_name);
+                    return NodeRefresh::new(c.as_ref(), start, true, Ok(info.clone()));
+                }
+            }
+
+            progress().start(&node_name, "fetching system info");
+            let result = c.get_node_system_info().await;
+            match &result {
+                Ok(_) => progress().finish(&node_name),
+                Err(e) => progress().fail(&node_name, &e.to_string()),
+            }
+            NodeRefresh::new(c.as_ref(), start, false, result)
         }).collect();
 
         fut.collect().await
     }
 }
 
+// a node's info, and how long getting it took
+pub struct NodeRefresh {
+    pub node_name: String,
+    pub timing: RefreshTiming,
+    pub result: Result<HostInfo, Box<dyn Error>>,
+}
+
+impl NodeRefresh {
+    fn new(client: &dyn SshClient, start: Instant, cached: bool, result: Result<HostInfo, Box<dyn Error>>) -> Self {
+        NodeRefresh {
+            node_name: client.node_name(),
+            timing: RefreshTiming {
+                connect_ms: client.connect_duration().map(|d| d.as_millis() as u64),
+                info_ms: start.elapsed().as_millis() as u64,
+                cached,
+                connect_error: None,
+            },
+            result,
+        }
+    }
+}
+
+// how long the info collected from a node is reused for while its generation is unchanged, since the generation doesn't cover
+// resource usage
+pub const MAX_CACHED_INFO_AGE: Duration = Duration::from_secs(120);
+
+// the cached info and its generation, if the info is recent enough to be reused
+fn reusable_host_info(cached: Option<&HostInfo>, now: DateTime<Local>) -> Option<(&HostInfo, &str)> {
+    let cached = cached?;
+    let age = now.signed_duration_since(cached.fetched_at?).to_std().ok()?;
+    if age > MAX_CACHED_INFO_AGE {
+        return None;
+    }
+    let generation = cached.system_info.as_ref()?.generation.as_deref()?;
+    Some((cached, generation))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+    use std::time::Duration;
+    use chrono::Local;
+    use crate::skatelet::apply_progress::ApplyEvent;
+    use crate::credentials::BecomeMethod;
+    use crate::ssh::{batch_script, become_command, checksum, parse_batch_output, key_permissions_warning, manifest_path, parse_sha256sum, rejects_progress, report_progress, resume_offset, reusable_host_info, staging_path, unix_line_endings, BatchResult, MAX_CACHED_INFO_AGE};
+    use crate::test_helpers;
+    use crate::test_helpers::temp_dir::TempDir;
+
+    #[test]
+    fn test_unix_line_endings() {
+        assert_eq!("cat <<'EOF' > /etc/skate/x\nline\nEOF\n", unix_line_endings("cat <<'EOF' > /etc/skate/x\r\nline\r\nEOF\r\n"));
+        assert_eq!("printf 'a\\r\\n'", unix_line_endings("printf 'a\\r\\n'"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_key_permissions_warning() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = TempDir::new("key");
+        let key = dir.join("id");
+        std::fs::write(&key, "key").unwrap();
+
+        std::fs::set_permissions(&key, std::fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(None, key_permissions_warning(&key).unwrap());
+        std::fs::set_permissions(&key, std::fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(key_permissions_warning(&key).unwrap().unwrap().contains("mode 644"));
+
+        std::fs::remove_file(&key).unwrap();
+        assert!(key_permissions_warning(&key).unwrap_err().starts_with("failed to read key"));
+    }
+
+    #[test]
+    fn test_become_command() {
+        assert_eq!(None, become_command(BecomeMethod::Sudo, "sudo podman ps", false));
+        assert_eq!(Some("sudo -S -p '' sh -c 'sudo podman ps'".to_string()), become_command(BecomeMethod::Sudo, "sudo podman ps", true));
+
+        // sudo only stands in for itself once doas made the command root
+        let script = become_command(BecomeMethod::Doas, "sudo -n podman ps -a --format '{{.Names}}'", false).unwrap();
+        assert!(script.starts_with("doas sh -c 'sudo() {"), "{}", script);
+        // run here without doas, with the quoted script unquoted by eval
+        let quoted = script.strip_prefix("doas sh -c ").unwrap().replace("podman", "echo podman");
+        let output = Command::new("sh").arg("-c").arg(format!("eval {}", quoted)).output().unwrap();
+        assert_eq!("podman ps -a --format {{.Names}}\n", String::from_utf8_lossy(&output.stdout));
+    }
+
+    #[tokio::test]
+    async fn test_upload_checksum() {
+        let mut source = std::io::Cursor::new(b"hello\n".to_vec());
+        let (sha256, size) = checksum(&mut source).await.unwrap();
+        assert_eq!("5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03", sha256);
+        assert_eq!(6, size);
+        // left at the start to be sent
+        assert_eq!(0, source.position());
+        assert_eq!(".skate-uploads/5891b5b522d5df08", staging_path(&sha256));
+        assert_eq!("/var/lib/skate/incoming/5891b5b522d5df08.yaml", manifest_path("hello\n"));
+
+        assert_eq!(Some(sha256.clone()), parse_sha256sum("5891B5B522D5DF086D0FF0B110FBD9D21BB4FC7163AF34D08286A2E846F6BE03  /etc/skate/x\n"));
+        assert_eq!(None, parse_sha256sum(""));
+        assert_eq!(None, parse_sha256sum("sha256sum: /etc/skate/x: No such file or directory"));
+
+        assert_eq!(0, resume_offset(None, 6));
+        assert_eq!(4, resume_offset(Some(4), 6));
+        assert_eq!(0, resume_offset(Some(7), 6));
+    }
+
+    #[test]
+    fn test_report_progress() {
+        let event = ApplyEvent::CreatingPod { name: "web.shop".to_string() }.to_line();
+        let (mut pending, mut stderr) = (format!("{}\nError: image not kno", event).into_bytes(), vec![]);
+
+        // the partial line waits for the rest of it
+        report_progress("node-1", &mut pending, &mut stderr, false);
+        assert!(stderr.is_empty());
+        assert_eq!(b"Error: image not kno".to_vec(), pending);
+
+        pending.extend_from_slice(b"wn");
+        report_progress("node-1", &mut pending, &mut stderr, true);
+        assert_eq!("Error: image not known", String::from_utf8(stderr).unwrap());
+        assert!(pending.is_empty());
+
+        assert!(rejects_progress(2, "error: unexpected argument '--progress' found\n\nUsage: skatelet apply <COMMAND>\n"));
+        assert!(!rejects_progress(1, "Error: failed to create pod"));
+    }
+
+    #[test]
+    fn test_batch_script() {
+        let cmds = vec![
+            "echo hello".to_string(),
+            "echo oops >&2; exit 3".to_string(),
+            "printf 'a b\\nc'".to_string(),
+        ];
+        let output = Command::new("sh").arg("-c").arg(batch_script(&cmds)).output().unwrap();
+        let results = parse_batch_output(&String::from_utf8_lossy(&output.stdout), cmds.len()).unwrap();
+
+        assert_eq!(results, vec![
+            BatchResult { exit_status: 0, stdout: "hello\n".to_string(), stderr: "".to_string() },
+            BatchResult { exit_status: 3, stdout: "".to_string(), stderr: "oops\n".to_string() },
+            BatchResult { exit_status: 0, stdout: "a b\nc".to_string(), stderr: "".to_string() },
+        ]);
+
+        assert!(parse_batch_output("", 1).is_err());
+    }
+
+    #[test]
+    fn test_reusable_host_info() {
+        let now = Local::now();
+        let mut info = test_helpers::objects::node_state("node-1").host_info.unwrap();
+        info.fetched_at = Some(now - Duration::from_secs(10));
+        // info from a skatelet without generations is always collected again
+        assert!(reusable_host_info(Some(&info), now).is_none());
+
+        info.system_info.as_mut().unwrap().generation = Some("abc".to_string());
+        assert_eq!(Some("abc"), reusable_host_info(Some(&info), now).map(|(_, g)| g));
+
+        info.fetched_at = Some(now - MAX_CACHED_INFO_AGE - Duration::from_secs(1));
+        assert!(reusable_host_info(Some(&info), now).is_none());
+        assert!(reusable_host_info(None, now).is_none());
+    }
+}
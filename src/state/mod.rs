@@ -1,2 +1,3 @@
 
+#[allow(clippy::module_inception)]
 pub mod state;
\ No newline at end of file
@@ -1,14 +1,18 @@
 use crate::config::{cache_dir, Config};
 use crate::filestore::ObjectListItem;
 use anyhow::anyhow;
+use chrono::{DateTime, Local};
 use itertools::Itertools;
-use k8s_openapi::api::core::v1::{Node as K8sNode, NodeAddress, NodeSpec, NodeStatus as K8sNodeStatus, Secret, Service};
+use k8s_openapi::api::core::v1::{Node as K8sNode, NodeAddress, NodeCondition, NodeSpec, NodeStatus as K8sNodeStatus, Secret, Service};
 use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
-use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{ObjectMeta, Time};
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error::Error;
-use std::fs::File;
+use std::fmt::{Display as FmtDisplay, Formatter};
+use std::fs::{File, OpenOptions};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
 use std::path::Path;
 use k8s_openapi::api::apps::v1::{DaemonSet, Deployment};
 use k8s_openapi::api::batch::v1::CronJob;
@@ -20,9 +24,10 @@ use crate::resource::{ResourceType, SupportedResources};
 use crate::skatelet::system::podman::PodmanPodInfo;
 use crate::skatelet::SystemInfo;
 use crate::spec::cert::ClusterIssuer;
+use k8s_openapi::api::policy::v1::PodDisruptionBudget;
 use crate::ssh::HostInfo;
 use crate::state::state::NodeStatus::{Healthy, Unhealthy, Unknown};
-use crate::util::{metadata_name, slugify, tabled_display_option};
+use crate::util::{metadata_name, quantity_to_bytes, quantity_to_cpus, slugify, tabled_display_option, NamespacedName};
 
 #[derive(Serialize, Deserialize, Clone, Debug, Display, PartialEq, Default)]
 pub enum NodeStatus {
@@ -32,6 +37,54 @@ pub enum NodeStatus {
     Unhealthy,
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Display, PartialEq)]
+pub enum NodeConditionType {
+    Ready,
+    DiskPressure,
+    NetworkUnavailable,
+    SkateletOutdated,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Display, PartialEq)]
+pub enum ConditionStatus {
+    True,
+    False,
+    Unknown,
+}
+
+// what was last observed about one aspect of the node, the transition time only moves when the status changes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NodeStateCondition {
+    #[serde(rename = "type")]
+    pub type_: NodeConditionType,
+    pub status: ConditionStatus,
+    pub reason: String,
+    pub message: Option<String>,
+    pub last_transition_time: DateTime<Local>,
+}
+
+// a change to the node's podman, kernel or cgroup driver, seen between two refreshes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HostChange {
+    pub name: String,
+    pub previous: String,
+    pub current: String,
+    pub seen_at: DateTime<Local>,
+}
+
+impl FmtDisplay for HostChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} changed from {} to {}", self.name, self.previous, self.current)
+    }
+}
+
+// how long a host change is warned about after it was seen
+const HOST_CHANGE_WARNING_HOURS: i64 = 24;
+
+// a node is under pressure when free disk or memory drops below these percentages
+const DISK_PRESSURE_FREE_PERCENT: u64 = 10;
+const MEMORY_PRESSURE_FREE_PERCENT: u64 = 5;
 
 #[derive(Tabled, Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 #[tabled(rename_all = "UPPERCASE")]
@@ -42,6 +95,47 @@ pub struct NodeState {
     pub message: Option<String>,
     #[tabled(skip)]
     pub host_info: Option<HostInfo>,
+    // labels from the node's config
+    #[tabled(skip)]
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    // the cpu and memory reserved in the node's config
+    #[tabled(skip)]
+    #[serde(default)]
+    pub system_reserved: BTreeMap<String, String>,
+    // the status above is the summary of the Ready condition
+    #[tabled(skip)]
+    #[serde(default)]
+    pub conditions: Vec<NodeStateCondition>,
+    // the host versions last seen on the node, kept while it's unreachable
+    #[tabled(skip)]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub host_versions: BTreeMap<String, String>,
+    #[tabled(skip)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub host_changes: Vec<HostChange>,
+    #[tabled(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_timing: Option<RefreshTiming>,
+}
+
+// how long the last refresh of the node took, to spot the nodes that slow every command down
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshTiming {
+    pub connect_ms: Option<u64>,
+    pub info_ms: u64,
+    // only the generation was checked, the cached info was reused
+    pub cached: bool,
+    // the node couldn't be connected to, connect_ms is how long it was tried for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_error: Option<String>,
+}
+
+impl RefreshTiming {
+    pub fn total_ms(&self) -> u64 {
+        self.connect_ms.unwrap_or_default() + self.info_ms
+    }
 }
 
 impl From<NodeState> for K8sNode {
@@ -62,6 +156,22 @@ impl From<NodeState> for K8sNode {
 
         spec.unschedulable = Some(!val.schedulable());
 
+        let heartbeat = val.host_info.as_ref().and_then(|h| h.fetched_at).map(|t| Time(t.into()));
+        let mut conditions: Vec<_> = val.conditions.iter().map(|c| NodeCondition {
+            type_: c.type_.to_string(),
+            status: c.status.to_string(),
+            reason: Some(c.reason.clone()),
+            message: c.message.clone(),
+            last_transition_time: Some(Time(c.last_transition_time.into())),
+            last_heartbeat_time: heartbeat.clone(),
+        }).collect();
+        // state from before conditions were kept
+        if val.conditions.is_empty() {
+            conditions.push(pressure_condition("DiskPressure", val.disk_pressure()));
+        }
+        conditions.push(pressure_condition("MemoryPressure", val.memory_pressure()));
+        status.conditions = Some(conditions);
+
         let sys_info = val.host_info.as_ref().and_then(|h| h.system_info.clone());
 
 
@@ -71,9 +181,12 @@ impl From<NodeState> for K8sNode {
                     ("cpu".to_string(), Quantity(format!("{}", si.num_cpus))),
                     ("memory".to_string(), Quantity(format!("{} Mib", si.total_memory_mib))),
                 ])),
+                 // no more than what's idle, nor what isn't reserved for the system
                  (Some(BTreeMap::<String, Quantity>::from([
-                     ("cpu".to_string(), Quantity(format!("{}", (si.num_cpus as f32) * (100.00 - si.cpu_usage) / 100.0))),
-                     ("memory".to_string(), Quantity(format!("{} Mib", si.total_memory_mib - si.used_memory_mib))),
+                     ("cpu".to_string(), Quantity(format!("{}", ((si.num_cpus as f32) * (100.00 - si.cpu_usage) / 100.0)
+                         .min((si.num_cpus as f32 - val.reserved_cpus() as f32).max(0.0))))),
+                     ("memory".to_string(), Quantity(format!("{} Mib", (si.total_memory_mib - si.used_memory_mib)
+                         .min(si.total_memory_mib.saturating_sub(val.reserved_system_memory() / 1024 / 1024))))),
                  ]))), ({
                     let mut addresses = vec![
                         NodeAddress {
@@ -89,11 +202,11 @@ impl From<NodeState> for K8sNode {
                     }
                     Some(addresses)
                 }), (
-                     Some(BTreeMap::<String, String>::from([
+                     Some(val.labels.clone().into_iter().chain([
                          ("skate.io/arch".to_string(), si.platform.arch.clone()),
                          ("skate.io/nodename".to_string(), val.node_name.clone()),
                          ("skate.io/hostname".to_string(), si.hostname.clone()),
-                     ]))
+                     ]).collect())
                  ))
             }
             None => (None, None, None, None)
@@ -108,7 +221,139 @@ impl From<NodeState> for K8sNode {
     }
 }
 
+fn pressure_condition(type_: &str, pressure: Option<String>) -> NodeCondition {
+    let (status, reason) = match pressure {
+        Some(_) => ("True", format!("SkateletHas{}", type_)),
+        None => ("False", format!("SkateletHasNo{}", type_)),
+    };
+    NodeCondition {
+        type_: type_.to_string(),
+        status: status.to_string(),
+        reason: Some(reason),
+        message: pressure,
+        ..Default::default()
+    }
+}
+
+// the versions are tags when released, builds from a commit are never outdated
+fn skatelet_outdated(skatelet: &str, skate: &str) -> bool {
+    let parse = |v: &str| semver::Version::parse(v.trim_start_matches('v')).ok();
+    match (parse(skatelet), parse(skate)) {
+        (Some(skatelet), Some(skate)) => skatelet < skate,
+        _ => false,
+    }
+}
+
 impl NodeState {
+    pub fn condition(&self, type_: NodeConditionType) -> Option<&NodeStateCondition> {
+        self.conditions.iter().find(|c| c.type_ == type_)
+    }
+
+    // the conditions as of the node's host info, or unknown when the node couldn't be reached
+    fn observe_conditions(&self, reachable: bool) -> Vec<(NodeConditionType, ConditionStatus, &'static str, Option<String>)> {
+        use NodeConditionType::*;
+        let info = match (reachable, self.host_info.as_ref()) {
+            (true, Some(info)) => info,
+            _ => return [Ready, DiskPressure, NetworkUnavailable, SkateletOutdated].into_iter()
+                .map(|t| (t, ConditionStatus::Unknown, "NodeUnreachable", Some("no info was collected from the node".to_string())))
+                .collect(),
+        };
+        let flag = |b: bool| if b { ConditionStatus::True } else { ConditionStatus::False };
+
+        let ready = match info.healthy() {
+            Ok(_) => (Ready, ConditionStatus::True, "SkateletReady", None),
+            Err(errs) => (Ready, ConditionStatus::False, "SkateletNotReady", Some(errs.join(". "))),
+        };
+        let disk = self.disk_pressure();
+        let disk = (DiskPressure, flag(disk.is_some()), if disk.is_some() { "SkateletHasDiskPressure" } else { "SkateletHasNoDiskPressure" }, disk);
+        let network = match info.system_info.as_ref().and_then(|si| si.internal_ip_address.as_ref()) {
+            Some(_) => (NetworkUnavailable, ConditionStatus::False, "InternalIPFound", None),
+            None => (NetworkUnavailable, ConditionStatus::True, "NoInternalIP", Some("the node reported no internal ip address".to_string())),
+        };
+        let skate_version = crate::util::version(false);
+        let outdated = match info.skatelet_version.as_deref() {
+            Some(v) if skatelet_outdated(v, &skate_version) =>
+                (SkateletOutdated, ConditionStatus::True, "SkateletOlderThanSkate", Some(format!("skatelet {} is older than skate {}", v, skate_version))),
+            Some(_) => (SkateletOutdated, ConditionStatus::False, "SkateletUpToDate", None),
+            None => (SkateletOutdated, ConditionStatus::Unknown, "SkateletNotFound", None),
+        };
+        vec![ready, disk, network, outdated]
+    }
+
+    // compares the host versions to the ones last seen, keeping the recent changes
+    pub fn track_host_changes(&mut self, previous: Option<&NodeState>, now: DateTime<Local>) {
+        let cutoff = now - chrono::Duration::hours(HOST_CHANGE_WARNING_HOURS);
+        let last_seen = previous.map(|p| p.host_versions.clone()).unwrap_or_default();
+        self.host_changes = previous.map(|p| p.host_changes.clone()).unwrap_or_default();
+        self.host_changes.retain(|c| c.seen_at > cutoff);
+
+        let info = match self.host_info.as_ref() {
+            Some(info) => info,
+            None => {
+                self.host_versions = last_seen;
+                return;
+            }
+        };
+        self.host_versions = info.host_versions();
+        for (name, current) in self.host_versions.iter() {
+            match last_seen.get(name) {
+                Some(previous) if previous != current => self.host_changes.push(HostChange {
+                    name: name.clone(),
+                    previous: previous.clone(),
+                    current: current.clone(),
+                    seen_at: now,
+                }),
+                _ => {}
+            }
+        }
+    }
+
+    // updates the conditions from the host info, and the status and message from the Ready condition
+    pub fn update_conditions(&mut self, reachable: bool, now: DateTime<Local>) {
+        self.conditions = self.observe_conditions(reachable).into_iter().map(|(type_, status, reason, message)| {
+            let last_transition_time = self.condition(type_)
+                .filter(|c| c.status == status)
+                .map(|c| c.last_transition_time)
+                .unwrap_or(now);
+            NodeStateCondition { type_, status, reason: reason.to_string(), message, last_transition_time }
+        }).collect();
+
+        let ready = self.condition(NodeConditionType::Ready).cloned();
+        match ready.map(|c| (c.status, c.message)) {
+            Some((ConditionStatus::True, _)) => (self.status, self.message) = (Healthy, None),
+            Some((ConditionStatus::False, message)) => (self.status, self.message) = (Unhealthy, message),
+            _ => self.status = Unknown,
+        }
+    }
+
+    pub fn reserved_cpus(&self) -> f64 {
+        self.system_reserved.get("cpu").and_then(|c| quantity_to_cpus(c)).unwrap_or(0.0)
+    }
+
+    pub fn reserved_system_memory(&self) -> u64 {
+        self.system_reserved.get("memory").and_then(|m| quantity_to_bytes(m)).unwrap_or(0)
+    }
+
+    // disk_pressure returns a message describing the first filesystem that is low on space, if any
+    pub fn disk_pressure(&self) -> Option<String> {
+        let si = self.host_info.as_ref()?.system_info.as_ref()?;
+        [("/", &si.root_disk), ("/var/lib/containers", &si.containers_disk), ("/var/lib/skate", &si.skate_disk)].into_iter()
+            .filter_map(|(path, disk)| disk.as_ref().map(|d| (path, d)))
+            .find(|(_, d)| d.total_space_mib > 0 && d.available_space_mib * 100 / d.total_space_mib < DISK_PRESSURE_FREE_PERCENT)
+            .map(|(path, d)| format!("{} has {} MiB free of {} MiB", path, d.available_space_mib, d.total_space_mib))
+    }
+
+    // memory_pressure returns a message describing the memory shortage, if any
+    pub fn memory_pressure(&self) -> Option<String> {
+        let si = self.host_info.as_ref()?.system_info.as_ref()?;
+        if si.total_memory_mib == 0 {
+            return None;
+        }
+        let free = si.total_memory_mib.saturating_sub(si.used_memory_mib);
+        (free * 100 / si.total_memory_mib < MEMORY_PRESSURE_FREE_PERCENT)
+            .then(|| format!("{} MiB memory free of {} MiB", free, si.total_memory_mib))
+    }
+
     pub fn filter_pods(&self, f: &dyn Fn(&PodmanPodInfo) -> bool) -> Vec<PodmanPodInfo> {
         self.host_info.as_ref().and_then(|h| {
             h.system_info.clone().and_then(|i| {
@@ -125,6 +370,7 @@ impl NodeState {
             SupportedResources::Secret(secret) => self.reconcile_secret_creation(secret),
             SupportedResources::Service(service) => self.reconcile_service_creation(service),
             SupportedResources::ClusterIssuer(issuer) => self.reconcile_cluster_issuer_creation(issuer),
+            SupportedResources::PodDisruptionBudget(pdb) => self.reconcile_pod_disruption_budget_creation(pdb),
             // This is a no-op since the only thing that happens when during the Deployment's ScheduledOperation is that we write the manifest to file for future reference
             // The state change is all done by the Pods' scheduled operations
             SupportedResources::Deployment(_) => { /* nothing to do */Ok(ReconciledResult::default()) }
@@ -140,6 +386,7 @@ impl NodeState {
             SupportedResources::Secret(secret) => self.reconcile_secret_deletion(secret),
             SupportedResources::Service(service) => self.reconcile_service_deletion(service),
             SupportedResources::ClusterIssuer(issuer) => self.reconcile_cluster_issuer_deletion(issuer),
+            SupportedResources::PodDisruptionBudget(pdb) => self.reconcile_pod_disruption_budget_deletion(pdb),
             SupportedResources::Deployment(deployment) => self.reconcile_deployment_deletion(deployment),
             SupportedResources::DaemonSet(daemonset) => self.reconcile_daemonset_deletion(daemonset),
         }
@@ -163,6 +410,23 @@ impl NodeState {
 
         Ok(ReconciledResult::removed())
     }
+    // the list is created when missing, so that a budget applied with the pods it covers is respected when they're scheduled
+    fn reconcile_pod_disruption_budget_creation(&mut self, pdb: &PodDisruptionBudget) -> Result<ReconciledResult, Box<dyn Error>> {
+        if let Some(si) = self.host_info.as_mut().and_then(|hi| hi.system_info.as_mut()) {
+            si.pod_disruption_budgets.get_or_insert_with(Vec::new).push(ObjectListItem::from(pdb));
+        }
+
+        Ok(ReconciledResult::added())
+    }
+    fn reconcile_pod_disruption_budget_deletion(&mut self, pdb: &PodDisruptionBudget) -> Result<ReconciledResult, Box<dyn Error>> {
+        self.host_info.as_mut().and_then(|hi| {
+            hi.system_info.as_mut().and_then(|si| {
+                si.pod_disruption_budgets.as_mut().map(|i| i.retain(|i| i.name != metadata_name(pdb)))
+            })
+        });
+
+        Ok(ReconciledResult::removed())
+    }
     fn reconcile_service_creation(&mut self, service: &Service) -> Result<ReconciledResult, Box<dyn Error>> {
         self.host_info.as_mut().and_then(|hi| {
             hi.system_info.as_mut().and_then(|si| {
@@ -270,21 +534,30 @@ impl NodeState {
     }
 
     fn reconcile_daemonset_deletion(&mut self, daemonset: &DaemonSet) -> Result<ReconciledResult, Box<dyn Error>> {
-        let name = metadata_name(daemonset).to_string();
+        let name = metadata_name(daemonset);
+        self.reconcile_owner_deletion(&OwnerRef::new(ResourceType::DaemonSet, &name));
         self.host_info.as_mut().and_then(|hi| hi.system_info.as_mut().and_then(|si|
-            si.pods.as_mut().map(|pods| pods.iter().filter(|p| !p.daemonset().is_empty() && p.daemonset() != name))
+            si.daemonsets.as_mut().map(|d| d.retain(|d| d.name != name))
         ));
         Ok(ReconciledResult::removed())
     }
 
     fn reconcile_deployment_deletion(&mut self, deployment: &Deployment) -> Result<ReconciledResult, Box<dyn Error>> {
-        let name = metadata_name(deployment).to_string();
+        let name = metadata_name(deployment);
+        self.reconcile_owner_deletion(&OwnerRef::new(ResourceType::Deployment, &name));
         self.host_info.as_mut().and_then(|hi| hi.system_info.as_mut().and_then(|si|
-            si.pods.as_mut().map(|pods| pods.iter().filter(|p| !p.deployment().is_empty() && p.deployment() != name))
+            si.deployments.as_mut().map(|d| d.retain(|d| d.name != name))
         ));
         Ok(ReconciledResult::removed())
     }
 
+    // removes all pods created by the owner from this node's state
+    fn reconcile_owner_deletion(&mut self, owner: &OwnerRef) {
+        self.host_info.as_mut().and_then(|hi| hi.system_info.as_mut().and_then(|si|
+            si.pods.as_mut().map(|pods| pods.retain(|p| OwnerRef::of(p).as_ref() != Some(owner)))
+        ));
+    }
+
     // whether we can schedule workloads on this node
     pub fn schedulable(&self) -> bool {
         if self.status != Healthy {
@@ -296,6 +569,36 @@ impl NodeState {
     }
 }
 
+// the resource that created a pod, recorded on the pod via its skate.io/deployment, skate.io/daemonset or skate.io/cronjob label
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OwnerRef {
+    pub resource_type: ResourceType,
+    pub name: NamespacedName,
+}
+
+impl OwnerRef {
+    pub fn new(resource_type: ResourceType, name: &NamespacedName) -> Self {
+        OwnerRef { resource_type, name: name.clone() }
+    }
+
+    pub fn of(pod: &PodmanPodInfo) -> Option<Self> {
+        let namespace = pod.namespace();
+        [
+            (ResourceType::Deployment, pod.deployment()),
+            (ResourceType::DaemonSet, pod.daemonset()),
+            (ResourceType::CronJob, pod.cronjob()),
+        ].into_iter()
+            .find(|(_, name)| !name.is_empty())
+            .map(|(resource_type, name)| OwnerRef { resource_type, name: NamespacedName::new(&name, &namespace) })
+    }
+}
+
+impl FmtDisplay for OwnerRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.resource_type, self.name)
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct ClusterState {
     pub cluster_name: String,
@@ -333,22 +636,30 @@ impl ReconciledResult {
 }
 
 impl ClusterState {
-    fn path(cluster_name: &str) -> String {
-        format!("{}/{}.state", cache_dir(), slugify(cluster_name))
+    pub(crate) fn path(cluster_name: &str) -> String {
+        Path::new(&cache_dir()).join(format!("{}.state", slugify(cluster_name))).to_string_lossy().to_string()
     }
-    #[allow(unused)]
     pub fn persist(&self) -> Result<(), Box<dyn Error>> {
-        let state_file = File::create(Path::new(ClusterState::path(&self.cluster_name.clone()).as_str()))
+        self.write_to(Path::new(ClusterState::path(&self.cluster_name.clone()).as_str()))
+    }
+
+    // the state holds secrets, so only the user can read it
+    pub(crate) fn write_to(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let state_file = options.open(path)
             .map_err(|e| anyhow!("failed to open or create state file").context(e))?;
         serde_json::to_writer(state_file, self)
             .map_err(|e| anyhow!("failed to serialize state").context(e))?;
         Ok(())
     }
 
+    // loads the state persisted by the last refresh, an empty one if there is none
     pub fn load(cluster_name: &str) -> Result<Self, Box<dyn Error>> {
-        let file = File::create(ClusterState::path(cluster_name)).map_err(|e| anyhow!("failed to open or create state file").context(e))?;
-
-        let result = serde_json::from_reader::<_, ClusterState>(file).map_err(|e| anyhow!("failed to parse cluster state").context(e));
+        let result = File::open(ClusterState::path(cluster_name)).map_err(|e| anyhow!("failed to open state file").context(e))
+            .and_then(|file| serde_json::from_reader::<_, ClusterState>(file).map_err(|e| anyhow!("failed to parse cluster state").context(e)));
 
         match result {
             Ok(state) => Ok(state),
@@ -393,6 +704,12 @@ impl ClusterState {
     }
 
 
+    pub fn track_host_changes(&mut self, previous: &ClusterState, now: DateTime<Local>) {
+        for node in self.nodes.iter_mut() {
+            node.track_host_changes(previous.nodes.iter().find(|n| n.node_name == node.node_name), now);
+        }
+    }
+
     pub fn reconcile_all_nodes(&mut self, cluster_name: &str, config: &Config, host_info: &[HostInfo]) -> Result<ReconciledResult, Box<dyn Error>> {
         let cluster = config.active_cluster(Some(cluster_name.to_string()))?;
 
@@ -419,6 +736,9 @@ impl ClusterState {
                     status: Unknown,
                     message: None,
                     host_info: None,
+                    labels: n.labels.clone(),
+                    system_reserved: n.system_reserved.clone(),
+                    ..Default::default()
                 }),
                 false => None
             }
@@ -428,23 +748,21 @@ impl ClusterState {
 
 
         let mut updated = 0;
+        let now = Local::now();
         // now that we have our list, go through and mark them healthy or unhealthy
         self.nodes = self.nodes.iter().map(|node| {
             let mut node = node.clone();
+            let node_config = cluster.nodes.iter().find(|n| n.name == node.node_name);
+            node.labels = node_config.map(|n| n.labels.clone()).unwrap_or_default();
+            node.system_reserved = node_config.map(|n| n.system_reserved.clone()).unwrap_or_default();
             match host_info.iter().find(|h| h.node_name == node.node_name) {
                 Some(info) => {
                     updated += 1;
-                    (node.status, node.message) = match info.healthy() {
-                        Ok(_) => (Healthy, None),
-                        Err(errs) => {
-                            let err_string = errs.join(". ").to_string();
-                            (Unhealthy, Some(err_string))
-                        }
-                    };
-                    node.host_info = Some(info.clone())
+                    node.host_info = Some(info.clone());
+                    node.update_conditions(true, now);
                 }
                 None => {
-                    node.status = Unknown;
+                    node.update_conditions(false, now);
                 }
             };
             node
@@ -492,9 +810,37 @@ impl ClusterState {
         self.filter_pods(&|p| p.deployment() == name && p.namespace() == namespace)
     }
 
+    // groups the pods created by deployments, daemonsets and cronjobs across all nodes by their owner
+    pub fn ownership_index(&self) -> HashMap<OwnerRef, Vec<(PodmanPodInfo, &NodeState)>> {
+        let mut index: HashMap<OwnerRef, Vec<_>> = HashMap::new();
+        for (pod, node) in self.filter_pods(&|_| true) {
+            if let Some(owner) = OwnerRef::of(&pod) {
+                index.entry(owner).or_default().push((pod, node));
+            }
+        }
+        index
+    }
+
+    pub fn owned_pods(&self, owner: &OwnerRef) -> Vec<(PodmanPodInfo, &NodeState)> {
+        self.ownership_index().remove(owner).unwrap_or_default()
+    }
+
+    // pods whose owner no longer has a manifest on any node
+    pub fn orphaned_pods(&self) -> Vec<(OwnerRef, PodmanPodInfo, &NodeState)> {
+        let owners: HashSet<OwnerRef> = self.catalogue(None, &[ResourceType::Deployment, ResourceType::DaemonSet, ResourceType::CronJob]).into_iter()
+            .map(|item| OwnerRef::new(item.object.resource_type.clone(), &item.object.name))
+            .collect();
+
+        self.ownership_index().into_iter()
+            .filter(|(owner, _)| !owners.contains(owner))
+            .flat_map(|(owner, pods)| pods.into_iter().map(move |(pod, node)| (owner.clone(), pod, node)))
+            .sorted_by_key(|(_, pod, node)| (node.node_name.clone(), pod.name.clone()))
+            .collect()
+    }
+
     // the catalogue is the list of 'applied' resources. 
     // does not include pods created due to another resource being applied
-    pub fn catalogue_mut(&mut self, filter_node: Option<&str>, filter_types: &[ResourceType]) -> Vec<MutCatalogueItem> {
+    pub fn catalogue_mut(&mut self, filter_node: Option<&str>, filter_types: &[ResourceType]) -> Vec<MutCatalogueItem<'_>> {
         self.nodes.iter_mut()
             .filter(|n|
                 filter_node.is_none() || n.node_name == filter_node.unwrap()
@@ -508,7 +854,7 @@ impl ClusterState {
             .unique_by(|x| format!("{}-{}", x.object.resource_type, x.object.name)).collect()
     }
 
-    pub fn catalogue(&self, filter_node: Option<&str>, filter_types: &[ResourceType]) -> Vec<CatalogueItem> {
+    pub fn catalogue(&self, filter_node: Option<&str>, filter_types: &[ResourceType]) -> Vec<CatalogueItem<'_>> {
         self.nodes.iter()
             .filter(|n|
                 filter_node.is_none() || n.node_name == filter_node.unwrap()
@@ -533,6 +879,7 @@ macro_rules! extract_mappings {
             (ResourceType::Service, $si.services.$suffixFunc()),
             (ResourceType::Secret, $si.secrets.$suffixFunc()),
             (ResourceType::ClusterIssuer, $si.cluster_issuers.$suffixFunc()),
+            (ResourceType::PodDisruptionBudget, $si.pod_disruption_budgets.$suffixFunc()),
             )
     };
 }
@@ -589,3 +936,98 @@ pub struct CatalogueItem<'a> {
     pub node: String,
 }
 
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Local};
+    use k8s_openapi::api::apps::v1::Deployment;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use crate::filestore::ObjectListItem;
+    use crate::resource::ResourceType;
+    use crate::state::state::{skatelet_outdated, ConditionStatus, HostChange, NodeConditionType, NodeStatus, OwnerRef};
+    use crate::test_helpers::objects::{cluster_state, node_state, pod, WithPod};
+    use crate::util::NamespacedName;
+
+    #[test]
+    fn test_ownership_index_and_orphans() {
+        let mut node1 = node_state("node-1")
+            .with_pod(&pod("web-0", "default", &[("skate.io/deployment", "web")]))
+            .with_pod(&pod("old-0", "default", &[("skate.io/deployment", "old")]));
+        let node2 = node_state("node-2")
+            .with_pod(&pod("web-1", "default", &[("skate.io/deployment", "web")]))
+            .with_pod(&pod("backup", "default", &[("skate.io/cronjob", "backup")]))
+            .with_pod(&pod("standalone", "default", &[]));
+
+        let deployment = Deployment { metadata: ObjectMeta::from(NamespacedName::new("web", "default")), ..Default::default() };
+        node1.host_info.as_mut().unwrap().system_info.as_mut().unwrap().deployments = Some(vec![ObjectListItem::from(&deployment)]);
+
+        let state = cluster_state(vec![node1, node2]);
+
+        let index = state.ownership_index();
+        assert_eq!(index.len(), 3);
+
+        let web = state.owned_pods(&OwnerRef::new(ResourceType::Deployment, &NamespacedName::new("web", "default")));
+        let web_nodes: Vec<_> = web.iter().map(|(_, n)| n.node_name.as_str()).collect();
+        assert_eq!(web_nodes, vec!["node-1", "node-2"]);
+
+        let orphans: Vec<_> = state.orphaned_pods().into_iter().map(|(o, p, n)| (o.to_string(), p.name, n.node_name.clone())).collect();
+        assert_eq!(orphans, vec![
+            ("deployment old.default".to_string(), "old-0.default".to_string(), "node-1".to_string()),
+            ("cronjob backup.default".to_string(), "backup.default".to_string(), "node-2".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_track_host_changes() {
+        let (before, now) = (Local::now() - Duration::hours(25), Local::now());
+        let mut seen = node_state("node-1");
+        seen.track_host_changes(None, before);
+        assert!(seen.host_changes.is_empty());
+        assert_eq!(Some(&"3.6.0".to_string()), seen.host_versions.get("podman"));
+
+        // unreachable, the versions are kept to compare against once it's back
+        let mut unreachable = node_state("node-1");
+        unreachable.host_info = None;
+        unreachable.track_host_changes(Some(&seen), before);
+        assert_eq!(seen.host_versions, unreachable.host_versions);
+
+        let mut upgraded = node_state("node-1");
+        upgraded.host_info.as_mut().unwrap().podman_version = Some("5.0.1".to_string());
+        upgraded.track_host_changes(Some(&unreachable), before);
+        assert_eq!(vec![HostChange { name: "podman".to_string(), previous: "3.6.0".to_string(), current: "5.0.1".to_string(), seen_at: before }], upgraded.host_changes);
+        assert_eq!("podman changed from 3.6.0 to 5.0.1", upgraded.host_changes[0].to_string());
+
+        let mut later = upgraded.clone();
+        later.track_host_changes(Some(&upgraded), now);
+        assert!(later.host_changes.is_empty());
+    }
+
+    #[test]
+    fn test_update_conditions() {
+        let (before, now) = (Local::now() - Duration::minutes(10), Local::now());
+        let mut node = node_state("node-1");
+        node.host_info.as_mut().unwrap().system_info.as_mut().unwrap().internal_ip_address = Some("10.0.0.1".to_string());
+        node.update_conditions(true, before);
+        assert_eq!(NodeStatus::Healthy, node.status);
+        assert_eq!(4, node.conditions.len());
+        assert_eq!(ConditionStatus::True, node.condition(NodeConditionType::Ready).unwrap().status);
+        assert_eq!(ConditionStatus::False, node.condition(NodeConditionType::NetworkUnavailable).unwrap().status);
+
+        // only conditions whose status changed get a new transition time
+        node.host_info.as_mut().unwrap().system_info.as_mut().unwrap().cordoned = true;
+        node.update_conditions(true, now);
+        assert_eq!(NodeStatus::Unhealthy, node.status);
+        assert_eq!(Some("Node is cordoned".to_string()), node.message);
+        let ready = node.condition(NodeConditionType::Ready).unwrap();
+        assert_eq!((ConditionStatus::False, "SkateletNotReady", now), (ready.status, ready.reason.as_str(), ready.last_transition_time));
+        assert_eq!(before, node.condition(NodeConditionType::DiskPressure).unwrap().last_transition_time);
+
+        node.update_conditions(false, now);
+        assert_eq!(NodeStatus::Unknown, node.status);
+        assert!(node.conditions.iter().all(|c| c.status == ConditionStatus::Unknown && c.reason == "NodeUnreachable"));
+
+        assert!(skatelet_outdated("0.1.0", "v0.2.0"));
+        assert!(!skatelet_outdated("v0.2.0", "0.2.0"));
+        assert!(!skatelet_outdated("0.1.0", "abc1234"));
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::time::Duration;
+use anyhow::anyhow;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use crate::apply::{read_manifests, Apply, ApplyDeps};
+use crate::config::{cache_dir, Access, Config};
+use crate::errors::SkateError;
+use crate::resource::{ResourceType, SupportedResources};
+use crate::scheduler::DefaultScheduler;
+use crate::skate::ConfigFileArgs;
+use crate::util::{hash_string, slugify, CHECKBOX_EMOJI};
+
+#[derive(Debug, Args)]
+pub struct SyncArgs {
+    #[arg(long, long_help = "Url of the git repository holding the manifests.")]
+    pub repo: String,
+    #[arg(long, default_value = ".", long_help = "Directory in the repository to read manifests from.")]
+    pub path: String,
+    #[arg(long, long_help = "Branch to sync, defaults to the repository's default branch.")]
+    pub branch: Option<String>,
+    #[arg(long, long_help = "Keep syncing at this interval, eg 30s, 5m or 1h. Syncs once if not set.")]
+    pub interval: Option<String>,
+    #[arg(long, long_help = "Delete resources that were removed from the repository.")]
+    pub prune: bool,
+    #[arg(long, long_help = "Will not affect the cluster if set to true")]
+    pub dry_run: bool,
+    #[command(flatten)]
+    pub config: ConfigFileArgs,
+}
+
+pub trait SyncDeps: ApplyDeps {}
+
+pub struct Sync<D: SyncDeps> {
+    pub deps: D,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct SyncedResource {
+    kind: String,
+    name: String,
+    namespace: String,
+    hash: String,
+}
+
+// what was last applied from the repository, so that only changes are applied and removals can be pruned
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+struct SyncState {
+    revision: String,
+    resources: BTreeMap<String, SyncedResource>,
+}
+
+impl SyncState {
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(SyncState::default());
+        }
+        Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    fn persist(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+impl<D: SyncDeps> Sync<D> {
+    pub async fn sync(&self, args: SyncArgs) -> Result<(), SkateError> {
+        let interval = args.interval.as_deref().map(parse_interval).transpose()?;
+
+        loop {
+            let result = self.sync_once(&args).await;
+            match interval {
+                None => return result,
+                Some(interval) => {
+                    if let Err(e) = result {
+                        eprintln!("sync failed: {}", e);
+                    }
+                    tokio::time::sleep(interval).await;
+                }
+            }
+        }
+    }
+
+    async fn sync_once(&self, args: &SyncArgs) -> Result<(), SkateError> {
+        let mut config = Config::load(args.config.skateconfig.clone())?;
+        if args.config.context.is_some() {
+            config.current_context = args.config.context.clone();
+        }
+        let cluster = config.authorized_cluster(config.current_context.clone(), Access::Deploy)?.clone();
+
+        let dir = PathBuf::from(cache_dir()).join("sync");
+        fs::create_dir_all(&dir).map_err(|e| anyhow!(e).context("failed to create sync directory"))?;
+        let slug = slugify(format!("{}-{}-{}", cluster.name, args.repo, args.branch.clone().unwrap_or_default()));
+        let checkout = dir.join(&slug);
+        let state_file = dir.join(format!("{}.yaml", slug));
+
+        let revision = checkout_repo(&args.repo, args.branch.as_deref(), &checkout)?;
+        let resources = read_manifests(manifest_files(&checkout.join(&args.path))?)?;
+
+        let previous = SyncState::load(&state_file)?;
+        let SyncPlan { changed, removed, next } = plan_sync(&previous, &revision, resources, args.prune);
+
+        if changed.is_empty() && (removed.is_empty() || !args.prune) {
+            println!("{} up to date at {}", CHECKBOX_EMOJI, revision);
+            if !args.dry_run {
+                next.persist(&state_file)?;
+            }
+            return Ok(());
+        }
+
+        if !changed.is_empty() {
+            println!("applying {} changed resources at {}", changed.len(), revision);
+            Apply::<D>::apply_supported_resources(&self.deps, &config, changed, args.dry_run, false, &DefaultScheduler::default()).await?;
+        }
+
+        if args.prune && !removed.is_empty() {
+            self.prune(&config, &removed, args.dry_run).await?;
+        }
+
+        if !args.dry_run {
+            next.persist(&state_file)?;
+        }
+        Ok(())
+    }
+
+    async fn prune(&self, config: &Config, removed: &[SyncedResource], dry_run: bool) -> Result<(), SkateError> {
+        let cluster = config.active_cluster(config.current_context.clone())?;
+        cluster.authorize_namespaces(&removed.iter().map(|r| r.namespace.as_str()).collect::<Vec<_>>())?;
+        let (conns, errors) = self.deps.get().cluster_connect(cluster).await;
+        if let Some(errors) = errors {
+            eprintln!("{}", errors)
+        }
+        let conns = conns.ok_or("failed to get cluster connections".to_string())?;
+
+        let mut errors = vec!();
+        for resource in removed {
+            let r_type = ResourceType::from_str(&resource.kind).map_err(|e| anyhow!(e).context(format!("unknown kind {}", resource.kind)))?;
+            if dry_run {
+                println!("would prune {} {}.{}", r_type, resource.name, resource.namespace);
+                continue;
+            }
+            for conn in conns.clients.iter() {
+                if let Err(e) = conn.remove_resource(r_type.clone(), &resource.name, &resource.namespace).await {
+                    errors.push(format!("{} - {}", conn.node_name(), e));
+                }
+            }
+            println!("{} pruned {} {}.{}", CHECKBOX_EMOJI, r_type, resource.name, resource.namespace);
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(anyhow!("\n{}", errors.join("\n")).context("failed to prune resources").into()),
+        }
+    }
+}
+
+struct SyncPlan {
+    changed: Vec<SupportedResources>,
+    removed: Vec<SyncedResource>,
+    // the state to persist once the sync is done
+    next: SyncState,
+}
+
+// the resources that changed since the previous sync and those removed from the repository since
+fn plan_sync(previous: &SyncState, revision: &str, resources: Vec<SupportedResources>, prune: bool) -> SyncPlan {
+    let mut next = SyncState {
+        revision: revision.to_string(),
+        resources: resources.iter().map(synced_resource).collect(),
+    };
+
+    let changed: Vec<_> = resources.into_iter()
+        .filter(|r| {
+            let (key, synced) = synced_resource(r);
+            previous.resources.get(&key) != Some(&synced)
+        })
+        .collect();
+    let removed: Vec<_> = previous.resources.iter()
+        .filter(|(key, _)| !next.resources.contains_key(*key))
+        .map(|(_, r)| r.clone())
+        .collect();
+
+    // resources that weren't pruned stay tracked, so they are pruned once pruning is asked for
+    if !prune {
+        next.resources.extend(removed.iter().map(|r| (resource_key(&r.kind, &r.namespace, &r.name), r.clone())));
+    }
+    SyncPlan { changed, removed, next }
+}
+
+fn resource_key(kind: &str, namespace: &str, name: &str) -> String {
+    format!("{}/{}/{}", kind, namespace, name)
+}
+
+fn synced_resource(resource: &SupportedResources) -> (String, SyncedResource) {
+    let name = resource.name();
+    let kind = resource.to_string();
+    let hash = hash_string(serde_yaml::to_string(resource).unwrap_or_default());
+    (resource_key(&kind, &name.namespace, &name.name), SyncedResource { kind, name: name.name, namespace: name.namespace, hash })
+}
+
+// clones the repository, or updates an existing clone, returning the checked out revision
+fn checkout_repo(repo: &str, branch: Option<&str>, dir: &Path) -> Result<String, Box<dyn Error>> {
+    let dir_str = dir.to_string_lossy().to_string();
+    if dir.join(".git").exists() {
+        let mut fetch = vec!["-C", &dir_str, "fetch", "--depth", "1", "--", "origin"];
+        fetch.extend(branch);
+        git(&fetch)?;
+        git(&["-C", &dir_str, "reset", "--hard", "FETCH_HEAD"])?;
+    } else {
+        let mut clone = vec!["clone", "--depth", "1"];
+        if let Some(branch) = branch {
+            clone.extend(["--branch", branch]);
+        }
+        // a repo starting with - would be taken for an option
+        clone.extend(["--", repo, &dir_str]);
+        git(&clone)?;
+    }
+    Ok(git(&["-C", &dir_str, "rev-parse", "--short", "HEAD"])?.trim().to_string())
+}
+
+fn git(args: &[&str]) -> Result<String, Box<dyn Error>> {
+    let output = Command::new("git").args(args).output().map_err(|e| anyhow!(e).context("failed to run git"))?;
+    if !output.status.success() {
+        return Err(anyhow!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// yaml files under the directory, in a stable order
+fn manifest_files(dir: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    if !dir.is_dir() {
+        return Err(anyhow!("{} is not a directory", dir.display()).into());
+    }
+    let mut files = vec!();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            if file_name.starts_with('.') {
+                continue;
+            }
+            if path.is_dir() {
+                dirs.push(path);
+            } else if matches!(path.extension().and_then(|e| e.to_str()), Some("yaml") | Some("yml")) {
+                files.push(path.to_string_lossy().to_string());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+pub(crate) fn parse_interval(interval: &str) -> Result<Duration, SkateError> {
+    let interval = interval.trim();
+    let (value, unit) = interval.split_at(interval.find(|c: char| !c.is_ascii_digit()).unwrap_or(interval.len()));
+    let value: u64 = value.parse().map_err(|_| anyhow!("invalid interval {}", interval))?;
+    let secs = match unit {
+        "" | "s" => value,
+        "m" => value * 60,
+        "h" => value * 60 * 60,
+        _ => return Err(anyhow!("invalid interval {}, expected eg 30s, 5m or 1h", interval).into()),
+    };
+    if secs == 0 {
+        return Err(anyhow!("interval must be greater than 0").into());
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::resource::SupportedResources;
+    use crate::sync::{parse_interval, plan_sync, SyncState};
+    use crate::test_helpers::objects;
+
+    fn pod(name: &str, version: &str) -> SupportedResources {
+        SupportedResources::Pod(objects::pod(name, "shop", &[("version", version)]))
+    }
+
+    fn names(resources: &[SupportedResources]) -> Vec<String> {
+        resources.iter().map(|r| r.name().to_string()).collect()
+    }
+
+    #[test]
+    fn test_plan_sync() {
+        let first = plan_sync(&SyncState::default(), "a1", vec![pod("web", "1"), pod("api", "1")], false);
+        assert_eq!(vec!["web.shop", "api.shop"], names(&first.changed));
+        assert!(first.removed.is_empty());
+        assert_eq!("a1", first.next.revision);
+
+        // only what changed is applied again
+        let second = plan_sync(&first.next, "b2", vec![pod("web", "2"), pod("api", "1")], false);
+        assert_eq!(vec!["web.shop"], names(&second.changed));
+        assert_eq!(first.next.resources.keys().collect::<Vec<_>>(), second.next.resources.keys().collect::<Vec<_>>());
+
+        // without prune the removed api stays tracked, also when nothing else changed, so that a later prune deletes it
+        let unpruned = plan_sync(&second.next, "c3", vec![pod("web", "2")], false);
+        assert!(unpruned.changed.is_empty());
+        assert_eq!(vec!["api"], unpruned.removed.iter().map(|r| r.name.as_str()).collect::<Vec<_>>());
+        assert!(unpruned.next.resources.contains_key("Pod/shop/api"), "{:?}", unpruned.next.resources.keys());
+
+        let again = plan_sync(&unpruned.next, "c3", vec![pod("web", "2")], false);
+        assert_eq!(1, again.removed.len());
+
+        let pruned = plan_sync(&again.next, "c3", vec![pod("web", "2")], true);
+        assert_eq!(vec!["api"], pruned.removed.iter().map(|r| r.name.as_str()).collect::<Vec<_>>());
+        assert!(!pruned.next.resources.contains_key("Pod/shop/api"));
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(Duration::from_secs(30), parse_interval("30s").unwrap());
+        assert_eq!(Duration::from_secs(30), parse_interval("30").unwrap());
+        assert_eq!(Duration::from_secs(300), parse_interval("5m").unwrap());
+        assert_eq!(Duration::from_secs(7200), parse_interval("2h").unwrap());
+        assert!(parse_interval("5d").is_err());
+        assert!(parse_interval("m").is_err());
+        assert!(parse_interval("0s").is_err());
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+use anyhow::anyhow;
+use k8s_openapi::api::core::v1::{EmptyDirVolumeSource, PodSpec, Volume, VolumeMount};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use crate::errors::SkateError;
+use crate::pod_spec::{pod_spec_mut, pod_template_mut};
+use crate::resource::SupportedResources;
+use crate::util::quantity_to_bytes;
+
+// the size of the pod's /dev/shm, eg 1Gi, which podman otherwise leaves at 64MB
+pub const SHM_SIZE_ANNOTATION: &str = "skate.io/shm-size";
+const SHM_VOLUME: &str = "skate-shm";
+
+// those kubernetes considers safe, they only affect the pod's own namespaces
+const SAFE_SYSCTLS: &[&str] = &[
+    "kernel.shm_rmid_forced",
+    "net.ipv4.ip_local_port_range",
+    "net.ipv4.ip_local_reserved_ports",
+    "net.ipv4.ip_unprivileged_port_start",
+    "net.ipv4.ping_group_range",
+    "net.ipv4.tcp_syncookies",
+    "net.ipv4.tcp_keepalive_time",
+    "net.ipv4.tcp_fin_timeout",
+    "net.ipv4.tcp_keepalive_intvl",
+    "net.ipv4.tcp_keepalive_probes",
+];
+
+fn allowed(sysctl: &str, allowed_unsafe: &[String]) -> bool {
+    SAFE_SYSCTLS.contains(&sysctl) || allowed_unsafe.iter().any(|a| match a.strip_suffix('*') {
+        Some(prefix) => sysctl.starts_with(prefix),
+        None => a == sysctl,
+    })
+}
+
+fn check_sysctls(name: &str, spec: &PodSpec, allowed_unsafe: &[String]) -> Result<(), SkateError> {
+    let sysctls = spec.security_context.as_ref().and_then(|s| s.sysctls.as_ref()).into_iter().flatten();
+    for sysctl in sysctls {
+        if !allowed(&sysctl.name, allowed_unsafe) {
+            return Err(anyhow!("{}: sysctl {} is unsafe, add it to allowedUnsafeSysctls in the cluster.yaml to allow it", name, sysctl.name).into());
+        }
+        // the pod shares the node's network namespace, so it would change the node's
+        if sysctl.name.starts_with("net.") && spec.host_network == Some(true) {
+            return Err(anyhow!("{}: sysctl {} can't be set on a hostNetwork pod", name, sysctl.name).into());
+        }
+    }
+    Ok(())
+}
+
+// mounts a memory backed emptyDir of the size at /dev/shm in each container that doesn't mount its own there
+fn size_shm(name: &str, spec: &mut PodSpec, size: &str) -> Result<(), SkateError> {
+    quantity_to_bytes(size).ok_or(anyhow!("{}: invalid {} {}", name, SHM_SIZE_ANNOTATION, size))?;
+    let containers = spec.containers.iter_mut().chain(spec.init_containers.iter_mut().flatten());
+    for container in containers {
+        let mounts = container.volume_mounts.get_or_insert_with(Vec::new);
+        if !mounts.iter().any(|m| m.mount_path == "/dev/shm") {
+            mounts.push(VolumeMount { name: SHM_VOLUME.to_string(), mount_path: "/dev/shm".to_string(), ..Default::default() });
+        }
+    }
+    let volumes = spec.volumes.get_or_insert_with(Vec::new);
+    volumes.retain(|v| v.name != SHM_VOLUME);
+    volumes.push(Volume {
+        name: SHM_VOLUME.to_string(),
+        empty_dir: Some(EmptyDirVolumeSource { medium: Some("Memory".to_string()), size_limit: Some(Quantity(size.to_string())) }),
+        ..Default::default()
+    });
+    Ok(())
+}
+
+fn pod_annotations(resource: &mut SupportedResources) -> Option<BTreeMap<String, String>> {
+    match resource {
+        SupportedResources::Pod(p) => p.metadata.annotations.clone(),
+        _ => pod_template_mut(resource)?.metadata.as_ref()?.annotations.clone(),
+    }
+}
+
+// checks the sysctls of the resource's pods and sizes their /dev/shm from the annotation. podman kube play sets the
+// sysctls itself, and the shm volume becomes a tmpfs like any other memory emptyDir
+pub fn resolve(allowed_unsafe: &[String], mut resource: SupportedResources) -> Result<SupportedResources, SkateError> {
+    let name = resource.name();
+    let shm_size = pod_annotations(&mut resource).and_then(|a| a.get(SHM_SIZE_ANNOTATION).cloned());
+    let spec = match pod_spec_mut(&mut resource) {
+        Some(spec) => spec,
+        None => return Ok(resource),
+    };
+    check_sysctls(&name.to_string(), spec, allowed_unsafe)?;
+    if let Some(size) = shm_size {
+        size_shm(&name.to_string(), spec, &size)?;
+    }
+    Ok(resource)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use k8s_openapi::api::core::v1::{Container, Pod, PodSecurityContext, PodSpec, Sysctl, VolumeMount};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use crate::resource::SupportedResources;
+    use crate::sysctl::{resolve, SHM_SIZE_ANNOTATION};
+    use crate::util::NamespacedName;
+
+    fn pod(sysctls: &[&str], annotations: &[(&str, &str)]) -> SupportedResources {
+        let mut meta = ObjectMeta::from(NamespacedName::new("db", "default"));
+        meta.annotations = Some(annotations.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<BTreeMap<_, _>>());
+        SupportedResources::Pod(Pod {
+            metadata: meta,
+            spec: Some(PodSpec {
+                containers: vec![
+                    Container { name: "postgres".to_string(), ..Default::default() },
+                    Container {
+                        name: "chrome".to_string(),
+                        volume_mounts: Some(vec![VolumeMount { name: "own".to_string(), mount_path: "/dev/shm".to_string(), ..Default::default() }]),
+                        ..Default::default()
+                    },
+                ],
+                security_context: Some(PodSecurityContext {
+                    sysctls: Some(sysctls.iter().map(|s| Sysctl { name: s.to_string(), value: "1".to_string() }).collect()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    }
+
+    fn spec(resource: SupportedResources) -> PodSpec {
+        match resource {
+            SupportedResources::Pod(p) => p.spec.unwrap(),
+            _ => panic!("not a pod"),
+        }
+    }
+
+    #[test]
+    fn test_resolve_sysctls() {
+        assert!(resolve(&[], pod(&["net.ipv4.tcp_syncookies", "kernel.shm_rmid_forced"], &[])).is_ok());
+        assert!(resolve(&[], pod(&["net.core.somaxconn"], &[])).unwrap_err().to_string().contains("sysctl net.core.somaxconn is unsafe, add it to allowedUnsafeSysctls"));
+        assert!(resolve(&["net.core.*".to_string()], pod(&["net.core.somaxconn"], &[])).is_ok());
+        assert!(resolve(&["kernel.msgmax".to_string()], pod(&["kernel.msgmnb"], &[])).is_err());
+
+        let mut host_network = spec(pod(&["net.ipv4.tcp_syncookies"], &[]));
+        host_network.host_network = Some(true);
+        let host_network = SupportedResources::Pod(Pod { metadata: ObjectMeta::from(NamespacedName::new("db", "default")), spec: Some(host_network), ..Default::default() });
+        assert!(resolve(&[], host_network).is_err());
+    }
+
+    #[test]
+    fn test_resolve_shm_size() {
+        let resolved = spec(resolve(&[], pod(&[], &[(SHM_SIZE_ANNOTATION, "1Gi")])).unwrap());
+        let volume = resolved.volumes.unwrap().remove(0);
+        let empty_dir = volume.empty_dir.unwrap();
+        assert_eq!((Some("Memory"), Some("1Gi")), (empty_dir.medium.as_deref(), empty_dir.size_limit.as_ref().map(|q| q.0.as_str())));
+        let mounts: Vec<_> = resolved.containers.iter().map(|c| c.volume_mounts.as_ref().unwrap()[0].name.as_str()).collect();
+        assert_eq!(vec![volume.name.as_str(), "own"], mounts);
+
+        assert!(resolve(&[], pod(&[], &[(SHM_SIZE_ANNOTATION, "huge")])).is_err());
+        assert!(spec(resolve(&[], pod(&[], &[])).unwrap()).volumes.is_none());
+    }
+}
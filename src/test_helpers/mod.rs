@@ -1,3 +1,4 @@
-
 pub mod ssh_mocks;
-pub mod objects;
\ No newline at end of file
+pub mod objects;
+pub mod runtime;
+pub mod temp_dir;
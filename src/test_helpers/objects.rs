@@ -5,7 +5,7 @@ use crate::skate::{Distribution, Platform};
 use crate::skatelet::system::DiskInfo;
 use crate::skatelet::SystemInfo;
 use crate::ssh::HostInfo;
-use crate::state::state::NodeState;
+use crate::state::state::{ClusterState, NodeState};
 use crate::state::state::NodeStatus::Healthy;
 use crate::util::NamespacedName;
 
@@ -15,6 +15,8 @@ pub fn node_state(name: &str) -> NodeState{
         node_name: name.to_string(),
         status: Healthy,
         message: None,
+        labels: BTreeMap::new(),
+        conditions: vec![],
         host_info: Some(HostInfo{
             node_name: name.to_string(),
             hostname: name.to_string(),
@@ -32,12 +34,16 @@ pub fn node_state(name: &str) -> NodeState{
                     total_space_mib: 40_000,
                     disk_kind: "ssd".to_string(),
                 }),
+                containers_disk: None,
+                skate_disk: None,
                 pods: None,
                 ingresses: None,
                 cronjobs: None,
+                cronjob_statuses: None,
                 secrets: None,
                 services: None,
                 cluster_issuers: None,
+                pod_disruption_budgets: None,
                 deployments: None,
                 daemonsets: None,
                 cpu_freq_mhz: 2,
@@ -47,13 +53,37 @@ pub fn node_state(name: &str) -> NodeState{
                 internal_ip_address: None,
                 hostname: name.to_string(),
                 cordoned: false,
+                generation: None,
+                pod_stats: None,
+                container_events: None,
+                resources: None,
+                ingress_certificates: None,
             }),
             podman_version: Some("3.6.0".to_string()),
             ovs_version: Some("1.0.0".to_string()),
+            kernel_version: Some("6.8.0-45-generic".to_string()),
+            cgroup_driver: Some("systemd".to_string()),
+            fetched_at: None,
         }),
+        system_reserved: BTreeMap::new(),
+        ..Default::default()
     }
 }
 
+// a pod as skate names and labels it, <name>.<namespace>, with the extra labels given
+#[allow(unused)]
+pub fn pod(name: &str, namespace: &str, labels: &[(&str, &str)]) -> Pod {
+    let mut meta = ObjectMeta::from(NamespacedName::new(name, namespace));
+    meta.name = Some(format!("{}.{}", name, namespace));
+    meta.labels.get_or_insert_with(Default::default).extend(labels.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+    Pod { metadata: meta, ..Default::default() }
+}
+
+#[allow(unused)]
+pub fn cluster_state(nodes: Vec<NodeState>) -> ClusterState {
+    ClusterState { cluster_name: "test".to_string(), nodes }
+}
+
 pub trait WithPod {
     #[allow(unused)]
     fn with_pod(self, pod: &Pod) -> Self;
@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use k8s_openapi::api::core::v1::{Pod, PodStatus};
+use crate::filestore::ObjectListItem;
+use crate::resource::{ResourceType, SupportedResources};
+use crate::ssh::HostInfo;
+use crate::test_helpers::objects::node_state;
+
+// an in-memory stand-in for the nodes' skatelet and podman. what's applied to a node is kept, and reported back by its system
+// info, so a refresh after an apply sees the pods it created. clones share the same nodes
+#[derive(Clone, Default)]
+pub struct FakeRuntime {
+    nodes: Arc<Mutex<BTreeMap<String, Vec<SupportedResources>>>>,
+}
+
+fn key(resource: &SupportedResources) -> (String, String, String) {
+    let kind = resource.to_string();
+    let mut resource = resource.clone();
+    let meta = resource.metadata_mut();
+    (kind, meta.name.clone().unwrap_or_default(), meta.namespace.clone().unwrap_or_default())
+}
+
+impl FakeRuntime {
+    // as the skatelet does, the manifest replaces the node's object of the same kind and name. pods start right away
+    pub fn apply(&self, node: &str, manifest: &str) -> Result<(), Box<dyn Error>> {
+        let mut resource: SupportedResources = serde_yaml::from_str(manifest)?;
+        if let SupportedResources::Pod(pod) = &mut resource {
+            pod.status = Some(PodStatus { phase: Some("Running".to_string()), ..Default::default() });
+        }
+        let mut nodes = self.nodes.lock().unwrap();
+        let objects = nodes.entry(node.to_string()).or_default();
+        objects.retain(|o| key(o) != key(&resource));
+        objects.push(resource);
+        Ok(())
+    }
+
+    pub fn remove_manifest(&self, node: &str, manifest: &str) -> Result<(), Box<dyn Error>> {
+        let resource: SupportedResources = serde_yaml::from_str(manifest)?;
+        if let Some(objects) = self.nodes.lock().unwrap().get_mut(node) {
+            objects.retain(|o| key(o) != key(&resource));
+        }
+        Ok(())
+    }
+
+    // removes the object and, like the skatelet, the pods labelled as its own
+    pub fn remove(&self, node: &str, resource_type: ResourceType, name: &str, namespace: &str) {
+        let owner_label = format!("skate.io/{}", resource_type);
+        if let Some(objects) = self.nodes.lock().unwrap().get_mut(node) {
+            objects.retain(|o| {
+                let (kind, object_name, object_namespace) = key(o);
+                let owned = match o {
+                    SupportedResources::Pod(p) => p.metadata.labels.as_ref().and_then(|l| l.get(&owner_label)).is_some_and(|n| n == name),
+                    _ => false,
+                };
+                let same = kind.to_lowercase() == resource_type.to_string() && object_name == name;
+                !((same || owned) && object_namespace == namespace)
+            });
+        }
+    }
+
+    pub fn resources(&self, node: &str) -> Vec<SupportedResources> {
+        self.nodes.lock().unwrap().get(node).cloned().unwrap_or_default()
+    }
+
+    pub fn pods(&self, node: &str) -> Vec<Pod> {
+        self.resources(node).into_iter().filter_map(|r| match r {
+            SupportedResources::Pod(p) => Some(p),
+            _ => None,
+        }).collect()
+    }
+
+    // the node's info as node_state has it, with the pods running on it and the other objects in its store
+    pub fn host_info(&self, node: &str) -> HostInfo {
+        let mut info = node_state(node).host_info.expect("node_state has host info");
+        let resources = self.resources(node);
+        let items = |f: &dyn Fn(&SupportedResources) -> Option<ObjectListItem>| Some(resources.iter().filter_map(f).collect::<Vec<_>>());
+        if let Some(system_info) = info.system_info.as_mut() {
+            system_info.pods = Some(self.pods(node).into_iter().map(|p| p.into()).collect());
+            system_info.deployments = items(&|r| match r { SupportedResources::Deployment(d) => Some(d.into()), _ => None });
+            system_info.daemonsets = items(&|r| match r { SupportedResources::DaemonSet(d) => Some(d.into()), _ => None });
+            system_info.cronjobs = items(&|r| match r { SupportedResources::CronJob(c) => Some(c.into()), _ => None });
+            system_info.ingresses = items(&|r| match r { SupportedResources::Ingress(i) => Some(i.into()), _ => None });
+            system_info.services = items(&|r| match r { SupportedResources::Service(s) => Some(s.into()), _ => None });
+            system_info.secrets = items(&|r| match r { SupportedResources::Secret(s) => Some(s.into()), _ => None });
+            system_info.cluster_issuers = items(&|r| match r { SupportedResources::ClusterIssuer(c) => Some(c.into()), _ => None });
+            system_info.pod_disruption_budgets = items(&|r| match r { SupportedResources::PodDisruptionBudget(p) => Some(p.into()), _ => None });
+        }
+        info
+    }
+}
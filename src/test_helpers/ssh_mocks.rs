@@ -1,17 +1,170 @@
+use std::error::Error;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use anyhow::anyhow;
 use async_trait::async_trait;
+use tokio::io::AsyncReadExt;
+use tokio::sync::mpsc;
 use crate::config::{Cluster, Node};
 use crate::deps::SshManager;
-use crate::ssh::{SshClient, SshClients, SshError, SshErrors};
+use crate::resource::ResourceType;
+use crate::skate::Platform;
+use crate::ssh::{HostInfo, SshClient, SshClients, SshError, SshErrors, Transfer, UploadSource};
+use crate::test_helpers::runtime::FakeRuntime;
 
-pub struct MockSshManager{}
+// the commands run on the nodes, as (node, command)
+pub type CommandLog = Arc<Mutex<Vec<(String, String)>>>;
+
+// MockSshManager connects to the cluster's nodes through a shared fake runtime, except those listed as unreachable, and
+// logs the commands run on all of them
+#[derive(Clone, Default)]
+pub struct MockSshManager {
+    pub runtime: FakeRuntime,
+    pub unreachable: Vec<String>,
+    pub commands: CommandLog,
+    // the output of commands starting with the prefix, commands without one fail
+    pub responses: Vec<(String, String)>,
+}
+
+impl MockSshManager {
+    fn client(&self, node_name: &str) -> MockSshClient {
+        MockSshClient {
+            runtime: self.runtime.clone(),
+            commands: self.commands.clone(),
+            responses: self.responses.clone(),
+            ..MockSshClient::new(node_name, false)
+        }
+    }
+
+    // connections to the named nodes, for code that takes them rather than a cluster
+    pub fn clients(&self, node_names: &[&str]) -> SshClients {
+        SshClients { clients: node_names.iter().map(|n| Box::new(self.client(n)) as Box<dyn SshClient>).collect() }
+    }
+}
 
 #[async_trait]
 impl SshManager for MockSshManager {
-    async fn node_connect(&self, _: &Cluster, _: &Node) -> Result<Box<dyn SshClient>, SshError> {
-        todo!("implement me")
+    async fn node_connect(&self, _: &Cluster, node: &Node) -> Result<Box<dyn SshClient>, SshError> {
+        match self.unreachable.contains(&node.name) {
+            true => Err(SshError { node_name: node.name.clone(), error: "timeout".to_string(), elapsed: Some(std::time::Duration::from_secs(5)) }),
+            false => Ok(Box::new(self.client(&node.name))),
+        }
+    }
+
+    async fn cluster_connect(&self, cluster: &Cluster) -> (Option<SshClients>, Option<SshErrors>) {
+        let mut clients = vec![];
+        let mut errors = vec![];
+        for node in cluster.nodes.iter() {
+            match self.node_connect(cluster, node).await {
+                Ok(client) => clients.push(client),
+                Err(e) => errors.push(e),
+            }
+        }
+        (
+            (!clients.is_empty()).then_some(SshClients { clients }),
+            (!errors.is_empty()).then_some(SshErrors { errors }),
+        )
     }
+}
 
-    async fn cluster_connect(&self, _: &Cluster) -> (Option<SshClients>, Option<SshErrors>) {
-        todo!("implement me")
+// MockSshClient records the manifests applied to it and fails applies when told to. what's applied goes into its runtime,
+// which its system info reports
+pub struct MockSshClient {
+    pub node_name: String,
+    pub fail_apply: bool,
+    pub applied: Mutex<Vec<String>>,
+    pub removed: Mutex<Vec<String>>,
+    pub runtime: FakeRuntime,
+    pub commands: CommandLog,
+    pub responses: Vec<(String, String)>,
+}
+
+impl MockSshClient {
+    pub fn new(node_name: &str, fail_apply: bool) -> Self {
+        MockSshClient {
+            node_name: node_name.to_string(),
+            fail_apply,
+            applied: Default::default(),
+            removed: Default::default(),
+            runtime: Default::default(),
+            commands: Default::default(),
+            responses: vec![],
+        }
+    }
+
+    fn respond(&self, cmd: &str) -> Result<String, Box<dyn Error>> {
+        self.commands.lock().unwrap().push((self.node_name.clone(), cmd.to_string()));
+        self.responses.iter().find(|(prefix, _)| cmd.starts_with(prefix.as_str()))
+            .map(|(_, output)| output.clone())
+            .ok_or(anyhow!("not implemented").into())
+    }
+}
+
+#[async_trait]
+impl SshClient for MockSshClient {
+    async fn get_node_system_info(&self) -> Result<HostInfo, Box<dyn Error>> {
+        Ok(self.runtime.host_info(&self.node_name))
+    }
+    // logged as install skatelet <arch>
+    async fn install_skatelet(&self, platform: Platform) -> Result<(), Box<dyn Error>> {
+        self.commands.lock().unwrap().push((self.node_name.clone(), format!("install skatelet {}", platform.arch)));
+        Ok(())
+    }
+    async fn apply_resource(&self, manifest: &str) -> Result<(String, String), Box<dyn Error>> {
+        if self.fail_apply {
+            return Err(anyhow!("failed to pull image").into());
+        }
+        self.runtime.apply(&self.node_name, manifest)?;
+        self.applied.lock().unwrap().push(manifest.to_string());
+        Ok(("".to_string(), "".to_string()))
+    }
+    async fn remove_resource(&self, resource_type: ResourceType, name: &str, namespace: &str) -> Result<(String, String), Box<dyn Error>> {
+        self.runtime.remove(&self.node_name, resource_type, name, namespace);
+        Ok(("".to_string(), "".to_string()))
+    }
+    async fn remove_resource_by_manifest(&self, manifest: &str) -> Result<(String, String), Box<dyn Error>> {
+        self.runtime.remove_manifest(&self.node_name, manifest)?;
+        self.removed.lock().unwrap().push(manifest.to_string());
+        Ok(("".to_string(), "".to_string()))
+    }
+    async fn execute_stdout(&self, cmd: &str, _: bool, _: bool) -> Result<(), Box<dyn Error>> {
+        self.respond(cmd).map(|_| ())
+    }
+    // the response is sent line by line, as a real node's output arrives
+    async fn execute_to_sender(&self, cmd: &str, sender: mpsc::Sender<String>) -> Result<(), Box<dyn Error>> {
+        let output = self.respond(cmd)?;
+        for line in output.lines() {
+            sender.send(format!("{}\n", line)).await?;
+        }
+        Ok(())
+    }
+    // exits with 0 for a command with a response, there's no terminal to attach to
+    async fn execute_interactive(&self, cmd: &str, _: bool, _: bool) -> Result<u32, Box<dyn Error>> {
+        self.respond(cmd).map(|_| 0)
+    }
+    async fn execute_noisy(&self, cmd: &str) -> Result<String, Box<dyn Error>> {
+        self.respond(cmd)
+    }
+    async fn execute(&self, cmd: &str) -> Result<String, Box<dyn Error>> {
+        self.respond(cmd)
+    }
+    // logged as upload <mode> <path>, since a real upload installs the file as root with that mode
+    async fn upload(&self, source: &mut dyn UploadSource, remote_path: &str, mode: u32) -> Result<Transfer, Box<dyn Error>> {
+        let mut contents = vec![];
+        source.read_to_end(&mut contents).await?;
+        self.commands.lock().unwrap().push((self.node_name.clone(), format!("upload {:o} {}", mode, remote_path)));
+        Ok(Transfer { size: contents.len() as u64, resumed_from: 0, sha256: "".to_string(), unchanged: false })
+    }
+    // logged as download <path>, the file's contents are the response to that
+    async fn download(&self, remote_path: &str, local_path: &Path) -> Result<Transfer, Box<dyn Error>> {
+        let contents = self.respond(&format!("download {}", remote_path))?;
+        tokio::fs::write(local_path, &contents).await?;
+        Ok(Transfer { size: contents.len() as u64, resumed_from: 0, sha256: "".to_string(), unchanged: false })
+    }
+    fn node_name(&self) -> String {
+        self.node_name.clone()
+    }
+    async fn connect(node: &Node) -> Result<Self, SshError> where Self: Sized {
+        Ok(MockSshClient::new(&node.name, false))
     }
 }
\ No newline at end of file
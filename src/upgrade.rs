@@ -1,7 +1,8 @@
 use clap::{Args, Subcommand};
-use crate::config::Config;
+use crate::config::{Access, Config};
 use crate::deps::{SshManager, With};
 use crate::errors::SkateError;
+use crate::report::{reported, Action, ActionResult};
 use crate::skate::ConfigFileArgs;
 
 #[derive(Clone, Debug, Args)]
@@ -19,7 +20,9 @@ pub enum Commands {
 
 #[derive(Clone, Debug, Args)]
 pub struct NodeArgs{
-    pub name: String
+    pub name: String,
+    #[arg(long, long_help = "Print the actions taken as json.")]
+    pub json: bool,
 }
 pub trait UpgradeDeps: With<dyn SshManager> {}
 
@@ -30,16 +33,20 @@ pub struct Upgrade<D: UpgradeDeps> {
 impl<D: UpgradeDeps> Upgrade<D> {
     pub async fn upgrade(&self, args: UpgradeArgs) -> Result<(), SkateError> {
         match &(args.command) {
-            Commands::Node(node_args) => self.upgrade_node(&args,&node_args).await?,
+            Commands::Node(node_args) => reported("upgrade node", node_args.json, async {
+                let mut actions = vec!();
+                let result = self.upgrade_node(&args, node_args, &mut actions).await;
+                (actions, result)
+            }).await?,
         }
         Ok(())
     }
      
-    async fn upgrade_node(&self, main_args: &UpgradeArgs, args: &NodeArgs) -> Result<(), SkateError> {
+    async fn upgrade_node(&self, main_args: &UpgradeArgs, args: &NodeArgs, actions: &mut Vec<Action>) -> Result<(), SkateError> {
 
-        let config = Config::load(Some(main_args.config.skateconfig.clone()))?;
+        let config = Config::load(main_args.config.skateconfig.clone())?;
 
-        let cluster = config.active_cluster(main_args.config.context.clone())?;
+        let cluster = config.authorized_cluster(main_args.config.context.clone(), Access::Admin)?;
 
         let ssh_mgr= self.deps.get();
         
@@ -50,6 +57,16 @@ impl<D: UpgradeDeps> Upgrade<D> {
         let si = conn.get_node_system_info().await?;
         
         conn.install_skatelet(si.platform).await?;
+        // so the daemon runs the new binary, nodes provisioned before it existed don't have one
+        let _ = conn.execute("sudo systemctl try-restart skatelet.service").await;
+
+        let before = si.skatelet_version.unwrap_or_default();
+        let after = conn.get_node_system_info().await?.skatelet_version.unwrap_or_default();
+        let action = match before == after {
+            true => Action::new("upgrade skatelet", &node.name, ActionResult::Unchanged).with_message(&after),
+            false => Action::new("upgrade skatelet", &node.name, ActionResult::Updated).with_message(&format!("{} -> {}", before, after)),
+        };
+        actions.push(action);
         Ok(())
     }
     
@@ -5,10 +5,11 @@ use std::fmt::{Display, Formatter};
 use std::fs::File;
 use std::hash::{Hash, Hasher};
 use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
 use std::path::Path;
+use std::str::FromStr;
 use anyhow::anyhow;
-use base64::Engine;
-use base64::engine::general_purpose;
 use chrono::{DateTime, Local};
 use deunicode::deunicode_char;
 use fs2::FileExt;
@@ -21,10 +22,37 @@ use regex::Regex;
 use once_cell::sync::Lazy;
 use crate::resource::SupportedResources;
 use crate::exec::{ShellExec};
+use crate::interactive;
+use crate::logging;
+use crate::image::pod_images;
+use crate::skatelet::apply_progress::{self, ApplyEvent};
+use crate::errors::SkateError;
+
+
+// a mark that's written as plain text when the output isn't a terminal, or with --non-interactive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Emoji {
+    symbol: &'static str,
+    plain: &'static str,
+}
 
+impl Emoji {
+    fn render(&self, decorated: bool) -> &'static str {
+        match decorated {
+            true => self.symbol,
+            false => self.plain,
+        }
+    }
+}
 
-pub const CHECKBOX_EMOJI: char = '✔';
-pub const CROSS_EMOJI: char = '✖';
+impl Display for Emoji {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.render(interactive::decorated()))
+    }
+}
+
+pub const CHECKBOX_EMOJI: Emoji = Emoji { symbol: "✔", plain: "[ok]" };
+pub const CROSS_EMOJI: Emoji = Emoji { symbol: "✖", plain: "[error]" };
 #[allow(unused)]
 pub const EQUAL_EMOJI: char = '~';
 #[allow(unused)]
@@ -98,7 +126,10 @@ where
     format!("{:x}", hasher.finish())
 }
 
-pub fn calc_k8s_resource_hash(obj: (impl Metadata<Ty=ObjectMeta> + Serialize + Clone)) -> String
+// the annotation holding the manifest as it was last applied, used to tell which fields a new apply removes
+pub const LAST_APPLIED_ANNOTATION: &str = "skate.io/last-applied-configuration";
+
+pub fn calc_k8s_resource_hash(obj: impl Metadata<Ty=ObjectMeta> + Serialize + Clone ) -> String
 {
     let mut obj = obj.clone();
 
@@ -109,10 +140,16 @@ pub fn calc_k8s_resource_hash(obj: (impl Metadata<Ty=ObjectMeta> + Serialize + C
 
 
     let mut annotations = obj.metadata().annotations.clone().unwrap_or_default();
+    // only changes along with the rest of the manifest, and an object applied before it existed shouldn't count as changed
+    annotations.remove(LAST_APPLIED_ANNOTATION);
 
     annotations = annotations.into_iter().sorted_by_key(|l| l.1.clone()).collect();
     obj.metadata_mut().annotations = Option::from(annotations);
 
+    // apply bumps the generation when the rest changes, and the resourceVersion is the hash itself
+    obj.metadata_mut().generation = None;
+    obj.metadata_mut().resource_version = None;
+
     let serialized = serde_yaml::to_string(&obj).unwrap();
 
     let mut hasher = DefaultHasher::new();
@@ -121,19 +158,48 @@ pub fn calc_k8s_resource_hash(obj: (impl Metadata<Ty=ObjectMeta> + Serialize + C
 }
 
 
-#[derive(Serialize, Deserialize, Debug, Clone, Eq, Hash, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, Hash, PartialEq, PartialOrd, Ord)]
 pub struct NamespacedName {
     pub name: String,
     pub namespace: String,
 }
 
+// for the names skate stores, which are already valid. the namespace is after the last dot, since names can have dots
 impl From<&str> for NamespacedName {
     fn from(s: &str) -> Self {
-        let parts: Vec<_> = s.split('.').collect();
-        return Self {
-            name: parts.first().unwrap_or(&"").to_string(),
-            namespace: parts.last().unwrap_or(&"").to_string(),
+        let (name, namespace) = s.rsplit_once('.').unwrap_or((s, ""));
+        Self {
+            name: name.to_string(),
+            namespace: namespace.to_string(),
+        }
+    }
+}
+
+// splits a <type>/<name> identifier, anything without one of the types is a pod's name, which may be <namespace>/<name>
+pub fn split_resource_identifier<'a>(identifier: &'a str, types: &[&str]) -> (&'a str, &'a str) {
+    match identifier.split_once('/') {
+        Some((kind, name)) if kind == "pod" || types.contains(&kind) => (kind, name),
+        _ => ("pod", identifier),
+    }
+}
+
+// namespaces are dns-1123 labels
+static RE_DNS_LABEL: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[a-z0-9]([-a-z0-9]*[a-z0-9])?$").unwrap());
+// names are dns-1123 subdomains
+static RE_DNS_SUBDOMAIN: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[a-z0-9]([-a-z0-9]*[a-z0-9])?(\.[a-z0-9]([-a-z0-9]*[a-z0-9])?)*$").unwrap());
+
+// parses <name>.<namespace> or <namespace>/<name>, checking both are valid
+impl FromStr for NamespacedName {
+    type Err = SkateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = match (s.split_once('/'), s.rsplit_once('.')) {
+            (Some((namespace, name)), _) => NamespacedName::new(name, namespace),
+            (None, Some((name, namespace))) => NamespacedName::new(name, namespace),
+            (None, None) => return Err(anyhow!("invalid name {}, expected <name>.<namespace> or <namespace>/<name>", s).into()),
         };
+        parsed.validate().map_err(|e| anyhow!("invalid name {}: {}", s, e))?;
+        Ok(parsed)
     }
 }
 
@@ -147,6 +213,38 @@ impl NamespacedName {
     pub fn new(name: &str, namespace: &str) -> Self {
         NamespacedName { name: name.to_string(), namespace: namespace.to_string() }
     }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.len() > 253 || !RE_DNS_SUBDOMAIN.is_match(&self.name) {
+            return Err(format!("name {} must be at most 253 lowercase letters, digits, '-' and '.', starting and ending with a letter or digit", self.name));
+        }
+        if self.namespace.len() > 63 || !RE_DNS_LABEL.is_match(&self.namespace) {
+            return Err(format!("namespace {} must be at most 63 lowercase letters, digits and '-', starting and ending with a letter or digit", self.namespace));
+        }
+        Ok(())
+    }
+
+    // a name given on the command line. with --namespace the name is taken as is, dots and all, otherwise it can be
+    // qualified as <name>.<namespace> or <namespace>/<name>, or be in the default namespace if there is one
+    pub fn from_arg(arg: &str, namespace: Option<&str>, default_namespace: Option<&str>) -> Result<Self, SkateError> {
+        let parsed = match (arg.contains('/'), namespace) {
+            (true, namespace) => {
+                let parsed: NamespacedName = arg.parse()?;
+                if let Some(namespace) = namespace.filter(|ns| *ns != parsed.namespace) {
+                    return Err(anyhow!("{} is in namespace {}, not {}", arg, parsed.namespace, namespace).into());
+                }
+                return Ok(parsed);
+            }
+            (false, Some(namespace)) => NamespacedName::new(arg, namespace),
+            (false, None) if arg.contains('.') => return arg.parse(),
+            (false, None) => match default_namespace {
+                Some(namespace) => NamespacedName::new(arg, namespace),
+                None => return Err(anyhow!("{} needs a namespace, use --namespace, <name>.<namespace> or <namespace>/<name>", arg).into()),
+            },
+        };
+        parsed.validate().map_err(|e| anyhow!("invalid name {}: {}", arg, e))?;
+        Ok(parsed)
+    }
 }
 
 // returns name, namespace
@@ -218,6 +316,17 @@ where
         .stderr(std::process::Stdio::null())
         .spawn();
 }
+// runs the script with the platform's shell, sh or cmd on windows
+pub fn shell_command(script: &str) -> std::process::Command {
+    let (shell, flag) = match cfg!(windows) {
+        true => ("cmd", "/C"),
+        false => ("sh", "-c"),
+    };
+    let mut cmd = std::process::Command::new(shell);
+    cmd.arg(flag).arg(script);
+    cmd
+}
+
 pub fn lock_file<T>(file: &str, cb: Box<dyn FnOnce() -> Result<T, Box<dyn Error>>>) -> Result<T, Box<dyn Error>> {
     let lock_path = Path::new(file);
     let lock_file = File::create(lock_path).map_err(|e| anyhow!("failed to create/open lock file: {}", e))?;
@@ -230,23 +339,65 @@ pub fn lock_file<T>(file: &str, cb: Box<dyn FnOnce() -> Result<T, Box<dyn Error>
     result
 }
 
+// manifests can hold secret values, so they're only ever written to tmpfs, readable by root alone, and removed once played
+const PLAY_MANIFEST_DIR: &str = "/run/skate/manifests";
+
 fn write_manifest_to_file(manifest: &str) -> Result<String, Box<dyn Error>> {
-    let file_path = format!("/tmp/skate-{}.yaml", hash_string(manifest));
-    let mut file = File::create(file_path.clone()).expect("failed to open file for manifests");
-    file.write_all(manifest.as_ref()).expect("failed to write manifest to file");
+    let mut dir = std::fs::DirBuilder::new();
+    dir.recursive(true);
+    #[cfg(unix)]
+    dir.mode(0o700);
+    dir.create(PLAY_MANIFEST_DIR).map_err(|e| anyhow!(e).context(format!("failed to create {}", PLAY_MANIFEST_DIR)))?;
+
+    let file_path = format!("{}/skate-{}.yaml", PLAY_MANIFEST_DIR, hash_string(manifest));
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(&file_path).map_err(|e| anyhow!(e).context("failed to open file for manifest"))?;
+    file.write_all(manifest.as_ref()).map_err(|e| anyhow!(e).context("failed to write manifest to file"))?;
     Ok(file_path)
 }
 
 
-pub fn apply_play(execer: &Box<dyn ShellExec>, object: &SupportedResources) -> Result<(), Box<dyn Error>> {
+// pulls the images that aren't on the node yet one by one, so each pull is reported rather than hidden in the play
+fn pull_missing_images(execer: &dyn ShellExec, object: &SupportedResources) -> Result<(), Box<dyn Error>> {
+    for image in pod_images(object) {
+        if execer.exec("podman", &["image", "exists", &image]).is_ok() {
+            continue;
+        }
+        apply_progress::report(ApplyEvent::PullingImage { image: image.clone() });
+        execer.exec("podman", &["pull", "--quiet", &image]).map_err(|e| anyhow!(e.to_string()).context(format!("failed to pull {}", image)))?;
+    }
+    Ok(())
+}
+
+// extra_args are passed on to podman kube play as they are
+pub fn apply_play(execer: &dyn ShellExec, object: &SupportedResources, extra_args: &[String]) -> Result<(), Box<dyn Error>> {
+    if apply_progress::enabled() {
+        pull_missing_images(execer, object)?;
+    }
+    if let SupportedResources::Pod(p) = object {
+        apply_progress::report(ApplyEvent::CreatingPod { name: p.metadata.name.clone().unwrap_or_default() });
+    }
+
     let file_path = write_manifest_to_file(&serde_yaml::to_string(object)?)?;
 
+    let log_args = match object {
+        SupportedResources::Pod(p) => logging::play_args(p.metadata.annotations.as_ref())?,
+        _ => vec![],
+    };
+
     let mut args = vec!["play", "kube", &file_path, "--start"];
     if !object.host_network() {
         args.push("--network=skate")
     }
+    args.extend(log_args.iter().map(|a| a.as_str()));
+    args.extend(extra_args.iter().map(|a| a.as_str()));
 
-    let result = execer.exec("podman", &args)?;
+    let result = execer.exec("podman", &args);
+    let _ = std::fs::remove_file(&file_path);
+    let result = result?;
 
     if !result.is_empty() {
         println!("{}", result);
@@ -263,7 +414,7 @@ pub fn version(long: bool) -> String {
     };
 
     if !long {
-        return format!("{}", short_version)
+        return short_version.to_string()
     }
     format!(r#"{}
 branch:{}
@@ -279,14 +430,125 @@ pub fn tabled_display_option<T>(o: &Option<T>) -> String where T: Display{
     }
 }
 
-pub fn transfer_file_cmd(contents: &str, remote_path: &str) -> String {
-    format!("sudo bash -c -eu 'echo {}| base64 --decode > {}'", general_purpose::STANDARD.encode(contents), remote_path)
+// quotes a value for use as a single argument in a remote shell command
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+pub static RE_CIDR: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^([0-9]{1,3}\.){3}[0-9]{1,3}($|/(16|24))").unwrap()
+});
+pub static RE_IP: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^([0-9]{1,3}\.){3}[0-9]{1,3}$").unwrap()
+});
+
+// quantity_to_bytes parses a kubernetes quantity such as 64Mi, 1G or 1e6 into bytes
+pub fn quantity_to_bytes(quantity: &str) -> Option<u64> {
+    let quantity = quantity.trim();
+    let split = quantity.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(quantity.len());
+    let (number, suffix) = quantity.split_at(split);
+    let number: f64 = number.parse().ok()?;
+
+    let multiplier: f64 = match suffix {
+        "" => 1.0,
+        "Ki" => 1024.0,
+        "Mi" => 1024f64.powi(2),
+        "Gi" => 1024f64.powi(3),
+        "Ti" => 1024f64.powi(4),
+        "Pi" => 1024f64.powi(5),
+        "Ei" => 1024f64.powi(6),
+        "k" => 1e3,
+        "M" => 1e6,
+        "G" => 1e9,
+        "T" => 1e12,
+        "P" => 1e15,
+        "E" => 1e18,
+        s if s.starts_with(['e', 'E']) => 10f64.powi(s[1..].parse().ok()?),
+        _ => return None,
+    };
+    Some((number * multiplier).round() as u64)
+}
+
+// quantity_to_cpus parses a kubernetes cpu quantity such as 500m or 2 into a number of cpus
+pub fn quantity_to_cpus(quantity: &str) -> Option<f64> {
+    let quantity = quantity.trim();
+    let cpus = match quantity.strip_suffix('m') {
+        Some(millis) => millis.parse::<f64>().ok()? / 1000.0,
+        None => quantity.parse::<f64>().ok()?,
+    };
+    match cpus.is_finite() && cpus >= 0.0 {
+        true => Some(cpus),
+        false => None,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use chrono::{Duration, Local};
-    use crate::util::age;
+    use crate::util::{age, quantity_to_bytes, quantity_to_cpus, shell_quote, NamespacedName, CHECKBOX_EMOJI, CROSS_EMOJI};
+
+    #[test]
+    fn test_emoji() {
+        assert_eq!("✔", CHECKBOX_EMOJI.render(true));
+        assert_eq!("[ok]", CHECKBOX_EMOJI.render(false));
+        assert_eq!("[error]", CROSS_EMOJI.render(false));
+    }
+
+    #[test]
+    fn test_namespaced_name() {
+        let parse = |s: &str| s.parse::<NamespacedName>().map(|n| (n.name, n.namespace)).map_err(|e| e.to_string());
+        let pair = |name: &str, namespace: &str| Ok((name.to_string(), namespace.to_string()));
+        assert_eq!(pair("web", "shop"), parse("web.shop"));
+        assert_eq!(pair("web", "shop"), parse("shop/web"));
+        // the namespace is after the last dot
+        assert_eq!(pair("api.v2", "shop"), parse("api.v2.shop"));
+        assert_eq!(pair("api.v2", "shop"), parse("shop/api.v2"));
+        assert!(parse("web").unwrap_err().contains("expected <name>.<namespace> or <namespace>/<name>"));
+        assert!(parse("Web.shop").unwrap_err().contains("name Web must be"));
+        assert!(parse("web.").unwrap_err().contains("namespace  must be"));
+        assert!(parse("a/b/c").is_err());
+
+        let from_arg = |arg: &str, namespace: Option<&str>, default: Option<&str>| NamespacedName::from_arg(arg, namespace, default).map(|n| n.to_string());
+        assert_eq!("api.v2.shop", from_arg("api.v2", Some("shop"), None).unwrap());
+        assert_eq!("web.shop", from_arg("shop/web", Some("shop"), None).unwrap());
+        assert!(from_arg("shop/web", Some("blog"), None).unwrap_err().to_string().contains("is in namespace shop, not blog"));
+        assert_eq!("web.default", from_arg("web", None, Some("default")).unwrap());
+        assert!(from_arg("web", None, None).unwrap_err().to_string().contains("needs a namespace"));
+
+        assert_eq!(NamespacedName::new("api.v2", "shop"), NamespacedName::from("api.v2.shop"));
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!("'alice@laptop (pid 1)'", shell_quote("alice@laptop (pid 1)"));
+        assert_eq!("'it'\\''s'", shell_quote("it's"));
+    }
+
+    #[test]
+    fn test_quantity_to_bytes() {
+        let conditions = &[
+            ("128", Some(128)),
+            ("64Mi", Some(64 * 1024 * 1024)),
+            ("1.5Gi", Some(1536 * 1024 * 1024)),
+            ("500M", Some(500_000_000)),
+            ("1e3", Some(1000)),
+            ("10x", None),
+            ("Mi", None),
+        ];
+
+        for (input, expect) in conditions {
+            assert_eq!(quantity_to_bytes(input), *expect, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_quantity_to_cpus() {
+        assert_eq!(quantity_to_cpus("500m"), Some(0.5));
+        assert_eq!(quantity_to_cpus("2"), Some(2.0));
+        assert_eq!(quantity_to_cpus("0.25"), Some(0.25));
+        assert_eq!(quantity_to_cpus("lots"), None);
+        assert_eq!(quantity_to_cpus("-1"), None);
+    }
 
     #[test]
     fn test_age() {
@@ -304,10 +566,3 @@ mod tests {
         }
     }
 }
-
-pub static RE_CIDR: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"^([0-9]{1,3}\.){3}[0-9]{1,3}($|/(16|24))").unwrap()
-});
-pub static RE_IP: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"^([0-9]{1,3}\.){3}[0-9]{1,3}$").unwrap()
-});
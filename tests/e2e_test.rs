@@ -1,4 +1,4 @@
-use std::{env, panic, process, time};
+use std::env;
 use tokio::process::{Command};
 use tokio::time::sleep;
 use std::error::Error;
@@ -10,7 +10,6 @@ use anyhow::anyhow;
 use colored::Colorize;
 use futures::stream::FuturesUnordered;
 use futures::StreamExt;
-use log::{error, info};
 use serde_json::Value;
 
 #[derive(Debug, Clone)]
@@ -161,7 +160,7 @@ async fn test_deployment() -> Result<(), anyhow::Error> {
         let parts: Vec<&str> = line.split_whitespace().collect();
         if parts.len() == 6 {
             assert_eq!(parts[0], "test-deployment");
-            assert_eq!(true, parts[1].starts_with("dpl-nginx-"));
+            assert!(parts[1].starts_with("dpl-nginx-"));
             assert_eq!(parts[2], "2/2");
             assert_eq!(parts[3], "Running");
             assert_eq!(parts[4], "0");
@@ -211,7 +210,7 @@ async fn test_service() -> Result<(), anyhow::Error> {
         let parts: Vec<&str> = line.split_whitespace().collect();
         if parts.len() == 6 {
             assert_eq!(parts[0], "test-deployment");
-            assert_eq!(true, parts[1].starts_with("nginx"));
+            assert!(parts[1].starts_with("nginx"));
             assert_eq!(parts[4], "80");
         }
     }